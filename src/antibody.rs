@@ -0,0 +1,172 @@
+use super::restraints::residue_id;
+use log::warn;
+use pdbtbx::PDB;
+
+// Antibody docking: the heavy and light chains of an antibody are told apart by the conserved
+// J segment motif of their variable domain, WGxG for heavy chains (and nanobodies) and FGxG for
+// light chains, and their CDR loops are the residues in the ranges of the numbering scheme the
+// structure is numbered with. Insertion codes are part of the range of their residue number.
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChainType {
+    Heavy,
+    Light,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Numbering {
+    #[default]
+    Chothia,
+    Kabat,
+    IMGT,
+}
+
+pub const NUMBERINGS: [Numbering; 3] = [Numbering::Chothia, Numbering::Kabat, Numbering::IMGT];
+
+// Variable domains end before this residue number in every numbering scheme
+const VARIABLE_DOMAIN_END: isize = 130;
+
+impl Numbering {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Numbering::Chothia => "chothia",
+            Numbering::Kabat => "kabat",
+            Numbering::IMGT => "imgt",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Numbering> {
+        NUMBERINGS
+            .into_iter()
+            .find(|numbering| numbering.name() == name.to_lowercase())
+    }
+
+    // First and last residue numbers of the CDR loops 1, 2 and 3
+    pub fn cdr_ranges(&self, chain_type: ChainType) -> [(isize, isize); 3] {
+        match (self, chain_type) {
+            (Numbering::Chothia, ChainType::Heavy) => [(26, 32), (52, 56), (95, 102)],
+            (Numbering::Kabat, ChainType::Heavy) => [(31, 35), (50, 65), (95, 102)],
+            (Numbering::Chothia | Numbering::Kabat, ChainType::Light) => {
+                [(24, 34), (50, 56), (89, 97)]
+            }
+            (Numbering::IMGT, _) => [(27, 38), (56, 65), (105, 117)],
+        }
+    }
+}
+
+// Chain type from the residue names of its variable domain, None if not an antibody chain
+pub fn chain_type(residue_names: &[&str]) -> Option<ChainType> {
+    for window in residue_names.windows(4) {
+        if window[1] == "GLY" && window[3] == "GLY" {
+            match window[0] {
+                "TRP" => return Some(ChainType::Heavy),
+                "PHE" => return Some(ChainType::Light),
+                _ => (),
+            }
+        }
+    }
+    None
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct AntibodyChain {
+    pub chain_id: String,
+    pub chain_type: ChainType,
+    // Residue IDs of the CDR loops
+    pub cdrs: Vec<String>,
+}
+
+// Heavy and light chains of the structure with their CDR residues
+pub fn detect_cdrs(structure: &PDB, numbering: Numbering) -> Vec<AntibodyChain> {
+    let mut chains: Vec<AntibodyChain> = Vec::new();
+    for chain in structure.chains() {
+        let names: Vec<&str> = chain
+            .residues()
+            .filter(|residue| residue.serial_number() < VARIABLE_DOMAIN_END)
+            .map(|residue| residue.name().unwrap_or(""))
+            .collect();
+        let chain_type = match chain_type(&names) {
+            Some(chain_type) => chain_type,
+            None => continue,
+        };
+        let ranges = numbering.cdr_ranges(chain_type);
+        let mut cdrs: Vec<String> = Vec::new();
+        for (loop_index, (first, last)) in ranges.iter().enumerate() {
+            let residues: Vec<String> = chain
+                .residues()
+                .filter(|residue| (*first..=*last).contains(&residue.serial_number()))
+                .map(|residue| residue_id(chain, residue))
+                .collect();
+            if residues.is_empty() {
+                warn!(
+                    "No residues of CDR {} in chain {}, is it {} numbered?",
+                    loop_index + 1,
+                    chain.id(),
+                    numbering.name()
+                );
+            }
+            cdrs.extend(residues);
+        }
+        chains.push(AntibodyChain {
+            chain_id: chain.id().to_string(),
+            chain_type,
+            cdrs,
+        });
+    }
+    chains
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::structure::parse_structure;
+
+    #[test]
+    fn test_detect_cdrs() {
+        assert_eq!(
+            chain_type(&["SER", "TRP", "GLY", "GLN", "GLY", "THR"]),
+            Some(ChainType::Heavy)
+        );
+        assert_eq!(
+            chain_type(&["PHE", "GLY", "GLY", "GLY", "THR"]),
+            Some(ChainType::Light)
+        );
+        assert_eq!(chain_type(&["TRP", "GLY", "GLN", "ALA"]), None);
+        assert_eq!(Numbering::from_name("IMGT"), Some(Numbering::IMGT));
+
+        // Light chain with residues in CDR L1 (27 and 27A) and the J motif from 98, and a chain
+        // that is not an antibody
+        let mut contents = String::new();
+        for (serial, (name, number, insertion)) in [
+            ("ALA", 20, " "),
+            ("SER", 27, " "),
+            ("GLN", 27, "A"),
+            ("PHE", 98, " "),
+            ("GLY", 99, " "),
+            ("GLN", 100, " "),
+            ("GLY", 101, " "),
+        ]
+        .iter()
+        .enumerate()
+        {
+            contents.push_str(&format!(
+                "ATOM  {:>5}  CA  {} L{:>4}{}   {:>8.3}   0.000   0.000  1.00  0.00           C\n",
+                serial + 1,
+                name,
+                number,
+                insertion,
+                serial as f64 * 3.8
+            ));
+        }
+        contents.push_str(
+            "ATOM      8  CA  TRP A   1      50.000   0.000   0.000  1.00  0.00           C\n",
+        );
+        let structure = parse_structure(&contents, 1).unwrap().unwrap();
+
+        let chains = detect_cdrs(&structure, Numbering::Chothia);
+        assert_eq!(chains.len(), 1);
+        assert_eq!(chains[0].chain_id, "L");
+        assert_eq!(chains[0].chain_type, ChainType::Light);
+        assert_eq!(chains[0].cdrs, vec!["L.SER.27", "L.GLN.27A"]);
+    }
+}
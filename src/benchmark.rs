@@ -0,0 +1,339 @@
+use super::contacts::residue_contacts;
+use super::rmsd::{interface_rmsd, ligand_rmsd};
+use pdbtbx::PDB;
+use std::collections::HashMap;
+
+// CAPRI evaluation of docking poses against a reference complex given as its receptor and ligand
+// (e.g. the bound structures of a BM5 case). Atoms of the docked structures are matched to the
+// reference by chain, residue number, insertion code and atom name, so both must share the
+// numbering. Metrics follow the CAPRI definitions:
+//  - fnat: fraction of the reference residue contacts (heavy atoms closer than 5 A) in the pose,
+//  - L-RMSD: ligand backbone RMSD once the receptor backbones are superposed,
+//  - I-RMSD: backbone RMSD of the reference interface residues (closer than 10 A to the partner)
+//    after their superposition.
+
+pub const CAPRI_CONTACT_CUTOFF: f64 = 5.0;
+pub const CAPRI_INTERFACE_CUTOFF: f64 = 10.0;
+const BACKBONE_ATOMS: [&str; 4] = ["N", "CA", "C", "O"];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum CapriClass {
+    Incorrect,
+    Acceptable,
+    Medium,
+    High,
+}
+
+pub const CAPRI_CLASSES: [CapriClass; 3] =
+    [CapriClass::Acceptable, CapriClass::Medium, CapriClass::High];
+
+impl CapriClass {
+    pub fn name(&self) -> &'static str {
+        match self {
+            CapriClass::Incorrect => "incorrect",
+            CapriClass::Acceptable => "acceptable",
+            CapriClass::Medium => "medium",
+            CapriClass::High => "high",
+        }
+    }
+
+    pub fn classify(fnat: f64, lrmsd: f64, irmsd: f64) -> CapriClass {
+        if fnat >= 0.5 && (lrmsd <= 1.0 || irmsd <= 1.0) {
+            CapriClass::High
+        } else if fnat >= 0.3 && (lrmsd <= 5.0 || irmsd <= 2.0) {
+            CapriClass::Medium
+        } else if fnat >= 0.1 && (lrmsd <= 10.0 || irmsd <= 4.0) {
+            CapriClass::Acceptable
+        } else {
+            CapriClass::Incorrect
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct CapriMetrics {
+    pub fnat: f64,
+    pub lrmsd: f64,
+    pub irmsd: f64,
+    pub class: CapriClass,
+}
+
+// Atoms of a docked structure found in the reference one
+#[derive(Debug, Default)]
+pub struct MatchedAtoms {
+    // Index of every matched atom in the order of pose::atom_coordinates
+    pub atoms: Vec<usize>,
+    pub reference: Vec<[f64; 3]>,
+    pub residues: Vec<usize>,
+    pub backbone: Vec<bool>,
+    pub heavy: Vec<bool>,
+}
+
+type AtomKey = (String, isize, Option<String>, String);
+
+fn atom_keys(structure: &PDB) -> Vec<(AtomKey, [f64; 3], bool)> {
+    let mut keys = Vec::new();
+    for chain in structure.chains() {
+        for residue in chain.residues() {
+            for atom in residue.atoms() {
+                let key = (
+                    chain.id().to_string(),
+                    residue.serial_number(),
+                    residue.insertion_code().map(String::from),
+                    atom.name().to_string(),
+                );
+                let hydrogen = atom.name().starts_with('H')
+                    || atom
+                        .element()
+                        .is_some_and(|element| element.symbol() == "H");
+                keys.push((key, [atom.x(), atom.y(), atom.z()], hydrogen));
+            }
+        }
+    }
+    keys
+}
+
+impl MatchedAtoms {
+    pub fn new(structure: &PDB, reference: &PDB) -> MatchedAtoms {
+        let reference_atoms: HashMap<AtomKey, [f64; 3]> = atom_keys(reference)
+            .into_iter()
+            .map(|(key, coordinate, _)| (key, coordinate))
+            .collect();
+        let mut matched = MatchedAtoms::default();
+        let mut residue_ids: Vec<(String, isize, Option<String>)> = Vec::new();
+        for (i, (key, _, hydrogen)) in atom_keys(structure).into_iter().enumerate() {
+            if let Some(coordinate) = reference_atoms.get(&key) {
+                let residue_id = (key.0.clone(), key.1, key.2.clone());
+                if residue_ids.last() != Some(&residue_id) {
+                    residue_ids.push(residue_id);
+                }
+                matched.atoms.push(i);
+                matched.reference.push(*coordinate);
+                matched.residues.push(residue_ids.len() - 1);
+                matched
+                    .backbone
+                    .push(BACKBONE_ATOMS.contains(&key.3.as_str()));
+                matched.heavy.push(!hydrogen);
+            }
+        }
+        matched
+    }
+
+    fn select(&self, coordinates: &[[f64; 3]], filter: &[bool]) -> Vec<[f64; 3]> {
+        self.atoms
+            .iter()
+            .zip(filter.iter())
+            .filter(|(_, &keep)| keep)
+            .map(|(&i, _)| coordinates[i])
+            .collect()
+    }
+
+    fn select_reference(&self, filter: &[bool]) -> Vec<[f64; 3]> {
+        self.reference
+            .iter()
+            .zip(filter.iter())
+            .filter(|(_, &keep)| keep)
+            .map(|(coordinate, _)| *coordinate)
+            .collect()
+    }
+
+    fn select_residues(&self, filter: &[bool]) -> Vec<usize> {
+        self.residues
+            .iter()
+            .zip(filter.iter())
+            .filter(|(_, &keep)| keep)
+            .map(|(residue, _)| *residue)
+            .collect()
+    }
+}
+
+pub struct CapriReference {
+    pub receptor: MatchedAtoms,
+    pub ligand: MatchedAtoms,
+    pub contacts: Vec<(usize, usize)>,
+    // Backbone atoms of the interface residues, as indexes of the backbone selections
+    pub receptor_interface: Vec<usize>,
+    pub ligand_interface: Vec<usize>,
+}
+
+impl CapriReference {
+    pub fn new(
+        receptor: &PDB,
+        ligand: &PDB,
+        receptor_reference: &PDB,
+        ligand_reference: &PDB,
+    ) -> Result<CapriReference, String> {
+        let receptor = MatchedAtoms::new(receptor, receptor_reference);
+        let ligand = MatchedAtoms::new(ligand, ligand_reference);
+        for (partner, matched) in [("receptor", &receptor), ("ligand", &ligand)] {
+            if !matched.backbone.iter().any(|&backbone| backbone) {
+                return Err(format!(
+                    "no {} backbone atom found in the reference",
+                    partner
+                ));
+            }
+        }
+        let contacts = residue_contacts(
+            &receptor.select_reference(&receptor.heavy),
+            &receptor.select_residues(&receptor.heavy),
+            &ligand.select_reference(&ligand.heavy),
+            &ligand.select_residues(&ligand.heavy),
+            CAPRI_CONTACT_CUTOFF,
+        );
+        if contacts.is_empty() {
+            return Err(String::from("no contacts between the reference partners"));
+        }
+
+        let interface = residue_contacts(
+            &receptor.select_reference(&receptor.heavy),
+            &receptor.select_residues(&receptor.heavy),
+            &ligand.select_reference(&ligand.heavy),
+            &ligand.select_residues(&ligand.heavy),
+            CAPRI_INTERFACE_CUTOFF,
+        );
+        let interface_atoms = |matched: &MatchedAtoms, residues: Vec<usize>| -> Vec<usize> {
+            matched
+                .select_residues(&matched.backbone)
+                .iter()
+                .enumerate()
+                .filter(|(_, residue)| residues.contains(residue))
+                .map(|(i, _)| i)
+                .collect()
+        };
+        let receptor_interface =
+            interface_atoms(&receptor, interface.iter().map(|(i, _)| *i).collect());
+        let ligand_interface =
+            interface_atoms(&ligand, interface.iter().map(|(_, j)| *j).collect());
+        Ok(CapriReference {
+            receptor,
+            ligand,
+            contacts,
+            receptor_interface,
+            ligand_interface,
+        })
+    }
+
+    // Metrics of a pose given by all the receptor and ligand coordinates
+    pub fn evaluate(&self, receptor: &[[f64; 3]], ligand: &[[f64; 3]]) -> CapriMetrics {
+        let contacts = residue_contacts(
+            &self.receptor.select(receptor, &self.receptor.heavy),
+            &self.receptor.select_residues(&self.receptor.heavy),
+            &self.ligand.select(ligand, &self.ligand.heavy),
+            &self.ligand.select_residues(&self.ligand.heavy),
+            CAPRI_CONTACT_CUTOFF,
+        );
+        let native = contacts
+            .iter()
+            .filter(|contact| self.contacts.binary_search(contact).is_ok())
+            .count();
+        let fnat = native as f64 / self.contacts.len() as f64;
+
+        let receptor_backbone = self.receptor.select(receptor, &self.receptor.backbone);
+        let receptor_reference = self.receptor.select_reference(&self.receptor.backbone);
+        let ligand_backbone = self.ligand.select(ligand, &self.ligand.backbone);
+        let ligand_reference = self.ligand.select_reference(&self.ligand.backbone);
+        let lrmsd = ligand_rmsd(
+            &receptor_backbone,
+            &receptor_reference,
+            &ligand_backbone,
+            &ligand_reference,
+        );
+        let irmsd = interface_rmsd(
+            &receptor_backbone,
+            &receptor_reference,
+            &ligand_backbone,
+            &ligand_reference,
+            &self.receptor_interface,
+            &self.ligand_interface,
+        );
+        CapriMetrics {
+            fnat,
+            lrmsd,
+            irmsd,
+            class: CapriClass::classify(fnat, lrmsd, irmsd),
+        }
+    }
+}
+
+// Fraction of the cases with a pose of at least the given class among their first top poses
+pub fn success_rate(cases: &[Vec<CapriClass>], top: usize, minimum: CapriClass) -> f64 {
+    if cases.is_empty() {
+        return 0.0;
+    }
+    let successes = cases
+        .iter()
+        .filter(|classes| classes.iter().take(top).any(|class| *class >= minimum))
+        .count();
+    successes as f64 / cases.len() as f64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pose::atom_coordinates;
+    use crate::structure::parse_structure;
+
+    fn residue(chain: &str, number: usize, x: f64, y: f64) -> String {
+        ["N", "CA", "C", "O"]
+            .iter()
+            .enumerate()
+            .map(|(i, name)| {
+                format!(
+                    "ATOM  {:>5} {:<4} ALA {}{:>4}    {:>8.3}{:>8.3}{:>8.3}  1.00  0.00           {}\n",
+                    number * 4 + i,
+                    name,
+                    chain,
+                    number,
+                    x + i as f64,
+                    y,
+                    (i % 2) as f64,
+                    &name[..1]
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_capri_metrics() {
+        assert_eq!(CapriClass::classify(0.6, 0.8, 3.0), CapriClass::High);
+        assert_eq!(CapriClass::classify(0.6, 3.0, 3.0), CapriClass::Medium);
+        assert_eq!(CapriClass::classify(0.2, 12.0, 3.0), CapriClass::Acceptable);
+        assert_eq!(CapriClass::classify(0.05, 0.5, 0.5), CapriClass::Incorrect);
+
+        let receptor_contents: String = (1..4)
+            .map(|i| residue("A", i, 0.0, 6.0 * i as f64))
+            .collect();
+        let ligand_contents: String = (1..3)
+            .map(|i| residue("B", i, 5.0, 6.0 * i as f64))
+            .collect();
+        let receptor = parse_structure(&receptor_contents, 1).unwrap().unwrap();
+        let ligand = parse_structure(&ligand_contents, 1).unwrap().unwrap();
+        let reference = CapriReference::new(&receptor, &ligand, &receptor, &ligand).unwrap();
+        assert_eq!(reference.contacts, vec![(0, 0), (1, 1)]);
+
+        let receptor_coordinates = atom_coordinates(&receptor);
+        let ligand_coordinates = atom_coordinates(&ligand);
+        let native = reference.evaluate(&receptor_coordinates, &ligand_coordinates);
+        assert_eq!(native.fnat, 1.0);
+        assert!(native.lrmsd < 1e-6 && native.irmsd < 1e-6);
+        assert_eq!(native.class, CapriClass::High);
+
+        // Ligand moved away from the receptor
+        let away: Vec<[f64; 3]> = ligand_coordinates
+            .iter()
+            .map(|c| [c[0] + 30.0, c[1], c[2]])
+            .collect();
+        let metrics = reference.evaluate(&receptor_coordinates, &away);
+        assert_eq!(metrics.fnat, 0.0);
+        assert!((metrics.lrmsd - 30.0).abs() < 1e-6);
+        assert_eq!(metrics.class, CapriClass::Incorrect);
+
+        let cases = vec![
+            vec![CapriClass::Incorrect, CapriClass::High],
+            vec![CapriClass::Acceptable],
+        ];
+        assert_eq!(success_rate(&cases, 1, CapriClass::Acceptable), 0.5);
+        assert_eq!(success_rate(&cases, 2, CapriClass::Acceptable), 1.0);
+        assert_eq!(success_rate(&cases, 2, CapriClass::Medium), 0.5);
+    }
+}
@@ -0,0 +1,181 @@
+use lightdock::constants::{
+    DEFAULT_LIG_NMD_FILE, DEFAULT_LIG_NM_FILE, DEFAULT_REC_NMD_FILE, DEFAULT_REC_NM_FILE,
+    DEFAULT_SCORE_TOP_CONTACTS,
+};
+use lightdock::nmodes::nmodes_path;
+use lightdock::policy::ResiduePolicy;
+use lightdock::qt::Quaternion;
+use lightdock::scoring::Method;
+use lightdock::scoring_from_files;
+use std::env;
+use std::fs::File;
+use std::io::BufWriter;
+use std::process;
+use std::thread;
+
+const STACK_SIZE: usize = 8 * 1024 * 1024;
+
+// Scores a single pose of the ligand against the receptor, the structures as prepared by the
+// setup (lightdock_*.pdb) and the pose as in the GSO output: translation of the ligand center,
+// rotation quaternion and receptor and ligand normal mode extents.
+
+fn main() {
+    // Spawn thread with explicit stack size
+    let child = thread::Builder::new()
+        .stack_size(STACK_SIZE)
+        .spawn(run)
+        .unwrap();
+
+    // Wait for thread to join
+    if child.join().is_err() {
+        process::exit(1);
+    }
+}
+
+fn usage(program: &str) -> ! {
+    eprintln!(
+        "Usage: {} receptor_pdb ligand_pdb method x y z qw qx qy qz [extents...]\n       [--anm-rec N] [--anm-lig N] [--top N] [--matrix output_csv] [--strict]",
+        program
+    );
+    process::exit(1);
+}
+
+// Value of the given option, removed from the arguments with the option
+fn take_option(args: &mut Vec<String>, name: &str) -> Option<String> {
+    let i = args.iter().position(|arg| arg == name)?;
+    if i + 1 >= args.len() {
+        eprintln!("Error: {} requires a value", name);
+        process::exit(1);
+    }
+    let value = args.remove(i + 1);
+    args.remove(i);
+    Some(value)
+}
+
+fn count_option(args: &mut Vec<String>, name: &str, default: usize) -> usize {
+    match take_option(args, name).map(|value| value.parse::<usize>()) {
+        Some(Ok(count)) => count,
+        Some(Err(_)) => {
+            eprintln!("Error: {} must be a number", name);
+            process::exit(1);
+        }
+        None => default,
+    }
+}
+
+fn run() {
+    env_logger::init();
+    let mut args: Vec<String> = env::args().collect();
+    let strict = args.iter().any(|arg| arg == "--strict");
+    args.retain(|arg| arg != "--strict");
+    let rec_num_anm = count_option(&mut args, "--anm-rec", 0);
+    let lig_num_anm = count_option(&mut args, "--anm-lig", 0);
+    let top = count_option(&mut args, "--top", DEFAULT_SCORE_TOP_CONTACTS);
+    let matrix = take_option(&mut args, "--matrix");
+    if args.len() < 11 {
+        usage(&args[0]);
+    }
+
+    let method = match Method::from_name(&args[3]) {
+        Some(method) => method,
+        None => {
+            eprintln!("Error: method not supported [{:?}]", args[3]);
+            process::exit(1);
+        }
+    };
+    let values: Vec<f64> = match args[4..].iter().map(|arg| arg.parse::<f64>()).collect() {
+        Ok(values) => values,
+        Err(_) => {
+            eprintln!("Error: pose coordinates must be numbers");
+            process::exit(1);
+        }
+    };
+    if values.len() != 7 + rec_num_anm + lig_num_anm {
+        eprintln!(
+            "Error: {} pose coordinates given, expected 7 plus {} receptor and {} ligand extents",
+            values.len(),
+            rec_num_anm,
+            lig_num_anm
+        );
+        process::exit(1);
+    }
+    let translation = &values[0..3];
+    let rotation = Quaternion::new(values[3], values[4], values[5], values[6]);
+    let rec_extents = &values[7..7 + rec_num_anm];
+    let lig_extents = &values[7 + rec_num_anm..];
+
+    let policy = if strict {
+        ResiduePolicy::Strict
+    } else {
+        ResiduePolicy::default()
+    };
+    let scoring = match scoring_from_files(
+        &method,
+        &args[1],
+        &args[2],
+        Some((
+            nmodes_path(DEFAULT_REC_NM_FILE, DEFAULT_REC_NMD_FILE),
+            rec_num_anm,
+        )),
+        Some((
+            nmodes_path(DEFAULT_LIG_NM_FILE, DEFAULT_LIG_NMD_FILE),
+            lig_num_anm,
+        )),
+        policy,
+    ) {
+        Ok(scoring) => scoring,
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            process::exit(1);
+        }
+    };
+
+    let energy = scoring.energy(translation, &rotation, rec_extents, lig_extents);
+    println!("{} energy {:.8}", method.name(), energy);
+    let energies = match scoring.residue_energies(translation, &rotation, rec_extents, lig_extents)
+    {
+        Some(energies) => energies,
+        None => return,
+    };
+    println!(
+        "Residue pairs {:.8}, constant {:.8}",
+        energies.total() - energies.offset,
+        energies.offset
+    );
+
+    // Residue pairs with the largest contributions, whatever their sign
+    let mut contacts: Vec<(usize, usize, f64)> = Vec::new();
+    for (i, row) in energies.energies.iter().enumerate() {
+        for (j, &energy) in row.iter().enumerate() {
+            if energy != 0.0 {
+                contacts.push((i, j, energy));
+            }
+        }
+    }
+    contacts.sort_by(|a, b| b.2.abs().total_cmp(&a.2.abs()));
+    if top > 0 && !contacts.is_empty() {
+        println!(
+            "Top {} of {} residue contacts:",
+            top.min(contacts.len()),
+            contacts.len()
+        );
+        for (i, j, energy) in contacts.iter().take(top) {
+            println!(
+                "  {:<14} {:<14} {:>12.6}",
+                energies.receptor[*i], energies.ligand[*j], energy
+            );
+        }
+    }
+
+    if let Some(csv_filename) = matrix {
+        let written = File::create(&csv_filename)
+            .and_then(|file| energies.write_csv(&mut BufWriter::new(file)));
+        match written {
+            Ok(_) => println!("Energy matrix written to {}", csv_filename),
+            Err(e) => {
+                eprintln!("Error writing energy matrix [{:?}]: {}", csv_filename, e);
+                process::exit(1);
+            }
+        }
+    }
+}
@@ -0,0 +1,175 @@
+use flate2::read::GzDecoder;
+use log::info;
+use std::error::Error;
+use std::fs::{self, File};
+use std::io::BufReader;
+use std::path::{Path, PathBuf};
+use std::process;
+
+// A docking job can be given as a single tar (optionally gzipped) or zip archive containing the
+// setup.json file, the lightdock_*.pdb structures, the ANM files and the initial positions,
+// either at the top level or inside a single folder. The archive is unpacked next to it, into a folder named after it, and reused by the following runs of other swarms.
+
+const BUNDLE_EXTENSIONS: [&str; 4] = [".tar.gz", ".tgz", ".tar", ".zip"];
+
+pub fn is_bundle(path: &str) -> bool {
+    let path = path.to_lowercase();
+    BUNDLE_EXTENSIONS
+        .iter()
+        .any(|extension| path.ends_with(extension))
+}
+
+// Folder the bundle is unpacked into, e.g. jobs/1ppe for jobs/1ppe.tar.gz
+pub fn bundle_directory(path: &str) -> PathBuf {
+    let lower = path.to_lowercase();
+    let stem = BUNDLE_EXTENSIONS
+        .iter()
+        .find(|extension| lower.ends_with(*extension))
+        .map(|extension| &path[..path.len() - extension.len()])
+        .unwrap_or(path);
+    PathBuf::from(stem)
+}
+
+fn setup_directory(directory: &Path) -> Option<PathBuf> {
+    if directory.join("setup.json").is_file() {
+        return Some(directory.to_path_buf());
+    }
+    let folders: Vec<PathBuf> = fs::read_dir(directory)
+        .ok()?
+        .flatten()
+        .map(|entry| entry.path())
+        .filter(|path| path.is_dir())
+        .collect();
+    match &folders[..] {
+        [folder] if folder.join("setup.json").is_file() => Some(folder.clone()),
+        _ => None,
+    }
+}
+
+fn extract(path: &str, directory: &Path) -> Result<(), Box<dyn Error>> {
+    fs::create_dir_all(directory)?;
+    let file = BufReader::new(File::open(path)?);
+    let lower = path.to_lowercase();
+    let unpacked: Result<(), Box<dyn Error>> = if lower.ends_with(".zip") {
+        zip::ZipArchive::new(file)
+            .and_then(|mut archive| archive.extract(directory))
+            .map_err(Into::into)
+    } else if lower.ends_with(".tar") {
+        tar::Archive::new(file)
+            .unpack(directory)
+            .map_err(Into::into)
+    } else {
+        tar::Archive::new(GzDecoder::new(file))
+            .unpack(directory)
+            .map_err(Into::into)
+    };
+    unpacked.map_err(|e| format!("unpacking {} failed: {}", path, e).into())
+}
+
+// Unpacks the bundle if needed and returns the folder containing its setup.json
+pub fn unpack_bundle(path: &str) -> Result<PathBuf, Box<dyn Error>> {
+    if !Path::new(path).is_file() {
+        return Err(format!("bundle {} not found", path).into());
+    }
+    let directory = bundle_directory(path);
+    if !directory.exists() {
+        // Unpacked aside and renamed, so concurrent runs never see a partial folder
+        let partial = PathBuf::from(format!("{}.partial.{}", directory.display(), process::id()));
+        info!("Unpacking {} into {}", path, directory.display());
+        if let Err(e) = extract(path, &partial) {
+            let _ = fs::remove_dir_all(&partial);
+            return Err(e);
+        }
+        if fs::rename(&partial, &directory).is_err() {
+            // Another run unpacked it first
+            fs::remove_dir_all(&partial)?;
+        }
+    }
+    setup_directory(&directory).ok_or(
+        format!(
+            "setup.json not found in {}, at the top level or in a single folder",
+            directory.display()
+        )
+        .into(),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use flate2::write::GzEncoder;
+    use flate2::Compression;
+    use std::io::Write;
+
+    #[test]
+    fn test_bundle_directory() {
+        assert!(is_bundle("jobs/1PPE.TAR.GZ"));
+        assert!(is_bundle("1ppe.zip"));
+        assert!(!is_bundle("setup.json"));
+        assert_eq!(
+            bundle_directory("jobs/1ppe.tar.gz"),
+            PathBuf::from("jobs/1ppe")
+        );
+        assert_eq!(bundle_directory("1ppe.tgz"), PathBuf::from("1ppe"));
+    }
+
+    #[test]
+    fn test_unpack_bundle() {
+        let temp = tempfile::tempdir().unwrap();
+        let directory = temp.path();
+        let files = [
+            ("job/setup.json", "{}"),
+            ("job/initial_positions_0.dat", ""),
+        ];
+
+        // Gzipped tar with the job in a folder
+        let bundle = directory.join("job.tar.gz");
+        let mut builder = tar::Builder::new(GzEncoder::new(
+            File::create(&bundle).unwrap(),
+            Compression::default(),
+        ));
+        for (name, content) in files {
+            let mut header = tar::Header::new_gnu();
+            header.set_size(content.len() as u64);
+            header.set_mode(0o644);
+            header.set_cksum();
+            builder
+                .append_data(&mut header, name, content.as_bytes())
+                .unwrap();
+        }
+        builder.into_inner().unwrap().finish().unwrap();
+
+        let unpacked = unpack_bundle(bundle.to_str().unwrap()).unwrap();
+        assert_eq!(unpacked, directory.join("job").join("job"));
+        assert!(unpacked.join("initial_positions_0.dat").is_file());
+        // Already unpacked folders are reused
+        assert_eq!(unpack_bundle(bundle.to_str().unwrap()).unwrap(), unpacked);
+
+        // Zip with the job at the top level
+        let bundle = directory.join("zipped.zip");
+        let mut writer = zip::ZipWriter::new(File::create(&bundle).unwrap());
+        for (name, content) in files {
+            writer
+                .start_file(
+                    name.trim_start_matches("job/"),
+                    zip::write::SimpleFileOptions::default(),
+                )
+                .unwrap();
+            writer.write_all(content.as_bytes()).unwrap();
+        }
+        writer.finish().unwrap();
+
+        let unpacked = unpack_bundle(bundle.to_str().unwrap()).unwrap();
+        assert_eq!(unpacked, directory.join("zipped"));
+        assert_eq!(
+            fs::read_to_string(unpacked.join("setup.json")).unwrap(),
+            "{}"
+        );
+
+        // Corrupted archives are reported
+        let bundle = directory.join("broken.tgz");
+        fs::write(&bundle, "not an archive").unwrap();
+        assert!(unpack_bundle(bundle.to_str().unwrap()).is_err());
+        assert!(!directory.join("broken").exists());
+    }
+}
@@ -0,0 +1,3538 @@
+use crate::antibody::{detect_cdrs, Numbering};
+use crate::benchmark::{success_rate, CapriClass, CapriMetrics, CapriReference, CAPRI_CLASSES};
+use crate::bundle::{is_bundle, unpack_bundle};
+use crate::clustering::{cluster_poses, hierarchical_clusters};
+use crate::constants::{
+    BENCHMARK_TOP_POSES, DEFAULT_CLASH_CUTOFF, DEFAULT_CLUSTER_ROTATION_CUTOFF,
+    DEFAULT_CLUSTER_TRANSLATION_CUTOFF, DEFAULT_CONTACT_CUTOFF, DEFAULT_DIVERSITY_FRACTION,
+    DEFAULT_DIVERSITY_MIN_STEPS, DEFAULT_DIVERSITY_THRESHOLD,
+    DEFAULT_ENERGY_CACHE_ANGULAR_RESOLUTION, DEFAULT_ENERGY_CACHE_RESOLUTION,
+    DEFAULT_ENERGY_CACHE_SIZE, DEFAULT_EXPORT_POSES, DEFAULT_FFT_ROTATIONS, DEFAULT_FFT_SPACING,
+    DEFAULT_GRID_SPACING, DEFAULT_LIGHTDOCK_PREFIX, DEFAULT_LIG_NMD_FILE, DEFAULT_LIG_NM_FILE,
+    DEFAULT_MAX_CLASHES, DEFAULT_MEMBRANE_CLEARANCE, DEFAULT_MEMBRANE_MARGIN,
+    DEFAULT_MEMBRANE_SPACING, DEFAULT_NOISE_DECAY, DEFAULT_OVERLAP_MAX_CLASHES,
+    DEFAULT_OVERLAP_MAX_SHIFT, DEFAULT_OVERLAP_STEP, DEFAULT_PEPTIDE_EXTENT, DEFAULT_PEPTIDE_MODES,
+    DEFAULT_PEPTIDE_TRANSLATION_STEP, DEFAULT_PRESCREEN_THRESHOLD, DEFAULT_PROFILE_FILE,
+    DEFAULT_PROPENSITY_CUTOFF, DEFAULT_PROPENSITY_WEIGHT, DEFAULT_PROTOCOL_ROTATION_SPREAD,
+    DEFAULT_PROTOCOL_SPREAD, DEFAULT_PROTOCOL_TOP_CLUSTERS, DEFAULT_PROTOCOL_TRANSLATION_STEP,
+    DEFAULT_RANK_IRMSD_CUTOFF, DEFAULT_RANK_POSES, DEFAULT_REC_NMD_FILE, DEFAULT_REC_NM_FILE,
+    DEFAULT_REFINEMENT_EPS, DEFAULT_REFINEMENT_ITERATIONS, DEFAULT_REFINEMENT_MAX_STEP,
+    DEFAULT_REFINEMENT_MEMORY, DEFAULT_REFINEMENT_TOP, DEFAULT_REGION_PENALTY,
+    DEFAULT_RESTRAINTS_MAX_ANGLE, DEFAULT_SANITIZE_REPORT, DEFAULT_SEED, DEFAULT_SHELL_RADIUS,
+    RANK_INTERFACE_CUTOFF,
+};
+use crate::contacts::{atom_residues, pose_weights, residue_contacts, ContactMap};
+use crate::decomposition::pose_coordinates;
+use crate::dfire::{DFIREOptions, DFIRE};
+use crate::dna::DNA;
+use crate::export::write_dcd;
+use crate::info::partner_info;
+use crate::mask::mask_structure;
+use crate::membrane::{add_beads, pad_nmodes, plane_beads, read_beads};
+use crate::minimization::LBFGS;
+use crate::nmodes::{nmodes_path, read_nmodes_file, Nmodes};
+use crate::output::{
+    gso_outputs, read_gso_output, swarm_directories, EventSocket, GSOEntry, GSOOutput,
+    RotationOutput, TrajectoryWriter, CONVERGENCE_HEADER, ROTATION_OUTPUTS,
+};
+use crate::parameters::ParameterOverrides;
+use crate::peptide::peptide_modes;
+use crate::policy::ResiduePolicy;
+use crate::pose::{apply_nmodes, atom_coordinates, ligand_pose, Pose};
+use crate::positions::{
+    check_dimensions, read_positions, swarm_files, OverlapCheck, SwarmGeometry,
+};
+use crate::presampling::fft_presampling;
+use crate::prmtop::Prmtop;
+use crate::profile::{enable_pose_timing, PROFILE_HEADER};
+use crate::propensity::{
+    default_propensities, read_propensities, InterfacePropensity, PropensityPartner,
+};
+use crate::protocol::CoarseToFine;
+use crate::pydock::PYDOCK;
+use crate::qt::Quaternion;
+use crate::refinement::{amber_inputs, gromacs_inputs};
+use crate::region::{DockingRegion, Shape};
+use crate::registry::{registered_methods, ScoringInput};
+use crate::restraints::{expand_restraints, residue_id, OrientationFilter};
+use crate::rmsd::{interface_atoms, interface_rmsd};
+use crate::sanitize::{write_report, Sanitizer};
+use crate::scoring::{
+    Calibrated, Calibration, ClashFilter, CompositeScore, Memoized, Method, Prescreened,
+    RestraintsMetric, Score, TermWeights, METHODS, RESTRAINTS_METRICS,
+};
+use crate::selftest::run_selftest;
+use crate::structure::{read_structure, write_complex, write_pdb, ComplexRemarks};
+use crate::swarm::{swarm_seed, DiversityInjection, NoiseSchedule};
+use crate::symmetry::{cn_pose, Symmetric};
+use crate::ternary::{place_second_ligand, Ternary};
+use crate::training::{write_potential, PairCounts};
+#[cfg(feature = "parquet")]
+use crate::trajectory::ParquetTrajectory;
+use crate::{build_scoring, GSO};
+use pdbtbx::PDB;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::env;
+use std::error::Error;
+use std::fs;
+use std::fs::File;
+use std::io;
+use std::io::{BufReader, BufWriter, Write};
+use std::panic::{self, AssertUnwindSafe};
+use std::path::{Path, PathBuf};
+use std::process::{self, Command, Stdio};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::thread;
+
+// Use 8MB as binary stack
+const STACK_SIZE: usize = 8 * 1024 * 1024;
+
+#[derive(Serialize, Deserialize, Debug)]
+struct SetupFile {
+    seed: Option<u64>,
+    anm_seed: u64,
+    ftdock_file: Option<String>,
+    noh: bool,
+    anm_rec: usize,
+    anm_lig: usize,
+    swarms: u32,
+    starting_points_seed: u32,
+    verbose_parser: bool,
+    noxt: bool,
+    now: bool,
+    restraints: Option<String>,
+    use_anm: bool,
+    glowworms: u32,
+    membrane: bool,
+    receptor_pdb: String,
+    ligand_pdb: String,
+    receptor_restraints: Option<HashMap<String, Vec<String>>>,
+    ligand_restraints: Option<HashMap<String, Vec<String>>>,
+    hotspots: Option<String>,
+    docking_region: Option<DockingRegionSetup>,
+    clash_filter: Option<ClashFilterSetup>,
+    calibration: Option<HashMap<String, CalibrationSetup>>,
+    consensus: Option<HashMap<String, f64>>,
+    compress_output: Option<bool>,
+    versioned_output: Option<bool>,
+    events: Option<String>,
+    convergence: Option<String>,
+    trajectory: Option<String>,
+    prescreen: Option<PrescreenSetup>,
+    dfire_grid: Option<GridSetup>,
+    dfire_shell: Option<ShellSetup>,
+    dfire_quantized: Option<bool>,
+    single_precision: Option<bool>,
+    fft_presampling: Option<FFTSetup>,
+    energy_cache: Option<EnergyCacheSetup>,
+    restraints_filter: Option<RestraintsFilterSetup>,
+    parameters: Option<String>,
+    prmtop: Option<PrmtopSetup>,
+    noise: Option<NoiseSetup>,
+    diversity: Option<DiversitySetup>,
+    vision_range_from_extent: Option<bool>,
+    softmax_temperature: Option<f64>,
+    movement_steps: Option<u32>,
+    sanitize: Option<SanitizeSetup>,
+    residue_policy: Option<String>,
+    restraints_metric: Option<String>,
+    term_weights: Option<TermWeightsSetup>,
+    interface_propensity: Option<PropensitySetup>,
+    peptide: Option<PeptideSetup>,
+    membrane_beads: Option<MembraneBeadsSetup>,
+    refinement: Option<RefinementSetup>,
+    second_ligand_pdb: Option<String>,
+    symmetry: Option<usize>,
+    rotation_output: Option<String>,
+    scoring_mask: Option<ScoringMaskSetup>,
+    scoring_weights: Option<ScoringWeightsSetup>,
+    antibody: Option<AntibodySetup>,
+    overlap_check: Option<OverlapCheckSetup>,
+    swarm_methods: Option<SwarmMethodsSetup>,
+    protocol: Option<ProtocolSetup>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+struct DiversitySetup {
+    threshold: Option<f64>,
+    min_steps: Option<u32>,
+    fraction: Option<f64>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+struct SanitizeSetup {
+    waters: Option<bool>,
+    hetero: Option<Vec<String>>,
+    altlocs: Option<bool>,
+    terminals: Option<bool>,
+    report: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+struct PropensitySetup {
+    weight: Option<f64>,
+    cutoff: Option<f64>,
+    table: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+struct PeptideSetup {
+    modes: Option<usize>,
+    translation_step: Option<f64>,
+    torsions: Option<bool>,
+    // ANM settings of the setup, the ones of the starting positions
+    #[serde(skip)]
+    positions_anm: (bool, usize, usize),
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+struct SwarmMethodsSetup {
+    methods: Option<HashMap<String, Vec<i32>>>,
+    file: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+struct MembraneBeadsSetup {
+    file: Option<String>,
+    planes: Option<[f64; 2]>,
+    spacing: Option<f64>,
+    margin: Option<f64>,
+    clearance: Option<f64>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+struct RefinementSetup {
+    top: Option<usize>,
+    iterations: Option<usize>,
+    memory: Option<usize>,
+    eps: Option<f64>,
+    max_step: Option<f64>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+struct ScoringMaskSetup {
+    receptor: Option<Vec<String>>,
+    ligand: Option<Vec<String>>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+struct TermWeightsSetup {
+    electrostatics: Option<f64>,
+    vdw: Option<f64>,
+    desolvation: Option<f64>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+struct ScoringWeightsSetup {
+    receptor: Option<Vec<WeightGroupSetup>>,
+    ligand: Option<Vec<WeightGroupSetup>>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+struct WeightGroupSetup {
+    chains: Option<Vec<String>>,
+    residues: Option<Vec<String>>,
+    weight: f64,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+struct AntibodySetup {
+    partner: Option<String>,
+    numbering: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+struct NoiseSetup {
+    temperature: f64,
+    decay: Option<f64>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+struct PrmtopSetup {
+    receptor: Option<String>,
+    ligand: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+struct RestraintsFilterSetup {
+    mode: String,
+    max_angle: Option<f64>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+struct OverlapCheckSetup {
+    mode: String,
+    cutoff: Option<f64>,
+    max_clashes: Option<usize>,
+    step: Option<f64>,
+    max_shift: Option<f64>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+struct ProtocolSetup {
+    coarse_steps: u32,
+    translation_step: Option<f64>,
+    top_clusters: Option<usize>,
+    spread: Option<f64>,
+    rotation_spread: Option<f64>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+struct EnergyCacheSetup {
+    size: Option<usize>,
+    resolution: Option<f64>,
+    angular_resolution: Option<f64>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+struct FFTSetup {
+    rotations: Option<usize>,
+    spacing: Option<f64>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+struct GridSetup {
+    spacing: Option<f64>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+struct ShellSetup {
+    radius: Option<f64>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+struct PrescreenSetup {
+    threshold: Option<f64>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+struct CalibrationSetup {
+    scale: Option<f64>,
+    offset: Option<f64>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+struct ClashFilterSetup {
+    cutoff: Option<f64>,
+    max_clashes: Option<usize>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+struct DockingRegionSetup {
+    shape: String,
+    center: [f64; 3],
+    size: Option<[f64; 3]>,
+    radius: Option<f64>,
+    penalty: Option<f64>,
+    reject: Option<bool>,
+}
+
+impl DockingRegionSetup {
+    fn shape(&self) -> Result<Shape, String> {
+        match &self.shape.to_lowercase()[..] {
+            "box" => match self.size {
+                Some(size) => Ok(Shape::Box {
+                    center: self.center,
+                    size,
+                }),
+                None => Err(String::from("box docking region requires a size")),
+            },
+            "sphere" => match self.radius {
+                Some(radius) => Ok(Shape::Sphere {
+                    center: self.center,
+                    radius,
+                }),
+                None => Err(String::from("sphere docking region requires a radius")),
+            },
+            shape => Err(format!("docking region shape {:?} not supported", shape)),
+        }
+    }
+}
+
+fn read_setup_from_file<P: AsRef<Path>>(path: P) -> Result<SetupFile, Box<dyn Error>> {
+    // Open the file in read-only mode with buffer.
+    let file = File::open(path)?;
+    let reader = BufReader::new(file);
+    // Read the JSON contents of the file as an instance of `SetupFile`.
+    let mut u: SetupFile = serde_json::from_reader(reader)?;
+    // The peptide mode always uses ANM, with its own ligand modes
+    if let Some(peptide) = u.peptide.as_mut() {
+        peptide.positions_anm = (u.use_anm, u.anm_rec, u.anm_lig);
+        if !u.use_anm {
+            u.anm_rec = 0;
+        }
+        u.use_anm = true;
+        u.anm_lig = peptide.modes.unwrap_or(DEFAULT_PEPTIDE_MODES);
+    }
+    // Return the `SetupFile`.
+    Ok(u)
+}
+
+fn parse_input_coordinates(swarm_filename: &str, setup: &SetupFile) -> Vec<Vec<f64>> {
+    let (use_anm, anm_rec, anm_lig) = match &setup.peptide {
+        Some(peptide) => peptide.positions_anm,
+        None => (setup.use_anm, setup.anm_rec, setup.anm_lig),
+    };
+    let positions = match read_positions(swarm_filename, use_anm, anm_rec, anm_lig) {
+        Ok(positions) => positions,
+        Err(e) => panic!(
+            "Error reading initial positions [{:?}]: {}",
+            swarm_filename, e
+        ),
+    };
+    if setup.peptide.is_none() {
+        return positions;
+    }
+    // Random starting extents of the peptide modes, as the ANM ones of the setup
+    let mut rng: StdRng = SeedableRng::seed_from_u64(setup.anm_seed);
+    positions
+        .into_iter()
+        .map(|mut position| {
+            position.truncate(7 + setup.anm_rec);
+            for _ in 0..setup.anm_lig {
+                position.push(rng.gen_range(-DEFAULT_PEPTIDE_EXTENT, DEFAULT_PEPTIDE_EXTENT));
+            }
+            position
+        })
+        .collect()
+}
+
+fn presample_positions(
+    positions: &mut [Vec<f64>],
+    receptor: &PDB,
+    ligand: &PDB,
+    fft: &FFTSetup,
+    seed: u64,
+) {
+    // Starting poses are searched around the center of the swarm and as far as its glowworms
+    let num_glowworms = positions.len() as f64;
+    let mut center = [0.0; 3];
+    for position in positions.iter() {
+        for k in 0..3 {
+            center[k] += position[k] / num_glowworms;
+        }
+    }
+    let spacing = fft.spacing.unwrap_or(DEFAULT_FFT_SPACING);
+    let radius = positions
+        .iter()
+        .map(|p| {
+            ((p[0] - center[0]).powi(2) + (p[1] - center[1]).powi(2) + (p[2] - center[2]).powi(2))
+                .sqrt()
+        })
+        .fold(spacing, f64::max);
+
+    let mut rng: StdRng = SeedableRng::seed_from_u64(seed);
+    let poses = fft_presampling(
+        receptor,
+        ligand,
+        &center,
+        radius,
+        positions.len(),
+        fft.rotations.unwrap_or(DEFAULT_FFT_ROTATIONS),
+        spacing,
+        &mut rng,
+    );
+    println!(
+        "FFT pre-sampling found {} starting poses for {} glowworms",
+        poses.len(),
+        positions.len()
+    );
+    // Glowworms without a pose keep their random start, ANM extents are not modified
+    for (position, pose) in positions.iter_mut().zip(poses.iter()) {
+        position[0..3].copy_from_slice(&pose.translation);
+        position[3] = pose.rotation.w;
+        position[4] = pose.rotation.x;
+        position[5] = pose.rotation.y;
+        position[6] = pose.rotation.z;
+    }
+}
+
+type Hotspots = HashMap<String, f64>;
+
+fn parse_hotspots(hotspots_filename: &str) -> Result<(Hotspots, Hotspots), Box<dyn Error>> {
+    // Each line contains the partner (R or L), the residue ID and its weight:
+    //   R A.ALA.23 1.5
+    let contents = fs::read_to_string(hotspots_filename)?;
+
+    let mut receptor_hotspots: Hotspots = HashMap::new();
+    let mut ligand_hotspots: Hotspots = HashMap::new();
+    for (i, line) in contents.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        if fields.len() != 3 {
+            return Err(format!("wrong format in line {}: {:?}", i + 1, line).into());
+        }
+        let weight = fields[2]
+            .parse::<f64>()
+            .map_err(|_| format!("wrong weight in line {}: {:?}", i + 1, fields[2]))?;
+        match fields[0] {
+            "R" => receptor_hotspots.insert(fields[1].to_string(), weight),
+            "L" => ligand_hotspots.insert(fields[1].to_string(), weight),
+            partner => {
+                return Err(format!("unknown partner {:?} in line {}", partner, i + 1).into())
+            }
+        };
+    }
+    Ok((receptor_hotspots, ligand_hotspots))
+}
+
+fn parse_swarm_methods(
+    swarm_methods_filename: &str,
+) -> Result<HashMap<i32, Method>, Box<dyn Error>> {
+    // Each line contains the swarm ID and the scoring method of that swarm:
+    //   12 dna
+    let contents = fs::read_to_string(swarm_methods_filename)?;
+
+    let mut methods: HashMap<i32, Method> = HashMap::new();
+    for (i, line) in contents.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        if fields.len() != 2 {
+            return Err(format!("wrong format in line {}: {:?}", i + 1, line).into());
+        }
+        let swarm_id = fields[0]
+            .parse::<i32>()
+            .map_err(|_| format!("wrong swarm ID in line {}: {:?}", i + 1, fields[0]))?;
+        let method = Method::from_name(fields[1])
+            .ok_or_else(|| format!("unknown method {:?} in line {}", fields[1], i + 1))?;
+        methods.insert(swarm_id, method);
+    }
+    Ok(methods)
+}
+
+// Scoring method assigned to the swarm in the setup, the one of the command line otherwise.
+// Assignments of the file take precedence over the ones of the setup.
+fn swarm_method(simulation_path: &str, setup: &SetupFile, swarm_id: i32, method: Method) -> Method {
+    let swarm_methods = match &setup.swarm_methods {
+        Some(swarm_methods) => swarm_methods,
+        None => return method,
+    };
+    let mut methods: HashMap<i32, Method> = HashMap::new();
+    for (name, swarms) in swarm_methods.methods.iter().flatten() {
+        let assigned = match Method::from_name(name) {
+            Some(assigned) => assigned,
+            None => panic!("Unknown method [{:?}] in swarm methods", name),
+        };
+        for swarm in swarms.iter() {
+            methods.insert(*swarm, assigned);
+        }
+    }
+    if let Some(file) = &swarm_methods.file {
+        let swarm_methods_filename = if simulation_path.is_empty() {
+            file.to_string()
+        } else {
+            format!("{}/{}", simulation_path, file)
+        };
+        println!("Reading swarm methods from {}", swarm_methods_filename);
+        match parse_swarm_methods(&swarm_methods_filename) {
+            Ok(file_methods) => methods.extend(file_methods),
+            Err(e) => {
+                panic!(
+                    "Error reading swarm methods file [{:?}]: {:?}",
+                    swarm_methods_filename,
+                    e.to_string()
+                );
+            }
+        }
+    }
+    methods.get(&swarm_id).copied().unwrap_or(method)
+}
+
+// Receptor and ligand residue propensities, from the built-in table or the given one
+#[allow(clippy::too_many_arguments)]
+fn interface_propensity(
+    simulation_path: &str,
+    setup: &PropensitySetup,
+    receptor: &PDB,
+    rec_nm: &Nmodes,
+    anm_rec: usize,
+    ligand: &PDB,
+    lig_nm: &Nmodes,
+    anm_lig: usize,
+) -> (PropensityPartner, PropensityPartner) {
+    let table = match &setup.table {
+        Some(table) => {
+            let table_filename = if simulation_path.is_empty() {
+                table.to_string()
+            } else {
+                format!("{}/{}", simulation_path, table)
+            };
+            println!("Reading interface propensities from {}", table_filename);
+            match read_propensities(&table_filename) {
+                Ok(table) => table,
+                Err(e) => {
+                    panic!(
+                        "Error reading interface propensities file [{:?}]: {:?}",
+                        table_filename,
+                        e.to_string()
+                    );
+                }
+            }
+        }
+        None => default_propensities(),
+    };
+    (
+        PropensityPartner::new(receptor, rec_nm.clone(), anm_rec, &table),
+        PropensityPartner::new(ligand, lig_nm.clone(), anm_lig, &table),
+    )
+}
+
+fn read_parameters(
+    simulation_path: &str,
+    setup: &SetupFile,
+    receptor: &PDB,
+    ligand: &PDB,
+) -> Option<ParameterOverrides> {
+    if setup.parameters.is_none() && setup.prmtop.is_none() {
+        return None;
+    }
+    let relative_path = |path: &str| {
+        if simulation_path.is_empty() {
+            path.to_string()
+        } else {
+            format!("{}/{}", simulation_path, path)
+        }
+    };
+    let mut parameters = ParameterOverrides::default();
+
+    // Topology parameters go first, so the ones of the overrides file take precedence
+    if let Some(prmtop) = &setup.prmtop {
+        for (path, structure, overrides) in [
+            (&prmtop.receptor, receptor, &mut parameters.receptor),
+            (&prmtop.ligand, ligand, &mut parameters.ligand),
+        ] {
+            let prmtop_filename = match path {
+                Some(path) => relative_path(path),
+                None => continue,
+            };
+            println!("Reading AMBER topology from {}", prmtop_filename);
+            let atoms =
+                Prmtop::read(&prmtop_filename).and_then(|prmtop| Ok(prmtop.overrides(structure)?));
+            match atoms {
+                Ok(atoms) => overrides.extend(atoms),
+                Err(e) => {
+                    panic!(
+                        "Error reading topology file [{:?}]: {:?}",
+                        prmtop_filename,
+                        e.to_string()
+                    );
+                }
+            }
+        }
+    }
+
+    if let Some(path) = &setup.parameters {
+        let parameters_filename = relative_path(path);
+        println!("Reading parameter overrides from {}", parameters_filename);
+        match ParameterOverrides::read(&parameters_filename) {
+            Ok(overrides) => {
+                parameters.receptor.extend(overrides.receptor);
+                parameters.ligand.extend(overrides.ligand);
+            }
+            Err(e) => {
+                panic!(
+                    "Error reading parameters file [{:?}]: {:?}",
+                    parameters_filename,
+                    e.to_string()
+                );
+            }
+        }
+    }
+    Some(parameters)
+}
+
+#[cfg(feature = "parquet")]
+fn open_trajectory(trajectory_filename: &str, setup: &SetupFile) -> Box<dyn TrajectoryWriter> {
+    let (rec_num_anm, lig_num_anm) = if setup.use_anm {
+        (setup.anm_rec, setup.anm_lig)
+    } else {
+        (0, 0)
+    };
+    match ParquetTrajectory::new(trajectory_filename, rec_num_anm, lig_num_anm) {
+        Ok(trajectory) => Box::new(trajectory),
+        Err(e) => panic!(
+            "Error creating trajectory file [{:?}]: {:?}",
+            trajectory_filename,
+            e.to_string()
+        ),
+    }
+}
+
+#[cfg(not(feature = "parquet"))]
+fn open_trajectory(_trajectory_filename: &str, _setup: &SetupFile) -> Box<dyn TrajectoryWriter> {
+    panic!("Trajectory output requires lightdock-rust to be built with the parquet feature");
+}
+
+fn structure_filename(simulation_path: &str, pdb: &str) -> String {
+    if simulation_path.is_empty() {
+        format!("{}{}", DEFAULT_LIGHTDOCK_PREFIX, pdb)
+    } else {
+        format!("{}/{}{}", simulation_path, DEFAULT_LIGHTDOCK_PREFIX, pdb)
+    }
+}
+
+fn read_structures(simulation_path: &str, setup: &SetupFile) -> (PDB, PDB) {
+    let receptor_filename = structure_filename(simulation_path, &setup.receptor_pdb);
+    // Parse receptor input PDB structure
+    println!("Reading receptor input structure: {}", receptor_filename);
+    let receptor = match read_structure(&receptor_filename) {
+        Ok(structure) => structure,
+        Err(e) => panic!("Error reading receptor structure: {}", e),
+    };
+
+    let ligand_filename = structure_filename(simulation_path, &setup.ligand_pdb);
+    // Parse ligand input PDB structure
+    println!("Reading ligand input structure: {}", ligand_filename);
+    let ligand = match read_structure(&ligand_filename) {
+        Ok(structure) => structure,
+        Err(e) => panic!("Error reading ligand structure: {}", e),
+    };
+
+    let (mut receptor, ligand) = match &setup.sanitize {
+        Some(sanitize) => sanitize_structures(simulation_path, sanitize, receptor, ligand),
+        None => (receptor, ligand),
+    };
+    if let Some(membrane) = &setup.membrane_beads {
+        add_membrane_beads(simulation_path, membrane, &mut receptor);
+    }
+    (receptor, ligand)
+}
+
+fn add_membrane_beads(simulation_path: &str, membrane: &MembraneBeadsSetup, receptor: &mut PDB) {
+    let beads = match (&membrane.file, membrane.planes) {
+        (Some(file), None) => {
+            let path = Path::new(simulation_path).join(file);
+            println!("Reading membrane beads: {}", path.display());
+            match read_beads(&path.to_string_lossy()) {
+                Ok(beads) => beads,
+                Err(e) => panic!("Error reading membrane beads: {}", e),
+            }
+        }
+        (None, Some(planes)) => {
+            let spacing = membrane.spacing.unwrap_or(DEFAULT_MEMBRANE_SPACING);
+            if spacing <= 0.0 {
+                panic!("Membrane bead spacing must be positive");
+            }
+            println!(
+                "Placing membrane beads on the planes z = {} and z = {}",
+                planes[0], planes[1]
+            );
+            plane_beads(
+                &atom_coordinates(receptor),
+                planes,
+                spacing,
+                membrane.margin.unwrap_or(DEFAULT_MEMBRANE_MARGIN),
+                membrane.clearance.unwrap_or(DEFAULT_MEMBRANE_CLEARANCE),
+            )
+        }
+        _ => panic!("Membrane beads require either a file or the z coordinates of two planes"),
+    };
+    println!("Adding {} membrane beads to the receptor", beads.len());
+    add_beads(receptor, &beads);
+}
+
+fn sanitize_structures(
+    simulation_path: &str,
+    sanitize: &SanitizeSetup,
+    mut receptor: PDB,
+    mut ligand: PDB,
+) -> (PDB, PDB) {
+    let sanitizer = Sanitizer {
+        waters: sanitize.waters.unwrap_or(true),
+        hetero: sanitize.hetero.clone().unwrap_or_default(),
+        altlocs: sanitize.altlocs.unwrap_or(true),
+        terminals: sanitize.terminals.unwrap_or(true),
+    };
+    let receptor_report = sanitizer.sanitize(&mut receptor);
+    let ligand_report = sanitizer.sanitize(&mut ligand);
+    println!(
+        "Sanitized structures: {} changes in receptor, {} in ligand",
+        receptor_report.len(),
+        ligand_report.len()
+    );
+
+    let report_filename = Path::new(simulation_path).join(
+        sanitize
+            .report
+            .as_deref()
+            .unwrap_or(DEFAULT_SANITIZE_REPORT),
+    );
+    let written = File::create(&report_filename).and_then(|file| {
+        let mut output = BufWriter::new(file);
+        write_report(&mut output, "receptor", &receptor_report)?;
+        write_report(&mut output, "ligand", &ligand_report)
+    });
+    if let Err(e) = written {
+        panic!(
+            "Error writing sanitization report [{:?}]: {}",
+            report_filename, e
+        );
+    }
+    (receptor, ligand)
+}
+
+// Policy for the residues not supported by the scoring function, --strict takes precedence
+fn residue_policy(setup: &SetupFile, strict: bool) -> ResiduePolicy {
+    if strict {
+        return ResiduePolicy::Strict;
+    }
+    match &setup.residue_policy {
+        Some(name) => match ResiduePolicy::from_name(name) {
+            Some(policy) => policy,
+            None => panic!(
+                "Unknown residue policy [{:?}], use drop, strict or map",
+                name
+            ),
+        },
+        None => ResiduePolicy::default(),
+    }
+}
+
+// Satisfaction of the active restraints biasing the scoring
+fn restraints_metric(setup: &SetupFile) -> RestraintsMetric {
+    match &setup.restraints_metric {
+        Some(name) => match RestraintsMetric::from_name(name) {
+            Some(metric) => metric,
+            None => {
+                let names: Vec<&str> = RESTRAINTS_METRICS
+                    .iter()
+                    .map(|metric| metric.name())
+                    .collect();
+                panic!(
+                    "Unknown restraints metric [{:?}], use {}",
+                    name,
+                    names.join(", ")
+                )
+            }
+        },
+        None => RestraintsMetric::default(),
+    }
+}
+
+// Weights of the energy terms of DNA and pyDock, the ones not given left at their default
+fn term_weights(setup: &SetupFile, method: &Method) -> TermWeights {
+    let mut weights = TermWeights::default();
+    let terms = match &setup.term_weights {
+        Some(terms) => terms,
+        None => return weights,
+    };
+    if !matches!(method, Method::DNA | Method::PYDOCK) {
+        panic!("Term weights are only supported by the DNA and PYDOCK scoring functions");
+    }
+    if terms.desolvation.is_some() && !matches!(method, Method::PYDOCK) {
+        panic!("Desolvation is only supported by the PYDOCK scoring function");
+    }
+    weights.electrostatics = terms.electrostatics.unwrap_or(weights.electrostatics);
+    weights.vdw = terms.vdw.unwrap_or(weights.vdw);
+    weights.desolvation = terms.desolvation.unwrap_or(weights.desolvation);
+    weights
+}
+
+// Scoring functions summed in a consensus score with their weights, the docking one first with
+// weight 1.0 unless given and the others in the order of METHODS. None if not set.
+fn consensus_weights(setup: &SetupFile, method: &Method) -> Option<Vec<(Method, f64)>> {
+    let weights = setup.consensus.as_ref()?;
+    let mut terms = vec![(*method, 1.0)];
+    for (name, &weight) in weights.iter() {
+        let term = match Method::from_name(name) {
+            Some(term) => term,
+            None => panic!("Consensus scoring function [{:?}] not supported", name),
+        };
+        if !weight.is_finite() {
+            panic!("Consensus weight of {} must be a number", name);
+        }
+        if term == *method {
+            terms[0].1 = weight;
+        } else {
+            terms.push((term, weight));
+        }
+    }
+    let order: Vec<Method> = METHODS.into_iter().chain(registered_methods()).collect();
+    terms[1..].sort_by_key(|(term, _)| order.iter().position(|known| known == term));
+    Some(terms)
+}
+
+// Scoring function of a method with its default options, stopping on the unknown ones
+fn default_scoring(method: &Method, input: ScoringInput) -> Box<dyn Score> {
+    match build_scoring(method, input) {
+        Ok(scoring) => scoring,
+        Err(e) => panic!("Error: {}", e),
+    }
+}
+
+// DFIRE scoring function with the given options, stopping if its potential cannot be loaded
+fn dfire_scoring(input: ScoringInput, options: DFIREOptions) -> Box<dyn Score> {
+    match DFIRE::new(input, options) {
+        Ok(scoring) => scoring,
+        Err(e) => panic!("Error: {}", e),
+    }
+}
+
+// Linear calibration of a scoring function if defined for its method
+fn calibrated(setup: &SetupFile, method: &Method, scoring: Box<dyn Score>) -> Box<dyn Score> {
+    let calibration = setup
+        .calibration
+        .as_ref()
+        .and_then(|calibrations| calibrations.get(method.name()));
+    match calibration {
+        Some(calibration) => {
+            let calibration = Calibration::new(
+                calibration.scale.unwrap_or(1.0),
+                calibration.offset.unwrap_or(0.0),
+            );
+            println!("Using calibration {:?} for {:?}", calibration, method);
+            Calibrated::new(scoring, calibration)
+        }
+        None => scoring,
+    }
+}
+
+// Options not supported when docking a second ligand
+fn check_second_ligand(setup: &SetupFile) -> Result<(), String> {
+    if setup.second_ligand_pdb.is_none() {
+        return Ok(());
+    }
+    let options = [
+        ("ANM", setup.use_anm),
+        ("prescreen", setup.prescreen.is_some()),
+        ("dfire_grid", setup.dfire_grid.is_some()),
+        ("dfire_shell", setup.dfire_shell.is_some()),
+        ("fft_presampling", setup.fft_presampling.is_some()),
+        ("interface_propensity", setup.interface_propensity.is_some()),
+        ("energy_cache", setup.energy_cache.is_some()),
+        ("calibration", setup.calibration.is_some()),
+        ("consensus", setup.consensus.is_some()),
+        ("docking_region", setup.docking_region.is_some()),
+        ("refinement", setup.refinement.is_some()),
+        ("symmetry", setup.symmetry.is_some()),
+    ];
+    let unsupported: Vec<&str> = options
+        .iter()
+        .filter(|(_, used)| *used)
+        .map(|(name, _)| *name)
+        .collect();
+    if !unsupported.is_empty() {
+        return Err(format!(
+            "docking a second ligand is not supported with {}",
+            unsupported.join(", ")
+        ));
+    }
+    Ok(())
+}
+
+// Second ligand of a ternary complex docking, None if not set
+fn read_second_ligand(
+    simulation_path: &str,
+    setup: &SetupFile,
+) -> Result<Option<PDB>, Box<dyn Error>> {
+    check_second_ligand(setup)?;
+    let second_ligand_pdb = match &setup.second_ligand_pdb {
+        Some(second_ligand_pdb) => second_ligand_pdb,
+        None => return Ok(None),
+    };
+    let filename = structure_filename(simulation_path, second_ligand_pdb);
+    println!("Reading second ligand input structure: {}", filename);
+    let structure = read_structure(&filename)
+        .map_err(|e| format!("reading second ligand structure {}: {}", filename, e))?;
+    Ok(Some(structure))
+}
+
+// Scoring function of a pair of partners without restraints, hot-spots nor normal modes
+fn plain_scoring(
+    method: &Method,
+    receptor: PDB,
+    ligand: PDB,
+    policy: ResiduePolicy,
+) -> Box<dyn Score> {
+    default_scoring(method, ScoringInput::new(receptor, ligand, policy))
+}
+
+// Structures and normal modes given to the scoring function, without the residues of the scoring
+// mask if any
+fn scoring_mask(
+    setup: &SetupFile,
+    receptor: PDB,
+    ligand: PDB,
+    rec_nm: Nmodes,
+    lig_nm: Nmodes,
+) -> (PDB, PDB, Nmodes, Nmodes) {
+    let mask = match &setup.scoring_mask {
+        Some(mask) => mask,
+        None => return (receptor, ligand, rec_nm, lig_nm),
+    };
+    let mut masked = Vec::new();
+    for (name, structure, nmodes, num_anm, entries) in [
+        ("receptor", receptor, rec_nm, setup.anm_rec, &mask.receptor),
+        ("ligand", ligand, lig_nm, setup.anm_lig, &mask.ligand),
+    ] {
+        let residues = match entries {
+            Some(entries) => expand_restraints(&structure, entries),
+            None => Vec::new(),
+        };
+        if residues.is_empty() {
+            masked.push((structure, nmodes));
+            continue;
+        }
+        let (structure, nmodes, num_masked) =
+            mask_structure(&structure, &nmodes, num_anm, &residues);
+        println!(
+            "Scoring mask: {} {} residues left out of the scoring",
+            num_masked, name
+        );
+        if num_masked < residues.len() {
+            eprintln!(
+                "Warning: {} residues of the {} scoring mask not found",
+                residues.len() - num_masked,
+                name
+            );
+        }
+        masked.push((structure, nmodes.into()));
+    }
+    let (ligand, lig_nm) = masked.pop().unwrap();
+    let (receptor, rec_nm) = masked.pop().unwrap();
+    (receptor, ligand, rec_nm, lig_nm)
+}
+
+// CDR residues of the antibody partner, and whether the antibody is the ligand
+fn antibody_cdrs(antibody: &AntibodySetup, receptor: &PDB, ligand: &PDB) -> (bool, Vec<String>) {
+    let partner = antibody.partner.as_deref().unwrap_or("receptor");
+    let (is_ligand, structure) = match &partner.to_lowercase()[..] {
+        "receptor" => (false, receptor),
+        "ligand" => (true, ligand),
+        partner => panic!("Antibody partner {:?} not supported", partner),
+    };
+    let numbering = match &antibody.numbering {
+        Some(name) => match Numbering::from_name(name) {
+            Some(numbering) => numbering,
+            None => panic!("Antibody numbering {:?} not supported", name),
+        },
+        None => Numbering::default(),
+    };
+    let chains = detect_cdrs(structure, numbering);
+    if chains.is_empty() {
+        panic!("No antibody heavy or light chain found in the {}", partner);
+    }
+    let mut cdrs: Vec<String> = Vec::new();
+    for chain in chains.iter() {
+        println!(
+            "Antibody {:?} chain {} with {} CDR residues ({} numbering)",
+            chain.chain_type,
+            chain.chain_id,
+            chain.cdrs.len(),
+            numbering.name()
+        );
+        cdrs.extend(chain.cdrs.iter().cloned());
+    }
+    (is_ligand, cdrs)
+}
+
+// Scoring weight of the residues of the weight groups of each partner, later groups taking
+// precedence over earlier ones
+fn scoring_weights(
+    setup: &SetupFile,
+    receptor: &PDB,
+    ligand: &PDB,
+) -> (HashMap<String, f64>, HashMap<String, f64>) {
+    let weights = match &setup.scoring_weights {
+        Some(weights) => weights,
+        None => return (HashMap::new(), HashMap::new()),
+    };
+    let mut partners = Vec::new();
+    for (name, structure, groups) in [
+        ("receptor", receptor, &weights.receptor),
+        ("ligand", ligand, &weights.ligand),
+    ] {
+        let mut residue_weights: HashMap<String, f64> = HashMap::new();
+        for group in groups.iter().flatten() {
+            if group.weight < 0.0 {
+                panic!("Scoring weights must not be negative");
+            }
+            if let Some(chains) = &group.chains {
+                for chain in structure
+                    .chains()
+                    .filter(|chain| chains.contains(&chain.id().to_string()))
+                {
+                    for residue in chain.residues() {
+                        residue_weights.insert(residue_id(chain, residue), group.weight);
+                    }
+                }
+            }
+            if let Some(residues) = &group.residues {
+                for res_id in expand_restraints(structure, residues) {
+                    residue_weights.insert(res_id, group.weight);
+                }
+            }
+        }
+        if !residue_weights.is_empty() {
+            println!(
+                "Scoring weights: {} {} residues weighted",
+                residue_weights.len(),
+                name
+            );
+        }
+        partners.push(residue_weights);
+    }
+    let lig_weights = partners.pop().unwrap();
+    let rec_weights = partners.pop().unwrap();
+    (rec_weights, lig_weights)
+}
+
+fn structure_radius(structure: &PDB) -> f64 {
+    atom_coordinates(structure)
+        .iter()
+        .map(|c| (c[0] * c[0] + c[1] * c[1] + c[2] * c[2]).sqrt())
+        .fold(0.0, f64::max)
+}
+
+fn read_nmodes(setup: &SetupFile, receptor: &PDB, ligand: &PDB) -> (Nmodes, Nmodes) {
+    let mut rec_nm = Nmodes::default();
+    let mut lig_nm = Nmodes::default();
+    if setup.use_anm {
+        if setup.anm_rec > 0 {
+            let path = nmodes_path(DEFAULT_REC_NM_FILE, DEFAULT_REC_NMD_FILE);
+            let num_atoms;
+            (rec_nm, num_atoms) = match read_nmodes_file(path, receptor, setup.anm_rec) {
+                Ok(nmodes) => nmodes,
+                Err(e) => {
+                    panic!("Error reading receptor ANM file [{:?}]: {}", path, e);
+                }
+            };
+            // Membrane beads added to the receptor do not move
+            if setup.membrane_beads.is_some() && num_atoms < receptor.atom_count() {
+                rec_nm = pad_nmodes(&rec_nm, setup.anm_rec, receptor.atom_count()).into();
+            } else if num_atoms != receptor.atom_count() {
+                panic!(
+                    "Receptor ANM file [{:?}] has modes for {} atoms, the receptor has {}",
+                    path,
+                    num_atoms,
+                    receptor.atom_count()
+                );
+            }
+        }
+        if let Some(peptide) = &setup.peptide {
+            lig_nm = peptide_modes(ligand, setup.anm_lig, peptide.torsions.unwrap_or(false)).into();
+        } else if setup.anm_lig > 0 {
+            let path = nmodes_path(DEFAULT_LIG_NM_FILE, DEFAULT_LIG_NMD_FILE);
+            let num_atoms;
+            (lig_nm, num_atoms) = match read_nmodes_file(path, ligand, setup.anm_lig) {
+                Ok(nmodes) => nmodes,
+                Err(e) => {
+                    panic!("Error reading ligand ANM file [{:?}]: {}", path, e);
+                }
+            };
+            if num_atoms != ligand.atom_count() {
+                panic!(
+                    "Ligand ANM file [{:?}] has modes for {} atoms, the ligand has {}",
+                    path,
+                    num_atoms,
+                    ligand.atom_count()
+                );
+            }
+        }
+    }
+    (rec_nm, lig_nm)
+}
+
+fn cluster(gso_filename: &str, translation_cutoff: f64, rotation_cutoff: f64) {
+    let output = match read_gso_output(gso_filename) {
+        Ok(output) => output,
+        Err(e) => {
+            eprintln!("Error reading GSO output [{:?}]: {}", gso_filename, e);
+            return;
+        }
+    };
+    let clusters = cluster_poses(&output.entries, translation_cutoff, rotation_cutoff);
+    println!("#Cluster  Members  Scoring  Glowworms");
+    for (i, cluster) in clusters.iter().enumerate() {
+        let members: Vec<String> = cluster.members.iter().map(|m| m.to_string()).collect();
+        println!(
+            "{}  {}  {:.8}  {}",
+            i,
+            cluster.members.len(),
+            output.entries[cluster.representative].scoring,
+            members.join(",")
+        );
+    }
+}
+
+fn export_trajectory(setup_filename: &str, swarm_directory: &str, glowworm: usize, prefix: &str) {
+    let setup = match read_setup_from_file(setup_filename) {
+        Ok(setup) => setup,
+        Err(e) => {
+            eprintln!(
+                "Error reading setup file [{:?}]: {:?}",
+                setup_filename,
+                e.to_string()
+            );
+            return;
+        }
+    };
+    let simulation_path = Path::new(setup_filename).parent().unwrap();
+    let (receptor, ligand) = read_structures(simulation_path.to_str().unwrap(), &setup);
+    let (rec_nm, lig_nm) = read_nmodes(&setup, &receptor, &ligand);
+    let (rec_num_anm, lig_num_anm) = if setup.use_anm {
+        (setup.anm_rec, setup.anm_lig)
+    } else {
+        (0, 0)
+    };
+
+    // Collect the saved steps of the swarm in order
+    let outputs = match gso_outputs(swarm_directory) {
+        Ok(outputs) => outputs,
+        Err(e) => {
+            eprintln!(
+                "Error reading swarm directory [{:?}]: {}",
+                swarm_directory, e
+            );
+            return;
+        }
+    };
+    if outputs.is_empty() {
+        eprintln!("No GSO output found in {:?}", swarm_directory);
+        return;
+    }
+
+    // Receptor and ligand poses of the glowworm at every saved step
+    let receptor_coordinates = atom_coordinates(&receptor);
+    let ligand_coordinates = atom_coordinates(&ligand);
+    let mut frames: Vec<Vec<[f64; 3]>> = Vec::new();
+    for (step, path) in outputs.iter() {
+        let output = match read_gso_output(path) {
+            Ok(output) => output,
+            Err(e) => {
+                eprintln!("Error reading GSO output [{:?}]: {}", path, e);
+                return;
+            }
+        };
+        let entry = match output.entries.get(glowworm) {
+            Some(entry) => entry,
+            None => {
+                eprintln!("Glowworm {} not found at step {}", glowworm, step);
+                return;
+            }
+        };
+        if entry.position.len() < 7 + rec_num_anm + lig_num_anm {
+            eprintln!(
+                "Wrong number of coordinates for glowworm {} at step {}",
+                glowworm, step
+            );
+            return;
+        }
+        let mut frame = receptor_coordinates.clone();
+        apply_nmodes(&mut frame, &rec_nm, &entry.position[7..7 + rec_num_anm]);
+        frame.extend(ligand_pose(
+            &ligand_coordinates,
+            &entry.translation(),
+            &entry.rotation(),
+            &lig_nm,
+            &entry.position[7 + rec_num_anm..7 + rec_num_anm + lig_num_anm],
+        ));
+        frames.push(frame);
+    }
+
+    // Topology is the complex at the first frame
+    let mut topology = receptor.clone();
+    for chain in ligand.chains() {
+        topology.model_mut(0).unwrap().add_chain(chain.clone());
+    }
+    let topology_filename = format!("{}.pdb", prefix);
+    let written = File::create(&topology_filename)
+        .and_then(|file| write_pdb(&mut BufWriter::new(file), &topology, Some(&frames[0])));
+    if let Err(e) = written {
+        eprintln!("Error writing topology [{:?}]: {}", topology_filename, e);
+        return;
+    }
+
+    let trajectory_filename = format!("{}.dcd", prefix);
+    let written = File::create(&trajectory_filename).and_then(|file| {
+        write_dcd(
+            &mut BufWriter::new(file),
+            &frames,
+            &format!("LightDock {} glowworm {}", swarm_directory, glowworm),
+        )
+    });
+    if let Err(e) = written {
+        eprintln!(
+            "Error writing trajectory [{:?}]: {}",
+            trajectory_filename, e
+        );
+        return;
+    }
+    println!(
+        "Glowworm {} trajectory ({} frames) written to {} and {}",
+        glowworm,
+        frames.len(),
+        topology_filename,
+        trajectory_filename
+    );
+}
+
+// Scoring function of the partners of a setup without restraints nor hot-spots, with its residue
+// weights, scoring mask, normal modes and parameter overrides
+fn setup_scoring(
+    setup: &SetupFile,
+    simulation_path: &str,
+    method: Method,
+    strict: bool,
+) -> Box<dyn Score> {
+    let (receptor, ligand) = read_structures(simulation_path, setup);
+    let (rec_nm, lig_nm) = read_nmodes(setup, &receptor, &ligand);
+    let parameters = read_parameters(simulation_path, setup, &receptor, &ligand);
+    let (rec_weights, lig_weights) = scoring_weights(setup, &receptor, &ligand);
+    let (receptor, ligand, rec_nm, lig_nm) = scoring_mask(setup, receptor, ligand, rec_nm, lig_nm);
+    let mut input = ScoringInput::new(receptor, ligand, residue_policy(setup, strict));
+    input.rec_weights = rec_weights;
+    input.rec_nmodes = rec_nm;
+    input.rec_num_anm = setup.anm_rec;
+    input.lig_weights = lig_weights;
+    input.lig_nmodes = lig_nm;
+    input.lig_num_anm = setup.anm_lig;
+    input.use_anm = setup.use_anm;
+    match method {
+        Method::DNA => DNA::new(input, parameters, false, TermWeights::default()),
+        Method::PYDOCK => PYDOCK::new(input, parameters, false, TermWeights::default()),
+        _ => default_scoring(&method, input),
+    }
+}
+
+fn energy_matrix(
+    setup_filename: &str,
+    gso_filename: &str,
+    glowworm: usize,
+    method: Method,
+    csv_filename: &str,
+    strict: bool,
+) {
+    let setup = match read_setup_from_file(setup_filename) {
+        Ok(setup) => setup,
+        Err(e) => {
+            eprintln!(
+                "Error reading setup file [{:?}]: {:?}",
+                setup_filename,
+                e.to_string()
+            );
+            return;
+        }
+    };
+    let output = match read_gso_output(gso_filename) {
+        Ok(output) => output,
+        Err(e) => {
+            eprintln!("Error reading GSO output [{:?}]: {}", gso_filename, e);
+            return;
+        }
+    };
+    let entry = match output.entries.get(glowworm) {
+        Some(entry) => entry,
+        None => {
+            eprintln!("Glowworm {} not found in {:?}", glowworm, gso_filename);
+            return;
+        }
+    };
+    let (rec_num_anm, lig_num_anm) = if setup.use_anm {
+        (setup.anm_rec, setup.anm_lig)
+    } else {
+        (0, 0)
+    };
+    if entry.position.len() < 7 + rec_num_anm + lig_num_anm {
+        eprintln!("Wrong number of coordinates for glowworm {}", glowworm);
+        return;
+    }
+
+    // The restraints, hot-spots and membrane of the setup bias the score of the glowworm but are
+    // not decomposed, so the matrix adds up to its energy without them
+    let simulation_path = Path::new(setup_filename).parent().unwrap();
+    let scoring = setup_scoring(&setup, simulation_path.to_str().unwrap(), method, strict);
+
+    let energies = match scoring.residue_energies(
+        &entry.translation(),
+        &entry.rotation(),
+        &entry.position[7..7 + rec_num_anm],
+        &entry.position[7 + rec_num_anm..7 + rec_num_anm + lig_num_anm],
+    ) {
+        Some(energies) => energies,
+        None => {
+            eprintln!("Error: {:?} does not support residue decomposition", method);
+            return;
+        }
+    };
+    let written =
+        File::create(csv_filename).and_then(|file| energies.write_csv(&mut BufWriter::new(file)));
+    if let Err(e) = written {
+        eprintln!("Error writing energy matrix [{:?}]: {}", csv_filename, e);
+        return;
+    }
+    println!(
+        "Glowworm {} {} energy without restraints, hot-spots and membrane {:.8} ({:.8} from residue pairs) written to {}",
+        glowworm,
+        method.name(),
+        energies.total(),
+        energies.total() - energies.offset,
+        csv_filename
+    );
+}
+
+// Scores of the poses of GSO output files (named gso_*) or position files, one pose per line as
+// the starting positions, with a scoring function other than the one of the docking
+fn rescore(
+    setup_filename: &str,
+    method: Method,
+    csv_filename: &str,
+    inputs: &[String],
+    strict: bool,
+) {
+    let setup = match read_setup_from_file(setup_filename) {
+        Ok(setup) => setup,
+        Err(e) => {
+            eprintln!(
+                "Error reading setup file [{:?}]: {:?}",
+                setup_filename,
+                e.to_string()
+            );
+            return;
+        }
+    };
+    let (rec_num_anm, lig_num_anm) = if setup.use_anm {
+        (setup.anm_rec, setup.anm_lig)
+    } else {
+        (0, 0)
+    };
+
+    // Poses of every input with their original score if known
+    let mut poses: Vec<Vec<Pose>> = Vec::new();
+    let mut scores: Vec<Vec<Option<f64>>> = Vec::new();
+    for input in inputs.iter() {
+        let is_gso_output = Path::new(input)
+            .file_name()
+            .and_then(|name| name.to_str())
+            .is_some_and(|name| name.starts_with("gso_"));
+        let entries: Vec<(Vec<f64>, Option<f64>)> = if is_gso_output {
+            match read_gso_output(input) {
+                Ok(output) => output
+                    .entries
+                    .into_iter()
+                    .map(|entry| (entry.position, Some(entry.scoring)))
+                    .collect(),
+                Err(e) => {
+                    eprintln!("Error reading GSO output [{:?}]: {}", input, e);
+                    return;
+                }
+            }
+        } else {
+            match read_positions(input, setup.use_anm, setup.anm_rec, setup.anm_lig) {
+                Ok(positions) => positions
+                    .into_iter()
+                    .map(|position| (position, None))
+                    .collect(),
+                Err(e) => {
+                    eprintln!("Error reading positions [{:?}]: {}", input, e);
+                    return;
+                }
+            }
+        };
+        let mut input_poses: Vec<Pose> = Vec::new();
+        let mut input_scores: Vec<Option<f64>> = Vec::new();
+        for (i, (position, scoring)) in entries.into_iter().enumerate() {
+            if position.len() < 7 + rec_num_anm + lig_num_anm {
+                eprintln!("Wrong number of coordinates for pose {} of {:?}", i, input);
+                return;
+            }
+            input_poses.push(Pose::from_position(
+                &position[..7 + rec_num_anm + lig_num_anm],
+                rec_num_anm,
+            ));
+            input_scores.push(scoring);
+        }
+        poses.push(input_poses);
+        scores.push(input_scores);
+    }
+
+    // Plain scoring function as for the energy matrix
+    let simulation_path = Path::new(setup_filename).parent().unwrap();
+    let scoring = setup_scoring(&setup, simulation_path.to_str().unwrap(), method, strict);
+    let mut output = match File::create(csv_filename) {
+        Ok(file) => BufWriter::new(file),
+        Err(e) => {
+            eprintln!("Error writing scores [{:?}]: {}", csv_filename, e);
+            return;
+        }
+    };
+    let mut num_poses = 0;
+    let mut written = writeln!(output, "source,pose,original,{}", method.name());
+    for (input, (input_poses, input_scores)) in inputs.iter().zip(poses.iter().zip(scores.iter())) {
+        let energies = scoring.energies(input_poses);
+        for (i, (energy, original)) in energies.iter().zip(input_scores.iter()).enumerate() {
+            let original = original.map_or(String::new(), |scoring| format!("{:.8}", scoring));
+            written = written
+                .and_then(|_| writeln!(output, "{},{},{},{:.8}", input, i, original, energy));
+        }
+        num_poses += input_poses.len();
+    }
+    if let Err(e) = written.and_then(|_| output.flush()) {
+        eprintln!("Error writing scores [{:?}]: {}", csv_filename, e);
+        return;
+    }
+    println!(
+        "{} poses of {} files scored with {} written to {}",
+        num_poses,
+        inputs.len(),
+        method.name(),
+        csv_filename
+    );
+}
+
+fn contact_map(setup_filename: &str, gso_filename: &str, csv_filename: &str, cutoff: f64) {
+    let setup = match read_setup_from_file(setup_filename) {
+        Ok(setup) => setup,
+        Err(e) => {
+            eprintln!(
+                "Error reading setup file [{:?}]: {:?}",
+                setup_filename,
+                e.to_string()
+            );
+            return;
+        }
+    };
+    let output = match read_gso_output(gso_filename) {
+        Ok(output) => output,
+        Err(e) => {
+            eprintln!("Error reading GSO output [{:?}]: {}", gso_filename, e);
+            return;
+        }
+    };
+    let simulation_path = Path::new(setup_filename).parent().unwrap();
+    let (receptor, ligand) = read_structures(simulation_path.to_str().unwrap(), &setup);
+    let (rec_nm, lig_nm) = read_nmodes(&setup, &receptor, &ligand);
+    let (rec_num_anm, lig_num_anm) = if setup.use_anm {
+        (setup.anm_rec, setup.anm_lig)
+    } else {
+        (0, 0)
+    };
+
+    let (receptor_residues, receptor_atoms) = atom_residues(&receptor);
+    let (ligand_residues, ligand_atoms) = atom_residues(&ligand);
+    let receptor_coordinates = atom_coordinates(&receptor);
+    let ligand_coordinates = atom_coordinates(&ligand);
+    let scores: Vec<f64> = output.entries.iter().map(|entry| entry.scoring).collect();
+    let mut map = ContactMap::new(&receptor_residues, &ligand_residues);
+    for (i, (entry, weight)) in output.entries.iter().zip(pose_weights(&scores)).enumerate() {
+        if entry.position.len() < 7 + rec_num_anm + lig_num_anm {
+            eprintln!("Wrong number of coordinates for glowworm {}", i);
+            return;
+        }
+        let mut receptor_pose = receptor_coordinates.clone();
+        apply_nmodes(
+            &mut receptor_pose,
+            &rec_nm,
+            &entry.position[7..7 + rec_num_anm],
+        );
+        let ligand_pose = ligand_pose(
+            &ligand_coordinates,
+            &entry.translation(),
+            &entry.rotation(),
+            &lig_nm,
+            &entry.position[7 + rec_num_anm..7 + rec_num_anm + lig_num_anm],
+        );
+        let contacts = residue_contacts(
+            &receptor_pose,
+            &receptor_atoms,
+            &ligand_pose,
+            &ligand_atoms,
+            cutoff,
+        );
+        map.add_pose(&contacts, weight);
+    }
+
+    let written =
+        File::create(csv_filename).and_then(|file| map.write_csv(&mut BufWriter::new(file)));
+    if let Err(e) = written {
+        eprintln!("Error writing contact map [{:?}]: {}", csv_filename, e);
+        return;
+    }
+    println!(
+        "{} residue contacts over {} poses written to {}",
+        map.contacts.len(),
+        output.entries.len(),
+        csv_filename
+    );
+}
+
+// Last saved poses of every swarm next to the setup file, the given number of best ones first
+fn top_poses(simulation_path: &str, num_poses: usize) -> Option<Vec<(u32, usize, GSOEntry)>> {
+    let directories = match swarm_directories(simulation_path) {
+        Ok(directories) => directories,
+        Err(e) => {
+            eprintln!("Error reading swarm directories: {}", e);
+            return None;
+        }
+    };
+    let mut poses: Vec<(u32, usize, GSOEntry)> = Vec::new();
+    for (swarm_id, directory) in directories.iter() {
+        match last_output(directory) {
+            Ok((_, output)) => poses.extend(
+                output
+                    .entries
+                    .into_iter()
+                    .enumerate()
+                    .map(|(glowworm, entry)| (*swarm_id, glowworm, entry)),
+            ),
+            Err(e) => eprintln!("Warning: skipping {:?}: {}", directory, e),
+        }
+    }
+    if poses.is_empty() {
+        eprintln!("No GSO output found in the swarm directories");
+        return None;
+    }
+    poses.sort_by(|a, b| b.2.scoring.total_cmp(&a.2.scoring));
+    poses.truncate(num_poses);
+    Some(poses)
+}
+
+fn rank(setup_filename: &str, num_poses: usize, irmsd_cutoff: f64) {
+    let setup = match read_setup_from_file(setup_filename) {
+        Ok(setup) => setup,
+        Err(e) => {
+            eprintln!(
+                "Error reading setup file [{:?}]: {:?}",
+                setup_filename,
+                e.to_string()
+            );
+            return;
+        }
+    };
+    let simulation_path = Path::new(setup_filename).parent().unwrap();
+    let simulation_path = simulation_path.to_str().unwrap();
+    let poses = match top_poses(simulation_path, num_poses) {
+        Some(poses) => poses,
+        None => return,
+    };
+
+    let (receptor, ligand) = read_structures(simulation_path, &setup);
+    let (rec_nm, lig_nm) = read_nmodes(&setup, &receptor, &ligand);
+    let (rec_num_anm, lig_num_anm) = if setup.use_anm {
+        (setup.anm_rec, setup.anm_lig)
+    } else {
+        (0, 0)
+    };
+    let receptor_coordinates = atom_coordinates(&receptor);
+    let ligand_coordinates = atom_coordinates(&ligand);
+    let mut complexes = Vec::new();
+    let mut interfaces: Vec<(Vec<usize>, Vec<usize>)> = Vec::new();
+    for (swarm_id, glowworm, entry) in poses.iter() {
+        if entry.position.len() < 7 + rec_num_anm + lig_num_anm {
+            eprintln!(
+                "Wrong number of coordinates for glowworm {} of swarm {}",
+                glowworm, swarm_id
+            );
+            return;
+        }
+        let complex = pose_coordinates(
+            &receptor_coordinates,
+            &rec_nm,
+            &entry.position[7..7 + rec_num_anm],
+            &ligand_coordinates,
+            &lig_nm,
+            &entry.position[7 + rec_num_anm..7 + rec_num_anm + lig_num_anm],
+            &entry.translation(),
+            &entry.rotation(),
+        );
+        interfaces.push(interface_atoms(
+            &complex.0,
+            &complex.1,
+            RANK_INTERFACE_CUTOFF,
+        ));
+        complexes.push(complex);
+    }
+
+    // Interface RMSD over the interface atoms of any of both poses
+    let union = |one: &[usize], two: &[usize]| -> Vec<usize> {
+        let mut atoms: Vec<usize> = one.iter().chain(two.iter()).copied().collect();
+        atoms.sort_unstable();
+        atoms.dedup();
+        atoms
+    };
+    let mut distances = vec![vec![0.0; poses.len()]; poses.len()];
+    for i in 0..poses.len() {
+        for j in i + 1..poses.len() {
+            let distance = interface_rmsd(
+                &complexes[i].0,
+                &complexes[j].0,
+                &complexes[i].1,
+                &complexes[j].1,
+                &union(&interfaces[i].0, &interfaces[j].0),
+                &union(&interfaces[i].1, &interfaces[j].1),
+            );
+            distances[i][j] = distance;
+            distances[j][i] = distance;
+        }
+    }
+
+    let clusters = hierarchical_clusters(&distances, irmsd_cutoff);
+    println!("#Cluster  Size  Swarm  Glowworm  Scoring  Members");
+    for (i, cluster) in clusters.iter().enumerate() {
+        let (swarm_id, glowworm, entry) = &poses[cluster.representative];
+        let members: Vec<String> = cluster
+            .members
+            .iter()
+            .map(|&m| format!("{}:{}", poses[m].0, poses[m].1))
+            .collect();
+        println!(
+            "{}  {}  {}  {}  {:.8}  {}",
+            i,
+            cluster.members.len(),
+            swarm_id,
+            glowworm,
+            entry.scoring,
+            members.join(",")
+        );
+    }
+}
+
+// Subdirectories of a benchmark with a setup file, one per case
+fn benchmark_cases(directory: &str) -> io::Result<Vec<PathBuf>> {
+    let mut cases: Vec<PathBuf> = fs::read_dir(directory)?
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| path.join("setup.json").is_file())
+        .collect();
+    cases.sort();
+    Ok(cases)
+}
+
+// Bound receptor and ligand of a case, named as in BM5: <case>_r_b.pdb and <case>_l_b.pdb
+fn reference_files(case: &Path) -> Option<(PathBuf, PathBuf)> {
+    let files: Vec<PathBuf> = fs::read_dir(case)
+        .ok()?
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .collect();
+    let find = |suffix: &str| {
+        files
+            .iter()
+            .find(|file| file.to_string_lossy().ends_with(suffix))
+            .cloned()
+    };
+    Some((find("_r_b.pdb")?, find("_l_b.pdb")?))
+}
+
+// Starting positions of every swarm of a case, in the case folder or its init folder
+fn starting_positions(case: &Path) -> Vec<String> {
+    let mut positions: Vec<(i32, String)> = Vec::new();
+    for folder in ["", "init"] {
+        if let Ok(entries) = fs::read_dir(case.join(folder)) {
+            for path in entries.filter_map(|entry| entry.ok().map(|entry| entry.path())) {
+                if let Some(swarm_id) = parse_swarm_id(&path) {
+                    let filename = path.file_name().unwrap().to_string_lossy().to_string();
+                    let relative = if folder.is_empty() {
+                        filename
+                    } else {
+                        format!("{}/{}", folder, filename)
+                    };
+                    positions.push((swarm_id, relative));
+                }
+            }
+        }
+    }
+    positions.sort();
+    positions
+        .into_iter()
+        .map(|(_, relative)| relative)
+        .collect()
+}
+
+// CAPRI metrics of the best poses of a case, from its folder
+fn evaluate_case(
+    receptor_reference: &Path,
+    ligand_reference: &Path,
+) -> Result<Vec<CapriMetrics>, Box<dyn Error>> {
+    let setup = read_setup_from_file("setup.json")?;
+    let num_poses = *BENCHMARK_TOP_POSES.iter().max().unwrap();
+    let poses = top_poses("", num_poses).ok_or("no poses to evaluate")?;
+    let (receptor, ligand) = read_structures("", &setup);
+    let (rec_nm, lig_nm) = read_nmodes(&setup, &receptor, &ligand);
+    let (rec_num_anm, lig_num_anm) = if setup.use_anm {
+        (setup.anm_rec, setup.anm_lig)
+    } else {
+        (0, 0)
+    };
+    let reference = CapriReference::new(
+        &receptor,
+        &ligand,
+        &read_structure(&receptor_reference.to_string_lossy())?,
+        &read_structure(&ligand_reference.to_string_lossy())?,
+    )?;
+    let receptor_coordinates = atom_coordinates(&receptor);
+    let ligand_coordinates = atom_coordinates(&ligand);
+    let mut metrics: Vec<CapriMetrics> = Vec::new();
+    for (swarm_id, glowworm, entry) in poses.iter() {
+        if entry.position.len() < 7 + rec_num_anm + lig_num_anm {
+            return Err(format!(
+                "wrong number of coordinates for glowworm {} of swarm {}",
+                glowworm, swarm_id
+            )
+            .into());
+        }
+        let (receptor_pose, ligand_pose) = pose_coordinates(
+            &receptor_coordinates,
+            &rec_nm,
+            &entry.position[7..7 + rec_num_anm],
+            &ligand_coordinates,
+            &lig_nm,
+            &entry.position[7 + rec_num_anm..7 + rec_num_anm + lig_num_anm],
+            &entry.translation(),
+            &entry.rotation(),
+        );
+        metrics.push(reference.evaluate(&receptor_pose, &ligand_pose));
+    }
+    Ok(metrics)
+}
+
+fn benchmark(directory: &str, run: Option<(u32, Method)>) {
+    let cases = match benchmark_cases(directory) {
+        Ok(cases) => cases,
+        Err(e) => {
+            eprintln!("Error reading benchmark directory [{:?}]: {}", directory, e);
+            return;
+        }
+    };
+    if cases.is_empty() {
+        eprintln!("No cases with a setup.json file found in {}", directory);
+        return;
+    }
+    let executable = match env::current_exe() {
+        Ok(executable) => executable,
+        Err(e) => {
+            eprintln!("Error locating the lightdock-rust executable: {}", e);
+            return;
+        }
+    };
+    let working_directory = env::current_dir().unwrap();
+
+    let mut results: Vec<(String, Vec<CapriMetrics>)> = Vec::new();
+    for case in cases.iter() {
+        let name = case.file_name().unwrap().to_string_lossy().to_string();
+        let (receptor_reference, ligand_reference) = match reference_files(case) {
+            Some(files) => files,
+            None => {
+                eprintln!(
+                    "Warning: skipping {}, no *_r_b.pdb and *_l_b.pdb reference",
+                    name
+                );
+                continue;
+            }
+        };
+        let case = working_directory.join(case);
+        if let Some((steps, method)) = &run {
+            for positions in starting_positions(&case) {
+                let status = Command::new(&executable)
+                    .args(["setup.json", &positions, &steps.to_string(), method.name()])
+                    .current_dir(&case)
+                    .stdout(Stdio::null())
+                    .status();
+                match status {
+                    Ok(status) if status.success() => {}
+                    _ => eprintln!("Warning: simulation of {} in {} failed", positions, name),
+                }
+            }
+        }
+        if let Err(e) = env::set_current_dir(&case) {
+            eprintln!("Warning: skipping {}: {}", name, e);
+            continue;
+        }
+        let metrics = evaluate_case(
+            &working_directory.join(receptor_reference),
+            &working_directory.join(ligand_reference),
+        );
+        env::set_current_dir(&working_directory).unwrap();
+        match metrics {
+            Ok(metrics) => results.push((name, metrics)),
+            Err(e) => eprintln!("Warning: skipping {}: {}", name, e),
+        }
+    }
+    if results.is_empty() {
+        eprintln!("No benchmark case could be evaluated");
+        return;
+    }
+
+    println!("#Case  Poses  Top1  Best  Rank  Fnat  L-RMSD  I-RMSD");
+    for (name, metrics) in results.iter() {
+        let (rank, best) = metrics
+            .iter()
+            .enumerate()
+            .rev()
+            .max_by_key(|(_, pose)| pose.class)
+            .unwrap();
+        println!(
+            "{}  {}  {}  {}  {}  {:.3}  {:.3}  {:.3}",
+            name,
+            metrics.len(),
+            metrics[0].class.name(),
+            best.class.name(),
+            rank + 1,
+            best.fnat,
+            best.lrmsd,
+            best.irmsd
+        );
+    }
+    let classes: Vec<Vec<CapriClass>> = results
+        .iter()
+        .map(|(_, metrics)| metrics.iter().map(|pose| pose.class).collect())
+        .collect();
+    println!("#Top  Acceptable  Medium  High  ({} cases)", classes.len());
+    for top in BENCHMARK_TOP_POSES {
+        let rates: Vec<String> = CAPRI_CLASSES
+            .iter()
+            .map(|class| format!("{:.1}%", 100.0 * success_rate(&classes, top, *class)))
+            .collect();
+        println!("{}  {}", top, rates.join("  "));
+    }
+}
+
+// PDB files given directly or found in the given directories
+fn complex_files(paths: &[String]) -> Result<Vec<String>, Box<dyn Error>> {
+    let mut files: Vec<String> = Vec::new();
+    for path in paths.iter() {
+        if !Path::new(path).is_dir() {
+            files.push(path.to_string());
+            continue;
+        }
+        let mut directory_files: Vec<String> = fs::read_dir(path)?
+            .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+            .filter(|file| file.extension().is_some_and(|extension| extension == "pdb"))
+            .map(|file| file.to_string_lossy().to_string())
+            .collect();
+        directory_files.sort();
+        files.extend(directory_files);
+    }
+    Ok(files)
+}
+
+fn train_potential(output_filename: &str, paths: &[String]) {
+    let files = match complex_files(paths) {
+        Ok(files) => files,
+        Err(e) => {
+            eprintln!("Error listing complexes: {}", e);
+            return;
+        }
+    };
+    let mut counts = PairCounts::default();
+    for file in files.iter() {
+        let structure = match read_structure(file) {
+            Ok(structure) => structure,
+            Err(e) => {
+                eprintln!("Error reading complex [{:?}]: {}", file, e);
+                return;
+            }
+        };
+        if structure.chain_count() < 2 {
+            eprintln!("Warning: complex {} has a single chain, skipped", file);
+            continue;
+        }
+        let num_pairs = counts.add_structure(&structure);
+        println!("{}: {} atom pairs", file, num_pairs);
+    }
+    if counts.num_pairs == 0 {
+        eprintln!("Error: no atom pairs found in the given complexes");
+        return;
+    }
+    let potential = counts.potential();
+    let result = File::create(output_filename)
+        .and_then(|file| write_potential(&mut BufWriter::new(file), &potential));
+    match result {
+        Ok(()) => println!(
+            "Potential from {} atom pairs of {} complexes written to {}",
+            counts.num_pairs, counts.num_structures, output_filename
+        ),
+        Err(e) => eprintln!("Error writing potential [{:?}]: {}", output_filename, e),
+    }
+}
+
+fn export_top(setup_filename: &str, output_directory: &str, num_poses: usize) {
+    let setup = match read_setup_from_file(setup_filename) {
+        Ok(setup) => setup,
+        Err(e) => {
+            eprintln!(
+                "Error reading setup file [{:?}]: {:?}",
+                setup_filename,
+                e.to_string()
+            );
+            return;
+        }
+    };
+    let simulation_path = Path::new(setup_filename).parent().unwrap();
+    let simulation_path = simulation_path.to_str().unwrap();
+    let poses = match top_poses(simulation_path, num_poses) {
+        Some(poses) => poses,
+        None => return,
+    };
+    if let Err(e) = fs::create_dir_all(output_directory) {
+        eprintln!(
+            "Error creating output directory [{:?}]: {}",
+            output_directory, e
+        );
+        return;
+    }
+
+    let (receptor, ligand) = read_structures(simulation_path, &setup);
+    let (rec_nm, lig_nm) = read_nmodes(&setup, &receptor, &ligand);
+    let (rec_num_anm, lig_num_anm) = if setup.use_anm {
+        (setup.anm_rec, setup.anm_lig)
+    } else {
+        (0, 0)
+    };
+    let receptor_coordinates = atom_coordinates(&receptor);
+    let ligand_coordinates = atom_coordinates(&ligand);
+    let mut complexes: Vec<String> = Vec::new();
+    println!("#Rank  Swarm  Glowworm  Scoring  File");
+    for (i, (swarm_id, glowworm, entry)) in poses.iter().enumerate() {
+        if entry.position.len() < 7 + rec_num_anm + lig_num_anm {
+            eprintln!(
+                "Wrong number of coordinates for glowworm {} of swarm {}",
+                glowworm, swarm_id
+            );
+            return;
+        }
+        let (receptor_pose, ligand_pose) = pose_coordinates(
+            &receptor_coordinates,
+            &rec_nm,
+            &entry.position[7..7 + rec_num_anm],
+            &ligand_coordinates,
+            &lig_nm,
+            &entry.position[7 + rec_num_anm..7 + rec_num_anm + lig_num_anm],
+            &entry.translation(),
+            &entry.rotation(),
+        );
+        let remarks = ComplexRemarks {
+            swarm: *swarm_id as usize,
+            glowworm: *glowworm,
+            scoring: entry.scoring,
+        };
+        let complex = format!("top_{}.pdb", i + 1);
+        let path = Path::new(output_directory).join(&complex);
+        let written = File::create(&path).and_then(|file| {
+            write_complex(
+                &mut BufWriter::new(file),
+                &receptor,
+                &receptor_pose,
+                &ligand,
+                &ligand_pose,
+                &remarks,
+                true,
+            )
+        });
+        if let Err(e) = written {
+            eprintln!("Error writing complex [{:?}]: {}", path, e);
+            return;
+        }
+        println!(
+            "{}  {}  {}  {:.8}  {}",
+            i + 1,
+            swarm_id,
+            glowworm,
+            entry.scoring,
+            complex
+        );
+        complexes.push(complex);
+    }
+
+    // Minimization inputs next to the complexes
+    for (filename, contents) in gromacs_inputs(&complexes)
+        .into_iter()
+        .chain(amber_inputs(&complexes))
+    {
+        let path = Path::new(output_directory).join(&filename);
+        if let Err(e) = fs::write(&path, contents) {
+            eprintln!("Error writing [{:?}]: {}", path, e);
+            return;
+        }
+    }
+    println!(
+        "{} complexes and GROMACS/AMBER minimization inputs written to {}",
+        complexes.len(),
+        output_directory
+    );
+}
+
+fn list_scorers() {
+    let yes_no = |value: bool| if value { "yes" } else { "no" };
+    for method in METHODS.into_iter().chain(registered_methods()) {
+        let data_files = if method.data_files().is_empty() {
+            String::from("none")
+        } else {
+            method.data_files().join(", ")
+        };
+        println!("{}", method.name());
+        println!("    {}", method.description());
+        println!("    data files: {}", data_files);
+        println!("    hydrogens:  {}", yes_no(method.needs_hydrogens()));
+        println!(
+            "    charges:    {}",
+            if method.uses_charges() {
+                "yes, assigned from built-in AMBER tables"
+            } else {
+                "no"
+            }
+        );
+    }
+}
+
+fn selftest() -> bool {
+    let results = run_selftest();
+    for result in results.iter() {
+        let status = match &result.energy {
+            Ok(energy) => format!(
+                "{:.6} (expected {:.6}) {}",
+                energy,
+                result.expected,
+                if result.passed() { "OK" } else { "FAILED" }
+            ),
+            Err(e) => format!("FAILED: {}", e),
+        };
+        println!(
+            "{:<8}{}  {}  [{:.2} s]",
+            result.method.name(),
+            result.complex,
+            status,
+            result.elapsed.as_secs_f64()
+        );
+    }
+    let failed = results.iter().filter(|result| !result.passed()).count();
+    if failed == 0 {
+        println!("All {} scoring functions passed", results.len());
+    } else {
+        println!("{} of {} scoring functions failed", failed, results.len());
+    }
+    failed == 0
+}
+
+fn info(setup_filename: &str, method: Method, strict: bool) {
+    let setup = match read_setup_from_file(setup_filename) {
+        Ok(setup) => setup,
+        Err(e) => {
+            eprintln!(
+                "Error reading setup file [{:?}]: {:?}",
+                setup_filename,
+                e.to_string()
+            );
+            return;
+        }
+    };
+    let simulation_path = Path::new(setup_filename).parent().unwrap();
+    let simulation_path = simulation_path.to_str().unwrap();
+    let (receptor, ligand) = read_structures(simulation_path, &setup);
+    let (rec_nm, lig_nm) = read_nmodes(&setup, &receptor, &ligand);
+    let parameters =
+        read_parameters(simulation_path, &setup, &receptor, &ligand).unwrap_or_default();
+    let (rec_num_anm, lig_num_anm) = if setup.use_anm {
+        (setup.anm_rec, setup.anm_lig)
+    } else {
+        (0, 0)
+    };
+
+    println!("Scoring function {}", method.name());
+    for (name, structure, restraints, nmodes, num_anm, overrides) in [
+        (
+            "Receptor",
+            &receptor,
+            &setup.receptor_restraints,
+            &rec_nm,
+            rec_num_anm,
+            &parameters.receptor,
+        ),
+        (
+            "Ligand",
+            &ligand,
+            &setup.ligand_restraints,
+            &lig_nm,
+            lig_num_anm,
+            &parameters.ligand,
+        ),
+    ] {
+        let (active, passive) = match restraints {
+            Some(restraints) => (
+                expand_restraints(structure, &restraints["active"]),
+                expand_restraints(structure, &restraints["passive"]),
+            ),
+            None => (Vec::new(), Vec::new()),
+        };
+        let info = partner_info(
+            &method,
+            structure,
+            &active,
+            &passive,
+            nmodes,
+            num_anm,
+            overrides,
+            residue_policy(&setup, strict),
+        );
+        println!("{}", name);
+        println!(
+            "    structure:  {} chains, {} residues, {} atoms ({} hydrogens)",
+            info.chains, info.residues, info.atoms, info.hydrogens
+        );
+        println!("    membrane:   {} beads", info.membrane_beads);
+        println!(
+            "    scored:     {} residues, {} atoms, {} left out",
+            info.scored_residues, info.scored_atoms, info.dropped_atoms
+        );
+        println!(
+            "    parameters: {} atoms from built-in tables, {} from overrides",
+            info.builtin_atoms, info.overridden_atoms
+        );
+        println!(
+            "    restraints: {} active, {} passive, {} not found",
+            info.active_restraints,
+            info.passive_restraints,
+            info.unresolved_restraints.len()
+        );
+        for restraint in info.unresolved_restraints.iter() {
+            println!("        {}", restraint);
+        }
+        println!(
+            "    ANM:        {} modes for {} atoms",
+            info.num_anm, info.nmodes_atoms
+        );
+    }
+}
+
+// Entry point of the lightdock-rust binary, also for downstream binaries registering their own
+// scoring functions (see registry::register_scoring) before parsing the command line
+pub fn main() {
+    // Spawn thread with explicit stack size
+    let child = thread::Builder::new()
+        .stack_size(STACK_SIZE)
+        .spawn(run)
+        .unwrap();
+
+    // Wait for thread to join
+    child.join().unwrap();
+}
+
+fn run() {
+    env_logger::init();
+    // Parse command line
+    let mut args: Vec<String> = env::args().collect();
+    // --strict makes residues not supported by the scoring function an error
+    let strict = args.iter().any(|arg| arg == "--strict");
+    args.retain(|arg| arg != "--strict");
+    // --profile writes the time of every phase of the GSO steps to the swarm directory
+    let profile = args.iter().any(|arg| arg == "--profile");
+    args.retain(|arg| arg != "--profile");
+    if args.len() > 1 && args[1] == "--list-scorers" {
+        list_scorers();
+        return;
+    }
+    if args.len() > 1 && args[1] == "selftest" {
+        if !selftest() {
+            process::exit(1);
+        }
+        return;
+    }
+    if args.len() > 1 && args[1] == "info" {
+        if args.len() != 4 {
+            eprintln!(
+                "Wrong command line. Usage: {} info setup_filename method [--strict]",
+                args[0]
+            );
+            return;
+        }
+        match Method::from_name(&args[3]) {
+            Some(method) => info(&args[2], method, strict),
+            None => eprintln!(
+                "Error: method not supported, see {} --list-scorers",
+                args[0]
+            ),
+        }
+        return;
+    }
+    if args.len() > 1 && args[1] == "export-trajectory" {
+        if args.len() != 6 {
+            eprintln!(
+                "Wrong command line. Usage: {} export-trajectory setup_filename swarm_directory glowworm output_prefix",
+                args[0]
+            );
+            return;
+        }
+        match args[4].parse::<usize>() {
+            Ok(glowworm) => export_trajectory(&args[2], &args[3], glowworm, &args[5]),
+            Err(_) => eprintln!("Error: glowworm argument must be a number"),
+        }
+        return;
+    }
+    if args.len() > 1 && args[1] == "energy-matrix" {
+        if args.len() != 7 {
+            eprintln!(
+                "Wrong command line. Usage: {} energy-matrix setup_filename gso_output glowworm method output_csv [--strict]",
+                args[0]
+            );
+            return;
+        }
+        let glowworm = match args[4].parse::<usize>() {
+            Ok(glowworm) => glowworm,
+            Err(_) => {
+                eprintln!("Error: glowworm argument must be a number");
+                return;
+            }
+        };
+        match Method::from_name(&args[5]) {
+            Some(method) => energy_matrix(&args[2], &args[3], glowworm, method, &args[6], strict),
+            None => eprintln!(
+                "Error: method not supported, see {} --list-scorers",
+                args[0]
+            ),
+        }
+        return;
+    }
+    if args.len() > 1 && args[1] == "rescore" {
+        if args.len() < 6 {
+            eprintln!(
+                "Wrong command line. Usage: {} rescore setup_filename method output_csv poses_file [poses_file ...] [--strict]",
+                args[0]
+            );
+            return;
+        }
+        match Method::from_name(&args[3]) {
+            Some(method) => rescore(&args[2], method, &args[4], &args[5..], strict),
+            None => eprintln!(
+                "Error: method not supported, see {} --list-scorers",
+                args[0]
+            ),
+        }
+        return;
+    }
+    if args.len() > 1 && args[1] == "contact-map" {
+        if args.len() != 5 && args.len() != 6 {
+            eprintln!(
+                "Wrong command line. Usage: {} contact-map setup_filename gso_output output_csv [cutoff]",
+                args[0]
+            );
+            return;
+        }
+        let cutoff = match args.get(5).map(|cutoff| cutoff.parse::<f64>()) {
+            None => DEFAULT_CONTACT_CUTOFF,
+            Some(Ok(cutoff)) => cutoff,
+            Some(Err(_)) => {
+                eprintln!("Error: cutoff argument must be a number");
+                return;
+            }
+        };
+        contact_map(&args[2], &args[3], &args[4], cutoff);
+        return;
+    }
+    if args.len() > 1 && args[1] == "rank" {
+        if args.len() < 3 || args.len() > 5 {
+            eprintln!(
+                "Wrong command line. Usage: {} rank setup_filename [num_poses [irmsd_cutoff]]",
+                args[0]
+            );
+            return;
+        }
+        let num_poses = match args.get(3).map(|n| n.parse::<usize>()) {
+            None => DEFAULT_RANK_POSES,
+            Some(Ok(n)) => n,
+            Some(Err(_)) => {
+                eprintln!("Error: number of poses must be a number");
+                return;
+            }
+        };
+        let cutoff = match args.get(4).map(|cutoff| cutoff.parse::<f64>()) {
+            None => DEFAULT_RANK_IRMSD_CUTOFF,
+            Some(Ok(cutoff)) => cutoff,
+            Some(Err(_)) => {
+                eprintln!("Error: cutoff argument must be a number");
+                return;
+            }
+        };
+        rank(&args[2], num_poses, cutoff);
+        return;
+    }
+    if args.len() > 1 && args[1] == "export-top" {
+        if args.len() != 4 && args.len() != 5 {
+            eprintln!(
+                "Wrong command line. Usage: {} export-top setup_filename output_directory [num_poses]",
+                args[0]
+            );
+            return;
+        }
+        let num_poses = match args.get(4).map(|n| n.parse::<usize>()) {
+            None => DEFAULT_EXPORT_POSES,
+            Some(Ok(n)) => n,
+            Some(Err(_)) => {
+                eprintln!("Error: number of poses must be a number");
+                return;
+            }
+        };
+        export_top(&args[2], &args[3], num_poses);
+        return;
+    }
+    if args.len() > 1 && args[1] == "benchmark" {
+        if args.len() != 3 && args.len() != 5 {
+            eprintln!(
+                "Wrong command line. Usage: {} benchmark benchmark_directory [steps method]",
+                args[0]
+            );
+            return;
+        }
+        let run = if args.len() == 5 {
+            let steps = match args[3].parse::<u32>() {
+                Ok(steps) => steps,
+                Err(_) => {
+                    eprintln!("Error: steps argument must be a number");
+                    return;
+                }
+            };
+            match Method::from_name(&args[4]) {
+                Some(method) => Some((steps, method)),
+                None => {
+                    eprintln!(
+                        "Error: method not supported, see {} --list-scorers",
+                        args[0]
+                    );
+                    return;
+                }
+            }
+        } else {
+            None
+        };
+        benchmark(&args[2], run);
+        return;
+    }
+    if args.len() > 1 && args[1] == "train-potential" {
+        if args.len() < 4 {
+            eprintln!(
+                "Wrong command line. Usage: {} train-potential output_file complex [complex ...]",
+                args[0]
+            );
+            return;
+        }
+        train_potential(&args[2], &args[3..]);
+        return;
+    }
+    if args.len() > 1 && args[1] == "cluster" {
+        if args.len() != 3 && args.len() != 5 {
+            eprintln!(
+                "Wrong command line. Usage: {} cluster gso_output [translation_cutoff rotation_cutoff]",
+                args[0]
+            );
+            return;
+        }
+        let cutoffs = if args.len() == 5 {
+            match (args[3].parse::<f64>(), args[4].parse::<f64>()) {
+                (Ok(translation), Ok(rotation)) => (translation, rotation),
+                _ => {
+                    eprintln!("Error: cutoff arguments must be numbers");
+                    return;
+                }
+            }
+        } else {
+            (
+                DEFAULT_CLUSTER_TRANSLATION_CUTOFF,
+                DEFAULT_CLUSTER_ROTATION_CUTOFF,
+            )
+        };
+        cluster(&args[2], cutoffs.0, cutoffs.1);
+        return;
+    }
+    // --threads N runs up to N swarms at the same time when several swarm files are given
+    let mut num_threads: Option<usize> = None;
+    if let Some(i) = args.iter().position(|arg| arg == "--threads") {
+        match args.get(i + 1).map(|n| n.parse::<usize>()) {
+            Some(Ok(n)) if n > 0 => num_threads = Some(n),
+            _ => {
+                eprintln!("Error: --threads requires a positive number of threads");
+                return;
+            }
+        }
+        args.drain(i..i + 2);
+    }
+    // --extend N continues the last saved step of the swarm for N more steps
+    let mut extend = false;
+    if let Some(i) = args.iter().position(|arg| arg == "--extend") {
+        if i + 1 >= args.len() {
+            eprintln!("Error: --extend requires the number of steps");
+            return;
+        }
+        let steps = args.remove(i + 1);
+        args.remove(i);
+        if args.len() == 4 {
+            args.insert(3, steps);
+        }
+        extend = true;
+    }
+    match args.len() {
+        5 => {
+            let mut setup_filename = &args[1];
+            let swarm_filename = &args[2];
+            // Jobs packed as an archive run from the folder they are unpacked into
+            let bundle_setup = String::from("setup.json");
+            if is_bundle(setup_filename) {
+                let directory = match unpack_bundle(setup_filename) {
+                    Ok(directory) => directory,
+                    Err(e) => {
+                        eprintln!("Error unpacking bundle [{:?}]: {}", setup_filename, e);
+                        return;
+                    }
+                };
+                println!("Running from bundle folder {}", directory.display());
+                if let Err(e) = env::set_current_dir(&directory) {
+                    eprintln!("Error entering bundle folder: {}", e);
+                    return;
+                }
+                setup_filename = &bundle_setup;
+            }
+            let num_steps = &args[3];
+            // parse the number
+            let steps: u32 = match num_steps.parse() {
+                Ok(n) => n,
+                Err(_) => {
+                    eprintln!("Error: steps argument must be a number");
+                    return;
+                }
+            };
+            // parse the type
+            let method = match Method::from_name(&args[4]) {
+                Some(method) => method,
+                None => {
+                    eprintln!(
+                        "Error: method not supported, see {} --list-scorers",
+                        args[0]
+                    );
+                    return;
+                }
+            };
+
+            // Load setup
+            let setup = match read_setup_from_file(setup_filename) {
+                Ok(setup) => setup,
+                Err(e) => {
+                    eprintln!(
+                        "Error reading setup file [{:?}]: {:?}",
+                        setup_filename,
+                        e.to_string()
+                    );
+                    return;
+                }
+            };
+            if let Err(e) = check_second_ligand(&setup) {
+                eprintln!("Error: {}", e);
+                return;
+            }
+
+            // Simulation path
+            let simulation_path = Path::new(setup_filename).parent().unwrap();
+
+            // Several swarms as a comma-separated list or a pattern of swarm files
+            if !swarm_filename.contains([',', '*', '?']) {
+                simulate(
+                    simulation_path.to_str().unwrap(),
+                    &setup,
+                    swarm_filename,
+                    steps,
+                    method,
+                    extend,
+                    strict,
+                    profile,
+                );
+                return;
+            }
+            let mut swarm_filenames = match swarm_files(swarm_filename) {
+                Ok(files) => files,
+                Err(e) => {
+                    eprintln!("Error listing swarm files [{:?}]: {}", swarm_filename, e);
+                    return;
+                }
+            };
+            if swarm_filenames.is_empty() {
+                eprintln!("Error: no swarm files found for {:?}", swarm_filename);
+                return;
+            }
+            swarm_filenames.sort_by_key(|f| parse_swarm_id(Path::new(f)));
+            let num_threads = num_threads
+                .unwrap_or_else(|| thread::available_parallelism().map_or(1, |n| n.get()));
+            println!(
+                "Running {} swarms in {} threads",
+                swarm_filenames.len(),
+                num_threads.min(swarm_filenames.len())
+            );
+            let failed = simulate_swarms(
+                simulation_path.to_str().unwrap(),
+                &setup,
+                &swarm_filenames,
+                steps,
+                method,
+                extend,
+                strict,
+                profile,
+                num_threads,
+            );
+            if !failed.is_empty() {
+                eprintln!(
+                    "Error: {} of {} swarms failed: {}",
+                    failed.len(),
+                    swarm_filenames.len(),
+                    failed.join(", ")
+                );
+                process::exit(1);
+            }
+        }
+        _ => {
+            eprintln!(
+                "Wrong command line. Usage: {0} setup_filename swarm_filename steps method [--strict] [--profile]
+       {0} setup_filename swarm_filename --extend steps method [--strict] [--profile]
+       {0} setup_filename swarm_files steps method [--threads N] [--strict] [--profile]
+       {0} --list-scorers
+       {0} selftest
+       {0} info setup_filename method [--strict]
+       {0} export-trajectory setup_filename swarm_directory glowworm output_prefix
+       {0} energy-matrix setup_filename gso_output glowworm method output_csv [--strict]
+       {0} rescore setup_filename method output_csv poses_file [poses_file ...] [--strict]
+       {0} contact-map setup_filename gso_output output_csv [cutoff]
+       {0} rank setup_filename [num_poses [irmsd_cutoff]]
+       {0} export-top setup_filename output_directory [num_poses]
+       {0} benchmark benchmark_directory [steps method]
+       {0} train-potential output_file complex [complex ...]
+       {0} cluster gso_output [translation_cutoff rotation_cutoff]",
+                args[0]
+            );
+        }
+    }
+}
+
+// Runs the swarms of the given files in a pool of threads, each one writing to its own swarm
+// directory. Returns the files of the swarms that failed.
+#[allow(clippy::too_many_arguments)]
+fn simulate_swarms(
+    simulation_path: &str,
+    setup: &SetupFile,
+    swarm_filenames: &[String],
+    steps: u32,
+    method: Method,
+    extend: bool,
+    strict: bool,
+    profile: bool,
+    num_threads: usize,
+) -> Vec<String> {
+    let next = AtomicUsize::new(0);
+    let failed: Mutex<Vec<String>> = Mutex::new(Vec::new());
+    thread::scope(|scope| {
+        for _ in 0..num_threads.min(swarm_filenames.len()) {
+            thread::Builder::new()
+                .stack_size(STACK_SIZE)
+                .spawn_scoped(scope, || {
+                    while let Some(swarm_filename) =
+                        swarm_filenames.get(next.fetch_add(1, Ordering::SeqCst))
+                    {
+                        // A failing swarm does not stop the others
+                        let result = panic::catch_unwind(AssertUnwindSafe(|| {
+                            simulate(
+                                simulation_path,
+                                setup,
+                                swarm_filename,
+                                steps,
+                                method,
+                                extend,
+                                strict,
+                                profile,
+                            )
+                        }));
+                        if result.is_err() {
+                            failed.lock().unwrap().push(swarm_filename.clone());
+                        }
+                    }
+                })
+                .expect("Error spawning swarm thread");
+        }
+    });
+    let mut failed = failed.into_inner().unwrap();
+    failed.sort_by_key(|f| parse_swarm_id(Path::new(f)));
+    failed
+}
+
+fn parse_swarm_id(path: &Path) -> Option<i32> {
+    path.file_name()
+        .and_then(|s| s.to_str())
+        .and_then(|s| s.strip_prefix("initial_positions_"))
+        .and_then(|s| s.strip_suffix(".dat"))
+        .and_then(|s| s.parse::<i32>().ok())
+}
+
+fn last_output(swarm_directory: &str) -> Result<(u32, GSOOutput), Box<dyn Error>> {
+    let outputs = gso_outputs(swarm_directory)?;
+    let (step, path) = outputs.last().ok_or("no GSO output found")?;
+    Ok((*step, read_gso_output(path)?))
+}
+
+#[allow(clippy::too_many_arguments)]
+fn simulate(
+    simulation_path: &str,
+    setup: &SetupFile,
+    swarm_filename: &str,
+    steps: u32,
+    method: Method,
+    extend: bool,
+    strict: bool,
+    profile: bool,
+) {
+    println!("Reading starting positions from {:?}", swarm_filename);
+    let file_path = Path::new(swarm_filename);
+    let swarm_id = parse_swarm_id(file_path).expect("Could not parse swarm from swarm filename");
+    println!("Swarm ID {:?}", swarm_id);
+
+    // Mixed campaigns may score some swarms with a different method
+    let method = swarm_method(simulation_path, setup, swarm_id, method);
+
+    // Every swarm has its own random sequence, derived from the setup seed and the swarm ID
+    let mut seed: u64 = swarm_seed(setup.seed.unwrap_or(DEFAULT_SEED), swarm_id as u64);
+    println!("Swarm seed {}", seed);
+    let swarm_directory = format!("swarm_{}", swarm_id);
+
+    if !fs::metadata(&swarm_directory)
+        .map(|m| m.is_dir())
+        .unwrap_or(false)
+    {
+        eprintln!(
+            "Output directory does not exist for swarm {:?}, creating it",
+            swarm_id
+        );
+        fs::create_dir(&swarm_directory).expect("Error creating directory");
+    }
+
+    println!("Writing to swarm dir {:?}", swarm_directory);
+    let mut positions = parse_input_coordinates(swarm_filename, setup);
+
+    // Continue from the last saved step of a previous run
+    let resumed = if extend {
+        let (step, output) = match last_output(&swarm_directory) {
+            Ok(last) => last,
+            Err(e) => panic!(
+                "Error reading last GSO output of {:?}: {}",
+                swarm_directory, e
+            ),
+        };
+        println!(
+            "Extending swarm from step {} with {} glowworms",
+            step,
+            output.entries.len()
+        );
+        positions = Vec::new();
+        for (i, entry) in output.entries.iter().enumerate() {
+            if let Err(e) =
+                check_dimensions(&entry.position, setup.use_anm, setup.anm_rec, setup.anm_lig)
+            {
+                panic!("Wrong position of glowworm {} at step {}: {}", i, step, e);
+            }
+            positions.push(entry.position.clone());
+        }
+        // Do not repeat the random numbers of the previous run
+        seed = seed.wrapping_add(step as u64);
+        Some((step, output.entries))
+    } else {
+        None
+    };
+
+    let (receptor, ligand) = read_structures(simulation_path, setup);
+    let second_ligand = match read_second_ligand(simulation_path, setup) {
+        Ok(second_ligand) => second_ligand,
+        Err(e) => panic!("Error: {}", e),
+    };
+
+    // Report where the swarm starts with respect to the receptor
+    let geometry = SwarmGeometry::new(&positions, &atom_coordinates(&receptor));
+    println!(
+        "Swarm center ({:.3}, {:.3}, {:.3}), radius {:.3} A, {:.3} A to the receptor",
+        geometry.center[0],
+        geometry.center[1],
+        geometry.center[2],
+        geometry.radius,
+        geometry.surface_distance
+    );
+    let ligand_radius = structure_radius(&ligand);
+    if let Some(problem) = geometry.problem(ligand_radius) {
+        eprintln!("Warning: {}", problem);
+    }
+
+    // Replace random starting poses by the best ones of an exhaustive FFT search
+    if let (Some(fft), None) = (&setup.fft_presampling, &resumed) {
+        presample_positions(&mut positions, &receptor, &ligand, fft, seed);
+    }
+
+    // Read ANM data if activated
+    let (rec_nm, lig_nm) = read_nmodes(setup, &receptor, &ligand);
+
+    // Restraints
+    let mut rec_active_restraints: Vec<String> = match &setup.receptor_restraints {
+        Some(restraints) => expand_restraints(&receptor, &restraints["active"]),
+        None => Vec::new(),
+    };
+    let rec_passive_restraints: Vec<String> = match &setup.receptor_restraints {
+        Some(restraints) => expand_restraints(&receptor, &restraints["passive"]),
+        None => Vec::new(),
+    };
+    let mut lig_active_restraints: Vec<String> = match &setup.ligand_restraints {
+        Some(restraints) => expand_restraints(&ligand, &restraints["active"]),
+        None => Vec::new(),
+    };
+    let lig_passive_restraints: Vec<String> = match &setup.ligand_restraints {
+        Some(restraints) => expand_restraints(&ligand, &restraints["passive"]),
+        None => Vec::new(),
+    };
+
+    // CDR loops of an antibody as active restraints
+    let antibody_ligand = match &setup.antibody {
+        Some(antibody) => {
+            let (is_ligand, cdrs) = antibody_cdrs(antibody, &receptor, &ligand);
+            let restraints = if is_ligand {
+                &mut lig_active_restraints
+            } else {
+                &mut rec_active_restraints
+            };
+            for cdr in cdrs {
+                if !restraints.contains(&cdr) {
+                    restraints.push(cdr);
+                }
+            }
+            is_ligand
+        }
+        None => false,
+    };
+
+    // Starting poses with the ligand restraints facing away from the receptor. The CDRs of an
+    // antibody ligand are turned towards the receptor even without a restraints filter.
+    let restraints_filter = match (&setup.restraints_filter, antibody_ligand) {
+        (Some(restraints_filter), _) => {
+            Some((restraints_filter.mode.clone(), restraints_filter.max_angle))
+        }
+        (None, true) => Some((String::from("reseed"), None)),
+        (None, false) => None,
+    };
+    if let (Some((mode, max_angle)), None) = (&restraints_filter, &resumed) {
+        let reseed = match &mode.to_lowercase()[..] {
+            "drop" => false,
+            "reseed" => true,
+            mode => panic!("Restraints filter mode {:?} not supported", mode),
+        };
+        let filter = OrientationFilter::new(
+            &receptor,
+            &rec_active_restraints,
+            &ligand,
+            &lig_active_restraints,
+            max_angle.unwrap_or(DEFAULT_RESTRAINTS_MAX_ANGLE),
+        );
+        match filter {
+            Some(filter) => {
+                let mut rng: StdRng = SeedableRng::seed_from_u64(seed);
+                let num_away = filter.apply(&mut positions, reseed, &mut rng);
+                println!(
+                    "{} starting poses facing away from the restraints ({}), {} glowworms",
+                    num_away,
+                    mode,
+                    positions.len()
+                );
+                if positions.is_empty() {
+                    panic!("No starting poses left after filtering by restraints");
+                }
+            }
+            None => eprintln!("Warning: restraints filter requires ligand active restraints"),
+        }
+    }
+
+    // Starting poses with the ligand buried in the receptor, flagged or moved away
+    if let (Some(overlap), None) = (&setup.overlap_check, &resumed) {
+        let repair = match &overlap.mode.to_lowercase()[..] {
+            "flag" => false,
+            "repair" => true,
+            mode => panic!("Overlap check mode {:?} not supported", mode),
+        };
+        let check = OverlapCheck::new(
+            &receptor,
+            &ligand,
+            ClashFilter::new(
+                overlap.cutoff.unwrap_or(DEFAULT_CLASH_CUTOFF),
+                overlap.max_clashes.unwrap_or(DEFAULT_OVERLAP_MAX_CLASHES),
+            ),
+        );
+        let (overlapping, unresolved) = if repair {
+            check.repair(
+                &mut positions,
+                overlap.step.unwrap_or(DEFAULT_OVERLAP_STEP),
+                overlap.max_shift.unwrap_or(DEFAULT_OVERLAP_MAX_SHIFT),
+            )
+        } else {
+            let overlapping = check.overlapping(&positions);
+            (overlapping.clone(), overlapping)
+        };
+        println!(
+            "{} starting poses overlapping the receptor ({}), {} glowworms",
+            overlapping.len(),
+            overlap.mode,
+            positions.len()
+        );
+        if !unresolved.is_empty() {
+            let glowworms: Vec<String> = unresolved.iter().map(|i| i.to_string()).collect();
+            eprintln!(
+                "Warning: glowworms {} start overlapping the receptor",
+                glowworms.join(", ")
+            );
+        }
+    }
+
+    // Hot-spot residues
+    let (rec_hotspots, lig_hotspots) = match &setup.hotspots {
+        Some(hotspots) => {
+            let hotspots_filename = if simulation_path.is_empty() {
+                hotspots.to_string()
+            } else {
+                format!("{}/{}", simulation_path, hotspots)
+            };
+            println!("Reading hot-spot residues from {}", hotspots_filename);
+            match parse_hotspots(&hotspots_filename) {
+                Ok(hotspots) => hotspots,
+                Err(e) => {
+                    panic!(
+                        "Error reading hot-spots file [{:?}]: {:?}",
+                        hotspots_filename,
+                        e.to_string()
+                    );
+                }
+            }
+        }
+        None => (HashMap::new(), HashMap::new()),
+    };
+
+    // Weights of the contributions of chains and regions of each partner
+    let (rec_weights, lig_weights) = scoring_weights(setup, &receptor, &ligand);
+
+    // Per-atom parameter overrides
+    if (setup.parameters.is_some() || setup.prmtop.is_some())
+        && matches!(
+            method,
+            Method::DFIRE | Method::DFIRE2 | Method::SIPPER | Method::TOBI | Method::Registered(_)
+        )
+    {
+        panic!("Parameter overrides are only supported by the DNA and PYDOCK scoring functions");
+    }
+    let parameters = read_parameters(simulation_path, setup, &receptor, &ligand);
+
+    // Masked residues are kept in the structures but left out of the scoring
+    let (receptor, ligand, rec_nm, lig_nm) = scoring_mask(setup, receptor, ligand, rec_nm, lig_nm);
+
+    // Clash pre-filter
+    let clash_filter = setup.clash_filter.as_ref().map(|filter| {
+        ClashFilter::new(
+            filter.cutoff.unwrap_or(DEFAULT_CLASH_CUTOFF),
+            filter.max_clashes.unwrap_or(DEFAULT_MAX_CLASHES),
+        )
+    });
+    if let Some(filter) = &clash_filter {
+        println!("Using clash pre-filter {:?}", filter);
+    }
+
+    // Scoring function
+    println!("Loading {:?} scoring function", method);
+    if setup.prescreen.is_some() && !matches!(method, Method::DFIRE) {
+        panic!("Pre-screening is only supported by the DFIRE scoring function");
+    }
+    if setup.dfire_grid.is_some() && !matches!(method, Method::DFIRE) {
+        panic!("Receptor grid is only supported by the DFIRE scoring function");
+    }
+    let grid_spacing = setup.dfire_grid.as_ref().map(|grid| {
+        let spacing = grid.spacing.unwrap_or(DEFAULT_GRID_SPACING);
+        println!("Using DFIRE receptor grid with {} A spacing", spacing);
+        spacing
+    });
+    if setup.dfire_shell.is_some() && !matches!(method, Method::DFIRE) {
+        panic!("Distance shell is only supported by the DFIRE scoring function");
+    }
+    if setup.dfire_shell.is_some() && setup.dfire_grid.is_some() {
+        panic!("Distance shell and receptor grid can not be used together");
+    }
+    let shell_radius = setup.dfire_shell.as_ref().map(|shell| {
+        let radius = shell.radius.unwrap_or(DEFAULT_SHELL_RADIUS);
+        if radius <= 0.0 {
+            panic!("Distance shell radius must be positive");
+        }
+        println!("Using DFIRE distance shell of {} A", radius);
+        radius
+    });
+    let quantized = setup.dfire_quantized.unwrap_or(false);
+    if quantized && !matches!(method, Method::DFIRE) {
+        panic!("Quantized potential is only supported by the DFIRE scoring function");
+    }
+    if quantized {
+        println!("Using quantized DFIRE potential");
+    }
+    let single_precision = setup.single_precision.unwrap_or(false);
+    if single_precision && !matches!(method, Method::DFIRE | Method::DNA) {
+        panic!("Single precision is only supported by the DFIRE and DNA scoring functions");
+    }
+    if single_precision && (quantized || grid_spacing.is_some() || shell_radius.is_some()) {
+        panic!(
+            "Single precision can not be used with the DFIRE grid, shell or quantized potential"
+        );
+    }
+    if single_precision {
+        println!("Scoring in single precision");
+    }
+    let policy = residue_policy(setup, strict);
+    if policy != ResiduePolicy::default() {
+        println!("Using {} policy for nonstandard residues", policy.name());
+    }
+    let restraints_metric = restraints_metric(setup);
+    if restraints_metric != RestraintsMetric::default() {
+        println!(
+            "Using {} metric for the restraints",
+            restraints_metric.name()
+        );
+    }
+    let term_weights = term_weights(setup, &method);
+    if term_weights != TermWeights::default() {
+        println!(
+            "Using term weights: electrostatics {}, van der Waals {}, desolvation {}",
+            term_weights.electrostatics, term_weights.vdw, term_weights.desolvation
+        );
+    }
+    let propensity = setup.interface_propensity.as_ref().map(|propensity| {
+        interface_propensity(
+            simulation_path,
+            propensity,
+            &receptor,
+            &rec_nm,
+            setup.anm_rec,
+            &ligand,
+            &lig_nm,
+            setup.anm_lig,
+        )
+    });
+    if let Some(order) = setup.symmetry {
+        if order < 2 {
+            panic!("Symmetry order must be at least 2");
+        }
+        if setup.use_anm {
+            panic!("Symmetric assemblies are not supported with ANM");
+        }
+        if receptor.atom_count() != ligand.atom_count() {
+            eprintln!(
+                "Warning: receptor and ligand are not the same subunit for symmetric docking"
+            );
+        }
+    }
+
+    let input = ScoringInput {
+        receptor,
+        rec_active_restraints,
+        rec_passive_restraints,
+        rec_hotspots,
+        rec_weights,
+        rec_nmodes: rec_nm,
+        rec_num_anm: setup.anm_rec,
+        ligand,
+        lig_active_restraints,
+        lig_passive_restraints,
+        lig_hotspots,
+        lig_weights,
+        lig_nmodes: lig_nm,
+        lig_num_anm: setup.anm_lig,
+        use_anm: setup.use_anm,
+        clash_filter,
+        restraints_metric,
+        policy,
+    };
+
+    // Global search with the reduced DFIRE model, whose best clusters seed the swarm
+    if let (Some(protocol), None) = (&setup.protocol, &resumed) {
+        if second_ligand.is_some() || setup.symmetry.is_some() {
+            panic!("Two-stage protocol is not supported with a second ligand or symmetry");
+        }
+        let protocol = CoarseToFine::new(
+            protocol.coarse_steps,
+            protocol
+                .translation_step
+                .unwrap_or(DEFAULT_PROTOCOL_TRANSLATION_STEP),
+            protocol
+                .top_clusters
+                .unwrap_or(DEFAULT_PROTOCOL_TOP_CLUSTERS),
+            DEFAULT_CLUSTER_TRANSLATION_CUTOFF,
+            DEFAULT_CLUSTER_ROTATION_CUTOFF,
+            protocol.spread.unwrap_or(DEFAULT_PROTOCOL_SPREAD),
+            protocol
+                .rotation_spread
+                .unwrap_or(DEFAULT_PROTOCOL_ROTATION_SPREAD),
+        );
+        if protocol.coarse_steps == 0 || protocol.top_clusters == 0 {
+            panic!("Two-stage protocol requires coarse steps and clusters");
+        }
+        if protocol.translation_step <= 0.0
+            || protocol.spread < 0.0
+            || protocol.rotation_spread < 0.0
+        {
+            panic!("Two-stage protocol translation step must be positive and spreads not negative");
+        }
+        let coarse = dfire_scoring(
+            ScoringInput {
+                rec_nmodes: Nmodes::default(),
+                rec_num_anm: 0,
+                lig_nmodes: Nmodes::default(),
+                lig_num_anm: 0,
+                use_anm: false,
+                clash_filter: None,
+                ..input.clone()
+            },
+            DFIREOptions {
+                reduced: true,
+                ..DFIREOptions::default()
+            },
+        );
+        let coarse_directory = format!("{}/coarse", swarm_directory);
+        fs::create_dir_all(&coarse_directory).expect("Error creating directory");
+        println!(
+            "Coarse stage of {} steps with translation step {}, writing to {:?}",
+            protocol.coarse_steps, protocol.translation_step, coarse_directory
+        );
+        let entries = protocol.coarse_search(&positions, seed, &coarse, coarse_directory);
+        let clusters = protocol.best_clusters(&entries);
+        for (i, cluster) in clusters.iter().enumerate() {
+            println!(
+                "Coarse cluster {}: {} members, scoring {:.8}",
+                i,
+                cluster.members.len(),
+                entries[cluster.representative].scoring
+            );
+        }
+        // Do not repeat the random numbers of the coarse stage
+        seed = seed.wrapping_add(protocol.coarse_steps as u64);
+        let num_nmodes = if setup.use_anm {
+            setup.anm_rec + setup.anm_lig
+        } else {
+            0
+        };
+        let mut rng: StdRng = SeedableRng::seed_from_u64(seed);
+        positions =
+            protocol.focused_positions(&entries, &clusters, positions.len(), num_nmodes, &mut rng);
+        println!(
+            "Fine stage from {} glowworms around the {} best coarse clusters",
+            positions.len(),
+            clusters.len()
+        );
+    }
+
+    // Other scoring functions of the consensus, with their default options
+    let consensus = consensus_weights(setup, &method);
+    let consensus_terms: Vec<(Box<dyn Score>, f64)> = consensus
+        .iter()
+        .flat_map(|terms| terms[1..].iter())
+        .map(|&(term, weight)| {
+            println!("Adding {:?} scoring function with weight {}", term, weight);
+            let scoring = default_scoring(
+                &term,
+                ScoringInput {
+                    clash_filter: None,
+                    ..input.clone()
+                },
+            );
+            (calibrated(setup, &term, scoring), weight)
+        })
+        .collect();
+
+    // Structures of the pairs of partners with the second ligand
+    let ternary =
+        second_ligand.map(|second| (input.receptor.clone(), input.ligand.clone(), second));
+    let scoring = match method {
+        Method::DFIRE => {
+            // Reduced backbone and CB model used as a fast first pass
+            let coarse = setup.prescreen.as_ref().map(|prescreen| {
+                let threshold = prescreen.threshold.unwrap_or(DEFAULT_PRESCREEN_THRESHOLD);
+                println!(
+                    "Using reduced DFIRE pre-screening with threshold {}",
+                    threshold
+                );
+                let coarse = dfire_scoring(
+                    ScoringInput {
+                        clash_filter: None,
+                        ..input.clone()
+                    },
+                    DFIREOptions {
+                        reduced: true,
+                        quantized,
+                        ..DFIREOptions::default()
+                    },
+                );
+                (coarse, threshold)
+            });
+            let full = dfire_scoring(
+                input,
+                DFIREOptions {
+                    reduced: false,
+                    grid_spacing,
+                    shell_radius,
+                    quantized,
+                    single_precision,
+                },
+            );
+            match coarse {
+                Some((coarse, threshold)) => Prescreened::new(coarse, full, threshold),
+                None => full,
+            }
+        }
+        Method::DNA => DNA::new(input, parameters, single_precision, term_weights),
+        Method::PYDOCK => PYDOCK::new(input, parameters, false, term_weights),
+        _ => default_scoring(&method, input),
+    };
+
+    // Weighted sum of the scoring functions of the consensus, each one calibrated on its own
+    let scoring = match &consensus {
+        Some(terms) => {
+            println!("Using consensus of {} scoring functions", terms.len());
+            let mut composite = vec![(calibrated(setup, &method, scoring), terms[0].1)];
+            composite.extend(consensus_terms);
+            CompositeScore::new(composite)
+        }
+        None => scoring,
+    };
+
+    // Score of a subunit against its symmetry mates
+    let scoring = match setup.symmetry {
+        Some(order) => {
+            println!("Docking a C{} symmetric assembly", order);
+            for position in positions.iter_mut() {
+                let rotation = Quaternion::new(position[3], position[4], position[5], position[6]);
+                let (translation, rotation) = cn_pose(&position[..3], &rotation, order);
+                position[..3].copy_from_slice(&translation);
+                position[3..7].copy_from_slice(&[rotation.w, rotation.x, rotation.y, rotation.z]);
+            }
+            Symmetric::new(scoring, order)
+        }
+        None => scoring,
+    };
+
+    // Sum of the interactions of the receptor and both ligands
+    let scoring = match ternary {
+        Some((receptor, ligand, second)) => {
+            println!("Docking a second ligand, scoring all pairs of partners");
+            // Starting poses of the second ligand next to the first one
+            let distance = ligand_radius + structure_radius(&second);
+            let mut rng: StdRng = SeedableRng::seed_from_u64(seed);
+            for position in positions.iter_mut().filter(|position| position.len() == 7) {
+                position.extend(place_second_ligand(&position[..3], distance, &mut rng).to_vec());
+            }
+            Ternary::new(
+                scoring,
+                plain_scoring(&method, receptor, second.clone(), policy),
+                plain_scoring(&method, ligand, second, policy),
+            )
+        }
+        None => scoring,
+    };
+
+    // Reward interfaces enriched in high propensity residues
+    let scoring = match (propensity, &setup.interface_propensity) {
+        (Some((receptor, ligand)), Some(propensity)) => {
+            let weight = propensity.weight.unwrap_or(DEFAULT_PROPENSITY_WEIGHT);
+            let cutoff = propensity.cutoff.unwrap_or(DEFAULT_PROPENSITY_CUTOFF);
+            println!(
+                "Using interface propensity term with weight {} and cutoff {} A",
+                weight, cutoff
+            );
+            InterfacePropensity::new(scoring, receptor, ligand, setup.use_anm, weight, cutoff)
+        }
+        _ => scoring,
+    };
+
+    // Skip the evaluation of repeated poses
+    let scoring = match &setup.energy_cache {
+        Some(cache) => {
+            let size = cache.size.unwrap_or(DEFAULT_ENERGY_CACHE_SIZE);
+            let resolution = cache.resolution.unwrap_or(DEFAULT_ENERGY_CACHE_RESOLUTION);
+            let angular_resolution = cache
+                .angular_resolution
+                .unwrap_or(DEFAULT_ENERGY_CACHE_ANGULAR_RESOLUTION);
+            println!(
+                "Using energy cache of {} poses with resolution {} and angular resolution {}",
+                size, resolution, angular_resolution
+            );
+            Memoized::new(scoring, size, resolution, angular_resolution)
+        }
+        None => scoring,
+    };
+
+    // Linear calibration of the scoring function if defined, the terms of a consensus already are
+    let scoring = match consensus {
+        Some(_) => scoring,
+        None => calibrated(setup, &method, scoring),
+    };
+
+    // Restrict ligand centers to the docking region if defined
+    let scoring = match &setup.docking_region {
+        Some(region) => {
+            let shape = match region.shape() {
+                Ok(shape) => shape,
+                Err(e) => panic!("Error in docking region: {}", e),
+            };
+            println!("Using docking region {:?}", shape);
+            DockingRegion::new(
+                scoring,
+                shape,
+                region.penalty.unwrap_or(DEFAULT_REGION_PENALTY),
+                region.reject.unwrap_or(false),
+            )
+        }
+        None => scoring,
+    };
+
+    // Glowworm Swarm Optimization algorithm
+    println!("Creating GSO with {} glowworms", positions.len());
+    let mut gso = GSO::new(
+        &positions,
+        seed,
+        &scoring,
+        setup.use_anm,
+        setup.anm_rec,
+        setup.anm_lig,
+        swarm_directory,
+    );
+    // Extended runs keep the vision ranges they reached
+    if setup.vision_range_from_extent.unwrap_or(false) {
+        gso.swarm.vision_range_from_extent();
+        if let Some(glowworm) = gso.swarm.glowworms.first() {
+            println!(
+                "Vision range {:.3} A, up to {:.3} A, from the swarm extent",
+                glowworm.vision_range, glowworm.max_vision_range
+            );
+        }
+    }
+    if let Some((step, entries)) = &resumed {
+        gso.resume(*step, entries);
+    }
+    gso.swarm.symmetry = setup.symmetry;
+    gso.swarm.versioned_output = setup.versioned_output.unwrap_or(false);
+    if let Some(name) = &setup.rotation_output {
+        match RotationOutput::from_name(name) {
+            Some(rotation_output) => {
+                println!(
+                    "Writing orientations also as {} columns",
+                    rotation_output.name()
+                );
+                gso.swarm.rotation_output = Some(rotation_output);
+            }
+            None => {
+                let names: Vec<&str> = ROTATION_OUTPUTS
+                    .iter()
+                    .map(|output| output.name())
+                    .collect();
+                panic!(
+                    "Unknown rotation output [{:?}], use {}",
+                    name,
+                    names.join(" or ")
+                );
+            }
+        }
+    }
+    if let Some(movement_steps) = setup.movement_steps {
+        if movement_steps == 0 {
+            panic!("Number of movement steps must be positive");
+        }
+        println!("Using {} movement steps per scoring", movement_steps);
+        gso.swarm.movement_steps = movement_steps;
+    }
+    if let Some(temperature) = setup.softmax_temperature {
+        if temperature <= 0.0 {
+            panic!("Softmax temperature must be positive");
+        }
+        println!(
+            "Using softmax neighbor selection with temperature {}",
+            temperature
+        );
+        for glowworm in gso.swarm.glowworms.iter_mut() {
+            glowworm.softmax_temperature = Some(temperature);
+        }
+    }
+    if let Some(peptide) = &setup.peptide {
+        let step = peptide
+            .translation_step
+            .unwrap_or(DEFAULT_PEPTIDE_TRANSLATION_STEP);
+        if step <= 0.0 {
+            panic!("Peptide translation step must be positive");
+        }
+        println!(
+            "Peptide mode with {} ligand modes (backbone torsions {}), translation step {}",
+            setup.anm_lig,
+            if peptide.torsions.unwrap_or(false) {
+                "enabled"
+            } else {
+                "disabled"
+            },
+            step
+        );
+        for glowworm in gso.swarm.glowworms.iter_mut() {
+            glowworm.translation_step = step;
+        }
+    }
+    gso.compress_output = setup.compress_output.unwrap_or(false);
+    if let Some(noise) = &setup.noise {
+        let decay = noise.decay.unwrap_or(DEFAULT_NOISE_DECAY);
+        if noise.temperature < 0.0 || !(0.0..=1.0).contains(&decay) {
+            panic!("Noise temperature can not be negative and decay must be between 0 and 1");
+        }
+        let schedule = NoiseSchedule::new(noise.temperature, decay);
+        println!("Using movement noise {:?}", schedule);
+        gso.noise = Some(schedule);
+    }
+    if let Some(diversity) = &setup.diversity {
+        let fraction = diversity.fraction.unwrap_or(DEFAULT_DIVERSITY_FRACTION);
+        if !(0.0..=1.0).contains(&fraction) {
+            panic!("Diversity fraction must be between 0 and 1");
+        }
+        // Random poses inside the sphere of the starting positions
+        let injection = DiversityInjection::new(
+            diversity.threshold.unwrap_or(DEFAULT_DIVERSITY_THRESHOLD),
+            diversity.min_steps.unwrap_or(DEFAULT_DIVERSITY_MIN_STEPS),
+            fraction,
+            geometry.center,
+            geometry.radius,
+        );
+        println!("Using diversity injection {:?}", injection);
+        gso.diversity = Some(injection);
+    }
+    if let Some(events) = &setup.events {
+        gso.swarm_id = usize::try_from(swarm_id).ok();
+        gso.events = if events == "-" {
+            Some(Box::new(io::stdout()))
+        } else if EventSocket::is_address(events) {
+            // A monitor not listening does not prevent the run
+            match EventSocket::connect(events) {
+                Ok(socket) => {
+                    println!("Sending step events to {}", events);
+                    Some(Box::new(BufWriter::new(socket)))
+                }
+                Err(e) => {
+                    eprintln!(
+                        "Warning: no step events, error connecting to {:?}: {}",
+                        events, e
+                    );
+                    None
+                }
+            }
+        } else {
+            let events_filename = format!("{}/{}", gso.output_directory, events);
+            println!("Streaming step events to {}", events_filename);
+            // Events of an extended run follow the ones of the previous run
+            let file = fs::OpenOptions::new()
+                .write(true)
+                .create(true)
+                .append(extend)
+                .truncate(!extend)
+                .open(&events_filename);
+            match file {
+                Ok(file) => Some(Box::new(BufWriter::new(file))),
+                Err(e) => panic!(
+                    "Error creating events file [{:?}]: {:?}",
+                    events_filename,
+                    e.to_string()
+                ),
+            }
+        };
+    }
+
+    if let Some(convergence) = &setup.convergence {
+        let convergence_filename = format!("{}/{}", gso.output_directory, convergence);
+        println!("Writing convergence to {}", convergence_filename);
+        // Rows of an extended run follow the ones of the previous run
+        let file = fs::OpenOptions::new()
+            .write(true)
+            .create(true)
+            .append(extend)
+            .truncate(!extend)
+            .open(&convergence_filename);
+        let mut writer = match file {
+            Ok(file) => BufWriter::new(file),
+            Err(e) => panic!(
+                "Error creating convergence file [{:?}]: {:?}",
+                convergence_filename,
+                e.to_string()
+            ),
+        };
+        let is_empty = writer.get_ref().metadata().map_or(true, |m| m.len() == 0);
+        if is_empty {
+            if let Err(e) = writeln!(writer, "{}", CONVERGENCE_HEADER) {
+                panic!(
+                    "Error writing convergence file [{:?}]: {:?}",
+                    convergence_filename,
+                    e.to_string()
+                );
+            }
+        }
+        gso.convergence = Some(Box::new(writer));
+    }
+
+    if profile {
+        let profile_filename = format!("{}/{}", gso.output_directory, DEFAULT_PROFILE_FILE);
+        println!("Writing profile to {}", profile_filename);
+        // Rows of an extended run follow the ones of the previous run
+        let file = fs::OpenOptions::new()
+            .write(true)
+            .create(true)
+            .append(extend)
+            .truncate(!extend)
+            .open(&profile_filename);
+        let mut writer = match file {
+            Ok(file) => BufWriter::new(file),
+            Err(e) => panic!(
+                "Error creating profile file [{:?}]: {:?}",
+                profile_filename,
+                e.to_string()
+            ),
+        };
+        let is_empty = writer.get_ref().metadata().map_or(true, |m| m.len() == 0);
+        if is_empty {
+            if let Err(e) = writeln!(writer, "{}", PROFILE_HEADER) {
+                panic!(
+                    "Error writing profile file [{:?}]: {:?}",
+                    profile_filename,
+                    e.to_string()
+                );
+            }
+        }
+        enable_pose_timing();
+        gso.profile = Some(Box::new(writer));
+    }
+
+    if let Some(trajectory) = &setup.trajectory {
+        let trajectory_filename = format!("{}/{}", gso.output_directory, trajectory);
+        println!("Writing trajectory to {}", trajectory_filename);
+        gso.trajectory = Some(open_trajectory(&trajectory_filename, setup));
+    }
+
+    // Simulate for the given steps
+    println!("Starting optimization ({} steps)", steps);
+    gso.run(steps);
+
+    // Local refinement of the best poses found
+    if let Some(refinement) = &setup.refinement {
+        let minimizer = LBFGS::new(
+            refinement.memory.unwrap_or(DEFAULT_REFINEMENT_MEMORY),
+            refinement
+                .iterations
+                .unwrap_or(DEFAULT_REFINEMENT_ITERATIONS),
+            refinement.eps.unwrap_or(DEFAULT_REFINEMENT_EPS),
+            refinement.max_step.unwrap_or(DEFAULT_REFINEMENT_MAX_STEP),
+        );
+        if minimizer.memory == 0 || minimizer.eps <= 0.0 || minimizer.max_step <= 0.0 {
+            panic!("Refinement memory, eps and max_step must be positive");
+        }
+        let top = refinement.top.unwrap_or(DEFAULT_REFINEMENT_TOP);
+        println!("Refining the {} best poses with L-BFGS", top);
+        let refined = gso.refine(gso.start_step + steps, top, &minimizer);
+        for (glowworm, scoring) in refined {
+            println!(
+                "Glowworm {} refined from {:.8} to {:.8}",
+                glowworm, scoring, gso.swarm.glowworms[glowworm].scoring
+            );
+        }
+    }
+}
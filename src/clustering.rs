@@ -0,0 +1,146 @@
+use super::output::GSOEntry;
+
+// Leader clustering of poses by their rigid-body parameters only. Poses are visited from best to
+// worst scoring and join the first cluster whose representative is closer than both cutoffs,
+// otherwise they become the representative of a new cluster. As no atom coordinates are needed,
+// it is much cheaper than RMSD clustering and it also takes the orientation into account.
+
+#[derive(Debug)]
+pub struct Cluster {
+    // Index of the best scoring pose of the cluster
+    pub representative: usize,
+    pub members: Vec<usize>,
+}
+
+pub fn pose_distance(one: &GSOEntry, two: &GSOEntry) -> (f64, f64) {
+    let t1 = one.translation();
+    let t2 = two.translation();
+    let translation =
+        ((t1[0] - t2[0]).powi(2) + (t1[1] - t2[1]).powi(2) + (t1[2] - t2[2]).powi(2)).sqrt();
+    (translation, one.rotation().distance(two.rotation()))
+}
+
+pub fn cluster_poses(
+    entries: &[GSOEntry],
+    translation_cutoff: f64,
+    rotation_cutoff: f64,
+) -> Vec<Cluster> {
+    let mut order: Vec<usize> = (0..entries.len()).collect();
+    order.sort_by(|&a, &b| entries[b].scoring.total_cmp(&entries[a].scoring));
+
+    let mut clusters: Vec<Cluster> = Vec::new();
+    for i in order {
+        let cluster = clusters.iter_mut().find(|cluster| {
+            let (translation, rotation) =
+                pose_distance(&entries[cluster.representative], &entries[i]);
+            translation <= translation_cutoff && rotation <= rotation_cutoff
+        });
+        match cluster {
+            Some(cluster) => cluster.members.push(i),
+            None => clusters.push(Cluster {
+                representative: i,
+                members: vec![i],
+            }),
+        }
+    }
+    clusters
+}
+
+// Agglomerative clustering with complete linkage from a symmetric matrix of distances: the two
+// closest clusters are merged while all their members are closer than the cutoff. Items are
+// expected from best to worst, so the representative is the first member of each cluster.
+pub fn hierarchical_clusters(distances: &[Vec<f64>], cutoff: f64) -> Vec<Cluster> {
+    let n = distances.len();
+    let mut linkage: Vec<Vec<f64>> = distances.to_vec();
+    let mut members: Vec<Vec<usize>> = (0..n).map(|i| vec![i]).collect();
+    let mut active: Vec<bool> = vec![true; n];
+    loop {
+        let mut closest: Option<(usize, usize)> = None;
+        for a in (0..n).filter(|&a| active[a]) {
+            for b in (a + 1..n).filter(|&b| active[b]) {
+                if closest.is_none_or(|(i, j)| linkage[a][b] < linkage[i][j]) {
+                    closest = Some((a, b));
+                }
+            }
+        }
+        let (a, b) = match closest {
+            Some((a, b)) if linkage[a][b] <= cutoff => (a, b),
+            _ => break,
+        };
+        // Distance between clusters is the one of their farthest members
+        for c in (0..n).filter(|&c| active[c] && c != a && c != b) {
+            let distance = linkage[a][c].max(linkage[b][c]);
+            linkage[a][c] = distance;
+            linkage[c][a] = distance;
+        }
+        active[b] = false;
+        let merged = std::mem::take(&mut members[b]);
+        members[a].extend(merged);
+    }
+    (0..n)
+        .filter(|&i| active[i])
+        .map(|i| {
+            let mut members = members[i].clone();
+            members.sort();
+            Cluster {
+                representative: members[0],
+                members,
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(position: Vec<f64>, scoring: f64) -> GSOEntry {
+        GSOEntry {
+            position,
+            rec_id: 0,
+            lig_id: 0,
+            luciferin: 0.0,
+            num_neighbors: 0,
+            vision_range: 0.0,
+            scoring,
+            z_score: None,
+            stagnation: None,
+        }
+    }
+
+    #[test]
+    fn test_cluster_poses() {
+        let entries = vec![
+            entry(vec![0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0], 1.0),
+            entry(vec![1.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0], 5.0),
+            // Same position, opposite orientation
+            entry(vec![1.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0], 3.0),
+            entry(vec![10.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0], 2.0),
+        ];
+        let clusters = cluster_poses(&entries, 2.0, 0.5);
+        assert_eq!(clusters.len(), 3);
+        assert_eq!(clusters[0].representative, 1);
+        assert_eq!(clusters[0].members, vec![1, 0]);
+        assert_eq!(clusters[1].members, vec![2]);
+        assert_eq!(clusters[2].members, vec![3]);
+    }
+
+    #[test]
+    fn test_hierarchical_clusters() {
+        // Points on a line at 0, 1, 2, 10 and 11
+        let points = [0.0, 1.0, 2.0, 10.0, 11.0_f64];
+        let distances: Vec<Vec<f64>> = points
+            .iter()
+            .map(|a| points.iter().map(|b| (a - b).abs()).collect())
+            .collect();
+        let clusters = hierarchical_clusters(&distances, 2.0);
+        assert_eq!(clusters.len(), 2);
+        assert_eq!(clusters[0].members, vec![0, 1, 2]);
+        assert_eq!(clusters[1].representative, 3);
+        // Complete linkage, 0 and 2 are too far apart
+        let clusters = hierarchical_clusters(&distances, 1.5);
+        assert_eq!(clusters.len(), 3);
+        assert_eq!(clusters[0].members, vec![0, 1]);
+        assert_eq!(clusters[1].members, vec![2]);
+    }
+}
@@ -4,9 +4,28 @@ pub const DEFAULT_SEED: u64 = 324_324;
 // Translation interpolation step
 pub const DEFAULT_TRANSLATION_STEP: f64 = 0.5;
 
+// Ligand normal modes, translation interpolation step and largest starting extent of the modes
+// of the peptide docking mode
+pub const DEFAULT_PEPTIDE_MODES: usize = 20;
+pub const DEFAULT_PEPTIDE_TRANSLATION_STEP: f64 = 0.25;
+pub const DEFAULT_PEPTIDE_EXTENT: f64 = 0.5;
+
 // Rotation interpolation step
 pub const DEFAULT_ROTATION_STEP: f64 = 0.5;
 
+// Luciferin and vision range (Angstroms) of the glowworms at the start of the simulation
+pub const DEFAULT_INITIAL_LUCIFERIN: f64 = 5.0;
+pub const DEFAULT_INITIAL_VISION_RANGE: f64 = 0.2;
+
+// Factor applied every step to the temperature of the movement noise
+pub const DEFAULT_NOISE_DECAY: f64 = 0.95;
+
+// Glowworms re-seeded when the mean distance between them falls below the threshold (Angstroms)
+// before the given step
+pub const DEFAULT_DIVERSITY_THRESHOLD: f64 = 2.0;
+pub const DEFAULT_DIVERSITY_MIN_STEPS: u32 = 50;
+pub const DEFAULT_DIVERSITY_FRACTION: f64 = 0.2;
+
 // When a quaternion SLERP is considered linear and not spherical
 pub const LINEAR_THRESHOLD: f64 = 0.9995;
 
@@ -20,9 +39,145 @@ pub const DEFAULT_LIGHTDOCK_PREFIX: &str = "lightdock_";
 // Membrane penalty for biasing the scoring
 pub const MEMBRANE_PENALTY_SCORE: f64 = 999.0;
 
+// Distance between membrane beads placed on planes, margin around the receptor they cover and
+// smallest distance to a receptor atom (Angstroms)
+pub const DEFAULT_MEMBRANE_SPACING: f64 = 6.0;
+pub const DEFAULT_MEMBRANE_MARGIN: f64 = 40.0;
+pub const DEFAULT_MEMBRANE_CLEARANCE: f64 = 3.0;
+
 // ANM interpolation step
 pub const DEFAULT_NMODES_STEP: f64 = 0.5;
 
 // 1D NumPy arrays containing calculated ANM from ProDy
 pub const DEFAULT_REC_NM_FILE: &str = "rec_nm.npy";
 pub const DEFAULT_LIG_NM_FILE: &str = "lig_nm.npy";
+
+// ProDy normal mode files read when the NumPy ones are not found
+pub const DEFAULT_REC_NMD_FILE: &str = "rec_nm.nmd";
+pub const DEFAULT_LIG_NMD_FILE: &str = "lig_nm.nmd";
+
+// Residue contacts listed by lightdock-score
+pub const DEFAULT_SCORE_TOP_CONTACTS: usize = 10;
+
+// Quaternions read from initial positions with a norm further from 1.0 are reported
+pub const QUATERNION_NORM_TOLERANCE: f64 = 1e-3;
+
+// Swarm centers closer than this distance to a receptor atom are considered embedded
+pub const EMBEDDED_SWARM_CUTOFF: f64 = 3.0;
+
+// Penalty per Angstrom for ligand centers outside of the docking region
+pub const DEFAULT_REGION_PENALTY: f64 = 10.0;
+
+// Scoring assigned to poses rejected without a full evaluation
+pub const REJECTED_POSE_SCORE: f64 = -999.0;
+
+// Heavy atoms closer than this distance are considered clashing
+pub const DEFAULT_CLASH_CUTOFF: f64 = 2.5;
+
+// Poses with more clashing heavy atom pairs are rejected by the clash pre-filter
+pub const DEFAULT_MAX_CLASHES: usize = 20;
+
+// Starting poses with more clashing heavy atom pairs are overlapping the receptor, and are moved
+// away along the swarm axis by steps up to the largest shift (Angstroms)
+pub const DEFAULT_OVERLAP_MAX_CLASHES: usize = 100;
+pub const DEFAULT_OVERLAP_STEP: f64 = 1.0;
+pub const DEFAULT_OVERLAP_MAX_SHIFT: f64 = 20.0;
+
+// Poses scoring below this value with the reduced DFIRE model are not re-scored
+pub const DEFAULT_PRESCREEN_THRESHOLD: f64 = 0.0;
+
+// DFIRE potential is only defined up to this distance
+pub const DFIRE_CUTOFF: f64 = 15.0;
+
+// DFIRE2 potential is given in distance bins of this width up to its cutoff, both in Angstroms
+pub const DFIRE2_CUTOFF: f64 = 15.0;
+pub const DFIRE2_BIN_WIDTH: f64 = 0.5;
+
+// Spacing in Angstroms of the precomputed DFIRE receptor grid
+pub const DEFAULT_GRID_SPACING: f64 = 1.0;
+
+// Residues of the DFIRE distance shell closer than this radius in Angstroms are scored atom by atom
+pub const DEFAULT_SHELL_RADIUS: f64 = 12.0;
+
+// FFT pre-sampling grids: atom radius, thickness of the receptor surface layer, penalty for
+// ligand atoms inside the receptor core and weight of the electrostatic term
+pub const FFT_PROBE_RADIUS: f64 = 1.8;
+pub const FFT_SURFACE_THICKNESS: f64 = 3.4;
+pub const FFT_CORE_PENALTY: f64 = -15.0;
+pub const FFT_ELEC_WEIGHT: f64 = 1.0;
+pub const DEFAULT_FFT_ROTATIONS: usize = 300;
+pub const DEFAULT_FFT_SPACING: f64 = 1.2;
+
+// Poses closer than both cutoffs (Angstroms and quaternion distance) are clustered together
+pub const DEFAULT_CLUSTER_TRANSLATION_CUTOFF: f64 = 4.0;
+pub const DEFAULT_CLUSTER_ROTATION_CUTOFF: f64 = 0.1;
+
+// Two-stage protocol: translation step of the global stage, number of its best clusters seeding
+// the fine stage and largest translation (Angstroms) and rotation (radians) of the poses seeded
+// around each cluster
+pub const DEFAULT_PROTOCOL_TRANSLATION_STEP: f64 = 1.5;
+pub const DEFAULT_PROTOCOL_TOP_CLUSTERS: usize = 5;
+pub const DEFAULT_PROTOCOL_SPREAD: f64 = 2.0;
+pub const DEFAULT_PROTOCOL_ROTATION_SPREAD: f64 = 0.35;
+
+// Residues with atoms closer than this distance are in contact in the swarm contact map
+pub const DEFAULT_CONTACT_CUTOFF: f64 = 5.0;
+
+// Top poses of all swarms are clustered by interface RMSD, with the interface made of the atoms
+// closer than the cutoff to the partner
+pub const DEFAULT_RANK_POSES: usize = 100;
+pub const DEFAULT_RANK_IRMSD_CUTOFF: f64 = 4.0;
+pub const RANK_INTERFACE_CUTOFF: f64 = 10.0;
+
+// Success rates of a benchmark are given for the first poses of every case
+pub const BENCHMARK_TOP_POSES: [usize; 4] = [1, 5, 10, 100];
+
+// Report of the changes made by the structure sanitizer, next to setup.json
+pub const DEFAULT_SANITIZE_REPORT: &str = "sanitize_report.txt";
+
+// Timing of the phases of every step written with --profile, in the swarm directory
+pub const DEFAULT_PROFILE_FILE: &str = "profile.csv";
+
+// Local refinement of the best poses at the end of a run: number of poses, L-BFGS iterations and
+// memory, finite differences step and largest step (Angstroms and radians)
+pub const DEFAULT_REFINEMENT_TOP: usize = 10;
+pub const DEFAULT_REFINEMENT_ITERATIONS: usize = 20;
+pub const DEFAULT_REFINEMENT_MEMORY: usize = 5;
+pub const DEFAULT_REFINEMENT_EPS: f64 = 0.05;
+pub const DEFAULT_REFINEMENT_MAX_STEP: f64 = 1.0;
+
+// Number of best poses exported as complexes for refinement
+pub const DEFAULT_EXPORT_POSES: usize = 10;
+
+// Weight of the mean interface propensity and distance between residue atoms in contact
+pub const DEFAULT_PROPENSITY_WEIGHT: f64 = 10.0;
+pub const DEFAULT_PROPENSITY_CUTOFF: f64 = 5.0;
+
+// Number of poses and rounding of the pose parameters of the energy cache, the translation in
+// Angstroms and the quaternion components and normal modes extents much finer
+pub const DEFAULT_ENERGY_CACHE_SIZE: usize = 1024;
+pub const DEFAULT_ENERGY_CACHE_RESOLUTION: f64 = 0.001;
+pub const DEFAULT_ENERGY_CACHE_ANGULAR_RESOLUTION: f64 = 0.00001;
+
+// Maximum angle in degrees between the ligand restrained patch and the direction to the receptor
+pub const DEFAULT_RESTRAINTS_MAX_ANGLE: f64 = 90.0;
+
+// Distance in Angstroms beyond the interface cutoff over which the satisfaction of a restraint
+// decays by a factor e with the distance metric
+pub const RESTRAINTS_DISTANCE_DECAY: f64 = 2.0;
+
+// Version of the gso_*.out format and its column header
+pub const GSO_OUTPUT_VERSION: u32 = 3;
+pub const GSO_OUTPUT_COLUMNS: &str =
+    "Coordinates  RecID  LigID  Luciferin  Neighbor's number  Vision Range  Scoring  Z-score  Stagnation";
+
+// Column header of the gso_*.out files written without a version, the default
+pub const GSO_LEGACY_OUTPUT_COLUMNS: &str =
+    "Coordinates  RecID  LigID  Luciferin  Neighbor's number  Vision Range  Scoring  Z-score";
+
+// Minimum luciferin increase of a glowworm not to be considered stagnated, as the luciferin of a
+// glowworm not moving converges asymptotically to a fraction of its scoring
+pub const STAGNATION_TOLERANCE: f64 = 1e-6;
+
+// Maximum number of output files waiting to be written in background
+pub const OUTPUT_QUEUE_SIZE: usize = 4;
@@ -0,0 +1,169 @@
+use super::decomposition::push_residue;
+use super::restraints::residue_id;
+use super::scoring::z_scores;
+use pdbtbx::PDB;
+use std::collections::{BTreeMap, HashMap};
+use std::io::{Error, Write};
+
+// Residue-residue contacts aggregated over the poses of a swarm. Each pose contributes with a
+// weight exp(z-score) of its scoring, so the occupancy of a contact is the weighted fraction of
+// poses where it is found: interface regions consistently predicted stand out even when no single
+// pose dominates.
+
+// Residue IDs and the residue of every atom, in the order of pose::atom_coordinates
+pub fn atom_residues(structure: &PDB) -> (Vec<String>, Vec<usize>) {
+    let mut residues: Vec<String> = Vec::new();
+    let mut atom_residues: Vec<usize> = Vec::new();
+    for chain in structure.chains() {
+        for residue in chain.residues() {
+            let res_id = residue_id(chain, residue);
+            for _atom in residue.atoms() {
+                push_residue(&mut residues, &mut atom_residues, &res_id);
+            }
+        }
+    }
+    (residues, atom_residues)
+}
+
+fn cell(coordinate: &[f64; 3], cutoff: f64) -> (i64, i64, i64) {
+    (
+        (coordinate[0] / cutoff).floor() as i64,
+        (coordinate[1] / cutoff).floor() as i64,
+        (coordinate[2] / cutoff).floor() as i64,
+    )
+}
+
+// Receptor and ligand residue pairs with atoms closer than the cutoff, sorted
+pub fn residue_contacts(
+    receptor_coordinates: &[[f64; 3]],
+    receptor_residues: &[usize],
+    ligand_coordinates: &[[f64; 3]],
+    ligand_residues: &[usize],
+    cutoff: f64,
+) -> Vec<(usize, usize)> {
+    let cutoff2 = cutoff * cutoff;
+    let mut grid: HashMap<(i64, i64, i64), Vec<usize>> = HashMap::new();
+    for (i, coordinate) in receptor_coordinates.iter().enumerate() {
+        grid.entry(cell(coordinate, cutoff)).or_default().push(i);
+    }
+
+    let mut contacts: Vec<(usize, usize)> = Vec::new();
+    for (j, la) in ligand_coordinates.iter().enumerate() {
+        let (cx, cy, cz) = cell(la, cutoff);
+        for dx in -1..=1 {
+            for dy in -1..=1 {
+                for dz in -1..=1 {
+                    if let Some(atoms) = grid.get(&(cx + dx, cy + dy, cz + dz)) {
+                        for &i in atoms.iter() {
+                            let ra = &receptor_coordinates[i];
+                            let dist = (ra[0] - la[0]) * (ra[0] - la[0])
+                                + (ra[1] - la[1]) * (ra[1] - la[1])
+                                + (ra[2] - la[2]) * (ra[2] - la[2]);
+                            if dist <= cutoff2 {
+                                contacts.push((receptor_residues[i], ligand_residues[j]));
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    }
+    contacts.sort_unstable();
+    contacts.dedup();
+    contacts
+}
+
+// Weight of every pose from its scoring, uniform if all poses score the same
+pub fn pose_weights(scores: &[f64]) -> Vec<f64> {
+    z_scores(scores).iter().map(|z| z.exp()).collect()
+}
+
+#[derive(Debug)]
+pub struct ContactMap {
+    pub receptor: Vec<String>,
+    pub ligand: Vec<String>,
+    // Weight and number of the poses with each contact
+    pub contacts: BTreeMap<(usize, usize), (f64, usize)>,
+    pub total_weight: f64,
+}
+
+impl ContactMap {
+    pub fn new(receptor: &[String], ligand: &[String]) -> ContactMap {
+        ContactMap {
+            receptor: receptor.to_vec(),
+            ligand: ligand.to_vec(),
+            contacts: BTreeMap::new(),
+            total_weight: 0.0,
+        }
+    }
+
+    pub fn add_pose(&mut self, contacts: &[(usize, usize)], weight: f64) {
+        for &contact in contacts.iter() {
+            let entry = self.contacts.entry(contact).or_insert((0.0, 0));
+            entry.0 += weight;
+            entry.1 += 1;
+        }
+        self.total_weight += weight;
+    }
+
+    pub fn occupancy(&self, contact: &(usize, usize)) -> f64 {
+        match self.contacts.get(contact) {
+            Some((weight, _)) if self.total_weight > 0.0 => weight / self.total_weight,
+            _ => 0.0,
+        }
+    }
+
+    // One line per contact, from the highest to the lowest occupancy
+    pub fn write_csv<W: Write>(&self, output: &mut W) -> Result<(), Error> {
+        let mut contacts: Vec<(&(usize, usize), f64)> = self
+            .contacts
+            .keys()
+            .map(|contact| (contact, self.occupancy(contact)))
+            .collect();
+        contacts.sort_by(|a, b| b.1.total_cmp(&a.1));
+        writeln!(output, "receptor,ligand,occupancy,poses")?;
+        for ((i, j), occupancy) in contacts {
+            writeln!(
+                output,
+                "{},{},{:.6},{}",
+                self.receptor[*i],
+                self.ligand[*j],
+                occupancy,
+                self.contacts[&(*i, *j)].1
+            )?;
+        }
+        output.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_residue_contacts() {
+        let receptor = vec![[0.0, 0.0, 0.0], [1.0, 0.0, 0.0], [10.0, 0.0, 0.0]];
+        let ligand = vec![[0.0, 3.0, 0.0], [10.0, 4.5, 0.0], [30.0, 0.0, 0.0]];
+        let contacts = residue_contacts(&receptor, &[0, 0, 1], &ligand, &[0, 1, 1], 5.0);
+        assert_eq!(contacts, vec![(0, 0), (1, 1)]);
+    }
+
+    #[test]
+    fn test_contact_map() {
+        let receptor = vec![String::from("A.ALA.1"), String::from("A.GLY.2")];
+        let ligand = vec![String::from("B.LYS.1")];
+        let mut map = ContactMap::new(&receptor, &ligand);
+        map.add_pose(&[(0, 0), (1, 0)], 3.0);
+        map.add_pose(&[(1, 0)], 1.0);
+        assert_eq!(map.occupancy(&(0, 0)), 0.75);
+        assert_eq!(map.occupancy(&(1, 0)), 1.0);
+
+        let mut output: Vec<u8> = Vec::new();
+        map.write_csv(&mut output).unwrap();
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "receptor,ligand,occupancy,poses\nA.GLY.2,B.LYS.1,1.000000,2\nA.ALA.1,B.LYS.1,0.750000,1\n"
+        );
+        assert_eq!(pose_weights(&[2.0, 2.0]), vec![1.0, 1.0]);
+    }
+}
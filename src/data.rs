@@ -0,0 +1,164 @@
+use flate2::read::GzDecoder;
+use log::info;
+use std::env;
+use std::error::Error;
+use std::fs;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+// Data files of the scoring functions (DFIRE parameters) are searched in $LIGHTDOCK_DATA, the data
+// folder of the current directory and the data folder of the source tree the binary was built
+// from. If $LIGHTDOCK_DATA_URL is set, a missing file is downloaded from <url>/<file> over HTTP
+// into the first of those folders. Gzipped files are decompressed and tar archives unpacked.
+
+const GZIP_MAGIC: [u8; 2] = [0x1f, 0x8b];
+
+pub fn data_folders() -> Vec<PathBuf> {
+    let mut folders: Vec<PathBuf> = Vec::new();
+    if let Ok(folder) = env::var("LIGHTDOCK_DATA") {
+        folders.push(PathBuf::from(folder));
+    }
+    folders.push(PathBuf::from("data"));
+    let source_folder = Path::new(env!("CARGO_MANIFEST_DIR")).join("data");
+    if !folders.contains(&source_folder) {
+        folders.push(source_folder);
+    }
+    folders
+}
+
+pub fn find_data_file(filename: &str) -> Result<PathBuf, String> {
+    let folders = data_folders();
+    if let Some(path) = folders
+        .iter()
+        .map(|folder| folder.join(filename))
+        .find(|path| path.is_file())
+    {
+        return Ok(path);
+    }
+
+    if let Ok(url) = env::var("LIGHTDOCK_DATA_URL") {
+        return fetch_data_file(filename, &url, &folders[0])
+            .map_err(|e| format!("Unable to download {} from {}: {}", filename, url, e));
+    }
+
+    let searched: Vec<String> = folders
+        .iter()
+        .map(|folder| folder.join(filename).display().to_string())
+        .collect();
+    Err(format!(
+        "{} not found, searched: {}. Set LIGHTDOCK_DATA to the data folder of the lightdock-rust \
+         repository (export LIGHTDOCK_DATA=/path/to/lightdock-rust/data) or LIGHTDOCK_DATA_URL to \
+         a location to download it from",
+        filename,
+        searched.join(", ")
+    ))
+}
+
+fn fetch_data_file(filename: &str, url: &str, folder: &Path) -> Result<PathBuf, Box<dyn Error>> {
+    fs::create_dir_all(folder)?;
+    let url = url.trim_end_matches('/');
+    let is_archive = url.ends_with(".tar.gz") || url.ends_with(".tgz") || url.ends_with(".tar");
+    let url = if is_archive {
+        url.to_string()
+    } else {
+        format!("{}/{}", url, filename)
+    };
+    info!("Downloading {} into {}", url, folder.display());
+
+    // Error statuses are errors, redirects are followed
+    let mut bytes: Vec<u8> = Vec::new();
+    ureq::get(&url)
+        .call()?
+        .into_reader()
+        .read_to_end(&mut bytes)?;
+
+    let path = folder.join(filename);
+    if is_archive {
+        if bytes.starts_with(&GZIP_MAGIC) {
+            tar::Archive::new(GzDecoder::new(&bytes[..])).unpack(folder)?;
+        } else {
+            tar::Archive::new(&bytes[..]).unpack(folder)?;
+        }
+        // Archives may keep the files inside a data folder
+        let nested = folder.join("data").join(filename);
+        if !path.is_file() && nested.is_file() {
+            fs::rename(&nested, &path)?;
+        }
+    } else {
+        if bytes.starts_with(&GZIP_MAGIC) {
+            let mut contents: Vec<u8> = Vec::new();
+            GzDecoder::new(&bytes[..]).read_to_end(&mut contents)?;
+            fs::write(&path, contents)?;
+        } else {
+            fs::write(&path, bytes)?;
+        }
+    }
+    if !path.is_file() {
+        return Err(format!("{} not found in the downloaded archive", filename).into());
+    }
+    Ok(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use flate2::write::GzEncoder;
+    use flate2::Compression;
+    use std::io::Write;
+    use std::net::TcpListener;
+    use std::thread;
+
+    #[test]
+    fn test_find_data_file() {
+        // Source tree data folder is always searched
+        let path = find_data_file("DCparams").unwrap();
+        assert!(path.ends_with("data/DCparams"));
+
+        if env::var("LIGHTDOCK_DATA_URL").is_err() {
+            let error = find_data_file("MISSINGparams").unwrap_err();
+            assert!(error.starts_with("MISSINGparams not found, searched: "));
+            assert!(error.contains("LIGHTDOCK_DATA"));
+        }
+    }
+
+    #[test]
+    fn test_fetch_data_file() {
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(b"1 2 3\n").unwrap();
+        let body = encoder.finish().unwrap();
+        // Server of a single gzipped file, not found for anything else
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let server = thread::spawn(move || {
+            for _ in 0..2 {
+                let (mut stream, _) = listener.accept().unwrap();
+                let mut request = [0; 1024];
+                let size = stream.read(&mut request).unwrap();
+                let request = String::from_utf8_lossy(&request[..size]).to_string();
+                if request.starts_with("GET /TESTparams ") {
+                    write!(
+                        stream,
+                        "HTTP/1.1 200 OK\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+                        body.len()
+                    )
+                    .unwrap();
+                    stream.write_all(&body).unwrap();
+                } else {
+                    write!(
+                        stream,
+                        "HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\nConnection: close\r\n\r\n"
+                    )
+                    .unwrap();
+                }
+            }
+        });
+
+        let temp = tempfile::tempdir().unwrap();
+        let folder = temp.path().join("data");
+        let path = fetch_data_file("TESTparams", &url, &folder).unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "1 2 3\n");
+        assert!(fetch_data_file("MISSINGparams", &url, &folder).is_err());
+        assert!(!folder.join("MISSINGparams").exists());
+        server.join().unwrap();
+    }
+}
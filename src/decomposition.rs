@@ -0,0 +1,187 @@
+use super::pose::{apply_nmodes, ligand_pose};
+use super::qt::Quaternion;
+use std::io::{Error, Write};
+
+// Interaction energy of a pose split into receptor residue by ligand residue contributions,
+// to see which contacts drive a prediction. Restraints, hot-spots and membrane terms are not
+// decomposed, so the matrix adds up to the score of the pose without them.
+
+#[derive(Debug, Clone)]
+pub struct ResidueEnergies {
+    pub receptor: Vec<String>,
+    pub ligand: Vec<String>,
+    // Row per receptor residue, column per ligand residue
+    pub energies: Vec<Vec<f64>>,
+    // Part of the score not due to any atom pair, like the DFIRE constant
+    pub offset: f64,
+}
+
+impl ResidueEnergies {
+    pub fn new(receptor: &[String], ligand: &[String], offset: f64) -> ResidueEnergies {
+        ResidueEnergies {
+            receptor: receptor.to_vec(),
+            ligand: ligand.to_vec(),
+            energies: vec![vec![0.0; ligand.len()]; receptor.len()],
+            offset,
+        }
+    }
+
+    pub fn add(&mut self, receptor_residue: usize, ligand_residue: usize, energy: f64) {
+        self.energies[receptor_residue][ligand_residue] += energy;
+    }
+
+    pub fn scale(&mut self, factor: f64) {
+        for row in self.energies.iter_mut() {
+            for energy in row.iter_mut() {
+                *energy *= factor;
+            }
+        }
+        self.offset *= factor;
+    }
+
+    // Add the energies of another scoring function times the given weight, matching residues by
+    // their IDs. Residues only scored by the other function are appended.
+    pub fn merge(&mut self, other: &ResidueEnergies, weight: f64) {
+        let rows: Vec<usize> = other
+            .receptor
+            .iter()
+            .map(|residue| residue_index(&mut self.receptor, residue))
+            .collect();
+        let columns: Vec<usize> = other
+            .ligand
+            .iter()
+            .map(|residue| residue_index(&mut self.ligand, residue))
+            .collect();
+        self.energies.resize(self.receptor.len(), Vec::new());
+        for row in self.energies.iter_mut() {
+            row.resize(self.ligand.len(), 0.0);
+        }
+        for (i, &row) in rows.iter().enumerate() {
+            for (j, &column) in columns.iter().enumerate() {
+                self.energies[row][column] += weight * other.energies[i][j];
+            }
+        }
+        self.offset += weight * other.offset;
+    }
+
+    pub fn total(&self) -> f64 {
+        self.energies.iter().flatten().sum::<f64>() + self.offset
+    }
+
+    // Only residues with at least one non-zero contribution are written, as a matrix with the
+    // receptor residues as rows and the ligand residues as columns
+    pub fn write_csv<W: Write>(&self, output: &mut W) -> Result<(), Error> {
+        let rows: Vec<usize> = (0..self.receptor.len())
+            .filter(|&i| self.energies[i].iter().any(|&energy| energy != 0.0))
+            .collect();
+        let columns: Vec<usize> = (0..self.ligand.len())
+            .filter(|&j| self.energies.iter().any(|row| row[j] != 0.0))
+            .collect();
+        write!(output, "receptor")?;
+        for &j in columns.iter() {
+            write!(output, ",{}", self.ligand[j])?;
+        }
+        writeln!(output)?;
+        for &i in rows.iter() {
+            write!(output, "{}", self.receptor[i])?;
+            for &j in columns.iter() {
+                write!(output, ",{:.6}", self.energies[i][j])?;
+            }
+            writeln!(output)?;
+        }
+        output.flush()
+    }
+}
+
+// Normal mode extents applied by a scoring function, none if ANM is disabled
+pub fn anm_extents(use_anm: bool, num_anm: usize, extents: &[f64]) -> &[f64] {
+    if use_anm {
+        &extents[..num_anm.min(extents.len())]
+    } else {
+        &[]
+    }
+}
+
+// Receptor and ligand coordinates of a pose, transformed as in the scoring functions
+pub fn pose_coordinates(
+    rec_coordinates: &[[f64; 3]],
+    rec_nmodes: &[f64],
+    rec_extents: &[f64],
+    lig_coordinates: &[[f64; 3]],
+    lig_nmodes: &[f64],
+    lig_extents: &[f64],
+    translation: &[f64],
+    rotation: &Quaternion,
+) -> (Vec<[f64; 3]>, Vec<[f64; 3]>) {
+    let mut receptor_pose = rec_coordinates.to_vec();
+    apply_nmodes(&mut receptor_pose, rec_nmodes, rec_extents);
+    let ligand_pose = ligand_pose(
+        lig_coordinates,
+        translation,
+        rotation,
+        lig_nmodes,
+        lig_extents,
+    );
+    (receptor_pose, ligand_pose)
+}
+
+// Index of a residue in the list, appended if missing
+fn residue_index(residues: &mut Vec<String>, residue: &str) -> usize {
+    match residues.iter().position(|known| known == residue) {
+        Some(index) => index,
+        None => {
+            residues.push(residue.to_string());
+            residues.len() - 1
+        }
+    }
+}
+
+// Index of the residue of every atom, residues listed in order of appearance
+pub fn push_residue(residues: &mut Vec<String>, atom_residues: &mut Vec<usize>, res_id: &str) {
+    if residues.last().map(|last| last.as_str()) != Some(res_id) {
+        residues.push(res_id.to_string());
+    }
+    atom_residues.push(residues.len() - 1);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_residue_energies() {
+        let receptor = vec![String::from("A.ALA.1"), String::from("A.GLY.2")];
+        let ligand = vec![String::from("B.LYS.1"), String::from("B.ASP.2")];
+        let mut energies = ResidueEnergies::new(&receptor, &ligand, 1.0);
+        energies.add(0, 1, -2.0);
+        energies.add(0, 1, -0.5);
+        energies.scale(2.0);
+        assert_eq!(energies.total(), -3.0);
+
+        let mut output: Vec<u8> = Vec::new();
+        energies.write_csv(&mut output).unwrap();
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "receptor,B.ASP.2\nA.ALA.1,-5.000000\n"
+        );
+
+        // Residues matched by ID, the ones missing appended
+        let mut other = ResidueEnergies::new(&[String::from("A.CYS.3")], &ligand, 0.5);
+        other.add(0, 0, 4.0);
+        energies.merge(&other, 0.5);
+        assert_eq!(energies.receptor.len(), 3);
+        assert_eq!(energies.energies[2], vec![2.0, 0.0]);
+        assert_eq!(energies.total(), -0.75);
+    }
+
+    #[test]
+    fn test_push_residue() {
+        let mut residues: Vec<String> = Vec::new();
+        let mut atom_residues: Vec<usize> = Vec::new();
+        for res_id in ["A.ALA.1", "A.ALA.1", "A.GLY.2", "A.ALA.1"] {
+            push_residue(&mut residues, &mut atom_residues, res_id);
+        }
+        assert_eq!(residues, vec!["A.ALA.1", "A.GLY.2", "A.ALA.1"]);
+        assert_eq!(atom_residues, vec![0, 0, 1, 2]);
+    }
+}
@@ -1,11 +1,28 @@
-use super::constants::{INTERFACE_CUTOFF, MEMBRANE_PENALTY_SCORE};
+use super::constants::{
+    DFIRE_CUTOFF, INTERFACE_CUTOFF, MEMBRANE_PENALTY_SCORE, REJECTED_POSE_SCORE,
+};
+use super::data::find_data_file;
+use super::decomposition::{anm_extents, pose_coordinates, push_residue, ResidueEnergies};
+use super::nmodes::Nmodes;
+use super::policy::{PolicyReport, ResidueAction, ResiduePolicy};
+use super::pose::select_nmodes;
+use super::precision::{to_single, SinglePartner, SinglePrecision};
+use super::profile::{add_pose_time, pose_timer};
 use super::qt::Quaternion;
-use super::scoring::{membrane_intersection, satisfied_restraints, Score};
+use super::registry::ScoringInput;
+use super::scoring::{
+    hotspot_bias, in_interface, interface_residues, membrane_intersection, restraints_satisfaction,
+    CellList, ClashFilter, Interface, MembraneGrid, RestraintsMetric, Score, Workspace,
+};
+use super::structure::is_hydrogen;
+use log::{info, warn};
 use pdbtbx::PDB;
+use std::cell::RefCell;
 use std::collections::HashMap;
-use std::env;
+use std::error::Error;
 use std::fs::File;
 use std::io::Read;
+use std::ops::Range;
 
 macro_rules! hashmap {
     ($( $key: expr => $val: expr ),*) => {{
@@ -16,32 +33,37 @@ macro_rules! hashmap {
 }
 
 pub fn r3_to_numerical(residue_name: &str) -> usize {
+    match residue_number(residue_name) {
+        Some(number) => number,
+        None => panic!("Residue name not supported in DFIRE scoring function"),
+    }
+}
+
+pub fn residue_number(residue_name: &str) -> Option<usize> {
     match residue_name {
-        "ALA" => 0,
-        "CYS" => 1,
-        "ASP" => 2,
-        "GLU" => 3,
-        "PHE" => 4,
-        "GLY" => 5,
-        "HIS" => 6,
-        "ILE" => 7,
-        "LYS" => 8,
-        "LEU" => 9,
-        "MET" => 10,
-        "ASN" => 11,
-        "PRO" => 12,
-        "GLN" => 13,
-        "ARG" => 14,
-        "SER" => 15,
-        "THR" => 16,
-        "VAL" => 17,
-        "TRP" => 18,
-        "TYR" => 19,
-        "MMB" => 20,
-        "MMY" => 0,
-        _ => {
-            panic!("Residue name not supported in DFIRE scoring function")
-        }
+        "ALA" => Some(0),
+        "CYS" => Some(1),
+        "ASP" => Some(2),
+        "GLU" => Some(3),
+        "PHE" => Some(4),
+        "GLY" => Some(5),
+        "HIS" => Some(6),
+        "ILE" => Some(7),
+        "LYS" => Some(8),
+        "LEU" => Some(9),
+        "MET" => Some(10),
+        "ASN" => Some(11),
+        "PRO" => Some(12),
+        "GLN" => Some(13),
+        "ARG" => Some(14),
+        "SER" => Some(15),
+        "THR" => Some(16),
+        "VAL" => Some(17),
+        "TRP" => Some(18),
+        "TYR" => Some(19),
+        "MMB" => Some(20),
+        "MMY" => Some(0),
+        _ => None,
     }
 }
 
@@ -52,6 +74,43 @@ const DIST_TO_BINS: &[usize] = &[
     32,
 ];
 
+// Size of the potential table: atom types of both atoms and distance bins
+pub const DFIRE_ATOM_TYPES: usize = 169;
+pub const DFIRE_BINS: usize = 20;
+
+// Distance bin of a pair of atoms, None at or beyond the cutoff
+pub fn distance_bin(distance: f64) -> Option<usize> {
+    if distance >= DFIRE_CUTOFF {
+        return None;
+    }
+    let d = distance * 2.0 - 1.0;
+    let bin = DIST_TO_BINS[d as usize] - 1;
+    (bin < DFIRE_BINS).then_some(bin)
+}
+
+// Lower and upper distances of a bin
+pub fn bin_range(bin: usize) -> (f64, f64) {
+    let indexes: Vec<usize> = (0..DIST_TO_BINS.len())
+        .filter(|&d| DIST_TO_BINS[d] - 1 == bin)
+        .collect();
+    let lower = match indexes.first() {
+        Some(0) | None => 0.0,
+        Some(&d) => (d + 1) as f64 / 2.0,
+    };
+    let upper = indexes.last().map_or(0.0, |&d| (d + 2) as f64 / 2.0);
+    (lower, upper)
+}
+
+// Atom type of an atom, None for residues and atoms without one
+pub fn atom_type(residue_name: &str, atom_name: &str) -> Option<usize> {
+    let residue = residue_number(residue_name)?;
+    let atom = ATOMNUMBER.get(&format!("{}{}", residue_name, atom_name)[..])?;
+    Some(ATOMRES[residue][*atom])
+}
+
+// Backbone and CB atoms kept by the reduced pre-screening model
+const REDUCED_ATOMS: &[&str] = &["N", "CA", "C", "O", "CB"];
+
 lazy_static! {
     static ref ATOMNUMBER: HashMap<&'static str, usize> = hashmap![
         "ALAN" => 0, "ALACA" => 1, "ALAC" => 2, "ALAO" => 3, "ALACB" => 4,
@@ -104,32 +163,54 @@ lazy_static! {
 pub struct DFIREDockingModel {
     pub atoms: Vec<usize>,
     pub coordinates: Vec<[f64; 3]>,
+    pub heavy_atoms: Vec<usize>,
     pub membrane: Vec<usize>,
     pub active_restraints: HashMap<String, Vec<usize>>,
     pub passive_restraints: HashMap<String, Vec<usize>>,
+    pub hotspots: HashMap<String, Vec<usize>>,
+    pub hotspot_weights: HashMap<String, f64>,
+    // Scoring weight of every atom, the one of its residue or 1.0
+    pub weights: Vec<f64>,
     pub num_anm: usize,
-    pub nmodes: Vec<f64>,
+    pub nmodes: Nmodes,
+    // Residue IDs and the residue of every atom, see decomposition::push_residue
+    pub residues: Vec<String>,
+    pub atom_residues: Vec<usize>,
 }
 
 impl<'a> DFIREDockingModel {
-    fn new(
+    pub fn new(
         structure: &'a PDB,
         active_restraints: &'a [String],
         passive_restraints: &'a [String],
-        nmodes: &[f64],
+        hotspots: &'a HashMap<String, f64>,
+        weights: &'a HashMap<String, f64>,
+        nmodes: &Nmodes,
         num_anm: usize,
+        reduced: bool,
+        policy: ResiduePolicy,
     ) -> DFIREDockingModel {
         let mut model = DFIREDockingModel {
             atoms: Vec::new(),
             coordinates: Vec::new(),
+            heavy_atoms: Vec::new(),
             membrane: Vec::new(),
             active_restraints: HashMap::new(),
             passive_restraints: HashMap::new(),
-            nmodes: nmodes.to_owned(),
+            hotspots: HashMap::new(),
+            hotspot_weights: hotspots.clone(),
+            weights: Vec::new(),
+            nmodes: nmodes.clone(),
+            residues: Vec::new(),
+            atom_residues: Vec::new(),
             num_anm,
         };
 
+        // Indexes in the full structure of the atoms kept in the model
+        let mut kept: Vec<usize> = Vec::new();
+        let mut structure_index: usize = 0;
         let mut atom_index: u64 = 0;
+        let mut report = PolicyReport::default();
         for chain in structure.chains() {
             for residue in chain.residues() {
                 let res_name = match residue.name() {
@@ -140,12 +221,39 @@ impl<'a> DFIREDockingModel {
                 if let Some(c) = residue.insertion_code() {
                     res_id.push_str(c);
                 }
+                let action = policy.action(
+                    "DFIRE",
+                    &res_id,
+                    res_name,
+                    residue_number(res_name).is_some(),
+                    |name| residue_number(name).is_some(),
+                    &mut report,
+                );
+                if action == ResidueAction::Drop {
+                    structure_index += residue.atom_count();
+                    continue;
+                }
 
                 for atom in residue.atoms() {
+                    structure_index += 1;
+                    let (scored_name, atom_name) = action.names(res_name, atom.name());
                     // Membrane beads MMB.BJ
-                    let rec_atom_type = format!("{}{}", res_name, atom.name());
+                    let rec_atom_type = format!("{}{}", scored_name, atom_name);
+                    if action != ResidueAction::Keep && !ATOMNUMBER.contains_key(&rec_atom_type[..])
+                    {
+                        report.dropped_atoms += 1;
+                        continue;
+                    }
+                    if reduced
+                        && rec_atom_type != "MMBBJ"
+                        && !REDUCED_ATOMS.contains(&atom.name().trim())
+                    {
+                        continue;
+                    }
                     if rec_atom_type == "MMBBJ" {
                         model.membrane.push(atom_index as usize);
+                    } else if !is_hydrogen(residue, atom) {
+                        model.heavy_atoms.push(atom_index as usize);
                     }
 
                     if active_restraints.contains(&res_id) {
@@ -174,7 +282,15 @@ impl<'a> DFIREDockingModel {
                         }
                     }
 
-                    let rnuma = r3_to_numerical(res_name);
+                    if hotspots.contains_key(&res_id) {
+                        model
+                            .hotspots
+                            .entry(res_id.to_string())
+                            .or_default()
+                            .push(atom_index as usize);
+                    }
+
+                    let rnuma = r3_to_numerical(scored_name);
                     let anuma = match ATOMNUMBER.get(&rec_atom_type[..]) {
                         Some(&a) => a,
                         _ => panic!("Not supported atom type {:?}", rec_atom_type),
@@ -182,82 +298,787 @@ impl<'a> DFIREDockingModel {
                     let atoma = ATOMRES[rnuma][anuma];
                     model.atoms.push(atoma);
                     model.coordinates.push([atom.x(), atom.y(), atom.z()]);
+                    push_residue(&mut model.residues, &mut model.atom_residues, &res_id);
+                    model
+                        .weights
+                        .push(weights.get(&res_id).copied().unwrap_or(1.0));
+                    kept.push(structure_index - 1);
                     atom_index += 1;
                 }
             }
         }
+
+        report.log("DFIRE");
+
+        // Normal modes are stored as (num_anm, num_atoms, 3), keep only the selected atoms
+        if kept.len() < structure_index && num_anm > 0 && !nmodes.is_empty() {
+            model.nmodes = select_nmodes(nmodes, num_anm, structure_index, &kept).into();
+        }
         model
     }
 }
 
+// Lookup of the DFIRE potential, (receptor atom type, ligand atom type, bin) -> 1d
+pub trait PotentialTable {
+    fn value(&self, index: usize) -> f64;
+}
+
+impl PotentialTable for Vec<f64> {
+    #[inline]
+    fn value(&self, index: usize) -> f64 {
+        self[index]
+    }
+}
+
+// DFIRE potential stored as 16-bit integers and a scale factor, a quarter of the size of the
+// table of doubles so more of it stays in the cache. Values are dequantized on the fly, with an
+// error of at most half the scale, the largest absolute value of the table over i16::MAX.
+pub struct QuantizedPotential {
+    pub scale: f64,
+    pub values: Vec<i16>,
+}
+
+impl QuantizedPotential {
+    pub fn new(potential: &[f64]) -> QuantizedPotential {
+        let max = potential
+            .iter()
+            .fold(0.0_f64, |max, value| max.max(value.abs()));
+        let scale = if max > 0.0 {
+            max / i16::MAX as f64
+        } else {
+            1.0
+        };
+        QuantizedPotential {
+            scale,
+            values: potential
+                .iter()
+                .map(|value| (value / scale).round() as i16)
+                .collect(),
+        }
+    }
+}
+
+impl PotentialTable for QuantizedPotential {
+    #[inline]
+    fn value(&self, index: usize) -> f64 {
+        self.values[index] as f64 * self.scale
+    }
+}
+
+// Multi-resolution scoring of large partners: pairs of residues with their centers within the
+// shell radius are scored atom by atom, farther pairs as if every atom of the ligand residue was
+// at the distance between the centers. The potential of the atoms of every receptor residue is
+// summed beforehand for each ligand atom type and distance bin, in profiles shared by the
+// residues with the same atoms and weights. Only the pairs scored atom by atom make the interface.
+pub struct DFIREShell {
+    pub radius: f64,
+    // Atoms of every residue of the receptor and the ligand
+    pub receptor_residues: Vec<Range<usize>>,
+    pub ligand_residues: Vec<Range<usize>>,
+    // Profile of every receptor residue, profiles as (profile, atom type, bin) -> 1d
+    pub receptor_profiles: Vec<usize>,
+    pub profiles: Vec<f64>,
+    // Residue centers of the pose being scored
+    receptor_centers: RefCell<Vec<[f64; 3]>>,
+    ligand_centers: RefCell<Vec<[f64; 3]>>,
+}
+
+// Atoms of every residue of a model, the atoms of a residue are consecutive
+fn residue_ranges(model: &DFIREDockingModel) -> Vec<Range<usize>> {
+    let mut ranges: Vec<Range<usize>> = vec![0..0; model.residues.len()];
+    for (i_atom, &residue) in model.atom_residues.iter().enumerate() {
+        if ranges[residue].is_empty() {
+            ranges[residue].start = i_atom;
+        }
+        ranges[residue].end = i_atom + 1;
+    }
+    ranges
+}
+
+fn residue_centers(ranges: &[Range<usize>], coordinates: &[[f64; 3]], centers: &mut [[f64; 3]]) {
+    for (center, atoms) in centers.iter_mut().zip(ranges) {
+        *center = [0.0; 3];
+        for coordinate in &coordinates[atoms.clone()] {
+            center[0] += coordinate[0];
+            center[1] += coordinate[1];
+            center[2] += coordinate[2];
+        }
+        let num_atoms = atoms.len() as f64;
+        center[0] /= num_atoms;
+        center[1] /= num_atoms;
+        center[2] /= num_atoms;
+    }
+}
+
+impl DFIREShell {
+    pub fn new(
+        potential: &[f64],
+        receptor: &DFIREDockingModel,
+        ligand: &DFIREDockingModel,
+        radius: f64,
+    ) -> DFIREShell {
+        let receptor_residues = residue_ranges(receptor);
+        let ligand_residues = residue_ranges(ligand);
+        let profile_size = DFIRE_ATOM_TYPES * DFIRE_BINS;
+        let mut keys: HashMap<Vec<(usize, u64)>, usize> = HashMap::new();
+        let mut receptor_profiles: Vec<usize> = Vec::with_capacity(receptor_residues.len());
+        let mut profiles: Vec<f64> = Vec::new();
+        for atoms in receptor_residues.iter() {
+            let mut key: Vec<(usize, u64)> = atoms
+                .clone()
+                .map(|i| (receptor.atoms[i], receptor.weights[i].to_bits()))
+                .collect();
+            key.sort_unstable();
+            let num_profiles = keys.len();
+            let profile = *keys.entry(key).or_insert_with(|| {
+                profiles.resize((num_profiles + 1) * profile_size, 0.0);
+                let values = &mut profiles[num_profiles * profile_size..];
+                for i in atoms.clone() {
+                    let potentials = &potential[receptor.atoms[i] * profile_size..];
+                    for (value, potential) in values.iter_mut().zip(potentials) {
+                        *value += potential * receptor.weights[i];
+                    }
+                }
+                num_profiles
+            });
+            receptor_profiles.push(profile);
+        }
+        DFIREShell {
+            radius,
+            receptor_centers: RefCell::new(vec![[0.0; 3]; receptor_residues.len()]),
+            ligand_centers: RefCell::new(vec![[0.0; 3]; ligand_residues.len()]),
+            receptor_residues,
+            ligand_residues,
+            receptor_profiles,
+            profiles,
+        }
+    }
+
+    pub fn num_profiles(&self) -> usize {
+        self.profiles.len() / (DFIRE_ATOM_TYPES * DFIRE_BINS)
+    }
+
+    // DFIRE sum of the pose before scaling, marking the interface of the pairs scored atom by atom
+    fn score<P: PotentialTable>(
+        &self,
+        potential: &P,
+        receptor: &DFIREDockingModel,
+        receptor_coordinates: &[[f64; 3]],
+        ligand: &DFIREDockingModel,
+        ligand_coordinates: &[[f64; 3]],
+        interface_receptor: &mut [usize],
+        interface_ligand: &mut [usize],
+    ) -> f64 {
+        let mut receptor_centers = self.receptor_centers.borrow_mut();
+        let mut ligand_centers = self.ligand_centers.borrow_mut();
+        residue_centers(
+            &self.receptor_residues,
+            receptor_coordinates,
+            &mut receptor_centers,
+        );
+        residue_centers(
+            &self.ligand_residues,
+            ligand_coordinates,
+            &mut ligand_centers,
+        );
+
+        let mut score: f64 = 0.0;
+        for (r, receptor_atoms) in self.receptor_residues.iter().enumerate() {
+            let rc = receptor_centers[r];
+            let profile =
+                &self.profiles[self.receptor_profiles[r] * DFIRE_ATOM_TYPES * DFIRE_BINS..];
+            for (l, ligand_atoms) in self.ligand_residues.iter().enumerate() {
+                let lc = ligand_centers[l];
+                let distance = ((rc[0] - lc[0]) * (rc[0] - lc[0])
+                    + (rc[1] - lc[1]) * (rc[1] - lc[1])
+                    + (rc[2] - lc[2]) * (rc[2] - lc[2]))
+                    .sqrt();
+                if distance <= self.radius {
+                    for i in receptor_atoms.clone() {
+                        let ra = receptor_coordinates[i];
+                        let atoma = receptor.atoms[i];
+                        for j in ligand_atoms.clone() {
+                            let la = ligand_coordinates[j];
+                            let dist = (ra[0] - la[0]) * (ra[0] - la[0])
+                                + (ra[1] - la[1]) * (ra[1] - la[1])
+                                + (ra[2] - la[2]) * (ra[2] - la[2]);
+                            if dist <= DFIRE_CUTOFF * DFIRE_CUTOFF {
+                                let d = dist.sqrt() * 2.0 - 1.0;
+                                let dfire_bin = DIST_TO_BINS[d as usize] - 1;
+                                score += potential
+                                    .value(atoma * 169 * 20 + ligand.atoms[j] * 20 + dfire_bin)
+                                    * receptor.weights[i]
+                                    * ligand.weights[j];
+                                if in_interface(dist) {
+                                    interface_receptor[i] = 1;
+                                    interface_ligand[j] = 1;
+                                }
+                            }
+                        }
+                    }
+                } else if let Some(bin) = distance_bin(distance) {
+                    for j in ligand_atoms.clone() {
+                        score += profile[ligand.atoms[j] * DFIRE_BINS + bin] * ligand.weights[j];
+                    }
+                }
+            }
+        }
+        score
+    }
+}
+
+// Precomputed DFIRE field of a rigid receptor, one value per grid point and ligand atom type
+pub struct DFIREGrid {
+    pub origin: [f64; 3],
+    pub spacing: f64,
+    pub dimensions: [usize; 3],
+    // Position of each DFIRE atom type in the grid values, only for types found in the ligand
+    pub slots: Vec<Option<usize>>,
+    pub num_slots: usize,
+    pub values: Vec<f32>,
+}
+
+impl DFIREGrid {
+    pub fn new(
+        potential: &[f64],
+        receptor: &DFIREDockingModel,
+        ligand: &DFIREDockingModel,
+        spacing: f64,
+    ) -> DFIREGrid {
+        let mut slots: Vec<Option<usize>> = vec![None; 169];
+        let mut types: Vec<usize> = Vec::new();
+        for &atom in ligand.atoms.iter() {
+            if slots[atom].is_none() {
+                slots[atom] = Some(types.len());
+                types.push(atom);
+            }
+        }
+
+        // Beyond the cutoff of every receptor atom the field is zero
+        let mut origin = [f64::MAX; 3];
+        let mut end = [f64::MIN; 3];
+        for coordinate in receptor.coordinates.iter() {
+            for k in 0..3 {
+                origin[k] = origin[k].min(coordinate[k] - DFIRE_CUTOFF);
+                end[k] = end[k].max(coordinate[k] + DFIRE_CUTOFF);
+            }
+        }
+        let mut dimensions = [0usize; 3];
+        for k in 0..3 {
+            dimensions[k] = ((end[k] - origin[k]) / spacing).ceil() as usize + 1;
+        }
+
+        let num_slots = types.len();
+        // Potential rearranged as [receptor type][bin][slot] for a contiguous inner loop
+        let mut table: Vec<f32> = vec![0.0; 169 * 20 * num_slots];
+        for atoma in 0..169 {
+            for dfire_bin in 0..20 {
+                for (slot, &atomb) in types.iter().enumerate() {
+                    table[(atoma * 20 + dfire_bin) * num_slots + slot] =
+                        potential[atoma * 169 * 20 + atomb * 20 + dfire_bin] as f32;
+                }
+            }
+        }
+        let mut values: Vec<f32> =
+            vec![0.0; dimensions[0] * dimensions[1] * dimensions[2] * num_slots];
+        let reach = (DFIRE_CUTOFF / spacing).ceil() as usize;
+        for (i, ra) in receptor.coordinates.iter().enumerate() {
+            let atoma = receptor.atoms[i];
+            let weight = receptor.weights[i] as f32;
+            let mut lower = [0usize; 3];
+            let mut upper = [0usize; 3];
+            for k in 0..3 {
+                let center = ((ra[k] - origin[k]) / spacing).round() as usize;
+                lower[k] = center.saturating_sub(reach);
+                upper[k] = (center + reach).min(dimensions[k] - 1);
+            }
+            for x in lower[0]..=upper[0] {
+                let dx = origin[0] + x as f64 * spacing - ra[0];
+                for y in lower[1]..=upper[1] {
+                    let dy = origin[1] + y as f64 * spacing - ra[1];
+                    for z in lower[2]..=upper[2] {
+                        let dz = origin[2] + z as f64 * spacing - ra[2];
+                        let dist = dx * dx + dy * dy + dz * dz;
+                        if dist <= DFIRE_CUTOFF * DFIRE_CUTOFF {
+                            let d = dist.sqrt() * 2.0 - 1.0;
+                            let dfire_bin = DIST_TO_BINS[d as usize] - 1;
+                            let point = ((x * dimensions[1] + y) * dimensions[2] + z) * num_slots;
+                            let row = (atoma * 20 + dfire_bin) * num_slots;
+                            for (value, potential) in values[point..point + num_slots]
+                                .iter_mut()
+                                .zip(&table[row..row + num_slots])
+                            {
+                                *value += potential * weight;
+                            }
+                        }
+                    }
+                }
+            }
+        }
+
+        DFIREGrid {
+            origin,
+            spacing,
+            dimensions,
+            slots,
+            num_slots,
+            values,
+        }
+    }
+
+    // Trilinear interpolation of the field for a ligand atom, zero outside of the grid
+    pub fn interpolate(&self, atom: usize, coordinate: &[f64; 3]) -> f64 {
+        let slot = match self.slots[atom] {
+            Some(slot) => slot,
+            None => return 0.0,
+        };
+        let mut cell = [0usize; 3];
+        let mut fraction = [0f64; 3];
+        for k in 0..3 {
+            let position = (coordinate[k] - self.origin[k]) / self.spacing;
+            if position < 0.0 || position >= (self.dimensions[k] - 1) as f64 {
+                return 0.0;
+            }
+            cell[k] = position as usize;
+            fraction[k] = position - cell[k] as f64;
+        }
+        let mut value = 0.0;
+        for corner in 0..8 {
+            let mut weight = 1.0;
+            let mut index = [0usize; 3];
+            for k in 0..3 {
+                let upper = (corner >> k) & 1;
+                index[k] = cell[k] + upper;
+                weight *= if upper == 1 {
+                    fraction[k]
+                } else {
+                    1.0 - fraction[k]
+                };
+            }
+            let point = ((index[0] * self.dimensions[1] + index[1]) * self.dimensions[2]
+                + index[2])
+                * self.num_slots;
+            value += weight * self.values[point + slot] as f64;
+        }
+        value
+    }
+}
+
+// Options of DFIRE only, the default ones score every atom with the exact potential
+#[derive(Debug, Clone, Copy, Default)]
+pub struct DFIREOptions {
+    // Backbone and CB model of the pre-screening
+    pub reduced: bool,
+    // Receptor grid of the potential with the given spacing (Angstroms)
+    pub grid_spacing: Option<f64>,
+    // Residue profiles of the potential within the given radius (Angstroms)
+    pub shell_radius: Option<f64>,
+    // 16-bit potential
+    pub quantized: bool,
+    // Poses scored in f32, not with the grid, shell or quantized potential
+    pub single_precision: bool,
+}
+
 pub struct DFIRE {
     pub potential: Vec<f64>,
+    // 16-bit potential replacing the table of doubles if set
+    pub quantized: Option<QuantizedPotential>,
     pub receptor: DFIREDockingModel,
     pub ligand: DFIREDockingModel,
     pub use_anm: bool,
+    pub clash_filter: Option<ClashFilter>,
+    pub restraints_metric: RestraintsMetric,
+    pub workspace: RefCell<Workspace>,
+    pub grid: Option<DFIREGrid>,
+    pub shell: Option<DFIREShell>,
+    // Membrane beads of the receptor, for the evaluations without the whole interface
+    pub membrane: Option<MembraneGrid>,
+    // Single precision copies of the partners and the potential scoring the poses if set
+    pub single: Option<SinglePrecision<Vec<f32>>>,
+}
+
+// DFIRE parameters, one value per line for every pair of atom types and distance bin
+fn parse_potentials(raw_parameters: &str) -> Result<Vec<f64>, String> {
+    let mut potential: Vec<f64> = Vec::with_capacity(169 * 169 * 20);
+    for (i, param) in raw_parameters.lines().take(169 * 169 * 20).enumerate() {
+        let value = param
+            .trim()
+            .parse::<f64>()
+            .map_err(|_| format!("wrong DFIRE parameter {:?} at line {}", param, i + 1))?;
+        potential.push(value);
+    }
+    if potential.len() < 169 * 169 * 20 {
+        return Err(format!(
+            "{} DFIRE parameters found, {} expected",
+            potential.len(),
+            169 * 169 * 20
+        ));
+    }
+    Ok(potential)
 }
 
 impl<'a> DFIRE {
     pub fn new(
-        receptor: PDB,
-        rec_active_restraints: Vec<String>,
-        rec_passive_restraints: Vec<String>,
-        rec_nmodes: Vec<f64>,
-        rec_num_anm: usize,
-        ligand: PDB,
-        lig_active_restraints: Vec<String>,
-        lig_passive_restraints: Vec<String>,
-        lig_nmodes: Vec<f64>,
-        lig_num_anm: usize,
-        use_anm: bool,
-    ) -> Box<dyn Score + 'a> {
+        input: ScoringInput,
+        options: DFIREOptions,
+    ) -> Result<Box<dyn Score + 'a>, Box<dyn Error>> {
+        let ScoringInput {
+            receptor,
+            rec_active_restraints,
+            rec_passive_restraints,
+            rec_hotspots,
+            rec_weights,
+            rec_nmodes,
+            rec_num_anm,
+            ligand,
+            lig_active_restraints,
+            lig_passive_restraints,
+            lig_hotspots,
+            lig_weights,
+            lig_nmodes,
+            lig_num_anm,
+            use_anm,
+            clash_filter,
+            restraints_metric,
+            policy,
+        } = input;
+        let DFIREOptions {
+            reduced,
+            grid_spacing,
+            shell_radius,
+            quantized,
+            single_precision,
+        } = options;
+        if single_precision && (quantized || grid_spacing.is_some() || shell_radius.is_some()) {
+            return Err(
+                "Single precision can not be used with the DFIRE grid, shell or quantized potential"
+                    .into(),
+            );
+        }
         let mut d = DFIRE {
-            potential: Vec::with_capacity(169 * 169 * 20),
+            potential: Vec::new(),
+            quantized: None,
             receptor: DFIREDockingModel::new(
                 &receptor,
                 &rec_active_restraints,
                 &rec_passive_restraints,
+                &rec_hotspots,
+                &rec_weights,
                 &rec_nmodes,
                 rec_num_anm,
+                reduced,
+                policy,
             ),
             ligand: DFIREDockingModel::new(
                 &ligand,
                 &lig_active_restraints,
                 &lig_passive_restraints,
+                &lig_hotspots,
+                &lig_weights,
                 &lig_nmodes,
                 lig_num_anm,
+                reduced,
+                policy,
             ),
             use_anm,
+            clash_filter,
+            restraints_metric,
+            grid: None,
+            shell: None,
+            membrane: None,
+            single: None,
+            workspace: RefCell::new(Workspace::default()),
         };
-        d.load_potentials();
-        Box::new(d)
+        d.workspace = RefCell::new(Workspace::new(
+            &d.receptor.coordinates,
+            &d.ligand.coordinates,
+        ));
+        if let Some(filter) = &d.clash_filter {
+            d.workspace.get_mut().clash_grid =
+                filter.grid(&d.receptor.coordinates, &d.receptor.heavy_atoms);
+        }
+        d.workspace.get_mut().cell_list =
+            Some(CellList::new(&d.receptor.coordinates, DFIRE_CUTOFF));
+        d.load_potentials()?;
+        if !d.receptor.membrane.is_empty() {
+            let beads: Vec<[f64; 3]> = d
+                .receptor
+                .membrane
+                .iter()
+                .map(|&i| d.receptor.coordinates[i])
+                .collect();
+            d.membrane = Some(MembraneGrid::new(beads));
+        }
+        if let Some(spacing) = grid_spacing {
+            if d.supports_grid() {
+                d.grid = Some(DFIREGrid::new(
+                    &d.potential,
+                    &d.receptor,
+                    &d.ligand,
+                    spacing,
+                ));
+                info!(
+                    "DFIRE receptor grid of {:?} points",
+                    d.grid.as_ref().unwrap().dimensions
+                );
+                // Only the interface is searched atom by atom, for the restraints and hot-spots
+                d.workspace.get_mut().cell_list =
+                    Some(CellList::new(&d.receptor.coordinates, INTERFACE_CUTOFF));
+            } else {
+                warn!("DFIRE grid requires a rigid receptor");
+            }
+        }
+        if let Some(radius) = shell_radius {
+            let shell = DFIREShell::new(&d.potential, &d.receptor, &d.ligand, radius);
+            info!(
+                "DFIRE distance shell of {} A, {} receptor residue profiles",
+                radius,
+                shell.num_profiles()
+            );
+            d.shell = Some(shell);
+        }
+        if single_precision {
+            d.single = Some(SinglePrecision::new(
+                SinglePartner::new(
+                    &d.receptor.coordinates,
+                    &d.receptor.nmodes,
+                    &d.receptor.weights,
+                ),
+                SinglePartner::new(&d.ligand.coordinates, &d.ligand.nmodes, &d.ligand.weights),
+                to_single(&d.potential),
+                DFIRE_CUTOFF,
+            ));
+            info!("DFIRE poses scored in single precision");
+        }
+        if quantized {
+            // Grid and shell keep the values they were built from
+            let potential = QuantizedPotential::new(&d.potential);
+            info!("DFIRE potential quantized with scale {:e}", potential.scale);
+            d.quantized = Some(potential);
+            d.potential = Vec::new();
+        }
+        Ok(Box::new(d))
     }
 
-    pub fn load_potentials(&mut self) {
-        let mut raw_parameters = String::new();
+    // Potential used for scoring, the quantized one if set
+    pub fn potential_table(&self) -> &dyn PotentialTable {
+        match &self.quantized {
+            Some(quantized) => quantized,
+            None => &self.potential,
+        }
+    }
 
-        let data_folder = match env::var("LIGHTDOCK_DATA") {
-            Ok(val) => val,
-            Err(_) => String::from("data"),
+    // DFIRE sum of the atom pairs of the pose before scaling, marking the interface. Only the
+    // ligand atoms around the cell of every receptor atom are visited, their distances computed
+    // four at a time.
+    fn pairs_score<P: PotentialTable>(
+        &self,
+        potential: &P,
+        cell_list: &mut CellList,
+        receptor_coordinates: &[[f64; 3]],
+        interface_receptor: &mut [usize],
+        interface_ligand: &mut [usize],
+    ) -> f64 {
+        let mut score: f64 = 0.0;
+        for (i, ra) in receptor_coordinates.iter().enumerate() {
+            let atoma = self.receptor.atoms[i];
+            let weighta = self.receptor.weights[i];
+            let (neighbors, distances) = cell_list.distances(i, ra);
+            for (&j, &dist) in neighbors.iter().zip(distances.iter()) {
+                if dist <= DFIRE_CUTOFF * DFIRE_CUTOFF {
+                    let atomb = self.ligand.atoms[j];
+                    let d = dist.sqrt() * 2.0 - 1.0;
+                    let dfire_bin = DIST_TO_BINS[d as usize] - 1;
+                    score += potential.value(atoma * 169 * 20 + atomb * 20 + dfire_bin)
+                        * weighta
+                        * self.ligand.weights[j];
+                    if in_interface(dist) {
+                        interface_receptor[i] = 1;
+                        interface_ligand[j] = 1;
+                    }
+                }
+            }
+        }
+        score
+    }
+
+    // Score of the pose biased by its satisfied restraints, hot-spots and membrane intersection
+    fn biased_score(
+        &self,
+        score: f64,
+        receptor_coordinates: &[[f64; 3]],
+        ligand_coordinates: &[[f64; 3]],
+        interface_receptor: &[usize],
+        interface_ligand: &[usize],
+    ) -> f64 {
+        // Bias the scoring depending on satisfied restraints
+        let perc_receptor_restraints: f64 = self.restraints_metric.satisfaction(
+            interface_receptor,
+            &self.receptor.active_restraints,
+            receptor_coordinates,
+            ligand_coordinates,
+        );
+        let perc_ligand_restraints: f64 = self.restraints_metric.satisfaction(
+            interface_ligand,
+            &self.ligand.active_restraints,
+            ligand_coordinates,
+            receptor_coordinates,
+        );
+        // Weighted hot-spot residues at the interface
+        let receptor_hotspots: f64 = hotspot_bias(
+            interface_receptor,
+            &self.receptor.hotspots,
+            &self.receptor.hotspot_weights,
+        );
+        let ligand_hotspots: f64 = hotspot_bias(
+            interface_ligand,
+            &self.ligand.hotspots,
+            &self.ligand.hotspot_weights,
+        );
+        // Take into account membrane intersection
+        let mut membrane_penalty: f64 = 0.0;
+        let intersection = match (&self.shell, &self.membrane) {
+            // Beads are not in the interface of the shell, made of the nearby residue pairs
+            (Some(_), Some(membrane)) => membrane.intersection(ligand_coordinates),
+            _ => membrane_intersection(interface_receptor, &self.receptor.membrane),
         };
+        if intersection > 0.0 {
+            membrane_penalty = MEMBRANE_PENALTY_SCORE * intersection;
+        }
 
-        let parameters_path: String = format!("{}/DCparams", data_folder);
+        score
+            + perc_receptor_restraints * score
+            + perc_ligand_restraints * score
+            + receptor_hotspots * score
+            + ligand_hotspots * score
+            - membrane_penalty
+    }
 
-        File::open(parameters_path)
-            .expect("Unable to open DFIRE parameters")
-            .read_to_string(&mut raw_parameters)
-            .expect("Unable to read DFIRE parameters");
+    // Energy of the pose scored in single precision
+    fn single_energy(
+        &self,
+        single: &SinglePrecision<Vec<f32>>,
+        translation: &[f64],
+        rotation: &Quaternion,
+        rec_nmodes: &[f64],
+        lig_nmodes: &[f64],
+    ) -> f64 {
+        let mut workspace = self.workspace.borrow_mut();
+        let Workspace {
+            receptor_coordinates,
+            ligand_coordinates,
+            interface_receptor,
+            interface_ligand,
+            clash_grid,
+            ..
+        } = &mut *workspace;
+        interface_receptor.fill(0);
+        interface_ligand.fill(0);
+        let mut buffers = single.workspace.borrow_mut();
 
-        let split = raw_parameters.lines();
-        let params: Vec<&str> = split.collect();
+        let timer = pose_timer();
+        let rec_extents = anm_extents(self.use_anm, self.receptor.num_anm, rec_nmodes);
+        let lig_extents = anm_extents(self.use_anm, self.ligand.num_anm, lig_nmodes);
+        buffers.pose(
+            &single.receptor,
+            &single.ligand,
+            translation,
+            rotation,
+            rec_extents,
+            lig_extents,
+        );
+        buffers.to_double(
+            receptor_coordinates,
+            ligand_coordinates,
+            !rec_extents.is_empty(),
+        );
+        add_pose_time(timer);
 
-        for param in params.iter().take(169 * 169 * 20) {
-            self.potential.push(param.trim().parse::<f64>().unwrap());
+        // Discard hopeless poses before the full evaluation
+        if let Some(filter) = &self.clash_filter {
+            // The grid is only hashed again if the receptor moved
+            if !rec_extents.is_empty() {
+                filter.update_grid(clash_grid, receptor_coordinates, &self.receptor.heavy_atoms);
+            }
+            if filter.is_clashing(
+                clash_grid,
+                receptor_coordinates,
+                ligand_coordinates,
+                &self.ligand.heavy_atoms,
+            ) {
+                return REJECTED_POSE_SCORE;
+            }
+        }
+
+        buffers.search(!rec_extents.is_empty());
+        let mut score: f32 = 0.0;
+        for i in 0..buffers.receptor_coordinates.len() {
+            let ra = buffers.receptor_coordinates[i];
+            let atoma = self.receptor.atoms[i];
+            let weighta = single.receptor.weights[i];
+            let (neighbors, distances) = buffers.cell_list.distances(i, &ra);
+            for (&j, &dist) in neighbors.iter().zip(distances.iter()) {
+                if dist <= (DFIRE_CUTOFF * DFIRE_CUTOFF) as f32 {
+                    let atomb = self.ligand.atoms[j];
+                    let d = dist.sqrt() * 2.0 - 1.0;
+                    let dfire_bin = DIST_TO_BINS[d as usize] - 1;
+                    score += single.parameters[atoma * 169 * 20 + atomb * 20 + dfire_bin]
+                        * weighta
+                        * single.ligand.weights[j];
+                    if in_interface(dist as f64) {
+                        interface_receptor[i] = 1;
+                        interface_ligand[j] = 1;
+                    }
+                }
+            }
         }
+
+        let score = -(score as f64 * 0.0157 - 4.7);
+        self.biased_score(
+            score,
+            receptor_coordinates,
+            ligand_coordinates,
+            interface_receptor,
+            interface_ligand,
+        )
+    }
+
+    // The grid holds the field of the receptor as it was built
+    pub fn supports_grid(&self) -> bool {
+        !(self.use_anm && self.receptor.num_anm > 0)
+    }
+
+    // Whether the score is biased by terms depending on the interface
+    pub fn has_interface_terms(&self) -> bool {
+        !(self.receptor.active_restraints.is_empty()
+            && self.ligand.active_restraints.is_empty()
+            && self.receptor.hotspots.is_empty()
+            && self.ligand.hotspots.is_empty())
+    }
+
+    pub fn load_potentials(&mut self) -> Result<(), Box<dyn Error>> {
+        let mut raw_parameters = String::new();
+
+        let parameters_path = find_data_file("DCparams")
+            .map_err(|e| format!("Unable to open DFIRE parameters: {}", e))?;
+
+        File::open(&parameters_path)
+            .and_then(|mut file| file.read_to_string(&mut raw_parameters))
+            .map_err(|e| {
+                format!(
+                    "Unable to read DFIRE parameters {}: {}",
+                    parameters_path.display(),
+                    e
+                )
+            })?;
+
+        self.potential = parse_potentials(&raw_parameters)
+            .map_err(|e| format!("{}: {}", parameters_path.display(), e))?;
+        Ok(())
     }
 
     pub fn get_potential(&mut self, x: usize, y: usize, z: usize) -> f64 {
-        self.potential[x + 169 * (y + 20 * z)]
+        self.potential_table().value(x + 169 * (y + 20 * z))
     }
 }
 
@@ -269,19 +1090,34 @@ impl Score for DFIRE {
         rec_nmodes: &[f64],
         lig_nmodes: &[f64],
     ) -> f64 {
+        if let Some(single) = &self.single {
+            return self.single_energy(single, translation, rotation, rec_nmodes, lig_nmodes);
+        }
         let mut score: f64 = 0.0;
 
-        // Clone receptor coordinates
-        let mut receptor_coordinates: Vec<[f64; 3]> = self.receptor.coordinates.clone();
+        // Start from the original coordinates in the preallocated buffers
+        let mut workspace = self.workspace.borrow_mut();
+        workspace.reset(
+            &self.receptor.coordinates,
+            &self.ligand.coordinates,
+            self.use_anm && self.receptor.num_anm > 0,
+        );
+        let Workspace {
+            receptor_coordinates,
+            ligand_coordinates,
+            interface_receptor,
+            interface_ligand,
+            cell_list,
+            clash_grid,
+        } = &mut *workspace;
         let rec_num_atoms = receptor_coordinates.len();
-        // Clone ligand coordinates
-        let mut ligand_coordinates: Vec<[f64; 3]> = self.ligand.coordinates.clone();
         let lig_num_atoms = ligand_coordinates.len();
 
+        let timer = pose_timer();
         // Get the proper ligand pose
         for (i_atom, coordinate) in ligand_coordinates.iter_mut().enumerate() {
             // First rotate
-            let rotated_coordinate = rotation.rotate(coordinate.to_vec());
+            let rotated_coordinate = rotation.rotate_point(coordinate);
             // Then tranlate
             coordinate[0] = rotated_coordinate[0] + translation[0];
             coordinate[1] = rotated_coordinate[1] + translation[1];
@@ -318,54 +1154,217 @@ impl Score for DFIRE {
                 }
             }
         }
-        // Calculate scoring and interface
-        let mut interface_receptor: Vec<usize> = vec![0; receptor_coordinates.len()];
-        let mut interface_ligand: Vec<usize> = vec![0; ligand_coordinates.len()];
+        add_pose_time(timer);
 
-        for (i, ra) in receptor_coordinates.iter().enumerate() {
-            let x1 = ra[0];
-            let y1 = ra[1];
-            let z1 = ra[2];
-            let atoma = self.receptor.atoms[i];
+        // Discard hopeless poses before the full evaluation
+        if let Some(filter) = &self.clash_filter {
+            // The grid is only hashed again if the receptor moved
+            if self.use_anm && self.receptor.num_anm > 0 {
+                filter.update_grid(clash_grid, receptor_coordinates, &self.receptor.heavy_atoms);
+            }
+            if filter.is_clashing(
+                clash_grid,
+                receptor_coordinates,
+                ligand_coordinates,
+                &self.ligand.heavy_atoms,
+            ) {
+                return REJECTED_POSE_SCORE;
+            }
+        }
+
+        if let Some(grid) = &self.grid {
             for (j, la) in ligand_coordinates.iter().enumerate() {
-                let dist = (x1 - la[0]) * (x1 - la[0])
-                    + (y1 - la[1]) * (y1 - la[1])
-                    + (z1 - la[2]) * (z1 - la[2]);
-                if dist <= 225. {
-                    let atomb = self.ligand.atoms[j];
-                    let d = dist.sqrt() * 2.0 - 1.0;
-                    let dfire_bin = DIST_TO_BINS[d as usize] - 1;
-                    score += self.potential[atoma * 169 * 20 + atomb * 20 + dfire_bin];
-                    if d <= INTERFACE_CUTOFF {
+                score += grid.interpolate(self.ligand.atoms[j], la) * self.ligand.weights[j];
+            }
+            if !self.has_interface_terms() {
+                // Membrane beads crossed by the ligand, without searching the interface
+                let intersection = self
+                    .membrane
+                    .as_ref()
+                    .map_or(0.0, |membrane| membrane.intersection(ligand_coordinates));
+                return -(score * 0.0157 - 4.7) - MEMBRANE_PENALTY_SCORE * intersection;
+            }
+            // Interface of the pairs closer than its cutoff, found in cells of that size
+            let cell_list = cell_list.as_mut().expect("DFIRE cell list not built");
+            cell_list.search(ligand_coordinates);
+            for (i, ra) in receptor_coordinates.iter().enumerate() {
+                let (neighbors, distances) = cell_list.distances(i, ra);
+                for (&j, &dist) in neighbors.iter().zip(distances.iter()) {
+                    if in_interface(dist) {
                         interface_receptor[i] = 1;
                         interface_ligand[j] = 1;
                     }
                 }
             }
+            return self.biased_score(
+                -(score * 0.0157 - 4.7),
+                receptor_coordinates,
+                ligand_coordinates,
+                interface_receptor,
+                interface_ligand,
+            );
         }
 
-        score = (score * 0.0157 - 4.7) * -1.0;
+        // Ligand atoms around the cells of the receptor, hashed again if its atoms were moved by
+        // the normal modes
+        let cell_list = cell_list.as_mut().expect("DFIRE cell list not built");
+        if self.shell.is_none() {
+            if self.use_anm && self.receptor.num_anm > 0 {
+                cell_list.update(receptor_coordinates);
+            }
+            cell_list.search(ligand_coordinates);
+        }
 
-        // Bias the scoring depending on satisfied restraints
-        let perc_receptor_restraints: f64 =
-            satisfied_restraints(&interface_receptor, &self.receptor.active_restraints);
-        let perc_ligand_restraints: f64 =
-            satisfied_restraints(&interface_ligand, &self.ligand.active_restraints);
-        // Take into account membrane intersection
-        let mut membrane_penalty: f64 = 0.0;
-        let intersection = membrane_intersection(&interface_receptor, &self.receptor.membrane);
-        if intersection > 0.0 {
-            membrane_penalty = MEMBRANE_PENALTY_SCORE * intersection;
+        // Generic over the table so the lookup is inlined in the loops over the atom pairs
+        score = match (&self.shell, &self.quantized) {
+            (Some(shell), Some(quantized)) => shell.score(
+                quantized,
+                &self.receptor,
+                receptor_coordinates,
+                &self.ligand,
+                ligand_coordinates,
+                interface_receptor,
+                interface_ligand,
+            ),
+            (Some(shell), None) => shell.score(
+                &self.potential,
+                &self.receptor,
+                receptor_coordinates,
+                &self.ligand,
+                ligand_coordinates,
+                interface_receptor,
+                interface_ligand,
+            ),
+            (None, Some(quantized)) => self.pairs_score(
+                quantized,
+                cell_list,
+                receptor_coordinates,
+                interface_receptor,
+                interface_ligand,
+            ),
+            (None, None) => self.pairs_score(
+                &self.potential,
+                cell_list,
+                receptor_coordinates,
+                interface_receptor,
+                interface_ligand,
+            ),
+        };
+
+        score = -(score * 0.0157 - 4.7);
+        self.biased_score(
+            score,
+            receptor_coordinates,
+            ligand_coordinates,
+            interface_receptor,
+            interface_ligand,
+        )
+    }
+
+    fn energy_with_interface(
+        &self,
+        translation: &[f64],
+        rotation: &Quaternion,
+        rec_nmodes: &[f64],
+        lig_nmodes: &[f64],
+    ) -> (f64, Option<Interface>) {
+        let energy = self.energy(translation, rotation, rec_nmodes, lig_nmodes);
+        // Poses scored on the receptor grid only have an interface for the terms depending on it
+        if self.grid.is_some() && !self.has_interface_terms() {
+            return (energy, None);
         }
+        // The evaluation leaves the interface of the pose in the workspace
+        let workspace = self.workspace.borrow();
+        let interface = Interface {
+            receptor: interface_residues(
+                &workspace.interface_receptor,
+                &self.receptor.atom_residues,
+                &self.receptor.residues,
+            ),
+            ligand: interface_residues(
+                &workspace.interface_ligand,
+                &self.ligand.atom_residues,
+                &self.ligand.residues,
+            ),
+        };
+        (energy, Some(interface))
+    }
+
+    fn restraints_satisfaction(
+        &self,
+        translation: &[f64],
+        rotation: &Quaternion,
+        rec_nmodes: &[f64],
+        lig_nmodes: &[f64],
+    ) -> Option<f64> {
+        if self.receptor.active_restraints.is_empty() && self.ligand.active_restraints.is_empty() {
+            return None;
+        }
+        // The evaluation leaves the interface of the pose in the workspace
+        self.energy(translation, rotation, rec_nmodes, lig_nmodes);
+        let workspace = self.workspace.borrow();
+        restraints_satisfaction(
+            &workspace.interface_receptor,
+            &self.receptor.active_restraints,
+            &workspace.interface_ligand,
+            &self.ligand.active_restraints,
+        )
+    }
 
-        score + perc_receptor_restraints * score + perc_ligand_restraints * score - membrane_penalty
+    fn residue_energies(
+        &self,
+        translation: &[f64],
+        rotation: &Quaternion,
+        rec_nmodes: &[f64],
+        lig_nmodes: &[f64],
+    ) -> Option<ResidueEnergies> {
+        let (receptor_coordinates, ligand_coordinates) = pose_coordinates(
+            &self.receptor.coordinates,
+            &self.receptor.nmodes,
+            anm_extents(self.use_anm, self.receptor.num_anm, rec_nmodes),
+            &self.ligand.coordinates,
+            &self.ligand.nmodes,
+            anm_extents(self.use_anm, self.ligand.num_anm, lig_nmodes),
+            translation,
+            rotation,
+        );
+        // Same terms as the full evaluation, the receptor grid is not used
+        let mut energies =
+            ResidueEnergies::new(&self.receptor.residues, &self.ligand.residues, 4.7);
+        let potential = self.potential_table();
+        for (i, ra) in receptor_coordinates.iter().enumerate() {
+            let atoma = self.receptor.atoms[i];
+            for (j, la) in ligand_coordinates.iter().enumerate() {
+                let dist = (ra[0] - la[0]) * (ra[0] - la[0])
+                    + (ra[1] - la[1]) * (ra[1] - la[1])
+                    + (ra[2] - la[2]) * (ra[2] - la[2]);
+                if dist <= DFIRE_CUTOFF * DFIRE_CUTOFF {
+                    let atomb = self.ligand.atoms[j];
+                    let d = dist.sqrt() * 2.0 - 1.0;
+                    let dfire_bin = DIST_TO_BINS[d as usize] - 1;
+                    energies.add(
+                        self.receptor.atom_residues[i],
+                        self.ligand.atom_residues[j],
+                        -potential.value(atoma * 169 * 20 + atomb * 20 + dfire_bin)
+                            * self.receptor.weights[i]
+                            * self.ligand.weights[j]
+                            * 0.0157,
+                    );
+                }
+            }
+        }
+        Some(energies)
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::membrane::add_beads;
+    use crate::pose::Pose;
     use crate::qt::Quaternion;
+    use crate::scoring::{Calibrated, Calibration};
+    use std::env;
 
     // #[test]
     // fn test_read_potentials() {
@@ -396,22 +1395,720 @@ mod tests {
             pdbtbx::open(&ligand_filename, pdbtbx::StrictnessLevel::Strict).unwrap();
 
         let scoring = DFIRE::new(
-            receptor,
-            Vec::new(),
-            Vec::new(),
-            Vec::new(),
-            0,
-            ligand,
-            Vec::new(),
-            Vec::new(),
-            Vec::new(),
-            0,
+            ScoringInput::new(receptor, ligand, ResiduePolicy::Strict),
+            DFIREOptions::default(),
+        )
+        .unwrap();
+
+        let translation = vec![0., 0., 0.];
+        let rotation = Quaternion::default();
+        let energy = scoring.energy(&translation, &rotation, &Vec::new(), &Vec::new());
+        assert_eq!(energy, 16.7540569503498);
+
+        // Residue contributions add up to the energy of the pose
+        let energies = scoring
+            .residue_energies(&translation, &rotation, &Vec::new(), &Vec::new())
+            .unwrap();
+        assert!((energies.total() - energy).abs() < 1e-9);
+
+        // Same energy in the same pass as the interface
+        let (interface_energy, interface) =
+            scoring.energy_with_interface(&translation, &rotation, &Vec::new(), &Vec::new());
+        assert_eq!(interface_energy, energy);
+        // Residues with atoms closer than the interface cutoff
+        let interface = interface.unwrap();
+        assert_eq!(
+            interface.receptor,
+            vec![
+                "A.ASP.933",
+                "A.ALA.934",
+                "A.ALA.937",
+                "A.LYS.938",
+                "A.MET.940",
+                "A.PHE.946",
+                "A.LYS.950"
+            ]
+        );
+        assert_eq!(interface.ligand.len(), 10);
+        assert_eq!(interface.ligand[0], "B.LYS.6");
+        // No contacts far away from the receptor
+        let (_, interface) =
+            scoring.energy_with_interface(&[1000.0, 0.0, 0.0], &rotation, &Vec::new(), &Vec::new());
+        assert_eq!(interface, Some(Interface::default()));
+    }
+
+    #[test]
+    fn test_2oob_clash_filter_calibrated() {
+        let cargo_path = match env::var("CARGO_MANIFEST_DIR") {
+            Ok(val) => val,
+            Err(_) => String::from("."),
+        };
+        let test_path: String = format!("{}/tests/2oob", cargo_path);
+
+        let receptor_filename: String = format!("{}/2oob_receptor.pdb", test_path);
+        let (receptor, _errors) =
+            pdbtbx::open(&receptor_filename, pdbtbx::StrictnessLevel::Strict).unwrap();
+
+        let ligand_filename: String = format!("{}/2oob_ligand.pdb", test_path);
+        let (ligand, _errors) =
+            pdbtbx::open(&ligand_filename, pdbtbx::StrictnessLevel::Strict).unwrap();
+
+        // Any heavy atom pair at interface distance is a clash
+        let scoring = DFIRE::new(
+            ScoringInput {
+                clash_filter: Some(ClashFilter::new(4.0, 0)),
+                ..ScoringInput::new(receptor, ligand, ResiduePolicy::Strict)
+            },
+            DFIREOptions::default(),
+        )
+        .unwrap();
+        let scoring = Calibrated::new(scoring, Calibration::new(0.5, 10.0));
+
+        // The rejection is not calibrated into a finite score
+        let rotation = Quaternion::default();
+        let energy = scoring.energy(&[0.0, 0.0, 0.0], &rotation, &Vec::new(), &Vec::new());
+        assert_eq!(energy, REJECTED_POSE_SCORE);
+        let (energy, _) =
+            scoring.energy_with_interface(&[0.0, 0.0, 0.0], &rotation, &Vec::new(), &Vec::new());
+        assert_eq!(energy, REJECTED_POSE_SCORE);
+        let poses = vec![
+            Pose::new(vec![0.0, 0.0, 0.0], rotation, Vec::new(), Vec::new()),
+            Pose::new(vec![1000.0, 0.0, 0.0], rotation, Vec::new(), Vec::new()),
+        ];
+        let energies = scoring.energies(&poses);
+        assert_eq!(energies[0], REJECTED_POSE_SCORE);
+        assert_ne!(energies[1], REJECTED_POSE_SCORE);
+    }
+
+    #[test]
+    fn test_scoring_weights() {
+        let cargo_path = match env::var("CARGO_MANIFEST_DIR") {
+            Ok(val) => val,
+            Err(_) => String::from("."),
+        };
+        let test_path: String = format!("{}/tests/2oob", cargo_path);
+        let (receptor, _errors) = pdbtbx::open(
+            format!("{}/2oob_receptor.pdb", test_path),
+            pdbtbx::StrictnessLevel::Strict,
+        )
+        .unwrap();
+        let (ligand, _errors) = pdbtbx::open(
+            format!("{}/2oob_ligand.pdb", test_path),
+            pdbtbx::StrictnessLevel::Strict,
+        )
+        .unwrap();
+
+        // Every contribution of the ligand doubled
+        let mut lig_weights: HashMap<String, f64> = HashMap::new();
+        for chain in ligand.chains() {
+            for residue in chain.residues() {
+                lig_weights.insert(crate::restraints::residue_id(chain, residue), 2.0);
+            }
+        }
+        let scoring = DFIRE::new(
+            ScoringInput {
+                lig_weights,
+                ..ScoringInput::new(receptor, ligand, ResiduePolicy::Strict)
+            },
+            DFIREOptions::default(),
+        )
+        .unwrap();
+
+        let translation = vec![0., 0., 0.];
+        let rotation = Quaternion::default();
+        let energy = scoring.energy(&translation, &rotation, &Vec::new(), &Vec::new());
+        assert!((energy - (2.0 * 16.7540569503498 - 4.7)).abs() < 1e-9);
+        let energies = scoring
+            .residue_energies(&translation, &rotation, &Vec::new(), &Vec::new())
+            .unwrap();
+        assert!((energies.total() - energy).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_reduced_model() {
+        let cargo_path = match env::var("CARGO_MANIFEST_DIR") {
+            Ok(val) => val,
+            Err(_) => String::from("."),
+        };
+        let ligand_filename: String = format!("{}/tests/2oob/2oob_ligand.pdb", cargo_path);
+        let (ligand, _errors) =
+            pdbtbx::open(&ligand_filename, pdbtbx::StrictnessLevel::Strict).unwrap();
+        let num_atoms = ligand.atom_count();
+        let nmodes: Nmodes = (0..num_atoms * 3)
+            .map(|i| i as f64)
+            .collect::<Vec<f64>>()
+            .into();
+
+        let hotspots = HashMap::new();
+        let full = DFIREDockingModel::new(
+            &ligand,
+            &[],
+            &[],
+            &hotspots,
+            &HashMap::new(),
+            &nmodes,
+            1,
+            false,
+            ResiduePolicy::Drop,
+        );
+        let reduced = DFIREDockingModel::new(
+            &ligand,
+            &[],
+            &[],
+            &hotspots,
+            &HashMap::new(),
+            &nmodes,
+            1,
+            true,
+            ResiduePolicy::Drop,
+        );
+
+        assert_eq!(full.coordinates.len(), num_atoms);
+        assert!(reduced.coordinates.len() < num_atoms);
+        assert_eq!(reduced.nmodes.len(), reduced.coordinates.len() * 3);
+        // Normal modes follow their atoms: first kept atom is N, its modes start at 0
+        assert_eq!(reduced.nmodes[0..3], [0.0, 1.0, 2.0]);
+        let i_atom = full
+            .coordinates
+            .iter()
+            .position(|c| *c == reduced.coordinates[5])
+            .unwrap();
+        assert!(i_atom > 5);
+        assert_eq!(reduced.nmodes[15], (i_atom * 3) as f64);
+    }
+
+    #[test]
+    fn test_residue_policy() {
+        let cargo_path = match env::var("CARGO_MANIFEST_DIR") {
+            Ok(val) => val,
+            Err(_) => String::from("."),
+        };
+        let ligand_filename: String = format!("{}/tests/2oob/2oob_ligand.pdb", cargo_path);
+        let (mut ligand, _errors) =
+            pdbtbx::open(&ligand_filename, pdbtbx::StrictnessLevel::Strict).unwrap();
+        let num_atoms = ligand.atom_count();
+        let nmodes: Nmodes = (0..num_atoms * 3)
+            .map(|i| i as f64)
+            .collect::<Vec<f64>>()
+            .into();
+        let hotspots = HashMap::new();
+        let reference = DFIREDockingModel::new(
+            &ligand,
+            &[],
+            &[],
+            &hotspots,
+            &HashMap::new(),
+            &nmodes,
+            1,
             false,
+            ResiduePolicy::Strict,
         );
 
+        // First residue MET as selenomethionine
+        let residue = ligand.residues_mut().next().unwrap();
+        let num_met_atoms = residue.atom_count();
+        for conformer in residue.conformers_mut() {
+            conformer.set_name("MSE");
+        }
+        for atom in residue.atoms_mut() {
+            if atom.name() == "SD" {
+                atom.set_name("SE").unwrap();
+            }
+        }
+
+        let dropped = DFIREDockingModel::new(
+            &ligand,
+            &[],
+            &[],
+            &hotspots,
+            &HashMap::new(),
+            &nmodes,
+            1,
+            false,
+            ResiduePolicy::Drop,
+        );
+        assert_eq!(dropped.coordinates.len(), num_atoms - num_met_atoms);
+        assert_eq!(dropped.atoms[..], reference.atoms[num_met_atoms..]);
+        assert_eq!(dropped.nmodes[0], (num_met_atoms * 3) as f64);
+        assert_eq!(dropped.residues[0], "B.GLN.2");
+
+        let mapped = DFIREDockingModel::new(
+            &ligand,
+            &[],
+            &[],
+            &hotspots,
+            &HashMap::new(),
+            &nmodes,
+            1,
+            false,
+            ResiduePolicy::Map,
+        );
+        assert_eq!(mapped.atoms, reference.atoms);
+        assert_eq!(mapped.nmodes[..], reference.nmodes[..]);
+        assert_eq!(mapped.residues[0], "B.MSE.1");
+    }
+
+    #[test]
+    fn test_parse_potentials() {
+        let error = parse_potentials("10.0\n-0.624030868\nNaN?\n").unwrap_err();
+        assert!(error.contains("line 3"));
+        let error = parse_potentials("10.0\n-0.624030868\n").unwrap_err();
+        assert!(error.contains("2 DFIRE parameters found"));
+        let raw_parameters = "0.5\n".repeat(169 * 169 * 20 + 1);
+        assert_eq!(
+            parse_potentials(&raw_parameters).unwrap().len(),
+            169 * 169 * 20
+        );
+    }
+
+    // Receptor and ligand models of 2oob and the DFIRE potential, to check the approximations of
+    // the scoring against the exact sums of the atom pairs
+    fn models_2oob() -> (DFIREDockingModel, DFIREDockingModel, Vec<f64>) {
+        let cargo_path = match env::var("CARGO_MANIFEST_DIR") {
+            Ok(val) => val,
+            Err(_) => String::from("."),
+        };
+        let test_path: String = format!("{}/tests/2oob", cargo_path);
+        let model = |filename: &str| {
+            let (structure, _errors) = pdbtbx::open(
+                format!("{}/{}", test_path, filename),
+                pdbtbx::StrictnessLevel::Strict,
+            )
+            .unwrap();
+            DFIREDockingModel::new(
+                &structure,
+                &[],
+                &[],
+                &HashMap::new(),
+                &HashMap::new(),
+                &Nmodes::default(),
+                0,
+                false,
+                ResiduePolicy::Strict,
+            )
+        };
+        let parameters = std::fs::read_to_string(find_data_file("DCparams").unwrap()).unwrap();
+        (
+            model("2oob_receptor.pdb"),
+            model("2oob_ligand.pdb"),
+            parse_potentials(&parameters).unwrap(),
+        )
+    }
+
+    // Exact DFIRE sum of the receptor for a ligand atom of the given type at a point, before
+    // scaling, with the sum of the absolute values of its terms and their number
+    fn field(
+        potential: &[f64],
+        receptor: &DFIREDockingModel,
+        atom: usize,
+        point: &[f64; 3],
+    ) -> (f64, f64, usize) {
+        let (mut value, mut magnitude, mut num_terms) = (0.0, 0.0, 0);
+        for (i, ra) in receptor.coordinates.iter().enumerate() {
+            let dist = (0..3).map(|k| (ra[k] - point[k]).powi(2)).sum::<f64>();
+            if dist <= DFIRE_CUTOFF * DFIRE_CUTOFF {
+                let dfire_bin = DIST_TO_BINS[(dist.sqrt() * 2.0 - 1.0) as usize] - 1;
+                let term = potential[receptor.atoms[i] * 169 * 20 + atom * 20 + dfire_bin]
+                    * receptor.weights[i];
+                value += term;
+                magnitude += term.abs();
+                num_terms += 1;
+            }
+        }
+        (value, magnitude, num_terms)
+    }
+
+    // Energy interpolated on a grid of the given spacing for the ligand at its original pose,
+    // from the exact field at the corners of the cell of every ligand atom, with the rounding
+    // error of the single precision values stored in the grid and the largest interpolation
+    // error. The interpolated value is a weighted mean of the values at the corners, at most a
+    // spacing away from the atom along every axis, so it is off by at most the largest
+    // difference between those and the field at the atom.
+    fn grid_interpolation(
+        spacing: f64,
+        potential: &[f64],
+        receptor: &DFIREDockingModel,
+        ligand: &DFIREDockingModel,
+    ) -> (f64, f64, f64) {
+        let grid = DFIREGrid::new(potential, receptor, ligand, spacing);
+        let (mut score, mut rounding, mut bound) = (0.0, 0.0, 0.0);
+        for (j, la) in ligand.coordinates.iter().enumerate() {
+            let (exact, _, _) = field(potential, receptor, ligand.atoms[j], la);
+            let (mut value, mut value_rounding, mut error): (f64, f64, f64) = (0.0, 0.0, 0.0);
+            for corner in 0..8 {
+                let mut weight = 1.0;
+                let mut point = [0.0; 3];
+                for k in 0..3 {
+                    let position = (la[k] - grid.origin[k]) / spacing;
+                    let cell = position as usize;
+                    let upper = (corner >> k) & 1;
+                    point[k] = grid.origin[k] + (cell + upper) as f64 * spacing;
+                    let fraction = position - cell as f64;
+                    weight *= if upper == 1 { fraction } else { 1.0 - fraction };
+                }
+                let (corner_value, magnitude, num_terms) =
+                    field(potential, receptor, ligand.atoms[j], &point);
+                value += weight * corner_value;
+                // Products and sums of the terms rounded to single precision
+                value_rounding += weight * (num_terms + 1) as f64 * f32::EPSILON as f64 * magnitude;
+                error = error.max((corner_value - exact).abs());
+            }
+            score += value * ligand.weights[j];
+            rounding += value_rounding * ligand.weights[j];
+            bound += (error + value_rounding) * ligand.weights[j];
+        }
+        (-(score * 0.0157 - 4.7), rounding * 0.0157, bound * 0.0157)
+    }
+
+    #[test]
+    fn test_2oob_grid() {
+        let cargo_path = match env::var("CARGO_MANIFEST_DIR") {
+            Ok(val) => val,
+            Err(_) => String::from("."),
+        };
+        let test_path: String = format!("{}/tests/2oob", cargo_path);
+
+        let receptor_filename: String = format!("{}/2oob_receptor.pdb", test_path);
+        let (receptor, _errors) =
+            pdbtbx::open(&receptor_filename, pdbtbx::StrictnessLevel::Strict).unwrap();
+
+        let ligand_filename: String = format!("{}/2oob_ligand.pdb", test_path);
+        let (ligand, _errors) =
+            pdbtbx::open(&ligand_filename, pdbtbx::StrictnessLevel::Strict).unwrap();
+
+        let scoring = DFIRE::new(
+            ScoringInput::new(receptor, ligand, ResiduePolicy::Strict),
+            DFIREOptions {
+                grid_spacing: Some(2.0),
+                ..DFIREOptions::default()
+            },
+        )
+        .unwrap();
+
         let translation = vec![0., 0., 0.];
         let rotation = Quaternion::default();
         let energy = scoring.energy(&translation, &rotation, &Vec::new(), &Vec::new());
-        assert_eq!(energy, 16.7540569503498);
+        // The field of the receptor interpolated at the ligand atoms, within the interpolation
+        // error of the spacing of the exact pairwise scoring
+        let (receptor, ligand, potential) = models_2oob();
+        let (interpolated, rounding, bound) =
+            grid_interpolation(2.0, &potential, &receptor, &ligand);
+        assert!((energy - interpolated).abs() <= rounding);
+        assert!((energy - 16.7540569503498).abs() <= bound);
+        // Far away from the receptor only the constant term remains
+        let translation = vec![1000., 0., 0.];
+        let energy = scoring.energy(&translation, &rotation, &Vec::new(), &Vec::new());
+        assert_eq!(energy, 4.7);
+    }
+
+    #[test]
+    fn test_2oob_grid_restraints() {
+        let cargo_path = match env::var("CARGO_MANIFEST_DIR") {
+            Ok(val) => val,
+            Err(_) => String::from("."),
+        };
+        let test_path: String = format!("{}/tests/2oob", cargo_path);
+
+        let scoring = |grid_spacing: Option<f64>| {
+            let receptor_filename: String = format!("{}/2oob_receptor.pdb", test_path);
+            let (receptor, _errors) =
+                pdbtbx::open(&receptor_filename, pdbtbx::StrictnessLevel::Strict).unwrap();
+            let ligand_filename: String = format!("{}/2oob_ligand.pdb", test_path);
+            let (ligand, _errors) =
+                pdbtbx::open(&ligand_filename, pdbtbx::StrictnessLevel::Strict).unwrap();
+            DFIRE::new(
+                ScoringInput {
+                    rec_active_restraints: vec![
+                        String::from("A.ASP.933"),
+                        String::from("A.LEU.929"),
+                    ],
+                    ..ScoringInput::new(receptor, ligand, ResiduePolicy::Strict)
+                },
+                DFIREOptions {
+                    grid_spacing,
+                    ..DFIREOptions::default()
+                },
+            )
+            .unwrap()
+        };
+        let full = scoring(None);
+        let grid = scoring(Some(2.0));
+
+        // Restraints are satisfied by the same interface, searched only within its cutoff
+        let translation = vec![0., 0., 0.];
+        let rotation = Quaternion::default();
+        let (energy, interface) =
+            full.energy_with_interface(&translation, &rotation, &Vec::new(), &Vec::new());
+        let (grid_energy, grid_interface) =
+            grid.energy_with_interface(&translation, &rotation, &Vec::new(), &Vec::new());
+        assert_eq!(grid_interface, interface);
+        assert_eq!(
+            grid.restraints_satisfaction(&translation, &rotation, &Vec::new(), &Vec::new()),
+            Some(0.5)
+        );
+        // Half of the receptor restraints are satisfied, the energy is biased by 1.5 times the
+        // unbiased one and so are its rounding and interpolation errors
+        let (receptor, ligand, potential) = models_2oob();
+        let (interpolated, rounding, bound) =
+            grid_interpolation(2.0, &potential, &receptor, &ligand);
+        assert!((grid_energy - 1.5 * interpolated).abs() <= 1.5 * rounding);
+        assert!((energy - 1.5 * 16.7540569503498).abs() < 1e-9);
+        assert!((grid_energy - energy).abs() <= 1.5 * bound);
+    }
+
+    #[test]
+    fn test_2oob_shell() {
+        let cargo_path = match env::var("CARGO_MANIFEST_DIR") {
+            Ok(val) => val,
+            Err(_) => String::from("."),
+        };
+        let test_path: String = format!("{}/tests/2oob", cargo_path);
+
+        let receptor_filename: String = format!("{}/2oob_receptor.pdb", test_path);
+        let (receptor, _errors) =
+            pdbtbx::open(&receptor_filename, pdbtbx::StrictnessLevel::Strict).unwrap();
+
+        let ligand_filename: String = format!("{}/2oob_ligand.pdb", test_path);
+        let (ligand, _errors) =
+            pdbtbx::open(&ligand_filename, pdbtbx::StrictnessLevel::Strict).unwrap();
+
+        let shell_scoring = |radius: Option<f64>| {
+            DFIRE::new(
+                ScoringInput::new(receptor.clone(), ligand.clone(), ResiduePolicy::Strict),
+                DFIREOptions {
+                    shell_radius: radius,
+                    ..DFIREOptions::default()
+                },
+            )
+            .unwrap()
+        };
+
+        let translation = vec![1., 0., 0.];
+        let rotation = Quaternion::default();
+        let (exact, exact_interface) = shell_scoring(None).energy_with_interface(
+            &translation,
+            &rotation,
+            &Vec::new(),
+            &Vec::new(),
+        );
+        // With every pair of residues within the shell the scoring is exact
+        let energy =
+            shell_scoring(Some(100.0)).energy(&translation, &rotation, &Vec::new(), &Vec::new());
+        assert!((energy - exact).abs() < 1e-9);
+        // Distant pairs are scored at the distance of their residue centers, the interface is
+        // made of the nearby ones
+        let (energy, interface) = shell_scoring(Some(10.0)).energy_with_interface(
+            &translation,
+            &rotation,
+            &Vec::new(),
+            &Vec::new(),
+        );
+        let (receptor, mut ligand, potential) = models_2oob();
+        for coordinate in ligand.coordinates.iter_mut() {
+            coordinate[0] += translation[0];
+        }
+        let center = |model: &DFIREDockingModel, atoms: &Range<usize>| {
+            let mut center = [0.0; 3];
+            residue_centers(
+                std::slice::from_ref(atoms),
+                &model.coordinates,
+                std::slice::from_mut(&mut center),
+            );
+            center
+        };
+        let mut sum = 0.0;
+        for receptor_atoms in residue_ranges(&receptor) {
+            let rc = center(&receptor, &receptor_atoms);
+            for ligand_atoms in residue_ranges(&ligand) {
+                let lc = center(&ligand, &ligand_atoms);
+                let distance = (0..3).map(|k| (rc[k] - lc[k]).powi(2)).sum::<f64>().sqrt();
+                for i in receptor_atoms.clone() {
+                    for j in ligand_atoms.clone() {
+                        let ra = receptor.coordinates[i];
+                        let la = ligand.coordinates[j];
+                        let dist = (0..3).map(|k| (ra[k] - la[k]).powi(2)).sum::<f64>();
+                        let dfire_bin = if distance <= 10.0 {
+                            (dist <= DFIRE_CUTOFF * DFIRE_CUTOFF)
+                                .then(|| DIST_TO_BINS[(dist.sqrt() * 2.0 - 1.0) as usize] - 1)
+                        } else {
+                            distance_bin(distance)
+                        };
+                        if let Some(dfire_bin) = dfire_bin {
+                            sum += potential
+                                [receptor.atoms[i] * 169 * 20 + ligand.atoms[j] * 20 + dfire_bin]
+                                * receptor.weights[i]
+                                * ligand.weights[j];
+                        }
+                    }
+                }
+            }
+        }
+        assert!((energy + (sum * 0.0157 - 4.7)).abs() < 1e-9);
+        assert_eq!(interface, exact_interface);
+    }
+
+    #[test]
+    fn test_2oob_quantized() {
+        let potential = vec![10.0, -0.624030868, 0.0, -10.0];
+        let quantized = QuantizedPotential::new(&potential);
+        for (i, value) in potential.iter().enumerate() {
+            assert!((quantized.value(i) - value).abs() <= quantized.scale / 2.0);
+        }
+        assert_eq!(quantized.value(0), 10.0);
+
+        let cargo_path = match env::var("CARGO_MANIFEST_DIR") {
+            Ok(val) => val,
+            Err(_) => String::from("."),
+        };
+        let test_path: String = format!("{}/tests/2oob", cargo_path);
+
+        let receptor_filename: String = format!("{}/2oob_receptor.pdb", test_path);
+        let (receptor, _errors) =
+            pdbtbx::open(&receptor_filename, pdbtbx::StrictnessLevel::Strict).unwrap();
+
+        let ligand_filename: String = format!("{}/2oob_ligand.pdb", test_path);
+        let (ligand, _errors) =
+            pdbtbx::open(&ligand_filename, pdbtbx::StrictnessLevel::Strict).unwrap();
+
+        let scoring = DFIRE::new(
+            ScoringInput::new(receptor, ligand, ResiduePolicy::Strict),
+            DFIREOptions {
+                quantized: true,
+                ..DFIREOptions::default()
+            },
+        )
+        .unwrap();
+
+        let translation = vec![0., 0., 0.];
+        let rotation = Quaternion::default();
+        let energy = scoring.energy(&translation, &rotation, &Vec::new(), &Vec::new());
+        // Every pair within the cutoff is off by at most half a quantization step
+        let (receptor, ligand, potential) = models_2oob();
+        let mut weights = 0.0;
+        for (i, ra) in receptor.coordinates.iter().enumerate() {
+            for (j, la) in ligand.coordinates.iter().enumerate() {
+                let dist = (0..3).map(|k| (ra[k] - la[k]).powi(2)).sum::<f64>();
+                if dist <= DFIRE_CUTOFF * DFIRE_CUTOFF {
+                    weights += (receptor.weights[i] * ligand.weights[j]).abs();
+                }
+            }
+        }
+        let bound = 0.0157 * QuantizedPotential::new(&potential).scale / 2.0 * weights;
+        assert!((energy - 16.7540569503498).abs() <= bound);
+        let energies = scoring
+            .residue_energies(&translation, &rotation, &Vec::new(), &Vec::new())
+            .unwrap();
+        assert!((energies.total() - energy).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_2oob_single_precision() {
+        let cargo_path = match env::var("CARGO_MANIFEST_DIR") {
+            Ok(val) => val,
+            Err(_) => String::from("."),
+        };
+        let test_path: String = format!("{}/tests/2oob", cargo_path);
+
+        let scoring = |options: DFIREOptions| {
+            let receptor_filename: String = format!("{}/2oob_receptor.pdb", test_path);
+            let (receptor, _errors) =
+                pdbtbx::open(&receptor_filename, pdbtbx::StrictnessLevel::Strict).unwrap();
+            let ligand_filename: String = format!("{}/2oob_ligand.pdb", test_path);
+            let (ligand, _errors) =
+                pdbtbx::open(&ligand_filename, pdbtbx::StrictnessLevel::Strict).unwrap();
+            DFIRE::new(
+                ScoringInput::new(receptor, ligand, ResiduePolicy::Strict),
+                options,
+            )
+        };
+        let single_options = DFIREOptions {
+            single_precision: true,
+            ..DFIREOptions::default()
+        };
+        let double = scoring(DFIREOptions::default()).unwrap();
+        let single = scoring(single_options).unwrap();
+
+        // The grid, shell and quantized potential are not scored in single precision
+        for options in [
+            DFIREOptions {
+                grid_spacing: Some(2.0),
+                ..single_options
+            },
+            DFIREOptions {
+                shell_radius: Some(10.0),
+                ..single_options
+            },
+            DFIREOptions {
+                quantized: true,
+                ..single_options
+            },
+        ] {
+            assert!(scoring(options).is_err());
+        }
+
+        // Same energies and interfaces up to the precision of f32
+        let poses = [
+            (vec![0., 0., 0.], Quaternion::default()),
+            (
+                vec![0.5, -1.0, 0.25],
+                Quaternion::from_rotation_vector(&[0.02, 0.0, -0.01]),
+            ),
+        ];
+        for (translation, rotation) in poses.iter() {
+            let (energy, interface) =
+                double.energy_with_interface(translation, rotation, &Vec::new(), &Vec::new());
+            let (single_energy, single_interface) =
+                single.energy_with_interface(translation, rotation, &Vec::new(), &Vec::new());
+            assert!((single_energy - energy).abs() < 1e-4 * energy.abs().max(1.0));
+            assert_eq!(single_interface, interface);
+        }
+        let energy = single.energy(&poses[0].0, &poses[0].1, &Vec::new(), &Vec::new());
+        assert!((energy - 16.7540569503498).abs() < 1e-3);
+    }
+
+    #[test]
+    fn test_membrane_grid() {
+        let cargo_path = match env::var("CARGO_MANIFEST_DIR") {
+            Ok(val) => val,
+            Err(_) => String::from("."),
+        };
+        let test_path: String = format!("{}/tests/2oob", cargo_path);
+
+        let receptor_filename: String = format!("{}/2oob_receptor.pdb", test_path);
+        let (mut receptor, _errors) =
+            pdbtbx::open(&receptor_filename, pdbtbx::StrictnessLevel::Strict).unwrap();
+
+        let ligand_filename: String = format!("{}/2oob_ligand.pdb", test_path);
+        let (ligand, _errors) =
+            pdbtbx::open(&ligand_filename, pdbtbx::StrictnessLevel::Strict).unwrap();
+
+        // Two beads on ligand atoms of the pose and two far away
+        let translation = vec![1., 0., 0.];
+        let rotation = Quaternion::default();
+        let mut beads: Vec<[f64; 3]> = ligand
+            .atoms()
+            .take(2)
+            .map(|atom| [atom.x() + 1.0, atom.y(), atom.z()])
+            .collect();
+        beads.extend([[100.0, 0.0, 0.0], [-100.0, 0.0, 0.0]]);
+        add_beads(&mut receptor, &beads);
+
+        let membrane_scoring = |shell_radius: Option<f64>| {
+            DFIRE::new(
+                ScoringInput::new(receptor.clone(), ligand.clone(), ResiduePolicy::Strict),
+                DFIREOptions {
+                    shell_radius,
+                    ..DFIREOptions::default()
+                },
+            )
+            .unwrap()
+        };
+        // Half of the beads are crossed, found from the interface or the bead grid
+        let exact =
+            membrane_scoring(None).energy(&translation, &rotation, &Vec::new(), &Vec::new());
+        let energy =
+            membrane_scoring(Some(100.0)).energy(&translation, &rotation, &Vec::new(), &Vec::new());
+        assert!(exact < -400.0);
+        assert!((energy - exact).abs() < 1e-9);
     }
 }
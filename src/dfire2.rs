@@ -0,0 +1,668 @@
+use super::constants::{DFIRE2_BIN_WIDTH, DFIRE2_CUTOFF, REJECTED_POSE_SCORE};
+use super::data::find_data_file;
+use super::decomposition::{anm_extents, pose_coordinates, push_residue, ResidueEnergies};
+use super::nmodes::Nmodes;
+use super::policy::{PolicyReport, ResidueAction, ResiduePolicy};
+use super::pose::{apply_nmodes, select_nmodes};
+use super::profile::{add_pose_time, pose_timer};
+use super::qt::Quaternion;
+use super::registry::ScoringInput;
+use super::scoring::{
+    hotspot_bias, in_interface, interface_residues, restraints_satisfaction, CellList, ClashFilter,
+    Interface, RestraintsMetric, Score, Workspace,
+};
+use log::info;
+use pdbtbx::PDB;
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
+use std::error::Error;
+use std::fs;
+
+// DFIRE2 atomic statistical potential, the distance-dependent energy of every pair of heavy atom
+// types of the standard amino acids. The potential is not distributed with lightdock-rust and
+// energies have not been checked against the DFIRE2 of the Python LightDock yet, so there is no
+// golden energy in the unit tests nor in selftest. It is read from the data file dfire2_pair.lib
+// (see data::find_data_file), a text file of lightdock-rust, not the format of the Python data,
+// with a line per pair of atom types: the residue and atom names of both atoms and the energies
+// of the distance bins of DFIRE2_BIN_WIDTH up to DFIRE2_CUTOFF:
+//
+//   ALA N ALA CA -0.0512 0.1224 ...
+//
+// The atom types are the residue and atom names found in the file, in their order, pairs not
+// given have no energy. Atoms without a type, like hydrogens, are left out of the model. The
+// score of a pose is the opposite of its DFIRE2 energy, biased by the restraints and hot-spots.
+
+pub const DFIRE2_DATA_FILE: &str = "dfire2_pair.lib";
+
+pub const DFIRE2_BINS: usize = (DFIRE2_CUTOFF / DFIRE2_BIN_WIDTH) as usize;
+
+const DFIRE2_CUTOFF2: f64 = DFIRE2_CUTOFF * DFIRE2_CUTOFF;
+
+// Distance bin of a pair of atoms from their squared distance, None at or beyond the cutoff
+pub fn distance_bin(distance2: f64) -> Option<usize> {
+    if distance2 >= DFIRE2_CUTOFF2 {
+        return None;
+    }
+    Some(((distance2.sqrt() / DFIRE2_BIN_WIDTH) as usize).min(DFIRE2_BINS - 1))
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct DFIRE2Potential {
+    // Atom type of the residue and atom names, "ALA CA"
+    pub atom_types: HashMap<String, usize>,
+    pub residues: HashSet<String>,
+    // (atom type, atom type, bin) -> 1d
+    pub energies: Vec<f64>,
+}
+
+impl DFIRE2Potential {
+    pub fn parse(contents: &str) -> Result<DFIRE2Potential, Box<dyn Error>> {
+        let mut potential = DFIRE2Potential::default();
+        let mut pairs: Vec<(usize, usize, Vec<f64>)> = Vec::new();
+        for (index, line) in contents.lines().enumerate() {
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            if fields.is_empty() || fields[0].starts_with('#') {
+                continue;
+            }
+            if fields.len() != 4 + DFIRE2_BINS {
+                return Err(format!(
+                    "line {} has {} fields, expected 4 atom names and {} energies",
+                    index + 1,
+                    fields.len(),
+                    DFIRE2_BINS
+                )
+                .into());
+            }
+            let energies: Result<Vec<f64>, _> =
+                fields[4..].iter().map(|v| v.parse::<f64>()).collect();
+            let energies = match energies {
+                Ok(energies) => energies,
+                Err(e) => return Err(format!("wrong energy in line {}: {}", index + 1, e).into()),
+            };
+            let first = potential.add_atom_type(fields[0], fields[1]);
+            let second = potential.add_atom_type(fields[2], fields[3]);
+            pairs.push((first, second, energies));
+        }
+        if pairs.is_empty() {
+            return Err("no atom pairs found".into());
+        }
+        let num_types = potential.atom_types.len();
+        potential.energies = vec![0.0; num_types * num_types * DFIRE2_BINS];
+        // The potential is symmetric, pairs may be given in one order only
+        for (first, second, energies) in pairs.iter() {
+            for (a, b) in [(*first, *second), (*second, *first)] {
+                let start = (a * num_types + b) * DFIRE2_BINS;
+                potential.energies[start..start + DFIRE2_BINS].copy_from_slice(energies);
+            }
+        }
+        Ok(potential)
+    }
+
+    pub fn load() -> Result<DFIRE2Potential, Box<dyn Error>> {
+        let path = find_data_file(DFIRE2_DATA_FILE)?;
+        let potential = DFIRE2Potential::parse(&fs::read_to_string(&path)?)
+            .map_err(|e| format!("{}: {}", path.display(), e))?;
+        info!(
+            "DFIRE2 potential of {} atom types",
+            potential.atom_types.len()
+        );
+        Ok(potential)
+    }
+
+    fn add_atom_type(&mut self, residue_name: &str, atom_name: &str) -> usize {
+        self.residues.insert(residue_name.to_string());
+        let next = self.atom_types.len();
+        *self
+            .atom_types
+            .entry(format!("{} {}", residue_name, atom_name))
+            .or_insert(next)
+    }
+
+    pub fn atom_type(&self, residue_name: &str, atom_name: &str) -> Option<usize> {
+        self.atom_types
+            .get(&format!("{} {}", residue_name, atom_name))
+            .copied()
+    }
+
+    pub fn supports_residue(&self, residue_name: &str) -> bool {
+        self.residues.contains(residue_name)
+    }
+
+    #[inline]
+    pub fn energy(&self, first: usize, second: usize, bin: usize) -> f64 {
+        self.energies[(first * self.atom_types.len() + second) * DFIRE2_BINS + bin]
+    }
+}
+
+pub struct DFIRE2DockingModel {
+    pub atoms: Vec<usize>,
+    pub coordinates: Vec<[f64; 3]>,
+    // Every atom with a type is a heavy atom
+    pub heavy_atoms: Vec<usize>,
+    pub active_restraints: HashMap<String, Vec<usize>>,
+    pub passive_restraints: HashMap<String, Vec<usize>>,
+    pub hotspots: HashMap<String, Vec<usize>>,
+    pub hotspot_weights: HashMap<String, f64>,
+    // Scoring weight of every atom, the one of its residue or 1.0
+    pub weights: Vec<f64>,
+    pub num_anm: usize,
+    pub nmodes: Nmodes,
+    // Residue IDs and the residue of every atom, see decomposition::push_residue
+    pub residues: Vec<String>,
+    pub atom_residues: Vec<usize>,
+}
+
+impl<'a> DFIRE2DockingModel {
+    pub fn new(
+        structure: &'a PDB,
+        active_restraints: &'a [String],
+        passive_restraints: &'a [String],
+        hotspots: &'a HashMap<String, f64>,
+        weights: &'a HashMap<String, f64>,
+        nmodes: &Nmodes,
+        num_anm: usize,
+        potential: &DFIRE2Potential,
+        policy: ResiduePolicy,
+    ) -> DFIRE2DockingModel {
+        let mut model = DFIRE2DockingModel {
+            atoms: Vec::new(),
+            coordinates: Vec::new(),
+            heavy_atoms: Vec::new(),
+            active_restraints: HashMap::new(),
+            passive_restraints: HashMap::new(),
+            hotspots: HashMap::new(),
+            hotspot_weights: hotspots.clone(),
+            weights: Vec::new(),
+            nmodes: nmodes.clone(),
+            residues: Vec::new(),
+            atom_residues: Vec::new(),
+            num_anm,
+        };
+
+        // Indexes in the full structure of the atoms kept in the model
+        let mut kept: Vec<usize> = Vec::new();
+        let mut structure_index: usize = 0;
+        let mut report = PolicyReport::default();
+        for chain in structure.chains() {
+            for residue in chain.residues() {
+                let res_name = match residue.name() {
+                    Some(name) => name,
+                    None => panic!("PDB Parsing Error: Residue name error"),
+                };
+                let mut res_id = format!("{}.{}.{}", chain.id(), res_name, residue.serial_number());
+                if let Some(c) = residue.insertion_code() {
+                    res_id.push_str(c);
+                }
+                let action = policy.action(
+                    "DFIRE2",
+                    &res_id,
+                    res_name,
+                    potential.supports_residue(res_name),
+                    |name| potential.supports_residue(name),
+                    &mut report,
+                );
+                if action == ResidueAction::Drop {
+                    structure_index += residue.atom_count();
+                    continue;
+                }
+
+                for atom in residue.atoms() {
+                    structure_index += 1;
+                    let (scored_name, atom_name) = action.names(res_name, atom.name().trim());
+                    let atom_type = match potential.atom_type(scored_name, atom_name) {
+                        Some(atom_type) => atom_type,
+                        None => {
+                            if action != ResidueAction::Keep {
+                                report.dropped_atoms += 1;
+                            }
+                            continue;
+                        }
+                    };
+                    let atom_index = model.atoms.len();
+                    if active_restraints.contains(&res_id) {
+                        model
+                            .active_restraints
+                            .entry(res_id.to_string())
+                            .or_default()
+                            .push(atom_index);
+                    }
+                    if passive_restraints.contains(&res_id) {
+                        model
+                            .passive_restraints
+                            .entry(res_id.to_string())
+                            .or_default()
+                            .push(atom_index);
+                    }
+                    if hotspots.contains_key(&res_id) {
+                        model
+                            .hotspots
+                            .entry(res_id.to_string())
+                            .or_default()
+                            .push(atom_index);
+                    }
+
+                    model.heavy_atoms.push(atom_index);
+                    model.atoms.push(atom_type);
+                    model.coordinates.push([atom.x(), atom.y(), atom.z()]);
+                    push_residue(&mut model.residues, &mut model.atom_residues, &res_id);
+                    model
+                        .weights
+                        .push(weights.get(&res_id).copied().unwrap_or(1.0));
+                    kept.push(structure_index - 1);
+                }
+            }
+        }
+        report.log("DFIRE2");
+
+        // Normal modes are stored as (num_anm, num_atoms, 3), keep only the selected atoms
+        if kept.len() < structure_index && num_anm > 0 && !nmodes.is_empty() {
+            model.nmodes = select_nmodes(nmodes, num_anm, structure_index, &kept).into();
+        }
+        model
+    }
+}
+
+pub struct DFIRE2 {
+    pub potential: DFIRE2Potential,
+    pub receptor: DFIRE2DockingModel,
+    pub ligand: DFIRE2DockingModel,
+    pub use_anm: bool,
+    pub clash_filter: Option<ClashFilter>,
+    pub restraints_metric: RestraintsMetric,
+    pub workspace: RefCell<Workspace>,
+}
+
+impl<'a> DFIRE2 {
+    pub fn new(input: ScoringInput) -> Result<Box<dyn Score + 'a>, Box<dyn Error>> {
+        let ScoringInput {
+            receptor,
+            rec_active_restraints,
+            rec_passive_restraints,
+            rec_hotspots,
+            rec_weights,
+            rec_nmodes,
+            rec_num_anm,
+            ligand,
+            lig_active_restraints,
+            lig_passive_restraints,
+            lig_hotspots,
+            lig_weights,
+            lig_nmodes,
+            lig_num_anm,
+            use_anm,
+            clash_filter,
+            restraints_metric,
+            policy,
+        } = input;
+        let potential = DFIRE2Potential::load()
+            .map_err(|e| format!("Unable to load DFIRE2 potential: {}", e))?;
+        let receptor = DFIRE2DockingModel::new(
+            &receptor,
+            &rec_active_restraints,
+            &rec_passive_restraints,
+            &rec_hotspots,
+            &rec_weights,
+            &rec_nmodes,
+            rec_num_anm,
+            &potential,
+            policy,
+        );
+        let ligand = DFIRE2DockingModel::new(
+            &ligand,
+            &lig_active_restraints,
+            &lig_passive_restraints,
+            &lig_hotspots,
+            &lig_weights,
+            &lig_nmodes,
+            lig_num_anm,
+            &potential,
+            policy,
+        );
+        Ok(Box::new(DFIRE2::from_models(
+            potential,
+            receptor,
+            ligand,
+            use_anm,
+            clash_filter,
+            restraints_metric,
+        )))
+    }
+
+    pub fn from_models(
+        potential: DFIRE2Potential,
+        receptor: DFIRE2DockingModel,
+        ligand: DFIRE2DockingModel,
+        use_anm: bool,
+        clash_filter: Option<ClashFilter>,
+        restraints_metric: RestraintsMetric,
+    ) -> DFIRE2 {
+        let mut workspace = Workspace::new(&receptor.coordinates, &ligand.coordinates);
+        workspace.cell_list = Some(CellList::new(&receptor.coordinates, DFIRE2_CUTOFF));
+        if let Some(filter) = &clash_filter {
+            workspace.clash_grid = filter.grid(&receptor.coordinates, &receptor.heavy_atoms);
+        }
+        DFIRE2 {
+            potential,
+            receptor,
+            ligand,
+            use_anm,
+            clash_filter,
+            restraints_metric,
+            workspace: RefCell::new(workspace),
+        }
+    }
+
+    // Score of the pose biased by its satisfied restraints and hot-spots
+    fn biased_score(
+        &self,
+        score: f64,
+        receptor_coordinates: &[[f64; 3]],
+        ligand_coordinates: &[[f64; 3]],
+        interface_receptor: &[usize],
+        interface_ligand: &[usize],
+    ) -> f64 {
+        let perc_receptor_restraints: f64 = self.restraints_metric.satisfaction(
+            interface_receptor,
+            &self.receptor.active_restraints,
+            receptor_coordinates,
+            ligand_coordinates,
+        );
+        let perc_ligand_restraints: f64 = self.restraints_metric.satisfaction(
+            interface_ligand,
+            &self.ligand.active_restraints,
+            ligand_coordinates,
+            receptor_coordinates,
+        );
+        let receptor_hotspots: f64 = hotspot_bias(
+            interface_receptor,
+            &self.receptor.hotspots,
+            &self.receptor.hotspot_weights,
+        );
+        let ligand_hotspots: f64 = hotspot_bias(
+            interface_ligand,
+            &self.ligand.hotspots,
+            &self.ligand.hotspot_weights,
+        );
+        score
+            + perc_receptor_restraints * score
+            + perc_ligand_restraints * score
+            + receptor_hotspots * score
+            + ligand_hotspots * score
+    }
+}
+
+impl Score for DFIRE2 {
+    fn energy(
+        &self,
+        translation: &[f64],
+        rotation: &Quaternion,
+        rec_nmodes: &[f64],
+        lig_nmodes: &[f64],
+    ) -> f64 {
+        let rec_extents = anm_extents(self.use_anm, self.receptor.num_anm, rec_nmodes);
+        let lig_extents = anm_extents(self.use_anm, self.ligand.num_anm, lig_nmodes);
+        // Start from the original coordinates in the preallocated buffers
+        let mut workspace = self.workspace.borrow_mut();
+        workspace.reset(
+            &self.receptor.coordinates,
+            &self.ligand.coordinates,
+            !rec_extents.is_empty(),
+        );
+        let Workspace {
+            receptor_coordinates,
+            ligand_coordinates,
+            interface_receptor,
+            interface_ligand,
+            cell_list,
+            clash_grid,
+        } = &mut *workspace;
+
+        let timer = pose_timer();
+        for coordinate in ligand_coordinates.iter_mut() {
+            let rotated_coordinate = rotation.rotate_point(coordinate);
+            for k in 0..3 {
+                coordinate[k] = rotated_coordinate[k] + translation[k];
+            }
+        }
+        apply_nmodes(ligand_coordinates, &self.ligand.nmodes, lig_extents);
+        apply_nmodes(receptor_coordinates, &self.receptor.nmodes, rec_extents);
+        add_pose_time(timer);
+
+        // Discard hopeless poses before the full evaluation
+        if let Some(filter) = &self.clash_filter {
+            // The grid is only hashed again if the receptor moved
+            if !rec_extents.is_empty() {
+                filter.update_grid(clash_grid, receptor_coordinates, &self.receptor.heavy_atoms);
+            }
+            if filter.is_clashing(
+                clash_grid,
+                receptor_coordinates,
+                ligand_coordinates,
+                &self.ligand.heavy_atoms,
+            ) {
+                return REJECTED_POSE_SCORE;
+            }
+        }
+
+        // Ligand atoms around the cells of the receptor, hashed again if its atoms were moved by
+        // the normal modes
+        let cell_list = cell_list.as_mut().expect("DFIRE2 cell list not built");
+        if !rec_extents.is_empty() {
+            cell_list.update(receptor_coordinates);
+        }
+        cell_list.search(ligand_coordinates);
+
+        let mut energy = 0.0;
+        for (i, ra) in receptor_coordinates.iter().enumerate() {
+            let atoma = self.receptor.atoms[i];
+            let weighta = self.receptor.weights[i];
+            let (neighbors, distances) = cell_list.distances(i, ra);
+            for (&j, &distance2) in neighbors.iter().zip(distances.iter()) {
+                if let Some(bin) = distance_bin(distance2) {
+                    energy += self.potential.energy(atoma, self.ligand.atoms[j], bin)
+                        * weighta
+                        * self.ligand.weights[j];
+                }
+                if in_interface(distance2) {
+                    interface_receptor[i] = 1;
+                    interface_ligand[j] = 1;
+                }
+            }
+        }
+
+        self.biased_score(
+            -energy,
+            receptor_coordinates,
+            ligand_coordinates,
+            interface_receptor,
+            interface_ligand,
+        )
+    }
+
+    fn energy_with_interface(
+        &self,
+        translation: &[f64],
+        rotation: &Quaternion,
+        rec_nmodes: &[f64],
+        lig_nmodes: &[f64],
+    ) -> (f64, Option<Interface>) {
+        let energy = self.energy(translation, rotation, rec_nmodes, lig_nmodes);
+        // The evaluation leaves the interface of the pose in the workspace
+        let workspace = self.workspace.borrow();
+        let interface = Interface {
+            receptor: interface_residues(
+                &workspace.interface_receptor,
+                &self.receptor.atom_residues,
+                &self.receptor.residues,
+            ),
+            ligand: interface_residues(
+                &workspace.interface_ligand,
+                &self.ligand.atom_residues,
+                &self.ligand.residues,
+            ),
+        };
+        (energy, Some(interface))
+    }
+
+    fn restraints_satisfaction(
+        &self,
+        translation: &[f64],
+        rotation: &Quaternion,
+        rec_nmodes: &[f64],
+        lig_nmodes: &[f64],
+    ) -> Option<f64> {
+        if self.receptor.active_restraints.is_empty() && self.ligand.active_restraints.is_empty() {
+            return None;
+        }
+        // The evaluation leaves the interface of the pose in the workspace
+        self.energy(translation, rotation, rec_nmodes, lig_nmodes);
+        let workspace = self.workspace.borrow();
+        restraints_satisfaction(
+            &workspace.interface_receptor,
+            &self.receptor.active_restraints,
+            &workspace.interface_ligand,
+            &self.ligand.active_restraints,
+        )
+    }
+
+    fn residue_energies(
+        &self,
+        translation: &[f64],
+        rotation: &Quaternion,
+        rec_nmodes: &[f64],
+        lig_nmodes: &[f64],
+    ) -> Option<ResidueEnergies> {
+        let (receptor_coordinates, ligand_coordinates) = pose_coordinates(
+            &self.receptor.coordinates,
+            &self.receptor.nmodes,
+            anm_extents(self.use_anm, self.receptor.num_anm, rec_nmodes),
+            &self.ligand.coordinates,
+            &self.ligand.nmodes,
+            anm_extents(self.use_anm, self.ligand.num_anm, lig_nmodes),
+            translation,
+            rotation,
+        );
+        let mut energies =
+            ResidueEnergies::new(&self.receptor.residues, &self.ligand.residues, 0.0);
+        for (i, ra) in receptor_coordinates.iter().enumerate() {
+            for (j, la) in ligand_coordinates.iter().enumerate() {
+                let distance2 = (ra[0] - la[0]) * (ra[0] - la[0])
+                    + (ra[1] - la[1]) * (ra[1] - la[1])
+                    + (ra[2] - la[2]) * (ra[2] - la[2]);
+                if let Some(bin) = distance_bin(distance2) {
+                    let energy =
+                        self.potential
+                            .energy(self.receptor.atoms[i], self.ligand.atoms[j], bin);
+                    energies.add(
+                        self.receptor.atom_residues[i],
+                        self.ligand.atom_residues[j],
+                        -energy * self.receptor.weights[i] * self.ligand.weights[j],
+                    );
+                }
+            }
+        }
+        Some(energies)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::structure::parse_structure;
+
+    fn library_line(first: &str, second: &str, energy: impl Fn(usize) -> f64) -> String {
+        let energies: Vec<String> = (0..DFIRE2_BINS).map(|b| energy(b).to_string()).collect();
+        format!("{} {} {}\n", first, second, energies.join(" "))
+    }
+
+    #[test]
+    fn test_dfire2() {
+        let library = format!(
+            "# Test library\n{}{}",
+            library_line("GLY CA", "ALA CB", |b| -((b + 1) as f64)),
+            library_line("ALA N", "GLY CA", |_| -0.5)
+        );
+        let potential = DFIRE2Potential::parse(&library).unwrap();
+        assert_eq!(potential.atom_types.len(), 3);
+        assert!(potential.supports_residue("ALA"));
+        // Pairs are symmetric
+        let (gly_ca, ala_n) = (
+            potential.atom_type("GLY", "CA").unwrap(),
+            potential.atom_type("ALA", "N").unwrap(),
+        );
+        assert_eq!(potential.energy(gly_ca, ala_n, 3), -0.5);
+        assert_eq!(distance_bin(14.99 * 14.99), Some(DFIRE2_BINS - 1));
+        assert_eq!(distance_bin(DFIRE2_CUTOFF2), None);
+        let error = DFIRE2Potential::parse("ALA N ALA CA 1.0").unwrap_err();
+        assert!(error.to_string().contains("line 1 has 5 fields"));
+
+        let receptor = parse_structure(
+            "\
+ATOM      1  CA  GLY A   1       0.000   0.000   0.000  1.00  0.00           C\n\
+ATOM      2  HA2 GLY A   1       0.000   0.000  -1.000  1.00  0.00           H\n",
+            1,
+        )
+        .unwrap()
+        .unwrap();
+        let ligand = parse_structure(
+            "\
+ATOM      1  N   ALA B   1       0.000   0.000   2.200  1.00  0.00           N\n\
+ATOM      2  CA  ALA B   1       0.000   0.000   2.700  1.00  0.00           C\n\
+ATOM      3  CB  ALA B   1       0.000   0.000   3.100  1.00  0.00           C\n",
+            1,
+        )
+        .unwrap()
+        .unwrap();
+        let no_weights: HashMap<String, f64> = HashMap::new();
+        // Atoms without a type are left out, with their normal modes
+        let receptor = DFIRE2DockingModel::new(
+            &receptor,
+            &[],
+            &[],
+            &no_weights,
+            &no_weights,
+            &Nmodes::default(),
+            0,
+            &potential,
+            ResiduePolicy::Strict,
+        );
+        let ligand = DFIRE2DockingModel::new(
+            &ligand,
+            &[String::from("B.ALA.1")],
+            &[],
+            &no_weights,
+            &no_weights,
+            &vec![0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 1.0].into(),
+            1,
+            &potential,
+            ResiduePolicy::Strict,
+        );
+        assert_eq!(receptor.coordinates.len(), 1);
+        assert_eq!(ligand.coordinates.len(), 2);
+        assert_eq!(ligand.nmodes[..], [0.0, 0.0, 0.0, 0.0, 0.0, 1.0]);
+
+        let scoring = DFIRE2::from_models(
+            potential,
+            receptor,
+            ligand,
+            true,
+            None,
+            RestraintsMetric::default(),
+        );
+        let rotation = Quaternion::default();
+        // N in the bin of 2.0 to 2.5 A and CB in the one of 3.0 to 3.5 A, both at the interface
+        let energy = scoring.energy(&[0.0, 0.0, 0.0], &rotation, &[], &[0.0]);
+        assert_eq!(energy, (0.5 + 7.0) * 2.0);
+        assert_eq!(
+            scoring.restraints_satisfaction(&[0.0, 0.0, 0.0], &rotation, &[], &[0.0]),
+            Some(1.0)
+        );
+        // CB moved by the normal mode to the bin of 4.0 to 4.5 A, out of the interface
+        let energy = scoring.energy(&[0.0, 0.0, 0.0], &rotation, &[], &[1.0]);
+        assert_eq!(energy, (0.5 + 9.0) * 2.0);
+        let energies = scoring
+            .residue_energies(&[0.0, 0.0, 0.0], &rotation, &[], &[1.0])
+            .unwrap();
+        assert_eq!(energies.total(), 0.5 + 9.0);
+    }
+}
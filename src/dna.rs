@@ -1,8 +1,22 @@
-use super::constants::{INTERFACE_CUTOFF2, MEMBRANE_PENALTY_SCORE};
+use super::constants::{MEMBRANE_PENALTY_SCORE, REJECTED_POSE_SCORE};
+use super::decomposition::{anm_extents, pose_coordinates, push_residue, ResidueEnergies};
+use super::nmodes::Nmodes;
+use super::parameters::{AtomOverride, AtomParameters, OverrideIndex, ParameterOverrides};
+use super::policy::{nucleic_variants, PolicyReport, ResidueAction, ResiduePolicy};
+use super::pose::select_nmodes;
+use super::precision::{to_single, SinglePartner, SinglePrecision};
+use super::profile::{add_pose_time, pose_timer};
 use super::qt::Quaternion;
-use super::scoring::{membrane_intersection, satisfied_restraints, Score};
+use super::registry::ScoringInput;
+use super::scoring::{
+    hotspot_bias, in_interface, interface_residues, membrane_intersection, restraints_satisfaction,
+    CellList, ClashFilter, Gradient, Interface, RestraintsMetric, Score, TermWeights, Workspace,
+};
+use super::structure::is_hydrogen;
+use log::{info, warn};
 use pdbtbx::PDB;
-use std::collections::HashMap;
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
 
 macro_rules! hashmap {
     ($( $key: expr => $val: expr ),*) => {{
@@ -62,6 +76,13 @@ pub fn atoms_in_residues(residue_name: &str) -> &'static [&'static str] {
 }
 
 lazy_static! {
+    // Residues with built-in AMBER types
+    static ref RESIDUES: HashSet<&'static str> = AMBER_TYPES
+        .keys()
+        .filter_map(|atom_id| atom_id.split_once('-').map(|(residue, _)| residue))
+        .filter(|residue| *residue != "*")
+        .collect();
+
     static ref VDW_CHARGES: HashMap<&'static str, f64> = hashmap![
         "IP" => 0.00277, "HS" => 0.0157, "HP" => 0.0157, "Na" => 0.00277, "N*" => 0.17, "Li" => 0.0183, "HO" => 0.0,
         "Rb" => 0.00017, "HC" => 0.0157, "HA" => 0.015, "O3" => 0.21, "CQ" => 0.086, "C*" => 0.086, "NA" => 0.17,
@@ -235,40 +256,67 @@ lazy_static! {
 pub struct DNADockingModel {
     pub atoms: Vec<usize>,
     pub coordinates: Vec<[f64; 3]>,
+    pub heavy_atoms: Vec<usize>,
     pub membrane: Vec<usize>,
     pub active_restraints: HashMap<String, Vec<usize>>,
     pub passive_restraints: HashMap<String, Vec<usize>>,
+    pub hotspots: HashMap<String, Vec<usize>>,
+    pub hotspot_weights: HashMap<String, f64>,
+    // Scoring weight of every atom, the one of its residue or 1.0
+    pub weights: Vec<f64>,
     pub num_anm: usize,
-    pub nmodes: Vec<f64>,
+    pub nmodes: Nmodes,
+    // Residue IDs and the residue of every atom, see decomposition::push_residue
+    pub residues: Vec<String>,
+    pub atom_residues: Vec<usize>,
     pub vdw_radii: Vec<f64>,
     pub vdw_charges: Vec<f64>,
     pub ele_charges: Vec<f64>,
+    // Atoms with parameters from the overrides
+    pub num_overridden: usize,
 }
 
 impl<'a> DNADockingModel {
-    fn new(
+    pub fn new(
         structure: &'a PDB,
         active_restraints: &'a [String],
         passive_restraints: &'a [String],
-        nmodes: &[f64],
+        hotspots: &'a HashMap<String, f64>,
+        weights: &'a HashMap<String, f64>,
+        nmodes: &Nmodes,
         num_anm: usize,
+        overrides: &[AtomOverride],
+        policy: ResiduePolicy,
     ) -> DNADockingModel {
         let mut model = DNADockingModel {
             atoms: Vec::new(),
             coordinates: Vec::new(),
+            heavy_atoms: Vec::new(),
             membrane: Vec::new(),
             active_restraints: HashMap::new(),
             passive_restraints: HashMap::new(),
-            nmodes: nmodes.to_owned(),
+            hotspots: HashMap::new(),
+            hotspot_weights: hotspots.clone(),
+            weights: Vec::new(),
+            nmodes: nmodes.clone(),
+            residues: Vec::new(),
+            atom_residues: Vec::new(),
             num_anm,
             vdw_radii: Vec::new(),
             vdw_charges: Vec::new(),
             ele_charges: Vec::new(),
+            num_overridden: 0,
         };
 
         let mut atom_index: u64 = 0;
+        let overrides = OverrideIndex::new(overrides);
+        // Indexes in the full structure of the atoms kept in the model
+        let mut kept: Vec<usize> = Vec::new();
+        let mut structure_index: usize = 0;
+        let mut report = PolicyReport::default();
         for chain in structure.chains() {
-            for residue in chain.residues() {
+            let variants = nucleic_variants(chain, |name| RESIDUES.contains(name));
+            for (residue, variant) in chain.residues().zip(variants.iter()) {
                 let res_name = match residue.name() {
                     Some(name) => name,
                     None => panic!("PDB Parsing Error: Residue name error"),
@@ -277,12 +325,44 @@ impl<'a> DNADockingModel {
                 if let Some(c) = residue.insertion_code() {
                     res_id.push_str(c);
                 }
+                // Terminal nucleotides are scored with their terminal parameters
+                let res_name = variant.as_deref().unwrap_or(res_name);
+                // Residues without built-in parameters are supported if given by the overrides
+                let supported = RESIDUES.contains(res_name)
+                    || residue.atoms().any(|atom| {
+                        overrides
+                            .lookup(&res_id, atom.name().trim())
+                            .atom_type
+                            .is_some()
+                    });
+                let action = policy.action(
+                    "DNA",
+                    &res_id,
+                    res_name,
+                    supported,
+                    |name| RESIDUES.contains(name),
+                    &mut report,
+                );
+                if action == ResidueAction::Drop {
+                    structure_index += residue.atom_count();
+                    continue;
+                }
 
                 for atom in residue.atoms() {
+                    structure_index += 1;
+                    let (scored_name, atom_name) = action.names(res_name, atom.name().trim());
+                    if action != ResidueAction::Keep
+                        && !AMBER_TYPES.contains_key(&*format!("{}-{}", scored_name, atom_name))
+                    {
+                        report.dropped_atoms += 1;
+                        continue;
+                    }
                     // Membrane beads MMB.BJ
                     let rec_atom_type = format!("{}{}", res_name, atom.name());
                     if rec_atom_type == "MMBBJ" {
                         model.membrane.push(atom_index as usize);
+                    } else if !is_hydrogen(residue, atom) {
+                        model.heavy_atoms.push(atom_index as usize);
                     }
 
                     if active_restraints.contains(&res_id) {
@@ -311,103 +391,375 @@ impl<'a> DNADockingModel {
                         }
                     }
 
-                    let atom_name = atom.name().trim();
+                    if hotspots.contains_key(&res_id) {
+                        model
+                            .hotspots
+                            .entry(res_id.to_string())
+                            .or_default()
+                            .push(atom_index as usize);
+                    }
+
+                    let res_name = scored_name;
                     let mut atom_id = format!("{}-{}", res_name, atom_name);
 
+                    let parameters = overrides.lookup(&res_id, atom_name);
+                    if parameters != AtomParameters::default() {
+                        model.num_overridden += 1;
+                    }
+
                     // Calculate AMBER type
-                    let amber_type = match AMBER_TYPES.get(&*atom_id) {
-                        Some(&amber) => amber,
-                        _ => {
-                            if atom_name == "H1" || atom_name == "H2" || atom_name == "H3" {
-                                atom_id = format!("{}-H", res_name);
-                                match AMBER_TYPES.get(&*atom_id) {
-                                    Some(&amber) => amber,
-                                    _ => panic!("DNA Error: Atom [{:?}] not supported", atom_id),
+                    let amber_type: &str = match &parameters.atom_type {
+                        Some(amber) => amber,
+                        None => match AMBER_TYPES.get(&*atom_id) {
+                            Some(&amber) => amber,
+                            _ => {
+                                if atom_name == "H1" || atom_name == "H2" || atom_name == "H3" {
+                                    atom_id = format!("{}-H", res_name);
+                                    match AMBER_TYPES.get(&*atom_id) {
+                                        Some(&amber) => amber,
+                                        _ => {
+                                            panic!("DNA Error: Atom [{:?}] not supported", atom_id)
+                                        }
+                                    }
+                                } else {
+                                    panic!("DNA Error: Atom [{:?}] not supported", atom_id);
                                 }
-                            } else {
-                                panic!("DNA Error: Atom [{:?}] not supported", atom_id);
                             }
-                        }
+                        },
                     };
 
                     // Assign electrostatics charge
-                    let ele_charge = match ELE_CHARGES.get(&*atom_id) {
-                        Some(&charge) => charge,
-                        _ => match NT_ELE_CHARGES.get(&*atom_id) {
+                    let ele_charge = match parameters.charge {
+                        Some(charge) => charge,
+                        None => match ELE_CHARGES.get(&*atom_id) {
                             Some(&charge) => charge,
-                            _ => panic!(
-                                "DNA Error: Atom [{:?}] electrostatics charge not found",
-                                atom_id
-                            ),
+                            _ => match NT_ELE_CHARGES.get(&*atom_id) {
+                                Some(&charge) => charge,
+                                _ => panic!(
+                                    "DNA Error: Atom [{:?}] electrostatics charge not found",
+                                    atom_id
+                                ),
+                            },
                         },
                     };
                     model.ele_charges.push(ele_charge);
 
                     // Assign VDW charge and radius
-                    let vdw_charge = match VDW_CHARGES.get(amber_type) {
-                        Some(&charge) => charge,
+                    let vdw_charge = match parameters
+                        .vdw_charge
+                        .or_else(|| VDW_CHARGES.get(amber_type).copied())
+                    {
+                        Some(charge) => charge,
                         _ => panic!("DNA Error: Atom [{:?}] VDW charge not found", atom_id),
                     };
                     model.vdw_charges.push(vdw_charge);
-                    let vdw_radius = match VDW_RADII.get(amber_type) {
-                        Some(&radius) => radius,
+                    let vdw_radius = match parameters
+                        .vdw_radius
+                        .or_else(|| VDW_RADII.get(amber_type).copied())
+                    {
+                        Some(radius) => radius,
                         _ => panic!("DNA Error: Atom [{:?}] VDW radius not found", atom_id),
                     };
                     model.vdw_radii.push(vdw_radius);
 
                     model.coordinates.push([atom.x(), atom.y(), atom.z()]);
+                    push_residue(&mut model.residues, &mut model.atom_residues, &res_id);
+                    model
+                        .weights
+                        .push(weights.get(&res_id).copied().unwrap_or(1.0));
+                    kept.push(structure_index - 1);
                     atom_index += 1;
                 }
             }
         }
+        report.log("DNA");
+
+        // Normal modes are stored as (num_anm, num_atoms, 3), keep only the selected atoms
+        if kept.len() < structure_index && num_anm > 0 && !nmodes.is_empty() {
+            model.nmodes = select_nmodes(nmodes, num_anm, structure_index, &kept).into();
+        }
+        if model.num_overridden > 0 {
+            info!("Atoms with overridden parameters: {}", model.num_overridden);
+        }
         model
     }
 }
 
+// Charges and radii of the atoms of a partner in single precision
+#[derive(Debug, Clone, Default)]
+pub struct DNASingleParameters {
+    pub vdw_radii: Vec<f32>,
+    pub vdw_charges: Vec<f32>,
+    pub ele_charges: Vec<f32>,
+}
+
+impl DNASingleParameters {
+    pub fn new(model: &DNADockingModel) -> DNASingleParameters {
+        DNASingleParameters {
+            vdw_radii: to_single(&model.vdw_radii),
+            vdw_charges: to_single(&model.vdw_charges),
+            ele_charges: to_single(&model.ele_charges),
+        }
+    }
+}
+
 pub struct DNA {
     pub potential: Vec<f64>,
     pub receptor: DNADockingModel,
     pub ligand: DNADockingModel,
     pub use_anm: bool,
+    pub clash_filter: Option<ClashFilter>,
+    pub restraints_metric: RestraintsMetric,
+    pub term_weights: TermWeights,
+    pub workspace: RefCell<Workspace>,
+    // Single precision copies of the partners and their parameters scoring the poses if set, the
+    // ones of the receptor first
+    pub single: Option<SinglePrecision<[DNASingleParameters; 2]>>,
 }
 
 impl<'a> DNA {
     pub fn new(
-        receptor: PDB,
-        rec_active_restraints: Vec<String>,
-        rec_passive_restraints: Vec<String>,
-        rec_nmodes: Vec<f64>,
-        rec_num_anm: usize,
-        ligand: PDB,
-        lig_active_restraints: Vec<String>,
-        lig_passive_restraints: Vec<String>,
-        lig_nmodes: Vec<f64>,
-        lig_num_anm: usize,
-        use_anm: bool,
+        input: ScoringInput,
+        parameters: Option<ParameterOverrides>,
+        single_precision: bool,
+        term_weights: TermWeights,
     ) -> Box<dyn Score + 'a> {
-        let d = DNA {
+        let ScoringInput {
+            receptor,
+            rec_active_restraints,
+            rec_passive_restraints,
+            rec_hotspots,
+            rec_weights,
+            rec_nmodes,
+            rec_num_anm,
+            ligand,
+            lig_active_restraints,
+            lig_passive_restraints,
+            lig_hotspots,
+            lig_weights,
+            lig_nmodes,
+            lig_num_anm,
+            use_anm,
+            clash_filter,
+            restraints_metric,
+            policy,
+        } = input;
+        // Same signature as PYDOCK::new
+        if term_weights.desolvation != 0.0 {
+            warn!("DNA scoring has no desolvation term, its weight is ignored");
+        }
+        let parameters = parameters.unwrap_or_default();
+        let mut d = DNA {
             potential: Vec::with_capacity(168 * 168 * 20),
             receptor: DNADockingModel::new(
                 &receptor,
                 &rec_active_restraints,
                 &rec_passive_restraints,
+                &rec_hotspots,
+                &rec_weights,
                 &rec_nmodes,
                 rec_num_anm,
+                &parameters.receptor,
+                policy,
             ),
             ligand: DNADockingModel::new(
                 &ligand,
                 &lig_active_restraints,
                 &lig_passive_restraints,
+                &lig_hotspots,
+                &lig_weights,
                 &lig_nmodes,
                 lig_num_anm,
+                &parameters.ligand,
+                policy,
             ),
             use_anm,
+            clash_filter,
+            restraints_metric,
+            term_weights,
+            workspace: RefCell::new(Workspace::default()),
+            single: None,
         };
+        d.workspace = RefCell::new(Workspace::new(
+            &d.receptor.coordinates,
+            &d.ligand.coordinates,
+        ));
+        if let Some(filter) = &d.clash_filter {
+            d.workspace.get_mut().clash_grid =
+                filter.grid(&d.receptor.coordinates, &d.receptor.heavy_atoms);
+        }
+        d.workspace.get_mut().cell_list =
+            Some(CellList::new(&d.receptor.coordinates, ELEC_DIST_CUTOFF));
+        if single_precision {
+            d.single = Some(SinglePrecision::new(
+                SinglePartner::new(
+                    &d.receptor.coordinates,
+                    &d.receptor.nmodes,
+                    &d.receptor.weights,
+                ),
+                SinglePartner::new(&d.ligand.coordinates, &d.ligand.nmodes, &d.ligand.weights),
+                [
+                    DNASingleParameters::new(&d.receptor),
+                    DNASingleParameters::new(&d.ligand),
+                ],
+                ELEC_DIST_CUTOFF,
+            ));
+            info!("DNA poses scored in single precision");
+        }
         Box::new(d)
     }
+
+    // Score of the pose biased by its satisfied restraints, hot-spots and membrane intersection
+    fn biased_score(
+        &self,
+        score: f64,
+        receptor_coordinates: &[[f64; 3]],
+        ligand_coordinates: &[[f64; 3]],
+        interface_receptor: &[usize],
+        interface_ligand: &[usize],
+    ) -> f64 {
+        // Bias the scoring depending on satisfied restraints
+        let perc_receptor_restraints: f64 = self.restraints_metric.satisfaction(
+            interface_receptor,
+            &self.receptor.active_restraints,
+            receptor_coordinates,
+            ligand_coordinates,
+        );
+        let perc_ligand_restraints: f64 = self.restraints_metric.satisfaction(
+            interface_ligand,
+            &self.ligand.active_restraints,
+            ligand_coordinates,
+            receptor_coordinates,
+        );
+        // Weighted hot-spot residues at the interface
+        let receptor_hotspots: f64 = hotspot_bias(
+            interface_receptor,
+            &self.receptor.hotspots,
+            &self.receptor.hotspot_weights,
+        );
+        let ligand_hotspots: f64 = hotspot_bias(
+            interface_ligand,
+            &self.ligand.hotspots,
+            &self.ligand.hotspot_weights,
+        );
+        // Take into account membrane intersection
+        let mut membrane_penalty: f64 = 0.0;
+        let intersection = membrane_intersection(interface_receptor, &self.receptor.membrane);
+        if intersection > 0.0 {
+            membrane_penalty = MEMBRANE_PENALTY_SCORE * intersection;
+        }
+
+        score
+            + perc_receptor_restraints * score
+            + perc_ligand_restraints * score
+            + receptor_hotspots * score
+            + ligand_hotspots * score
+            - membrane_penalty
+    }
+
+    // Energy of the pose scored in single precision
+    fn single_energy(
+        &self,
+        single: &SinglePrecision<[DNASingleParameters; 2]>,
+        translation: &[f64],
+        rotation: &Quaternion,
+        rec_nmodes: &[f64],
+        lig_nmodes: &[f64],
+    ) -> f64 {
+        let mut workspace = self.workspace.borrow_mut();
+        let Workspace {
+            receptor_coordinates,
+            ligand_coordinates,
+            interface_receptor,
+            interface_ligand,
+            clash_grid,
+            ..
+        } = &mut *workspace;
+        interface_receptor.fill(0);
+        interface_ligand.fill(0);
+        let mut buffers = single.workspace.borrow_mut();
+
+        let timer = pose_timer();
+        let rec_extents = anm_extents(self.use_anm, self.receptor.num_anm, rec_nmodes);
+        let lig_extents = anm_extents(self.use_anm, self.ligand.num_anm, lig_nmodes);
+        buffers.pose(
+            &single.receptor,
+            &single.ligand,
+            translation,
+            rotation,
+            rec_extents,
+            lig_extents,
+        );
+        buffers.to_double(
+            receptor_coordinates,
+            ligand_coordinates,
+            !rec_extents.is_empty(),
+        );
+        add_pose_time(timer);
+
+        // Discard hopeless poses before the full evaluation
+        if let Some(filter) = &self.clash_filter {
+            // The grid is only hashed again if the receptor moved
+            if !rec_extents.is_empty() {
+                filter.update_grid(clash_grid, receptor_coordinates, &self.receptor.heavy_atoms);
+            }
+            if filter.is_clashing(
+                clash_grid,
+                receptor_coordinates,
+                ligand_coordinates,
+                &self.ligand.heavy_atoms,
+            ) {
+                return REJECTED_POSE_SCORE;
+            }
+        }
+
+        buffers.search(!rec_extents.is_empty());
+        let [receptor, ligand] = &single.parameters;
+        let mut total_elec: f32 = 0.0;
+        let mut total_vdw: f32 = 0.0;
+        for i in 0..buffers.receptor_coordinates.len() {
+            let ra = buffers.receptor_coordinates[i];
+            let weighta = single.receptor.weights[i];
+            let (neighbors, distances) = buffers.cell_list.distances(i, &ra);
+            for (&j, &distance2) in neighbors.iter().zip(distances.iter()) {
+                // Electrostatics energy
+                if distance2 <= ELEC_DIST_CUTOFF2 as f32 {
+                    let atom_elec = (receptor.ele_charges[i] * ligand.ele_charges[j] / distance2)
+                        .clamp(ELEC_MIN_CUTOFF as f32, ELEC_MAX_CUTOFF as f32);
+                    total_elec += atom_elec * weighta * single.ligand.weights[j];
+                }
+
+                // Van der Waals energy
+                if distance2 <= VDW_DIST_CUTOFF2 as f32 {
+                    let vdw_energy = (receptor.vdw_charges[i] * ligand.vdw_charges[j]).sqrt();
+                    let vdw_radius = receptor.vdw_radii[i] + ligand.vdw_radii[j];
+                    let p6 = vdw_radius.powi(6) / distance2.powi(3);
+                    let k = (vdw_energy * (p6 * p6 - 2.0 * p6)).min(VDW_CUTOFF as f32);
+                    total_vdw += k * weighta * single.ligand.weights[j];
+                }
+
+                // Interface calculation
+                if in_interface(distance2 as f64) {
+                    interface_receptor[i] = 1;
+                    interface_ligand[j] = 1;
+                }
+            }
+        }
+        let total_elec = total_elec as f64 * FACTOR / EPSILON;
+        let score = -(self.term_weights.electrostatics * total_elec
+            + self.term_weights.vdw * total_vdw as f64);
+        self.biased_score(
+            score,
+            receptor_coordinates,
+            ligand_coordinates,
+            interface_receptor,
+            interface_ligand,
+        )
+    }
 }
 
 impl Score for DNA {
+    #[allow(clippy::manual_clamp, clippy::neg_multiply)]
     fn energy(
         &self,
         translation: &[f64],
@@ -415,17 +767,32 @@ impl Score for DNA {
         rec_nmodes: &[f64],
         lig_nmodes: &[f64],
     ) -> f64 {
-        // Clone receptor coordinates
-        let mut receptor_coordinates: Vec<[f64; 3]> = self.receptor.coordinates.clone();
+        if let Some(single) = &self.single {
+            return self.single_energy(single, translation, rotation, rec_nmodes, lig_nmodes);
+        }
+        // Start from the original coordinates in the preallocated buffers
+        let mut workspace = self.workspace.borrow_mut();
+        workspace.reset(
+            &self.receptor.coordinates,
+            &self.ligand.coordinates,
+            self.use_anm && self.receptor.num_anm > 0,
+        );
+        let Workspace {
+            receptor_coordinates,
+            ligand_coordinates,
+            interface_receptor,
+            interface_ligand,
+            cell_list,
+            clash_grid,
+        } = &mut *workspace;
         let rec_num_atoms = receptor_coordinates.len();
-        // Clone ligand coordinates
-        let mut ligand_coordinates: Vec<[f64; 3]> = self.ligand.coordinates.clone();
         let lig_num_atoms = ligand_coordinates.len();
 
+        let timer = pose_timer();
         // Get the proper ligand pose
         for (i_atom, coordinate) in ligand_coordinates.iter_mut().enumerate() {
             // First rotate
-            let rotated_coordinate = rotation.rotate(coordinate.to_vec());
+            let rotated_coordinate = rotation.rotate_point(coordinate);
             // Then tranlate
             coordinate[0] = rotated_coordinate[0] + translation[0];
             coordinate[1] = rotated_coordinate[1] + translation[1];
@@ -462,21 +829,39 @@ impl Score for DNA {
                 }
             }
         }
-        // Calculate scoring and interface
-        let mut interface_receptor: Vec<usize> = vec![0; receptor_coordinates.len()];
-        let mut interface_ligand: Vec<usize> = vec![0; ligand_coordinates.len()];
+        add_pose_time(timer);
 
+        // Discard hopeless poses before the full evaluation
+        if let Some(filter) = &self.clash_filter {
+            // The grid is only hashed again if the receptor moved
+            if self.use_anm && self.receptor.num_anm > 0 {
+                filter.update_grid(clash_grid, receptor_coordinates, &self.receptor.heavy_atoms);
+            }
+            if filter.is_clashing(
+                clash_grid,
+                receptor_coordinates,
+                ligand_coordinates,
+                &self.ligand.heavy_atoms,
+            ) {
+                return REJECTED_POSE_SCORE;
+            }
+        }
+
+        // Ligand atoms around the cells of the receptor, hashed again if its atoms were moved by
+        // the normal modes
+        let cell_list = cell_list.as_mut().expect("DNA cell list not built");
+        if self.use_anm && self.receptor.num_anm > 0 {
+            cell_list.update(receptor_coordinates);
+        }
+        cell_list.search(ligand_coordinates);
+
+        // Calculate scoring and interface
         let mut total_elec = 0.0;
         let mut total_vdw = 0.0;
         for (i, ra) in receptor_coordinates.iter().enumerate() {
-            let x1 = ra[0];
-            let y1 = ra[1];
-            let z1 = ra[2];
-            for (j, la) in ligand_coordinates.iter().enumerate() {
-                let distance2 = (x1 - la[0]) * (x1 - la[0])
-                    + (y1 - la[1]) * (y1 - la[1])
-                    + (z1 - la[2]) * (z1 - la[2]);
-
+            let weighta = self.receptor.weights[i];
+            let (neighbors, distances) = cell_list.distances(i, ra);
+            for (&j, &distance2) in neighbors.iter().zip(distances.iter()) {
                 // Electrostatics energy
                 if distance2 <= ELEC_DIST_CUTOFF2 {
                     let mut atom_elec =
@@ -487,7 +872,7 @@ impl Score for DNA {
                     if atom_elec < ELEC_MIN_CUTOFF {
                         atom_elec = ELEC_MIN_CUTOFF;
                     }
-                    total_elec += atom_elec;
+                    total_elec += atom_elec * weighta * self.ligand.weights[j];
                 }
 
                 // Van der Waals energy
@@ -500,32 +885,268 @@ impl Score for DNA {
                     if k > VDW_CUTOFF {
                         k = VDW_CUTOFF;
                     }
-                    total_vdw += k;
+                    total_vdw += k * weighta * self.ligand.weights[j];
                 }
 
                 // Interface calculation
-                if distance2 <= INTERFACE_CUTOFF2 {
+                if in_interface(distance2) {
                     interface_receptor[i] = 1;
                     interface_ligand[j] = 1;
                 }
             }
         }
         total_elec = total_elec * FACTOR / EPSILON;
-        let score = (total_elec + total_vdw) * -1.0;
+        let score = (self.term_weights.electrostatics * total_elec
+            + self.term_weights.vdw * total_vdw)
+            * -1.0;
 
-        // Bias the scoring depending on satisfied restraints
-        let perc_receptor_restraints: f64 =
-            satisfied_restraints(&interface_receptor, &self.receptor.active_restraints);
-        let perc_ligand_restraints: f64 =
-            satisfied_restraints(&interface_ligand, &self.ligand.active_restraints);
-        // Take into account membrane intersection
-        let mut membrane_penalty: f64 = 0.0;
-        let intersection = membrane_intersection(&interface_receptor, &self.receptor.membrane);
-        if intersection > 0.0 {
-            membrane_penalty = MEMBRANE_PENALTY_SCORE * intersection;
+        self.biased_score(
+            score,
+            receptor_coordinates,
+            ligand_coordinates,
+            interface_receptor,
+            interface_ligand,
+        )
+    }
+
+    fn energy_with_interface(
+        &self,
+        translation: &[f64],
+        rotation: &Quaternion,
+        rec_nmodes: &[f64],
+        lig_nmodes: &[f64],
+    ) -> (f64, Option<Interface>) {
+        let energy = self.energy(translation, rotation, rec_nmodes, lig_nmodes);
+        // The evaluation leaves the interface of the pose in the workspace
+        let workspace = self.workspace.borrow();
+        let interface = Interface {
+            receptor: interface_residues(
+                &workspace.interface_receptor,
+                &self.receptor.atom_residues,
+                &self.receptor.residues,
+            ),
+            ligand: interface_residues(
+                &workspace.interface_ligand,
+                &self.ligand.atom_residues,
+                &self.ligand.residues,
+            ),
+        };
+        (energy, Some(interface))
+    }
+
+    fn restraints_satisfaction(
+        &self,
+        translation: &[f64],
+        rotation: &Quaternion,
+        rec_nmodes: &[f64],
+        lig_nmodes: &[f64],
+    ) -> Option<f64> {
+        if self.receptor.active_restraints.is_empty() && self.ligand.active_restraints.is_empty() {
+            return None;
+        }
+        // The evaluation leaves the interface of the pose in the workspace
+        self.energy(translation, rotation, rec_nmodes, lig_nmodes);
+        let workspace = self.workspace.borrow();
+        restraints_satisfaction(
+            &workspace.interface_receptor,
+            &self.receptor.active_restraints,
+            &workspace.interface_ligand,
+            &self.ligand.active_restraints,
+        )
+    }
+
+    // Derivatives of the electrostatics and van der Waals terms, zero where they are clamped. The
+    // restraints, hot-spots and membrane bias of the pose are taken as constant.
+    fn gradient(
+        &self,
+        translation: &[f64],
+        rotation: &Quaternion,
+        rec_nmodes: &[f64],
+        lig_nmodes: &[f64],
+    ) -> Option<Gradient> {
+        let rec_extents = anm_extents(self.use_anm, self.receptor.num_anm, rec_nmodes);
+        let lig_extents = anm_extents(self.use_anm, self.ligand.num_anm, lig_nmodes);
+        let (receptor_coordinates, ligand_coordinates) = pose_coordinates(
+            &self.receptor.coordinates,
+            &self.receptor.nmodes,
+            rec_extents,
+            &self.ligand.coordinates,
+            &self.ligand.nmodes,
+            lig_extents,
+            translation,
+            rotation,
+        );
+        if let Some(filter) = &self.clash_filter {
+            let workspace = self.workspace.borrow();
+            let moved;
+            let grid = if rec_extents.is_empty() {
+                &workspace.clash_grid
+            } else {
+                moved = filter.grid(&receptor_coordinates, &self.receptor.heavy_atoms);
+                &moved
+            };
+            if filter.is_clashing(
+                grid,
+                &receptor_coordinates,
+                &ligand_coordinates,
+                &self.ligand.heavy_atoms,
+            ) {
+                return None;
+            }
+        }
+
+        // Derivatives of the score over the coordinates of every atom
+        let mut receptor_derivatives = vec![[0.0; 3]; receptor_coordinates.len()];
+        let mut ligand_derivatives = vec![[0.0; 3]; ligand_coordinates.len()];
+        let mut interface_receptor = vec![0; receptor_coordinates.len()];
+        let mut interface_ligand = vec![0; ligand_coordinates.len()];
+        for (i, ra) in receptor_coordinates.iter().enumerate() {
+            for (j, la) in ligand_coordinates.iter().enumerate() {
+                let delta = [la[0] - ra[0], la[1] - ra[1], la[2] - ra[2]];
+                let distance2 = delta[0] * delta[0] + delta[1] * delta[1] + delta[2] * delta[2];
+                // Derivative of the energy over the squared distance
+                let mut derivative = 0.0;
+                if distance2 <= ELEC_DIST_CUTOFF2 {
+                    let charges = self.receptor.ele_charges[i] * self.ligand.ele_charges[j];
+                    let atom_elec = charges / distance2;
+                    if atom_elec > ELEC_MIN_CUTOFF && atom_elec < ELEC_MAX_CUTOFF {
+                        derivative -= charges / (distance2 * distance2) * FACTOR / EPSILON
+                            * self.term_weights.electrostatics;
+                    }
+                }
+                if distance2 <= VDW_DIST_CUTOFF2 {
+                    let vdw_energy =
+                        (self.receptor.vdw_charges[i] * self.ligand.vdw_charges[j]).sqrt();
+                    let vdw_radius = self.receptor.vdw_radii[i] + self.ligand.vdw_radii[j];
+                    let p6 = vdw_radius.powi(6) / distance2.powi(3);
+                    if vdw_energy * (p6 * p6 - 2.0 * p6) < VDW_CUTOFF {
+                        derivative += vdw_energy
+                            * (2.0 * p6 - 2.0)
+                            * (-3.0 * p6 / distance2)
+                            * self.term_weights.vdw;
+                    }
+                }
+                if derivative != 0.0 {
+                    // The score is the opposite of the energy
+                    let scale =
+                        -2.0 * derivative * self.receptor.weights[i] * self.ligand.weights[j];
+                    for k in 0..3 {
+                        ligand_derivatives[j][k] += scale * delta[k];
+                        receptor_derivatives[i][k] -= scale * delta[k];
+                    }
+                }
+                if in_interface(distance2) {
+                    interface_receptor[i] = 1;
+                    interface_ligand[j] = 1;
+                }
+            }
         }
+        let bias = self.biased_score(
+            1.0,
+            &receptor_coordinates,
+            &ligand_coordinates,
+            &interface_receptor,
+            &interface_ligand,
+        ) - self.biased_score(
+            0.0,
+            &receptor_coordinates,
+            &ligand_coordinates,
+            &interface_receptor,
+            &interface_ligand,
+        );
 
-        score + perc_receptor_restraints * score + perc_ligand_restraints * score - membrane_penalty
+        let mut gradient = Gradient {
+            translation: [0.0; 3],
+            rotation: [0.0; 3],
+            rec_nmodes: vec![0.0; rec_nmodes.len()],
+            lig_nmodes: vec![0.0; lig_nmodes.len()],
+        };
+        let lig_num_atoms = ligand_coordinates.len();
+        for (j, derivatives) in ligand_derivatives.iter().enumerate() {
+            // A rotation on top of the pose one turns the rotated atom around the origin
+            let rotated = rotation.rotate_point(&self.ligand.coordinates[j]);
+            for k in 0..3 {
+                gradient.translation[k] += derivatives[k];
+                gradient.rotation[k] += rotated[(k + 1) % 3] * derivatives[(k + 2) % 3]
+                    - rotated[(k + 2) % 3] * derivatives[(k + 1) % 3];
+            }
+            for (i_nm, value) in gradient.lig_nmodes[..lig_extents.len()]
+                .iter_mut()
+                .enumerate()
+            {
+                for k in 0..3 {
+                    *value +=
+                        self.ligand.nmodes[i_nm * lig_num_atoms * 3 + j * 3 + k] * derivatives[k];
+                }
+            }
+        }
+        let rec_num_atoms = receptor_coordinates.len();
+        for (i, derivatives) in receptor_derivatives.iter().enumerate() {
+            for (i_nm, value) in gradient.rec_nmodes[..rec_extents.len()]
+                .iter_mut()
+                .enumerate()
+            {
+                for k in 0..3 {
+                    *value +=
+                        self.receptor.nmodes[i_nm * rec_num_atoms * 3 + i * 3 + k] * derivatives[k];
+                }
+            }
+        }
+        gradient.scale(bias);
+        Some(gradient)
+    }
+
+    fn residue_energies(
+        &self,
+        translation: &[f64],
+        rotation: &Quaternion,
+        rec_nmodes: &[f64],
+        lig_nmodes: &[f64],
+    ) -> Option<ResidueEnergies> {
+        let (receptor_coordinates, ligand_coordinates) = pose_coordinates(
+            &self.receptor.coordinates,
+            &self.receptor.nmodes,
+            anm_extents(self.use_anm, self.receptor.num_anm, rec_nmodes),
+            &self.ligand.coordinates,
+            &self.ligand.nmodes,
+            anm_extents(self.use_anm, self.ligand.num_anm, lig_nmodes),
+            translation,
+            rotation,
+        );
+        let mut energies =
+            ResidueEnergies::new(&self.receptor.residues, &self.ligand.residues, 0.0);
+        for (i, ra) in receptor_coordinates.iter().enumerate() {
+            for (j, la) in ligand_coordinates.iter().enumerate() {
+                let distance2 = (ra[0] - la[0]) * (ra[0] - la[0])
+                    + (ra[1] - la[1]) * (ra[1] - la[1])
+                    + (ra[2] - la[2]) * (ra[2] - la[2]);
+                let mut energy = 0.0;
+                if distance2 <= ELEC_DIST_CUTOFF2 {
+                    energy += (self.receptor.ele_charges[i] * self.ligand.ele_charges[j]
+                        / distance2)
+                        .clamp(ELEC_MIN_CUTOFF, ELEC_MAX_CUTOFF)
+                        * FACTOR
+                        / EPSILON
+                        * self.term_weights.electrostatics;
+                }
+                if distance2 <= VDW_DIST_CUTOFF2 {
+                    let vdw_energy =
+                        (self.receptor.vdw_charges[i] * self.ligand.vdw_charges[j]).sqrt();
+                    let vdw_radius = self.receptor.vdw_radii[i] + self.ligand.vdw_radii[j];
+                    let p6 = vdw_radius.powi(6) / distance2.powi(3);
+                    energy +=
+                        (vdw_energy * (p6 * p6 - 2.0 * p6)).min(VDW_CUTOFF) * self.term_weights.vdw;
+                }
+                if energy != 0.0 {
+                    energies.add(
+                        self.receptor.atom_residues[i],
+                        self.ligand.atom_residues[j],
+                        -energy * self.receptor.weights[i] * self.ligand.weights[j],
+                    );
+                }
+            }
+        }
+        Some(energies)
     }
 }
 
@@ -552,17 +1173,10 @@ mod tests {
             pdbtbx::open(&ligand_filename, pdbtbx::StrictnessLevel::Strict).unwrap();
 
         let scoring = DNA::new(
-            receptor,
-            Vec::new(),
-            Vec::new(),
-            Vec::new(),
-            0,
-            ligand,
-            Vec::new(),
-            Vec::new(),
-            Vec::new(),
-            0,
+            ScoringInput::new(receptor, ligand, ResiduePolicy::Strict),
+            None,
             false,
+            TermWeights::default(),
         );
 
         let translation = vec![0., 0., 0.];
@@ -570,4 +1184,64 @@ mod tests {
         let energy = scoring.energy(&translation, &rotation, &Vec::new(), &Vec::new());
         assert_eq!(energy, -364.88126358158974);
     }
+    #[test]
+    fn test_1azp_single_precision() {
+        let cargo_path = match env::var("CARGO_MANIFEST_DIR") {
+            Ok(val) => val,
+            Err(_) => String::from("."),
+        };
+        let test_path: String = format!("{}/tests/1azp", cargo_path);
+
+        let receptor_filename: String = format!("{}/1azp_receptor.pdb", test_path);
+        let (receptor, _errors) =
+            pdbtbx::open(&receptor_filename, pdbtbx::StrictnessLevel::Strict).unwrap();
+
+        let ligand_filename: String = format!("{}/1azp_ligand.pdb", test_path);
+        let (ligand, _errors) =
+            pdbtbx::open(&ligand_filename, pdbtbx::StrictnessLevel::Strict).unwrap();
+
+        let scoring = DNA::new(
+            ScoringInput::new(receptor, ligand, ResiduePolicy::Strict),
+            None,
+            true,
+            TermWeights::default(),
+        );
+
+        // Same energy as in double precision up to the precision of f32
+        let translation = vec![0., 0., 0.];
+        let rotation = Quaternion::default();
+        let energy = scoring.energy(&translation, &rotation, &Vec::new(), &Vec::new());
+        assert!((energy - -364.88126358158974).abs() < 0.05);
+    }
+
+    #[test]
+    fn test_1azp_gradient() {
+        let test_path = format!("{}/tests/1azp", env!("CARGO_MANIFEST_DIR"));
+        let (receptor, _errors) = pdbtbx::open(
+            format!("{}/1azp_receptor.pdb", test_path),
+            pdbtbx::StrictnessLevel::Strict,
+        )
+        .unwrap();
+        let (ligand, _errors) = pdbtbx::open(
+            format!("{}/1azp_ligand.pdb", test_path),
+            pdbtbx::StrictnessLevel::Strict,
+        )
+        .unwrap();
+        let scoring = DNA::new(
+            ScoringInput::new(receptor, ligand, ResiduePolicy::Strict),
+            None,
+            false,
+            TermWeights::default(),
+        );
+
+        // Same derivatives as the finite differences, away from the crystal pose. The step is small
+        // enough not to cross the distance cutoffs, where the energy jumps.
+        let translation = [1.5, -1.0, 2.0];
+        let rotation = Quaternion::from_rotation_vector(&[0.05, -0.02, 0.03]);
+        let analytical = scoring.gradient(&translation, &rotation, &[], &[]).unwrap();
+        let numerical = scoring.numerical_gradient(&translation, &rotation, &[], &[], 1e-7);
+        for (a, n) in analytical.to_vec().iter().zip(numerical.to_vec().iter()) {
+            assert!((a - n).abs() < 1e-4 * n.abs().max(1.0), "{} != {}", a, n);
+        }
+    }
 }
@@ -0,0 +1,85 @@
+use std::io::{Error, Write};
+
+// CHARMM/NAMD DCD trajectories: little-endian Fortran unformatted records, single precision
+// coordinates and no unit cell.
+
+fn write_record<W: Write>(output: &mut W, record: &[u8]) -> Result<(), Error> {
+    let length = (record.len() as i32).to_le_bytes();
+    output.write_all(&length)?;
+    output.write_all(record)?;
+    output.write_all(&length)
+}
+
+pub fn write_dcd<W: Write>(
+    output: &mut W,
+    frames: &[Vec<[f64; 3]>],
+    title: &str,
+) -> Result<(), Error> {
+    let num_atoms = frames.first().map_or(0, |frame| frame.len());
+    if frames.iter().any(|frame| frame.len() != num_atoms) {
+        return Err(Error::other(
+            "all DCD frames must have the same number of atoms",
+        ));
+    }
+
+    // Header: number of frames, first step, saving frequency, number of steps, time step
+    // and CHARMM version
+    let mut header: Vec<u8> = b"CORD".to_vec();
+    let mut control = [0_i32; 20];
+    control[0] = frames.len() as i32;
+    control[2] = 1;
+    control[3] = frames.len() as i32;
+    control[9] = 1.0_f32.to_bits() as i32;
+    control[19] = 24;
+    for value in control.iter() {
+        header.extend_from_slice(&value.to_le_bytes());
+    }
+    write_record(output, &header)?;
+
+    let mut title_record: Vec<u8> = 1_i32.to_le_bytes().to_vec();
+    let mut title_line = format!("{:<80}", title).into_bytes();
+    title_line.truncate(80);
+    title_record.extend_from_slice(&title_line);
+    write_record(output, &title_record)?;
+
+    write_record(output, &(num_atoms as i32).to_le_bytes())?;
+
+    for frame in frames.iter() {
+        for axis in 0..3 {
+            let mut record: Vec<u8> = Vec::with_capacity(num_atoms * 4);
+            for coordinate in frame.iter() {
+                record.extend_from_slice(&(coordinate[axis] as f32).to_le_bytes());
+            }
+            write_record(output, &record)?;
+        }
+    }
+    output.flush()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_write_dcd() {
+        let frames = vec![
+            vec![[1.0, 2.0, 3.0], [4.0, 5.0, 6.0]],
+            vec![[1.5, 2.5, 3.5], [4.5, 5.5, 6.5]],
+        ];
+        let mut output: Vec<u8> = Vec::new();
+        write_dcd(&mut output, &frames, "glowworm 0").unwrap();
+
+        // Header, title and number of atoms records plus 3 records per frame
+        let expected = (84 + 8) + (84 + 8) + (4 + 8) + 2 * 3 * (2 * 4 + 8);
+        assert_eq!(output.len(), expected);
+        assert_eq!(&output[4..8], b"CORD");
+        assert_eq!(i32::from_le_bytes(output[8..12].try_into().unwrap()), 2);
+        // First X coordinate of the second frame
+        let offset = (84 + 8) + (84 + 8) + (4 + 8) + 3 * (2 * 4 + 8) + 4;
+        let x = f32::from_le_bytes(output[offset..offset + 4].try_into().unwrap());
+        assert_eq!(x, 1.5);
+
+        let wrong = vec![vec![[0.0, 0.0, 0.0]], vec![]];
+        assert!(write_dcd(&mut Vec::new(), &wrong, "").is_err());
+    }
+}
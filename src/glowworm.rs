@@ -1,6 +1,13 @@
-use super::constants::{DEFAULT_NMODES_STEP, DEFAULT_ROTATION_STEP, DEFAULT_TRANSLATION_STEP};
+use super::constants::{
+    DEFAULT_INITIAL_LUCIFERIN, DEFAULT_INITIAL_VISION_RANGE, DEFAULT_NMODES_STEP,
+    DEFAULT_ROTATION_STEP, DEFAULT_TRANSLATION_STEP, STAGNATION_TOLERANCE,
+};
 use super::qt::Quaternion;
 use super::scoring::Score;
+use super::symmetry::cn_pose;
+use super::ternary::LigandPose;
+use rand::prelude::StdRng;
+use rand::Rng;
 use std::f64;
 
 pub struct Glowworm<'a> {
@@ -9,6 +16,8 @@ pub struct Glowworm<'a> {
     pub rotation: Quaternion,
     pub rec_nmodes: Vec<f64>,
     pub lig_nmodes: Vec<f64>,
+    pub rec_id: usize,
+    pub lig_id: usize,
     pub scoring_function: &'a Box<dyn Score>,
     pub rho: f64,
     pub gamma: f64,
@@ -23,6 +32,14 @@ pub struct Glowworm<'a> {
     pub moved: bool,
     pub step: u32,
     pub use_anm: bool,
+    // Neighbor probabilities from a softmax of the luciferin differences if set
+    pub softmax_temperature: Option<f64>,
+    // Translation interpolation step
+    pub translation_step: f64,
+    // Pose of the second ligand when docking two ligands
+    pub second_ligand: Option<LigandPose>,
+    // Consecutive steps without moving nor improving its luciferin
+    pub stagnation: u32,
 }
 
 impl<'a> Glowworm<'a> {
@@ -41,12 +58,15 @@ impl<'a> Glowworm<'a> {
             rotation,
             rec_nmodes,
             lig_nmodes,
+            // Receptor and ligand conformers, only one of each is supported for now
+            rec_id: 0,
+            lig_id: 0,
             scoring_function,
             rho: 0.5,
             gamma: 0.4,
             beta: 0.08,
-            luciferin: 5.0,
-            vision_range: 0.2,
+            luciferin: DEFAULT_INITIAL_LUCIFERIN,
+            vision_range: DEFAULT_INITIAL_VISION_RANGE,
             max_vision_range: 5.0,
             max_neighbors: 5,
             neighbors: Vec::new(),
@@ -55,30 +75,48 @@ impl<'a> Glowworm<'a> {
             moved: false,
             step: 0,
             use_anm,
+            softmax_temperature: None,
+            translation_step: DEFAULT_TRANSLATION_STEP,
+            second_ligand: None,
+            stagnation: 0,
         }
     }
 
     pub fn compute_luciferin(&mut self) {
         if self.moved || self.step == 0 {
-            self.scoring = self.scoring_function.energy(
-                &self.translation,
-                &self.rotation,
-                &self.rec_nmodes,
-                &self.lig_nmodes,
-            );
+            self.scoring = match &self.second_ligand {
+                Some(second) => self
+                    .scoring_function
+                    .second_ligand_energy(
+                        &self.translation,
+                        &self.rotation,
+                        &self.rec_nmodes,
+                        &self.lig_nmodes,
+                        second,
+                    )
+                    .expect("Scoring function does not support a second ligand"),
+                None => self.scoring_function.energy(
+                    &self.translation,
+                    &self.rotation,
+                    &self.rec_nmodes,
+                    &self.lig_nmodes,
+                ),
+            };
         }
+        let previous = self.luciferin;
         self.luciferin = (1.0 - self.rho) * self.luciferin + self.gamma * self.scoring;
+        if self.step > 0 {
+            if self.moved || self.luciferin - previous > STAGNATION_TOLERANCE {
+                self.stagnation = 0;
+            } else {
+                self.stagnation += 1;
+            }
+        }
         self.step += 1;
     }
 
     pub fn distance(&mut self, other: &Glowworm) -> f64 {
-        let x1 = self.translation[0];
-        let x2 = other.translation[0];
-        let y1 = self.translation[1];
-        let y2 = other.translation[1];
-        let z1 = self.translation[2];
-        let z2 = other.translation[2];
-        ((x1 - x2) * (x1 - x2) + (y1 - y2) * (y1 - y2) + (z1 - z2) * (z1 - z2)).sqrt()
+        distance(self, other)
     }
 
     pub fn is_neighbor(&mut self, other: &Glowworm) -> bool {
@@ -96,6 +134,10 @@ impl<'a> Glowworm<'a> {
     }
 
     pub fn compute_probability_moving_toward_neighbor(&mut self, luciferins: &[f64]) {
+        if let Some(temperature) = self.softmax_temperature {
+            self.compute_softmax_probabilities(luciferins, temperature);
+            return;
+        }
         self.probabilities = Vec::new();
 
         let mut total_sum: f64 = 0.0;
@@ -111,6 +153,36 @@ impl<'a> Glowworm<'a> {
         }
     }
 
+    fn compute_softmax_probabilities(&mut self, luciferins: &[f64], temperature: f64) {
+        let differences: Vec<f64> = self
+            .neighbors
+            .iter()
+            .map(|&neighbor_id| luciferins[neighbor_id as usize] - self.luciferin)
+            .collect();
+        // Shifted by the largest difference to avoid overflows
+        let max_difference = differences.iter().copied().fold(f64::MIN, f64::max);
+        self.probabilities = differences
+            .iter()
+            .map(|difference| ((difference - max_difference) / temperature).exp())
+            .collect();
+        let total_sum: f64 = self.probabilities.iter().sum();
+        for probability in self.probabilities.iter_mut() {
+            *probability /= total_sum;
+        }
+    }
+
+    // Scales each probability by a random factor between exp(-noise) and exp(noise)
+    pub fn perturb_probabilities(&mut self, noise: f64, rng: &mut StdRng) {
+        let mut total_sum: f64 = 0.0;
+        for probability in self.probabilities.iter_mut() {
+            *probability *= (noise * rng.gen_range(-1.0, 1.0)).exp();
+            total_sum += *probability;
+        }
+        for probability in self.probabilities.iter_mut() {
+            *probability /= total_sum;
+        }
+    }
+
     pub fn select_random_neighbor(&mut self, random_number: f64) -> u32 {
         if self.neighbors.is_empty() {
             return self.id;
@@ -144,7 +216,7 @@ impl<'a> Glowworm<'a> {
             let norm: f64 =
                 (delta_x[0] * delta_x[0] + delta_x[1] * delta_x[1] + delta_x[2] * delta_x[2])
                     .sqrt();
-            let coef: f64 = DEFAULT_TRANSLATION_STEP / norm;
+            let coef: f64 = self.translation_step / norm;
             delta_x[0] *= coef;
             delta_x[1] *= coef;
             delta_x[2] *= coef;
@@ -165,7 +237,12 @@ impl<'a> Glowworm<'a> {
                     cum_norm += diff * diff
                 }
                 let anm_rec_norm: f64 = cum_norm.sqrt();
-                let anm_rec_coef: f64 = DEFAULT_NMODES_STEP / anm_rec_norm;
+                // Same extents, e.g. both still at the starting zero ones
+                let anm_rec_coef: f64 = if anm_rec_norm > 0.0 {
+                    DEFAULT_NMODES_STEP / anm_rec_norm
+                } else {
+                    0.0
+                };
                 for i in 0..self.rec_nmodes.len() {
                     delta_anm[i] *= anm_rec_coef;
                     self.rec_nmodes[i] += delta_anm[i];
@@ -180,7 +257,11 @@ impl<'a> Glowworm<'a> {
                     cum_norm += diff * diff
                 }
                 let anm_lig_norm: f64 = cum_norm.sqrt();
-                let anm_lig_coef: f64 = DEFAULT_NMODES_STEP / anm_lig_norm;
+                let anm_lig_coef: f64 = if anm_lig_norm > 0.0 {
+                    DEFAULT_NMODES_STEP / anm_lig_norm
+                } else {
+                    0.0
+                };
                 for i in 0..self.lig_nmodes.len() {
                     delta_anm[i] *= anm_lig_coef;
                     self.lig_nmodes[i] += delta_anm[i];
@@ -188,6 +269,112 @@ impl<'a> Glowworm<'a> {
             }
         }
     }
+
+    // Same interpolation of the pose of the second ligand
+    pub fn move_second_ligand_towards(&mut self, other: &LigandPose) {
+        if let Some(second) = self.second_ligand.as_mut() {
+            let delta: Vec<f64> = (0..3)
+                .map(|k| other.translation[k] - second.translation[k])
+                .collect();
+            let norm = delta.iter().map(|d| d * d).sum::<f64>().sqrt();
+            if norm > 0.0 {
+                for k in 0..3 {
+                    second.translation[k] += self.translation_step / norm * delta[k];
+                }
+            }
+            second.rotation = second
+                .rotation
+                .slerp(&other.rotation, DEFAULT_ROTATION_STEP);
+        }
+    }
+
+    // Random displacement of up to noise times the interpolation steps along every coordinate
+    pub fn perturb_movement(&mut self, noise: f64, rng: &mut StdRng) {
+        if let Some(second) = self.second_ligand.as_mut() {
+            for coordinate in second.translation.iter_mut() {
+                *coordinate += noise * self.translation_step * rng.gen_range(-1.0, 1.0);
+            }
+        }
+        for coordinate in self.translation.iter_mut() {
+            *coordinate += noise * self.translation_step * rng.gen_range(-1.0, 1.0);
+        }
+        if self.use_anm {
+            for extent in self.rec_nmodes.iter_mut().chain(self.lig_nmodes.iter_mut()) {
+                *extent += noise * DEFAULT_NMODES_STEP * rng.gen_range(-1.0, 1.0);
+            }
+        }
+    }
+
+    // Restores the parts of the pose with NaN or infinite values, e.g. after a division by a zero
+    // norm, from the given previous pose. Returns whether any part was restored.
+    pub fn restore_corrupted(
+        &mut self,
+        translation: &[f64],
+        rotation: &Quaternion,
+        rec_nmodes: &[f64],
+        lig_nmodes: &[f64],
+        second_ligand: &Option<LigandPose>,
+    ) -> bool {
+        let is_corrupted = |values: &[f64]| values.iter().any(|value| !value.is_finite());
+        let quaternion = |q: &Quaternion| [q.w, q.x, q.y, q.z];
+        let mut restored = false;
+        if is_corrupted(&self.translation) {
+            self.translation = translation.to_vec();
+            restored = true;
+        }
+        if is_corrupted(&quaternion(&self.rotation)) {
+            self.rotation = *rotation;
+            restored = true;
+        }
+        if is_corrupted(&self.rec_nmodes) {
+            self.rec_nmodes = rec_nmodes.to_vec();
+            restored = true;
+        }
+        if is_corrupted(&self.lig_nmodes) {
+            self.lig_nmodes = lig_nmodes.to_vec();
+            restored = true;
+        }
+        if let Some(second) = self.second_ligand.as_ref() {
+            if is_corrupted(&second.translation) || is_corrupted(&quaternion(&second.rotation)) {
+                self.second_ligand = second_ligand.clone();
+                restored = true;
+            }
+        }
+        restored
+    }
+
+    // Projects the pose onto the closest one generating a ring of the given order
+    pub fn make_symmetric(&mut self, order: usize) {
+        let (translation, rotation) = cn_pose(&self.translation, &self.rotation, order);
+        self.translation = translation;
+        self.rotation = rotation;
+    }
+
+    // Starts over from the given pose with the initial luciferin and vision range of its swarm
+    pub fn reset(
+        &mut self,
+        translation: Vec<f64>,
+        rotation: Quaternion,
+        luciferin: f64,
+        vision_range: f64,
+    ) {
+        // The second ligand keeps its position with respect to the first one
+        if let Some(second) = self.second_ligand.as_mut() {
+            for k in 0..3 {
+                second.translation[k] += translation[k] - self.translation[k];
+            }
+        }
+        self.translation = translation;
+        self.rotation = rotation;
+        self.rec_nmodes.fill(0.0);
+        self.lig_nmodes.fill(0.0);
+        self.luciferin = luciferin;
+        self.vision_range = vision_range;
+        self.neighbors.clear();
+        self.probabilities.clear();
+        self.moved = true;
+        self.stagnation = 0;
+    }
 }
 
 pub fn distance(one: &Glowworm, two: &Glowworm) -> f64 {
@@ -198,5 +385,12 @@ pub fn distance(one: &Glowworm, two: &Glowworm) -> f64 {
     let y2 = two.translation[1];
     let z1 = one.translation[2];
     let z2 = two.translation[2];
-    ((x1 - x2) * (x1 - x2) + (y1 - y2) * (y1 - y2) + (z1 - z2) * (z1 - z2)).sqrt()
+    let mut distance2 = (x1 - x2) * (x1 - x2) + (y1 - y2) * (y1 - y2) + (z1 - z2) * (z1 - z2);
+    // With two ligands, the translations of both
+    if let (Some(second_one), Some(second_two)) = (&one.second_ligand, &two.second_ligand) {
+        distance2 += (0..3)
+            .map(|k| (second_one.translation[k] - second_two.translation[k]).powi(2))
+            .sum::<f64>();
+    }
+    distance2.sqrt()
 }
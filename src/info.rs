@@ -0,0 +1,308 @@
+use super::dfire::DFIREDockingModel;
+use super::dfire2::{DFIRE2DockingModel, DFIRE2Potential};
+use super::dna::DNADockingModel;
+use super::membrane::MEMBRANE_RESIDUE;
+use super::nmodes::Nmodes;
+use super::parameters::AtomOverride;
+use super::policy::ResiduePolicy;
+use super::pydock::PYDOCKDockingModel;
+use super::restraints::residue_id;
+use super::scoring::Method;
+use super::sipper::{SIPPERDockingModel, SIPPERPotential};
+use super::tobi::{TOBIDockingModel, TOBIPotential};
+use pdbtbx::PDB;
+use std::collections::HashMap;
+
+// Statistics of a partner as taken by a scoring function, a fast sanity check of a setup before
+// launching a campaign: what the structure holds, how many atoms got parameters from the built-in
+// tables of the scoring function or from the overrides, which restraints were found among the
+// scored residues and how many atoms the normal modes cover. Only the scoring model is built, the
+// potentials are not loaded.
+
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct PartnerInfo {
+    pub chains: usize,
+    pub residues: usize,
+    pub atoms: usize,
+    pub hydrogens: usize,
+    pub membrane_beads: usize,
+    // Residues and atoms of the scoring model, and the atoms left out of it
+    pub scored_residues: usize,
+    pub scored_atoms: usize,
+    pub builtin_atoms: usize,
+    pub overridden_atoms: usize,
+    pub dropped_atoms: usize,
+    // Restraint residue IDs given, and the ones without any scored atom
+    pub active_restraints: usize,
+    pub passive_restraints: usize,
+    pub unresolved_restraints: Vec<String>,
+    // Normal modes and the atoms they are given for
+    pub num_anm: usize,
+    pub nmodes_atoms: usize,
+}
+
+pub fn partner_info(
+    method: &Method,
+    structure: &PDB,
+    active_restraints: &[String],
+    passive_restraints: &[String],
+    nmodes: &Nmodes,
+    num_anm: usize,
+    overrides: &[AtomOverride],
+    policy: ResiduePolicy,
+) -> PartnerInfo {
+    let hotspots: HashMap<String, f64> = HashMap::new();
+    let weights: HashMap<String, f64> = HashMap::new();
+    // Scored residues and atoms, atoms with overridden parameters and restrained residues found
+    let (scored_residues, scored_atoms, overridden_atoms, active, passive) = match method {
+        Method::DFIRE => {
+            let model = DFIREDockingModel::new(
+                structure,
+                active_restraints,
+                passive_restraints,
+                &hotspots,
+                &weights,
+                nmodes,
+                num_anm,
+                false,
+                policy,
+            );
+            (
+                model.residues.len(),
+                model.coordinates.len(),
+                0,
+                model.active_restraints,
+                model.passive_restraints,
+            )
+        }
+        Method::DFIRE2 => {
+            let potential = match DFIRE2Potential::load() {
+                Ok(potential) => potential,
+                Err(e) => panic!("Unable to load DFIRE2 potential: {}", e),
+            };
+            let model = DFIRE2DockingModel::new(
+                structure,
+                active_restraints,
+                passive_restraints,
+                &hotspots,
+                &weights,
+                nmodes,
+                num_anm,
+                &potential,
+                policy,
+            );
+            (
+                model.residues.len(),
+                model.coordinates.len(),
+                0,
+                model.active_restraints,
+                model.passive_restraints,
+            )
+        }
+        Method::TOBI => {
+            let potential = match TOBIPotential::load() {
+                Ok(potential) => potential,
+                Err(e) => panic!("Unable to load TOBI potential: {}", e),
+            };
+            let model = TOBIDockingModel::new(
+                structure,
+                active_restraints,
+                passive_restraints,
+                &hotspots,
+                &weights,
+                nmodes,
+                num_anm,
+                &potential,
+                policy,
+            );
+            (
+                model.residues.len(),
+                model.coordinates.len(),
+                0,
+                model.active_restraints,
+                model.passive_restraints,
+            )
+        }
+        Method::SIPPER => {
+            let potential = match SIPPERPotential::load() {
+                Ok(potential) => potential,
+                Err(e) => panic!("Unable to load SIPPER potential: {}", e),
+            };
+            let model = SIPPERDockingModel::new(
+                structure,
+                active_restraints,
+                passive_restraints,
+                &hotspots,
+                &weights,
+                nmodes,
+                num_anm,
+                &potential,
+                policy,
+            );
+            (
+                model.residues.len(),
+                model.coordinates.len(),
+                0,
+                model.active_restraints,
+                model.passive_restraints,
+            )
+        }
+        Method::Registered(_) => {
+            // The model of the scoring function is not known, every atom is taken as scored
+            let mut active = HashMap::new();
+            let mut passive = HashMap::new();
+            let mut index = 0;
+            for chain in structure.chains() {
+                for residue in chain.residues() {
+                    let res_id = residue_id(chain, residue);
+                    for (restraints, found) in [
+                        (active_restraints, &mut active),
+                        (passive_restraints, &mut passive),
+                    ] {
+                        if restraints.contains(&res_id) {
+                            found
+                                .entry(res_id.clone())
+                                .or_insert_with(Vec::new)
+                                .extend(index..index + residue.atom_count());
+                        }
+                    }
+                    index += residue.atom_count();
+                }
+            }
+            (
+                structure.residue_count(),
+                structure.atom_count(),
+                0,
+                active,
+                passive,
+            )
+        }
+        Method::DNA => {
+            let model = DNADockingModel::new(
+                structure,
+                active_restraints,
+                passive_restraints,
+                &hotspots,
+                &weights,
+                nmodes,
+                num_anm,
+                overrides,
+                policy,
+            );
+            (
+                model.residues.len(),
+                model.coordinates.len(),
+                model.num_overridden,
+                model.active_restraints,
+                model.passive_restraints,
+            )
+        }
+        Method::PYDOCK => {
+            let model = PYDOCKDockingModel::new(
+                structure,
+                active_restraints,
+                passive_restraints,
+                &hotspots,
+                &weights,
+                nmodes,
+                num_anm,
+                overrides,
+                policy,
+            );
+            (
+                model.residues.len(),
+                model.coordinates.len(),
+                model.num_overridden,
+                model.active_restraints,
+                model.passive_restraints,
+            )
+        }
+    };
+
+    let mut unresolved_restraints: Vec<String> = Vec::new();
+    for (restraints, found) in [(active_restraints, &active), (passive_restraints, &passive)] {
+        for restraint in restraints.iter() {
+            if !found.contains_key(restraint) && !unresolved_restraints.contains(restraint) {
+                unresolved_restraints.push(restraint.clone());
+            }
+        }
+    }
+
+    PartnerInfo {
+        chains: structure.chain_count(),
+        residues: structure.residue_count(),
+        atoms: structure.atom_count(),
+        hydrogens: structure
+            .atoms()
+            .filter(|atom| atom.name().trim().starts_with('H'))
+            .count(),
+        membrane_beads: structure
+            .residues()
+            .filter(|residue| residue.name() == Some(MEMBRANE_RESIDUE))
+            .map(|residue| residue.atom_count())
+            .sum(),
+        scored_residues,
+        scored_atoms,
+        builtin_atoms: scored_atoms - overridden_atoms,
+        overridden_atoms,
+        dropped_atoms: structure.atom_count() - scored_atoms,
+        active_restraints: active_restraints.len(),
+        passive_restraints: passive_restraints.len(),
+        unresolved_restraints,
+        num_anm,
+        nmodes_atoms: if num_anm > 0 {
+            nmodes.len() / (3 * num_anm)
+        } else {
+            0
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::structure::parse_structure;
+
+    #[test]
+    fn test_partner_info() {
+        // A residue without DFIRE atom types, dropped by the default policy
+        let structure = parse_structure(
+            "\
+ATOM      1  N   ALA A   1       0.000   0.000   0.000  1.00  0.00           N\n\
+ATOM      2  CA  ALA A   1       1.000   0.000   0.000  1.00  0.00           C\n\
+ATOM      3  CB  ALA A   1       1.000   1.000   0.000  1.00  0.00           C\n\
+ATOM      4  C1  XYZ A   2       2.000   0.000   0.000  1.00  0.00           C\n\
+ATOM      5  N   GLY B   1       3.000   0.000   0.000  1.00  0.00           N\n",
+            1,
+        )
+        .unwrap()
+        .unwrap();
+        let nmodes: Nmodes = vec![0.0; 2 * 5 * 3].into();
+
+        let info = partner_info(
+            &Method::DFIRE,
+            &structure,
+            &[String::from("A.ALA.1"), String::from("A.XYZ.2")],
+            &[String::from("B.GLY.1"), String::from("A.ALA.1")],
+            &nmodes,
+            2,
+            &[],
+            ResiduePolicy::default(),
+        );
+        assert_eq!(info.chains, 2);
+        assert_eq!(info.residues, 3);
+        assert_eq!(info.atoms, 5);
+        assert_eq!(info.hydrogens, 0);
+        assert_eq!(info.membrane_beads, 0);
+        assert_eq!(info.scored_residues, 2);
+        assert_eq!(
+            info.builtin_atoms + info.overridden_atoms,
+            info.scored_atoms
+        );
+        assert_eq!(info.scored_atoms + info.dropped_atoms, 5);
+        assert_eq!(info.active_restraints, 2);
+        assert_eq!(info.passive_restraints, 2);
+        assert_eq!(info.unresolved_restraints, vec!["A.XYZ.2"]);
+        assert_eq!(info.nmodes_atoms, 5);
+    }
+}
@@ -2,25 +2,109 @@
 extern crate lazy_static;
 extern crate rand;
 
+pub mod antibody;
+pub mod benchmark;
+pub mod bundle;
+mod cli;
+pub mod clustering;
 pub mod constants;
+pub mod contacts;
+pub mod data;
+pub mod decomposition;
 pub mod dfire;
+pub mod dfire2;
 pub mod dna;
+pub mod export;
 pub mod glowworm;
+pub mod info;
+pub mod mask;
+pub mod membrane;
+pub mod minimization;
+pub mod moves;
+pub mod nmodes;
+pub mod output;
+pub mod parameters;
+pub mod peptide;
+pub mod policy;
+pub mod pose;
+pub mod positions;
+pub mod precision;
+pub mod presampling;
+pub mod prmtop;
+pub mod profile;
+pub mod propensity;
+pub mod protocol;
 pub mod pydock;
 pub mod qt;
+pub mod refinement;
+pub mod region;
+pub mod registry;
+pub mod restraints;
+pub mod rmsd;
+pub mod sanitize;
 pub mod scoring;
+pub mod selftest;
+pub mod sipper;
+pub mod solvation;
+pub mod structure;
 pub mod swarm;
+pub mod symmetry;
+pub mod ternary;
+pub mod tobi;
+pub mod training;
+#[cfg(feature = "parquet")]
+pub mod trajectory;
 
+use decomposition::ResidueEnergies;
+use dfire::{DFIREOptions, DFIRE};
+use dfire2::DFIRE2;
+use dna::DNA;
 use log::info;
+use minimization::LBFGS;
+use nmodes::{read_nmodes_file, Nmodes};
+use output::{
+    gso_output_path, refined_output_path, write_file, ConvergenceRow, GSOEntry, OutputWriter,
+    StepEvent, TrajectoryWriter,
+};
+use policy::ResiduePolicy;
+use pose::Pose;
+use profile::{take_pose_time, StepProfile};
+use pydock::PYDOCK;
+use qt::Quaternion;
 use rand::rngs::StdRng;
 use rand::SeedableRng;
-use scoring::Score;
-use swarm::Swarm;
+use registry::{provider, ScoringInput};
+use scoring::{Method, Score, TermWeights};
+use sipper::SIPPER;
+use std::error::Error;
+use std::io::Write;
+use std::time::Instant;
+use structure::read_structure;
+use swarm::{DiversityInjection, NoiseSchedule, Swarm};
+use tobi::TOBI;
+
+// Command line of lightdock-rust, for the binaries of downstream crates that register their own
+// scoring functions (see registry::register_scoring) before running it
+pub fn run_cli() {
+    cli::main();
+}
 
 pub struct GSO<'a> {
     pub swarm: Swarm<'a>,
     pub rng: StdRng,
     pub output_directory: String,
+    pub compress_output: bool,
+    pub events: Option<Box<dyn Write>>,
+    // Swarm written in the step events
+    pub swarm_id: Option<usize>,
+    pub convergence: Option<Box<dyn Write>>,
+    // Time of every phase per step, see profile::StepProfile
+    pub profile: Option<Box<dyn Write>>,
+    pub trajectory: Option<Box<dyn TrajectoryWriter>>,
+    // Last step of a previous run this one continues
+    pub start_step: u32,
+    pub noise: Option<NoiseSchedule>,
+    pub diversity: Option<DiversityInjection>,
 }
 
 impl<'a> GSO<'a> {
@@ -37,23 +121,362 @@ impl<'a> GSO<'a> {
             swarm: Swarm::new(),
             rng: SeedableRng::seed_from_u64(seed),
             output_directory,
+            compress_output: false,
+            events: None,
+            swarm_id: None,
+            convergence: None,
+            profile: None,
+            trajectory: None,
+            start_step: 0,
+            noise: None,
+            diversity: None,
         };
+        gso.swarm.seed = Some(seed);
         gso.swarm
             .add_glowworms(positions, scoring, use_anm, rec_num_anm, lig_num_anm);
         gso
     }
 
+    // Restores the glowworm states saved at the given step of a previous run
+    pub fn resume(&mut self, step: u32, entries: &[GSOEntry]) {
+        for (glowworm, entry) in self.swarm.glowworms.iter_mut().zip(entries.iter()) {
+            glowworm.luciferin = entry.luciferin;
+            glowworm.vision_range = entry.vision_range;
+            glowworm.scoring = entry.scoring;
+            glowworm.step = step;
+            // Saved poses are the ones after the last movement, not scored yet
+            glowworm.moved = true;
+        }
+        self.start_step = step;
+    }
+
     pub fn run(&mut self, steps: u32) {
-        for step in 1..steps + 1 {
+        let mut writer = OutputWriter::default();
+        for step in self.start_step + 1..self.start_step + steps + 1 {
             info!("Step {}", step);
+            let mut profile = StepProfile::new(step);
+            // Poses evaluated out of the scoring phase are not counted
+            take_pose_time();
+            let start = Instant::now();
             self.swarm.update_luciferin();
-            self.swarm.movement_phase(&mut self.rng);
+            profile.anm = take_pose_time();
+            profile.scoring = start.elapsed().saturating_sub(profile.anm);
+
+            let start = Instant::now();
+            if let Some(events) = self.events.as_mut() {
+                let mut event = StepEvent::new(step, &self.swarm);
+                event.swarm = self.swarm_id;
+                if let Err(why) = event.write(events) {
+                    panic!("Error writing GSO step event: {:?}", why);
+                }
+            }
+            if let Some(convergence) = self.convergence.as_mut() {
+                if let Err(why) = ConvergenceRow::new(step, &self.swarm).write(convergence) {
+                    panic!("Error writing GSO convergence: {:?}", why);
+                }
+            }
+            profile.io = start.elapsed();
+
+            let start = Instant::now();
+            self.swarm.search_neighbors();
+            profile.neighbors = start.elapsed();
+
+            let start = Instant::now();
+            let noise = self.noise.map_or(0.0, |noise| noise.level(step));
+            self.swarm.move_glowworms(&mut self.rng, noise);
+            // Avoid a premature convergence of the swarm
+            if let Some(injection) = self.diversity.filter(|i| step < i.min_steps) {
+                let diversity = self.swarm.diversity();
+                if diversity < injection.threshold {
+                    let num_reseeded = self.swarm.reseed(&injection, &mut self.rng);
+                    info!(
+                        "Diversity {:.3} below {}, {} glowworms re-seeded",
+                        diversity, injection.threshold, num_reseeded
+                    );
+                }
+            }
+            profile.movement = start.elapsed();
+
+            let start = Instant::now();
             if step % 10 == 0 || step == 1 {
-                match self.swarm.save(step, &self.output_directory) {
-                    Ok(ok) => ok,
-                    Err(why) => panic!("Error saving GSO output: {:?}", why),
+                let mut contents: Vec<u8> = Vec::new();
+                let saved = self.swarm.write_output(step, &mut contents).and_then(|_| {
+                    writer.write(
+                        gso_output_path(&self.output_directory, step, self.compress_output),
+                        contents,
+                        self.compress_output,
+                    )
+                });
+                if let Err(why) = saved {
+                    panic!("Error saving GSO output: {:?}", why);
+                }
+                if let Some(trajectory) = self.trajectory.as_mut() {
+                    if let Err(why) = trajectory.append(step, &self.swarm) {
+                        panic!("Error saving GSO trajectory: {:?}", why);
+                    }
                 }
             }
+            profile.io += start.elapsed();
+            if let Some(output) = self.profile.as_mut() {
+                if let Err(why) = profile.write(output) {
+                    panic!("Error writing GSO profile: {:?}", why);
+                }
+            }
+        }
+        if let Err(why) = writer.finish() {
+            panic!("Error saving GSO output: {:?}", why);
+        }
+        if let Some(convergence) = self.convergence.as_mut() {
+            if let Err(why) = convergence.flush() {
+                panic!("Error writing GSO convergence: {:?}", why);
+            }
+        }
+        if let Some(profile) = self.profile.as_mut() {
+            if let Err(why) = profile.flush() {
+                panic!("Error writing GSO profile: {:?}", why);
+            }
+        }
+        if let Some(trajectory) = self.trajectory.as_mut() {
+            if let Err(why) = trajectory.close() {
+                panic!("Error saving GSO trajectory: {:?}", why);
+            }
         }
     }
+
+    // Refines the best poses after the given last step and saves all of them in gso_refined.out,
+    // returning the refined glowworms and their scores before refinement
+    pub fn refine(&mut self, step: u32, top: usize, minimizer: &LBFGS) -> Vec<(usize, f64)> {
+        let refined = self.swarm.refine(top, minimizer);
+        let mut contents: Vec<u8> = Vec::new();
+        let saved = self.swarm.write_output(step, &mut contents).and_then(|_| {
+            write_file(
+                &refined_output_path(&self.output_directory, self.compress_output),
+                &contents,
+                self.compress_output,
+            )
+        });
+        if let Err(why) = saved {
+            panic!("Error saving refined GSO output: {:?}", why);
+        }
+        refined
+    }
+}
+
+// Scoring function of the given method for the receptor and ligand structure files, prepared as
+// by the setup, without restraints. Normal modes are read from the NumPy or NMD files given with
+// their number of modes.
+pub fn scoring_from_files(
+    method: &Method,
+    receptor_path: &str,
+    ligand_path: &str,
+    rec_nmodes: Option<(&str, usize)>,
+    lig_nmodes: Option<(&str, usize)>,
+    policy: ResiduePolicy,
+) -> Result<Box<dyn Score>, Box<dyn Error>> {
+    let receptor = read_structure(receptor_path)?;
+    let ligand = read_structure(ligand_path)?;
+    let mut models_nmodes: Vec<(Nmodes, usize)> = Vec::new();
+    for (structure, nmodes) in [(&receptor, rec_nmodes), (&ligand, lig_nmodes)] {
+        models_nmodes.push(match nmodes {
+            Some((path, num_anm)) if num_anm > 0 => {
+                let (values, num_atoms) = read_nmodes_file(path, structure, num_anm)
+                    .map_err(|e| format!("Error reading ANM file [{:?}]: {}", path, e))?;
+                if num_atoms != structure.atom_count() {
+                    return Err(format!(
+                        "ANM file [{:?}] has modes for {} atoms, the structure has {}",
+                        path,
+                        num_atoms,
+                        structure.atom_count()
+                    )
+                    .into());
+                }
+                (values, num_anm)
+            }
+            _ => (Nmodes::default(), 0),
+        });
+    }
+    let (lig_nm, lig_num_anm) = models_nmodes.pop().unwrap();
+    let (rec_nm, rec_num_anm) = models_nmodes.pop().unwrap();
+    let mut input = ScoringInput::new(receptor, ligand, policy);
+    input.rec_nmodes = rec_nm;
+    input.rec_num_anm = rec_num_anm;
+    input.lig_nmodes = lig_nm;
+    input.lig_num_anm = lig_num_anm;
+    input.use_anm = rec_num_anm + lig_num_anm > 0;
+    build_scoring(method, input)
+}
+
+// Scoring function of the given method with the default options of the built-in methods, an
+// error if the method was not registered
+pub fn build_scoring(
+    method: &Method,
+    input: ScoringInput,
+) -> Result<Box<dyn Score>, Box<dyn Error>> {
+    let scoring = match method {
+        Method::DFIRE => DFIRE::new(input, DFIREOptions::default())?,
+        Method::DFIRE2 => DFIRE2::new(input)?,
+        Method::TOBI => TOBI::new(input)?,
+        Method::SIPPER => SIPPER::new(input)?,
+        Method::Registered(name) => provider(name)?.build(input),
+        Method::DNA => DNA::new(input, None, false, TermWeights::default()),
+        Method::PYDOCK => PYDOCK::new(input, None, false, TermWeights::default()),
+    };
+    Ok(scoring)
+}
+
+// Energy of a single pose, the translation of the ligand center and its rotation, given the
+// receptor and ligand structure files. Normal modes are given as the path of their file and the
+// extents of the pose, one per mode.
+pub fn compute_energy_for_pose(
+    method: &Method,
+    receptor_path: &str,
+    ligand_path: &str,
+    translation: &[f64],
+    rotation: &Quaternion,
+    rec_nmodes: Option<(&str, &[f64])>,
+    lig_nmodes: Option<(&str, &[f64])>,
+) -> Result<f64, Box<dyn Error>> {
+    let scoring = scoring_from_files(
+        method,
+        receptor_path,
+        ligand_path,
+        rec_nmodes.map(|(path, extents)| (path, extents.len())),
+        lig_nmodes.map(|(path, extents)| (path, extents.len())),
+        ResiduePolicy::default(),
+    )?;
+    Ok(scoring.energy(
+        translation,
+        rotation,
+        rec_nmodes.map_or(&[], |(_, extents)| extents),
+        lig_nmodes.map_or(&[], |(_, extents)| extents),
+    ))
+}
+
+// Energies of many poses of the same partners, see compute_energy_for_pose. Normal modes are
+// given as the path of their file and the number of modes, the extents of every pose must have
+// as many values.
+pub fn compute_energies_for_poses(
+    method: &Method,
+    receptor_path: &str,
+    ligand_path: &str,
+    poses: &[Pose],
+    rec_nmodes: Option<(&str, usize)>,
+    lig_nmodes: Option<(&str, usize)>,
+) -> Result<Vec<f64>, Box<dyn Error>> {
+    let scoring = scoring_from_files(
+        method,
+        receptor_path,
+        ligand_path,
+        rec_nmodes,
+        lig_nmodes,
+        ResiduePolicy::default(),
+    )?;
+    Ok(scoring.energies(poses))
+}
+
+// Energy of a pose split into receptor residue by ligand residue contributions, see
+// compute_energies_for_poses for the normal modes. Restraints, hot-spots and membrane are not
+// decomposed, so the total is the energy of the pose without their bias. The matrix can be written
+// as CSV with ResidueEnergies::write_csv, e.g. for interface heatmaps.
+pub fn compute_residue_energies_for_pose(
+    method: &Method,
+    receptor_path: &str,
+    ligand_path: &str,
+    pose: &Pose,
+    rec_nmodes: Option<(&str, usize)>,
+    lig_nmodes: Option<(&str, usize)>,
+) -> Result<ResidueEnergies, Box<dyn Error>> {
+    let scoring = scoring_from_files(
+        method,
+        receptor_path,
+        ligand_path,
+        rec_nmodes,
+        lig_nmodes,
+        ResiduePolicy::default(),
+    )?;
+    match scoring.residue_energies(
+        &pose.translation,
+        &pose.rotation,
+        &pose.rec_nmodes,
+        &pose.lig_nmodes,
+    ) {
+        Some(energies) => Ok(energies),
+        None => Err(format!("{:?} does not support residue energies", method).into()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_compute_energy_for_pose() {
+        let test_path = format!("{}/tests/2oob", env!("CARGO_MANIFEST_DIR"));
+        let energy = compute_energy_for_pose(
+            &Method::DFIRE,
+            &format!("{}/2oob_receptor.pdb", test_path),
+            &format!("{}/2oob_ligand.pdb", test_path),
+            &[0.0, 0.0, 0.0],
+            &Quaternion::default(),
+            None,
+            None,
+        )
+        .unwrap();
+        assert_eq!(energy, 16.7540569503498);
+
+        let missing = compute_energy_for_pose(
+            &Method::DFIRE,
+            &format!("{}/missing.pdb", test_path),
+            &format!("{}/2oob_ligand.pdb", test_path),
+            &[0.0, 0.0, 0.0],
+            &Quaternion::default(),
+            None,
+            None,
+        );
+        assert!(missing.is_err());
+
+        // Same energy in a batch of poses
+        let poses = vec![
+            Pose::new(vec![0.0; 3], Quaternion::default(), Vec::new(), Vec::new()),
+            Pose::new(
+                vec![1000.0, 0.0, 0.0],
+                Quaternion::default(),
+                Vec::new(),
+                Vec::new(),
+            ),
+        ];
+        let energies = compute_energies_for_poses(
+            &Method::DFIRE,
+            &format!("{}/2oob_receptor.pdb", test_path),
+            &format!("{}/2oob_ligand.pdb", test_path),
+            &poses,
+            None,
+            None,
+        )
+        .unwrap();
+        assert_eq!(energies.len(), 2);
+        assert_eq!(energies[0], energy);
+    }
+
+    #[test]
+    fn test_compute_residue_energies_for_pose() {
+        let test_path = format!("{}/tests/2oob", env!("CARGO_MANIFEST_DIR"));
+        let pose = Pose::new(vec![0.0; 3], Quaternion::default(), Vec::new(), Vec::new());
+        let energies = compute_residue_energies_for_pose(
+            &Method::DFIRE,
+            &format!("{}/2oob_receptor.pdb", test_path),
+            &format!("{}/2oob_ligand.pdb", test_path),
+            &pose,
+            None,
+            None,
+        )
+        .unwrap();
+        // Without restraints the contributions add up to the energy of the pose
+        assert!((energies.total() - 16.7540569503498).abs() < 1e-9);
+        assert!(energies
+            .energies
+            .iter()
+            .flatten()
+            .any(|&energy| energy != 0.0));
+    }
 }
@@ -0,0 +1,90 @@
+use super::pose::select_nmodes;
+use super::restraints::residue_id;
+use pdbtbx::PDB;
+
+// Residues excluded from the scoring, like engineered tags or disordered loops. The scoring
+// functions are given copies of the structures without the masked residues, and the normal modes
+// of the atoms left, while poses, output and exported complexes keep the full structures. Poses
+// do not depend on the atoms of a structure, so masked atoms still move with the pose.
+
+// Structure without the residues of the mask, with the normal modes of the atoms left, given as
+// (num_anm, num_atoms, 3), and the number of masked residues
+pub fn mask_structure(
+    structure: &PDB,
+    nmodes: &[f64],
+    num_anm: usize,
+    residues: &[String],
+) -> (PDB, Vec<f64>, usize) {
+    // Indexes in the full structure of the atoms kept
+    let mut kept: Vec<usize> = Vec::new();
+    let mut num_atoms: usize = 0;
+    let mut num_masked: usize = 0;
+    for chain in structure.chains() {
+        for residue in chain.residues() {
+            if residues.contains(&residue_id(chain, residue)) {
+                num_masked += 1;
+            } else {
+                kept.extend(num_atoms..num_atoms + residue.atom_count());
+            }
+            num_atoms += residue.atom_count();
+        }
+    }
+
+    let mut masked = structure.clone();
+    for chain in masked.chains_mut() {
+        let indexes: Vec<usize> = chain
+            .residues()
+            .enumerate()
+            .filter(|(_, residue)| residues.contains(&residue_id(chain, residue)))
+            .map(|(index, _)| index)
+            .collect();
+        for index in indexes.into_iter().rev() {
+            chain.remove_residue(index);
+        }
+    }
+    masked.remove_empty();
+
+    let nmodes = if num_anm > 0 && !nmodes.is_empty() {
+        select_nmodes(nmodes, num_anm, num_atoms, &kept)
+    } else {
+        nmodes.to_vec()
+    };
+    (masked, nmodes, num_masked)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::structure::parse_structure;
+
+    #[test]
+    fn test_mask_structure() {
+        let structure = parse_structure(
+            "\
+ATOM      1  N   ALA A   1       0.000   0.000   0.000  1.00  0.00           N\n\
+ATOM      2  CA  ALA A   1       1.000   0.000   0.000  1.00  0.00           C\n\
+ATOM      3  N   GLY A   2       2.000   0.000   0.000  1.00  0.00           N\n\
+ATOM      4  N   SER B   1       3.000   0.000   0.000  1.00  0.00           N\n",
+            1,
+        )
+        .unwrap()
+        .unwrap();
+        // One mode, the x displacement of every atom is its index
+        let nmodes = [0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 2.0, 0.0, 0.0, 3.0, 0.0, 0.0];
+
+        let mask = vec![String::from("A.ALA.1"), String::from("B.SER.1")];
+        let (masked, masked_nmodes, num_masked) = mask_structure(&structure, &nmodes, 1, &mask);
+        assert_eq!(num_masked, 2);
+        assert_eq!(masked.atom_count(), 1);
+        assert_eq!(masked.chain_count(), 1);
+        assert_eq!(masked.atoms().next().unwrap().x(), 2.0);
+        assert_eq!(masked_nmodes, vec![2.0, 0.0, 0.0]);
+
+        // Without normal modes, and with residues not in the structure
+        let (masked, masked_nmodes, num_masked) =
+            mask_structure(&structure, &[], 0, &[String::from("C.ALA.1")]);
+        assert_eq!(num_masked, 0);
+        assert_eq!(masked.atom_count(), 4);
+        assert!(masked_nmodes.is_empty());
+    }
+}
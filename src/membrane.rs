@@ -0,0 +1,176 @@
+use super::structure::read_structure;
+use pdbtbx::{Atom, PDB};
+use std::error::Error;
+
+// Membrane beads are MMB.BJ atoms of the receptor, the poses with a ligand crossing them are
+// penalized by the scoring functions. Instead of merging them into the receptor PDB file, they can
+// be read from a separate file or placed on two planes perpendicular to the z axis, and are then
+// added to the receptor after reading it.
+
+pub const MEMBRANE_RESIDUE: &str = "MMB";
+pub const MEMBRANE_BEAD: &str = "BJ";
+
+// Every atom of the file is a bead, whatever its name
+pub fn read_beads(path: &str) -> Result<Vec<[f64; 3]>, Box<dyn Error>> {
+    let structure = read_structure(path)?;
+    let beads: Vec<[f64; 3]> = structure
+        .atoms()
+        .map(|atom| [atom.x(), atom.y(), atom.z()])
+        .collect();
+    if beads.is_empty() {
+        return Err(format!("no membrane beads found in {}", path).into());
+    }
+    Ok(beads)
+}
+
+// Square grids of beads at both z coordinates, covering the receptor plus the margin around it.
+// Beads closer than the clearance to a receptor atom are left out, where the receptor crosses
+// the membrane.
+pub fn plane_beads(
+    receptor: &[[f64; 3]],
+    planes: [f64; 2],
+    spacing: f64,
+    margin: f64,
+    clearance: f64,
+) -> Vec<[f64; 3]> {
+    let mut lower = [f64::MAX; 2];
+    let mut upper = [f64::MIN; 2];
+    for coordinate in receptor.iter() {
+        for k in 0..2 {
+            lower[k] = lower[k].min(coordinate[k]);
+            upper[k] = upper[k].max(coordinate[k]);
+        }
+    }
+    if receptor.is_empty() {
+        lower = [0.0; 2];
+        upper = [0.0; 2];
+    }
+    let center = [(lower[0] + upper[0]) / 2.0, (lower[1] + upper[1]) / 2.0];
+    let half_sizes = [
+        (upper[0] - lower[0]) / 2.0 + margin,
+        (upper[1] - lower[1]) / 2.0 + margin,
+    ];
+    let points = [
+        (half_sizes[0] / spacing).floor() as i64,
+        (half_sizes[1] / spacing).floor() as i64,
+    ];
+
+    let clearance2 = clearance * clearance;
+    let mut beads: Vec<[f64; 3]> = Vec::new();
+    for z in planes.iter() {
+        for i in -points[0]..=points[0] {
+            for j in -points[1]..=points[1] {
+                let bead = [
+                    center[0] + i as f64 * spacing,
+                    center[1] + j as f64 * spacing,
+                    *z,
+                ];
+                let clashes = receptor.iter().any(|coordinate| {
+                    (0..3)
+                        .map(|k| (coordinate[k] - bead[k]).powi(2))
+                        .sum::<f64>()
+                        < clearance2
+                });
+                if !clashes {
+                    beads.push(bead);
+                }
+            }
+        }
+    }
+    beads
+}
+
+// Appends the beads to the last chain of the structure, one MMB residue each after its last
+// residue
+pub fn add_beads(structure: &mut PDB, beads: &[[f64; 3]]) {
+    let mut serial_number = structure
+        .atoms()
+        .map(|atom| atom.serial_number())
+        .max()
+        .unwrap_or(0);
+    let chain = match structure.chains_mut().last() {
+        Some(chain) => chain,
+        None => panic!("Membrane beads require a receptor with at least one chain"),
+    };
+    let mut residue_number = chain
+        .residues()
+        .map(|residue| residue.serial_number())
+        .max()
+        .unwrap_or(0);
+    for bead in beads.iter() {
+        serial_number += 1;
+        residue_number += 1;
+        let atom = Atom::new(
+            false,
+            serial_number,
+            MEMBRANE_BEAD,
+            bead[0],
+            bead[1],
+            bead[2],
+            1.0,
+            0.0,
+            "P",
+            0,
+        )
+        .unwrap();
+        chain.add_atom(atom, (residue_number, None), (MEMBRANE_RESIDUE, None));
+    }
+}
+
+// Receptor normal modes with zero displacements for the added beads, as (modes, atoms, 3)
+pub fn pad_nmodes(nmodes: &[f64], num_modes: usize, num_atoms: usize) -> Vec<f64> {
+    if num_modes == 0 {
+        return nmodes.to_vec();
+    }
+    let mode_length = nmodes.len() / num_modes;
+    let mut padded: Vec<f64> = Vec::with_capacity(num_modes * num_atoms * 3);
+    for mode in nmodes.chunks(mode_length) {
+        padded.extend_from_slice(mode);
+        padded.resize(padded.len() + num_atoms * 3 - mode_length, 0.0);
+    }
+    padded
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::structure::parse_structure;
+
+    #[test]
+    fn test_membrane_beads() {
+        let mut receptor = parse_structure(
+            "\
+ATOM      1  CA  ALA A   1      -5.000   0.000   0.000  1.00  0.00           C\n\
+ATOM      2  CA  ALA A   2       5.000   0.000   0.000  1.00  0.00           C\n",
+            1,
+        )
+        .unwrap()
+        .unwrap();
+        let coordinates: Vec<[f64; 3]> = receptor
+            .atoms()
+            .map(|atom| [atom.x(), atom.y(), atom.z()])
+            .collect();
+
+        // 3 x 3 grids, the beads along the receptor left out at z = 0
+        let beads = plane_beads(&coordinates, [-15.0, 0.0], 10.0, 10.0, 6.0);
+        assert_eq!(beads.len(), 15);
+        assert!(beads.contains(&[0.0, 0.0, -15.0]));
+        assert!(beads.contains(&[10.0, -10.0, 0.0]));
+        assert!(!beads.contains(&[0.0, 0.0, 0.0]));
+        assert!(!beads.contains(&[10.0, 0.0, 0.0]));
+
+        add_beads(&mut receptor, &beads[..2]);
+        assert_eq!(receptor.atom_count(), 4);
+        let residue = receptor.residues().last().unwrap();
+        assert_eq!(residue.name(), Some(MEMBRANE_RESIDUE));
+        assert_eq!(residue.serial_number(), 4);
+        assert_eq!(residue.atoms().next().unwrap().name(), MEMBRANE_BEAD);
+        assert_eq!(receptor.atoms().last().unwrap().serial_number(), 4);
+
+        let padded = pad_nmodes(&[1.0; 12], 2, 4);
+        assert_eq!(padded.len(), 24);
+        assert_eq!(&padded[..8], &[1.0, 1.0, 1.0, 1.0, 1.0, 1.0, 0.0, 0.0]);
+        assert_eq!(&padded[12..18], &[1.0; 6]);
+        assert_eq!(&padded[18..], &[0.0; 6]);
+    }
+}
@@ -0,0 +1,249 @@
+use super::constants::REJECTED_POSE_SCORE;
+use super::qt::Quaternion;
+use super::scoring::Score;
+use std::collections::VecDeque;
+
+// Local rigid-body refinement of poses by L-BFGS on the gradient of the scoring function, the
+// analytical one if it has it and the numerical one otherwise. The score is maximized over the translation and a rotation applied on top of the pose
+// one, normal mode extents are kept as they are. Steps are taken in the tangent space of the
+// current pose and the history of the quasi-Newton approximation is made of those steps.
+
+// Armijo condition of the backtracking line search and largest number of halvings of the step
+const ARMIJO: f64 = 1e-4;
+const MAX_BACKTRACKS: usize = 20;
+
+#[derive(Debug, Clone, Copy)]
+pub struct LBFGS {
+    // Number of steps kept to approximate the inverse Hessian
+    pub memory: usize,
+    pub max_iterations: usize,
+    // Finite differences step of the numerical gradient
+    pub eps: f64,
+    // Largest norm of a step, Angstroms and radians together
+    pub max_step: f64,
+    // Stop when the gradient norm falls below it
+    pub tolerance: f64,
+}
+
+#[derive(Debug, Clone)]
+pub struct RefinedPose {
+    pub translation: Vec<f64>,
+    pub rotation: Quaternion,
+    pub scoring: f64,
+    pub iterations: usize,
+}
+
+fn dot(a: &[f64], b: &[f64]) -> f64 {
+    a.iter().zip(b.iter()).map(|(x, y)| x * y).sum()
+}
+
+impl LBFGS {
+    pub fn new(memory: usize, max_iterations: usize, eps: f64, max_step: f64) -> Self {
+        LBFGS {
+            memory,
+            max_iterations,
+            eps,
+            max_step,
+            tolerance: 1e-6,
+        }
+    }
+
+    // Gradient of the function minimized, the negative score, over the six rigid-body parameters
+    fn gradient(
+        &self,
+        scoring: &dyn Score,
+        translation: &[f64],
+        rotation: &Quaternion,
+        rec_nmodes: &[f64],
+        lig_nmodes: &[f64],
+    ) -> Vec<f64> {
+        let gradient = scoring
+            .gradient(translation, rotation, rec_nmodes, lig_nmodes)
+            .unwrap_or_else(|| {
+                scoring.numerical_gradient(translation, rotation, rec_nmodes, lig_nmodes, self.eps)
+            });
+        gradient
+            .translation
+            .iter()
+            .chain(gradient.rotation.iter())
+            .map(|value| -value)
+            .collect()
+    }
+
+    // Two-loop recursion: product of the inverse Hessian approximation and the negative gradient
+    fn direction(&self, gradient: &[f64], history: &VecDeque<(Vec<f64>, Vec<f64>)>) -> Vec<f64> {
+        let mut q: Vec<f64> = gradient.to_vec();
+        let mut alphas: Vec<f64> = Vec::with_capacity(history.len());
+        for (s, y) in history.iter().rev() {
+            let alpha = dot(s, &q) / dot(y, s);
+            for (qi, yi) in q.iter_mut().zip(y.iter()) {
+                *qi -= alpha * yi;
+            }
+            alphas.push(alpha);
+        }
+        if let Some((s, y)) = history.back() {
+            let gamma = dot(s, y) / dot(y, y);
+            for qi in q.iter_mut() {
+                *qi *= gamma;
+            }
+        }
+        for ((s, y), alpha) in history.iter().zip(alphas.iter().rev()) {
+            let beta = dot(y, &q) / dot(y, s);
+            for (qi, si) in q.iter_mut().zip(s.iter()) {
+                *qi += (alpha - beta) * si;
+            }
+        }
+        q.iter().map(|value| -value).collect()
+    }
+
+    fn limit(&self, direction: &mut [f64]) {
+        let norm = dot(direction, direction).sqrt();
+        if norm > self.max_step {
+            for value in direction.iter_mut() {
+                *value *= self.max_step / norm;
+            }
+        }
+    }
+
+    pub fn refine(
+        &self,
+        scoring: &dyn Score,
+        translation: &[f64],
+        rotation: &Quaternion,
+        rec_nmodes: &[f64],
+        lig_nmodes: &[f64],
+    ) -> RefinedPose {
+        let mut pose = RefinedPose {
+            translation: translation.to_vec(),
+            rotation: *rotation,
+            scoring: scoring.energy(translation, rotation, rec_nmodes, lig_nmodes),
+            iterations: 0,
+        };
+        if pose.scoring == REJECTED_POSE_SCORE {
+            return pose;
+        }
+        let mut gradient = self.gradient(
+            scoring,
+            &pose.translation,
+            &pose.rotation,
+            rec_nmodes,
+            lig_nmodes,
+        );
+        let mut history: VecDeque<(Vec<f64>, Vec<f64>)> = VecDeque::new();
+        while pose.iterations < self.max_iterations {
+            if dot(&gradient, &gradient).sqrt() < self.tolerance {
+                break;
+            }
+            let mut direction = self.direction(&gradient, &history);
+            if dot(&gradient, &direction) >= 0.0 {
+                // Not a descent direction, start over from the steepest descent
+                history.clear();
+                direction = gradient.iter().map(|value| -value).collect();
+            }
+            self.limit(&mut direction);
+            let slope = dot(&gradient, &direction);
+
+            // Backtracking line search on the negative score
+            let mut step = 1.0;
+            let mut accepted: Option<(Vec<f64>, Quaternion, f64)> = None;
+            for _ in 0..MAX_BACKTRACKS {
+                let trial_translation: Vec<f64> = (0..3)
+                    .map(|k| pose.translation[k] + step * direction[k])
+                    .collect();
+                let rotation_step = [
+                    step * direction[3],
+                    step * direction[4],
+                    step * direction[5],
+                ];
+                let mut trial_rotation =
+                    Quaternion::from_rotation_vector(&rotation_step) * pose.rotation;
+                trial_rotation.normalize();
+                let trial_scoring =
+                    scoring.energy(&trial_translation, &trial_rotation, rec_nmodes, lig_nmodes);
+                if trial_scoring != REJECTED_POSE_SCORE
+                    && -trial_scoring <= -pose.scoring + ARMIJO * step * slope
+                {
+                    accepted = Some((trial_translation, trial_rotation, trial_scoring));
+                    break;
+                }
+                step *= 0.5;
+            }
+            let (trial_translation, trial_rotation, trial_scoring) = match accepted {
+                Some(trial) => trial,
+                None => break,
+            };
+            pose.translation = trial_translation;
+            pose.rotation = trial_rotation;
+            pose.scoring = trial_scoring;
+            pose.iterations += 1;
+
+            let new_gradient = self.gradient(
+                scoring,
+                &pose.translation,
+                &pose.rotation,
+                rec_nmodes,
+                lig_nmodes,
+            );
+            let s: Vec<f64> = direction.iter().map(|value| step * value).collect();
+            let y: Vec<f64> = new_gradient
+                .iter()
+                .zip(gradient.iter())
+                .map(|(new, old)| new - old)
+                .collect();
+            // Only curvature information keeping the approximation positive definite
+            if dot(&s, &y) > 1e-10 {
+                history.push_back((s, y));
+                if history.len() > self.memory {
+                    history.pop_front();
+                }
+            }
+            gradient = new_gradient;
+        }
+        pose
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Best pose at (1, -2, 3) with the x axis rotated onto the y axis
+    struct Funnel;
+
+    impl Score for Funnel {
+        fn energy(&self, translation: &[f64], rotation: &Quaternion, _: &[f64], _: &[f64]) -> f64 {
+            let axis = rotation.rotate_point(&[1.0, 0.0, 0.0]);
+            -(translation[0] - 1.0).powi(2)
+                - 2.0 * (translation[1] + 2.0).powi(2)
+                - (translation[2] - 3.0).powi(2)
+                + 5.0 * axis[1]
+        }
+    }
+
+    #[test]
+    fn test_lbfgs_refinement() {
+        let minimizer = LBFGS::new(5, 100, 1e-5, 0.5);
+        let start = Quaternion::from_rotation_vector(&[0.0, 0.0, 0.5]);
+        let initial = Funnel.energy(&[0.0, 0.0, 0.0], &start, &[], &[]);
+        let refined = minimizer.refine(&Funnel, &[0.0, 0.0, 0.0], &start, &[], &[]);
+        assert!(refined.iterations > 0);
+        assert!(refined.scoring > initial);
+        assert!((refined.scoring - 5.0).abs() < 1e-6);
+        for (value, expected) in refined.translation.iter().zip([1.0, -2.0, 3.0]) {
+            assert!((value - expected).abs() < 1e-3);
+        }
+        let axis = refined.rotation.rotate_point(&[1.0, 0.0, 0.0]);
+        assert!((axis[1] - 1.0).abs() < 1e-6);
+
+        // Rejected poses are left as they are
+        struct Rejected;
+        impl Score for Rejected {
+            fn energy(&self, _: &[f64], _: &Quaternion, _: &[f64], _: &[f64]) -> f64 {
+                REJECTED_POSE_SCORE
+            }
+        }
+        let refined = minimizer.refine(&Rejected, &[0.0, 0.0, 0.0], &start, &[], &[]);
+        assert_eq!(refined.iterations, 0);
+        assert_eq!(refined.translation, vec![0.0, 0.0, 0.0]);
+    }
+}
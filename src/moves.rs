@@ -0,0 +1,40 @@
+use super::glowworm::Glowworm;
+use super::qt::Quaternion;
+use super::ternary::LigandPose;
+
+// Pose of a glowworm before the movement phase, the one its followers move towards
+#[derive(Debug, Clone, Default)]
+pub struct GlowwormPose {
+    pub translation: Vec<f64>,
+    pub rotation: Quaternion,
+    pub rec_nmodes: Vec<f64>,
+    pub lig_nmodes: Vec<f64>,
+    pub second_ligand: Option<LigandPose>,
+}
+
+// Rule moving a glowworm towards the pose of the neighbor it follows. It is applied once per
+// movement sub-step, the swarm takes care of the noise, symmetry and corrupted poses afterwards.
+// Implementations must set glowworm.moved, glowworms following themselves are not moved.
+pub trait MoveOperator {
+    fn move_towards(&self, glowworm: &mut Glowworm, neighbor_id: u32, neighbor: &GlowwormPose);
+}
+
+// Default rule of the GSO: a fixed step of the translation and normal modes towards the ones of
+// the neighbor and a spherical interpolation of the rotation
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Interpolation;
+
+impl MoveOperator for Interpolation {
+    fn move_towards(&self, glowworm: &mut Glowworm, neighbor_id: u32, neighbor: &GlowwormPose) {
+        glowworm.move_towards(
+            neighbor_id,
+            &neighbor.translation,
+            &neighbor.rotation,
+            &neighbor.rec_nmodes,
+            &neighbor.lig_nmodes,
+        );
+        if let (true, Some(second_ligand)) = (glowworm.moved, &neighbor.second_ligand) {
+            glowworm.move_second_ligand_towards(second_ligand);
+        }
+    }
+}
@@ -0,0 +1,559 @@
+use memmap2::Mmap;
+use npyz::{DType, NpyFile, Order};
+use pdbtbx::PDB;
+use std::collections::HashMap;
+use std::error::Error;
+use std::fs;
+use std::ops::Deref;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, Weak};
+
+// Normal modes files written by the Python setup are NumPy arrays of shape
+// (num_modes, num_atoms, 3), or flattened versions of them. Only the total number of values of a
+// flattened array can be checked, the dimensions of the others are checked against the expected
+// layout so a transposed array is not read as garbage deformations. The files are memory mapped
+// once per process and their values shared by all the swarms run in its threads.
+//
+// ProDy also writes normal modes as NMD text files, usually computed on the CA atoms only. Their
+// mode vectors are given to every atom of the structure from the atom of its residue, its CA if
+// there is one, like the Python setup does when writing the NumPy arrays.
+
+// Where and how the values of a NumPy file of normal modes are stored
+#[derive(Debug, Clone, Copy)]
+struct NpyLayout {
+    offset: usize,
+    big_endian: bool,
+    num_values: usize,
+    num_atoms: usize,
+}
+
+// Layout of the values of the array after checking its header and the size of the file, so a
+// file truncated while being copied is not read as a shorter array
+fn npy_layout(bytes: &[u8], num_modes: usize) -> Result<NpyLayout, Box<dyn Error>> {
+    let reader = NpyFile::new(bytes)?;
+    let shape: Vec<usize> = reader.shape().iter().map(|&n| n as usize).collect();
+    let expected = "(num_modes, num_atoms, 3)";
+    if shape.len() > 1 && reader.order() == Order::Fortran {
+        return Err(format!(
+            "array of shape {:?} is stored in Fortran order, expected C order {}",
+            shape, expected
+        )
+        .into());
+    }
+    let big_endian = match reader.dtype() {
+        DType::Plain(type_str) if type_str.to_string() == "<f8" => false,
+        DType::Plain(type_str) if type_str.to_string() == ">f8" => true,
+        dtype => {
+            return Err(format!("array values are {}, not 64-bit floats", dtype.descr()).into())
+        }
+    };
+    let num_values: usize = shape.iter().product();
+    let num_atoms = match shape[..] {
+        [length] => {
+            if num_modes == 0 || length % (num_modes * 3) != 0 {
+                return Err(format!(
+                    "flattened array of {} values is not {} modes of 3 coordinates per atom",
+                    length, num_modes
+                )
+                .into());
+            }
+            length / (num_modes * 3)
+        }
+        [modes, length] => {
+            if modes != num_modes || length % 3 != 0 {
+                return Err(format!(
+                    "array of shape ({}, {}) is not (num_modes, num_atoms * 3) with {} modes",
+                    modes, length, num_modes
+                )
+                .into());
+            }
+            length / 3
+        }
+        [modes, atoms, 3] if modes == num_modes => atoms,
+        [modes, atoms, coordinates] => {
+            let problem = if shape.contains(&num_modes) && shape.contains(&3) {
+                String::from("looks transposed")
+            } else if coordinates == 3 {
+                format!("has {} modes", modes)
+            } else {
+                format!("has {} coordinates per atom", coordinates)
+            };
+            return Err(format!(
+                "array of shape ({}, {}, {}) {}, expected {} with {} modes",
+                modes, atoms, coordinates, problem, expected, num_modes
+            )
+            .into());
+        }
+        _ => {
+            return Err(format!("array of shape {:?}, expected {}", shape, expected).into());
+        }
+    };
+    // Magic string and version, then the length of the header in 2 bytes for version 1 files
+    // and in 4 bytes for the later ones
+    let offset = if bytes[6] == 1 {
+        10 + u16::from_le_bytes([bytes[8], bytes[9]]) as usize
+    } else {
+        12 + u32::from_le_bytes([bytes[8], bytes[9], bytes[10], bytes[11]]) as usize
+    };
+    if bytes.len() != offset + num_values * 8 {
+        return Err(format!(
+            "array has {} bytes of values, its shape {:?} says {}",
+            bytes.len().saturating_sub(offset),
+            shape,
+            num_values * 8
+        )
+        .into());
+    }
+    Ok(NpyLayout {
+        offset,
+        big_endian,
+        num_values,
+        num_atoms,
+    })
+}
+
+// Values decoded one by one from the bytes of the file
+fn npy_values<'a>(bytes: &'a [u8], layout: &NpyLayout) -> impl Iterator<Item = f64> + 'a {
+    let big_endian = layout.big_endian;
+    bytes[layout.offset..].chunks_exact(8).map(move |value| {
+        let value: [u8; 8] = value.try_into().unwrap();
+        if big_endian {
+            f64::from_be_bytes(value)
+        } else {
+            f64::from_le_bytes(value)
+        }
+    })
+}
+
+// Normal modes as (num_modes, num_atoms, 3) values and their number of atoms
+pub fn parse_npy_nmodes(
+    bytes: &[u8],
+    num_modes: usize,
+) -> Result<(Vec<f64>, usize), Box<dyn Error>> {
+    let layout = npy_layout(bytes, num_modes)?;
+    let mut values: Vec<f64> = Vec::with_capacity(layout.num_values);
+    values.extend(npy_values(bytes, &layout));
+    Ok((values, layout.num_atoms))
+}
+
+// NumPy file of normal modes mapped in memory. Its pages are read from the page cache shared by
+// all the processes mapping the file, the values are read in place when they are stored in the
+// byte order of the machine, as the Python setup writes them, and only decoded into a copy
+// otherwise.
+#[derive(Debug)]
+pub struct MappedNmodes {
+    map: Mmap,
+    layout: NpyLayout,
+    // Values in the other byte order or not aligned in the file
+    decoded: Option<Vec<f64>>,
+}
+
+impl MappedNmodes {
+    pub fn open(path: &str, num_modes: usize) -> Result<MappedNmodes, Box<dyn Error>> {
+        let file = fs::File::open(path)?;
+        // SAFETY: the map is only read, through slices that do not outlive it. Truncating or
+        // writing the file while it is mapped would change the values under those slices or fault
+        // on the missing pages: normal modes files are written by the setup before the
+        // simulation starts and lightdock-rust never opens them for writing, so the file is not
+        // modified for as long as it is mapped, like the structures of the setup.
+        let map = unsafe { Mmap::map(&file)? };
+        let layout = npy_layout(&map[..], num_modes)?;
+        let in_place = layout.big_endian == cfg!(target_endian = "big")
+            && map[layout.offset..]
+                .as_ptr()
+                .align_offset(std::mem::align_of::<f64>())
+                == 0;
+        let decoded = if in_place {
+            None
+        } else {
+            Some(npy_values(&map[..], &layout).collect())
+        };
+        Ok(MappedNmodes {
+            map,
+            layout,
+            decoded,
+        })
+    }
+
+    pub fn num_atoms(&self) -> usize {
+        self.layout.num_atoms
+    }
+
+    pub fn values(&self) -> &[f64] {
+        match &self.decoded {
+            Some(values) => values,
+            // SAFETY: the values start at an address aligned for f64, are stored in the byte
+            // order of the machine and npy_layout checked that the map holds num_values of them
+            // after the offset. Any bit pattern is a valid f64, and the slice borrows self so it
+            // does not outlive the map.
+            None => unsafe {
+                std::slice::from_raw_parts(
+                    self.map[self.layout.offset..].as_ptr() as *const f64,
+                    self.layout.num_values,
+                )
+            },
+        }
+    }
+}
+
+// Normal modes of a partner as (num_modes, num_atoms, 3) values, read in place from a mapped
+// NumPy file or owned when computed. Clones share the values, so the scoring functions of all the
+// swarms of a process read the same ones instead of a copy each.
+#[derive(Debug, Clone)]
+pub enum Nmodes {
+    Owned(Arc<[f64]>),
+    Mapped(Arc<MappedNmodes>),
+}
+
+impl Default for Nmodes {
+    fn default() -> Self {
+        Nmodes::Owned(Arc::from(Vec::new()))
+    }
+}
+
+impl From<Vec<f64>> for Nmodes {
+    fn from(values: Vec<f64>) -> Self {
+        Nmodes::Owned(Arc::from(values))
+    }
+}
+
+impl Deref for Nmodes {
+    type Target = [f64];
+
+    fn deref(&self) -> &[f64] {
+        match self {
+            Nmodes::Owned(values) => values,
+            Nmodes::Mapped(mapped) => mapped.values(),
+        }
+    }
+}
+
+lazy_static! {
+    // Files mapped in this process by their path and number of modes, shared by the swarms run in
+    // its threads so every file is mapped and checked once. The map is released with the last
+    // scoring function using it.
+    static ref MAPPED_NMODES: Mutex<HashMap<(PathBuf, usize), Weak<MappedNmodes>>> =
+        Mutex::new(HashMap::new());
+}
+
+pub fn map_npy_nmodes(path: &str, num_modes: usize) -> Result<Arc<MappedNmodes>, Box<dyn Error>> {
+    let key = (fs::canonicalize(path)?, num_modes);
+    let mut mapped = MAPPED_NMODES.lock().unwrap();
+    if let Some(nmodes) = mapped.get(&key).and_then(Weak::upgrade) {
+        return Ok(nmodes);
+    }
+    mapped.retain(|_, nmodes| nmodes.strong_count() > 0);
+    let nmodes = Arc::new(MappedNmodes::open(path, num_modes)?);
+    mapped.insert(key, Arc::downgrade(&nmodes));
+    Ok(nmodes)
+}
+
+pub fn read_npy_nmodes(path: &str, num_modes: usize) -> Result<(Nmodes, usize), Box<dyn Error>> {
+    let nmodes = map_npy_nmodes(path, num_modes)?;
+    let num_atoms = nmodes.num_atoms();
+    Ok((Nmodes::Mapped(nmodes), num_atoms))
+}
+
+// Atoms and mode vectors of a ProDy NMD file
+#[derive(Debug, Clone, Default)]
+pub struct NmdFile {
+    pub atom_names: Vec<String>,
+    pub chain_ids: Vec<String>,
+    pub resids: Vec<isize>,
+    pub num_atoms: usize,
+    // Unscaled mode vectors of num_atoms * 3 values
+    pub modes: Vec<Vec<f64>>,
+}
+
+pub fn parse_nmd(contents: &str) -> Result<NmdFile, Box<dyn Error>> {
+    let mut nmd = NmdFile::default();
+    let mut mode_lines: Vec<Vec<&str>> = Vec::new();
+    for line in contents.lines() {
+        let mut fields = line.split_whitespace();
+        let keyword = match fields.next() {
+            Some(keyword) => keyword,
+            None => continue,
+        };
+        let values: Vec<&str> = fields.collect();
+        match keyword {
+            "atomnames" => nmd.atom_names = values.iter().map(|v| v.to_string()).collect(),
+            "chainids" => nmd.chain_ids = values.iter().map(|v| v.to_string()).collect(),
+            "resids" => {
+                nmd.resids = match values.iter().map(|v| v.parse::<isize>()).collect() {
+                    Ok(resids) => resids,
+                    Err(e) => return Err(format!("wrong residue number in resids: {}", e).into()),
+                }
+            }
+            "coordinates" => {
+                if !values.len().is_multiple_of(3) {
+                    return Err(format!("{} coordinates are not 3 per atom", values.len()).into());
+                }
+                nmd.num_atoms = values.len() / 3;
+            }
+            "mode" => mode_lines.push(values),
+            _ => (),
+        }
+    }
+    if nmd.num_atoms == 0 {
+        return Err("no atom coordinates found".into());
+    }
+    let length = nmd.num_atoms * 3;
+    for (index, values) in mode_lines.iter().enumerate() {
+        // Mode lines may start with the mode index and its scaling factor
+        if values.len() < length || values.len() > length + 2 {
+            return Err(format!(
+                "mode {} has {} values, expected {} for {} atoms",
+                index + 1,
+                values.len(),
+                length,
+                nmd.num_atoms
+            )
+            .into());
+        }
+        let vector: Result<Vec<f64>, _> = values[values.len() - length..]
+            .iter()
+            .map(|v| v.parse::<f64>())
+            .collect();
+        match vector {
+            Ok(vector) => nmd.modes.push(vector),
+            Err(e) => return Err(format!("wrong value in mode {}: {}", index + 1, e).into()),
+        }
+    }
+    for (name, len) in [
+        ("atomnames", nmd.atom_names.len()),
+        ("chainids", nmd.chain_ids.len()),
+        ("resids", nmd.resids.len()),
+    ] {
+        if len != 0 && len != nmd.num_atoms {
+            return Err(format!("{} has {} values for {} atoms", name, len, nmd.num_atoms).into());
+        }
+    }
+    Ok(nmd)
+}
+
+// Normal modes of the structure as (num_modes, num_atoms, 3) from the first modes of the NMD
+// file. Membrane beads do not move.
+pub fn nmd_nmodes(
+    nmd: &NmdFile,
+    structure: &PDB,
+    num_modes: usize,
+) -> Result<Vec<f64>, Box<dyn Error>> {
+    if nmd.modes.len() < num_modes {
+        return Err(format!(
+            "file has {} modes, {} requested",
+            nmd.modes.len(),
+            num_modes
+        )
+        .into());
+    }
+    // Atom of the file giving the vectors of every atom, the same one if the file is all-atom
+    let indexes: Vec<Option<usize>> = if nmd.num_atoms == structure.atom_count() {
+        (0..nmd.num_atoms).map(Some).collect()
+    } else {
+        if nmd.chain_ids.is_empty() || nmd.resids.is_empty() {
+            return Err(format!(
+                "file has {} atoms without chainids and resids to map them to the {} atoms of the structure",
+                nmd.num_atoms,
+                structure.atom_count()
+            )
+            .into());
+        }
+        let mut residues: HashMap<(&str, isize), usize> = HashMap::new();
+        for index in 0..nmd.num_atoms {
+            let key = (nmd.chain_ids[index].as_str(), nmd.resids[index]);
+            let is_ca = nmd.atom_names.get(index).is_some_and(|name| name == "CA");
+            if is_ca || !residues.contains_key(&key) {
+                residues.insert(key, index);
+            }
+        }
+        let mut indexes: Vec<Option<usize>> = Vec::with_capacity(structure.atom_count());
+        for chain in structure.chains() {
+            for residue in chain.residues() {
+                let index = if residue.name() == Some("MMB") {
+                    None
+                } else {
+                    match residues.get(&(chain.id(), residue.serial_number())) {
+                        Some(index) => Some(*index),
+                        None => {
+                            return Err(format!(
+                                "residue {}.{}.{} not found in the file",
+                                chain.id(),
+                                residue.name().unwrap_or(""),
+                                residue.serial_number()
+                            )
+                            .into())
+                        }
+                    }
+                };
+                indexes.extend(std::iter::repeat_n(index, residue.atom_count()));
+            }
+        }
+        indexes
+    };
+    let mut nmodes: Vec<f64> = Vec::with_capacity(num_modes * indexes.len() * 3);
+    for mode in nmd.modes.iter().take(num_modes) {
+        for index in indexes.iter() {
+            match index {
+                Some(index) => nmodes.extend_from_slice(&mode[index * 3..index * 3 + 3]),
+                None => nmodes.extend_from_slice(&[0.0, 0.0, 0.0]),
+            }
+        }
+    }
+    Ok(nmodes)
+}
+
+// The NumPy file, or the NMD one if it is the only one found
+pub fn nmodes_path<'a>(npy_path: &'a str, nmd_path: &'a str) -> &'a str {
+    if !Path::new(npy_path).exists() && Path::new(nmd_path).exists() {
+        nmd_path
+    } else {
+        npy_path
+    }
+}
+
+// Normal modes of the structure read from a NumPy or an NMD file, and their number of atoms
+pub fn read_nmodes_file(
+    path: &str,
+    structure: &PDB,
+    num_modes: usize,
+) -> Result<(Nmodes, usize), Box<dyn Error>> {
+    if path.ends_with(".nmd") {
+        let nmd = parse_nmd(&fs::read_to_string(path)?)?;
+        let nmodes = nmd_nmodes(&nmd, structure, num_modes)?;
+        Ok((nmodes.into(), structure.atom_count()))
+    } else {
+        read_npy_nmodes(path, num_modes)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // NumPy file with a header describing the given shape and values 0, 1, 2...
+    fn npy(descr: &str, fortran_order: bool, shape: &str, num_values: usize) -> Vec<u8> {
+        let mut header = format!(
+            "{{'descr': '{}', 'fortran_order': {}, 'shape': {}, }}",
+            descr,
+            if fortran_order { "True" } else { "False" },
+            shape
+        );
+        while (10 + header.len() + 1) % 64 != 0 {
+            header.push(' ');
+        }
+        header.push('\n');
+        let mut bytes: Vec<u8> = b"\x93NUMPY\x01\x00".to_vec();
+        bytes.extend_from_slice(&(header.len() as u16).to_le_bytes());
+        bytes.extend_from_slice(header.as_bytes());
+        for i in 0..num_values {
+            if descr == "<f8" {
+                bytes.extend_from_slice(&(i as f64).to_le_bytes());
+            } else {
+                bytes.extend_from_slice(&(i as f32).to_le_bytes());
+            }
+        }
+        bytes
+    }
+
+    #[test]
+    fn test_npy_shape() {
+        let (values, num_atoms) = parse_npy_nmodes(&npy("<f8", false, "(2, 4, 3)", 24), 2).unwrap();
+        assert_eq!(num_atoms, 4);
+        assert_eq!(values[23], 23.0);
+        let (_, num_atoms) = parse_npy_nmodes(&npy("<f8", false, "(24,)", 24), 2).unwrap();
+        assert_eq!(num_atoms, 4);
+        let (_, num_atoms) = parse_npy_nmodes(&npy("<f8", false, "(2, 12)", 24), 2).unwrap();
+        assert_eq!(num_atoms, 4);
+
+        let error = |bytes: Vec<u8>| parse_npy_nmodes(&bytes, 2).unwrap_err().to_string();
+        assert!(error(npy("<f8", false, "(4, 2, 3)", 24)).contains("looks transposed"));
+        assert!(error(npy("<f8", false, "(4, 3, 2)", 24)).contains("looks transposed"));
+        assert!(error(npy("<f8", false, "(3, 4, 3)", 36)).contains("has 3 modes"));
+        assert!(error(npy("<f8", true, "(2, 4, 3)", 24)).contains("Fortran order"));
+        assert!(error(npy("<f8", false, "(25,)", 25)).contains("flattened"));
+        assert!(error(npy("<f4", false, "(2, 4, 3)", 24)).contains("64-bit floats"));
+    }
+
+    #[test]
+    fn test_mapped_npy() {
+        let directory = std::env::temp_dir().join("lightdock_test_mapped_npy");
+        fs::create_dir_all(&directory).unwrap();
+        let path = directory.join("rec_nm.npy");
+        let path = path.to_str().unwrap();
+        let bytes = npy("<f8", false, "(2, 4, 3)", 24);
+        fs::write(path, &bytes).unwrap();
+
+        let mapped = MappedNmodes::open(path, 2).unwrap();
+        assert_eq!(mapped.num_atoms(), 4);
+        assert_eq!(mapped.values(), &parse_npy_nmodes(&bytes, 2).unwrap().0[..]);
+        // Mapped once for all the swarms of the process
+        let shared = map_npy_nmodes(path, 2).unwrap();
+        assert!(Arc::ptr_eq(&shared, &map_npy_nmodes(path, 2).unwrap()));
+        let (nmodes, _) = read_npy_nmodes(path, 2).unwrap();
+        assert_eq!(nmodes[23], 23.0);
+        // The values are read in place, not copied
+        assert_eq!(nmodes.as_ptr(), shared.values().as_ptr());
+        assert_eq!(nmodes.clone().as_ptr(), nmodes.as_ptr());
+        // And the map released with its last user
+        let key = (fs::canonicalize(path).unwrap(), 2);
+        drop((mapped, shared, nmodes));
+        assert!(MAPPED_NMODES.lock().unwrap()[&key].upgrade().is_none());
+
+        // Big endian values are decoded as well
+        let mut big_endian = npy(">f8", false, "(2, 4, 3)", 0);
+        for i in 0..24 {
+            big_endian.extend_from_slice(&(i as f64).to_be_bytes());
+        }
+        assert_eq!(parse_npy_nmodes(&big_endian, 2).unwrap().0[5], 5.0);
+        let big_endian_path = directory.join("lig_nm.npy");
+        fs::write(&big_endian_path, &big_endian).unwrap();
+        let mapped = MappedNmodes::open(big_endian_path.to_str().unwrap(), 2).unwrap();
+        assert_eq!(mapped.values()[5], 5.0);
+
+        // A truncated file is not read as a shorter array
+        fs::write(path, &bytes[..bytes.len() - 8]).unwrap();
+        let error = MappedNmodes::open(path, 2).unwrap_err().to_string();
+        fs::remove_dir_all(&directory).unwrap();
+        assert!(error.contains("184 bytes of values"));
+    }
+
+    #[test]
+    fn test_nmd_nmodes() {
+        let structure = crate::structure::parse_structure(
+            "\
+ATOM      1  N   ALA A   1       0.000   0.000   0.000  1.00  0.00           N\n\
+ATOM      2  CA  ALA A   1       1.000   0.000   0.000  1.00  0.00           C\n\
+ATOM      3  CA  GLY A   2       2.000   0.000   0.000  1.00  0.00           C\n",
+            1,
+        )
+        .unwrap()
+        .unwrap();
+        // CA atoms only, the first mode with index and scale, the second one without
+        let contents = "nmwiz_load test.nmd
+name test
+atomnames CA CA
+resnames ALA GLY
+resids 1 2
+chainids A A
+coordinates 1.000 0.000 0.000 2.000 0.000 0.000
+mode 1 2.50 0.1 0.2 0.3 0.4 0.5 0.6
+mode 0.7 0.8 0.9 1.0 1.1 1.2
+";
+        let nmd = parse_nmd(contents).unwrap();
+        assert_eq!(nmd.num_atoms, 2);
+        assert_eq!(nmd.modes.len(), 2);
+        let nmodes = nmd_nmodes(&nmd, &structure, 1).unwrap();
+        assert_eq!(nmodes, vec![0.1, 0.2, 0.3, 0.1, 0.2, 0.3, 0.4, 0.5, 0.6]);
+        assert_eq!(
+            nmd_nmodes(&nmd, &structure, 2).unwrap()[9..12],
+            [0.7, 0.8, 0.9]
+        );
+        let error = nmd_nmodes(&nmd, &structure, 3).unwrap_err().to_string();
+        assert!(error.contains("2 modes, 3 requested"));
+
+        let error = parse_nmd("coordinates 0 0 0\nmode 1 2")
+            .unwrap_err()
+            .to_string();
+        assert!(error.contains("mode 1 has 2 values"));
+    }
+}
@@ -0,0 +1,747 @@
+use super::constants::{GSO_OUTPUT_VERSION, OUTPUT_QUEUE_SIZE};
+use super::qt::Quaternion;
+use super::swarm::Swarm;
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use log::warn;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::error::Error;
+use std::fs;
+use std::fs::File;
+use std::io;
+use std::io::{BufWriter, Read, Write};
+use std::net::TcpStream;
+#[cfg(unix)]
+use std::os::unix::net::UnixStream;
+use std::path::Path;
+use std::sync::mpsc::{sync_channel, SyncSender};
+use std::thread;
+
+// The gso_*.out format, written with the version and metadata lines if versioned_output is set:
+//
+//   #Version: 3
+//   #Step: 10
+//   #Glowworms: 200
+//   #Coordinates  RecID  LigID  Luciferin  Neighbor's number  Vision Range  Scoring  Z-score  Stagnation
+//   (x, y, z, qw, qx, qy, qz[, rec_nmodes...][, lig_nmodes...])    0    0   ...
+//
+// Stagnation is the number of consecutive steps the glowworm has neither moved nor improved its
+// luciferin, version 2 outputs lack it. Orientations can also be written after the last column as
+// a rotation matrix (r11 r12 r13 r21 ... r33, row by row) or as a unit axis and an angle in
+// degrees (x y z angle), see RotationOutput. These columns are ignored when reading.
+//
+// Header lines start with '#', metadata is given as "#Key: value". Outputs written without
+// versioned_output keep the legacy header, only the column names up to the Z-score, and have no
+// stagnation column. They are read as version 1, like older files also lacking the Z-score.
+// Outputs may be gzip compressed (gso_*.out.gz).
+
+// gzip magic number
+const GZIP_MAGIC: [u8; 2] = [0x1f, 0x8b];
+
+// Orientation columns added to the GSO output besides the quaternion
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RotationOutput {
+    Matrix,
+    AxisAngle,
+}
+
+pub const ROTATION_OUTPUTS: [RotationOutput; 2] =
+    [RotationOutput::Matrix, RotationOutput::AxisAngle];
+
+impl RotationOutput {
+    pub fn name(&self) -> &'static str {
+        match self {
+            RotationOutput::Matrix => "matrix",
+            RotationOutput::AxisAngle => "axis_angle",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<RotationOutput> {
+        ROTATION_OUTPUTS
+            .into_iter()
+            .find(|output| output.name() == name.to_lowercase())
+    }
+
+    // Header of the columns, after the ones of GSO_OUTPUT_COLUMNS
+    pub fn columns(&self) -> &'static str {
+        match self {
+            RotationOutput::Matrix => "Rotation matrix",
+            RotationOutput::AxisAngle => "Rotation axis  Angle",
+        }
+    }
+
+    pub fn write<W: Write + ?Sized>(
+        &self,
+        rotation: &Quaternion,
+        output: &mut W,
+    ) -> io::Result<()> {
+        match self {
+            RotationOutput::Matrix => {
+                for row in rotation.to_rotation_matrix() {
+                    for value in row {
+                        write!(output, " {:.7}", value)?;
+                    }
+                }
+            }
+            RotationOutput::AxisAngle => {
+                let (axis, angle) = rotation.to_axis_angle();
+                write!(
+                    output,
+                    " {:.7} {:.7} {:.7} {:.5}",
+                    axis[0],
+                    axis[1],
+                    axis[2],
+                    angle.to_degrees()
+                )?;
+            }
+        }
+        Ok(())
+    }
+}
+
+pub fn gso_output_path(output_directory: &str, step: u32, compress: bool) -> String {
+    if compress {
+        format!("{}/gso_{}.out.gz", output_directory, step)
+    } else {
+        format!("{}/gso_{}.out", output_directory, step)
+    }
+}
+
+// Poses of a run after the local refinement of the best ones
+pub fn refined_output_path(output_directory: &str, compress: bool) -> String {
+    if compress {
+        format!("{}/gso_refined.out.gz", output_directory)
+    } else {
+        format!("{}/gso_refined.out", output_directory)
+    }
+}
+
+pub fn write_file(path: &str, contents: &[u8], compress: bool) -> Result<(), io::Error> {
+    let mut output = BufWriter::new(File::create(path)?);
+    if compress {
+        let mut encoder = GzEncoder::new(output, Compression::default());
+        encoder.write_all(contents)?;
+        output = encoder.finish()?;
+    } else {
+        output.write_all(contents)?;
+    }
+    output.flush()
+}
+
+pub trait TrajectoryWriter {
+    fn append(&mut self, step: u32, swarm: &Swarm) -> Result<(), Box<dyn Error>>;
+    fn close(&mut self) -> Result<(), Box<dyn Error>>;
+}
+
+struct OutputJob {
+    path: String,
+    contents: Vec<u8>,
+    compress: bool,
+}
+
+pub struct OutputWriter {
+    sender: Option<SyncSender<OutputJob>>,
+    handle: Option<thread::JoinHandle<Result<(), io::Error>>>,
+}
+
+impl Default for OutputWriter {
+    fn default() -> Self {
+        OutputWriter::new(OUTPUT_QUEUE_SIZE)
+    }
+}
+
+impl OutputWriter {
+    pub fn new(queue_size: usize) -> Self {
+        // Files are compressed and written in a background thread. The queue is bounded, the
+        // optimization only blocks if the writer falls behind by more than queue_size files.
+        let (sender, receiver) = sync_channel::<OutputJob>(queue_size);
+        let handle = thread::spawn(move || {
+            for job in receiver {
+                write_file(&job.path, &job.contents, job.compress)?;
+            }
+            Ok(())
+        });
+        OutputWriter {
+            sender: Some(sender),
+            handle: Some(handle),
+        }
+    }
+
+    pub fn write(
+        &mut self,
+        path: String,
+        contents: Vec<u8>,
+        compress: bool,
+    ) -> Result<(), io::Error> {
+        let job = OutputJob {
+            path,
+            contents,
+            compress,
+        };
+        let sent = match &self.sender {
+            Some(sender) => sender.send(job).is_ok(),
+            None => false,
+        };
+        if sent {
+            Ok(())
+        } else {
+            // The writer thread stopped, report its error
+            self.finish()
+        }
+    }
+
+    pub fn finish(&mut self) -> Result<(), io::Error> {
+        // Wait for all the pending files to be written
+        self.sender = None;
+        match self.handle.take() {
+            Some(handle) => match handle.join() {
+                Ok(result) => result,
+                Err(_) => Err(io::Error::other("output writer thread panicked")),
+            },
+            None => Err(io::Error::other("output writer already finished")),
+        }
+    }
+}
+
+impl Drop for OutputWriter {
+    fn drop(&mut self) {
+        if self.handle.is_some() {
+            if let Err(why) = self.finish() {
+                warn!("Error writing output: {:?}", why);
+            }
+        }
+    }
+}
+
+pub struct GSOEntry {
+    pub position: Vec<f64>,
+    pub rec_id: usize,
+    pub lig_id: usize,
+    pub luciferin: f64,
+    pub num_neighbors: usize,
+    pub vision_range: f64,
+    pub scoring: f64,
+    pub z_score: Option<f64>,
+    pub stagnation: Option<u32>,
+}
+
+impl GSOEntry {
+    pub fn translation(&self) -> [f64; 3] {
+        [self.position[0], self.position[1], self.position[2]]
+    }
+
+    pub fn rotation(&self) -> Quaternion {
+        Quaternion::new(
+            self.position[3],
+            self.position[4],
+            self.position[5],
+            self.position[6],
+        )
+    }
+}
+
+pub struct GSOOutput {
+    pub version: u32,
+    pub metadata: HashMap<String, String>,
+    pub entries: Vec<GSOEntry>,
+}
+
+fn parse_entry(line: &str, version: u32) -> Result<GSOEntry, Box<dyn Error>> {
+    let end = line.find(')').ok_or("missing closing parenthesis")?;
+    let mut position: Vec<f64> = Vec::new();
+    for value in line[1..end].split(',') {
+        position.push(value.trim().parse::<f64>()?);
+    }
+    if position.len() < 7 {
+        return Err(format!("{} coordinates found, at least 7 expected", position.len()).into());
+    }
+
+    let fields: Vec<&str> = line[end + 1..].split_whitespace().collect();
+    if fields.len() < 6 {
+        return Err(format!("{} columns found, at least 6 expected", fields.len()).into());
+    }
+    Ok(GSOEntry {
+        position,
+        rec_id: fields[0].parse::<usize>()?,
+        lig_id: fields[1].parse::<usize>()?,
+        luciferin: fields[2].parse::<f64>()?,
+        num_neighbors: fields[3].parse::<usize>()?,
+        vision_range: fields[4].parse::<f64>()?,
+        scoring: fields[5].parse::<f64>()?,
+        z_score: match fields.get(6) {
+            Some(value) => Some(value.parse::<f64>()?),
+            None => None,
+        },
+        // Older versions may have rotation columns after the Z-score
+        stagnation: match fields.get(7).filter(|_| version >= 3) {
+            Some(value) => Some(value.parse::<u32>()?),
+            None => None,
+        },
+    })
+}
+
+pub fn parse_gso_output(contents: &str) -> Result<GSOOutput, Box<dyn Error>> {
+    let mut output = GSOOutput {
+        version: 1,
+        metadata: HashMap::new(),
+        entries: Vec::new(),
+    };
+    for (i, line) in contents.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+        if let Some(header) = line.strip_prefix('#') {
+            // Unknown header lines are ignored
+            if let Some((key, value)) = header.split_once(':') {
+                let (key, value) = (key.trim(), value.trim());
+                // The version is given before the entries, whose columns depend on it
+                if key == "Version" {
+                    output.version = value
+                        .parse::<u32>()
+                        .map_err(|_| format!("wrong format version {:?}", value))?;
+                    if output.version > GSO_OUTPUT_VERSION {
+                        warn!(
+                            "GSO output format version {} is newer than supported version {}",
+                            output.version, GSO_OUTPUT_VERSION
+                        );
+                    }
+                }
+                output.metadata.insert(key.to_string(), value.to_string());
+            }
+            continue;
+        }
+        if !line.starts_with('(') {
+            warn!("Ignoring line {} in GSO output: {:?}", i + 1, line);
+            continue;
+        }
+        let entry =
+            parse_entry(line, output.version).map_err(|e| format!("line {}: {}", i + 1, e))?;
+        output.entries.push(entry);
+    }
+    Ok(output)
+}
+
+// Saved steps of a swarm and their output files, in order
+pub fn gso_outputs(output_directory: &str) -> io::Result<Vec<(u32, String)>> {
+    let mut outputs: Vec<(u32, String)> = Vec::new();
+    for entry in fs::read_dir(output_directory)?.flatten() {
+        let filename = entry.file_name().to_string_lossy().to_string();
+        let step = filename
+            .strip_prefix("gso_")
+            .and_then(|s| s.strip_suffix(".out").or_else(|| s.strip_suffix(".out.gz")))
+            .and_then(|s| s.parse::<u32>().ok());
+        if let Some(step) = step {
+            outputs.push((step, entry.path().to_string_lossy().to_string()));
+        }
+    }
+    outputs.sort();
+    Ok(outputs)
+}
+
+// swarm_<id> directories of a simulation sorted by swarm ID
+pub fn swarm_directories(simulation_path: &str) -> io::Result<Vec<(u32, String)>> {
+    let path = if simulation_path.is_empty() {
+        "."
+    } else {
+        simulation_path
+    };
+    let mut directories: Vec<(u32, String)> = Vec::new();
+    for entry in fs::read_dir(path)?.flatten() {
+        let filename = entry.file_name().to_string_lossy().to_string();
+        let swarm_id = filename
+            .strip_prefix("swarm_")
+            .and_then(|s| s.parse::<u32>().ok());
+        if let (Some(swarm_id), true) = (swarm_id, entry.path().is_dir()) {
+            directories.push((swarm_id, entry.path().to_string_lossy().to_string()));
+        }
+    }
+    directories.sort();
+    Ok(directories)
+}
+
+pub fn read_gso_output<P: AsRef<Path>>(path: P) -> Result<GSOOutput, Box<dyn Error>> {
+    let bytes = fs::read(path)?;
+    let mut contents = String::new();
+    if bytes.starts_with(&GZIP_MAGIC) {
+        GzDecoder::new(&bytes[..]).read_to_string(&mut contents)?;
+    } else {
+        contents = String::from_utf8(bytes)?;
+    }
+    parse_gso_output(&contents)
+}
+
+// Step events are streamed as newline-delimited JSON, one object per step:
+//
+//   {"step":1,"glowworms":200,"best":{"id":12,...},"min_scoring":...,"mean_scoring":...}
+
+#[derive(Serialize, Debug)]
+pub struct PoseEvent {
+    pub id: u32,
+    pub translation: [f64; 3],
+    pub rotation: [f64; 4],
+    pub rec_nmodes: Vec<f64>,
+    pub lig_nmodes: Vec<f64>,
+    pub luciferin: f64,
+    pub scoring: f64,
+}
+
+#[derive(Serialize, Debug)]
+pub struct StepEvent {
+    pub step: u32,
+    // Swarm of the run, to tell apart the events of many swarms sent to the same monitor
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub swarm: Option<usize>,
+    pub glowworms: usize,
+    pub best: Option<PoseEvent>,
+    pub min_scoring: f64,
+    pub max_scoring: f64,
+    pub mean_scoring: f64,
+    pub mean_luciferin: f64,
+    pub mean_vision_range: f64,
+    pub moved: usize,
+    // Mean distance between glowworms, see Swarm::diversity
+    pub diversity: f64,
+}
+
+impl StepEvent {
+    pub fn new(step: u32, swarm: &Swarm) -> Self {
+        let glowworms = &swarm.glowworms;
+        let n = glowworms.len().max(1) as f64;
+        let scores: Vec<f64> = glowworms.iter().map(|g| g.scoring).collect();
+        let best = glowworms
+            .iter()
+            .max_by(|a, b| a.scoring.total_cmp(&b.scoring))
+            .map(|g| PoseEvent {
+                id: g.id,
+                translation: [g.translation[0], g.translation[1], g.translation[2]],
+                rotation: [g.rotation.w, g.rotation.x, g.rotation.y, g.rotation.z],
+                rec_nmodes: g.rec_nmodes.clone(),
+                lig_nmodes: g.lig_nmodes.clone(),
+                luciferin: g.luciferin,
+                scoring: g.scoring,
+            });
+        StepEvent {
+            step,
+            swarm: None,
+            glowworms: glowworms.len(),
+            min_scoring: scores.iter().copied().reduce(f64::min).unwrap_or(0.0),
+            max_scoring: scores.iter().copied().reduce(f64::max).unwrap_or(0.0),
+            best,
+            mean_scoring: scores.iter().sum::<f64>() / n,
+            mean_luciferin: glowworms.iter().map(|g| g.luciferin).sum::<f64>() / n,
+            mean_vision_range: glowworms.iter().map(|g| g.vision_range).sum::<f64>() / n,
+            moved: glowworms.iter().filter(|g| g.moved).count(),
+            diversity: swarm.diversity(),
+        }
+    }
+
+    pub fn write<W: Write + ?Sized>(&self, output: &mut W) -> Result<(), Box<dyn Error>> {
+        serde_json::to_writer(&mut *output, self)?;
+        writeln!(output)?;
+        output.flush()?;
+        Ok(())
+    }
+}
+
+// Step events can also be sent to a monitoring process listening on a TCP address
+// (tcp://host:port) or a Unix socket (unix:///path/to/socket). A monitor going away must not stop
+// the docking, so the connection is dropped at the first error with a warning and the following
+// events are discarded.
+pub struct EventSocket {
+    address: String,
+    stream: Option<Box<dyn Write>>,
+}
+
+impl EventSocket {
+    pub fn is_address(address: &str) -> bool {
+        address.starts_with("tcp://") || address.starts_with("unix://")
+    }
+
+    pub fn connect(address: &str) -> Result<EventSocket, Box<dyn Error>> {
+        let stream: Box<dyn Write> = if let Some(host) = address.strip_prefix("tcp://") {
+            let stream = TcpStream::connect(host)?;
+            stream.set_nodelay(true)?;
+            Box::new(stream)
+        } else if let Some(path) = address.strip_prefix("unix://") {
+            #[cfg(unix)]
+            {
+                Box::new(UnixStream::connect(path)?)
+            }
+            #[cfg(not(unix))]
+            {
+                return Err(format!("Unix sockets not supported [{:?}]", path).into());
+            }
+        } else {
+            return Err(format!(
+                "wrong address {:?}, expected tcp://host:port or unix://path",
+                address
+            )
+            .into());
+        };
+        Ok(EventSocket {
+            address: address.to_string(),
+            stream: Some(stream),
+        })
+    }
+
+    fn disconnect(&mut self, error: io::Error) {
+        warn!(
+            "Event socket {} closed ({}), step events are discarded",
+            self.address, error
+        );
+        self.stream = None;
+    }
+}
+
+impl Write for EventSocket {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if let Some(stream) = self.stream.as_mut() {
+            if let Err(e) = stream.write_all(buf) {
+                self.disconnect(e);
+            }
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        if let Some(stream) = self.stream.as_mut() {
+            if let Err(e) = stream.flush() {
+                self.disconnect(e);
+            }
+        }
+        Ok(())
+    }
+}
+
+// Convergence of a run, a CSV row per step whatever the steps saved: best and mean scoring of
+// the swarm and the fraction of restraints satisfied by the best pose, empty without restraints
+//
+//   step,best,mean,restraints
+//   1,17.30515290,3.12402918,0.500
+
+pub const CONVERGENCE_HEADER: &str = "step,best,mean,restraints";
+
+#[derive(Debug)]
+pub struct ConvergenceRow {
+    pub step: u32,
+    pub best: f64,
+    pub mean: f64,
+    pub restraints: Option<f64>,
+}
+
+impl ConvergenceRow {
+    pub fn new(step: u32, swarm: &Swarm) -> Self {
+        let glowworms = &swarm.glowworms;
+        let n = glowworms.len().max(1) as f64;
+        let best = glowworms
+            .iter()
+            .max_by(|a, b| a.scoring.total_cmp(&b.scoring));
+        ConvergenceRow {
+            step,
+            best: best.map_or(0.0, |g| g.scoring),
+            mean: glowworms.iter().map(|g| g.scoring).sum::<f64>() / n,
+            restraints: best.and_then(|g| {
+                g.scoring_function.restraints_satisfaction(
+                    &g.translation,
+                    &g.rotation,
+                    &g.rec_nmodes,
+                    &g.lig_nmodes,
+                )
+            }),
+        }
+    }
+
+    pub fn write<W: Write + ?Sized>(&self, output: &mut W) -> Result<(), Box<dyn Error>> {
+        let restraints = self
+            .restraints
+            .map_or(String::new(), |restraints| format!("{:.3}", restraints));
+        writeln!(
+            output,
+            "{},{:.8},{:.8},{}",
+            self.step, self.best, self.mean, restraints
+        )?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_legacy_output() {
+        let contents =
+            "#Coordinates  RecID  LigID  Luciferin  Neighbor's number  Vision Range  Scoring\n\
+            (1.0, 2.0, 3.0, 1.0, 0.0, 0.0, 0.0, 0.5)    0    0   5.10000000  2 3.560 -7.41740250\n";
+        let output = parse_gso_output(contents).unwrap();
+        assert_eq!(output.version, 1);
+        assert_eq!(output.entries.len(), 1);
+        let entry = &output.entries[0];
+        assert_eq!(entry.translation(), [1.0, 2.0, 3.0]);
+        assert_eq!(entry.rotation(), Quaternion::default());
+        assert_eq!(entry.position.len(), 8);
+        assert_eq!(entry.num_neighbors, 2);
+        assert_eq!(entry.scoring, -7.4174025);
+        assert_eq!(entry.z_score, None);
+    }
+
+    #[test]
+    fn test_parse_versioned_output() {
+        let contents = "#Version: 2\n#Step: 10\n#Glowworms: 2\n\
+            #Coordinates  RecID  LigID  Luciferin  Neighbor's number  Vision Range  Scoring  Z-score\n\
+            (1.0, 2.0, 3.0, 1.0, 0.0, 0.0, 0.0)    0    1   5.10000000  0 0.200 2.00000000 1.00000\n\
+            \n\
+            (1.0, 2.0, 3.0, 1.0, 0.0, 0.0, 0.0)    0    1   5.10000000  0 0.200 -2.00000000 -1.00000\n";
+        let output = parse_gso_output(contents).unwrap();
+        assert_eq!(output.version, 2);
+        assert_eq!(output.metadata["Step"], "10");
+        assert_eq!(output.entries.len(), 2);
+        assert_eq!(output.entries[0].lig_id, 1);
+        assert_eq!(output.entries[1].z_score, Some(-1.0));
+        // Rotation columns of version 2 outputs are not read as the stagnation
+        assert_eq!(output.entries[1].stagnation, None);
+
+        let contents = "#Version: 3\n\
+            #Coordinates  RecID  LigID  Luciferin  Neighbor's number  Vision Range  Scoring  Z-score  Stagnation\n\
+            (1.0, 2.0, 3.0, 1.0, 0.0, 0.0, 0.0)    0    1   5.10000000  0 0.200 2.00000000 1.00000 7\n";
+        let output = parse_gso_output(contents).unwrap();
+        assert_eq!(output.version, 3);
+        assert_eq!(output.entries[0].z_score, Some(1.0));
+        assert_eq!(output.entries[0].stagnation, Some(7));
+    }
+
+    #[test]
+    fn test_read_compressed_output() {
+        use flate2::write::GzEncoder;
+        use flate2::Compression;
+        use std::io::Write;
+
+        let contents = "#Version: 2\n\
+            (1.0, 2.0, 3.0, 1.0, 0.0, 0.0, 0.0)    0    0   5.10000000  0 0.200 2.00000000 0.00000\n";
+        let path = std::env::temp_dir().join("lightdock_test_gso_1.out.gz");
+        let mut encoder = GzEncoder::new(fs::File::create(&path).unwrap(), Compression::default());
+        encoder.write_all(contents.as_bytes()).unwrap();
+        encoder.finish().unwrap();
+
+        let output = read_gso_output(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(output.version, 2);
+        assert_eq!(output.entries[0].scoring, 2.0);
+    }
+
+    #[test]
+    fn test_step_event() {
+        use crate::scoring::Score;
+        use std::io::BufRead;
+
+        struct Height;
+
+        impl Score for Height {
+            fn energy(&self, translation: &[f64], _: &Quaternion, _: &[f64], _: &[f64]) -> f64 {
+                translation[2]
+            }
+        }
+
+        let scoring: Box<dyn Score> = Box::new(Height);
+        let mut swarm = Swarm::new();
+        let positions = vec![
+            vec![0.0, 0.0, 1.0, 1.0, 0.0, 0.0, 0.0],
+            vec![0.0, 0.0, 3.0, 1.0, 0.0, 0.0, 0.0],
+        ];
+        swarm.add_glowworms(&positions, &scoring, false, 0, 0);
+        swarm.update_luciferin();
+
+        let event = StepEvent::new(1, &swarm);
+        assert_eq!(event.best.as_ref().unwrap().id, 1);
+        assert_eq!(event.min_scoring, 1.0);
+        assert_eq!(event.max_scoring, 3.0);
+        assert_eq!(event.mean_scoring, 2.0);
+        assert_eq!(event.diversity, 2.0);
+
+        let mut output: Vec<u8> = Vec::new();
+        event.write(&mut output).unwrap();
+        let line = String::from_utf8(output).unwrap();
+        assert!(line.starts_with("{\"step\":1,\"glowworms\":2,"));
+        assert!(line.ends_with("}\n"));
+
+        // Events sent to a monitor, which goes away after the first one
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = format!("tcp://{}", listener.local_addr().unwrap());
+        assert!(EventSocket::is_address(&address));
+        let mut socket = EventSocket::connect(&address).unwrap();
+        let (connection, _) = listener.accept().unwrap();
+        let mut event = StepEvent::new(1, &swarm);
+        event.swarm = Some(7);
+        event.write(&mut socket).unwrap();
+        let mut line = String::new();
+        io::BufReader::new(&connection)
+            .read_line(&mut line)
+            .unwrap();
+        assert!(line.starts_with("{\"step\":1,\"swarm\":7,\"glowworms\":2,"));
+        drop(connection);
+        drop(listener);
+        for step in 2..10 {
+            StepEvent::new(step, &swarm).write(&mut socket).unwrap();
+        }
+        assert!(EventSocket::connect("udp://127.0.0.1:1").is_err());
+
+        let mut output: Vec<u8> = Vec::new();
+        ConvergenceRow::new(1, &swarm).write(&mut output).unwrap();
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "1,3.00000000,2.00000000,\n"
+        );
+    }
+
+    #[test]
+    fn test_output_writer() {
+        let path = std::env::temp_dir().join("lightdock_test_writer.out");
+        let path = path.to_str().unwrap().to_string();
+        let mut writer = OutputWriter::new(1);
+        writer
+            .write(path.clone(), b"first".to_vec(), false)
+            .unwrap();
+        writer
+            .write(path.clone(), b"second".to_vec(), false)
+            .unwrap();
+        writer.finish().unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "second");
+        fs::remove_file(&path).unwrap();
+
+        // Errors in the background thread are reported back
+        let mut writer = OutputWriter::new(1);
+        let missing = String::from("/nonexistent/lightdock/gso_1.out");
+        let result = writer
+            .write(missing.clone(), Vec::new(), false)
+            .and_then(|_| writer.finish());
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_gso_outputs() {
+        let directory = std::env::temp_dir().join("lightdock_test_gso_outputs");
+        fs::create_dir_all(&directory).unwrap();
+        for filename in [
+            "gso_1.out",
+            "gso_10.out",
+            "gso_2.out.gz",
+            "gso_events.jsonl",
+        ] {
+            fs::write(directory.join(filename), "").unwrap();
+        }
+        let outputs = gso_outputs(directory.to_str().unwrap()).unwrap();
+        let steps: Vec<u32> = outputs.iter().map(|(step, _)| *step).collect();
+        assert_eq!(steps, vec![1, 2, 10]);
+        assert!(outputs[1].1.ends_with("gso_2.out.gz"));
+        fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[test]
+    fn test_parse_wrong_output() {
+        assert!(parse_gso_output("(1.0, 2.0, 3.0)    0    0   5.1  0 0.2 2.0\n").is_err());
+        assert!(parse_gso_output("(1.0, 2.0, 3.0, 1.0, 0.0, 0.0, 0.0)    0    0\n").is_err());
+    }
+}
@@ -0,0 +1,211 @@
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::error::Error;
+use std::fs;
+
+// Per-atom parameters of the DNA and pyDock scoring functions can be overridden with a TOML
+// file, for atoms or residues missing from the built-in AMBER tables:
+//
+//   [[receptor]]
+//   select = "A.HEM.*:FE"   # chain.residue.number:atom, '*' matches anything
+//   charge = 2.0            # electrostatics charge
+//   vdw_radius = 1.2
+//   vdw_charge = 0.01       # Van der Waals well depth
+//   atom_type = "FE"        # AMBER type used for the parameters not given
+//
+// Selections without an atom name match all the atoms of the residues. Later entries take
+// precedence over earlier ones.
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct AtomOverride {
+    pub select: String,
+    pub charge: Option<f64>,
+    pub vdw_radius: Option<f64>,
+    pub vdw_charge: Option<f64>,
+    pub atom_type: Option<String>,
+}
+
+#[derive(Deserialize, Debug, Clone, Default)]
+#[serde(deny_unknown_fields)]
+pub struct ParameterOverrides {
+    #[serde(default)]
+    pub receptor: Vec<AtomOverride>,
+    #[serde(default)]
+    pub ligand: Vec<AtomOverride>,
+}
+
+#[derive(Debug, Default, PartialEq)]
+pub struct AtomParameters {
+    pub charge: Option<f64>,
+    pub vdw_radius: Option<f64>,
+    pub vdw_charge: Option<f64>,
+    pub atom_type: Option<String>,
+}
+
+// Splits a selection into chain, residue name, residue number and atom name
+fn selection_fields(select: &str) -> Result<[&str; 4], String> {
+    let (residue, atom) = select.split_once(':').unwrap_or((select, "*"));
+    let fields: Vec<&str> = residue.split('.').collect();
+    if fields.len() != 3 || fields.iter().chain([&atom]).any(|field| field.is_empty()) {
+        return Err(format!(
+            "wrong selection {:?}, expected chain.residue.number[:atom]",
+            select
+        ));
+    }
+    Ok([fields[0], fields[1], fields[2], atom])
+}
+
+impl AtomOverride {
+    fn check(&self) -> Result<(), String> {
+        selection_fields(&self.select)?;
+        if self.vdw_radius.is_some_and(|radius| radius <= 0.0) {
+            return Err(format!("{}: vdw_radius must be positive", self.select));
+        }
+        if self.vdw_charge.is_some_and(|charge| charge < 0.0) {
+            return Err(format!("{}: vdw_charge can not be negative", self.select));
+        }
+        Ok(())
+    }
+
+    // Residue IDs are chain.residue.number with the insertion code appended to the number
+    pub fn matches(&self, res_id: &str, atom_name: &str) -> bool {
+        let selection = match selection_fields(&self.select) {
+            Ok(selection) => selection,
+            Err(_) => return false,
+        };
+        let mut values: Vec<&str> = res_id.splitn(3, '.').collect();
+        values.push(atom_name);
+        values.len() == 4
+            && values
+                .iter()
+                .zip(selection)
+                .all(|(value, pattern)| pattern == "*" || pattern == *value)
+    }
+}
+
+// Exact selections (no wildcards, with an atom name) are indexed, so long per-atom lists like
+// the ones from an AMBER topology stay cheap to look up
+pub struct OverrideIndex<'a> {
+    overrides: &'a [AtomOverride],
+    exact: HashMap<&'a str, Vec<usize>>,
+    patterns: Vec<usize>,
+}
+
+impl<'a> OverrideIndex<'a> {
+    pub fn new(overrides: &'a [AtomOverride]) -> OverrideIndex<'a> {
+        let mut exact: HashMap<&str, Vec<usize>> = HashMap::new();
+        let mut patterns: Vec<usize> = Vec::new();
+        for (i, entry) in overrides.iter().enumerate() {
+            if entry.select.contains(':') && !entry.select.contains('*') {
+                exact.entry(entry.select.as_str()).or_default().push(i);
+            } else {
+                patterns.push(i);
+            }
+        }
+        OverrideIndex {
+            overrides,
+            exact,
+            patterns,
+        }
+    }
+
+    pub fn lookup(&self, res_id: &str, atom_name: &str) -> AtomParameters {
+        let mut matching: Vec<usize> = self
+            .patterns
+            .iter()
+            .copied()
+            .filter(|&i| self.overrides[i].matches(res_id, atom_name))
+            .collect();
+        if !self.exact.is_empty() {
+            if let Some(indexes) = self.exact.get(format!("{}:{}", res_id, atom_name).as_str()) {
+                matching.extend(indexes);
+                matching.sort();
+            }
+        }
+        let mut parameters = AtomParameters::default();
+        for entry in matching.into_iter().map(|i| &self.overrides[i]) {
+            parameters.charge = entry.charge.or(parameters.charge);
+            parameters.vdw_radius = entry.vdw_radius.or(parameters.vdw_radius);
+            parameters.vdw_charge = entry.vdw_charge.or(parameters.vdw_charge);
+            parameters.atom_type = entry.atom_type.clone().or(parameters.atom_type);
+        }
+        parameters
+    }
+}
+
+impl ParameterOverrides {
+    pub fn parse(contents: &str) -> Result<ParameterOverrides, Box<dyn Error>> {
+        let overrides: ParameterOverrides = toml::from_str(contents)?;
+        for entry in overrides.receptor.iter().chain(overrides.ligand.iter()) {
+            entry.check()?;
+        }
+        Ok(overrides)
+    }
+
+    pub fn read(path: &str) -> Result<ParameterOverrides, Box<dyn Error>> {
+        ParameterOverrides::parse(&fs::read_to_string(path)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parameter_overrides() {
+        let contents = r#"
+            [[receptor]]
+            select = "*.HEM.*"
+            charge = 0.0
+            atom_type = "CT"
+
+            [[receptor]]
+            select = "A.HEM.150:FE"
+            charge = 2.0
+            vdw_radius = 1.2
+
+            [[ligand]]
+            select = "B.ALA.12A:CB"
+            vdw_charge = 0.1
+        "#;
+        let overrides = ParameterOverrides::parse(contents).unwrap();
+        assert_eq!(overrides.receptor.len(), 2);
+
+        let receptor = OverrideIndex::new(&overrides.receptor);
+        let ligand = OverrideIndex::new(&overrides.ligand);
+        let iron = receptor.lookup("A.HEM.150", "FE");
+        assert_eq!(
+            iron,
+            AtomParameters {
+                charge: Some(2.0),
+                vdw_radius: Some(1.2),
+                vdw_charge: None,
+                atom_type: Some(String::from("CT")),
+            }
+        );
+        assert_eq!(receptor.lookup("B.HEM.150", "CA").charge, Some(0.0));
+        assert_eq!(
+            receptor.lookup("A.ALA.150", "FE"),
+            AtomParameters::default()
+        );
+        assert_eq!(ligand.lookup("B.ALA.12A", "CB").vdw_charge, Some(0.1));
+        assert_eq!(ligand.lookup("B.ALA.12", "CB").vdw_charge, None);
+    }
+
+    #[test]
+    fn test_wrong_parameter_overrides() {
+        let error = ParameterOverrides::parse("[[receptor]]\nselect = \"A.HEM\"\n").unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "wrong selection \"A.HEM\", expected chain.residue.number[:atom]"
+        );
+        let error =
+            ParameterOverrides::parse("[[ligand]]\nselect = \"A.HEM.1\"\nvdw_radius = -1.0\n")
+                .unwrap_err();
+        assert_eq!(error.to_string(), "A.HEM.1: vdw_radius must be positive");
+        assert!(
+            ParameterOverrides::parse("[[ligand]]\nselect = \"A.HEM.1\"\nradius = 1.0\n").is_err()
+        );
+    }
+}
@@ -0,0 +1,377 @@
+use log::warn;
+use pdbtbx::PDB;
+
+// Ligand normal modes of the peptide docking mode, computed from the ligand structure instead of
+// being read from the ANM file of the setup. Peptides are too flexible for rigid-body docking, so
+// they get more modes than a protein ligand:
+//  - backbone torsion modes (optional): the displacement of the atoms after every phi and psi
+//    angle of the chain for a small rotation around the bond, i.e. the linear approximation of the
+//    torsion change,
+//  - ANM modes from the CA atoms filling the rest, every atom following the CA of its residue.
+// All modes are flattened as (num_modes, num_atoms, 3) with unit norm, like the ANM files.
+
+// Distance between CA atoms interacting in the elastic network
+pub const ANM_CUTOFF: f64 = 15.0;
+
+// Eigenvalues and eigenvectors (as columns) of a symmetric matrix by cyclic Jacobi rotations
+pub fn symmetric_eigen(matrix: &[Vec<f64>]) -> (Vec<f64>, Vec<Vec<f64>>) {
+    let n = matrix.len();
+    let mut a = matrix.to_vec();
+    let mut v = vec![vec![0.0; n]; n];
+    for (i, row) in v.iter_mut().enumerate() {
+        row[i] = 1.0;
+    }
+    let scale: f64 = a.iter().flatten().map(|value| value * value).sum::<f64>();
+    for _sweep in 0..100 {
+        let off_diagonal: f64 = (0..n)
+            .flat_map(|p| (p + 1..n).map(move |q| (p, q)))
+            .map(|(p, q)| a[p][q] * a[p][q])
+            .sum();
+        if off_diagonal <= 1e-24 * scale.max(1e-300) {
+            break;
+        }
+        for p in 0..n {
+            for q in p + 1..n {
+                if a[p][q].abs() < 1e-300 {
+                    continue;
+                }
+                let theta = (a[q][q] - a[p][p]) / (2.0 * a[p][q]);
+                let t = if theta == 0.0 {
+                    1.0
+                } else {
+                    theta.signum() / (theta.abs() + (theta * theta + 1.0).sqrt())
+                };
+                let c = 1.0 / (t * t + 1.0).sqrt();
+                let s = t * c;
+                for row in a.iter_mut() {
+                    let akp = row[p];
+                    let akq = row[q];
+                    row[p] = c * akp - s * akq;
+                    row[q] = s * akp + c * akq;
+                }
+                for k in 0..n {
+                    let apk = a[p][k];
+                    let aqk = a[q][k];
+                    a[p][k] = c * apk - s * aqk;
+                    a[q][k] = s * apk + c * aqk;
+                }
+                for row in v.iter_mut() {
+                    let vkp = row[p];
+                    let vkq = row[q];
+                    row[p] = c * vkp - s * vkq;
+                    row[q] = s * vkp + c * vkq;
+                }
+            }
+        }
+    }
+    ((0..n).map(|i| a[i][i]).collect(), v)
+}
+
+fn normalize(mode: &mut [[f64; 3]]) {
+    let norm = mode
+        .iter()
+        .map(|d| d[0] * d[0] + d[1] * d[1] + d[2] * d[2])
+        .sum::<f64>()
+        .sqrt();
+    if norm > 0.0 {
+        for d in mode.iter_mut() {
+            for k in 0..3 {
+                d[k] /= norm;
+            }
+        }
+    }
+}
+
+struct PeptideAtom {
+    chain: usize,
+    residue: usize,
+    name: String,
+    coordinate: [f64; 3],
+}
+
+fn peptide_atoms(structure: &PDB) -> Vec<PeptideAtom> {
+    let mut atoms: Vec<PeptideAtom> = Vec::new();
+    let mut residue_index = 0;
+    for (chain_index, chain) in structure.chains().enumerate() {
+        for residue in chain.residues() {
+            for atom in residue.atoms() {
+                atoms.push(PeptideAtom {
+                    chain: chain_index,
+                    residue: residue_index,
+                    name: atom.name().to_string(),
+                    coordinate: [atom.x(), atom.y(), atom.z()],
+                });
+            }
+            residue_index += 1;
+        }
+    }
+    atoms
+}
+
+fn find_atom(atoms: &[PeptideAtom], residue: usize, name: &str) -> Option<usize> {
+    atoms
+        .iter()
+        .position(|atom| atom.residue == residue && atom.name == name)
+}
+
+// Displacement of the atoms after the given bond for a rotation around it
+fn torsion_mode(atoms: &[PeptideAtom], from: usize, to: usize, moving: &[bool]) -> Vec<[f64; 3]> {
+    let pivot = atoms[to].coordinate;
+    let mut axis = [
+        pivot[0] - atoms[from].coordinate[0],
+        pivot[1] - atoms[from].coordinate[1],
+        pivot[2] - atoms[from].coordinate[2],
+    ];
+    let length = (axis[0] * axis[0] + axis[1] * axis[1] + axis[2] * axis[2]).sqrt();
+    for value in axis.iter_mut() {
+        *value /= length;
+    }
+    let mut mode: Vec<[f64; 3]> = atoms
+        .iter()
+        .zip(moving.iter())
+        .map(|(atom, &moves)| {
+            if !moves {
+                return [0.0; 3];
+            }
+            let r = [
+                atom.coordinate[0] - pivot[0],
+                atom.coordinate[1] - pivot[1],
+                atom.coordinate[2] - pivot[2],
+            ];
+            [
+                axis[1] * r[2] - axis[2] * r[1],
+                axis[2] * r[0] - axis[0] * r[2],
+                axis[0] * r[1] - axis[1] * r[0],
+            ]
+        })
+        .collect();
+    normalize(&mut mode);
+    mode
+}
+
+// Phi and psi torsion modes along every chain, in sequence order
+pub fn torsion_modes(structure: &PDB) -> Vec<Vec<[f64; 3]>> {
+    let atoms = peptide_atoms(structure);
+    let num_residues = atoms.last().map_or(0, |atom| atom.residue + 1);
+    let chain_of = |residue: usize| {
+        atoms
+            .iter()
+            .find(|atom| atom.residue == residue)
+            .map(|atom| atom.chain)
+    };
+    let mut modes: Vec<Vec<[f64; 3]>> = Vec::new();
+    for residue in 0..num_residues {
+        let chain = chain_of(residue);
+        let first = residue == 0 || chain_of(residue - 1) != chain;
+        let last = residue + 1 == num_residues || chain_of(residue + 1) != chain;
+        let later = |atom: &PeptideAtom| Some(atom.chain) == chain && atom.residue > residue;
+        let (n, ca, c) = (
+            find_atom(&atoms, residue, "N"),
+            find_atom(&atoms, residue, "CA"),
+            find_atom(&atoms, residue, "C"),
+        );
+        if let (false, Some(n), Some(ca)) = (first, n, ca) {
+            let moving: Vec<bool> = atoms
+                .iter()
+                .map(|atom| {
+                    later(atom) || (atom.residue == residue && atom.name != "N" && atom.name != "H")
+                })
+                .collect();
+            modes.push(torsion_mode(&atoms, n, ca, &moving));
+        }
+        if let (false, Some(ca), Some(c)) = (last, ca, c) {
+            let moving: Vec<bool> = atoms
+                .iter()
+                .map(|atom| {
+                    later(atom)
+                        || (atom.residue == residue && ["C", "O", "OXT"].contains(&&atom.name[..]))
+                })
+                .collect();
+            modes.push(torsion_mode(&atoms, ca, c, &moving));
+        }
+    }
+    modes
+}
+
+// Softest ANM modes of the CA network, the six rigid-body ones left out
+pub fn anm_modes(structure: &PDB, num_modes: usize) -> Vec<Vec<[f64; 3]>> {
+    let atoms = peptide_atoms(structure);
+    let nodes: Vec<(usize, [f64; 3])> = atoms
+        .iter()
+        .filter(|atom| atom.name == "CA")
+        .map(|atom| (atom.residue, atom.coordinate))
+        .collect();
+    let n = nodes.len() * 3;
+    let mut hessian = vec![vec![0.0; n]; n];
+    for i in 0..nodes.len() {
+        for j in i + 1..nodes.len() {
+            let d = [
+                nodes[j].1[0] - nodes[i].1[0],
+                nodes[j].1[1] - nodes[i].1[1],
+                nodes[j].1[2] - nodes[i].1[2],
+            ];
+            let r2 = d[0] * d[0] + d[1] * d[1] + d[2] * d[2];
+            if r2 > ANM_CUTOFF * ANM_CUTOFF {
+                continue;
+            }
+            for a in 0..3 {
+                for b in 0..3 {
+                    let value = d[a] * d[b] / r2;
+                    hessian[3 * i + a][3 * j + b] = -value;
+                    hessian[3 * j + b][3 * i + a] = -value;
+                    hessian[3 * i + a][3 * i + b] += value;
+                    hessian[3 * j + a][3 * j + b] += value;
+                }
+            }
+        }
+    }
+    let (eigenvalues, eigenvectors) = symmetric_eigen(&hessian);
+    let mut order: Vec<usize> = (0..n).collect();
+    order.sort_by(|&a, &b| eigenvalues[a].total_cmp(&eigenvalues[b]));
+
+    let available = n.saturating_sub(6);
+    if available < num_modes {
+        warn!(
+            "Only {} ANM modes available for {} CA atoms, {} requested",
+            available,
+            nodes.len(),
+            num_modes
+        );
+    }
+    order
+        .iter()
+        .skip(6)
+        .take(num_modes)
+        .map(|&column| {
+            let mut mode: Vec<[f64; 3]> = atoms
+                .iter()
+                .map(|atom| {
+                    match nodes
+                        .iter()
+                        .position(|(residue, _)| *residue == atom.residue)
+                    {
+                        Some(node) => [
+                            eigenvectors[3 * node][column],
+                            eigenvectors[3 * node + 1][column],
+                            eigenvectors[3 * node + 2][column],
+                        ],
+                        None => [0.0; 3],
+                    }
+                })
+                .collect();
+            normalize(&mut mode);
+            mode
+        })
+        .collect()
+}
+
+// Flattened ligand modes: torsion modes first if enabled, ANM modes up to the given number and
+// zero modes if there are not enough of them
+pub fn peptide_modes(structure: &PDB, num_modes: usize, torsions: bool) -> Vec<f64> {
+    let num_atoms = structure.atom_count();
+    let mut modes: Vec<Vec<[f64; 3]>> = if torsions {
+        torsion_modes(structure)
+    } else {
+        Vec::new()
+    };
+    modes.truncate(num_modes);
+    let num_anm = num_modes - modes.len();
+    if num_anm > 0 {
+        modes.extend(anm_modes(structure, num_anm));
+    }
+    modes.resize(num_modes, vec![[0.0; 3]; num_atoms]);
+    modes.into_iter().flatten().flatten().collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::structure::parse_structure;
+
+    fn peptide() -> PDB {
+        let mut contents = String::new();
+        let mut serial = 1;
+        for residue in 1..6 {
+            let x = 3.8 * residue as f64;
+            let y = if residue % 2 == 0 { 1.0 } else { -1.0 };
+            for (name, dx, dy, element) in [
+                ("N", -1.2, 0.3, "N"),
+                ("CA", 0.0, 0.0, "C"),
+                ("C", 1.3, 0.4, "C"),
+                ("O", 1.5, 1.6, "O"),
+            ] {
+                contents.push_str(&format!(
+                    "ATOM  {:>5}  {:<3} GLY A{:>4}    {:>8.3}{:>8.3}{:>8.3}  1.00  0.00           {}\n",
+                    serial,
+                    name,
+                    residue,
+                    x + dx,
+                    y * (1.0 + dy),
+                    0.2 * residue as f64,
+                    element
+                ));
+                serial += 1;
+            }
+        }
+        parse_structure(&contents, 1).unwrap().unwrap()
+    }
+
+    #[test]
+    fn test_symmetric_eigen() {
+        let matrix = vec![
+            vec![2.0, 1.0, 0.0],
+            vec![1.0, 2.0, 0.0],
+            vec![0.0, 0.0, 5.0],
+        ];
+        let (eigenvalues, eigenvectors) = symmetric_eigen(&matrix);
+        let mut sorted = eigenvalues.clone();
+        sorted.sort_by(f64::total_cmp);
+        for (value, expected) in sorted.iter().zip([1.0, 3.0, 5.0]) {
+            assert!((value - expected).abs() < 1e-9);
+        }
+        // A v = lambda v for every column
+        for (k, eigenvalue) in eigenvalues.iter().enumerate() {
+            for i in 0..3 {
+                let product: f64 = (0..3).map(|j| matrix[i][j] * eigenvectors[j][k]).sum();
+                assert!((product - eigenvalue * eigenvectors[i][k]).abs() < 1e-9);
+            }
+        }
+    }
+
+    #[test]
+    fn test_peptide_modes() {
+        let structure = peptide();
+        let num_atoms = structure.atom_count();
+
+        // psi of the first residue, then phi and psi of the inner ones and phi of the last one
+        let torsions = torsion_modes(&structure);
+        assert_eq!(torsions.len(), 8);
+        // The first residue does not move with the phi of the second one
+        assert!(torsions[1][..4].iter().all(|d| *d == [0.0; 3]));
+        assert!(torsions[1][4..].iter().any(|d| *d != [0.0; 3]));
+
+        let anm = anm_modes(&structure, 3);
+        assert_eq!(anm.len(), 3);
+        // Every atom follows the CA of its residue
+        assert_eq!(anm[0][0], anm[0][1]);
+        let dot: f64 = anm[0]
+            .iter()
+            .zip(anm[1].iter())
+            .map(|(a, b)| a[0] * b[0] + a[1] * b[1] + a[2] * b[2])
+            .sum();
+        assert!(dot.abs() < 1e-9);
+
+        let modes = peptide_modes(&structure, 10, true);
+        assert_eq!(modes.len(), 10 * num_atoms * 3);
+        let norm: f64 = modes[9 * num_atoms * 3..]
+            .iter()
+            .map(|value| value * value)
+            .sum();
+        assert!((norm - 1.0).abs() < 1e-9);
+        // Not enough ANM modes for 5 CA atoms, zero modes fill the rest
+        let modes = peptide_modes(&structure, 12, false);
+        assert!(modes[11 * num_atoms * 3..]
+            .iter()
+            .all(|value| *value == 0.0));
+    }
+}
@@ -0,0 +1,285 @@
+use log::warn;
+
+// Policy for the residues a scoring function has no parameters for, shared by the DFIRE, DNA and
+// pyDock model builders:
+//  - drop (default): the residue is left out of the model and reported in the log,
+//  - strict: building the model fails,
+//  - map: the residue is scored as its nearest standard residue (e.g. MSE as MET), with atoms
+//    renamed as needed and the ones missing in the standard residue left out. Residues without
+//    a known standard counterpart are dropped.
+// Left out atoms are still part of the structure, so they are moved with the poses and written
+// in the exported complexes, but they do not contribute to the scoring.
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ResiduePolicy {
+    #[default]
+    Drop,
+    Strict,
+    Map,
+}
+
+pub const RESIDUE_POLICIES: [ResiduePolicy; 3] = [
+    ResiduePolicy::Drop,
+    ResiduePolicy::Strict,
+    ResiduePolicy::Map,
+];
+
+impl ResiduePolicy {
+    pub fn name(&self) -> &'static str {
+        match self {
+            ResiduePolicy::Drop => "drop",
+            ResiduePolicy::Strict => "strict",
+            ResiduePolicy::Map => "map",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<ResiduePolicy> {
+        RESIDUE_POLICIES
+            .into_iter()
+            .find(|policy| policy.name() == name.to_lowercase())
+    }
+}
+
+// Nearest standard residue of common modified and protonation variant residues, with the atoms
+// named differently in it
+pub fn nearest_standard(
+    residue_name: &str,
+) -> Option<(&'static str, &'static [(&'static str, &'static str)])> {
+    match residue_name {
+        "MSE" => Some(("MET", &[("SE", "SD")])),
+        "SEP" => Some(("SER", &[])),
+        "TPO" => Some(("THR", &[])),
+        "PTR" => Some(("TYR", &[])),
+        "HYP" => Some(("PRO", &[])),
+        "MLY" | "M3L" | "ALY" | "KCX" | "LYN" => Some(("LYS", &[])),
+        "CSO" | "CSD" | "CME" | "CYX" | "CYM" => Some(("CYS", &[])),
+        "HSD" | "HSE" | "HSP" | "HID" | "HIE" | "HIP" => Some(("HIS", &[])),
+        "ASH" => Some(("ASP", &[])),
+        "GLH" => Some(("GLU", &[])),
+        _ => None,
+    }
+}
+
+// AMBER variants of the nucleotides of a chain, one per residue and None if the name in the
+// structure is the right one. The nucleotide starting the chain is the 5' variant (e.g. DA5) if it
+// has no 5'-phosphate, the one ending it the 3' variant (DA3), and a lone nucleotide without
+// phosphate the free variant (DAN). Nucleotides are the residues with known 5' variants. The
+// hydrogens of protonated nucleotides tell their form, so they are only terminal with their
+// terminal hydroxyl hydrogen (H5T or H3T).
+pub fn nucleic_variants(
+    chain: &pdbtbx::Chain,
+    supports_residue: impl Fn(&str) -> bool,
+) -> Vec<Option<String>> {
+    let nucleic: Vec<usize> = chain
+        .residues()
+        .enumerate()
+        .filter(|(_, residue)| {
+            residue
+                .name()
+                .is_some_and(|name| supports_residue(&format!("{}5", name)))
+        })
+        .map(|(index, _)| index)
+        .collect();
+    let (first, last) = match (nucleic.first(), nucleic.last()) {
+        (Some(&first), Some(&last)) => (first, last),
+        _ => return vec![None; chain.residue_count()],
+    };
+    chain
+        .residues()
+        .enumerate()
+        .map(|(index, residue)| {
+            if index != first && index != last {
+                return None;
+            }
+            let name = residue.name()?;
+            let has_atom = |name: &str| residue.atoms().any(|atom| atom.name().trim() == name);
+            let protonated = residue
+                .atoms()
+                .any(|atom| atom.name().trim().starts_with('H'));
+            let five_prime = index == first && !has_atom("P") && (!protonated || has_atom("H5T"));
+            let three_prime = index == last && (!protonated || has_atom("H3T"));
+            let suffix = match (five_prime, three_prime) {
+                (true, true) => "N",
+                (true, false) => "5",
+                (false, true) => "3",
+                (false, false) => return None,
+            };
+            let variant = format!("{}{}", name, suffix);
+            if supports_residue(&variant) {
+                Some(variant)
+            } else {
+                None
+            }
+        })
+        .collect()
+}
+
+// How a model builder takes the atoms of a residue
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ResidueAction {
+    Keep,
+    // As the given standard residue, with the atoms renamed
+    Map(&'static str, &'static [(&'static str, &'static str)]),
+    Drop,
+}
+
+impl ResidueAction {
+    // Residue and atom names the atom is scored with
+    pub fn names<'a>(&self, residue_name: &'a str, atom_name: &'a str) -> (&'a str, &'a str) {
+        match self {
+            ResidueAction::Map(standard, renames) => {
+                let atom_name = renames
+                    .iter()
+                    .find(|(name, _)| *name == atom_name)
+                    .map_or(atom_name, |(_, standard_name)| standard_name);
+                (standard, atom_name)
+            }
+            _ => (residue_name, atom_name),
+        }
+    }
+}
+
+// Residues dropped or mapped while building a model
+#[derive(Debug, Default)]
+pub struct PolicyReport {
+    pub dropped: Vec<String>,
+    pub mapped: Vec<String>,
+    pub dropped_atoms: usize,
+}
+
+impl PolicyReport {
+    pub fn log(&self, scorer: &str) {
+        if !self.dropped.is_empty() {
+            warn!(
+                "{}: {} residues not supported, left out of the scoring: {}",
+                scorer,
+                self.dropped.len(),
+                self.dropped.join(", ")
+            );
+        }
+        if !self.mapped.is_empty() {
+            warn!(
+                "{}: {} residues scored as their nearest standard residue ({} atoms left out): {}",
+                scorer,
+                self.mapped.len(),
+                self.dropped_atoms,
+                self.mapped.join(", ")
+            );
+        }
+    }
+}
+
+impl ResiduePolicy {
+    // Action for a residue, given whether the scoring function supports it and a residue name
+    pub fn action(
+        &self,
+        scorer: &str,
+        res_id: &str,
+        res_name: &str,
+        supported: bool,
+        supports_residue: impl Fn(&str) -> bool,
+        report: &mut PolicyReport,
+    ) -> ResidueAction {
+        if supported {
+            return ResidueAction::Keep;
+        }
+        match self {
+            ResiduePolicy::Strict => panic!(
+                "{} Error: residue [{:?}] not supported (strict residue policy)",
+                scorer, res_id
+            ),
+            ResiduePolicy::Map => match nearest_standard(res_name) {
+                Some((standard, renames)) if supports_residue(standard) => {
+                    report.mapped.push(format!("{} as {}", res_id, standard));
+                    ResidueAction::Map(standard, renames)
+                }
+                _ => {
+                    report.dropped.push(res_id.to_string());
+                    ResidueAction::Drop
+                }
+            },
+            ResiduePolicy::Drop => {
+                report.dropped.push(res_id.to_string());
+                ResidueAction::Drop
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_residue_policy() {
+        assert_eq!(ResiduePolicy::from_name("MAP"), Some(ResiduePolicy::Map));
+        assert_eq!(ResiduePolicy::from_name("keep"), None);
+        let supports = |name: &str| name == "MET" || name == "ALA";
+        let mut report = PolicyReport::default();
+
+        let action =
+            ResiduePolicy::Map.action("DFIRE", "A.MSE.1", "MSE", false, supports, &mut report);
+        assert_eq!(action.names("MSE", "SE"), ("MET", "SD"));
+        assert_eq!(action.names("MSE", "CA"), ("MET", "CA"));
+        assert_eq!(
+            ResiduePolicy::Map.action("DFIRE", "A.SEP.2", "SEP", false, supports, &mut report),
+            ResidueAction::Drop
+        );
+        assert_eq!(
+            ResiduePolicy::Drop.action("DFIRE", "A.MSE.3", "MSE", false, supports, &mut report),
+            ResidueAction::Drop
+        );
+        assert_eq!(
+            ResiduePolicy::Strict.action("DFIRE", "A.ALA.4", "ALA", true, supports, &mut report),
+            ResidueAction::Keep
+        );
+        assert_eq!(report.mapped, vec!["A.MSE.1 as MET"]);
+        assert_eq!(report.dropped, vec!["A.SEP.2", "A.MSE.3"]);
+    }
+
+    #[test]
+    fn test_nucleic_variants() {
+        use crate::structure::parse_structure;
+        let supports =
+            |name: &str| ["DA", "DA5", "DA3", "DAN", "DC", "DC5", "DC3", "DCN"].contains(&name);
+        let structure = parse_structure(
+            "\
+ATOM      1  C1' DA  B   1       0.000   0.000   0.000  1.00  0.00           C\n\
+ATOM      2  P   DC  B   2       3.000   0.000   0.000  1.00  0.00           P\n\
+ATOM      3  P   DA  B   3       6.000   0.000   0.000  1.00  0.00           P\n\
+ATOM      4  P   DC  C   1      10.000   0.000   0.000  1.00  0.00           P\n\
+ATOM      5  P   DA  C   2      13.000   0.000   0.000  1.00  0.00           P\n\
+ATOM      6  C1' DA  D   1      20.000   0.000   0.000  1.00  0.00           C\n\
+ATOM      7  CA  ALA E   1      30.000   0.000   0.000  1.00  0.00           C\n\
+ATOM      8  P   DC  F   1      40.000   0.000   0.000  1.00  0.00           P\n\
+ATOM      9  H1' DC  F   1      41.000   0.000   0.000  1.00  0.00           H\n\
+ATOM     10  P   DC  F   2      43.000   0.000   0.000  1.00  0.00           P\n\
+ATOM     11  H3T DC  F   2      44.000   0.000   0.000  1.00  0.00           H\n",
+            1,
+        )
+        .unwrap()
+        .unwrap();
+        let variants: Vec<Vec<Option<String>>> = structure
+            .chains()
+            .map(|chain| nucleic_variants(chain, supports))
+            .collect();
+        // 5' without phosphate, internal and 3'
+        assert_eq!(
+            variants[0],
+            vec![Some(String::from("DA5")), None, Some(String::from("DA3"))]
+        );
+        // 5'-phosphate keeps the internal variant
+        assert_eq!(variants[1], vec![None, Some(String::from("DA3"))]);
+        assert_eq!(variants[2], vec![Some(String::from("DAN"))]);
+        assert_eq!(variants[3], vec![None]);
+        // Protonated nucleotides are only terminal with their terminal hydrogen
+        assert_eq!(variants[4], vec![None, Some(String::from("DC3"))]);
+    }
+
+    #[test]
+    #[should_panic(expected = "strict residue policy")]
+    fn test_strict_policy() {
+        let mut report = PolicyReport::default();
+        ResiduePolicy::Strict.action("DFIRE", "A.MSE.1", "MSE", false, |_| true, &mut report);
+    }
+}
@@ -0,0 +1,140 @@
+use super::qt::Quaternion;
+use pdbtbx::PDB;
+
+// Pose of the ligand over the receptor: translation of the ligand center, its rotation and the
+// extents of the normal modes of both partners
+#[derive(Debug, Clone, PartialEq)]
+pub struct Pose {
+    pub translation: Vec<f64>,
+    pub rotation: Quaternion,
+    pub rec_nmodes: Vec<f64>,
+    pub lig_nmodes: Vec<f64>,
+}
+
+impl Pose {
+    pub fn new(
+        translation: Vec<f64>,
+        rotation: Quaternion,
+        rec_nmodes: Vec<f64>,
+        lig_nmodes: Vec<f64>,
+    ) -> Pose {
+        Pose {
+            translation,
+            rotation,
+            rec_nmodes,
+            lig_nmodes,
+        }
+    }
+
+    // From a glowworm position: translation, rotation quaternion (w, x, y, z) and the extents of
+    // the receptor and then the ligand normal modes
+    pub fn from_position(position: &[f64], num_rec_nmodes: usize) -> Pose {
+        Pose {
+            translation: position[0..3].to_vec(),
+            rotation: Quaternion::new(position[3], position[4], position[5], position[6]),
+            rec_nmodes: position[7..7 + num_rec_nmodes].to_vec(),
+            lig_nmodes: position[7 + num_rec_nmodes..].to_vec(),
+        }
+    }
+}
+
+// Atomic coordinates follow the order in which the scoring functions read the structures:
+// chains, residues and atoms as found in the PDB file.
+
+pub fn atom_coordinates(structure: &PDB) -> Vec<[f64; 3]> {
+    let mut coordinates: Vec<[f64; 3]> = Vec::new();
+    for chain in structure.chains() {
+        for residue in chain.residues() {
+            for atom in residue.atoms() {
+                coordinates.push([atom.x(), atom.y(), atom.z()]);
+            }
+        }
+    }
+    coordinates
+}
+
+pub fn apply_nmodes(coordinates: &mut [[f64; 3]], nmodes: &[f64], extents: &[f64]) {
+    // nmodes is the flattened (num_anm, num_atoms, 3) array
+    let num_atoms = coordinates.len();
+    for (i_atom, coordinate) in coordinates.iter_mut().enumerate() {
+        for (i_nm, extent) in extents.iter().enumerate() {
+            coordinate[0] += nmodes[i_nm * num_atoms * 3 + i_atom * 3] * extent;
+            coordinate[1] += nmodes[i_nm * num_atoms * 3 + i_atom * 3 + 1] * extent;
+            coordinate[2] += nmodes[i_nm * num_atoms * 3 + i_atom * 3 + 2] * extent;
+        }
+    }
+}
+
+// Normal modes of a subset of the atoms, from the flattened (num_anm, num_atoms, 3) array
+pub fn select_nmodes(
+    nmodes: &[f64],
+    num_anm: usize,
+    num_atoms: usize,
+    atoms: &[usize],
+) -> Vec<f64> {
+    (0..num_anm)
+        .flat_map(|i_nm| {
+            atoms.iter().flat_map(move |&i_atom| {
+                let start = i_nm * num_atoms * 3 + i_atom * 3;
+                start..start + 3
+            })
+        })
+        .map(|i| nmodes[i])
+        .collect()
+}
+
+pub fn rotate_and_translate(
+    coordinates: &mut [[f64; 3]],
+    rotation: &Quaternion,
+    translation: &[f64],
+) {
+    for coordinate in coordinates.iter_mut() {
+        let rotated_coordinate = rotation.rotate(coordinate.to_vec());
+        coordinate[0] = rotated_coordinate[0] + translation[0];
+        coordinate[1] = rotated_coordinate[1] + translation[1];
+        coordinate[2] = rotated_coordinate[2] + translation[2];
+    }
+}
+
+pub fn ligand_pose(
+    coordinates: &[[f64; 3]],
+    translation: &[f64],
+    rotation: &Quaternion,
+    nmodes: &[f64],
+    extents: &[f64],
+) -> Vec<[f64; 3]> {
+    // Same transformation as in the scoring functions: rotation, translation and then ANM
+    let mut pose = coordinates.to_vec();
+    rotate_and_translate(&mut pose, rotation, translation);
+    apply_nmodes(&mut pose, nmodes, extents);
+    pose
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ligand_pose() {
+        let coordinates = vec![[1.0, 0.0, 0.0], [0.0, 1.0, 0.0]];
+        // 90 degrees around Z
+        let rotation = Quaternion::new(0.5_f64.sqrt(), 0.0, 0.0, 0.5_f64.sqrt());
+        let nmodes = vec![0.0, 0.0, 1.0, 0.0, 0.0, 2.0];
+        let pose = ligand_pose(&coordinates, &[1.0, 1.0, 1.0], &rotation, &nmodes, &[0.5]);
+        assert!((pose[0][0] - 1.0).abs() < 1e-9);
+        assert!((pose[0][1] - 2.0).abs() < 1e-9);
+        assert!((pose[0][2] - 1.5).abs() < 1e-9);
+        assert!((pose[1][0] - 0.0).abs() < 1e-9);
+        assert!((pose[1][1] - 1.0).abs() < 1e-9);
+        assert!((pose[1][2] - 2.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_pose_from_position() {
+        let pose = Pose::from_position(&[1.0, 2.0, 3.0, 1.0, 0.0, 0.0, 0.0, 0.5, -0.5, 0.25], 2);
+        assert_eq!(pose.translation, vec![1.0, 2.0, 3.0]);
+        assert_eq!(pose.rotation, Quaternion::default());
+        assert_eq!(pose.rec_nmodes, vec![0.5, -0.5]);
+        assert_eq!(pose.lig_nmodes, vec![0.25]);
+    }
+}
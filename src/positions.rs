@@ -0,0 +1,473 @@
+use super::constants::{DFIRE_CUTOFF, EMBEDDED_SWARM_CUTOFF, QUATERNION_NORM_TOLERANCE};
+use super::pose::ligand_pose;
+use super::qt::Quaternion;
+use super::scoring::{ClashFilter, ClashGrid};
+use super::structure::is_hydrogen;
+use log::warn;
+use pdbtbx::PDB;
+use std::error::Error;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+// Initial positions files (initial_positions_<swarm>.dat) contain one glowworm per line: the
+// translation, the rotation quaternion (w, x, y, z) and, if ANM is used, the receptor and ligand
+// extents. Values are separated by any whitespace, blank lines and lines or trailing parts
+// starting with '#' are ignored.
+
+pub fn check_dimensions(
+    position: &[f64],
+    use_anm: bool,
+    rec_num_anm: usize,
+    lig_num_anm: usize,
+) -> Result<(), String> {
+    if use_anm {
+        let expected = 7 + rec_num_anm + lig_num_anm;
+        if position.len() != expected {
+            return Err(format!(
+                "{} values found, {} expected (7 + {} receptor + {} ligand modes)",
+                position.len(),
+                expected,
+                rec_num_anm,
+                lig_num_anm
+            ));
+        }
+    } else if position.len() < 7 {
+        return Err(format!(
+            "{} values found, at least 7 expected",
+            position.len()
+        ));
+    }
+    Ok(())
+}
+
+fn normalize_rotation(position: &mut [f64]) -> Result<(), String> {
+    // Rotations assume unit quaternions, a skewed norm would also scale the ligand
+    let mut rotation = Quaternion::new(position[3], position[4], position[5], position[6]);
+    let norm = rotation.norm();
+    if norm < f64::EPSILON {
+        return Err(format!("degenerate quaternion {:?}", &position[3..7]));
+    }
+    if (norm - 1.0).abs() > QUATERNION_NORM_TOLERANCE {
+        warn!(
+            "Quaternion {:?} has norm {:.6}, normalizing it",
+            &position[3..7],
+            norm
+        );
+    }
+    rotation.normalize();
+    position[3] = rotation.w;
+    position[4] = rotation.x;
+    position[5] = rotation.y;
+    position[6] = rotation.z;
+    Ok(())
+}
+
+pub fn parse_positions(
+    contents: &str,
+    use_anm: bool,
+    rec_num_anm: usize,
+    lig_num_anm: usize,
+) -> Result<Vec<Vec<f64>>, Box<dyn Error>> {
+    let mut positions: Vec<Vec<f64>> = Vec::new();
+    for (i, line) in contents.lines().enumerate() {
+        let line = match line.find('#') {
+            Some(start) => &line[..start],
+            None => line,
+        };
+        if line.trim().is_empty() {
+            continue;
+        }
+        let mut position: Vec<f64> = Vec::new();
+        for value in line.split_whitespace() {
+            match value.parse::<f64>() {
+                Ok(value) => position.push(value),
+                Err(_) => return Err(format!("line {}: wrong value {:?}", i + 1, value).into()),
+            }
+        }
+        check_dimensions(&position, use_anm, rec_num_anm, lig_num_anm)
+            .and_then(|_| normalize_rotation(&mut position))
+            .map_err(|e| format!("line {}: {}", i + 1, e))?;
+        positions.push(position);
+    }
+    Ok(positions)
+}
+
+pub fn read_positions(
+    path: &str,
+    use_anm: bool,
+    rec_num_anm: usize,
+    lig_num_anm: usize,
+) -> Result<Vec<Vec<f64>>, Box<dyn Error>> {
+    let contents = fs::read_to_string(path)?;
+    parse_positions(&contents, use_anm, rec_num_anm, lig_num_anm)
+}
+
+// Whether the name matches the pattern, where '*' is any sequence of characters and '?' any
+// single character
+pub fn wildcard_match(pattern: &str, name: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let name: Vec<char> = name.chars().collect();
+    // Position after the last '*' and the name position it is matching up to
+    let mut star: Option<(usize, usize)> = None;
+    let (mut p, mut n) = (0, 0);
+    while n < name.len() {
+        if p < pattern.len() && (pattern[p] == '?' || pattern[p] == name[n]) {
+            p += 1;
+            n += 1;
+        } else if p < pattern.len() && pattern[p] == '*' {
+            star = Some((p + 1, n));
+            p += 1;
+        } else if let Some((after, matched)) = star {
+            // The last '*' takes one more character
+            p = after;
+            n = matched + 1;
+            star = Some((after, n));
+        } else {
+            return false;
+        }
+    }
+    pattern[p..].iter().all(|&c| c == '*')
+}
+
+// Swarm files given as a comma-separated list or as a pattern of file names with '*' and '?'
+// wildcards, e.g. "initial_positions_*.dat". Files matching a pattern are sorted by name.
+pub fn swarm_files(argument: &str) -> io::Result<Vec<String>> {
+    let mut files: Vec<String> = Vec::new();
+    for item in argument.split(',').map(str::trim).filter(|s| !s.is_empty()) {
+        if !item.contains(['*', '?']) {
+            files.push(item.to_string());
+            continue;
+        }
+        let path = Path::new(item);
+        let directory = path.parent().filter(|p| !p.as_os_str().is_empty());
+        let pattern = path
+            .file_name()
+            .map(|name| name.to_string_lossy().to_string())
+            .unwrap_or_default();
+        let mut matching: Vec<String> = Vec::new();
+        for entry in fs::read_dir(directory.unwrap_or(Path::new(".")))?.flatten() {
+            let name = entry.file_name().to_string_lossy().to_string();
+            if entry.path().is_file() && wildcard_match(&pattern, &name) {
+                matching.push(match directory {
+                    Some(directory) => directory.join(&name).to_string_lossy().to_string(),
+                    None => name,
+                });
+            }
+        }
+        matching.sort();
+        files.extend(matching);
+    }
+    Ok(files)
+}
+
+#[derive(Debug)]
+pub struct SwarmGeometry {
+    pub center: [f64; 3],
+    // Largest distance of a starting position to the center
+    pub radius: f64,
+    // Distance from the center to the closest receptor atom
+    pub surface_distance: f64,
+}
+
+impl SwarmGeometry {
+    pub fn new(positions: &[Vec<f64>], receptor_coordinates: &[[f64; 3]]) -> SwarmGeometry {
+        let distance = |one: &[f64], two: &[f64]| -> f64 {
+            ((one[0] - two[0]).powi(2) + (one[1] - two[1]).powi(2) + (one[2] - two[2]).powi(2))
+                .sqrt()
+        };
+        let mut center = [0.0; 3];
+        for position in positions.iter() {
+            for k in 0..3 {
+                center[k] += position[k] / positions.len() as f64;
+            }
+        }
+        let radius = positions
+            .iter()
+            .map(|position| distance(position, &center))
+            .fold(0.0, f64::max);
+        let surface_distance = receptor_coordinates
+            .iter()
+            .map(|coordinate| distance(coordinate, &center))
+            .fold(f64::MAX, f64::min);
+        SwarmGeometry {
+            center,
+            radius,
+            surface_distance,
+        }
+    }
+
+    // Swarms starting inside the receptor or too far away for any contact with the ligand
+    pub fn problem(&self, ligand_radius: f64) -> Option<String> {
+        if self.surface_distance < EMBEDDED_SWARM_CUTOFF {
+            Some(format!(
+                "swarm center is {:.3} A from the closest receptor atom, embedded in the receptor",
+                self.surface_distance
+            ))
+        } else if self.surface_distance > ligand_radius + DFIRE_CUTOFF {
+            Some(format!(
+                "swarm center is {:.3} A from the closest receptor atom, too far for the ligand (radius {:.3} A) to reach it",
+                self.surface_distance, ligand_radius
+            ))
+        } else {
+            None
+        }
+    }
+}
+
+// Heavy atom coordinates of a structure
+fn heavy_atoms(structure: &PDB) -> Vec<[f64; 3]> {
+    structure
+        .residues()
+        .flat_map(|residue| {
+            residue
+                .atoms()
+                .filter(move |atom| !is_hydrogen(residue, atom))
+        })
+        .map(|atom| [atom.x(), atom.y(), atom.z()])
+        .collect()
+}
+
+// Starting poses where the ligand heavily overlaps the receptor, which would spend many of the
+// first steps escaping the clash. Poses are checked as rigid bodies, normal modes are not applied.
+#[derive(Debug)]
+pub struct OverlapCheck {
+    receptor: Vec<[f64; 3]>,
+    ligand: Vec<[f64; 3]>,
+    receptor_center: [f64; 3],
+    pub filter: ClashFilter,
+    grid: ClashGrid,
+}
+
+impl OverlapCheck {
+    pub fn new(receptor: &PDB, ligand: &PDB, filter: ClashFilter) -> OverlapCheck {
+        let receptor = heavy_atoms(receptor);
+        let mut receptor_center = [0.0; 3];
+        for coordinate in receptor.iter() {
+            for k in 0..3 {
+                receptor_center[k] += coordinate[k] / receptor.len() as f64;
+            }
+        }
+        let receptor_atoms: Vec<usize> = (0..receptor.len()).collect();
+        OverlapCheck {
+            grid: filter.grid(&receptor, &receptor_atoms),
+            receptor,
+            ligand: heavy_atoms(ligand),
+            receptor_center,
+            filter,
+        }
+    }
+
+    pub fn is_overlapping(&self, position: &[f64]) -> bool {
+        let rotation = Quaternion::new(position[3], position[4], position[5], position[6]);
+        let pose = ligand_pose(&self.ligand, &position[0..3], &rotation, &[], &[]);
+        let ligand_atoms: Vec<usize> = (0..pose.len()).collect();
+        self.filter
+            .is_clashing(&self.grid, &self.receptor, &pose, &ligand_atoms)
+    }
+
+    // Indexes of the overlapping positions
+    pub fn overlapping(&self, positions: &[Vec<f64>]) -> Vec<usize> {
+        (0..positions.len())
+            .filter(|&i| self.is_overlapping(&positions[i]))
+            .collect()
+    }
+
+    // Moves the overlapping positions away from the receptor along the swarm axis, from the
+    // receptor center through the swarm center, by the given step until they no longer overlap or
+    // the largest shift is reached. Returns the indexes of the overlapping positions and the ones
+    // still overlapping after the largest shift.
+    pub fn repair(
+        &self,
+        positions: &mut [Vec<f64>],
+        step: f64,
+        max_shift: f64,
+    ) -> (Vec<usize>, Vec<usize>) {
+        let overlapping = self.overlapping(positions);
+        let mut axis = self.receptor_center.map(|c| -c);
+        for position in positions.iter() {
+            for k in 0..3 {
+                axis[k] += position[k] / positions.len() as f64;
+            }
+        }
+        let norm = axis.iter().map(|v| v * v).sum::<f64>().sqrt();
+        let mut unresolved: Vec<usize> = Vec::new();
+        for &i in overlapping.iter() {
+            let position = &mut positions[i];
+            // Swarm centered on the receptor, every pose moves away from the receptor center
+            let direction = if norm > f64::EPSILON {
+                axis.map(|v| v / norm)
+            } else {
+                let away: Vec<f64> = (0..3)
+                    .map(|k| position[k] - self.receptor_center[k])
+                    .collect();
+                let norm = away.iter().map(|v| v * v).sum::<f64>().sqrt();
+                if norm > f64::EPSILON {
+                    [away[0] / norm, away[1] / norm, away[2] / norm]
+                } else {
+                    [0.0, 0.0, 1.0]
+                }
+            };
+            let mut shift = 0.0;
+            while self.is_overlapping(position) && shift + step <= max_shift {
+                for k in 0..3 {
+                    position[k] += direction[k] * step;
+                }
+                shift += step;
+            }
+            if self.is_overlapping(position) {
+                unresolved.push(i);
+            }
+        }
+        (overlapping, unresolved)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_swarm_files() {
+        assert!(wildcard_match(
+            "initial_positions_*.dat",
+            "initial_positions_12.dat"
+        ));
+        assert!(wildcard_match(
+            "initial_positions_?.dat",
+            "initial_positions_1.dat"
+        ));
+        assert!(!wildcard_match(
+            "initial_positions_?.dat",
+            "initial_positions_12.dat"
+        ));
+        assert!(wildcard_match("*_*.dat", "initial_positions_12.dat"));
+        assert!(!wildcard_match("*.dat", "initial_positions_12.dat.gz"));
+        assert!(wildcard_match("*", ""));
+
+        let directory = tempfile::tempdir().unwrap();
+        for name in [
+            "initial_positions_1.dat",
+            "initial_positions_0.dat",
+            "setup.json",
+        ] {
+            fs::write(directory.path().join(name), "").unwrap();
+        }
+        let pattern = directory.path().join("initial_positions_*.dat");
+        let files = swarm_files(pattern.to_str().unwrap()).unwrap();
+        let names: Vec<&str> = files
+            .iter()
+            .map(|f| Path::new(f).file_name().unwrap().to_str().unwrap())
+            .collect();
+        assert_eq!(
+            names,
+            ["initial_positions_0.dat", "initial_positions_1.dat"]
+        );
+        assert_eq!(swarm_files("a.dat, b.dat").unwrap(), ["a.dat", "b.dat"]);
+    }
+
+    #[test]
+    fn test_parse_positions() {
+        let contents = "# Swarm 0\n\
+                        1.0  2.0\t3.0 1.0 0.0 0.0 0.0 0.0 0.0\n\
+                        \n\
+                        -1.5 0.5 2.0 0.0 2.0 0.0 0.0 0.1 -0.2  # extents\n";
+        let positions = parse_positions(contents, true, 1, 1).unwrap();
+        assert_eq!(positions.len(), 2);
+        assert_eq!(
+            positions[0],
+            vec![1.0, 2.0, 3.0, 1.0, 0.0, 0.0, 0.0, 0.0, 0.0]
+        );
+        // Quaternion is normalized
+        assert_eq!(
+            positions[1],
+            vec![-1.5, 0.5, 2.0, 0.0, 1.0, 0.0, 0.0, 0.1, -0.2]
+        );
+    }
+
+    #[test]
+    fn test_wrong_positions() {
+        let error = parse_positions("1.0 2.0 3.0 1.0 0.0 0.0\n", false, 0, 0).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "line 1: 6 values found, at least 7 expected"
+        );
+
+        let contents = "1.0 2.0 3.0 1.0 0.0 0.0 0.0\n1.0 2.0 3.0 1.0 0.0 0.0 0.0 0.5\n";
+        let error = parse_positions(contents, true, 1, 1).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "line 1: 7 values found, 9 expected (7 + 1 receptor + 1 ligand modes)"
+        );
+
+        let error = parse_positions("1.0 2.0 3.0 0.0 0.0 0.0 0.0\n", false, 0, 0).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "line 1: degenerate quaternion [0.0, 0.0, 0.0, 0.0]"
+        );
+
+        let error = parse_positions("1.0 2.0 3.0 1.0 0.0 0,0 0.0\n", false, 0, 0).unwrap_err();
+        assert_eq!(error.to_string(), "line 1: wrong value \"0,0\"");
+    }
+
+    #[test]
+    fn test_swarm_geometry() {
+        let positions = vec![
+            vec![10.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0],
+            vec![12.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0],
+        ];
+        let receptor: Vec<[f64; 3]> = vec![[0.0, 0.0, 0.0], [5.0, 0.0, 0.0]];
+        let geometry = SwarmGeometry::new(&positions, &receptor);
+        assert_eq!(geometry.center, [11.0, 0.0, 0.0]);
+        assert_eq!(geometry.radius, 1.0);
+        assert_eq!(geometry.surface_distance, 6.0);
+        assert!(geometry.problem(10.0).is_none());
+        assert!(geometry.problem(-10.0).unwrap().contains("too far"));
+
+        let receptor: Vec<[f64; 3]> = vec![[11.5, 0.0, 0.0]];
+        let geometry = SwarmGeometry::new(&positions, &receptor);
+        assert!(geometry.problem(10.0).unwrap().contains("embedded"));
+    }
+
+    #[test]
+    fn test_overlap_check() {
+        use crate::structure::parse_structure;
+
+        // Receptor of two atoms centered at the origin, ligand of two atoms and a hydrogen
+        let receptor = parse_structure(
+            "\
+ATOM      1  CA  ALA A   1       0.000   0.000  -0.500  1.00  0.00           C\n\
+ATOM      2  CB  ALA A   1       0.000   0.000   0.500  1.00  0.00           C\n",
+            1,
+        )
+        .unwrap()
+        .unwrap();
+        let ligand = parse_structure(
+            "\
+ATOM      1  CA  GLY B   1       0.000   0.000  -0.500  1.00  0.00           C\n\
+ATOM      2  N   GLY B   1       0.000   0.000   0.500  1.00  0.00           N\n\
+ATOM      3  H   GLY B   1       0.000   0.000   1.500  1.00  0.00           H\n",
+            1,
+        )
+        .unwrap()
+        .unwrap();
+        let check = OverlapCheck::new(&receptor, &ligand, ClashFilter::new(2.5, 2));
+        assert_eq!(check.ligand.len(), 2);
+
+        let mut positions = vec![
+            vec![0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0],
+            vec![20.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0],
+        ];
+        assert_eq!(check.overlapping(&positions), vec![0]);
+        // Swarm axis along +x, the overlapping pose moves until at most 2 pairs clash
+        let (overlapping, unresolved) = check.repair(&mut positions, 1.0, 20.0);
+        assert_eq!(overlapping, vec![0]);
+        assert!(unresolved.is_empty());
+        assert_eq!(positions[0][0..3], [3.0, 0.0, 0.0]);
+        assert_eq!(positions[1][0..3], [20.0, 0.0, 0.0]);
+
+        let mut positions = vec![vec![0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0]];
+        let (_, unresolved) = check.repair(&mut positions, 1.0, 0.5);
+        assert_eq!(unresolved, vec![0]);
+    }
+}
@@ -0,0 +1,216 @@
+use super::qt::Quaternion;
+use super::scoring::CellList;
+use std::cell::RefCell;
+
+// Single precision evaluation of DFIRE and DNA for exploratory runs. Coordinates, normal modes,
+// atom weights and the parameters of the scoring function are copied as f32 and the atom pairs
+// are scored in single precision, so twice as many distances fit in a SIMD register and the
+// evaluation reads half the memory. Energies differ from the double precision ones from their
+// sixth or seventh significant digit, simulations are only reproducible in the same mode.
+
+pub fn to_single(values: &[f64]) -> Vec<f32> {
+    values.iter().map(|&value| value as f32).collect()
+}
+
+// Coordinates, normal modes and atom weights of a partner in single precision
+#[derive(Debug, Clone, Default)]
+pub struct SinglePartner {
+    pub coordinates: Vec<[f32; 3]>,
+    pub nmodes: Vec<f32>,
+    pub weights: Vec<f32>,
+}
+
+impl SinglePartner {
+    pub fn new(coordinates: &[[f64; 3]], nmodes: &[f64], weights: &[f64]) -> SinglePartner {
+        SinglePartner {
+            coordinates: coordinates
+                .iter()
+                .map(|coordinate| coordinate.map(|value| value as f32))
+                .collect(),
+            nmodes: to_single(nmodes),
+            weights: to_single(weights),
+        }
+    }
+}
+
+// Buffers of the evaluation of a pose in single precision
+#[derive(Debug, Clone, Default)]
+pub struct SingleWorkspace {
+    pub receptor_coordinates: Vec<[f32; 3]>,
+    pub ligand_coordinates: Vec<[f32; 3]>,
+    pub cell_list: CellList<f32>,
+}
+
+impl SingleWorkspace {
+    // Partners moved to the pose, transformed as in the scoring functions: the ligand is rotated,
+    // translated and then deformed by its normal modes, the receptor only by its normal modes
+    pub fn pose(
+        &mut self,
+        receptor: &SinglePartner,
+        ligand: &SinglePartner,
+        translation: &[f64],
+        rotation: &Quaternion,
+        rec_extents: &[f64],
+        lig_extents: &[f64],
+    ) {
+        let matrix = rotation
+            .to_rotation_matrix()
+            .map(|row| row.map(|value| value as f32));
+        let translation = [
+            translation[0] as f32,
+            translation[1] as f32,
+            translation[2] as f32,
+        ];
+        for (coordinate, original) in self
+            .ligand_coordinates
+            .iter_mut()
+            .zip(ligand.coordinates.iter())
+        {
+            for k in 0..3 {
+                coordinate[k] = matrix[k][0] * original[0]
+                    + matrix[k][1] * original[1]
+                    + matrix[k][2] * original[2]
+                    + translation[k];
+            }
+        }
+        add_nmodes(&mut self.ligand_coordinates, &ligand.nmodes, lig_extents);
+        self.receptor_coordinates
+            .copy_from_slice(&receptor.coordinates);
+        add_nmodes(
+            &mut self.receptor_coordinates,
+            &receptor.nmodes,
+            rec_extents,
+        );
+    }
+
+    // Sorts the ligand atoms of the pose in the cells of the receptor, hashed again if it moved
+    pub fn search(&mut self, receptor_moved: bool) {
+        if receptor_moved {
+            self.cell_list.update(&self.receptor_coordinates);
+        }
+        self.cell_list.search(&self.ligand_coordinates);
+    }
+
+    // Coordinates of the pose in double precision, for the terms computed from them. The ones of
+    // a rigid receptor are left as they are.
+    pub fn to_double(
+        &self,
+        receptor_coordinates: &mut [[f64; 3]],
+        ligand_coordinates: &mut [[f64; 3]],
+        receptor_moved: bool,
+    ) {
+        if receptor_moved {
+            for (coordinate, single) in receptor_coordinates
+                .iter_mut()
+                .zip(self.receptor_coordinates.iter())
+            {
+                *coordinate = single.map(f64::from);
+            }
+        }
+        for (coordinate, single) in ligand_coordinates
+            .iter_mut()
+            .zip(self.ligand_coordinates.iter())
+        {
+            *coordinate = single.map(f64::from);
+        }
+    }
+}
+
+// Single precision copies of the partners and the parameters of a scoring function, with the
+// buffers of their evaluation
+#[derive(Debug)]
+pub struct SinglePrecision<P> {
+    pub receptor: SinglePartner,
+    pub ligand: SinglePartner,
+    pub parameters: P,
+    pub workspace: RefCell<SingleWorkspace>,
+}
+
+impl<P> SinglePrecision<P> {
+    pub fn new(
+        receptor: SinglePartner,
+        ligand: SinglePartner,
+        parameters: P,
+        cutoff: f64,
+    ) -> SinglePrecision<P> {
+        let workspace = SingleWorkspace {
+            receptor_coordinates: receptor.coordinates.clone(),
+            ligand_coordinates: ligand.coordinates.clone(),
+            cell_list: CellList::new(&receptor.coordinates, cutoff),
+        };
+        SinglePrecision {
+            receptor,
+            ligand,
+            parameters,
+            workspace: RefCell::new(workspace),
+        }
+    }
+}
+
+// Adds the normal modes, the flattened (num_anm, num_atoms, 3) array, scaled by their extents
+fn add_nmodes(coordinates: &mut [[f32; 3]], nmodes: &[f32], extents: &[f64]) {
+    let num_atoms = coordinates.len();
+    for (i_nm, &extent) in extents.iter().enumerate() {
+        let extent = extent as f32;
+        let mode = &nmodes[i_nm * num_atoms * 3..(i_nm + 1) * num_atoms * 3];
+        for (coordinate, displacement) in coordinates.iter_mut().zip(mode.chunks_exact(3)) {
+            for k in 0..3 {
+                coordinate[k] += displacement[k] * extent;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pose::{apply_nmodes, ligand_pose};
+
+    #[test]
+    fn test_single_pose() {
+        let receptor_coordinates = vec![[0.0, 0.0, 0.0], [1.5, -2.0, 3.25]];
+        let ligand_coordinates = vec![[1.0, 0.0, 0.0], [0.0, 1.0, 0.0], [2.0, 2.0, -1.0]];
+        let rec_nmodes = vec![0.0, 0.0, 1.0, 0.5, 0.0, 0.0];
+        let lig_nmodes = vec![1.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 2.0];
+        let receptor = SinglePartner::new(&receptor_coordinates, &rec_nmodes, &[1.0, 1.0]);
+        let ligand = SinglePartner::new(&ligand_coordinates, &lig_nmodes, &[1.0, 0.5, 1.0]);
+        let single = SinglePrecision::new(receptor, ligand, (), 5.0);
+
+        let rotation = Quaternion::new(0.5_f64.sqrt(), 0.0, 0.0, 0.5_f64.sqrt());
+        let translation = [1.0, -3.0, 0.5];
+        let mut workspace = single.workspace.borrow_mut();
+        workspace.pose(
+            &single.receptor,
+            &single.ligand,
+            &translation,
+            &rotation,
+            &[0.5],
+            &[-1.0],
+        );
+        let mut receptor_pose = vec![[0.0; 3]; 2];
+        let mut ligand_pose_single = vec![[0.0; 3]; 3];
+        workspace.to_double(&mut receptor_pose, &mut ligand_pose_single, true);
+
+        // Same pose as in double precision up to the precision of f32
+        let mut expected_receptor = receptor_coordinates.clone();
+        apply_nmodes(&mut expected_receptor, &rec_nmodes, &[0.5]);
+        let expected_ligand = ligand_pose(
+            &ligand_coordinates,
+            &translation,
+            &rotation,
+            &lig_nmodes,
+            &[-1.0],
+        );
+        for (pose, expected) in [
+            (&receptor_pose, &expected_receptor),
+            (&ligand_pose_single, &expected_ligand),
+        ] {
+            for (coordinate, expected) in pose.iter().zip(expected.iter()) {
+                for k in 0..3 {
+                    assert!((coordinate[k] - expected[k]).abs() < 1e-5);
+                }
+            }
+        }
+        assert_eq!(single.ligand.weights, vec![1.0, 0.5, 1.0]);
+    }
+}
@@ -0,0 +1,362 @@
+use super::constants::{
+    FFT_CORE_PENALTY, FFT_ELEC_WEIGHT, FFT_PROBE_RADIUS, FFT_SURFACE_THICKNESS,
+};
+use super::qt::Quaternion;
+use pdbtbx::PDB;
+use rand::prelude::StdRng;
+use rustfft::num_complex::Complex;
+use rustfft::{Fft, FftPlanner};
+use std::sync::Arc;
+
+// Exhaustive rigid-body search in the spirit of FTDock: for each sampled rotation of the ligand,
+// shape complementarity and a coarse electrostatic term are evaluated for every translation of
+// the grid at once as a correlation computed with FFTs. The search is limited to a cubic grid
+// around the swarm center, so only receptor atoms close to the swarm contribute.
+
+#[derive(Debug, Clone)]
+pub struct SampledPose {
+    pub translation: [f64; 3],
+    pub rotation: Quaternion,
+    pub score: f64,
+}
+
+struct Atom {
+    coordinates: [f64; 3],
+    charge: f64,
+}
+
+fn atom_charge(residue_name: &str, atom_name: &str) -> f64 {
+    // Formal charges of the ionizable side chains spread over their terminal atoms
+    match (residue_name, atom_name) {
+        ("LYS", "NZ") => 1.0,
+        ("ARG", "NH1") | ("ARG", "NH2") => 0.5,
+        ("ASP", "OD1") | ("ASP", "OD2") => -0.5,
+        ("GLU", "OE1") | ("GLU", "OE2") => -0.5,
+        _ => 0.0,
+    }
+}
+
+fn heavy_atoms(structure: &PDB) -> Vec<Atom> {
+    let mut atoms: Vec<Atom> = Vec::new();
+    for chain in structure.chains() {
+        for residue in chain.residues() {
+            let residue_name = residue.name().unwrap_or("");
+            for atom in residue.atoms() {
+                let atom_name = atom.name().trim();
+                if atom_name.starts_with('H') {
+                    continue;
+                }
+                atoms.push(Atom {
+                    coordinates: [atom.x(), atom.y(), atom.z()],
+                    charge: atom_charge(residue_name, atom_name),
+                });
+            }
+        }
+    }
+    atoms
+}
+
+struct Grid {
+    size: usize,
+    spacing: f64,
+    origin: [f64; 3],
+    forward: Arc<dyn Fft<f64>>,
+    inverse: Arc<dyn Fft<f64>>,
+}
+
+impl Grid {
+    fn index(&self, x: usize, y: usize, z: usize) -> usize {
+        (x * self.size + y) * self.size + z
+    }
+
+    fn point(&self, x: usize, y: usize, z: usize) -> [f64; 3] {
+        [
+            self.origin[0] + x as f64 * self.spacing,
+            self.origin[1] + y as f64 * self.spacing,
+            self.origin[2] + z as f64 * self.spacing,
+        ]
+    }
+
+    // Range of grid indexes closer than the given distance to a coordinate, clipped to the grid
+    fn range(&self, coordinate: f64, origin: f64, distance: f64) -> std::ops::Range<usize> {
+        let lower = ((coordinate - distance - origin) / self.spacing)
+            .ceil()
+            .max(0.0) as usize;
+        let upper = ((coordinate + distance - origin) / self.spacing).floor() + 1.0;
+        lower..(upper.max(0.0) as usize).min(self.size)
+    }
+
+    // 3D transform as 1D transforms along each of the axes
+    fn transform(&self, data: &mut [Complex<f64>], inverse: bool) {
+        let fft = if inverse {
+            &self.inverse
+        } else {
+            &self.forward
+        };
+        let n = self.size;
+        let mut line: Vec<Complex<f64>> = vec![Complex::new(0.0, 0.0); n];
+        for x in 0..n {
+            for y in 0..n {
+                let start = self.index(x, y, 0);
+                fft.process(&mut data[start..start + n]);
+            }
+        }
+        for x in 0..n {
+            for z in 0..n {
+                for y in 0..n {
+                    line[y] = data[self.index(x, y, z)];
+                }
+                fft.process(&mut line);
+                for y in 0..n {
+                    data[self.index(x, y, z)] = line[y];
+                }
+            }
+        }
+        for y in 0..n {
+            for z in 0..n {
+                for x in 0..n {
+                    line[x] = data[self.index(x, y, z)];
+                }
+                fft.process(&mut line);
+                for x in 0..n {
+                    data[self.index(x, y, z)] = line[x];
+                }
+            }
+        }
+    }
+}
+
+fn distance2(one: &[f64; 3], two: &[f64; 3]) -> f64 {
+    (one[0] - two[0]).powi(2) + (one[1] - two[1]).powi(2) + (one[2] - two[2]).powi(2)
+}
+
+pub fn fft_presampling(
+    receptor: &PDB,
+    ligand: &PDB,
+    center: &[f64; 3],
+    radius: f64,
+    num_poses: usize,
+    num_rotations: usize,
+    spacing: f64,
+    rng: &mut StdRng,
+) -> Vec<SampledPose> {
+    let receptor_atoms = heavy_atoms(receptor);
+    let ligand_atoms = heavy_atoms(ligand);
+    let ligand_radius = ligand_atoms
+        .iter()
+        .map(|atom| distance2(&atom.coordinates, &[0.0; 3]).sqrt())
+        .fold(0.0, f64::max);
+
+    // The grid is large enough for the ligand not to wrap around at any accepted translation
+    let half = radius + 2.0 * ligand_radius + FFT_PROBE_RADIUS + FFT_SURFACE_THICKNESS;
+    let size = (2.0 * half / spacing).ceil() as usize + 1;
+    let mut planner = FftPlanner::new();
+    let grid = Grid {
+        size,
+        spacing,
+        origin: [center[0] - half, center[1] - half, center[2] - half],
+        forward: planner.plan_fft_forward(size),
+        inverse: planner.plan_fft_inverse(size),
+    };
+    let num_points = size * size * size;
+
+    // Receptor shape: positive surface layer and penalized core
+    let mut receptor_shape: Vec<Complex<f64>> = vec![Complex::new(0.0, 0.0); num_points];
+    let surface = FFT_PROBE_RADIUS + FFT_SURFACE_THICKNESS;
+    for atom in receptor_atoms.iter() {
+        let c = &atom.coordinates;
+        for x in grid.range(c[0], grid.origin[0], surface) {
+            for y in grid.range(c[1], grid.origin[1], surface) {
+                for z in grid.range(c[2], grid.origin[2], surface) {
+                    let d2 = distance2(c, &grid.point(x, y, z));
+                    let value = &mut receptor_shape[grid.index(x, y, z)];
+                    if d2 <= FFT_PROBE_RADIUS * FFT_PROBE_RADIUS {
+                        value.re = FFT_CORE_PENALTY;
+                    } else if d2 <= surface * surface && value.re == 0.0 {
+                        value.re = 1.0;
+                    }
+                }
+            }
+        }
+    }
+
+    // Receptor electrostatic potential with a distance-dependent dielectric, zero in the core
+    let charged: Vec<&Atom> = receptor_atoms.iter().filter(|a| a.charge != 0.0).collect();
+    let mut receptor_potential: Vec<Complex<f64>> = vec![Complex::new(0.0, 0.0); num_points];
+    for x in 0..size {
+        for y in 0..size {
+            for z in 0..size {
+                let i = grid.index(x, y, z);
+                if receptor_shape[i].re < 0.0 {
+                    continue;
+                }
+                let point = grid.point(x, y, z);
+                for atom in charged.iter() {
+                    let d2 = distance2(&atom.coordinates, &point).max(4.0);
+                    receptor_potential[i].re += 332.0 * atom.charge / (4.0 * d2);
+                }
+            }
+        }
+    }
+
+    grid.transform(&mut receptor_shape, false);
+    grid.transform(&mut receptor_potential, false);
+
+    let mut candidates: Vec<SampledPose> = Vec::new();
+    let mut ligand_shape: Vec<Complex<f64>> = vec![Complex::new(0.0, 0.0); num_points];
+    let mut ligand_charges: Vec<Complex<f64>> = vec![Complex::new(0.0, 0.0); num_points];
+    let wrap = |value: f64| -> usize {
+        let i = (value / spacing).round() as i64;
+        i.rem_euclid(size as i64) as usize
+    };
+    for _ in 0..num_rotations {
+        let rotation = Quaternion::random(rng);
+        ligand_shape.fill(Complex::new(0.0, 0.0));
+        ligand_charges.fill(Complex::new(0.0, 0.0));
+        // Ligand grids are relative to the ligand center, negative offsets wrap around
+        for atom in ligand_atoms.iter() {
+            let c = rotation.rotate(atom.coordinates.to_vec());
+            let reach = (FFT_PROBE_RADIUS / spacing).ceil() as i64;
+            for dx in -reach..=reach {
+                for dy in -reach..=reach {
+                    for dz in -reach..=reach {
+                        let offset = [
+                            dx as f64 * spacing,
+                            dy as f64 * spacing,
+                            dz as f64 * spacing,
+                        ];
+                        let p = [
+                            (c[0] / spacing).round() * spacing + offset[0],
+                            (c[1] / spacing).round() * spacing + offset[1],
+                            (c[2] / spacing).round() * spacing + offset[2],
+                        ];
+                        if distance2(&p, &[c[0], c[1], c[2]]) <= FFT_PROBE_RADIUS * FFT_PROBE_RADIUS
+                        {
+                            ligand_shape[grid.index(wrap(p[0]), wrap(p[1]), wrap(p[2]))].re = 1.0;
+                        }
+                    }
+                }
+            }
+            if atom.charge != 0.0 {
+                ligand_charges[grid.index(wrap(c[0]), wrap(c[1]), wrap(c[2]))].re += atom.charge;
+            }
+        }
+        grid.transform(&mut ligand_shape, false);
+        grid.transform(&mut ligand_charges, false);
+
+        // Score of placing the ligand center at each grid point
+        for i in 0..num_points {
+            ligand_shape[i] = receptor_shape[i] * ligand_shape[i].conj()
+                - receptor_potential[i] * ligand_charges[i].conj() * FFT_ELEC_WEIGHT;
+        }
+        grid.transform(&mut ligand_shape, true);
+
+        let mut best: Vec<SampledPose> = Vec::new();
+        for x in 0..size {
+            for y in 0..size {
+                for z in 0..size {
+                    let translation = grid.point(x, y, z);
+                    if distance2(&translation, center) > radius * radius {
+                        continue;
+                    }
+                    let score = ligand_shape[grid.index(x, y, z)].re / num_points as f64;
+                    if score > 0.0 {
+                        best.push(SampledPose {
+                            translation,
+                            rotation,
+                            score,
+                        });
+                    }
+                }
+            }
+        }
+        best.sort_by(|a, b| b.score.total_cmp(&a.score));
+        best.truncate(num_poses);
+        candidates.append(&mut best);
+    }
+
+    select_poses(candidates, num_poses, 2.0 * spacing)
+}
+
+// Best poses keeping them apart in translation or orientation
+pub fn select_poses(
+    mut candidates: Vec<SampledPose>,
+    num_poses: usize,
+    min_distance: f64,
+) -> Vec<SampledPose> {
+    candidates.sort_by(|a, b| b.score.total_cmp(&a.score));
+    let mut selected: Vec<SampledPose> = Vec::new();
+    for candidate in candidates.into_iter() {
+        if selected.len() == num_poses {
+            break;
+        }
+        let distinct = selected.iter().all(|pose| {
+            distance2(&pose.translation, &candidate.translation) >= min_distance * min_distance
+                || pose.rotation.distance(candidate.rotation) > 0.1
+        });
+        if distinct {
+            selected.push(candidate);
+        }
+    }
+    selected
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::SeedableRng;
+    use std::env;
+
+    #[test]
+    fn test_select_poses() {
+        let pose = |x: f64, w: f64, score: f64| SampledPose {
+            translation: [x, 0.0, 0.0],
+            rotation: Quaternion::new(w, (1.0 - w * w).sqrt(), 0.0, 0.0),
+            score,
+        };
+        let candidates = vec![
+            pose(0.0, 1.0, 1.0),
+            pose(0.5, 1.0, 3.0),
+            pose(0.5, 0.0, 2.0),
+            pose(5.0, 1.0, 0.5),
+        ];
+        let selected = select_poses(candidates, 3, 1.0);
+        let scores: Vec<f64> = selected.iter().map(|p| p.score).collect();
+        assert_eq!(scores, vec![3.0, 2.0, 0.5]);
+    }
+
+    #[test]
+    fn test_fft_presampling() {
+        let cargo_path = match env::var("CARGO_MANIFEST_DIR") {
+            Ok(val) => val,
+            Err(_) => String::from("."),
+        };
+        let test_path: String = format!("{}/tests/2oob", cargo_path);
+        let receptor_filename: String = format!("{}/2oob_receptor.pdb", test_path);
+        let (receptor, _errors) =
+            pdbtbx::open(&receptor_filename, pdbtbx::StrictnessLevel::Strict).unwrap();
+
+        let ligand_filename: String = format!("{}/2oob_ligand.pdb", test_path);
+        let (mut ligand, _errors) =
+            pdbtbx::open(&ligand_filename, pdbtbx::StrictnessLevel::Strict).unwrap();
+        // Search around the native position of the ligand moved to the origin
+        let mut center = [0.0; 3];
+        for atom in ligand.atoms() {
+            center[0] += atom.x() / ligand.atom_count() as f64;
+            center[1] += atom.y() / ligand.atom_count() as f64;
+            center[2] += atom.z() / ligand.atom_count() as f64;
+        }
+        ligand.apply_transformation(&pdbtbx::TransformationMatrix::translation(
+            -center[0], -center[1], -center[2],
+        ));
+
+        let mut rng: StdRng = SeedableRng::seed_from_u64(324324);
+        let poses = fft_presampling(&receptor, &ligand, &center, 3.0, 5, 2, 2.0, &mut rng);
+        assert!(!poses.is_empty());
+        assert!(poses.len() <= 5);
+        for pose in poses.iter() {
+            assert!(pose.score > 0.0);
+            assert!(distance2(&pose.translation, &center) <= 9.0);
+        }
+    }
+}
@@ -0,0 +1,324 @@
+use super::parameters::AtomOverride;
+use super::restraints::residue_id;
+use log::warn;
+use pdbtbx::PDB;
+use std::collections::HashMap;
+use std::error::Error;
+use std::fs;
+
+// AMBER topology (prmtop/parm7) files give the charges and Lennard-Jones parameters of the exact
+// system of an MD setup. They are turned into per-atom parameter overrides of the DNA and pyDock
+// scoring functions, matching the residues of the topology to the ones of the structure in order
+// and their atoms by name.
+
+// Charges are stored multiplied by this factor (sqrt of the Coulomb constant in kcal/mol)
+const AMBER_CHARGE_FACTOR: f64 = 18.2223;
+// Radius used for atoms without Lennard-Jones parameters, like the hydroxyl hydrogens of the
+// built-in tables
+const MIN_VDW_RADIUS: f64 = 0.0001;
+
+#[derive(Debug)]
+pub struct Prmtop {
+    pub atom_names: Vec<String>,
+    pub atom_types: Vec<String>,
+    pub charges: Vec<f64>,
+    // Van der Waals radius (half of Rmin) and well depth
+    pub vdw_radii: Vec<f64>,
+    pub vdw_charges: Vec<f64>,
+    pub residue_labels: Vec<String>,
+    // Index of the first atom of each residue
+    pub residue_pointers: Vec<usize>,
+}
+
+// Fortran format width, e.g. 8 for 10I8 or 16 for 5E16.8
+fn format_width(format: &str) -> Option<usize> {
+    let format = format.trim().strip_prefix("%FORMAT(")?.strip_suffix(')')?;
+    let start = format.find(|c: char| c.is_ascii_alphabetic())?;
+    let width: String = format[start + 1..]
+        .chars()
+        .take_while(|c| c.is_ascii_digit())
+        .collect();
+    width.parse().ok()
+}
+
+fn sections(contents: &str) -> Result<HashMap<String, Vec<String>>, Box<dyn Error>> {
+    let mut sections: HashMap<String, Vec<String>> = HashMap::new();
+    let mut lines = contents.lines().peekable();
+    while let Some(line) = lines.next() {
+        let flag = match line.strip_prefix("%FLAG") {
+            Some(flag) => flag.trim().to_string(),
+            None => continue,
+        };
+        let width = match lines.next().and_then(format_width) {
+            Some(width) if width > 0 => width,
+            _ => return Err(format!("wrong %FORMAT of section {}", flag).into()),
+        };
+        let mut values: Vec<String> = Vec::new();
+        while let Some(line) = lines.next_if(|line| !line.starts_with('%')) {
+            let chars: Vec<char> = line.trim_end().chars().collect();
+            for chunk in chars.chunks(width) {
+                values.push(chunk.iter().collect::<String>().trim().to_string());
+            }
+        }
+        sections.insert(flag, values);
+    }
+    Ok(sections)
+}
+
+fn section<'a>(
+    sections: &'a HashMap<String, Vec<String>>,
+    flag: &str,
+    expected: usize,
+) -> Result<&'a [String], String> {
+    match sections.get(flag) {
+        Some(values) if values.len() >= expected => Ok(&values[..expected]),
+        Some(values) => Err(format!(
+            "section {} has {} values, {} expected",
+            flag,
+            values.len(),
+            expected
+        )),
+        None => Err(format!("section {} not found", flag)),
+    }
+}
+
+fn numbers<T: std::str::FromStr>(values: &[String], flag: &str) -> Result<Vec<T>, String> {
+    values
+        .iter()
+        .map(|value| {
+            value
+                .parse::<T>()
+                .map_err(|_| format!("wrong value {:?} in section {}", value, flag))
+        })
+        .collect()
+}
+
+impl Prmtop {
+    pub fn parse(contents: &str) -> Result<Prmtop, Box<dyn Error>> {
+        let sections = sections(contents)?;
+        let pointers: Vec<usize> = numbers(section(&sections, "POINTERS", 12)?, "POINTERS")?;
+        let (num_atoms, num_types, num_residues) = (pointers[0], pointers[1], pointers[11]);
+
+        let atom_names = section(&sections, "ATOM_NAME", num_atoms)?.to_vec();
+        let atom_types = section(&sections, "AMBER_ATOM_TYPE", num_atoms)?.to_vec();
+        let charges: Vec<f64> = numbers(section(&sections, "CHARGE", num_atoms)?, "CHARGE")?;
+        let type_indexes: Vec<usize> = numbers(
+            section(&sections, "ATOM_TYPE_INDEX", num_atoms)?,
+            "ATOM_TYPE_INDEX",
+        )?;
+        let nonbonded_indexes: Vec<i64> = numbers(
+            section(&sections, "NONBONDED_PARM_INDEX", num_types * num_types)?,
+            "NONBONDED_PARM_INDEX",
+        )?;
+        let num_pairs = num_types * (num_types + 1) / 2;
+        let acoef: Vec<f64> = numbers(
+            section(&sections, "LENNARD_JONES_ACOEF", num_pairs)?,
+            "LENNARD_JONES_ACOEF",
+        )?;
+        let bcoef: Vec<f64> = numbers(
+            section(&sections, "LENNARD_JONES_BCOEF", num_pairs)?,
+            "LENNARD_JONES_BCOEF",
+        )?;
+        let residue_labels = section(&sections, "RESIDUE_LABEL", num_residues)?.to_vec();
+        let residue_pointers: Vec<usize> = numbers(
+            section(&sections, "RESIDUE_POINTER", num_residues)?,
+            "RESIDUE_POINTER",
+        )?;
+        if residue_pointers
+            .windows(2)
+            .any(|pointers| pointers[0] > pointers[1])
+            || residue_pointers
+                .iter()
+                .any(|&pointer| pointer == 0 || pointer > num_atoms)
+        {
+            return Err("wrong RESIDUE_POINTER section".into());
+        }
+
+        // A = eps * Rmin^12 and B = 2 * eps * Rmin^6 for the interaction of a type with itself
+        let mut vdw_radii: Vec<f64> = Vec::with_capacity(num_atoms);
+        let mut vdw_charges: Vec<f64> = Vec::with_capacity(num_atoms);
+        for &type_index in type_indexes.iter() {
+            if type_index == 0 || type_index > num_types {
+                return Err(format!("wrong atom type index {}", type_index).into());
+            }
+            let pair = nonbonded_indexes[(num_types + 1) * (type_index - 1)];
+            let (a, b) = if pair > 0 && pair as usize <= num_pairs {
+                (acoef[pair as usize - 1], bcoef[pair as usize - 1])
+            } else {
+                (0.0, 0.0)
+            };
+            if a > 0.0 && b > 0.0 {
+                vdw_radii.push((2.0 * a / b).powf(1.0 / 6.0) / 2.0);
+                vdw_charges.push(b * b / (4.0 * a));
+            } else {
+                vdw_radii.push(MIN_VDW_RADIUS);
+                vdw_charges.push(0.0);
+            }
+        }
+
+        Ok(Prmtop {
+            atom_names,
+            atom_types,
+            charges: charges.iter().map(|c| c / AMBER_CHARGE_FACTOR).collect(),
+            vdw_radii,
+            vdw_charges,
+            residue_labels,
+            residue_pointers: residue_pointers.iter().map(|p| p - 1).collect(),
+        })
+    }
+
+    pub fn read(path: &str) -> Result<Prmtop, Box<dyn Error>> {
+        Prmtop::parse(&fs::read_to_string(path)?)
+    }
+
+    fn residue_atoms(&self, residue: usize) -> std::ops::Range<usize> {
+        let end = match self.residue_pointers.get(residue + 1) {
+            Some(&end) => end,
+            None => self.atom_names.len(),
+        };
+        self.residue_pointers[residue]..end
+    }
+
+    // Parameters of every atom of the structure found in the topology. Residues are matched in
+    // order, skipping membrane beads, and atoms by name within each residue.
+    pub fn overrides(&self, structure: &PDB) -> Result<Vec<AtomOverride>, String> {
+        let mut overrides: Vec<AtomOverride> = Vec::new();
+        let mut residue_index: usize = 0;
+        let mut missing: usize = 0;
+        for chain in structure.chains() {
+            for residue in chain.residues() {
+                if residue.name() == Some("MMB") {
+                    continue;
+                }
+                if residue_index >= self.residue_labels.len() {
+                    return Err(format!(
+                        "structure has more residues than the {} of the topology",
+                        self.residue_labels.len()
+                    ));
+                }
+                let res_id = residue_id(chain, residue);
+                let atoms = self.residue_atoms(residue_index);
+                for atom in residue.atoms() {
+                    let name = atom.name().trim();
+                    match atoms.clone().find(|&i| self.atom_names[i] == name) {
+                        Some(i) => overrides.push(AtomOverride {
+                            select: format!("{}:{}", res_id, name),
+                            charge: Some(self.charges[i]),
+                            vdw_radius: Some(self.vdw_radii[i]),
+                            vdw_charge: Some(self.vdw_charges[i]),
+                            atom_type: Some(self.atom_types[i].clone()),
+                        }),
+                        None => missing += 1,
+                    }
+                }
+                residue_index += 1;
+            }
+        }
+        if missing > 0 {
+            warn!(
+                "{} atoms not found in the topology, using the built-in parameters",
+                missing
+            );
+        }
+        Ok(overrides)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pdbtbx::{Atom, Chain, Model};
+
+    // Two residues, CT (R* 1.908, eps 0.1094) and HO (no Lennard-Jones) atom types
+    const PRMTOP: &str = "\
+%VERSION  VERSION_STAMP = V0001.000
+%FLAG TITLE
+%FORMAT(20a4)
+test
+%FLAG POINTERS
+%FORMAT(10I8)
+       3       2       0       0       0       0       0       0       0       0
+       0       2       0       0       0       0       0       0       0       0
+%FLAG ATOM_NAME
+%FORMAT(20a4)
+CA  CB  HO
+%FLAG CHARGE
+%FORMAT(5E16.8)
+  1.82223000E+00 -3.64446000E+00  0.00000000E+00
+%FLAG ATOM_TYPE_INDEX
+%FORMAT(10I8)
+       1       1       2
+%FLAG NONBONDED_PARM_INDEX
+%FORMAT(10I8)
+       1       2       2       3
+%FLAG RESIDUE_LABEL
+%FORMAT(20a4)
+ALA GLY
+%FLAG RESIDUE_POINTER
+%FORMAT(10I8)
+       1       3
+%FLAG LENNARD_JONES_ACOEF
+%FORMAT(5E16.8)
+  1.04308023E+06  0.00000000E+00  0.00000000E+00
+%FLAG LENNARD_JONES_BCOEF
+%FORMAT(5E16.8)
+  6.75612247E+02  0.00000000E+00  0.00000000E+00
+%FLAG AMBER_ATOM_TYPE
+%FORMAT(20a4)
+CT  CT  HO
+";
+
+    #[test]
+    fn test_parse_prmtop() {
+        let prmtop = Prmtop::parse(PRMTOP).unwrap();
+        assert_eq!(prmtop.atom_names, vec!["CA", "CB", "HO"]);
+        assert_eq!(prmtop.atom_types, vec!["CT", "CT", "HO"]);
+        assert!((prmtop.charges[0] - 0.1).abs() < 1e-9);
+        assert!((prmtop.charges[1] + 0.2).abs() < 1e-9);
+        assert!((prmtop.vdw_radii[0] - 1.908).abs() < 1e-4);
+        assert!((prmtop.vdw_charges[0] - 0.1094).abs() < 1e-4);
+        assert_eq!(prmtop.vdw_radii[2], MIN_VDW_RADIUS);
+        assert_eq!(prmtop.vdw_charges[2], 0.0);
+        assert_eq!(prmtop.residue_pointers, vec![0, 2]);
+        assert_eq!(prmtop.residue_atoms(1), 2..3);
+
+        assert_eq!(
+            Prmtop::parse("%FLAG POINTERS\n%FORMAT(10I8)\n       3\n")
+                .unwrap_err()
+                .to_string(),
+            "section POINTERS has 1 values, 12 expected"
+        );
+    }
+
+    #[test]
+    fn test_prmtop_overrides() {
+        let prmtop = Prmtop::parse(PRMTOP).unwrap();
+        let mut chain = Chain::new("A").unwrap();
+        for (serial, residue_name, atom_name) in [
+            (1, "ALA", "CB"),
+            (1, "ALA", "CA"),
+            (2, "GLY", "HO"),
+            (2, "GLY", "N"),
+        ] {
+            let atom = Atom::new(false, 1, atom_name, 0.0, 0.0, 0.0, 1.0, 0.0, "", 0).unwrap();
+            chain.add_atom(atom, (serial, None), (residue_name, None));
+        }
+        let mut structure = PDB::new();
+        structure.add_model(Model::from_iter(0, [chain].into_iter()));
+
+        let overrides = prmtop.overrides(&structure).unwrap();
+        let selections: Vec<&str> = overrides.iter().map(|o| o.select.as_str()).collect();
+        assert_eq!(selections, vec!["A.ALA.1:CB", "A.ALA.1:CA", "A.GLY.2:HO"]);
+        assert!((overrides[0].charge.unwrap() + 0.2).abs() < 1e-9);
+        assert_eq!(overrides[2].atom_type.as_deref(), Some("HO"));
+
+        let mut extra = Chain::new("B").unwrap();
+        extra.add_atom(
+            Atom::new(false, 1, "CA", 0.0, 0.0, 0.0, 1.0, 0.0, "", 0).unwrap(),
+            (1, None),
+            ("ALA", None),
+        );
+        structure.add_model(Model::from_iter(1, [extra].into_iter()));
+        assert!(prmtop.overrides(&structure).is_err());
+    }
+}
@@ -0,0 +1,110 @@
+use std::cell::Cell;
+use std::error::Error;
+use std::io::Write;
+use std::time::{Duration, Instant};
+
+// Time spent in every phase of a GSO step, a CSV row per step in seconds:
+//
+//   step,scoring,anm,neighbors,movement,io
+//   1,0.81234500,0.01234500,0.00012000,0.00034000,0.00451200
+//
+// The ANM phase is the transformation of the coordinates of every evaluated pose by the scoring
+// functions, the rigid-body rotation and translation of the ligand included, and it is not
+// counted in the scoring phase. The IO phase writes the outputs, events, convergence rows and
+// trajectory of the step.
+
+pub const PROFILE_HEADER: &str = "step,scoring,anm,neighbors,movement,io";
+
+thread_local! {
+    // Time transforming the pose coordinates in this thread, None if not profiling
+    static POSE_TIME: Cell<Option<Duration>> = const { Cell::new(None) };
+}
+
+// Starts timing the pose transformations of the scoring functions run in this thread
+pub fn enable_pose_timing() {
+    POSE_TIME.with(|time| time.set(Some(Duration::ZERO)));
+}
+
+pub fn pose_timer() -> Option<Instant> {
+    POSE_TIME.with(|time| time.get().map(|_| Instant::now()))
+}
+
+pub fn add_pose_time(start: Option<Instant>) {
+    if let Some(start) = start {
+        POSE_TIME.with(|time| time.set(time.get().map(|total| total + start.elapsed())));
+    }
+}
+
+// Time of the pose transformations since the last call
+pub fn take_pose_time() -> Duration {
+    POSE_TIME.with(|time| {
+        let total = time.get().unwrap_or_default();
+        if time.get().is_some() {
+            time.set(Some(Duration::ZERO));
+        }
+        total
+    })
+}
+
+#[derive(Debug, Default)]
+pub struct StepProfile {
+    pub step: u32,
+    pub scoring: Duration,
+    pub anm: Duration,
+    pub neighbors: Duration,
+    pub movement: Duration,
+    pub io: Duration,
+}
+
+impl StepProfile {
+    pub fn new(step: u32) -> Self {
+        StepProfile {
+            step,
+            ..Default::default()
+        }
+    }
+
+    pub fn write<W: Write + ?Sized>(&self, output: &mut W) -> Result<(), Box<dyn Error>> {
+        writeln!(
+            output,
+            "{},{:.8},{:.8},{:.8},{:.8},{:.8}",
+            self.step,
+            self.scoring.as_secs_f64(),
+            self.anm.as_secs_f64(),
+            self.neighbors.as_secs_f64(),
+            self.movement.as_secs_f64(),
+            self.io.as_secs_f64()
+        )?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pose_timing() {
+        // Nothing is timed before enabling it
+        assert!(pose_timer().is_none());
+        add_pose_time(pose_timer());
+        assert_eq!(take_pose_time(), Duration::ZERO);
+
+        enable_pose_timing();
+        let start = pose_timer();
+        assert!(start.is_some());
+        std::thread::sleep(Duration::from_millis(2));
+        add_pose_time(start);
+        assert!(take_pose_time() >= Duration::from_millis(2));
+        assert_eq!(take_pose_time(), Duration::ZERO);
+
+        let mut output: Vec<u8> = Vec::new();
+        let mut profile = StepProfile::new(3);
+        profile.scoring = Duration::from_millis(1500);
+        profile.write(&mut output).unwrap();
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "3,1.50000000,0.00000000,0.00000000,0.00000000,0.00000000\n"
+        );
+    }
+}
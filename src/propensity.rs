@@ -0,0 +1,332 @@
+use super::constants::REJECTED_POSE_SCORE;
+use super::contacts::{atom_residues, residue_contacts};
+use super::decomposition::{anm_extents, pose_coordinates, ResidueEnergies};
+use super::nmodes::Nmodes;
+use super::pose::atom_coordinates;
+use super::qt::Quaternion;
+use super::scoring::{Interface, Score};
+use super::ternary::LigandPose;
+use pdbtbx::PDB;
+use std::collections::HashMap;
+use std::error::Error;
+use std::fs;
+
+// Interface propensity term: some residue types are found at protein-protein interfaces much
+// more often than at the rest of the surface. Poses are rewarded by the mean propensity of the
+// residues of both partners at the interface, so the term favors interfaces enriched in sticky
+// residues and not just larger ones. Residues without a propensity (nucleotides, hetero groups)
+// do not count.
+
+// Natural logarithm of the interface propensity of the standard amino acids, approximate values
+// as reported for protein-protein interfaces (e.g. Jones and Thornton, 1996)
+pub const INTERFACE_PROPENSITIES: [(&str, f64); 20] = [
+    ("TRP", 0.83),
+    ("TYR", 0.66),
+    ("MET", 0.66),
+    ("PHE", 0.61),
+    ("CYS", 0.43),
+    ("ILE", 0.44),
+    ("HIS", 0.41),
+    ("LEU", 0.40),
+    ("ARG", 0.27),
+    ("VAL", 0.27),
+    ("GLY", -0.07),
+    ("ASN", -0.08),
+    ("GLN", -0.11),
+    ("ALA", -0.17),
+    ("THR", -0.18),
+    ("PRO", -0.25),
+    ("SER", -0.33),
+    ("LYS", -0.36),
+    ("ASP", -0.38),
+    ("GLU", -0.38),
+];
+
+pub fn default_propensities() -> HashMap<String, f64> {
+    INTERFACE_PROPENSITIES
+        .iter()
+        .map(|(name, propensity)| (name.to_string(), *propensity))
+        .collect()
+}
+
+// Each line contains a residue name and its propensity, e.g. "TRP 0.83"
+pub fn parse_propensities(contents: &str) -> Result<HashMap<String, f64>, Box<dyn Error>> {
+    let mut propensities: HashMap<String, f64> = HashMap::new();
+    for (i, line) in contents.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        if fields.len() != 2 {
+            return Err(format!("wrong format in line {}: {:?}", i + 1, line).into());
+        }
+        let propensity = fields[1]
+            .parse::<f64>()
+            .map_err(|_| format!("wrong propensity in line {}: {:?}", i + 1, fields[1]))?;
+        propensities.insert(fields[0].to_uppercase(), propensity);
+    }
+    Ok(propensities)
+}
+
+pub fn read_propensities(path: &str) -> Result<HashMap<String, f64>, Box<dyn Error>> {
+    parse_propensities(&fs::read_to_string(path)?)
+}
+
+pub struct PropensityPartner {
+    pub coordinates: Vec<[f64; 3]>,
+    pub nmodes: Nmodes,
+    pub num_anm: usize,
+    pub atom_residues: Vec<usize>,
+    // Propensity of every residue, None if its type has no propensity
+    pub propensities: Vec<Option<f64>>,
+}
+
+impl PropensityPartner {
+    pub fn new(
+        structure: &PDB,
+        nmodes: Nmodes,
+        num_anm: usize,
+        table: &HashMap<String, f64>,
+    ) -> PropensityPartner {
+        let (residues, atom_residues) = atom_residues(structure);
+        let propensities = residues
+            .iter()
+            .map(|res_id| {
+                res_id
+                    .split('.')
+                    .nth(1)
+                    .and_then(|name| table.get(name).copied())
+            })
+            .collect();
+        PropensityPartner {
+            coordinates: atom_coordinates(structure),
+            nmodes,
+            num_anm,
+            atom_residues,
+            propensities,
+        }
+    }
+}
+
+pub struct InterfacePropensity {
+    pub scoring: Box<dyn Score>,
+    pub receptor: PropensityPartner,
+    pub ligand: PropensityPartner,
+    pub use_anm: bool,
+    pub weight: f64,
+    pub cutoff: f64,
+}
+
+impl InterfacePropensity {
+    pub fn new(
+        scoring: Box<dyn Score>,
+        receptor: PropensityPartner,
+        ligand: PropensityPartner,
+        use_anm: bool,
+        weight: f64,
+        cutoff: f64,
+    ) -> Box<dyn Score> {
+        Box::new(InterfacePropensity {
+            scoring,
+            receptor,
+            ligand,
+            use_anm,
+            weight,
+            cutoff,
+        })
+    }
+
+    // Mean propensity of the interface residues of both partners, zero without interface
+    pub fn mean_propensity(
+        &self,
+        translation: &[f64],
+        rotation: &Quaternion,
+        rec_nmodes: &[f64],
+        lig_nmodes: &[f64],
+    ) -> f64 {
+        let (receptor_coordinates, ligand_coordinates) = pose_coordinates(
+            &self.receptor.coordinates,
+            &self.receptor.nmodes,
+            anm_extents(self.use_anm, self.receptor.num_anm, rec_nmodes),
+            &self.ligand.coordinates,
+            &self.ligand.nmodes,
+            anm_extents(self.use_anm, self.ligand.num_anm, lig_nmodes),
+            translation,
+            rotation,
+        );
+        let contacts = residue_contacts(
+            &receptor_coordinates,
+            &self.receptor.atom_residues,
+            &ligand_coordinates,
+            &self.ligand.atom_residues,
+            self.cutoff,
+        );
+        let mut receptor_interface: Vec<usize> = contacts.iter().map(|(i, _)| *i).collect();
+        let mut ligand_interface: Vec<usize> = contacts.iter().map(|(_, j)| *j).collect();
+        receptor_interface.sort_unstable();
+        receptor_interface.dedup();
+        ligand_interface.sort_unstable();
+        ligand_interface.dedup();
+
+        let propensities: Vec<f64> = receptor_interface
+            .iter()
+            .filter_map(|&i| self.receptor.propensities[i])
+            .chain(
+                ligand_interface
+                    .iter()
+                    .filter_map(|&j| self.ligand.propensities[j]),
+            )
+            .collect();
+        if propensities.is_empty() {
+            return 0.0;
+        }
+        propensities.iter().sum::<f64>() / propensities.len() as f64
+    }
+}
+
+impl Score for InterfacePropensity {
+    fn energy(
+        &self,
+        translation: &[f64],
+        rotation: &Quaternion,
+        rec_nmodes: &[f64],
+        lig_nmodes: &[f64],
+    ) -> f64 {
+        let score = self
+            .scoring
+            .energy(translation, rotation, rec_nmodes, lig_nmodes);
+        if score == REJECTED_POSE_SCORE {
+            return score;
+        }
+        score + self.weight * self.mean_propensity(translation, rotation, rec_nmodes, lig_nmodes)
+    }
+
+    fn energy_with_interface(
+        &self,
+        translation: &[f64],
+        rotation: &Quaternion,
+        rec_nmodes: &[f64],
+        lig_nmodes: &[f64],
+    ) -> (f64, Option<Interface>) {
+        let (score, interface) =
+            self.scoring
+                .energy_with_interface(translation, rotation, rec_nmodes, lig_nmodes);
+        if score == REJECTED_POSE_SCORE {
+            return (score, interface);
+        }
+        let propensity = self.mean_propensity(translation, rotation, rec_nmodes, lig_nmodes);
+        (score + self.weight * propensity, interface)
+    }
+
+    fn residue_energies(
+        &self,
+        translation: &[f64],
+        rotation: &Quaternion,
+        rec_nmodes: &[f64],
+        lig_nmodes: &[f64],
+    ) -> Option<ResidueEnergies> {
+        // Not decomposed by residue pairs, the term goes to the constant offset
+        let mut energies =
+            self.scoring
+                .residue_energies(translation, rotation, rec_nmodes, lig_nmodes)?;
+        energies.offset +=
+            self.weight * self.mean_propensity(translation, rotation, rec_nmodes, lig_nmodes);
+        Some(energies)
+    }
+
+    fn restraints_satisfaction(
+        &self,
+        translation: &[f64],
+        rotation: &Quaternion,
+        rec_nmodes: &[f64],
+        lig_nmodes: &[f64],
+    ) -> Option<f64> {
+        self.scoring
+            .restraints_satisfaction(translation, rotation, rec_nmodes, lig_nmodes)
+    }
+
+    // Propensity of the interface of the receptor and the first ligand only
+    fn second_ligand_energy(
+        &self,
+        translation: &[f64],
+        rotation: &Quaternion,
+        rec_nmodes: &[f64],
+        lig_nmodes: &[f64],
+        second: &LigandPose,
+    ) -> Option<f64> {
+        let score = self.scoring.second_ligand_energy(
+            translation,
+            rotation,
+            rec_nmodes,
+            lig_nmodes,
+            second,
+        )?;
+        if score == REJECTED_POSE_SCORE {
+            return Some(score);
+        }
+        Some(
+            score
+                + self.weight * self.mean_propensity(translation, rotation, rec_nmodes, lig_nmodes),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::structure::parse_structure;
+
+    struct Constant;
+
+    impl Score for Constant {
+        fn energy(&self, _: &[f64], _: &Quaternion, _: &[f64], _: &[f64]) -> f64 {
+            10.0
+        }
+    }
+
+    #[test]
+    fn test_interface_propensity() {
+        let table = parse_propensities("# Test table\nTRP 1.0\nlys -0.5\n").unwrap();
+        assert_eq!(table["LYS"], -0.5);
+        assert!(parse_propensities("TRP high\n").is_err());
+        assert_eq!(default_propensities().len(), 20);
+
+        let receptor = parse_structure(
+            "\
+ATOM      1  CA  TRP A   1       0.000   0.000   0.000  1.00  0.00           C\n\
+ATOM      2  CA  LYS A   2       0.000  20.000   0.000  1.00  0.00           C\n",
+            1,
+        )
+        .unwrap()
+        .unwrap();
+        let ligand = parse_structure(
+            "\
+ATOM      1  CA  LYS B   1       0.000   0.000   0.000  1.00  0.00           C\n\
+ATOM      2   P   DA B   2      20.000   0.000   0.000  1.00  0.00           P\n",
+            1,
+        )
+        .unwrap()
+        .unwrap();
+        let scoring = InterfacePropensity::new(
+            Box::new(Constant),
+            PropensityPartner::new(&receptor, Nmodes::default(), 0, &table),
+            PropensityPartner::new(&ligand, Nmodes::default(), 0, &table),
+            false,
+            2.0,
+            5.0,
+        );
+        let rotation = Quaternion::default();
+        // TRP and LYS at the interface
+        assert_eq!(scoring.energy(&[0.0, 3.0, 0.0], &rotation, &[], &[]), 10.5);
+        // Both LYS at the interface
+        assert_eq!(scoring.energy(&[0.0, 23.0, 0.0], &rotation, &[], &[]), 9.0);
+        // TRP against a nucleotide
+        assert_eq!(
+            scoring.energy(&[-20.0, 3.0, 0.0], &rotation, &[], &[]),
+            12.0
+        );
+        // No interface
+        assert_eq!(scoring.energy(&[0.0, 50.0, 0.0], &rotation, &[], &[]), 10.0);
+    }
+}
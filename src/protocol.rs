@@ -0,0 +1,189 @@
+use super::clustering::{cluster_poses, Cluster};
+use super::output::GSOEntry;
+use super::qt::Quaternion;
+use super::scoring::Score;
+use super::GSO;
+use rand::prelude::StdRng;
+use rand::Rng;
+
+// Two-stage coarse-to-fine docking of a swarm. A global stage searches the rigid-body poses with
+// a fast coarse scoring function and larger steps, then the starting poses of the swarm are
+// replaced by poses spread around the representatives of its best clusters, to be optimized in a
+// fine stage with the atomic scoring function and, if enabled, ANM.
+
+#[derive(Debug, Clone)]
+pub struct CoarseToFine {
+    pub coarse_steps: u32,
+    // Translation step of the glowworms in the global stage
+    pub translation_step: f64,
+    // Number of best clusters of the global stage seeding the fine stage
+    pub top_clusters: usize,
+    pub translation_cutoff: f64,
+    pub rotation_cutoff: f64,
+    // Largest translation in Angstroms and rotation in radians of the poses around a cluster
+    pub spread: f64,
+    pub rotation_spread: f64,
+}
+
+impl CoarseToFine {
+    pub fn new(
+        coarse_steps: u32,
+        translation_step: f64,
+        top_clusters: usize,
+        translation_cutoff: f64,
+        rotation_cutoff: f64,
+        spread: f64,
+        rotation_spread: f64,
+    ) -> Self {
+        CoarseToFine {
+            coarse_steps,
+            translation_step,
+            top_clusters,
+            translation_cutoff,
+            rotation_cutoff,
+            spread,
+            rotation_spread,
+        }
+    }
+
+    // Global stage from the rigid-body part of the given poses, saving its GSO outputs in the
+    // given directory. Returns the last poses of the swarm.
+    pub fn coarse_search(
+        &self,
+        positions: &[Vec<f64>],
+        seed: u64,
+        scoring: &Box<dyn Score>,
+        output_directory: String,
+    ) -> Vec<GSOEntry> {
+        let rigid: Vec<Vec<f64>> = positions.iter().map(|p| p[..7].to_vec()).collect();
+        let mut gso = GSO::new(&rigid, seed, scoring, false, 0, 0, output_directory);
+        for glowworm in gso.swarm.glowworms.iter_mut() {
+            glowworm.translation_step = self.translation_step;
+        }
+        gso.run(self.coarse_steps);
+        gso.swarm.entries()
+    }
+
+    // Best clusters of the poses of the global stage, best first
+    pub fn best_clusters(&self, entries: &[GSOEntry]) -> Vec<Cluster> {
+        let mut clusters = cluster_poses(entries, self.translation_cutoff, self.rotation_cutoff);
+        clusters.truncate(self.top_clusters);
+        clusters
+    }
+
+    // Starting poses of the fine stage, taken in turns around the representatives of the given
+    // clusters. The representatives are kept as they are, the rest are randomly translated and
+    // rotated up to the spreads. Normal mode extents start from zero.
+    pub fn focused_positions(
+        &self,
+        entries: &[GSOEntry],
+        clusters: &[Cluster],
+        num_positions: usize,
+        num_nmodes: usize,
+        rng: &mut StdRng,
+    ) -> Vec<Vec<f64>> {
+        let mut positions: Vec<Vec<f64>> = Vec::with_capacity(num_positions);
+        for i in 0..num_positions {
+            let representative = &entries[clusters[i % clusters.len()].representative];
+            let mut translation = representative.translation();
+            let mut rotation = representative.rotation();
+            if i >= clusters.len() {
+                let shift = random_in_sphere(rng);
+                for k in 0..3 {
+                    translation[k] += self.spread * shift[k];
+                }
+                let axis = random_in_sphere(rng);
+                let turn = Quaternion::from_rotation_vector(&[
+                    self.rotation_spread * axis[0],
+                    self.rotation_spread * axis[1],
+                    self.rotation_spread * axis[2],
+                ]);
+                rotation = turn * rotation;
+                rotation.normalize();
+            }
+            let mut position = translation.to_vec();
+            position.extend([rotation.w, rotation.x, rotation.y, rotation.z]);
+            position.resize(7 + num_nmodes, 0.0);
+            positions.push(position);
+        }
+        positions
+    }
+}
+
+// Uniform inside the unit sphere
+fn random_in_sphere(rng: &mut StdRng) -> [f64; 3] {
+    loop {
+        let point = [
+            rng.gen_range(-1.0, 1.0),
+            rng.gen_range(-1.0, 1.0),
+            rng.gen_range(-1.0, 1.0),
+        ];
+        if point.iter().map(|x: &f64| x * x).sum::<f64>() <= 1.0 {
+            return point;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::SeedableRng;
+
+    struct Height;
+
+    impl Score for Height {
+        fn energy(&self, translation: &[f64], _: &Quaternion, _: &[f64], _: &[f64]) -> f64 {
+            translation[2]
+        }
+    }
+
+    #[test]
+    fn test_coarse_to_fine() {
+        let protocol = CoarseToFine::new(3, 1.0, 2, 4.0, 0.1, 2.0, 0.2);
+        let scoring: Box<dyn Score> = Box::new(Height);
+        let positions = vec![
+            vec![0.0, 0.0, 10.0, 1.0, 0.0, 0.0, 0.0, 0.5],
+            vec![0.0, 0.0, 11.0, 1.0, 0.0, 0.0, 0.0, 0.5],
+            vec![20.0, 0.0, 5.0, 1.0, 0.0, 0.0, 0.0, 0.5],
+            vec![-20.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 0.5],
+        ];
+        let directory = std::env::temp_dir().join("lightdock_test_coarse_to_fine");
+        std::fs::create_dir_all(&directory).unwrap();
+        let entries = protocol.coarse_search(
+            &positions,
+            0,
+            &scoring,
+            directory.to_str().unwrap().to_string(),
+        );
+        std::fs::remove_dir_all(&directory).unwrap();
+        assert_eq!(entries.len(), 4);
+        assert!(entries.iter().all(|entry| entry.position.len() == 7));
+
+        // The two glowworms close to each other are one cluster and the worst one is left out
+        let clusters = protocol.best_clusters(&entries);
+        assert_eq!(clusters.len(), 2);
+        assert_eq!(entries[clusters[0].representative].translation()[0], 0.0);
+        assert_eq!(entries[clusters[1].representative].translation()[0], 20.0);
+
+        let mut rng: StdRng = SeedableRng::seed_from_u64(0);
+        let focused = protocol.focused_positions(&entries, &clusters, 6, 1, &mut rng);
+        assert_eq!(focused.len(), 6);
+        assert_eq!(
+            focused[0][..7],
+            entries[clusters[0].representative].position[..7]
+        );
+        for (i, position) in focused.iter().enumerate() {
+            let representative = &entries[clusters[i % 2].representative];
+            let center = representative.translation();
+            let distance = (0..3)
+                .map(|k| (position[k] - center[k]).powi(2))
+                .sum::<f64>()
+                .sqrt();
+            assert!(distance <= protocol.spread);
+            let rotation = Quaternion::new(position[3], position[4], position[5], position[6]);
+            assert!(rotation.distance(representative.rotation()) < 0.1);
+            assert_eq!(position.len(), 8);
+            assert_eq!(position[7], 0.0);
+        }
+    }
+}
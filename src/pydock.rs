@@ -1,8 +1,21 @@
-use super::constants::{INTERFACE_CUTOFF2, MEMBRANE_PENALTY_SCORE};
+use super::constants::{MEMBRANE_PENALTY_SCORE, REJECTED_POSE_SCORE};
+use super::decomposition::{anm_extents, pose_coordinates, push_residue, ResidueEnergies};
+use super::nmodes::Nmodes;
+use super::parameters::{AtomOverride, AtomParameters, OverrideIndex, ParameterOverrides};
+use super::policy::{nucleic_variants, PolicyReport, ResidueAction, ResiduePolicy};
+use super::pose::select_nmodes;
+use super::profile::{add_pose_time, pose_timer};
 use super::qt::Quaternion;
-use super::scoring::{membrane_intersection, satisfied_restraints, Score};
+use super::registry::ScoringInput;
+use super::scoring::{
+    hotspot_bias, in_interface, interface_residues, membrane_intersection, restraints_satisfaction,
+    ClashFilter, Interface, RestraintsMetric, Score, TermWeights, Workspace,
+};
+use super::solvation::{solvation_radius, Desolvation, SolvationClass, SolvationModel};
+use super::structure::is_hydrogen;
 use pdbtbx::PDB;
-use std::collections::HashMap;
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
 
 use log::{info, warn};
 
@@ -64,6 +77,13 @@ pub fn atoms_in_residues(residue_name: &str) -> &'static [&'static str] {
 }
 
 lazy_static! {
+    // Residues with built-in AMBER types
+    static ref RESIDUES: HashSet<&'static str> = AMBER_TYPES
+        .keys()
+        .filter_map(|atom_id| atom_id.split_once('-').map(|(residue, _)| residue))
+        .filter(|residue| *residue != "*")
+        .collect();
+
     static ref VDW_CHARGES: HashMap<&'static str, f64> = hashmap![
         "IP" => 0.00277, "HS" => 0.0157, "HP" => 0.0157, "Na" => 0.00277, "N*" => 0.17, "Li" => 0.0183, "HO" => 0.0,
         "Rb" => 0.00017, "HC" => 0.0157, "HA" => 0.015, "O3" => 0.21, "CQ" => 0.086, "C*" => 0.086, "NA" => 0.17,
@@ -239,40 +259,70 @@ lazy_static! {
 pub struct PYDOCKDockingModel {
     pub atoms: Vec<usize>,
     pub coordinates: Vec<[f64; 3]>,
+    pub heavy_atoms: Vec<usize>,
     pub membrane: Vec<usize>,
     pub active_restraints: HashMap<String, Vec<usize>>,
     pub passive_restraints: HashMap<String, Vec<usize>>,
+    pub hotspots: HashMap<String, Vec<usize>>,
+    pub hotspot_weights: HashMap<String, f64>,
+    // Scoring weight of every atom, the one of its residue or 1.0
+    pub weights: Vec<f64>,
     pub num_anm: usize,
-    pub nmodes: Vec<f64>,
+    pub nmodes: Nmodes,
+    // Residue IDs and the residue of every atom, see decomposition::push_residue
+    pub residues: Vec<String>,
+    pub atom_residues: Vec<usize>,
     pub vdw_radii: Vec<f64>,
     pub vdw_charges: Vec<f64>,
     pub ele_charges: Vec<f64>,
+    // Solvation class and radius of every heavy atom
+    pub solvation: Vec<Option<(SolvationClass, f64)>>,
+    // Atoms with parameters from the overrides
+    pub num_overridden: usize,
 }
 
 impl<'a> PYDOCKDockingModel {
-    fn new(
+    pub fn new(
         structure: &'a PDB,
         active_restraints: &'a [String],
         passive_restraints: &'a [String],
-        nmodes: &[f64],
+        hotspots: &'a HashMap<String, f64>,
+        weights: &'a HashMap<String, f64>,
+        nmodes: &Nmodes,
         num_anm: usize,
+        overrides: &[AtomOverride],
+        policy: ResiduePolicy,
     ) -> PYDOCKDockingModel {
         let mut model = PYDOCKDockingModel {
             atoms: Vec::new(),
             coordinates: Vec::new(),
+            heavy_atoms: Vec::new(),
             membrane: Vec::new(),
             active_restraints: HashMap::new(),
             passive_restraints: HashMap::new(),
-            nmodes: nmodes.to_owned(),
+            hotspots: HashMap::new(),
+            hotspot_weights: hotspots.clone(),
+            weights: Vec::new(),
+            nmodes: nmodes.clone(),
+            residues: Vec::new(),
+            atom_residues: Vec::new(),
             num_anm,
             vdw_radii: Vec::new(),
             vdw_charges: Vec::new(),
             ele_charges: Vec::new(),
+            solvation: Vec::new(),
+            num_overridden: 0,
         };
 
         let mut atom_index: u64 = 0;
+        let overrides = OverrideIndex::new(overrides);
+        // Indexes in the full structure of the atoms kept in the model
+        let mut kept: Vec<usize> = Vec::new();
+        let mut structure_index: usize = 0;
+        let mut report = PolicyReport::default();
         for chain in structure.chains() {
-            for residue in chain.residues() {
+            let variants = nucleic_variants(chain, |name| RESIDUES.contains(name));
+            for (residue, variant) in chain.residues().zip(variants.iter()) {
                 let res_name = match residue.name() {
                     Some(name) => name,
                     None => panic!("PDB Parsing Error: Residue name error"),
@@ -281,12 +331,44 @@ impl<'a> PYDOCKDockingModel {
                 if let Some(c) = residue.insertion_code() {
                     res_id.push_str(c);
                 }
+                // Terminal nucleotides are scored with their terminal parameters
+                let res_name = variant.as_deref().unwrap_or(res_name);
+                // Residues without built-in parameters are supported if given by the overrides
+                let supported = RESIDUES.contains(res_name)
+                    || residue.atoms().any(|atom| {
+                        overrides
+                            .lookup(&res_id, atom.name().trim())
+                            .atom_type
+                            .is_some()
+                    });
+                let action = policy.action(
+                    "PYDOCK",
+                    &res_id,
+                    res_name,
+                    supported,
+                    |name| RESIDUES.contains(name),
+                    &mut report,
+                );
+                if action == ResidueAction::Drop {
+                    structure_index += residue.atom_count();
+                    continue;
+                }
 
                 for atom in residue.atoms() {
+                    structure_index += 1;
+                    let (scored_name, atom_name) = action.names(res_name, atom.name().trim());
+                    if action != ResidueAction::Keep
+                        && !AMBER_TYPES.contains_key(&*format!("{}-{}", scored_name, atom_name))
+                    {
+                        report.dropped_atoms += 1;
+                        continue;
+                    }
                     // Membrane beads MMB.BJ
                     let rec_atom_type = format!("{}{}", res_name, atom.name());
                     if rec_atom_type == "MMBBJ" {
                         model.membrane.push(atom_index as usize);
+                    } else if !is_hydrogen(residue, atom) {
+                        model.heavy_atoms.push(atom_index as usize);
                     }
 
                     if active_restraints.contains(&res_id) {
@@ -315,114 +397,232 @@ impl<'a> PYDOCKDockingModel {
                         }
                     }
 
-                    let atom_name = atom.name().trim();
+                    if hotspots.contains_key(&res_id) {
+                        model
+                            .hotspots
+                            .entry(res_id.to_string())
+                            .or_default()
+                            .push(atom_index as usize);
+                    }
+
+                    let res_name = scored_name;
                     let mut atom_id = format!("{}-{}", res_name, atom_name);
 
+                    let parameters = overrides.lookup(&res_id, atom_name);
+                    if parameters != AtomParameters::default() {
+                        model.num_overridden += 1;
+                    }
+
                     // Calculate AMBER type
-                    let amber_type = match AMBER_TYPES.get(&*atom_id) {
-                        Some(&amber) => amber,
-                        _ => {
-                            if atom_name == "H1" || atom_name == "H2" || atom_name == "H3" {
-                                atom_id = format!("{}-H", res_name);
-                                match AMBER_TYPES.get(&*atom_id) {
-                                    Some(&amber) => amber,
-                                    _ => panic!("PYDOCK Error: Atom [{:?}] not supported", atom_id),
-                                }
-                            } else {
-                                warn!(
-                                    "PYDOCK Warning: Atom [{:?}] not supported, trying generic",
-                                    atom_id
-                                );
-                                let atom_element = match atom_name.chars().nth(0) {
-                                    Some(element) => element,
-                                    _ => panic!("PYDOCK Error: Atom element could not be guessed from [{:?}]", atom_name),
-                                };
-                                atom_id = format!("*-{}", atom_element);
-                                match AMBER_TYPES.get(&*atom_id) {
-                                    Some(&amber) => amber,
-                                    _ => panic!("PYDOCK Error: Atom [{:?}] not supported", atom_id),
+                    let amber_type: &str = match &parameters.atom_type {
+                        Some(amber) => amber,
+                        None => match AMBER_TYPES.get(&*atom_id) {
+                            Some(&amber) => amber,
+                            _ => {
+                                if atom_name == "H1" || atom_name == "H2" || atom_name == "H3" {
+                                    atom_id = format!("{}-H", res_name);
+                                    match AMBER_TYPES.get(&*atom_id) {
+                                        Some(&amber) => amber,
+                                        _ => panic!(
+                                            "PYDOCK Error: Atom [{:?}] not supported",
+                                            atom_id
+                                        ),
+                                    }
+                                } else {
+                                    warn!(
+                                        "PYDOCK Warning: Atom [{:?}] not supported, trying generic",
+                                        atom_id
+                                    );
+                                    let atom_element = match atom_name.chars().nth(0) {
+                                        Some(element) => element,
+                                        _ => panic!("PYDOCK Error: Atom element could not be guessed from [{:?}]", atom_name),
+                                    };
+                                    atom_id = format!("*-{}", atom_element);
+                                    match AMBER_TYPES.get(&*atom_id) {
+                                        Some(&amber) => amber,
+                                        _ => panic!(
+                                            "PYDOCK Error: Atom [{:?}] not supported",
+                                            atom_id
+                                        ),
+                                    }
                                 }
                             }
-                        }
+                        },
                     };
 
                     // Assign electrostatics charge
-                    let ele_charge = match ELE_CHARGES.get(&*atom_id) {
-                        Some(&charge) => charge,
-                        _ => match NT_ELE_CHARGES.get(&*atom_id) {
+                    let ele_charge = match parameters.charge {
+                        Some(charge) => charge,
+                        None => match ELE_CHARGES.get(&*atom_id) {
                             Some(&charge) => charge,
-                            _ => panic!(
-                                "PYDOCK Error: Atom [{:?}] electrostatics charge not found",
-                                atom_id
-                            ),
+                            _ => match NT_ELE_CHARGES.get(&*atom_id) {
+                                Some(&charge) => charge,
+                                _ => panic!(
+                                    "PYDOCK Error: Atom [{:?}] electrostatics charge not found",
+                                    atom_id
+                                ),
+                            },
                         },
                     };
                     model.ele_charges.push(ele_charge);
 
                     // Assign VDW charge and radius
-                    let vdw_charge = match VDW_CHARGES.get(amber_type) {
-                        Some(&charge) => charge,
+                    let vdw_charge = match parameters
+                        .vdw_charge
+                        .or_else(|| VDW_CHARGES.get(amber_type).copied())
+                    {
+                        Some(charge) => charge,
                         _ => panic!("PYDOCK Error: Atom [{:?}] VDW charge not found", atom_id),
                     };
                     model.vdw_charges.push(vdw_charge);
-                    let vdw_radius = match VDW_RADII.get(amber_type) {
-                        Some(&radius) => radius,
+                    let vdw_radius = match parameters
+                        .vdw_radius
+                        .or_else(|| VDW_RADII.get(amber_type).copied())
+                    {
+                        Some(radius) => radius,
                         _ => panic!("PYDOCK Error: Atom [{:?}] VDW radius not found", atom_id),
                     };
                     model.vdw_radii.push(vdw_radius);
+                    model.solvation.push(
+                        SolvationClass::of(res_name, atom_name)
+                            .map(|class| (class, solvation_radius(atom_name))),
+                    );
 
                     model.coordinates.push([atom.x(), atom.y(), atom.z()]);
+                    push_residue(&mut model.residues, &mut model.atom_residues, &res_id);
+                    model
+                        .weights
+                        .push(weights.get(&res_id).copied().unwrap_or(1.0));
+                    kept.push(structure_index - 1);
                     atom_index += 1;
                 }
             }
         }
+        report.log("PYDOCK");
+
+        // Normal modes are stored as (num_anm, num_atoms, 3), keep only the selected atoms
+        if kept.len() < structure_index && num_anm > 0 && !nmodes.is_empty() {
+            model.nmodes = select_nmodes(nmodes, num_anm, structure_index, &kept).into();
+        }
         info!("Atoms read: {}", atom_index);
+        if model.num_overridden > 0 {
+            info!("Atoms with overridden parameters: {}", model.num_overridden);
+        }
         model
     }
+
+    // Heavy atoms with their surface exposed in the structure
+    pub fn solvation_model(&self) -> SolvationModel {
+        let atoms: Vec<(usize, f64, SolvationClass, f64)> = self
+            .solvation
+            .iter()
+            .enumerate()
+            .filter_map(|(i, solvation)| {
+                solvation.map(|(class, radius)| (i, radius, class, self.weights[i]))
+            })
+            .collect();
+        SolvationModel::new(&self.coordinates, &atoms)
+    }
 }
 
 pub struct PYDOCK {
     pub receptor: PYDOCKDockingModel,
     pub ligand: PYDOCKDockingModel,
     pub use_anm: bool,
+    pub clash_filter: Option<ClashFilter>,
+    pub restraints_metric: RestraintsMetric,
+    pub term_weights: TermWeights,
+    // Surfaces of the unbound partners if the desolvation term is weighted
+    pub desolvation: Option<Desolvation>,
+    pub workspace: RefCell<Workspace>,
 }
 
 impl<'a> PYDOCK {
     pub fn new(
-        receptor: PDB,
-        rec_active_restraints: Vec<String>,
-        rec_passive_restraints: Vec<String>,
-        rec_nmodes: Vec<f64>,
-        rec_num_anm: usize,
-        ligand: PDB,
-        lig_active_restraints: Vec<String>,
-        lig_passive_restraints: Vec<String>,
-        lig_nmodes: Vec<f64>,
-        lig_num_anm: usize,
-        use_anm: bool,
+        input: ScoringInput,
+        parameters: Option<ParameterOverrides>,
+        single_precision: bool,
+        term_weights: TermWeights,
     ) -> Box<dyn Score + 'a> {
-        let d = PYDOCK {
+        let ScoringInput {
+            receptor,
+            rec_active_restraints,
+            rec_passive_restraints,
+            rec_hotspots,
+            rec_weights,
+            rec_nmodes,
+            rec_num_anm,
+            ligand,
+            lig_active_restraints,
+            lig_passive_restraints,
+            lig_hotspots,
+            lig_weights,
+            lig_nmodes,
+            lig_num_anm,
+            use_anm,
+            clash_filter,
+            restraints_metric,
+            policy,
+        } = input;
+        // Same signature as DNA::new
+        if single_precision {
+            warn!("pyDock poses are always scored in double precision");
+        }
+        let parameters = parameters.unwrap_or_default();
+        let mut d = PYDOCK {
             receptor: PYDOCKDockingModel::new(
                 &receptor,
                 &rec_active_restraints,
                 &rec_passive_restraints,
+                &rec_hotspots,
+                &rec_weights,
                 &rec_nmodes,
                 rec_num_anm,
+                &parameters.receptor,
+                policy,
             ),
             ligand: PYDOCKDockingModel::new(
                 &ligand,
                 &lig_active_restraints,
                 &lig_passive_restraints,
+                &lig_hotspots,
+                &lig_weights,
                 &lig_nmodes,
                 lig_num_anm,
+                &parameters.ligand,
+                policy,
             ),
             use_anm,
+            clash_filter,
+            restraints_metric,
+            term_weights,
+            desolvation: None,
+            workspace: RefCell::new(Workspace::default()),
         };
+        d.workspace = RefCell::new(Workspace::new(
+            &d.receptor.coordinates,
+            &d.ligand.coordinates,
+        ));
+        if let Some(filter) = &d.clash_filter {
+            d.workspace.get_mut().clash_grid =
+                filter.grid(&d.receptor.coordinates, &d.receptor.heavy_atoms);
+        }
+        if term_weights.desolvation != 0.0 {
+            d.desolvation = Some(Desolvation::new(
+                d.receptor.solvation_model(),
+                d.ligand.solvation_model(),
+            ));
+            info!(
+                "pyDock desolvation weighted by {}",
+                term_weights.desolvation
+            );
+        }
         Box::new(d)
     }
 }
 
 impl Score for PYDOCK {
+    #[allow(clippy::manual_clamp, clippy::neg_multiply)]
     fn energy(
         &self,
         translation: &[f64],
@@ -430,17 +630,29 @@ impl Score for PYDOCK {
         rec_nmodes: &[f64],
         lig_nmodes: &[f64],
     ) -> f64 {
-        // Clone receptor coordinates
-        let mut receptor_coordinates: Vec<[f64; 3]> = self.receptor.coordinates.clone();
+        // Start from the original coordinates in the preallocated buffers
+        let mut workspace = self.workspace.borrow_mut();
+        workspace.reset(
+            &self.receptor.coordinates,
+            &self.ligand.coordinates,
+            self.use_anm && self.receptor.num_anm > 0,
+        );
+        let Workspace {
+            receptor_coordinates,
+            ligand_coordinates,
+            interface_receptor,
+            interface_ligand,
+            clash_grid,
+            ..
+        } = &mut *workspace;
         let rec_num_atoms = receptor_coordinates.len();
-        // Clone ligand coordinates
-        let mut ligand_coordinates: Vec<[f64; 3]> = self.ligand.coordinates.clone();
         let lig_num_atoms = ligand_coordinates.len();
 
+        let timer = pose_timer();
         // Get the proper ligand pose
         for (i_atom, coordinate) in ligand_coordinates.iter_mut().enumerate() {
             // First rotate
-            let rotated_coordinate = rotation.rotate(coordinate.to_vec());
+            let rotated_coordinate = rotation.rotate_point(coordinate);
             // Then tranlate
             coordinate[0] = rotated_coordinate[0] + translation[0];
             coordinate[1] = rotated_coordinate[1] + translation[1];
@@ -477,16 +689,32 @@ impl Score for PYDOCK {
                 }
             }
         }
-        // Calculate scoring and interface
-        let mut interface_receptor: Vec<usize> = vec![0; receptor_coordinates.len()];
-        let mut interface_ligand: Vec<usize> = vec![0; ligand_coordinates.len()];
+        add_pose_time(timer);
+
+        // Discard hopeless poses before the full evaluation
+        if let Some(filter) = &self.clash_filter {
+            // The grid is only hashed again if the receptor moved
+            if self.use_anm && self.receptor.num_anm > 0 {
+                filter.update_grid(clash_grid, receptor_coordinates, &self.receptor.heavy_atoms);
+            }
+            if filter.is_clashing(
+                clash_grid,
+                receptor_coordinates,
+                ligand_coordinates,
+                &self.ligand.heavy_atoms,
+            ) {
+                return REJECTED_POSE_SCORE;
+            }
+        }
 
+        // Calculate scoring and interface
         let mut total_elec = 0.0;
         let mut total_vdw = 0.0;
         for (i, ra) in receptor_coordinates.iter().enumerate() {
             let x1 = ra[0];
             let y1 = ra[1];
             let z1 = ra[2];
+            let weighta = self.receptor.weights[i];
             for (j, la) in ligand_coordinates.iter().enumerate() {
                 let distance2 = (x1 - la[0]) * (x1 - la[0])
                     + (y1 - la[1]) * (y1 - la[1])
@@ -502,7 +730,7 @@ impl Score for PYDOCK {
                     if atom_elec < ELEC_MIN_CUTOFF {
                         atom_elec = ELEC_MIN_CUTOFF;
                     }
-                    total_elec += atom_elec;
+                    total_elec += atom_elec * weighta * self.ligand.weights[j];
                 }
 
                 // Van der Waals energy
@@ -515,32 +743,169 @@ impl Score for PYDOCK {
                     if k > VDW_CUTOFF {
                         k = VDW_CUTOFF;
                     }
-                    total_vdw += k;
+                    total_vdw += k * weighta * self.ligand.weights[j];
                 }
 
                 // Interface calculation
-                if distance2 <= INTERFACE_CUTOFF2 {
+                if in_interface(distance2) {
                     interface_receptor[i] = 1;
                     interface_ligand[j] = 1;
                 }
             }
         }
         total_elec = total_elec * FACTOR / EPSILON;
-        let score = (total_elec + total_vdw) * -1.0;
+        let mut score = (self.term_weights.electrostatics * total_elec
+            + self.term_weights.vdw * total_vdw)
+            * -1.0;
+        if let Some(desolvation) = &self.desolvation {
+            score -= self.term_weights.desolvation
+                * desolvation.energy(receptor_coordinates, ligand_coordinates, rotation);
+        }
 
         // Bias the scoring depending on satisfied restraints
-        let perc_receptor_restraints: f64 =
-            satisfied_restraints(&interface_receptor, &self.receptor.active_restraints);
-        let perc_ligand_restraints: f64 =
-            satisfied_restraints(&interface_ligand, &self.ligand.active_restraints);
+        let perc_receptor_restraints: f64 = self.restraints_metric.satisfaction(
+            interface_receptor,
+            &self.receptor.active_restraints,
+            receptor_coordinates,
+            ligand_coordinates,
+        );
+        let perc_ligand_restraints: f64 = self.restraints_metric.satisfaction(
+            interface_ligand,
+            &self.ligand.active_restraints,
+            ligand_coordinates,
+            receptor_coordinates,
+        );
+        // Weighted hot-spot residues at the interface
+        let receptor_hotspots: f64 = hotspot_bias(
+            interface_receptor,
+            &self.receptor.hotspots,
+            &self.receptor.hotspot_weights,
+        );
+        let ligand_hotspots: f64 = hotspot_bias(
+            interface_ligand,
+            &self.ligand.hotspots,
+            &self.ligand.hotspot_weights,
+        );
         // Take into account membrane intersection
         let mut membrane_penalty: f64 = 0.0;
-        let intersection = membrane_intersection(&interface_receptor, &self.receptor.membrane);
+        let intersection = membrane_intersection(interface_receptor, &self.receptor.membrane);
         if intersection > 0.0 {
             membrane_penalty = MEMBRANE_PENALTY_SCORE * intersection;
         }
 
-        score + perc_receptor_restraints * score + perc_ligand_restraints * score - membrane_penalty
+        score
+            + perc_receptor_restraints * score
+            + perc_ligand_restraints * score
+            + receptor_hotspots * score
+            + ligand_hotspots * score
+            - membrane_penalty
+    }
+
+    fn energy_with_interface(
+        &self,
+        translation: &[f64],
+        rotation: &Quaternion,
+        rec_nmodes: &[f64],
+        lig_nmodes: &[f64],
+    ) -> (f64, Option<Interface>) {
+        let energy = self.energy(translation, rotation, rec_nmodes, lig_nmodes);
+        // The evaluation leaves the interface of the pose in the workspace
+        let workspace = self.workspace.borrow();
+        let interface = Interface {
+            receptor: interface_residues(
+                &workspace.interface_receptor,
+                &self.receptor.atom_residues,
+                &self.receptor.residues,
+            ),
+            ligand: interface_residues(
+                &workspace.interface_ligand,
+                &self.ligand.atom_residues,
+                &self.ligand.residues,
+            ),
+        };
+        (energy, Some(interface))
+    }
+
+    fn restraints_satisfaction(
+        &self,
+        translation: &[f64],
+        rotation: &Quaternion,
+        rec_nmodes: &[f64],
+        lig_nmodes: &[f64],
+    ) -> Option<f64> {
+        if self.receptor.active_restraints.is_empty() && self.ligand.active_restraints.is_empty() {
+            return None;
+        }
+        // The evaluation leaves the interface of the pose in the workspace
+        self.energy(translation, rotation, rec_nmodes, lig_nmodes);
+        let workspace = self.workspace.borrow();
+        restraints_satisfaction(
+            &workspace.interface_receptor,
+            &self.receptor.active_restraints,
+            &workspace.interface_ligand,
+            &self.ligand.active_restraints,
+        )
+    }
+
+    fn residue_energies(
+        &self,
+        translation: &[f64],
+        rotation: &Quaternion,
+        rec_nmodes: &[f64],
+        lig_nmodes: &[f64],
+    ) -> Option<ResidueEnergies> {
+        let (receptor_coordinates, ligand_coordinates) = pose_coordinates(
+            &self.receptor.coordinates,
+            &self.receptor.nmodes,
+            anm_extents(self.use_anm, self.receptor.num_anm, rec_nmodes),
+            &self.ligand.coordinates,
+            &self.ligand.nmodes,
+            anm_extents(self.use_anm, self.ligand.num_anm, lig_nmodes),
+            translation,
+            rotation,
+        );
+        // Desolvation is not split by residue pairs but kept as the constant part
+        let desolvation = match &self.desolvation {
+            Some(desolvation) => {
+                -self.term_weights.desolvation
+                    * desolvation.energy(&receptor_coordinates, &ligand_coordinates, rotation)
+            }
+            None => 0.0,
+        };
+        let mut energies =
+            ResidueEnergies::new(&self.receptor.residues, &self.ligand.residues, desolvation);
+        for (i, ra) in receptor_coordinates.iter().enumerate() {
+            for (j, la) in ligand_coordinates.iter().enumerate() {
+                let distance2 = (ra[0] - la[0]) * (ra[0] - la[0])
+                    + (ra[1] - la[1]) * (ra[1] - la[1])
+                    + (ra[2] - la[2]) * (ra[2] - la[2]);
+                let mut energy = 0.0;
+                if distance2 <= ELEC_DIST_CUTOFF2 {
+                    energy += (self.receptor.ele_charges[i] * self.ligand.ele_charges[j]
+                        / distance2)
+                        .clamp(ELEC_MIN_CUTOFF, ELEC_MAX_CUTOFF)
+                        * FACTOR
+                        / EPSILON
+                        * self.term_weights.electrostatics;
+                }
+                if distance2 <= VDW_DIST_CUTOFF2 {
+                    let vdw_energy =
+                        (self.receptor.vdw_charges[i] * self.ligand.vdw_charges[j]).sqrt();
+                    let vdw_radius = self.receptor.vdw_radii[i] + self.ligand.vdw_radii[j];
+                    let p6 = vdw_radius.powi(6) / distance2.powi(3);
+                    energy +=
+                        (vdw_energy * (p6 * p6 - 2.0 * p6)).min(VDW_CUTOFF) * self.term_weights.vdw;
+                }
+                if energy != 0.0 {
+                    energies.add(
+                        self.receptor.atom_residues[i],
+                        self.ligand.atom_residues[j],
+                        -energy * self.receptor.weights[i] * self.ligand.weights[j],
+                    );
+                }
+            }
+        }
+        Some(energies)
     }
 }
 
@@ -567,22 +932,116 @@ mod tests {
             pdbtbx::open(&ligand_filename, pdbtbx::StrictnessLevel::Strict).unwrap();
 
         let scoring = PYDOCK::new(
-            receptor,
-            Vec::new(),
-            Vec::new(),
-            Vec::new(),
-            0,
-            ligand,
-            Vec::new(),
-            Vec::new(),
-            Vec::new(),
-            0,
+            ScoringInput::new(receptor, ligand, ResiduePolicy::Strict),
+            None,
             false,
+            TermWeights::default(),
         );
 
         let translation = vec![0., 0., 0.];
         let rotation = Quaternion::default();
         let energy = scoring.energy(&translation, &rotation, &Vec::new(), &Vec::new());
         assert_eq!(energy, -364.88126358158974);
+
+        // Residue contributions add up to the energy of the pose
+        let energies = scoring
+            .residue_energies(&translation, &rotation, &Vec::new(), &Vec::new())
+            .unwrap();
+        assert!((energies.total() - energy).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_1azp_desolvation() {
+        let cargo_path = match env::var("CARGO_MANIFEST_DIR") {
+            Ok(val) => val,
+            Err(_) => String::from("."),
+        };
+        let test_path: String = format!("{}/tests/1azp", cargo_path);
+        let scoring = |term_weights: TermWeights| {
+            let (receptor, _errors) = pdbtbx::open(
+                format!("{}/1azp_receptor.pdb", test_path),
+                pdbtbx::StrictnessLevel::Strict,
+            )
+            .unwrap();
+            let (ligand, _errors) = pdbtbx::open(
+                format!("{}/1azp_ligand.pdb", test_path),
+                pdbtbx::StrictnessLevel::Strict,
+            )
+            .unwrap();
+            PYDOCK::new(
+                ScoringInput::new(receptor, ligand, ResiduePolicy::Strict),
+                None,
+                false,
+                term_weights,
+            )
+        };
+        let translation = vec![0., 0., 0.];
+        let rotation = Quaternion::default();
+        let reference = scoring(TermWeights::pydock());
+        let energy = reference.energy(&translation, &rotation, &Vec::new(), &Vec::new());
+        let without_desolvation = scoring(TermWeights {
+            desolvation: 0.0,
+            ..TermWeights::pydock()
+        })
+        .energy(&translation, &rotation, &Vec::new(), &Vec::new());
+        // The interface of the complex buries surface, changing its solvation energy
+        assert!(energy.is_finite());
+        assert!((energy - without_desolvation).abs() > 1e-3);
+
+        // Desolvation is the constant part of the residue contributions
+        let energies = reference
+            .residue_energies(&translation, &rotation, &Vec::new(), &Vec::new())
+            .unwrap();
+        assert!((energies.total() - energy).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_parameter_overrides() {
+        let cargo_path = match env::var("CARGO_MANIFEST_DIR") {
+            Ok(val) => val,
+            Err(_) => String::from("."),
+        };
+        let ligand_filename: String = format!("{}/tests/1azp/1azp_ligand.pdb", cargo_path);
+        let (ligand, _errors) =
+            pdbtbx::open(&ligand_filename, pdbtbx::StrictnessLevel::Strict).unwrap();
+
+        let overrides = ParameterOverrides::parse(
+            "[[ligand]]\nselect = \"*.*.*:P\"\ncharge = 0.5\nvdw_radius = 2.5\n",
+        )
+        .unwrap();
+        let model = PYDOCKDockingModel::new(
+            &ligand,
+            &[],
+            &[],
+            &HashMap::new(),
+            &HashMap::new(),
+            &Nmodes::default(),
+            0,
+            &overrides.ligand,
+            ResiduePolicy::Strict,
+        );
+        let reference = PYDOCKDockingModel::new(
+            &ligand,
+            &[],
+            &[],
+            &HashMap::new(),
+            &HashMap::new(),
+            &Nmodes::default(),
+            0,
+            &[],
+            ResiduePolicy::Strict,
+        );
+        let mut num_overridden = 0;
+        for (i, atom) in ligand.atoms().enumerate() {
+            if atom.name() == "P" {
+                assert_eq!(model.ele_charges[i], 0.5);
+                assert_eq!(model.vdw_radii[i], 2.5);
+                assert_eq!(model.vdw_charges[i], reference.vdw_charges[i]);
+                num_overridden += 1;
+            } else {
+                assert_eq!(model.ele_charges[i], reference.ele_charges[i]);
+            }
+        }
+        assert!(num_overridden > 0);
     }
 }
@@ -60,6 +60,12 @@ impl Quaternion {
         vec![r.x, r.y, r.z]
     }
 
+    pub fn rotate_point(&self, point: &[f64; 3]) -> [f64; 3] {
+        let v = Quaternion::new(0., point[0], point[1], point[2]);
+        let r = *self * v * self.inverse();
+        [r.x, r.y, r.z]
+    }
+
     pub fn lerp(&self, other: Quaternion, t: f64) -> Quaternion {
         *self * (1.0 - t) + other * t
     }
@@ -83,13 +89,62 @@ impl Quaternion {
             result.normalize();
             result
         } else {
-            q_dot = ((q_dot).min(1.0)).max(-1.0);
+            q_dot = q_dot.clamp(-1.0, 1.0);
             let omega = q_dot.acos();
             let so = omega.sin();
             q1 * (((1.0 - t) * omega).sin() / so) + q2 * ((t * omega).sin() / so)
         }
     }
 
+    // Rotation of |v| radians around the direction of v
+    pub fn from_rotation_vector(v: &[f64; 3]) -> Quaternion {
+        let angle = (v[0] * v[0] + v[1] * v[1] + v[2] * v[2]).sqrt();
+        if float_equals(angle, 0.0) {
+            return Quaternion::default();
+        }
+        let s = (angle / 2.0).sin() / angle;
+        Quaternion::new((angle / 2.0).cos(), s * v[0], s * v[1], s * v[2])
+    }
+
+    // Matrix of the rotation, rows applied to column vectors as rotate_point does
+    pub fn to_rotation_matrix(&self) -> [[f64; 3]; 3] {
+        let mut q = *self;
+        q.normalize();
+        let (w, x, y, z) = (q.w, q.x, q.y, q.z);
+        [
+            [
+                1.0 - 2.0 * (y * y + z * z),
+                2.0 * (x * y - w * z),
+                2.0 * (x * z + w * y),
+            ],
+            [
+                2.0 * (x * y + w * z),
+                1.0 - 2.0 * (x * x + z * z),
+                2.0 * (y * z - w * x),
+            ],
+            [
+                2.0 * (x * z - w * y),
+                2.0 * (y * z + w * x),
+                1.0 - 2.0 * (x * x + y * y),
+            ],
+        ]
+    }
+
+    // Unit axis and angle in radians from 0 to PI of the rotation, any axis for no rotation
+    pub fn to_axis_angle(&self) -> ([f64; 3], f64) {
+        let mut q = *self;
+        q.normalize();
+        // q and -q are the same rotation, the one with positive w has the shortest angle
+        if q.w < 0.0 {
+            q = -q;
+        }
+        let s = (q.x * q.x + q.y * q.y + q.z * q.z).sqrt();
+        if float_equals(s, 0.0) {
+            return ([1.0, 0.0, 0.0], 0.0);
+        }
+        ([q.x / s, q.y / s, q.z / s], 2.0 * s.atan2(q.w))
+    }
+
     pub fn random(rng: &mut rand::prelude::StdRng) -> Quaternion {
         let u1 = rng.gen::<f64>();
         let u2 = rng.gen::<f64>();
@@ -198,6 +253,7 @@ impl ops::Div<f64> for Quaternion {
 }
 
 #[cfg(test)]
+#[allow(clippy::approx_constant)]
 mod tests {
     use super::*;
 
@@ -447,6 +503,40 @@ mod tests {
         assert!(expected == s);
     }
 
+    #[test]
+    fn test_from_rotation_vector() {
+        let q = Quaternion::from_rotation_vector(&[0.0, 0.0, PI / 2.0]);
+        let rotated = q.rotate_point(&[1.0, 0.0, 0.0]);
+        assert!(rotated[0].abs() < 1e-12);
+        assert!((rotated[1] - 1.0).abs() < 1e-12);
+        assert!(Quaternion::from_rotation_vector(&[0.0; 3]) == Quaternion::default());
+    }
+
+    #[test]
+    fn test_rotation_matrix_and_axis_angle() {
+        let q = Quaternion::new(0.7, 0.1, -0.5, 0.3);
+        let matrix = q.to_rotation_matrix();
+        let point = [1.5, -2.0, 0.5];
+        let rotated = q.rotate_point(&point);
+        for i in 0..3 {
+            let value: f64 = (0..3).map(|j| matrix[i][j] * point[j]).sum();
+            assert!((value - rotated[i]).abs() < 1e-12);
+        }
+
+        // Back to the quaternion as a rotation vector, with the opposite sign too
+        let (axis, angle) = (-q).to_axis_angle();
+        let r =
+            Quaternion::from_rotation_vector(&[axis[0] * angle, axis[1] * angle, axis[2] * angle]);
+        let mut normalized = q;
+        normalized.normalize();
+        assert!(normalized.distance(r) < 1e-12);
+        assert!(angle > 0.0 && angle <= PI);
+        assert_eq!(
+            Quaternion::default().to_axis_angle(),
+            ([1.0, 0.0, 0.0], 0.0)
+        );
+    }
+
     #[test]
     fn test_random_quaternion() {
         use rand::SeedableRng;
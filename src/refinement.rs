@@ -0,0 +1,110 @@
+// Input templates to minimize the exported complexes with external MD engines. Every template is
+// a (file name, contents) pair: a GROMACS run in vacuum with pdb2gmx, and an AMBER run in
+// generalized Born implicit solvent with tleap and sander. Force fields are set in the scripts and
+// can be changed there, and hetero groups need their own parameters in both engines.
+
+pub const GROMACS_MDP: &str = "\
+; Steepest descent minimization of the LightDock complexes
+integrator    = steep
+emtol         = 100.0
+emstep        = 0.01
+nsteps        = 5000
+cutoff-scheme = Verlet
+nstlist       = 10
+coulombtype   = Cut-off
+rcoulomb      = 1.2
+rvdw          = 1.2
+pbc           = xyz
+";
+
+pub const AMBER_MIN_IN: &str = "\
+Minimization of the LightDock complexes in generalized Born implicit solvent
+ &cntrl
+  imin=1, maxcyc=2000, ncyc=500,
+  ntb=0, igb=5, cut=999.0,
+  ntpr=100,
+ /
+";
+
+fn complex_list(complexes: &[String]) -> String {
+    complexes
+        .iter()
+        .map(|complex| complex.trim_end_matches(".pdb"))
+        .collect::<Vec<&str>>()
+        .join(" ")
+}
+
+pub fn gromacs_inputs(complexes: &[String]) -> Vec<(String, String)> {
+    let script = format!(
+        "\
+#!/bin/bash
+# GROMACS energy minimization in vacuum of the LightDock complexes
+set -e
+FORCE_FIELD=${{FORCE_FIELD:-amber99sb-ildn}}
+for complex in {}; do
+    gmx pdb2gmx -f ${{complex}}.pdb -o ${{complex}}_processed.gro -p ${{complex}}.top \\
+        -i ${{complex}}_posre.itp -ff ${{FORCE_FIELD}} -water none -ignh
+    gmx editconf -f ${{complex}}_processed.gro -o ${{complex}}_box.gro -c -d 1.5 -bt cubic
+    gmx grompp -f minimization.mdp -c ${{complex}}_box.gro -p ${{complex}}.top \\
+        -o ${{complex}}_min.tpr -maxwarn 1
+    gmx mdrun -deffnm ${{complex}}_min
+    gmx editconf -f ${{complex}}_min.gro -o ${{complex}}_min.pdb
+done
+",
+        complex_list(complexes)
+    );
+    vec![
+        (String::from("minimization.mdp"), GROMACS_MDP.to_string()),
+        (String::from("minimize_gromacs.sh"), script),
+    ]
+}
+
+pub fn amber_inputs(complexes: &[String]) -> Vec<(String, String)> {
+    let script = format!(
+        "\
+#!/bin/bash
+# AMBER energy minimization in implicit solvent of the LightDock complexes
+set -e
+for complex in {}; do
+    cat > ${{complex}}_tleap.in << EOF
+source leaprc.protein.ff14SB
+source leaprc.DNA.OL15
+set default PBRadii mbondi2
+complex = loadpdb ${{complex}}.pdb
+saveamberparm complex ${{complex}}.prmtop ${{complex}}.inpcrd
+quit
+EOF
+    tleap -f ${{complex}}_tleap.in
+    sander -O -i minimization.in -p ${{complex}}.prmtop -c ${{complex}}.inpcrd \\
+        -o ${{complex}}_min.out -r ${{complex}}_min.rst
+    ambpdb -p ${{complex}}.prmtop -c ${{complex}}_min.rst > ${{complex}}_min.pdb
+done
+",
+        complex_list(complexes)
+    );
+    vec![
+        (String::from("minimization.in"), AMBER_MIN_IN.to_string()),
+        (String::from("minimize_amber.sh"), script),
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_inputs() {
+        let complexes = vec![String::from("top_1.pdb"), String::from("top_2.pdb")];
+        let gromacs = gromacs_inputs(&complexes);
+        assert_eq!(gromacs[0].0, "minimization.mdp");
+        assert!(gromacs[1]
+            .1
+            .contains("for complex in top_1 top_2; do\n    gmx pdb2gmx -f ${complex}.pdb"));
+        let amber = amber_inputs(&complexes);
+        assert_eq!(amber[0].1, AMBER_MIN_IN);
+        assert!(amber[1].1.contains("for complex in top_1 top_2; do"));
+        assert!(amber[1]
+            .1
+            .contains("complex = loadpdb ${complex}.pdb\nsaveamberparm"));
+    }
+}
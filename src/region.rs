@@ -0,0 +1,219 @@
+use super::constants::REJECTED_POSE_SCORE;
+use super::decomposition::ResidueEnergies;
+use super::qt::Quaternion;
+use super::scoring::{Interface, Score};
+use super::ternary::LigandPose;
+
+#[derive(Debug, Clone)]
+pub enum Shape {
+    Box { center: [f64; 3], size: [f64; 3] },
+    Sphere { center: [f64; 3], radius: f64 },
+}
+
+impl Shape {
+    pub fn distance_outside(&self, point: &[f64]) -> f64 {
+        // Distance from the point to the region, zero if the point is inside
+        match self {
+            Shape::Box { center, size } => {
+                let mut distance2 = 0.0;
+                for i in 0..3 {
+                    let excess = ((point[i] - center[i]).abs() - size[i] / 2.0).max(0.0);
+                    distance2 += excess * excess;
+                }
+                distance2.sqrt()
+            }
+            Shape::Sphere { center, radius } => {
+                let distance = ((point[0] - center[0]) * (point[0] - center[0])
+                    + (point[1] - center[1]) * (point[1] - center[1])
+                    + (point[2] - center[2]) * (point[2] - center[2]))
+                    .sqrt();
+                (distance - radius).max(0.0)
+            }
+        }
+    }
+}
+
+pub struct DockingRegion {
+    pub scoring: Box<dyn Score>,
+    pub shape: Shape,
+    pub penalty: f64,
+    pub reject: bool,
+}
+
+impl DockingRegion {
+    pub fn new(
+        scoring: Box<dyn Score>,
+        shape: Shape,
+        penalty: f64,
+        reject: bool,
+    ) -> Box<dyn Score> {
+        Box::new(DockingRegion {
+            scoring,
+            shape,
+            penalty,
+            reject,
+        })
+    }
+}
+
+impl Score for DockingRegion {
+    fn energy(
+        &self,
+        translation: &[f64],
+        rotation: &Quaternion,
+        rec_nmodes: &[f64],
+        lig_nmodes: &[f64],
+    ) -> f64 {
+        // Ligand structures are centered, the translation is the ligand center
+        let distance = self.shape.distance_outside(translation);
+        if distance > 0.0 && self.reject {
+            return REJECTED_POSE_SCORE;
+        }
+        self.scoring
+            .energy(translation, rotation, rec_nmodes, lig_nmodes)
+            - self.penalty * distance
+    }
+
+    fn residue_energies(
+        &self,
+        translation: &[f64],
+        rotation: &Quaternion,
+        rec_nmodes: &[f64],
+        lig_nmodes: &[f64],
+    ) -> Option<ResidueEnergies> {
+        // The penalty is not due to any residue pair
+        let distance = self.shape.distance_outside(translation);
+        if distance > 0.0 && self.reject {
+            return None;
+        }
+        let mut energies =
+            self.scoring
+                .residue_energies(translation, rotation, rec_nmodes, lig_nmodes)?;
+        energies.offset -= self.penalty * distance;
+        Some(energies)
+    }
+
+    fn energy_with_interface(
+        &self,
+        translation: &[f64],
+        rotation: &Quaternion,
+        rec_nmodes: &[f64],
+        lig_nmodes: &[f64],
+    ) -> (f64, Option<Interface>) {
+        let distance = self.shape.distance_outside(translation);
+        if distance > 0.0 && self.reject {
+            return (REJECTED_POSE_SCORE, None);
+        }
+        let (energy, interface) =
+            self.scoring
+                .energy_with_interface(translation, rotation, rec_nmodes, lig_nmodes);
+        (energy - self.penalty * distance, interface)
+    }
+
+    fn restraints_satisfaction(
+        &self,
+        translation: &[f64],
+        rotation: &Quaternion,
+        rec_nmodes: &[f64],
+        lig_nmodes: &[f64],
+    ) -> Option<f64> {
+        self.scoring
+            .restraints_satisfaction(translation, rotation, rec_nmodes, lig_nmodes)
+    }
+
+    // The region is the one of the first ligand, like the restraints
+    fn second_ligand_energy(
+        &self,
+        translation: &[f64],
+        rotation: &Quaternion,
+        rec_nmodes: &[f64],
+        lig_nmodes: &[f64],
+        second: &LigandPose,
+    ) -> Option<f64> {
+        let distance = self.shape.distance_outside(translation);
+        if distance > 0.0 && self.reject {
+            return Some(REJECTED_POSE_SCORE);
+        }
+        let energy = self.scoring.second_ligand_energy(
+            translation,
+            rotation,
+            rec_nmodes,
+            lig_nmodes,
+            second,
+        )?;
+        if energy == REJECTED_POSE_SCORE {
+            return Some(energy);
+        }
+        Some(energy - self.penalty * distance)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct Constant;
+
+    impl Score for Constant {
+        fn energy(&self, _: &[f64], _: &Quaternion, _: &[f64], _: &[f64]) -> f64 {
+            10.0
+        }
+
+        fn residue_energies(
+            &self,
+            _: &[f64],
+            _: &Quaternion,
+            _: &[f64],
+            _: &[f64],
+        ) -> Option<ResidueEnergies> {
+            Some(ResidueEnergies::new(&[], &[], 10.0))
+        }
+    }
+
+    #[test]
+    fn test_distance_outside() {
+        let cube = Shape::Box {
+            center: [0.0, 0.0, 0.0],
+            size: [10.0, 10.0, 10.0],
+        };
+        assert_eq!(cube.distance_outside(&[4.0, -4.0, 0.0]), 0.0);
+        assert_eq!(cube.distance_outside(&[8.0, 0.0, 0.0]), 3.0);
+        assert_eq!(cube.distance_outside(&[8.0, 9.0, 0.0]), 5.0);
+
+        let sphere = Shape::Sphere {
+            center: [1.0, 1.0, 1.0],
+            radius: 2.0,
+        };
+        assert_eq!(sphere.distance_outside(&[2.0, 1.0, 1.0]), 0.0);
+        assert_eq!(sphere.distance_outside(&[1.0, 1.0, 6.0]), 3.0);
+    }
+
+    #[test]
+    fn test_docking_region() {
+        let shape = Shape::Sphere {
+            center: [0.0, 0.0, 0.0],
+            radius: 5.0,
+        };
+        let rotation = Quaternion::default();
+
+        let penalized = DockingRegion::new(Box::new(Constant), shape.clone(), 2.0, false);
+        assert_eq!(
+            penalized.energy(&[1.0, 0.0, 0.0], &rotation, &[], &[]),
+            10.0
+        );
+        assert_eq!(penalized.energy(&[7.0, 0.0, 0.0], &rotation, &[], &[]), 6.0);
+        let energies = penalized
+            .residue_energies(&[7.0, 0.0, 0.0], &rotation, &[], &[])
+            .unwrap();
+        assert_eq!(energies.total(), 6.0);
+
+        let rejected = DockingRegion::new(Box::new(Constant), shape, 2.0, true);
+        assert_eq!(
+            rejected.energy(&[7.0, 0.0, 0.0], &rotation, &[], &[]),
+            REJECTED_POSE_SCORE
+        );
+        assert!(rejected
+            .residue_energies(&[7.0, 0.0, 0.0], &rotation, &[], &[])
+            .is_none());
+    }
+}
@@ -0,0 +1,204 @@
+use super::nmodes::Nmodes;
+use super::policy::ResiduePolicy;
+use super::scoring::{ClashFilter, Method, RestraintsMetric, Score, METHODS};
+use pdbtbx::PDB;
+use std::collections::HashMap;
+use std::sync::{Arc, RwLock};
+
+// Scoring functions implemented outside of lightdock-rust. A downstream crate implements Score
+// and a ScoringProvider building it, registers the provider by name before docking and the name
+// is then accepted everywhere a method is (scoring_from_files, the command line of run_cli, the
+// consensus and swarm methods of the setup) as Method::Registered. Providers are built from the
+// same ScoringInput as the built-in methods; the options of a single built-in method, like the
+// DFIRE grid or the parameter overrides, are not part of it.
+
+// Partners and options shared by every scoring function, built-in or registered
+#[derive(Clone)]
+pub struct ScoringInput {
+    pub receptor: PDB,
+    pub rec_active_restraints: Vec<String>,
+    pub rec_passive_restraints: Vec<String>,
+    pub rec_hotspots: HashMap<String, f64>,
+    pub rec_weights: HashMap<String, f64>,
+    pub rec_nmodes: Nmodes,
+    pub rec_num_anm: usize,
+    pub ligand: PDB,
+    pub lig_active_restraints: Vec<String>,
+    pub lig_passive_restraints: Vec<String>,
+    pub lig_hotspots: HashMap<String, f64>,
+    pub lig_weights: HashMap<String, f64>,
+    pub lig_nmodes: Nmodes,
+    pub lig_num_anm: usize,
+    pub use_anm: bool,
+    pub clash_filter: Option<ClashFilter>,
+    pub restraints_metric: RestraintsMetric,
+    pub policy: ResiduePolicy,
+}
+
+impl ScoringInput {
+    // Partners without restraints, hot-spots, residue weights nor normal modes
+    pub fn new(receptor: PDB, ligand: PDB, policy: ResiduePolicy) -> Self {
+        ScoringInput {
+            receptor,
+            rec_active_restraints: Vec::new(),
+            rec_passive_restraints: Vec::new(),
+            rec_hotspots: HashMap::new(),
+            rec_weights: HashMap::new(),
+            rec_nmodes: Nmodes::default(),
+            rec_num_anm: 0,
+            ligand,
+            lig_active_restraints: Vec::new(),
+            lig_passive_restraints: Vec::new(),
+            lig_hotspots: HashMap::new(),
+            lig_weights: HashMap::new(),
+            lig_nmodes: Nmodes::default(),
+            lig_num_anm: 0,
+            use_anm: false,
+            clash_filter: None,
+            restraints_metric: RestraintsMetric::default(),
+            policy,
+        }
+    }
+}
+
+pub trait ScoringProvider: Send + Sync {
+    // Name selecting the scoring function, compared ignoring case
+    fn name(&self) -> &'static str;
+
+    fn description(&self) -> &'static str;
+
+    // Whether structures must include hydrogens
+    fn needs_hydrogens(&self) -> bool {
+        false
+    }
+
+    fn build(&self, input: ScoringInput) -> Box<dyn Score>;
+}
+
+lazy_static! {
+    static ref PROVIDERS: RwLock<Vec<Arc<dyn ScoringProvider>>> = RwLock::new(Vec::new());
+}
+
+// Register a scoring function, an error if its name is empty or already taken
+pub fn register_scoring(provider: impl ScoringProvider + 'static) -> Result<Method, String> {
+    let name = provider.name().to_lowercase();
+    let mut providers = PROVIDERS.write().unwrap();
+    if name.is_empty()
+        || METHODS.iter().any(|method| method.name() == name)
+        || providers
+            .iter()
+            .any(|known| known.name().to_lowercase() == name)
+    {
+        return Err(format!(
+            "Scoring function name {:?} is empty or already taken",
+            provider.name()
+        ));
+    }
+    let method = Method::Registered(provider.name());
+    providers.push(Arc::new(provider));
+    Ok(method)
+}
+
+// Registered scoring functions, in their order of registration
+pub(crate) fn registered_methods() -> Vec<Method> {
+    PROVIDERS
+        .read()
+        .unwrap()
+        .iter()
+        .map(|provider| Method::Registered(provider.name()))
+        .collect()
+}
+
+pub(crate) fn registered_method(name: &str) -> Option<Method> {
+    provider(name)
+        .ok()
+        .map(|provider| Method::Registered(provider.name()))
+}
+
+// Provider of a scoring function by its name, an error if none was registered with it
+pub(crate) fn provider(name: &str) -> Result<Arc<dyn ScoringProvider>, String> {
+    let name = name.to_lowercase();
+    PROVIDERS
+        .read()
+        .unwrap()
+        .iter()
+        .find(|provider| provider.name().to_lowercase() == name)
+        .cloned()
+        .ok_or(format!("Scoring function {:?} not registered", name))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::qt::Quaternion;
+
+    struct Distance;
+
+    impl Score for Distance {
+        fn energy(&self, translation: &[f64], _: &Quaternion, _: &[f64], _: &[f64]) -> f64 {
+            -translation.iter().map(|x| x * x).sum::<f64>().sqrt()
+        }
+    }
+
+    struct DistanceProvider(&'static str);
+
+    impl ScoringProvider for DistanceProvider {
+        fn name(&self) -> &'static str {
+            self.0
+        }
+
+        fn description(&self) -> &'static str {
+            "Distance of the ligand center to the origin"
+        }
+
+        fn build(&self, _: ScoringInput) -> Box<dyn Score> {
+            Box::new(Distance)
+        }
+    }
+
+    #[test]
+    fn test_register_scoring() {
+        let method = register_scoring(DistanceProvider("Distance")).unwrap();
+        assert_eq!(Method::from_name("distance"), Some(method));
+        assert_eq!(method.name(), "Distance");
+        assert!(registered_methods().contains(&method));
+        assert!(!method.needs_hydrogens());
+
+        // Names of built-in and registered methods are taken
+        assert!(register_scoring(DistanceProvider("DFIRE")).is_err());
+        assert!(register_scoring(DistanceProvider("distance")).is_err());
+        assert!(register_scoring(DistanceProvider("")).is_err());
+
+        // Unknown names are an error, not a panic
+        assert!(provider("unknown").is_err());
+        let unknown = Method::Registered("unknown");
+        assert!(!unknown.needs_hydrogens());
+        assert_eq!(unknown.description(), "Scoring function not registered");
+
+        let structure = PDB::new();
+        let scoring = provider(method.name()).unwrap().build(ScoringInput {
+            receptor: structure.clone(),
+            rec_active_restraints: Vec::new(),
+            rec_passive_restraints: Vec::new(),
+            rec_hotspots: HashMap::new(),
+            rec_weights: HashMap::new(),
+            rec_nmodes: Nmodes::default(),
+            rec_num_anm: 0,
+            ligand: structure,
+            lig_active_restraints: Vec::new(),
+            lig_passive_restraints: Vec::new(),
+            lig_hotspots: HashMap::new(),
+            lig_weights: HashMap::new(),
+            lig_nmodes: Nmodes::default(),
+            lig_num_anm: 0,
+            use_anm: false,
+            clash_filter: None,
+            restraints_metric: RestraintsMetric::default(),
+            policy: ResiduePolicy::default(),
+        });
+        assert_eq!(
+            scoring.energy(&[3.0, 4.0, 0.0], &Quaternion::default(), &[], &[]),
+            -5.0
+        );
+    }
+}
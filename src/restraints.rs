@@ -0,0 +1,264 @@
+use super::qt::Quaternion;
+use log::warn;
+use pdbtbx::PDB;
+use rand::prelude::StdRng;
+
+// Restrained residues of the ligand must face the receptor to ever be at the interface. Starting
+// poses where the restrained patch of the ligand points away from the receptor restraints (or the
+// receptor center if there are none) can be dropped or given a new random orientation before the
+// optimization, like the swarm filtering done by the Python setup.
+
+// Maximum number of random orientations tried when re-seeding a pose
+const MAX_RESEED_TRIES: usize = 1000;
+
+#[derive(Debug)]
+pub struct OrientationFilter {
+    // Center of the ligand and of its restrained patch, ligand frame
+    pub ligand_center: [f64; 3],
+    pub ligand_patch: [f64; 3],
+    // Point of the receptor the patch should face
+    pub target: [f64; 3],
+    pub min_cosine: f64,
+}
+
+pub fn residue_id(chain: &pdbtbx::Chain, residue: &pdbtbx::Residue) -> String {
+    let mut res_id = format!(
+        "{}.{}.{}",
+        chain.id(),
+        residue.name().unwrap_or(""),
+        residue.serial_number()
+    );
+    if let Some(c) = residue.insertion_code() {
+        res_id.push_str(c);
+    }
+    res_id
+}
+
+// Parses a residue range entry, "A:10-50" or "A:10", into its chain and first and last residue
+// numbers. Residue numbers may be negative, e.g. "A:-3-5".
+fn parse_range(entry: &str) -> Option<(&str, isize, isize)> {
+    let (chain, range) = entry.split_once(':')?;
+    if chain.is_empty() || range.is_empty() {
+        return None;
+    }
+    // Skip the sign of the first number when looking for the separator
+    let (first, last) = match range[1..].find('-') {
+        Some(separator) => range.split_at(separator + 1),
+        None => (range, ""),
+    };
+    let first: isize = first.trim().parse().ok()?;
+    let last: isize = match last.strip_prefix('-') {
+        Some(last) => last.trim().parse().ok()?,
+        None => first,
+    };
+    if last < first {
+        return None;
+    }
+    Some((chain.trim(), first, last))
+}
+
+// Expands the chain and residue range entries of a restraints list (e.g. "A:10-50") into the IDs
+// of the residues of the structure in that range, insertion codes included. Residue IDs are kept
+// as given and duplicates removed, keeping the first occurrence.
+pub fn expand_restraints(structure: &PDB, entries: &[String]) -> Vec<String> {
+    let mut restraints: Vec<String> = Vec::new();
+    for entry in entries.iter() {
+        if !entry.contains(':') {
+            if !restraints.contains(entry) {
+                restraints.push(entry.clone());
+            }
+            continue;
+        }
+        let (chain_id, first, last) = match parse_range(entry) {
+            Some(range) => range,
+            None => panic!(
+                "Wrong restraints range {:?}, expected e.g. \"A:10-50\"",
+                entry
+            ),
+        };
+        let mut num_residues = 0;
+        for chain in structure.chains().filter(|chain| chain.id() == chain_id) {
+            for residue in chain.residues() {
+                if (first..=last).contains(&residue.serial_number()) {
+                    let res_id = residue_id(chain, residue);
+                    if !restraints.contains(&res_id) {
+                        restraints.push(res_id);
+                    }
+                    num_residues += 1;
+                }
+            }
+        }
+        if num_residues == 0 {
+            warn!("Restraints range {:?} matches no residue", entry);
+        }
+    }
+    restraints
+}
+
+// Center of all atoms, or only of the atoms of the given residues if any
+fn center(structure: &PDB, residues: &[String]) -> Option<[f64; 3]> {
+    let mut center = [0.0; 3];
+    let mut num_atoms = 0;
+    for chain in structure.chains() {
+        for residue in chain.residues() {
+            if !residues.is_empty() && !residues.contains(&residue_id(chain, residue)) {
+                continue;
+            }
+            for atom in residue.atoms() {
+                center[0] += atom.x();
+                center[1] += atom.y();
+                center[2] += atom.z();
+                num_atoms += 1;
+            }
+        }
+    }
+    if num_atoms == 0 {
+        return None;
+    }
+    Some(center.map(|c| c / num_atoms as f64))
+}
+
+impl OrientationFilter {
+    pub fn new(
+        receptor: &PDB,
+        rec_restraints: &[String],
+        ligand: &PDB,
+        lig_restraints: &[String],
+        max_angle: f64,
+    ) -> Option<OrientationFilter> {
+        if lig_restraints.is_empty() {
+            return None;
+        }
+        Some(OrientationFilter {
+            ligand_center: center(ligand, &[])?,
+            ligand_patch: center(ligand, lig_restraints)?,
+            target: center(receptor, rec_restraints)?,
+            min_cosine: max_angle.to_radians().cos(),
+        })
+    }
+
+    pub fn is_facing(&self, translation: &[f64], rotation: &Quaternion) -> bool {
+        let center = rotation.rotate_point(&self.ligand_center);
+        let patch = rotation.rotate_point(&self.ligand_patch);
+        let mut direction = [0.0; 3];
+        let mut to_target = [0.0; 3];
+        for k in 0..3 {
+            direction[k] = patch[k] - center[k];
+            to_target[k] = self.target[k] - (center[k] + translation[k]);
+        }
+        let norms = (direction.iter().map(|v| v * v).sum::<f64>()
+            * to_target.iter().map(|v| v * v).sum::<f64>())
+        .sqrt();
+        if norms < f64::EPSILON {
+            // Patch at the ligand center or ligand on the target, any orientation may work
+            return true;
+        }
+        let dot: f64 = (0..3).map(|k| direction[k] * to_target[k]).sum();
+        dot / norms >= self.min_cosine
+    }
+
+    // Drops or re-orients the positions facing away, returns how many of them were found
+    pub fn apply(&self, positions: &mut Vec<Vec<f64>>, reseed: bool, rng: &mut StdRng) -> usize {
+        let facing = |position: &[f64]| {
+            let rotation = Quaternion::new(position[3], position[4], position[5], position[6]);
+            self.is_facing(&position[0..3], &rotation)
+        };
+        let num_away = positions.iter().filter(|p| !facing(p)).count();
+        if !reseed {
+            positions.retain(|p| facing(p));
+            return num_away;
+        }
+        for position in positions.iter_mut() {
+            for _ in 0..MAX_RESEED_TRIES {
+                if facing(position) {
+                    break;
+                }
+                let rotation = Quaternion::random(rng);
+                position[3] = rotation.w;
+                position[4] = rotation.x;
+                position[5] = rotation.y;
+                position[6] = rotation.z;
+            }
+        }
+        num_away
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::structure::parse_structure;
+    use rand::SeedableRng;
+
+    #[test]
+    fn test_expand_restraints() {
+        assert_eq!(parse_range("A:10-50"), Some(("A", 10, 50)));
+        assert_eq!(parse_range("B:-3--1"), Some(("B", -3, -1)));
+        assert_eq!(parse_range("A:7"), Some(("A", 7, 7)));
+        assert_eq!(parse_range("A:50-10"), None);
+        assert_eq!(parse_range(":1-2"), None);
+
+        let contents = "\
+ATOM      1  CA  SER A   1       0.000   1.000   2.000  1.00  0.00           C\n\
+ATOM      2  CA  GLY A   2       1.000   1.000   2.000  1.00  0.00           C\n\
+ATOM      3  CA  GLY A   2A      2.000   1.000   2.000  1.00  0.00           C\n\
+ATOM      4  CA  LYS A   3       3.000   1.000   2.000  1.00  0.00           C\n\
+ATOM      5  CA  ALA B   2       4.000   1.000   2.000  1.00  0.00           C\n";
+        let structure = parse_structure(contents, 1).unwrap().unwrap();
+        let entries = vec![
+            String::from("A.SER.1"),
+            String::from("A:1-2"),
+            String::from("B:2"),
+            String::from("C:1-100"),
+        ];
+        assert_eq!(
+            expand_restraints(&structure, &entries),
+            vec!["A.SER.1", "A.GLY.2", "A.GLY.2A", "B.ALA.2"]
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "Wrong restraints range")]
+    fn test_wrong_restraints_range() {
+        let structure = parse_structure(
+            "ATOM      1  CA  SER A   1       0.000   1.000   2.000  1.00  0.00           C\n",
+            1,
+        )
+        .unwrap()
+        .unwrap();
+        expand_restraints(&structure, &[String::from("A:ten-20")]);
+    }
+
+    #[test]
+    fn test_orientation_filter() {
+        // Ligand patch along +x, receptor target at the origin
+        let filter = OrientationFilter {
+            ligand_center: [0.0; 3],
+            ligand_patch: [1.0, 0.0, 0.0],
+            target: [0.0; 3],
+            min_cosine: 0.0,
+        };
+        let identity = Quaternion::default();
+        assert!(filter.is_facing(&[-10.0, 0.0, 0.0], &identity));
+        assert!(!filter.is_facing(&[10.0, 0.0, 0.0], &identity));
+        // Half turn around z brings the patch to -x
+        let half_turn = Quaternion::new(0.0, 0.0, 0.0, 1.0);
+        assert!(filter.is_facing(&[10.0, 0.0, 0.0], &half_turn));
+
+        let mut rng: StdRng = SeedableRng::seed_from_u64(324324);
+        let away = vec![10.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0];
+        let facing = vec![-10.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0];
+        let mut positions = vec![away.clone(), facing.clone()];
+        assert_eq!(filter.apply(&mut positions, false, &mut rng), 1);
+        assert_eq!(positions, vec![facing.clone()]);
+
+        let mut positions = vec![away, facing];
+        assert_eq!(filter.apply(&mut positions, true, &mut rng), 1);
+        assert_eq!(positions.len(), 2);
+        assert_eq!(positions[0][0..3], [10.0, 0.0, 0.0]);
+        for position in positions.iter() {
+            let rotation = Quaternion::new(position[3], position[4], position[5], position[6]);
+            assert!(filter.is_facing(&position[0..3], &rotation));
+        }
+    }
+}
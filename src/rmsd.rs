@@ -0,0 +1,302 @@
+use super::qt::Quaternion;
+
+// RMSD between sets of atomic coordinates given in the same atom order. The least-squares
+// superposition (Kabsch problem) is solved with quaternions (Horn, 1987): the optimal rotation is
+// the eigenvector of the largest eigenvalue of a 4x4 symmetric matrix built from the covariance
+// of both sets, so no SVD is needed and the rotation is directly a Quaternion.
+
+#[derive(Debug, Clone, Copy)]
+pub struct Superposition {
+    // Applied to the mobile coordinates, rotation first and then translation
+    pub rotation: Quaternion,
+    pub translation: [f64; 3],
+    pub rmsd: f64,
+}
+
+impl Superposition {
+    pub fn apply(&self, coordinates: &[[f64; 3]]) -> Vec<[f64; 3]> {
+        coordinates
+            .iter()
+            .map(|coordinate| {
+                let rotated = self.rotation.rotate_point(coordinate);
+                [
+                    rotated[0] + self.translation[0],
+                    rotated[1] + self.translation[1],
+                    rotated[2] + self.translation[2],
+                ]
+            })
+            .collect()
+    }
+}
+
+pub fn centroid(coordinates: &[[f64; 3]]) -> [f64; 3] {
+    let mut center = [0.0; 3];
+    for coordinate in coordinates.iter() {
+        for k in 0..3 {
+            center[k] += coordinate[k];
+        }
+    }
+    let n = coordinates.len().max(1) as f64;
+    [center[0] / n, center[1] / n, center[2] / n]
+}
+
+// RMSD without any superposition
+pub fn rmsd(one: &[[f64; 3]], two: &[[f64; 3]]) -> f64 {
+    assert_eq!(one.len(), two.len(), "RMSD of sets with different sizes");
+    if one.is_empty() {
+        return 0.0;
+    }
+    let total: f64 = one
+        .iter()
+        .zip(two.iter())
+        .map(|(a, b)| (a[0] - b[0]).powi(2) + (a[1] - b[1]).powi(2) + (a[2] - b[2]).powi(2))
+        .sum();
+    (total / one.len() as f64).sqrt()
+}
+
+// Eigenvalues and eigenvectors (as columns) of a symmetric matrix by cyclic Jacobi rotations
+fn jacobi_eigen(matrix: [[f64; 4]; 4]) -> ([f64; 4], [[f64; 4]; 4]) {
+    let mut a = matrix;
+    let mut v = [[0.0; 4]; 4];
+    for (i, row) in v.iter_mut().enumerate() {
+        row[i] = 1.0;
+    }
+    for _sweep in 0..50 {
+        let off_diagonal: f64 = (0..4)
+            .flat_map(|p| (p + 1..4).map(move |q| (p, q)))
+            .map(|(p, q)| a[p][q] * a[p][q])
+            .sum();
+        if off_diagonal < 1e-30 {
+            break;
+        }
+        for p in 0..4 {
+            for q in p + 1..4 {
+                if a[p][q].abs() < 1e-300 {
+                    continue;
+                }
+                let theta = (a[q][q] - a[p][p]) / (2.0 * a[p][q]);
+                let t = theta.signum() / (theta.abs() + (theta * theta + 1.0).sqrt());
+                let c = 1.0 / (t * t + 1.0).sqrt();
+                let s = t * c;
+                for k in 0..4 {
+                    let akp = a[k][p];
+                    let akq = a[k][q];
+                    a[k][p] = c * akp - s * akq;
+                    a[k][q] = s * akp + c * akq;
+                }
+                for k in 0..4 {
+                    let apk = a[p][k];
+                    let aqk = a[q][k];
+                    a[p][k] = c * apk - s * aqk;
+                    a[q][k] = s * apk + c * aqk;
+                }
+                for row in v.iter_mut() {
+                    let vkp = row[p];
+                    let vkq = row[q];
+                    row[p] = c * vkp - s * vkq;
+                    row[q] = s * vkp + c * vkq;
+                }
+            }
+        }
+    }
+    ([a[0][0], a[1][1], a[2][2], a[3][3]], v)
+}
+
+// Rotation and translation of mobile minimizing its RMSD to target
+pub fn superpose(mobile: &[[f64; 3]], target: &[[f64; 3]]) -> Superposition {
+    assert_eq!(
+        mobile.len(),
+        target.len(),
+        "RMSD of sets with different sizes"
+    );
+    let mobile_center = centroid(mobile);
+    let target_center = centroid(target);
+
+    // Covariance of the centered sets and sum of their squared norms
+    let mut s = [[0.0; 3]; 3];
+    let mut norms: f64 = 0.0;
+    for (m, t) in mobile.iter().zip(target.iter()) {
+        let x = [
+            m[0] - mobile_center[0],
+            m[1] - mobile_center[1],
+            m[2] - mobile_center[2],
+        ];
+        let y = [
+            t[0] - target_center[0],
+            t[1] - target_center[1],
+            t[2] - target_center[2],
+        ];
+        for i in 0..3 {
+            for j in 0..3 {
+                s[i][j] += x[i] * y[j];
+            }
+            norms += x[i] * x[i] + y[i] * y[i];
+        }
+    }
+    let [[sxx, sxy, sxz], [syx, syy, syz], [szx, szy, szz]] = s;
+    let n = [
+        [sxx + syy + szz, syz - szy, szx - sxz, sxy - syx],
+        [syz - szy, sxx - syy - szz, sxy + syx, szx + sxz],
+        [szx - sxz, sxy + syx, -sxx + syy - szz, syz + szy],
+        [sxy - syx, szx + sxz, syz + szy, -sxx - syy + szz],
+    ];
+    let (eigenvalues, eigenvectors) = jacobi_eigen(n);
+    let best = (0..4)
+        .max_by(|&a, &b| eigenvalues[a].total_cmp(&eigenvalues[b]))
+        .unwrap();
+    let mut rotation = Quaternion::new(
+        eigenvectors[0][best],
+        eigenvectors[1][best],
+        eigenvectors[2][best],
+        eigenvectors[3][best],
+    );
+    rotation.normalize();
+
+    let rotated_center = rotation.rotate_point(&mobile_center);
+    let num_atoms = mobile.len().max(1) as f64;
+    Superposition {
+        rotation,
+        translation: [
+            target_center[0] - rotated_center[0],
+            target_center[1] - rotated_center[1],
+            target_center[2] - rotated_center[2],
+        ],
+        rmsd: ((norms - 2.0 * eigenvalues[best]).max(0.0) / num_atoms).sqrt(),
+    }
+}
+
+// Ligand RMSD once the model receptor is superposed onto the reference one
+pub fn ligand_rmsd(
+    receptor_model: &[[f64; 3]],
+    receptor_reference: &[[f64; 3]],
+    ligand_model: &[[f64; 3]],
+    ligand_reference: &[[f64; 3]],
+) -> f64 {
+    let superposition = superpose(receptor_model, receptor_reference);
+    rmsd(&superposition.apply(ligand_model), ligand_reference)
+}
+
+// Atoms of each partner closer than the cutoff to any atom of the other one
+pub fn interface_atoms(
+    receptor: &[[f64; 3]],
+    ligand: &[[f64; 3]],
+    cutoff: f64,
+) -> (Vec<usize>, Vec<usize>) {
+    let cutoff2 = cutoff * cutoff;
+    let mut receptor_interface = vec![false; receptor.len()];
+    let mut ligand_interface = vec![false; ligand.len()];
+    for (i, ra) in receptor.iter().enumerate() {
+        for (j, la) in ligand.iter().enumerate() {
+            let dist = (ra[0] - la[0]).powi(2) + (ra[1] - la[1]).powi(2) + (ra[2] - la[2]).powi(2);
+            if dist <= cutoff2 {
+                receptor_interface[i] = true;
+                ligand_interface[j] = true;
+            }
+        }
+    }
+    let indexes = |interface: Vec<bool>| -> Vec<usize> {
+        (0..interface.len()).filter(|&i| interface[i]).collect()
+    };
+    (indexes(receptor_interface), indexes(ligand_interface))
+}
+
+// RMSD of the given interface atoms of both partners after their superposition
+pub fn interface_rmsd(
+    receptor_model: &[[f64; 3]],
+    receptor_reference: &[[f64; 3]],
+    ligand_model: &[[f64; 3]],
+    ligand_reference: &[[f64; 3]],
+    receptor_interface: &[usize],
+    ligand_interface: &[usize],
+) -> f64 {
+    let select = |receptor: &[[f64; 3]], ligand: &[[f64; 3]]| -> Vec<[f64; 3]> {
+        receptor_interface
+            .iter()
+            .map(|&i| receptor[i])
+            .chain(ligand_interface.iter().map(|&j| ligand[j]))
+            .collect()
+    };
+    superpose(
+        &select(receptor_model, ligand_model),
+        &select(receptor_reference, ligand_reference),
+    )
+    .rmsd
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn transform(coordinates: &[[f64; 3]], rotation: &Quaternion, shift: f64) -> Vec<[f64; 3]> {
+        coordinates
+            .iter()
+            .map(|c| {
+                let r = rotation.rotate_point(c);
+                [r[0] + shift, r[1] - shift, r[2] + 2.0 * shift]
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_superpose() {
+        let mobile = vec![
+            [0.0, 0.0, 0.0],
+            [1.5, 0.0, 0.0],
+            [1.5, 2.0, 0.0],
+            [0.0, 2.0, 1.0],
+            [-1.0, 0.5, 3.0],
+        ];
+        let mut rotation = Quaternion::new(0.3, -0.5, 0.7, 0.2);
+        rotation.normalize();
+        let target = transform(&mobile, &rotation, 4.0);
+        assert!(rmsd(&mobile, &target) > 1.0);
+
+        let superposition = superpose(&mobile, &target);
+        assert!(superposition.rmsd < 1e-9);
+        assert!(rmsd(&superposition.apply(&mobile), &target) < 1e-9);
+        assert!(superposition.rotation.distance(rotation) < 1e-9);
+    }
+
+    #[test]
+    fn test_ligand_and_interface_rmsd() {
+        let receptor = vec![
+            [0.0, 0.0, 0.0],
+            [3.0, 0.0, 0.0],
+            [0.0, 3.0, 0.0],
+            [20.0, 0.0, 0.0],
+        ];
+        let ligand = vec![[0.0, 0.0, 4.0], [3.0, 0.0, 4.0], [0.0, 0.0, 30.0]];
+        let (receptor_interface, ligand_interface) = interface_atoms(&receptor, &ligand, 5.0);
+        assert_eq!(receptor_interface, vec![0, 1, 2]);
+        assert_eq!(ligand_interface, vec![0, 1]);
+
+        // Whole complex moved and the ligand shifted by 1 Angstrom along Z
+        let mut rotation = Quaternion::new(0.9, 0.1, -0.3, 0.2);
+        rotation.normalize();
+        let receptor_model = transform(&receptor, &rotation, -2.0);
+        let shifted: Vec<[f64; 3]> = ligand.iter().map(|c| [c[0], c[1], c[2] + 1.0]).collect();
+        let ligand_model = transform(&shifted, &rotation, -2.0);
+        let l_rmsd = ligand_rmsd(&receptor_model, &receptor, &ligand_model, &ligand);
+        assert!((l_rmsd - 1.0).abs() < 1e-9);
+        let i_rmsd = interface_rmsd(
+            &receptor_model,
+            &receptor,
+            &ligand_model,
+            &ligand,
+            &receptor_interface,
+            &ligand_interface,
+        );
+        assert!(i_rmsd > 0.0 && i_rmsd < 1.0);
+        assert_eq!(
+            interface_rmsd(
+                &receptor,
+                &receptor,
+                &ligand,
+                &ligand,
+                &receptor_interface,
+                &ligand_interface
+            ),
+            0.0
+        );
+    }
+}
@@ -0,0 +1,267 @@
+use super::restraints::residue_id;
+use pdbtbx::{Atom, Residue, PDB};
+use std::io::{Error, Write};
+
+// Load-time cleanup of the input structures, replacing the manual editing of the PDB files:
+// waters, selected hetero groups and alternate locations are removed and nonstandard terminal
+// atom names are renamed. Every change is recorded in a report. As atoms may be removed, normal
+// modes and restraints must refer to the sanitized structures.
+
+const WATER_NAMES: [&str; 6] = ["HOH", "WAT", "H2O", "DOD", "TIP", "SOL"];
+
+// Modified amino acids given as HETATM records, kept as part of the chain by the ligands class
+const MODIFIED_RESIDUES: [&str; 8] = ["MSE", "SEP", "TPO", "PTR", "HYP", "MLY", "CSO", "KCX"];
+
+// CHARMM and other nonstandard names of terminal atoms
+const TERMINAL_NAMES: [(&str, &str); 7] = [
+    ("OT1", "O"),
+    ("OT2", "OXT"),
+    ("OC1", "O"),
+    ("OC2", "OXT"),
+    ("HT1", "H1"),
+    ("HT2", "H2"),
+    ("HT3", "H3"),
+];
+
+#[derive(Debug, Clone)]
+pub struct Sanitizer {
+    pub waters: bool,
+    // Residue names or classes ("ions", "ligands" or "all") of the hetero groups to remove
+    pub hetero: Vec<String>,
+    pub altlocs: bool,
+    pub terminals: bool,
+}
+
+impl Default for Sanitizer {
+    fn default() -> Sanitizer {
+        Sanitizer {
+            waters: true,
+            hetero: Vec::new(),
+            altlocs: true,
+            terminals: true,
+        }
+    }
+}
+
+fn is_water(residue: &Residue) -> bool {
+    residue
+        .name()
+        .is_some_and(|name| WATER_NAMES.contains(&name))
+}
+
+fn is_hetero(residue: &Residue) -> bool {
+    residue.atoms().all(|atom| atom.hetero())
+}
+
+impl Sanitizer {
+    fn removes_hetero(&self, residue: &Residue) -> bool {
+        if !is_hetero(residue) || is_water(residue) {
+            return false;
+        }
+        let name = residue.name().unwrap_or("");
+        let ion = residue.atom_count() == 1;
+        self.hetero
+            .iter()
+            .any(|selection| match selection.as_str() {
+                "all" => true,
+                "ions" => ion,
+                "ligands" => !ion && !MODIFIED_RESIDUES.contains(&name),
+                _ => selection.eq_ignore_ascii_case(name),
+            })
+    }
+
+    // Cleans the structure in place and returns the report of the changes, one line each
+    pub fn sanitize(&self, structure: &mut PDB) -> Vec<String> {
+        let mut report: Vec<String> = Vec::new();
+        for chain in structure.chains_mut() {
+            let chain_id = chain.id().to_string();
+            let removed: Vec<(isize, Option<String>)> = chain
+                .residues()
+                .filter(|residue| {
+                    let remove = (self.waters && is_water(residue)) || self.removes_hetero(residue);
+                    if remove {
+                        let kind = if is_water(residue) {
+                            "water"
+                        } else {
+                            "hetero group"
+                        };
+                        report.push(format!("Removed {} {}", kind, residue_id(chain, residue)));
+                    }
+                    remove
+                })
+                .map(|residue| {
+                    (
+                        residue.serial_number(),
+                        residue.insertion_code().map(String::from),
+                    )
+                })
+                .collect();
+            chain.remove_residues_by(|residue| {
+                removed.iter().any(|(serial_number, insertion_code)| {
+                    residue.id() == (*serial_number, insertion_code.as_deref())
+                })
+            });
+
+            if self.altlocs {
+                for residue in chain.residues_mut() {
+                    if let Some(line) = remove_altlocs(residue) {
+                        report.push(format!(
+                            "{} of {}.{}.{}{}",
+                            line,
+                            chain_id,
+                            residue.name().unwrap_or(""),
+                            residue.serial_number(),
+                            residue.insertion_code().unwrap_or("")
+                        ));
+                    }
+                }
+            }
+
+            if self.terminals {
+                let mut renamed: Vec<String> = Vec::new();
+                for residue in chain.residues_mut() {
+                    let res_id = format!(
+                        "{}.{}{}",
+                        residue.name().unwrap_or(""),
+                        residue.serial_number(),
+                        residue.insertion_code().unwrap_or("")
+                    );
+                    for atom in residue.atoms_mut() {
+                        if let Some(name) = rename_terminal(atom) {
+                            renamed.push(format!(
+                                "Renamed terminal atom {} to {} in {}.{}",
+                                name,
+                                atom.name(),
+                                chain_id,
+                                res_id
+                            ));
+                        }
+                    }
+                }
+                report.extend(renamed);
+            }
+        }
+        structure.remove_empty();
+        report
+    }
+}
+
+// Keeps the alternate location with the highest occupancy (the first one on ties) as part of the
+// residue and removes the rest, returning the report line if there was any
+fn remove_altlocs(residue: &mut Residue) -> Option<String> {
+    let mut locations: Vec<(String, f64)> = Vec::new();
+    for conformer in residue.conformers() {
+        if let Some(location) = conformer.alternative_location() {
+            let occupancy = conformer.atoms().map(|atom| atom.occupancy()).sum::<f64>()
+                / conformer.atom_count().max(1) as f64;
+            locations.push((location.to_string(), occupancy));
+        }
+    }
+    if locations.is_empty() {
+        return None;
+    }
+    let mut kept = 0;
+    for (i, location) in locations.iter().enumerate() {
+        if location.1 > locations[kept].1 {
+            kept = i;
+        }
+    }
+    let kept_location = locations[kept].0.clone();
+    let kept_atoms: Vec<(String, Atom)> = residue
+        .conformers()
+        .filter(|conformer| conformer.alternative_location() == Some(kept_location.as_str()))
+        .flat_map(|conformer| {
+            conformer
+                .atoms()
+                .map(move |atom| (conformer.name().to_string(), atom.clone()))
+        })
+        .collect();
+    residue.remove_conformers_by(|conformer| conformer.alternative_location().is_some());
+    for (name, mut atom) in kept_atoms {
+        atom.set_occupancy(1.0).unwrap();
+        residue.add_atom(atom, (name.as_str(), None));
+    }
+    let removed: Vec<String> = locations
+        .into_iter()
+        .map(|(location, _)| location)
+        .filter(|location| *location != kept_location)
+        .collect();
+    Some(format!(
+        "Kept alternate location {} (removed {})",
+        kept_location,
+        removed.join(", ")
+    ))
+}
+
+// Renames the atom if it has a nonstandard terminal name, returning its former name
+fn rename_terminal(atom: &mut Atom) -> Option<String> {
+    if atom.hetero() {
+        return None;
+    }
+    let (name, standard) = TERMINAL_NAMES
+        .iter()
+        .find(|(name, _)| *name == atom.name())?;
+    atom.set_name(*standard).unwrap();
+    Some(name.to_string())
+}
+
+pub fn write_report<W: Write>(output: &mut W, title: &str, report: &[String]) -> Result<(), Error> {
+    writeln!(output, "# {}: {} changes", title, report.len())?;
+    for line in report.iter() {
+        writeln!(output, "{}", line)?;
+    }
+    output.flush()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::structure::parse_structure;
+
+    #[test]
+    fn test_sanitize() {
+        let contents = "\
+ATOM      1  N   SER A   1       0.000   1.000   2.000  1.00  0.00           N\n\
+ATOM      2  CA ASER A   1       1.000   1.000   2.000  0.40  0.00           C\n\
+ATOM      3  CA BSER A   1       1.100   1.000   2.000  0.60  0.00           C\n\
+ATOM      4  OT1 SER A   1       2.000   1.000   2.000  1.00  0.00           O\n\
+ATOM      5  OT2 SER A   1       2.500   1.000   2.000  1.00  0.00           O\n\
+HETATM    6  SE  MSE A   2       4.000   1.000   2.000  1.00  0.00          SE\n\
+HETATM    7  C1  MSE A   2       4.500   1.000   2.000  1.00  0.00           C\n\
+HETATM    8 ZN    ZN A 101       6.000   1.000   2.000  1.00  0.00          ZN\n\
+HETATM    9  S   SO4 A 102       7.000   1.000   2.000  1.00  0.00           S\n\
+HETATM   10  O1  SO4 A 102       7.500   1.000   2.000  1.00  0.00           O\n\
+HETATM   11  O   HOH A 201       9.000   1.000   2.000  1.00  0.00           O\n";
+        let mut structure = parse_structure(contents, 1).unwrap().unwrap();
+        let sanitizer = Sanitizer {
+            hetero: vec![String::from("ions"), String::from("ligands")],
+            ..Default::default()
+        };
+        let report = sanitizer.sanitize(&mut structure);
+        assert_eq!(
+            report,
+            vec![
+                "Removed hetero group A.ZN.101",
+                "Removed hetero group A.SO4.102",
+                "Removed water A.HOH.201",
+                "Kept alternate location B (removed A) of A.SER.1",
+                "Renamed terminal atom OT1 to O in A.SER.1",
+                "Renamed terminal atom OT2 to OXT in A.SER.1",
+            ]
+        );
+        let residue = structure.residues().next().unwrap();
+        assert_eq!(residue.conformer_count(), 1);
+        let names: Vec<&str> = residue.atoms().map(|atom| atom.name()).collect();
+        assert_eq!(names, vec!["N", "O", "OXT", "CA"]);
+        assert_eq!(residue.atoms().nth(3).unwrap().x(), 1.1);
+        // Modified residues are kept
+        assert_eq!(structure.residue_count(), 2);
+
+        let mut output: Vec<u8> = Vec::new();
+        write_report(&mut output, "receptor", &report[..1]).unwrap();
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "# receptor: 1 changes\nRemoved hetero group A.ZN.101\n"
+        );
+    }
+}
@@ -1,11 +1,124 @@
+use super::constants::{
+    INTERFACE_CUTOFF, INTERFACE_CUTOFF2, REJECTED_POSE_SCORE, RESTRAINTS_DISTANCE_DECAY,
+};
+use super::decomposition::ResidueEnergies;
+use super::pose::Pose;
 use super::qt::Quaternion;
+use super::registry::{provider, registered_method};
+use super::ternary::LigandPose;
+use log::info;
+use lru::LruCache;
+use std::cell::{Cell, RefCell};
 use std::collections::HashMap;
+use std::fmt;
+use std::num::NonZeroUsize;
+use wide::{f32x8, f64x4};
 
-#[derive(Debug)]
+#[derive(Clone, Copy, PartialEq, Eq)]
 pub enum Method {
     DFIRE,
+    DFIRE2,
     DNA,
     PYDOCK,
+    SIPPER,
+    TOBI,
+    // Scoring function of a downstream crate by its name, see registry::register_scoring
+    Registered(&'static str),
+}
+
+pub const METHODS: [Method; 6] = [
+    Method::DFIRE,
+    Method::DFIRE2,
+    Method::DNA,
+    Method::PYDOCK,
+    Method::SIPPER,
+    Method::TOBI,
+];
+
+impl Method {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Method::DFIRE => "dfire",
+            Method::DFIRE2 => "dfire2",
+            Method::DNA => "dna",
+            Method::PYDOCK => "pydock",
+            Method::SIPPER => "sipper",
+            Method::TOBI => "tobi",
+            Method::Registered(name) => name,
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Method> {
+        METHODS
+            .into_iter()
+            .find(|method| method.name() == name.to_lowercase())
+            .or_else(|| registered_method(name))
+    }
+
+    pub fn description(&self) -> &'static str {
+        match self {
+            Method::DFIRE => "Atomic statistical potential (DFIRE) for protein-protein docking",
+            Method::DFIRE2 => "Atomic statistical potential (DFIRE2) for protein-protein docking",
+            Method::DNA => {
+                "AMBER electrostatics and Van der Waals for protein-nucleic acid docking"
+            }
+            Method::PYDOCK => "pyDock electrostatics and Van der Waals for protein-protein docking",
+            Method::SIPPER => {
+                "Residue-pair statistical potential (SIPPER) for protein-protein docking"
+            }
+            Method::TOBI => "Coarse-grained residue potential (TOBI) for protein-protein docking",
+            Method::Registered(name) => provider(name)
+                .map_or("Scoring function not registered", |provider| {
+                    provider.description()
+                }),
+        }
+    }
+
+    // External data files, see data::find_data_file
+    pub fn data_files(&self) -> &'static [&'static str] {
+        match self {
+            Method::DFIRE => &["DCparams"],
+            Method::DFIRE2 => &["dfire2_pair.lib"],
+            Method::SIPPER => &["sipper.dat"],
+            Method::TOBI => &["tobi.dat"],
+            Method::DNA | Method::PYDOCK | Method::Registered(_) => &[],
+        }
+    }
+
+    // Whether structures must include hydrogens, the statistical potentials have no types for them
+    pub fn needs_hydrogens(&self) -> bool {
+        match self {
+            Method::DFIRE | Method::DFIRE2 | Method::SIPPER | Method::TOBI => false,
+            Method::DNA | Method::PYDOCK => true,
+            Method::Registered(name) => {
+                provider(name).is_ok_and(|provider| provider.needs_hydrogens())
+            }
+        }
+    }
+
+    // Whether an electrostatics term is scored, charges come from the built-in AMBER tables
+    pub fn uses_charges(&self) -> bool {
+        match self {
+            Method::DFIRE | Method::DFIRE2 | Method::SIPPER | Method::TOBI => false,
+            Method::DNA | Method::PYDOCK => true,
+            Method::Registered(_) => false,
+        }
+    }
+}
+
+// Built-in methods by their variant, registered ones by their name
+impl fmt::Debug for Method {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Method::DFIRE => write!(f, "DFIRE"),
+            Method::DFIRE2 => write!(f, "DFIRE2"),
+            Method::DNA => write!(f, "DNA"),
+            Method::PYDOCK => write!(f, "PYDOCK"),
+            Method::SIPPER => write!(f, "SIPPER"),
+            Method::TOBI => write!(f, "TOBI"),
+            Method::Registered(_) => write!(f, "{}", self.name()),
+        }
+    }
 }
 
 pub trait Score {
@@ -15,7 +128,1032 @@ pub trait Score {
         rotation: &Quaternion,
         rec_nmodes: &[f64],
         lig_nmodes: &[f64],
-    ) -> f64;
+    ) -> f64;
+
+    // Energies of many poses, in their order. Scoring functions may evaluate them together, by
+    // default they are scored one after the other.
+    fn energies(&self, poses: &[Pose]) -> Vec<f64> {
+        poses
+            .iter()
+            .map(|pose| {
+                self.energy(
+                    &pose.translation,
+                    &pose.rotation,
+                    &pose.rec_nmodes,
+                    &pose.lig_nmodes,
+                )
+            })
+            .collect()
+    }
+
+    // Energy of a pose split by receptor and ligand residues, None if not supported
+    fn residue_energies(
+        &self,
+        _translation: &[f64],
+        _rotation: &Quaternion,
+        _rec_nmodes: &[f64],
+        _lig_nmodes: &[f64],
+    ) -> Option<ResidueEnergies> {
+        None
+    }
+
+    // Energy of a pose with the residues at its interface, None if not supported
+    fn energy_with_interface(
+        &self,
+        translation: &[f64],
+        rotation: &Quaternion,
+        rec_nmodes: &[f64],
+        lig_nmodes: &[f64],
+    ) -> (f64, Option<Interface>) {
+        (
+            self.energy(translation, rotation, rec_nmodes, lig_nmodes),
+            None,
+        )
+    }
+
+    // Fraction of the active restraints of both partners satisfied by a pose, None if there are
+    // no restraints or the scoring function does not support them
+    fn restraints_satisfaction(
+        &self,
+        _translation: &[f64],
+        _rotation: &Quaternion,
+        _rec_nmodes: &[f64],
+        _lig_nmodes: &[f64],
+    ) -> Option<f64> {
+        None
+    }
+
+    // Energy with a second ligand at the given pose, None if the scoring function does not
+    // support it, only multi-ligand scoring functions do
+    fn second_ligand_energy(
+        &self,
+        _translation: &[f64],
+        _rotation: &Quaternion,
+        _rec_nmodes: &[f64],
+        _lig_nmodes: &[f64],
+        _second: &LigandPose,
+    ) -> Option<f64> {
+        None
+    }
+
+    // Analytical derivatives of the energy over the parameters of numerical_gradient, None if the
+    // scoring function has none or the pose is rejected
+    fn gradient(
+        &self,
+        _translation: &[f64],
+        _rotation: &Quaternion,
+        _rec_nmodes: &[f64],
+        _lig_nmodes: &[f64],
+    ) -> Option<Gradient> {
+        None
+    }
+
+    // Central finite differences of the energy over the translation, a rotation applied on top
+    // of the pose one (as a rotation vector, in radians) and the normal mode extents, eps being
+    // the step of every parameter
+    fn numerical_gradient(
+        &self,
+        translation: &[f64],
+        rotation: &Quaternion,
+        rec_nmodes: &[f64],
+        lig_nmodes: &[f64],
+        eps: f64,
+    ) -> Gradient {
+        let mut gradient = Gradient {
+            translation: [0.0; 3],
+            rotation: [0.0; 3],
+            rec_nmodes: vec![0.0; rec_nmodes.len()],
+            lig_nmodes: vec![0.0; lig_nmodes.len()],
+        };
+        let mut shifted = translation.to_vec();
+        for k in 0..3 {
+            shifted[k] = translation[k] + eps;
+            let plus = self.energy(&shifted, rotation, rec_nmodes, lig_nmodes);
+            shifted[k] = translation[k] - eps;
+            let minus = self.energy(&shifted, rotation, rec_nmodes, lig_nmodes);
+            shifted[k] = translation[k];
+            gradient.translation[k] = (plus - minus) / (2.0 * eps);
+        }
+        for k in 0..3 {
+            let mut step = [0.0; 3];
+            step[k] = eps;
+            let plus_rotation = Quaternion::from_rotation_vector(&step) * *rotation;
+            step[k] = -eps;
+            let minus_rotation = Quaternion::from_rotation_vector(&step) * *rotation;
+            let plus = self.energy(translation, &plus_rotation, rec_nmodes, lig_nmodes);
+            let minus = self.energy(translation, &minus_rotation, rec_nmodes, lig_nmodes);
+            gradient.rotation[k] = (plus - minus) / (2.0 * eps);
+        }
+        let mut shifted = rec_nmodes.to_vec();
+        for k in 0..rec_nmodes.len() {
+            shifted[k] = rec_nmodes[k] + eps;
+            let plus = self.energy(translation, rotation, &shifted, lig_nmodes);
+            shifted[k] = rec_nmodes[k] - eps;
+            let minus = self.energy(translation, rotation, &shifted, lig_nmodes);
+            shifted[k] = rec_nmodes[k];
+            gradient.rec_nmodes[k] = (plus - minus) / (2.0 * eps);
+        }
+        let mut shifted = lig_nmodes.to_vec();
+        for k in 0..lig_nmodes.len() {
+            shifted[k] = lig_nmodes[k] + eps;
+            let plus = self.energy(translation, rotation, rec_nmodes, &shifted);
+            shifted[k] = lig_nmodes[k] - eps;
+            let minus = self.energy(translation, rotation, rec_nmodes, &shifted);
+            shifted[k] = lig_nmodes[k];
+            gradient.lig_nmodes[k] = (plus - minus) / (2.0 * eps);
+        }
+        gradient
+    }
+}
+
+// Derivatives of the energy with respect to the pose parameters
+#[derive(Debug, Clone, PartialEq)]
+pub struct Gradient {
+    pub translation: [f64; 3],
+    pub rotation: [f64; 3],
+    pub rec_nmodes: Vec<f64>,
+    pub lig_nmodes: Vec<f64>,
+}
+
+impl Gradient {
+    // All the derivatives as a single vector, in the order of the fields
+    pub fn to_vec(&self) -> Vec<f64> {
+        self.translation
+            .iter()
+            .chain(self.rotation.iter())
+            .chain(self.rec_nmodes.iter())
+            .chain(self.lig_nmodes.iter())
+            .copied()
+            .collect()
+    }
+
+    pub fn scale(&mut self, factor: f64) {
+        for value in self
+            .translation
+            .iter_mut()
+            .chain(self.rotation.iter_mut())
+            .chain(self.rec_nmodes.iter_mut())
+            .chain(self.lig_nmodes.iter_mut())
+        {
+            *value *= factor;
+        }
+    }
+
+    // Add the derivatives of another energy times the given weight
+    pub fn add(&mut self, other: &Gradient, weight: f64) {
+        for k in 0..3 {
+            self.translation[k] += weight * other.translation[k];
+            self.rotation[k] += weight * other.rotation[k];
+        }
+        for (value, other) in self.rec_nmodes.iter_mut().zip(other.rec_nmodes.iter()) {
+            *value += weight * other;
+        }
+        for (value, other) in self.lig_nmodes.iter_mut().zip(other.lig_nmodes.iter()) {
+            *value += weight * other;
+        }
+    }
+
+    pub fn norm(&self) -> f64 {
+        self.to_vec()
+            .iter()
+            .map(|value| value * value)
+            .sum::<f64>()
+            .sqrt()
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Calibration {
+    pub scale: f64,
+    pub offset: f64,
+}
+
+impl Default for Calibration {
+    fn default() -> Self {
+        Calibration {
+            scale: 1.0,
+            offset: 0.0,
+        }
+    }
+}
+
+impl Calibration {
+    pub fn new(scale: f64, offset: f64) -> Self {
+        Calibration { scale, offset }
+    }
+
+    // A rejected pose stays rejected whatever the scale
+    pub fn apply(&self, score: f64) -> f64 {
+        if score == REJECTED_POSE_SCORE {
+            return score;
+        }
+        self.scale * score + self.offset
+    }
+}
+
+// Buffers reused by every energy evaluation of a scoring function to avoid allocations
+#[derive(Default)]
+pub struct Workspace {
+    pub receptor_coordinates: Vec<[f64; 3]>,
+    pub ligand_coordinates: Vec<[f64; 3]>,
+    pub interface_receptor: Vec<usize>,
+    pub interface_ligand: Vec<usize>,
+    // Receptor and ligand atoms in cells, if the atom pairs within a cutoff are searched
+    pub cell_list: Option<CellList>,
+    // Receptor heavy atoms in the cells of the clash filter, if any
+    pub clash_grid: ClashGrid,
+}
+
+impl Workspace {
+    pub fn new(receptor_coordinates: &[[f64; 3]], ligand_coordinates: &[[f64; 3]]) -> Workspace {
+        Workspace {
+            receptor_coordinates: receptor_coordinates.to_vec(),
+            ligand_coordinates: ligand_coordinates.to_vec(),
+            interface_receptor: vec![0; receptor_coordinates.len()],
+            interface_ligand: vec![0; ligand_coordinates.len()],
+            cell_list: None,
+            clash_grid: ClashGrid::default(),
+        }
+    }
+
+    // Starts an evaluation from the original coordinates. A rigid receptor is never moved, so
+    // its coordinates are only copied back if its normal modes are applied.
+    pub fn reset(
+        &mut self,
+        receptor_coordinates: &[[f64; 3]],
+        ligand_coordinates: &[[f64; 3]],
+        receptor_moves: bool,
+    ) {
+        if receptor_moves {
+            self.receptor_coordinates
+                .copy_from_slice(receptor_coordinates);
+        }
+        self.ligand_coordinates.copy_from_slice(ligand_coordinates);
+        self.interface_receptor.fill(0);
+        self.interface_ligand.fill(0);
+    }
+}
+
+// Floating point type of the coordinates in a cell list, with the kernel computing the squared
+// distances of the ligand atoms in a neighbor list to a receptor atom as many at a time as fit in
+// a SIMD register
+pub trait Real: Copy + Default + Into<f64> {
+    fn squared_distances(
+        xs: &[Self],
+        ys: &[Self],
+        zs: &[Self],
+        coordinate: &[Self; 3],
+        distances: &mut [Self],
+    );
+}
+
+// The differences are squared and added in the same order as one pair at a time, so the
+// distances are exactly the same
+macro_rules! impl_real {
+    ($real:ty, $simd:ty, $lanes:expr) => {
+        impl Real for $real {
+            fn squared_distances(
+                xs: &[$real],
+                ys: &[$real],
+                zs: &[$real],
+                coordinate: &[$real; 3],
+                distances: &mut [$real],
+            ) {
+                let (x1, y1, z1) = (
+                    <$simd>::splat(coordinate[0]),
+                    <$simd>::splat(coordinate[1]),
+                    <$simd>::splat(coordinate[2]),
+                );
+                let lanes = xs.len() / $lanes * $lanes;
+                for k in (0..lanes).step_by($lanes) {
+                    let load = |values: &[$real]| {
+                        <$simd>::from(<[$real; $lanes]>::try_from(&values[k..k + $lanes]).unwrap())
+                    };
+                    let dx = x1 - load(xs);
+                    let dy = y1 - load(ys);
+                    let dz = z1 - load(zs);
+                    let distance2 = dx * dx + dy * dy + dz * dz;
+                    distances[k..k + $lanes].copy_from_slice(&distance2.to_array());
+                }
+                for k in lanes..xs.len() {
+                    distances[k] = (coordinate[0] - xs[k]) * (coordinate[0] - xs[k])
+                        + (coordinate[1] - ys[k]) * (coordinate[1] - ys[k])
+                        + (coordinate[2] - zs[k]) * (coordinate[2] - zs[k]);
+                }
+            }
+        }
+    };
+}
+
+impl_real!(f64, f64x4, 4);
+impl_real!(f32, f32x8, 8);
+
+// Receptor atoms hashed in cubic cells with the side of the cutoff, so the ligand atoms closer than
+// the cutoff to a receptor atom are among the ones in the 27 cells around its cell instead of all
+// the ligand atoms. Ligand atoms around every cell are kept in order, so the atom pairs are still
+// visited as in the loop over all the receptor and then all the ligand atoms and the scores summed
+// over them do not change.
+#[derive(Debug, Clone, Default)]
+pub struct CellList<T: Real = f64> {
+    pub cutoff: f64,
+    origin: [f64; 3],
+    dimensions: [usize; 3],
+    // Cell of every receptor atom
+    cells: Vec<usize>,
+    // Ligand atoms around every cell, from starts[cell] to starts[cell + 1]
+    starts: Vec<usize>,
+    neighbors: Vec<usize>,
+    // Coordinates of the ligand atoms in the neighbor lists, one array per axis so their
+    // distances to a receptor atom are computed several at a time
+    xs: Vec<T>,
+    ys: Vec<T>,
+    zs: Vec<T>,
+    // Squared distances of the last neighbor list to its receptor atom
+    distances: Vec<T>,
+}
+
+impl<T: Real> CellList<T> {
+    pub fn new(receptor_coordinates: &[[T; 3]], cutoff: f64) -> CellList<T> {
+        let mut cell_list = CellList {
+            cutoff,
+            ..Default::default()
+        };
+        cell_list.update(receptor_coordinates);
+        cell_list
+    }
+
+    pub fn num_cells(&self) -> usize {
+        self.dimensions.iter().product()
+    }
+
+    // Hashes the receptor atoms again, after they moved
+    pub fn update(&mut self, receptor_coordinates: &[[T; 3]]) {
+        let mut lower = [0.0; 3];
+        let mut upper = [0.0; 3];
+        if let Some(first) = receptor_coordinates.first() {
+            lower = first.map(Into::into);
+            upper = lower;
+        }
+        for coordinate in receptor_coordinates.iter() {
+            for k in 0..3 {
+                lower[k] = lower[k].min(coordinate[k].into());
+                upper[k] = upper[k].max(coordinate[k].into());
+            }
+        }
+        self.origin = lower;
+        for k in 0..3 {
+            self.dimensions[k] = ((upper[k] - lower[k]) / self.cutoff) as usize + 1;
+        }
+        self.cells.clear();
+        for coordinate in receptor_coordinates.iter() {
+            let mut index = [0usize; 3];
+            for k in 0..3 {
+                index[k] = (((coordinate[k].into() - self.origin[k]) / self.cutoff) as usize)
+                    .min(self.dimensions[k] - 1);
+            }
+            self.cells
+                .push((index[0] * self.dimensions[1] + index[1]) * self.dimensions[2] + index[2]);
+        }
+    }
+
+    // Cells around a ligand atom and their number, none if it is beyond the cutoff of all of them
+    fn around(&self, coordinate: &[T; 3], cells: &mut [usize; 27]) -> usize {
+        let mut lower = [0usize; 3];
+        let mut upper = [0usize; 3];
+        for k in 0..3 {
+            let position = ((coordinate[k].into() - self.origin[k]) / self.cutoff).floor();
+            if position < -1.0 || position > self.dimensions[k] as f64 {
+                return 0;
+            }
+            let position = position as i64;
+            lower[k] = (position - 1).max(0) as usize;
+            upper[k] = (position + 1).min(self.dimensions[k] as i64 - 1) as usize;
+        }
+        let mut num_cells = 0;
+        for x in lower[0]..=upper[0] {
+            for y in lower[1]..=upper[1] {
+                for z in lower[2]..=upper[2] {
+                    cells[num_cells] = (x * self.dimensions[1] + y) * self.dimensions[2] + z;
+                    num_cells += 1;
+                }
+            }
+        }
+        num_cells
+    }
+
+    // Sorts the ligand atoms of the pose in the cells around them
+    pub fn search(&mut self, ligand_coordinates: &[[T; 3]]) {
+        let num_cells = self.num_cells();
+        let mut cells = [0usize; 27];
+        // Ligand atoms around every cell, counted two positions ahead so the sums are the starts
+        // of the cells one position ahead, moved to their place while the cells are filled
+        self.starts.clear();
+        self.starts.resize(num_cells + 2, 0);
+        for coordinate in ligand_coordinates.iter() {
+            let n = self.around(coordinate, &mut cells);
+            for &cell in cells[..n].iter() {
+                self.starts[cell + 2] += 1;
+            }
+        }
+        for cell in 2..num_cells + 2 {
+            self.starts[cell] += self.starts[cell - 1];
+        }
+        let num_neighbors = self.starts[num_cells + 1];
+        self.neighbors.resize(num_neighbors, 0);
+        self.xs.resize(num_neighbors, T::default());
+        self.ys.resize(num_neighbors, T::default());
+        self.zs.resize(num_neighbors, T::default());
+        for (j, coordinate) in ligand_coordinates.iter().enumerate() {
+            let n = self.around(coordinate, &mut cells);
+            for &cell in cells[..n].iter() {
+                let k = self.starts[cell + 1];
+                self.neighbors[k] = j;
+                self.xs[k] = coordinate[0];
+                self.ys[k] = coordinate[1];
+                self.zs[k] = coordinate[2];
+                self.starts[cell + 1] += 1;
+            }
+        }
+    }
+
+    // Ligand atoms that may be closer than the cutoff to the receptor atom, in order
+    #[inline]
+    pub fn neighbors(&self, receptor_atom: usize) -> &[usize] {
+        let cell = self.cells[receptor_atom];
+        &self.neighbors[self.starts[cell]..self.starts[cell + 1]]
+    }
+
+    // Neighbors of the receptor atom at the given coordinate and their squared distances to it
+    pub fn distances(&mut self, receptor_atom: usize, coordinate: &[T; 3]) -> (&[usize], &[T]) {
+        let cell = self.cells[receptor_atom];
+        let (start, end) = (self.starts[cell], self.starts[cell + 1]);
+        self.distances.resize(end - start, T::default());
+        T::squared_distances(
+            &self.xs[start..end],
+            &self.ys[start..end],
+            &self.zs[start..end],
+            coordinate,
+            &mut self.distances,
+        );
+        (&self.neighbors[start..end], &self.distances)
+    }
+}
+
+// Receptor and ligand residues in contact in a pose, as residue IDs in the order of the structures
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Interface {
+    pub receptor: Vec<String>,
+    pub ligand: Vec<String>,
+}
+
+// Residues of the atoms flagged in the interface of an evaluation
+pub fn interface_residues(
+    interface: &[usize],
+    atom_residues: &[usize],
+    residues: &[String],
+) -> Vec<String> {
+    let mut flagged = vec![false; residues.len()];
+    for (i, &in_interface) in interface.iter().enumerate() {
+        if in_interface == 1 {
+            flagged[atom_residues[i]] = true;
+        }
+    }
+    residues
+        .iter()
+        .zip(flagged)
+        .filter(|(_, flagged)| *flagged)
+        .map(|(residue, _)| residue.clone())
+        .collect()
+}
+
+pub struct Calibrated {
+    pub scoring: Box<dyn Score>,
+    pub calibration: Calibration,
+}
+
+impl Calibrated {
+    pub fn new(scoring: Box<dyn Score>, calibration: Calibration) -> Box<dyn Score> {
+        Box::new(Calibrated {
+            scoring,
+            calibration,
+        })
+    }
+}
+
+impl Score for Calibrated {
+    fn energy(
+        &self,
+        translation: &[f64],
+        rotation: &Quaternion,
+        rec_nmodes: &[f64],
+        lig_nmodes: &[f64],
+    ) -> f64 {
+        // Bring the raw scoring to a scale comparable with other methods
+        self.calibration.apply(
+            self.scoring
+                .energy(translation, rotation, rec_nmodes, lig_nmodes),
+        )
+    }
+
+    fn energies(&self, poses: &[Pose]) -> Vec<f64> {
+        self.scoring
+            .energies(poses)
+            .into_iter()
+            .map(|energy| self.calibration.apply(energy))
+            .collect()
+    }
+
+    fn residue_energies(
+        &self,
+        translation: &[f64],
+        rotation: &Quaternion,
+        rec_nmodes: &[f64],
+        lig_nmodes: &[f64],
+    ) -> Option<ResidueEnergies> {
+        let mut energies =
+            self.scoring
+                .residue_energies(translation, rotation, rec_nmodes, lig_nmodes)?;
+        energies.scale(self.calibration.scale);
+        energies.offset += self.calibration.offset;
+        Some(energies)
+    }
+
+    fn energy_with_interface(
+        &self,
+        translation: &[f64],
+        rotation: &Quaternion,
+        rec_nmodes: &[f64],
+        lig_nmodes: &[f64],
+    ) -> (f64, Option<Interface>) {
+        let (energy, interface) =
+            self.scoring
+                .energy_with_interface(translation, rotation, rec_nmodes, lig_nmodes);
+        (self.calibration.apply(energy), interface)
+    }
+
+    fn gradient(
+        &self,
+        translation: &[f64],
+        rotation: &Quaternion,
+        rec_nmodes: &[f64],
+        lig_nmodes: &[f64],
+    ) -> Option<Gradient> {
+        let mut gradient = self
+            .scoring
+            .gradient(translation, rotation, rec_nmodes, lig_nmodes)?;
+        gradient.scale(self.calibration.scale);
+        Some(gradient)
+    }
+
+    fn restraints_satisfaction(
+        &self,
+        translation: &[f64],
+        rotation: &Quaternion,
+        rec_nmodes: &[f64],
+        lig_nmodes: &[f64],
+    ) -> Option<f64> {
+        self.scoring
+            .restraints_satisfaction(translation, rotation, rec_nmodes, lig_nmodes)
+    }
+
+    fn second_ligand_energy(
+        &self,
+        translation: &[f64],
+        rotation: &Quaternion,
+        rec_nmodes: &[f64],
+        lig_nmodes: &[f64],
+        second: &LigandPose,
+    ) -> Option<f64> {
+        let energy = self.scoring.second_ligand_energy(
+            translation,
+            rotation,
+            rec_nmodes,
+            lig_nmodes,
+            second,
+        )?;
+        Some(self.calibration.apply(energy))
+    }
+}
+
+pub struct Prescreened {
+    pub coarse: Box<dyn Score>,
+    pub full: Box<dyn Score>,
+    pub threshold: f64,
+}
+
+impl Prescreened {
+    pub fn new(coarse: Box<dyn Score>, full: Box<dyn Score>, threshold: f64) -> Box<dyn Score> {
+        Box::new(Prescreened {
+            coarse,
+            full,
+            threshold,
+        })
+    }
+}
+
+impl Score for Prescreened {
+    fn energy(
+        &self,
+        translation: &[f64],
+        rotation: &Quaternion,
+        rec_nmodes: &[f64],
+        lig_nmodes: &[f64],
+    ) -> f64 {
+        // Only poses surviving the cheap model are evaluated with the full one
+        let coarse = self
+            .coarse
+            .energy(translation, rotation, rec_nmodes, lig_nmodes);
+        if coarse < self.threshold {
+            return REJECTED_POSE_SCORE;
+        }
+        self.full
+            .energy(translation, rotation, rec_nmodes, lig_nmodes)
+    }
+
+    fn energies(&self, poses: &[Pose]) -> Vec<f64> {
+        // The surviving poses are scored together by the full model
+        let mut energies = self.coarse.energies(poses);
+        let survivors: Vec<Pose> = poses
+            .iter()
+            .zip(energies.iter())
+            .filter(|(_, &coarse)| coarse >= self.threshold)
+            .map(|(pose, _)| pose.clone())
+            .collect();
+        let mut full = self.full.energies(&survivors).into_iter();
+        for energy in energies.iter_mut() {
+            *energy = if *energy < self.threshold {
+                REJECTED_POSE_SCORE
+            } else {
+                full.next().unwrap()
+            };
+        }
+        energies
+    }
+
+    fn residue_energies(
+        &self,
+        translation: &[f64],
+        rotation: &Quaternion,
+        rec_nmodes: &[f64],
+        lig_nmodes: &[f64],
+    ) -> Option<ResidueEnergies> {
+        self.full
+            .residue_energies(translation, rotation, rec_nmodes, lig_nmodes)
+    }
+
+    fn energy_with_interface(
+        &self,
+        translation: &[f64],
+        rotation: &Quaternion,
+        rec_nmodes: &[f64],
+        lig_nmodes: &[f64],
+    ) -> (f64, Option<Interface>) {
+        let coarse = self
+            .coarse
+            .energy(translation, rotation, rec_nmodes, lig_nmodes);
+        if coarse < self.threshold {
+            return (REJECTED_POSE_SCORE, None);
+        }
+        self.full
+            .energy_with_interface(translation, rotation, rec_nmodes, lig_nmodes)
+    }
+
+    fn gradient(
+        &self,
+        translation: &[f64],
+        rotation: &Quaternion,
+        rec_nmodes: &[f64],
+        lig_nmodes: &[f64],
+    ) -> Option<Gradient> {
+        let coarse = self
+            .coarse
+            .energy(translation, rotation, rec_nmodes, lig_nmodes);
+        if coarse < self.threshold {
+            return None;
+        }
+        self.full
+            .gradient(translation, rotation, rec_nmodes, lig_nmodes)
+    }
+
+    fn restraints_satisfaction(
+        &self,
+        translation: &[f64],
+        rotation: &Quaternion,
+        rec_nmodes: &[f64],
+        lig_nmodes: &[f64],
+    ) -> Option<f64> {
+        self.full
+            .restraints_satisfaction(translation, rotation, rec_nmodes, lig_nmodes)
+    }
+
+    // The first ligand is prescreened, the second one only scored by the full model
+    fn second_ligand_energy(
+        &self,
+        translation: &[f64],
+        rotation: &Quaternion,
+        rec_nmodes: &[f64],
+        lig_nmodes: &[f64],
+        second: &LigandPose,
+    ) -> Option<f64> {
+        let coarse = self
+            .coarse
+            .energy(translation, rotation, rec_nmodes, lig_nmodes);
+        if coarse < self.threshold {
+            return Some(REJECTED_POSE_SCORE);
+        }
+        self.full
+            .second_ligand_energy(translation, rotation, rec_nmodes, lig_nmodes, second)
+    }
+}
+
+// Consensus of several scoring functions, the weighted sum of their scores. The interface and
+// restraints of a pose are the ones of the first function.
+pub struct CompositeScore {
+    pub terms: Vec<(Box<dyn Score>, f64)>,
+}
+
+impl CompositeScore {
+    pub fn new(terms: Vec<(Box<dyn Score>, f64)>) -> Box<dyn Score> {
+        if terms.is_empty() {
+            panic!("Composite score requires at least one scoring function");
+        }
+        Box::new(CompositeScore { terms })
+    }
+
+    // Weighted sum of the scores, rejected if any function rejects the pose
+    fn combine(&self, scores: impl Iterator<Item = f64>) -> f64 {
+        let mut total = 0.0;
+        for (score, (_, weight)) in scores.zip(self.terms.iter()) {
+            if score == REJECTED_POSE_SCORE {
+                return REJECTED_POSE_SCORE;
+            }
+            total += weight * score;
+        }
+        total
+    }
+}
+
+impl Score for CompositeScore {
+    fn energy(
+        &self,
+        translation: &[f64],
+        rotation: &Quaternion,
+        rec_nmodes: &[f64],
+        lig_nmodes: &[f64],
+    ) -> f64 {
+        self.combine(
+            self.terms
+                .iter()
+                .map(|(scoring, _)| scoring.energy(translation, rotation, rec_nmodes, lig_nmodes)),
+        )
+    }
+
+    fn energies(&self, poses: &[Pose]) -> Vec<f64> {
+        // Every function scores the poses together
+        let energies: Vec<Vec<f64>> = self
+            .terms
+            .iter()
+            .map(|(scoring, _)| scoring.energies(poses))
+            .collect();
+        (0..poses.len())
+            .map(|i| self.combine(energies.iter().map(|term| term[i])))
+            .collect()
+    }
+
+    fn residue_energies(
+        &self,
+        translation: &[f64],
+        rotation: &Quaternion,
+        rec_nmodes: &[f64],
+        lig_nmodes: &[f64],
+    ) -> Option<ResidueEnergies> {
+        // Only decomposed if every function is
+        let mut energies: Option<ResidueEnergies> = None;
+        for (scoring, weight) in self.terms.iter() {
+            let term = scoring.residue_energies(translation, rotation, rec_nmodes, lig_nmodes)?;
+            match energies.as_mut() {
+                Some(energies) => energies.merge(&term, *weight),
+                None => {
+                    let mut term = term;
+                    term.scale(*weight);
+                    energies = Some(term);
+                }
+            }
+        }
+        energies
+    }
+
+    fn energy_with_interface(
+        &self,
+        translation: &[f64],
+        rotation: &Quaternion,
+        rec_nmodes: &[f64],
+        lig_nmodes: &[f64],
+    ) -> (f64, Option<Interface>) {
+        let (first, interface) =
+            self.terms[0]
+                .0
+                .energy_with_interface(translation, rotation, rec_nmodes, lig_nmodes);
+        let energy =
+            self.combine(std::iter::once(first).chain(self.terms[1..].iter().map(
+                |(scoring, _)| scoring.energy(translation, rotation, rec_nmodes, lig_nmodes),
+            )));
+        (energy, interface)
+    }
+
+    fn gradient(
+        &self,
+        translation: &[f64],
+        rotation: &Quaternion,
+        rec_nmodes: &[f64],
+        lig_nmodes: &[f64],
+    ) -> Option<Gradient> {
+        // Only analytical if every function has it
+        let mut gradient: Option<Gradient> = None;
+        for (scoring, weight) in self.terms.iter() {
+            let term = scoring.gradient(translation, rotation, rec_nmodes, lig_nmodes)?;
+            match gradient.as_mut() {
+                Some(gradient) => gradient.add(&term, *weight),
+                None => {
+                    let mut term = term;
+                    term.scale(*weight);
+                    gradient = Some(term);
+                }
+            }
+        }
+        gradient
+    }
+
+    fn restraints_satisfaction(
+        &self,
+        translation: &[f64],
+        rotation: &Quaternion,
+        rec_nmodes: &[f64],
+        lig_nmodes: &[f64],
+    ) -> Option<f64> {
+        self.terms[0]
+            .0
+            .restraints_satisfaction(translation, rotation, rec_nmodes, lig_nmodes)
+    }
+
+    // Only supported if every function is
+    fn second_ligand_energy(
+        &self,
+        translation: &[f64],
+        rotation: &Quaternion,
+        rec_nmodes: &[f64],
+        lig_nmodes: &[f64],
+        second: &LigandPose,
+    ) -> Option<f64> {
+        let scores = self
+            .terms
+            .iter()
+            .map(|(scoring, _)| {
+                scoring.second_ligand_energy(translation, rotation, rec_nmodes, lig_nmodes, second)
+            })
+            .collect::<Option<Vec<f64>>>()?;
+        Some(self.combine(scores.into_iter()))
+    }
+}
+
+// Glowworms converging onto the same leader end up evaluating almost identical poses, energies
+// are cached by pose parameters rounded to the given resolutions. A quaternion component or an
+// extent changes far less than a translation for the same displacement of the ligand atoms, so
+// they are rounded to their own angular resolution
+pub struct Memoized {
+    pub scoring: Box<dyn Score>,
+    pub resolution: f64,
+    pub angular_resolution: f64,
+    cache: RefCell<LruCache<Vec<i64>, f64>>,
+    evaluations: Cell<usize>,
+    hits: Cell<usize>,
+}
+
+impl Memoized {
+    pub fn new(
+        scoring: Box<dyn Score>,
+        size: usize,
+        resolution: f64,
+        angular_resolution: f64,
+    ) -> Box<dyn Score> {
+        let size = NonZeroUsize::new(size).expect("Energy cache size must be positive");
+        Box::new(Memoized {
+            scoring,
+            resolution,
+            angular_resolution,
+            cache: RefCell::new(LruCache::new(size)),
+            evaluations: Cell::new(0),
+            hits: Cell::new(0),
+        })
+    }
+
+    fn key(
+        &self,
+        translation: &[f64],
+        rotation: &Quaternion,
+        rec_nmodes: &[f64],
+        lig_nmodes: &[f64],
+    ) -> Vec<i64> {
+        let angular = [rotation.w, rotation.x, rotation.y, rotation.z]
+            .into_iter()
+            .chain(rec_nmodes.iter().copied())
+            .chain(lig_nmodes.iter().copied());
+        translation
+            .iter()
+            .map(|value| (value / self.resolution).round() as i64)
+            .chain(angular.map(|value| (value / self.angular_resolution).round() as i64))
+            .collect()
+    }
+}
+
+impl Score for Memoized {
+    fn energy(
+        &self,
+        translation: &[f64],
+        rotation: &Quaternion,
+        rec_nmodes: &[f64],
+        lig_nmodes: &[f64],
+    ) -> f64 {
+        self.evaluations.set(self.evaluations.get() + 1);
+        let key = self.key(translation, rotation, rec_nmodes, lig_nmodes);
+        if let Some(&energy) = self.cache.borrow_mut().get(&key) {
+            self.hits.set(self.hits.get() + 1);
+            return energy;
+        }
+        let energy = self
+            .scoring
+            .energy(translation, rotation, rec_nmodes, lig_nmodes);
+        self.cache.borrow_mut().put(key, energy);
+        energy
+    }
+
+    fn residue_energies(
+        &self,
+        translation: &[f64],
+        rotation: &Quaternion,
+        rec_nmodes: &[f64],
+        lig_nmodes: &[f64],
+    ) -> Option<ResidueEnergies> {
+        self.scoring
+            .residue_energies(translation, rotation, rec_nmodes, lig_nmodes)
+    }
+
+    // Interfaces are not cached, the pose is always evaluated
+    fn energy_with_interface(
+        &self,
+        translation: &[f64],
+        rotation: &Quaternion,
+        rec_nmodes: &[f64],
+        lig_nmodes: &[f64],
+    ) -> (f64, Option<Interface>) {
+        self.scoring
+            .energy_with_interface(translation, rotation, rec_nmodes, lig_nmodes)
+    }
+
+    fn gradient(
+        &self,
+        translation: &[f64],
+        rotation: &Quaternion,
+        rec_nmodes: &[f64],
+        lig_nmodes: &[f64],
+    ) -> Option<Gradient> {
+        self.scoring
+            .gradient(translation, rotation, rec_nmodes, lig_nmodes)
+    }
+
+    fn restraints_satisfaction(
+        &self,
+        translation: &[f64],
+        rotation: &Quaternion,
+        rec_nmodes: &[f64],
+        lig_nmodes: &[f64],
+    ) -> Option<f64> {
+        self.scoring
+            .restraints_satisfaction(translation, rotation, rec_nmodes, lig_nmodes)
+    }
+
+    // The key does not include the pose of the second ligand, these poses are not cached
+    fn second_ligand_energy(
+        &self,
+        translation: &[f64],
+        rotation: &Quaternion,
+        rec_nmodes: &[f64],
+        lig_nmodes: &[f64],
+        second: &LigandPose,
+    ) -> Option<f64> {
+        self.scoring
+            .second_ligand_energy(translation, rotation, rec_nmodes, lig_nmodes, second)
+    }
+}
+
+impl Drop for Memoized {
+    fn drop(&mut self) {
+        info!(
+            "Energy cache: {} hits in {} evaluations",
+            self.hits.get(),
+            self.evaluations.get()
+        );
+    }
 }
 
 pub fn satisfied_restraints(interface: &[usize], restraints: &HashMap<String, Vec<usize>>) -> f64 {
@@ -35,6 +1173,163 @@ pub fn satisfied_restraints(interface: &[usize], restraints: &HashMap<String, Ve
     num_residues as f64 / restraints.len() as f64
 }
 
+// Fraction of the atoms of the restrained residues at the interface
+pub fn satisfied_restraint_atoms(
+    interface: &[usize],
+    restraints: &HashMap<String, Vec<usize>>,
+) -> f64 {
+    let num_atoms: usize = restraints
+        .values()
+        .map(|atom_indexes| atom_indexes.len())
+        .sum();
+    if num_atoms == 0 {
+        return 0.0;
+    }
+    let num_contacts: usize = restraints
+        .values()
+        .flatten()
+        .filter(|&&i| interface[i] == 1)
+        .count();
+    num_contacts as f64 / num_atoms as f64
+}
+
+// Mean satisfaction of the restrained residues, 1.0 for residues at the interface and decaying
+// exponentially with the distance from their closest atom to the partner beyond the cutoff
+pub fn restraints_distance_satisfaction(
+    restraints: &HashMap<String, Vec<usize>>,
+    coordinates: &[[f64; 3]],
+    partner_coordinates: &[[f64; 3]],
+) -> f64 {
+    if restraints.is_empty() {
+        return 0.0;
+    }
+    let mut satisfaction = 0.0;
+    for atom_indexes in restraints.values() {
+        let mut min_distance2 = f64::MAX;
+        for &i in atom_indexes.iter() {
+            let a = &coordinates[i];
+            for b in partner_coordinates.iter() {
+                let distance2 = (a[0] - b[0]) * (a[0] - b[0])
+                    + (a[1] - b[1]) * (a[1] - b[1])
+                    + (a[2] - b[2]) * (a[2] - b[2]);
+                min_distance2 = min_distance2.min(distance2);
+            }
+        }
+        satisfaction += if in_interface(min_distance2) {
+            1.0
+        } else {
+            (-(min_distance2.sqrt() - INTERFACE_CUTOFF) / RESTRAINTS_DISTANCE_DECAY).exp()
+        };
+    }
+    satisfaction / restraints.len() as f64
+}
+
+// How the satisfaction of the active restraints of a partner biases the scoring:
+//  - residues (default): fraction of the restrained residues with an atom at the interface,
+//  - atoms: fraction of the atoms of the restrained residues at the interface, so large residues
+//    barely touching the partner count less,
+//  - distance: residues at the interface count fully, farther ones less and less with the
+//    distance from the partner, so poses close to satisfying a restraint are told apart.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum RestraintsMetric {
+    #[default]
+    Residues,
+    Atoms,
+    Distance,
+}
+
+pub const RESTRAINTS_METRICS: [RestraintsMetric; 3] = [
+    RestraintsMetric::Residues,
+    RestraintsMetric::Atoms,
+    RestraintsMetric::Distance,
+];
+
+impl RestraintsMetric {
+    pub fn name(&self) -> &'static str {
+        match self {
+            RestraintsMetric::Residues => "residues",
+            RestraintsMetric::Atoms => "atoms",
+            RestraintsMetric::Distance => "distance",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<RestraintsMetric> {
+        RESTRAINTS_METRICS
+            .into_iter()
+            .find(|metric| metric.name() == name.to_lowercase())
+    }
+
+    // Satisfaction from 0.0 to 1.0 of the restraints of a partner in the evaluated pose
+    pub fn satisfaction(
+        &self,
+        interface: &[usize],
+        restraints: &HashMap<String, Vec<usize>>,
+        coordinates: &[[f64; 3]],
+        partner_coordinates: &[[f64; 3]],
+    ) -> f64 {
+        match self {
+            RestraintsMetric::Residues => satisfied_restraints(interface, restraints),
+            RestraintsMetric::Atoms => satisfied_restraint_atoms(interface, restraints),
+            RestraintsMetric::Distance => {
+                restraints_distance_satisfaction(restraints, coordinates, partner_coordinates)
+            }
+        }
+    }
+}
+
+// Weights of the terms of the DNA and pyDock energies. The default ones give the energy scored
+// so far, electrostatics and van der Waals without desolvation, the ones of pyDock its reference
+// energy. Only pyDock has a desolvation term.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TermWeights {
+    pub electrostatics: f64,
+    pub vdw: f64,
+    pub desolvation: f64,
+}
+
+impl Default for TermWeights {
+    fn default() -> Self {
+        TermWeights {
+            electrostatics: 1.0,
+            vdw: 1.0,
+            desolvation: 0.0,
+        }
+    }
+}
+
+impl TermWeights {
+    pub fn pydock() -> TermWeights {
+        TermWeights {
+            electrostatics: 1.0,
+            vdw: 0.1,
+            desolvation: 1.0,
+        }
+    }
+}
+
+// Satisfied active restraints of both partners over all of them
+pub fn restraints_satisfaction(
+    interface_receptor: &[usize],
+    rec_restraints: &HashMap<String, Vec<usize>>,
+    interface_ligand: &[usize],
+    lig_restraints: &HashMap<String, Vec<usize>>,
+) -> Option<f64> {
+    let num_restraints = rec_restraints.len() + lig_restraints.len();
+    if num_restraints == 0 {
+        return None;
+    }
+    let satisfied = satisfied_restraints(interface_receptor, rec_restraints)
+        * rec_restraints.len() as f64
+        + satisfied_restraints(interface_ligand, lig_restraints) * lig_restraints.len() as f64;
+    Some(satisfied / num_restraints as f64)
+}
+
+// Receptor and ligand atoms are at the interface when closer than INTERFACE_CUTOFF Angstroms, the
+// criterion of every scoring function given the squared distance between both atoms
+pub fn in_interface(distance2: f64) -> bool {
+    distance2 <= INTERFACE_CUTOFF2
+}
+
 pub fn membrane_intersection(interface: &[usize], membrane: &[usize]) -> f64 {
     if membrane.is_empty() {
         return 0.0;
@@ -45,3 +1340,588 @@ pub fn membrane_intersection(interface: &[usize], membrane: &[usize]) -> f64 {
     }
     num_beads as f64 / membrane.len() as f64
 }
+
+// Membrane beads hashed in cells of the interface cutoff, to find the beads crossed by the ligand
+// straight from its coordinates, without the interface of the whole receptor. Beads do not move
+// with the normal modes of the receptor.
+#[derive(Debug, Clone)]
+pub struct MembraneGrid {
+    pub cutoff: f64,
+    pub beads: Vec<[f64; 3]>,
+    cells: HashMap<(i64, i64, i64), Vec<usize>>,
+    // Beads crossed by the pose being evaluated
+    crossed: RefCell<Vec<bool>>,
+}
+
+impl MembraneGrid {
+    pub fn new(beads: Vec<[f64; 3]>) -> MembraneGrid {
+        let mut grid = MembraneGrid {
+            cutoff: INTERFACE_CUTOFF,
+            cells: HashMap::new(),
+            crossed: RefCell::new(vec![false; beads.len()]),
+            beads,
+        };
+        for (i, bead) in grid.beads.iter().enumerate() {
+            grid.cells.entry(grid.cell(bead)).or_default().push(i);
+        }
+        grid
+    }
+
+    fn cell(&self, coordinate: &[f64; 3]) -> (i64, i64, i64) {
+        (
+            (coordinate[0] / self.cutoff).floor() as i64,
+            (coordinate[1] / self.cutoff).floor() as i64,
+            (coordinate[2] / self.cutoff).floor() as i64,
+        )
+    }
+
+    // Fraction of the beads at the interface with a ligand atom, like membrane_intersection
+    pub fn intersection(&self, ligand_coordinates: &[[f64; 3]]) -> f64 {
+        if self.beads.is_empty() {
+            return 0.0;
+        }
+        let mut crossed = self.crossed.borrow_mut();
+        crossed.fill(false);
+        let mut num_beads: usize = 0;
+        for la in ligand_coordinates.iter() {
+            let (cx, cy, cz) = self.cell(la);
+            for dx in -1..=1 {
+                for dy in -1..=1 {
+                    for dz in -1..=1 {
+                        if let Some(beads) = self.cells.get(&(cx + dx, cy + dy, cz + dz)) {
+                            for &i in beads.iter() {
+                                let bead = &self.beads[i];
+                                let dist = (bead[0] - la[0]) * (bead[0] - la[0])
+                                    + (bead[1] - la[1]) * (bead[1] - la[1])
+                                    + (bead[2] - la[2]) * (bead[2] - la[2]);
+                                if in_interface(dist) && !crossed[i] {
+                                    crossed[i] = true;
+                                    num_beads += 1;
+                                }
+                            }
+                        }
+                    }
+                }
+            }
+        }
+        num_beads as f64 / self.beads.len() as f64
+    }
+}
+
+// Receptor heavy atoms hashed in cells with the side of the clash cutoff, built once with the
+// scoring function and hashed again only when the normal modes move the receptor. Cells keep
+// their buffers, so evaluations do not allocate.
+#[derive(Debug, Clone, Default)]
+pub struct ClashGrid {
+    cells: HashMap<(i64, i64, i64), Vec<usize>>,
+}
+
+#[derive(Debug, Clone)]
+pub struct ClashFilter {
+    pub cutoff: f64,
+    pub max_clashes: usize,
+}
+
+impl ClashFilter {
+    pub fn new(cutoff: f64, max_clashes: usize) -> Self {
+        ClashFilter {
+            cutoff,
+            max_clashes,
+        }
+    }
+
+    fn cell(&self, coordinate: &[f64; 3]) -> (i64, i64, i64) {
+        (
+            (coordinate[0] / self.cutoff).floor() as i64,
+            (coordinate[1] / self.cutoff).floor() as i64,
+            (coordinate[2] / self.cutoff).floor() as i64,
+        )
+    }
+
+    // Receptor heavy atoms in the cells of the filter
+    pub fn grid(
+        &self,
+        receptor_coordinates: &[[f64; 3]],
+        receptor_heavy_atoms: &[usize],
+    ) -> ClashGrid {
+        let mut grid = ClashGrid::default();
+        self.update_grid(&mut grid, receptor_coordinates, receptor_heavy_atoms);
+        grid
+    }
+
+    // Hashes the receptor heavy atoms again, after the normal modes moved them
+    pub fn update_grid(
+        &self,
+        grid: &mut ClashGrid,
+        receptor_coordinates: &[[f64; 3]],
+        receptor_heavy_atoms: &[usize],
+    ) {
+        for atoms in grid.cells.values_mut() {
+            atoms.clear();
+        }
+        for &i in receptor_heavy_atoms.iter() {
+            grid.cells
+                .entry(self.cell(&receptor_coordinates[i]))
+                .or_default()
+                .push(i);
+        }
+    }
+
+    pub fn is_clashing(
+        &self,
+        grid: &ClashGrid,
+        receptor_coordinates: &[[f64; 3]],
+        ligand_coordinates: &[[f64; 3]],
+        ligand_heavy_atoms: &[usize],
+    ) -> bool {
+        // Count heavy atom pairs closer than the cutoff in the cells around every ligand heavy
+        // atom, stopping as soon as the limit is exceeded
+        let cutoff2 = self.cutoff * self.cutoff;
+        let mut num_clashes: usize = 0;
+        for &j in ligand_heavy_atoms.iter() {
+            let la = &ligand_coordinates[j];
+            let (cx, cy, cz) = self.cell(la);
+            for dx in -1..=1 {
+                for dy in -1..=1 {
+                    for dz in -1..=1 {
+                        if let Some(atoms) = grid.cells.get(&(cx + dx, cy + dy, cz + dz)) {
+                            for &i in atoms.iter() {
+                                let ra = &receptor_coordinates[i];
+                                let dist = (ra[0] - la[0]) * (ra[0] - la[0])
+                                    + (ra[1] - la[1]) * (ra[1] - la[1])
+                                    + (ra[2] - la[2]) * (ra[2] - la[2]);
+                                if dist < cutoff2 {
+                                    num_clashes += 1;
+                                    if num_clashes > self.max_clashes {
+                                        return true;
+                                    }
+                                }
+                            }
+                        }
+                    }
+                }
+            }
+        }
+        false
+    }
+}
+
+pub fn hotspot_bias(
+    interface: &[usize],
+    hotspots: &HashMap<String, Vec<usize>>,
+    weights: &HashMap<String, f64>,
+) -> f64 {
+    // Weighted fraction of hot-spot residues found at the interface. A weight of 1.0
+    // for every residue behaves like the percentage of satisfied restraints.
+    if hotspots.is_empty() {
+        return 0.0;
+    }
+    let mut weighted_residues = 0.0;
+    for (residue_id, atom_indexes) in hotspots.iter() {
+        if atom_indexes.iter().any(|&i| interface[i] == 1) {
+            weighted_residues += weights.get(residue_id).copied().unwrap_or(0.0);
+        }
+    }
+    weighted_residues / hotspots.len() as f64
+}
+
+pub fn z_scores(scores: &[f64]) -> Vec<f64> {
+    // Normalize scores against their own distribution, zero if there is no spread
+    if scores.is_empty() {
+        return Vec::new();
+    }
+    let n = scores.len() as f64;
+    let mean = scores.iter().sum::<f64>() / n;
+    let std = (scores.iter().map(|s| (s - mean) * (s - mean)).sum::<f64>() / n).sqrt();
+    scores
+        .iter()
+        .map(|s| if std > 0.0 { (s - mean) / std } else { 0.0 })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::rc::Rc;
+
+    #[test]
+    fn test_method_names() {
+        for method in METHODS {
+            assert_eq!(
+                Method::from_name(method.name()).unwrap().name(),
+                method.name()
+            );
+        }
+        assert!(matches!(Method::from_name("PyDock"), Some(Method::PYDOCK)));
+        assert!(Method::from_name("vdw").is_none());
+    }
+
+    struct Quadratic;
+
+    impl Score for Quadratic {
+        fn energy(
+            &self,
+            translation: &[f64],
+            rotation: &Quaternion,
+            rec_nmodes: &[f64],
+            lig_nmodes: &[f64],
+        ) -> f64 {
+            let axis = rotation.rotate_point(&[1.0, 0.0, 0.0]);
+            -translation[0] * translation[0] + 2.0 * translation[1] + axis[1]
+                - 3.0 * rec_nmodes[0] * rec_nmodes[0]
+                + lig_nmodes[0]
+        }
+    }
+
+    #[test]
+    fn test_numerical_gradient() {
+        let gradient = Quadratic.numerical_gradient(
+            &[1.0, 0.0, 5.0],
+            &Quaternion::default(),
+            &[0.5],
+            &[2.0],
+            1e-4,
+        );
+        let expected = [-2.0, 2.0, 0.0, 0.0, 0.0, 1.0, -3.0, 1.0];
+        let values = gradient.to_vec();
+        assert_eq!(values.len(), expected.len());
+        for (value, expected) in values.iter().zip(expected.iter()) {
+            assert!((value - expected).abs() < 1e-6);
+        }
+        assert!((gradient.norm() - 19.0_f64.sqrt()).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_hotspot_bias() {
+        let interface: Vec<usize> = vec![1, 0, 0, 1, 0];
+        let mut hotspots: HashMap<String, Vec<usize>> = HashMap::new();
+        hotspots.insert(String::from("A.ALA.1"), vec![0, 1]);
+        hotspots.insert(String::from("A.LYS.2"), vec![2]);
+        let mut weights: HashMap<String, f64> = HashMap::new();
+        weights.insert(String::from("A.ALA.1"), 1.5);
+        weights.insert(String::from("A.LYS.2"), 0.5);
+
+        assert_eq!(hotspot_bias(&interface, &hotspots, &weights), 0.75);
+        assert_eq!(hotspot_bias(&interface, &HashMap::new(), &weights), 0.0);
+    }
+
+    #[test]
+    fn test_restraints_satisfaction() {
+        let mut rec_restraints: HashMap<String, Vec<usize>> = HashMap::new();
+        rec_restraints.insert(String::from("A.ALA.1"), vec![0, 1]);
+        rec_restraints.insert(String::from("A.LYS.2"), vec![2]);
+        let mut lig_restraints: HashMap<String, Vec<usize>> = HashMap::new();
+        lig_restraints.insert(String::from("B.GLY.1"), vec![0]);
+
+        // One receptor and one ligand restraint satisfied out of three
+        let satisfaction =
+            restraints_satisfaction(&[0, 1, 0], &rec_restraints, &[1], &lig_restraints);
+        assert!((satisfaction.unwrap() - 2.0 / 3.0).abs() < 1e-12);
+        let empty = HashMap::new();
+        assert_eq!(
+            restraints_satisfaction(&[0, 1, 0], &empty, &[1], &empty),
+            None
+        );
+    }
+
+    #[test]
+    fn test_restraints_metrics() {
+        let mut restraints: HashMap<String, Vec<usize>> = HashMap::new();
+        restraints.insert(String::from("A.ALA.1"), vec![0, 1, 2]);
+        restraints.insert(String::from("A.LYS.2"), vec![3]);
+        let coordinates: Vec<[f64; 3]> = vec![
+            [0.0, 0.0, 0.0],
+            [1.0, 0.0, 0.0],
+            [2.0, 0.0, 0.0],
+            [20.0, 0.0, 0.0],
+        ];
+        let partner: Vec<[f64; 3]> = vec![[-3.0, 0.0, 0.0], [20.0, 3.9 + 2.0, 0.0]];
+        // Only the first atom of the first residue is at the interface
+        let interface: Vec<usize> = vec![1, 0, 0, 0];
+
+        let satisfaction = |metric: RestraintsMetric| {
+            metric.satisfaction(&interface, &restraints, &coordinates, &partner)
+        };
+        assert_eq!(satisfaction(RestraintsMetric::Residues), 0.5);
+        assert_eq!(satisfaction(RestraintsMetric::Atoms), 0.25);
+        // The second residue is one decay length away from the interface
+        let expected = (1.0 + (-1.0_f64).exp()) / 2.0;
+        assert!((satisfaction(RestraintsMetric::Distance) - expected).abs() < 1e-12);
+        assert_eq!(
+            RestraintsMetric::Distance.satisfaction(
+                &interface,
+                &HashMap::new(),
+                &coordinates,
+                &partner
+            ),
+            0.0
+        );
+        assert_eq!(
+            RestraintsMetric::from_name("Atoms"),
+            Some(RestraintsMetric::Atoms)
+        );
+    }
+
+    #[test]
+    fn test_clash_filter() {
+        let receptor: Vec<[f64; 3]> = vec![[0.0, 0.0, 0.0], [1.0, 0.0, 0.0], [10.0, 0.0, 0.0]];
+        let ligand: Vec<[f64; 3]> = vec![[0.5, 0.5, 0.0], [10.5, 0.0, 0.0], [20.0, 0.0, 0.0]];
+
+        // Three pairs are below the cutoff
+        let filter = ClashFilter::new(1.0, 2);
+        let grid = filter.grid(&receptor, &[0, 1, 2]);
+        assert!(filter.is_clashing(&grid, &receptor, &ligand, &[0, 1, 2]));
+        let filter = ClashFilter::new(1.0, 3);
+        assert!(!filter.is_clashing(&grid, &receptor, &ligand, &[0, 1, 2]));
+        // Only heavy atoms are taken into account
+        let filter = ClashFilter::new(1.0, 0);
+        let grid = filter.grid(&receptor, &[0, 1]);
+        assert!(!filter.is_clashing(&grid, &receptor, &ligand, &[1, 2]));
+
+        // The grid follows the receptor atoms once hashed again
+        let mut grid = filter.grid(&receptor, &[0, 1, 2]);
+        let moved: Vec<[f64; 3]> = vec![[20.0, 0.5, 0.0], [30.0, 0.0, 0.0], [40.0, 0.0, 0.0]];
+        assert!(!filter.is_clashing(&grid, &moved, &ligand, &[2]));
+        filter.update_grid(&mut grid, &moved, &[0, 1, 2]);
+        assert!(filter.is_clashing(&grid, &moved, &ligand, &[2]));
+        assert!(!filter.is_clashing(&grid, &moved, &ligand, &[0, 1]));
+    }
+
+    #[test]
+    fn test_membrane_grid() {
+        let beads: Vec<[f64; 3]> = vec![
+            [0.0, 0.0, 0.0],
+            [6.0, 0.0, 0.0],
+            [12.0, 0.0, 0.0],
+            [-3.0, 0.0, 0.0],
+        ];
+        let grid = MembraneGrid::new(beads);
+        // The first bead is crossed by two atoms, the last one just within the cutoff
+        let ligand: Vec<[f64; 3]> = vec![[1.0, 1.0, 0.0], [0.0, -1.0, 0.0], [-6.5, 1.5, 0.0]];
+        assert_eq!(grid.intersection(&ligand), 0.5);
+        // Same fraction as from the interface of the whole receptor
+        let interface: Vec<usize> =
+            grid.beads
+                .iter()
+                .map(|bead| {
+                    ligand.iter().any(|la| {
+                        in_interface((0..3).map(|k| (bead[k] - la[k]).powi(2)).sum::<f64>())
+                    }) as usize
+                })
+                .collect();
+        assert_eq!(membrane_intersection(&interface, &[0, 1, 2, 3]), 0.5);
+        assert_eq!(grid.intersection(&[[20.0, 0.0, 0.0]]), 0.0);
+        assert_eq!(MembraneGrid::new(Vec::new()).intersection(&ligand), 0.0);
+    }
+
+    #[test]
+    fn test_cell_list() {
+        use rand::{Rng, SeedableRng};
+
+        let mut rng: rand::prelude::StdRng = SeedableRng::seed_from_u64(0);
+        let mut random_coordinates = |n: usize, size: f64| -> Vec<[f64; 3]> {
+            (0..n)
+                .map(|_| {
+                    [
+                        rng.gen_range(0.0, size),
+                        rng.gen_range(0.0, size),
+                        rng.gen_range(0.0, size),
+                    ]
+                })
+                .collect()
+        };
+        let receptor = random_coordinates(300, 40.0);
+        let mut ligand = random_coordinates(100, 60.0);
+        // Atoms on the border of the cutoff and far from all the cells
+        ligand.push([receptor[0][0] + 5.0, receptor[0][1], receptor[0][2]]);
+        ligand.push([-100.0, 0.0, 0.0]);
+        let close = |receptor: &[[f64; 3]], i: usize, j: usize| {
+            let (ra, la) = (receptor[i], ligand[j]);
+            (ra[0] - la[0]).powi(2) + (ra[1] - la[1]).powi(2) + (ra[2] - la[2]).powi(2) <= 25.0
+        };
+
+        let mut cell_list = CellList::new(&receptor, 5.0);
+        assert!(cell_list.num_cells() > 27);
+        cell_list.search(&ligand);
+        let mut num_pairs = 0;
+        for i in 0..receptor.len() {
+            let neighbors = cell_list.neighbors(i);
+            // In order and with all the ligand atoms within the cutoff
+            assert!(neighbors.windows(2).all(|pair| pair[0] < pair[1]));
+            assert!(neighbors.len() < ligand.len());
+            for j in 0..ligand.len() {
+                if close(&receptor, i, j) {
+                    assert!(neighbors.contains(&j));
+                    num_pairs += 1;
+                }
+            }
+            assert!(!neighbors.contains(&(ligand.len() - 1)));
+        }
+        assert!(num_pairs > 0);
+        assert!(cell_list.neighbors(0).contains(&(ligand.len() - 2)));
+
+        // Moved receptor atoms are found in their new cells
+        let moved: Vec<[f64; 3]> = receptor.iter().map(|r| [r[0] + 10.0, r[1], r[2]]).collect();
+        cell_list.update(&moved);
+        cell_list.search(&ligand);
+        for i in 0..moved.len() {
+            for j in 0..ligand.len() {
+                if close(&moved, i, j) {
+                    assert!(cell_list.neighbors(i).contains(&j));
+                }
+            }
+        }
+
+        // Same distances four at a time as one pair at a time
+        for (i, ra) in moved.iter().enumerate() {
+            let (neighbors, distances) = cell_list.distances(i, ra);
+            assert_eq!(neighbors.len(), distances.len());
+            for (&j, &distance2) in neighbors.iter().zip(distances.iter()) {
+                let la = ligand[j];
+                let expected = (ra[0] - la[0]) * (ra[0] - la[0])
+                    + (ra[1] - la[1]) * (ra[1] - la[1])
+                    + (ra[2] - la[2]) * (ra[2] - la[2]);
+                assert_eq!(distance2.to_bits(), expected.to_bits());
+            }
+        }
+    }
+
+    #[test]
+    fn test_z_scores() {
+        assert_eq!(z_scores(&[1.0, 3.0, 5.0, 7.0]).len(), 4);
+        let z = z_scores(&[2.0, 4.0, 4.0, 4.0, 5.0, 5.0, 7.0, 9.0]);
+        assert_eq!(z[0], -1.5);
+        assert_eq!(z[7], 2.0);
+        assert_eq!(z_scores(&[3.0, 3.0]), vec![0.0, 0.0]);
+        assert!(z_scores(&[]).is_empty());
+    }
+
+    #[test]
+    fn test_calibration() {
+        let calibration = Calibration::new(0.5, -2.0);
+        assert_eq!(calibration.apply(10.0), 3.0);
+        assert_eq!(Calibration::default().apply(-7.5), -7.5);
+        assert_eq!(calibration.apply(REJECTED_POSE_SCORE), REJECTED_POSE_SCORE);
+    }
+
+    struct Height(f64);
+
+    impl Score for Height {
+        fn energy(&self, translation: &[f64], _: &Quaternion, _: &[f64], _: &[f64]) -> f64 {
+            translation[2] + self.0
+        }
+    }
+
+    #[test]
+    fn test_prescreened() {
+        let scoring = Prescreened::new(Box::new(Height(0.0)), Box::new(Height(100.0)), 1.0);
+        let rotation = Quaternion::default();
+        assert_eq!(scoring.energy(&[0.0, 0.0, 2.0], &rotation, &[], &[]), 102.0);
+        assert_eq!(
+            scoring.energy(&[0.0, 0.0, 0.5], &rotation, &[], &[]),
+            REJECTED_POSE_SCORE
+        );
+
+        // Same energies in a batch, in the order of the poses
+        let poses: Vec<Pose> = [2.0, 0.5, 3.0]
+            .iter()
+            .map(|&z| Pose::new(vec![0.0, 0.0, z], rotation, Vec::new(), Vec::new()))
+            .collect();
+        assert_eq!(
+            scoring.energies(&poses),
+            vec![102.0, REJECTED_POSE_SCORE, 103.0]
+        );
+        let calibrated = Calibrated::new(Box::new(Height(0.0)), Calibration::new(2.0, 1.0));
+        assert_eq!(calibrated.energies(&poses), vec![5.0, 2.0, 7.0]);
+        assert!(calibrated.energies(&[]).is_empty());
+    }
+
+    #[test]
+    fn test_composite_score() {
+        let scoring = CompositeScore::new(vec![
+            (Box::new(Height(0.0)), 1.0),
+            (Box::new(Height(10.0)), 0.5),
+        ]);
+        let rotation = Quaternion::default();
+        assert_eq!(scoring.energy(&[0.0, 0.0, 2.0], &rotation, &[], &[]), 8.0);
+        let poses: Vec<Pose> = [2.0, 4.0]
+            .iter()
+            .map(|&z| Pose::new(vec![0.0, 0.0, z], rotation, Vec::new(), Vec::new()))
+            .collect();
+        assert_eq!(scoring.energies(&poses), vec![8.0, 11.0]);
+        assert_eq!(
+            scoring.energy_with_interface(&[0.0, 0.0, 2.0], &rotation, &[], &[]),
+            (8.0, None)
+        );
+        // Not decomposed by the test functions
+        assert!(scoring
+            .residue_energies(&[0.0, 0.0, 2.0], &rotation, &[], &[])
+            .is_none());
+
+        // Functions calibrated on their own before the weighted sum
+        let scoring = CompositeScore::new(vec![
+            (
+                Calibrated::new(Box::new(Height(0.0)), Calibration::new(2.0, 1.0)),
+                1.0,
+            ),
+            (
+                Calibrated::new(Box::new(Height(10.0)), Calibration::new(0.5, -5.0)),
+                0.5,
+            ),
+        ]);
+        assert_eq!(scoring.energy(&[0.0, 0.0, 2.0], &rotation, &[], &[]), 5.5);
+        assert_eq!(scoring.energies(&poses), vec![5.5, 10.0]);
+
+        // A pose rejected by any function is rejected
+        let prescreened = Prescreened::new(Box::new(Height(0.0)), Box::new(Height(0.0)), 1.0);
+        let scoring = CompositeScore::new(vec![(Box::new(Height(0.0)), 1.0), (prescreened, 1.0)]);
+        assert_eq!(
+            scoring.energy(&[0.0, 0.0, 0.5], &rotation, &[], &[]),
+            REJECTED_POSE_SCORE
+        );
+    }
+
+    struct Counter(Rc<Cell<usize>>);
+
+    impl Score for Counter {
+        fn energy(&self, translation: &[f64], _: &Quaternion, _: &[f64], _: &[f64]) -> f64 {
+            self.0.set(self.0.get() + 1);
+            translation[0]
+        }
+    }
+
+    #[test]
+    fn test_memoized() {
+        let calls = Rc::new(Cell::new(0));
+        let scoring = Memoized::new(Box::new(Counter(calls.clone())), 2, 0.01, 0.0001);
+        let rotation = Quaternion::default();
+        assert_eq!(scoring.energy(&[1.0, 0.0, 0.0], &rotation, &[], &[]), 1.0);
+        // Same pose within the resolution
+        assert_eq!(scoring.energy(&[1.001, 0.0, 0.0], &rotation, &[], &[]), 1.0);
+        assert_eq!(scoring.energy(&[2.0, 0.0, 0.0], &rotation, &[], &[]), 2.0);
+        assert_eq!(scoring.energy(&[3.0, 0.0, 0.0], &rotation, &[], &[]), 3.0);
+        // Least recently used pose has been evicted
+        assert_eq!(scoring.energy(&[1.0, 0.0, 0.0], &rotation, &[], &[]), 1.0);
+        assert_eq!(calls.get(), 4);
+    }
+
+    #[test]
+    fn test_memoized_rotation() {
+        let calls = Rc::new(Cell::new(0));
+        // Coarse translation resolution, quaternion components differ by less than it
+        let scoring = Memoized::new(Box::new(Counter(calls.clone())), 16, 0.1, 0.0001);
+        let one = Quaternion::from_rotation_vector(&[0.0, 0.0, 0.0]);
+        let two = Quaternion::from_rotation_vector(&[0.0, 0.0, 3.0_f64.to_radians()]);
+        assert!((two.z - one.z).abs() < 0.1);
+        scoring.energy(&[1.0, 0.0, 0.0], &one, &[], &[]);
+        scoring.energy(&[1.0, 0.0, 0.0], &two, &[], &[]);
+        assert_eq!(calls.get(), 2);
+        // Same rotation within the angular resolution
+        scoring.energy(&[1.0, 0.0, 0.0], &two, &[], &[]);
+        assert_eq!(calls.get(), 2);
+        // Extents are rounded to the angular resolution too
+        scoring.energy(&[1.0, 0.0, 0.0], &two, &[0.01], &[]);
+        scoring.energy(&[1.0, 0.0, 0.0], &two, &[0.02], &[]);
+        assert_eq!(calls.get(), 4);
+    }
+}
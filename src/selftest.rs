@@ -0,0 +1,127 @@
+use super::build_scoring;
+use super::policy::ResiduePolicy;
+use super::qt::Quaternion;
+use super::registry::ScoringInput;
+use super::scoring::{Method, Score, METHODS};
+use pdbtbx::{Context, StrictnessLevel, PDB};
+use std::io::{BufReader, Cursor};
+use std::panic;
+use std::time::{Duration, Instant};
+
+// The structures of the unit tests are embedded so an installation can be checked without the
+// source tree: every scoring function is run on the complex in its crystal pose and the energy
+// compared to the one of the unit tests. DFIRE2, SIPPER and TOBI are left out, their potentials
+// are not distributed and there are no reference energies of the Python LightDock for them.
+
+const ENERGY_TOLERANCE: f64 = 1e-6;
+
+const STRUCTURES: [(&str, &str, &str); 2] = [
+    (
+        "1azp",
+        include_str!("../tests/1azp/1azp_receptor.pdb"),
+        include_str!("../tests/1azp/1azp_ligand.pdb"),
+    ),
+    (
+        "2oob",
+        include_str!("../tests/2oob/2oob_receptor.pdb"),
+        include_str!("../tests/2oob/2oob_ligand.pdb"),
+    ),
+];
+
+#[derive(Debug)]
+pub struct SelfTestResult {
+    pub method: Method,
+    pub complex: &'static str,
+    pub expected: f64,
+    // Energy or the reason the scoring function could not be run
+    pub energy: Result<f64, String>,
+    pub elapsed: Duration,
+}
+
+impl SelfTestResult {
+    pub fn passed(&self) -> bool {
+        match self.energy {
+            Ok(energy) => (energy - self.expected).abs() < ENERGY_TOLERANCE,
+            Err(_) => false,
+        }
+    }
+}
+
+// Complex and energy of the unit tests, None for DFIRE2, SIPPER and TOBI whose potentials
+// are not distributed nor validated
+fn golden(method: &Method) -> Option<(&'static str, f64)> {
+    match method {
+        Method::DFIRE => Some(("2oob", 16.7540569503498)),
+        Method::DFIRE2 | Method::SIPPER | Method::TOBI | Method::Registered(_) => None,
+        Method::DNA => Some(("1azp", -364.88126358158974)),
+        Method::PYDOCK => Some(("1azp", -364.88126358158974)),
+    }
+}
+
+fn read_structure(contents: &str) -> Result<PDB, String> {
+    let reader = BufReader::new(Cursor::new(contents.as_bytes()));
+    match pdbtbx::open_pdb_raw(reader, Context::None, StrictnessLevel::Strict) {
+        Ok((structure, _errors)) => Ok(structure),
+        Err(errors) => Err(format!("{:?}", errors)),
+    }
+}
+
+fn scorer(method: &Method, receptor: PDB, ligand: PDB) -> Box<dyn Score> {
+    match build_scoring(
+        method,
+        ScoringInput::new(receptor, ligand, ResiduePolicy::Strict),
+    ) {
+        Ok(scoring) => scoring,
+        Err(e) => panic!("{}", e),
+    }
+}
+
+fn complex_energy(method: &Method, complex: &str) -> Result<f64, String> {
+    let (_, receptor, ligand) = STRUCTURES
+        .iter()
+        .find(|(name, _, _)| *name == complex)
+        .ok_or(format!("unknown complex {}", complex))?;
+    let receptor = read_structure(receptor)?;
+    let ligand = read_structure(ligand)?;
+    // Scoring functions panic on missing data files or unsupported atoms
+    panic::catch_unwind(panic::AssertUnwindSafe(|| {
+        let scoring = scorer(method, receptor, ligand);
+        scoring.energy(&[0.0, 0.0, 0.0], &Quaternion::default(), &[], &[])
+    }))
+    .map_err(|e| {
+        e.downcast_ref::<String>()
+            .cloned()
+            .or_else(|| e.downcast_ref::<&str>().map(|s| s.to_string()))
+            .unwrap_or_else(|| String::from("scoring function panicked"))
+    })
+}
+
+pub fn run_selftest() -> Vec<SelfTestResult> {
+    METHODS
+        .into_iter()
+        .filter_map(|method| {
+            let (complex, expected) = golden(&method)?;
+            let start = Instant::now();
+            let energy = complex_energy(&method, complex);
+            Some(SelfTestResult {
+                method,
+                complex,
+                expected,
+                energy,
+                elapsed: start.elapsed(),
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_selftest() {
+        for result in run_selftest() {
+            assert!(result.passed(), "{:?}", result);
+        }
+    }
+}
@@ -0,0 +1,650 @@
+use super::constants::{INTERFACE_CUTOFF, REJECTED_POSE_SCORE};
+use super::data::find_data_file;
+use super::decomposition::{anm_extents, pose_coordinates, push_residue, ResidueEnergies};
+use super::nmodes::Nmodes;
+use super::policy::{PolicyReport, ResidueAction, ResiduePolicy};
+use super::pose::{apply_nmodes, select_nmodes};
+use super::profile::{add_pose_time, pose_timer};
+use super::qt::Quaternion;
+use super::registry::ScoringInput;
+use super::scoring::{
+    hotspot_bias, in_interface, interface_residues, restraints_satisfaction, CellList, ClashFilter,
+    Interface, RestraintsMetric, Score, Workspace,
+};
+use super::structure::is_hydrogen;
+use log::info;
+use pdbtbx::PDB;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::error::Error;
+use std::fs;
+
+// SIPPER residue-pair statistical potential, the consensus partner of pyDock. The energy of a
+// pose is the sum of the potential of the types of every pair of receptor and ligand residues in
+// contact, residues being in contact when any of their heavy atoms are at the interface (see
+// scoring::in_interface). The potential is not distributed with lightdock-rust and energies have
+// not been checked against the SIPPER of the Python LightDock yet, so there is no golden energy in
+// the unit tests nor in selftest. It is read from the data file sipper.dat (see
+// data::find_data_file), a text file of lightdock-rust, not the format of the Python data, with
+// the residue types and a square matrix of energies, a row per type in the order of the types:
+//
+//   types ALA ARG ASN ASP CYS GLN GLU GLY HIS ILE LEU LYS MET PHE PRO SER THR TRP TYR VAL
+//   -0.12 0.08 ...
+//
+// Residues whose name is not a type are handled by the residue policy. The score of a pose is the
+// opposite of its SIPPER energy, biased by the restraints and hot-spots.
+
+pub const SIPPER_DATA_FILE: &str = "sipper.dat";
+
+#[derive(Debug, Clone, Default)]
+pub struct SIPPERPotential {
+    pub types: HashMap<String, usize>,
+    // (type, type) -> 1d
+    pub energies: Vec<f64>,
+}
+
+impl SIPPERPotential {
+    pub fn parse(contents: &str) -> Result<SIPPERPotential, Box<dyn Error>> {
+        let mut potential = SIPPERPotential::default();
+        let mut rows: Vec<Vec<f64>> = Vec::new();
+        for (index, line) in contents.lines().enumerate() {
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            if fields.is_empty() || fields[0].starts_with('#') {
+                continue;
+            }
+            if fields[0] == "types" {
+                for (i, name) in fields[1..].iter().enumerate() {
+                    potential.types.insert(name.to_string(), i);
+                }
+                continue;
+            }
+            let row: Result<Vec<f64>, _> = fields.iter().map(|v| v.parse::<f64>()).collect();
+            match row {
+                Ok(row) => rows.push(row),
+                Err(e) => return Err(format!("wrong energy in line {}: {}", index + 1, e).into()),
+            }
+        }
+        let num_types = potential.types.len();
+        if num_types == 0 {
+            return Err("residue types not found".into());
+        }
+        if rows.len() != num_types || rows.iter().any(|row| row.len() != num_types) {
+            return Err(format!("expected {} rows of {} energies", num_types, num_types).into());
+        }
+        potential.energies = rows.concat();
+        Ok(potential)
+    }
+
+    pub fn load() -> Result<SIPPERPotential, Box<dyn Error>> {
+        let path = find_data_file(SIPPER_DATA_FILE)?;
+        let potential = SIPPERPotential::parse(&fs::read_to_string(&path)?)
+            .map_err(|e| format!("{}: {}", path.display(), e))?;
+        info!(
+            "SIPPER potential of {} residue types",
+            potential.types.len()
+        );
+        Ok(potential)
+    }
+
+    pub fn residue_type(&self, residue_name: &str) -> Option<usize> {
+        self.types.get(residue_name).copied()
+    }
+
+    pub fn supports_residue(&self, residue_name: &str) -> bool {
+        self.types.contains_key(residue_name)
+    }
+
+    #[inline]
+    pub fn energy(&self, first: usize, second: usize) -> f64 {
+        self.energies[first * self.types.len() + second]
+    }
+}
+
+pub struct SIPPERDockingModel {
+    // Residue type of every atom
+    pub atoms: Vec<usize>,
+    pub coordinates: Vec<[f64; 3]>,
+    // Every atom of the model is a heavy atom
+    pub heavy_atoms: Vec<usize>,
+    pub active_restraints: HashMap<String, Vec<usize>>,
+    pub passive_restraints: HashMap<String, Vec<usize>>,
+    pub hotspots: HashMap<String, Vec<usize>>,
+    pub hotspot_weights: HashMap<String, f64>,
+    // Scoring weight of every residue, the one given or 1.0
+    pub weights: Vec<f64>,
+    pub num_anm: usize,
+    pub nmodes: Nmodes,
+    // Residue IDs and the residue of every atom, see decomposition::push_residue
+    pub residues: Vec<String>,
+    pub atom_residues: Vec<usize>,
+    // Type of every residue
+    pub residue_types: Vec<usize>,
+}
+
+impl<'a> SIPPERDockingModel {
+    pub fn new(
+        structure: &'a PDB,
+        active_restraints: &'a [String],
+        passive_restraints: &'a [String],
+        hotspots: &'a HashMap<String, f64>,
+        weights: &'a HashMap<String, f64>,
+        nmodes: &Nmodes,
+        num_anm: usize,
+        potential: &SIPPERPotential,
+        policy: ResiduePolicy,
+    ) -> SIPPERDockingModel {
+        let mut model = SIPPERDockingModel {
+            atoms: Vec::new(),
+            coordinates: Vec::new(),
+            heavy_atoms: Vec::new(),
+            active_restraints: HashMap::new(),
+            passive_restraints: HashMap::new(),
+            hotspots: HashMap::new(),
+            hotspot_weights: hotspots.clone(),
+            weights: Vec::new(),
+            nmodes: nmodes.clone(),
+            residues: Vec::new(),
+            atom_residues: Vec::new(),
+            residue_types: Vec::new(),
+            num_anm,
+        };
+
+        // Indexes in the full structure of the atoms kept in the model
+        let mut kept: Vec<usize> = Vec::new();
+        let mut structure_index: usize = 0;
+        let mut report = PolicyReport::default();
+        for chain in structure.chains() {
+            for residue in chain.residues() {
+                let res_name = match residue.name() {
+                    Some(name) => name,
+                    None => panic!("PDB Parsing Error: Residue name error"),
+                };
+                let mut res_id = format!("{}.{}.{}", chain.id(), res_name, residue.serial_number());
+                if let Some(c) = residue.insertion_code() {
+                    res_id.push_str(c);
+                }
+                let action = policy.action(
+                    "SIPPER",
+                    &res_id,
+                    res_name,
+                    potential.supports_residue(res_name),
+                    |name| potential.supports_residue(name),
+                    &mut report,
+                );
+                if action == ResidueAction::Drop {
+                    structure_index += residue.atom_count();
+                    continue;
+                }
+
+                for atom in residue.atoms() {
+                    structure_index += 1;
+                    let (scored_name, _) = action.names(res_name, atom.name().trim());
+                    if is_hydrogen(residue, atom) {
+                        continue;
+                    }
+                    let residue_type = match potential.residue_type(scored_name) {
+                        Some(residue_type) => residue_type,
+                        None => continue,
+                    };
+                    let atom_index = model.atoms.len();
+                    if active_restraints.contains(&res_id) {
+                        model
+                            .active_restraints
+                            .entry(res_id.to_string())
+                            .or_default()
+                            .push(atom_index);
+                    }
+                    if passive_restraints.contains(&res_id) {
+                        model
+                            .passive_restraints
+                            .entry(res_id.to_string())
+                            .or_default()
+                            .push(atom_index);
+                    }
+                    if hotspots.contains_key(&res_id) {
+                        model
+                            .hotspots
+                            .entry(res_id.to_string())
+                            .or_default()
+                            .push(atom_index);
+                    }
+
+                    model.heavy_atoms.push(atom_index);
+                    model.atoms.push(residue_type);
+                    model.coordinates.push([atom.x(), atom.y(), atom.z()]);
+                    let num_residues = model.residues.len();
+                    push_residue(&mut model.residues, &mut model.atom_residues, &res_id);
+                    if model.residues.len() > num_residues {
+                        model.residue_types.push(residue_type);
+                        model
+                            .weights
+                            .push(weights.get(&res_id).copied().unwrap_or(1.0));
+                    }
+                    kept.push(structure_index - 1);
+                }
+            }
+        }
+        report.log("SIPPER");
+
+        // Normal modes are stored as (num_anm, num_atoms, 3), keep only the selected atoms
+        if kept.len() < structure_index && num_anm > 0 && !nmodes.is_empty() {
+            model.nmodes = select_nmodes(nmodes, num_anm, structure_index, &kept).into();
+        }
+        model
+    }
+}
+
+pub struct SIPPER {
+    pub potential: SIPPERPotential,
+    pub receptor: SIPPERDockingModel,
+    pub ligand: SIPPERDockingModel,
+    pub use_anm: bool,
+    pub clash_filter: Option<ClashFilter>,
+    pub restraints_metric: RestraintsMetric,
+    pub workspace: RefCell<Workspace>,
+    // Receptor and ligand residue pairs in contact in the evaluated pose
+    contacts: RefCell<Vec<(usize, usize)>>,
+}
+
+impl<'a> SIPPER {
+    pub fn new(input: ScoringInput) -> Result<Box<dyn Score + 'a>, Box<dyn Error>> {
+        let ScoringInput {
+            receptor,
+            rec_active_restraints,
+            rec_passive_restraints,
+            rec_hotspots,
+            rec_weights,
+            rec_nmodes,
+            rec_num_anm,
+            ligand,
+            lig_active_restraints,
+            lig_passive_restraints,
+            lig_hotspots,
+            lig_weights,
+            lig_nmodes,
+            lig_num_anm,
+            use_anm,
+            clash_filter,
+            restraints_metric,
+            policy,
+        } = input;
+        let potential = SIPPERPotential::load()
+            .map_err(|e| format!("Unable to load SIPPER potential: {}", e))?;
+        let receptor = SIPPERDockingModel::new(
+            &receptor,
+            &rec_active_restraints,
+            &rec_passive_restraints,
+            &rec_hotspots,
+            &rec_weights,
+            &rec_nmodes,
+            rec_num_anm,
+            &potential,
+            policy,
+        );
+        let ligand = SIPPERDockingModel::new(
+            &ligand,
+            &lig_active_restraints,
+            &lig_passive_restraints,
+            &lig_hotspots,
+            &lig_weights,
+            &lig_nmodes,
+            lig_num_anm,
+            &potential,
+            policy,
+        );
+        Ok(Box::new(SIPPER::from_models(
+            potential,
+            receptor,
+            ligand,
+            use_anm,
+            clash_filter,
+            restraints_metric,
+        )))
+    }
+
+    pub fn from_models(
+        potential: SIPPERPotential,
+        receptor: SIPPERDockingModel,
+        ligand: SIPPERDockingModel,
+        use_anm: bool,
+        clash_filter: Option<ClashFilter>,
+        restraints_metric: RestraintsMetric,
+    ) -> SIPPER {
+        let mut workspace = Workspace::new(&receptor.coordinates, &ligand.coordinates);
+        workspace.cell_list = Some(CellList::new(&receptor.coordinates, INTERFACE_CUTOFF));
+        if let Some(filter) = &clash_filter {
+            workspace.clash_grid = filter.grid(&receptor.coordinates, &receptor.heavy_atoms);
+        }
+        SIPPER {
+            potential,
+            receptor,
+            ligand,
+            use_anm,
+            clash_filter,
+            restraints_metric,
+            workspace: RefCell::new(workspace),
+            contacts: RefCell::new(Vec::new()),
+        }
+    }
+
+    // Energy of a pair of receptor and ligand residues in contact
+    fn pair_energy(&self, receptor_residue: usize, ligand_residue: usize) -> f64 {
+        self.potential.energy(
+            self.receptor.residue_types[receptor_residue],
+            self.ligand.residue_types[ligand_residue],
+        ) * self.receptor.weights[receptor_residue]
+            * self.ligand.weights[ligand_residue]
+    }
+
+    // Score of the pose biased by its satisfied restraints and hot-spots
+    fn biased_score(
+        &self,
+        score: f64,
+        receptor_coordinates: &[[f64; 3]],
+        ligand_coordinates: &[[f64; 3]],
+        interface_receptor: &[usize],
+        interface_ligand: &[usize],
+    ) -> f64 {
+        let perc_receptor_restraints: f64 = self.restraints_metric.satisfaction(
+            interface_receptor,
+            &self.receptor.active_restraints,
+            receptor_coordinates,
+            ligand_coordinates,
+        );
+        let perc_ligand_restraints: f64 = self.restraints_metric.satisfaction(
+            interface_ligand,
+            &self.ligand.active_restraints,
+            ligand_coordinates,
+            receptor_coordinates,
+        );
+        let receptor_hotspots: f64 = hotspot_bias(
+            interface_receptor,
+            &self.receptor.hotspots,
+            &self.receptor.hotspot_weights,
+        );
+        let ligand_hotspots: f64 = hotspot_bias(
+            interface_ligand,
+            &self.ligand.hotspots,
+            &self.ligand.hotspot_weights,
+        );
+        score
+            + perc_receptor_restraints * score
+            + perc_ligand_restraints * score
+            + receptor_hotspots * score
+            + ligand_hotspots * score
+    }
+}
+
+impl Score for SIPPER {
+    fn energy(
+        &self,
+        translation: &[f64],
+        rotation: &Quaternion,
+        rec_nmodes: &[f64],
+        lig_nmodes: &[f64],
+    ) -> f64 {
+        let rec_extents = anm_extents(self.use_anm, self.receptor.num_anm, rec_nmodes);
+        let lig_extents = anm_extents(self.use_anm, self.ligand.num_anm, lig_nmodes);
+        // Start from the original coordinates in the preallocated buffers
+        let mut workspace = self.workspace.borrow_mut();
+        workspace.reset(
+            &self.receptor.coordinates,
+            &self.ligand.coordinates,
+            !rec_extents.is_empty(),
+        );
+        let Workspace {
+            receptor_coordinates,
+            ligand_coordinates,
+            interface_receptor,
+            interface_ligand,
+            cell_list,
+            clash_grid,
+        } = &mut *workspace;
+
+        let timer = pose_timer();
+        for coordinate in ligand_coordinates.iter_mut() {
+            let rotated_coordinate = rotation.rotate_point(coordinate);
+            for k in 0..3 {
+                coordinate[k] = rotated_coordinate[k] + translation[k];
+            }
+        }
+        apply_nmodes(ligand_coordinates, &self.ligand.nmodes, lig_extents);
+        apply_nmodes(receptor_coordinates, &self.receptor.nmodes, rec_extents);
+        add_pose_time(timer);
+
+        // Discard hopeless poses before the full evaluation
+        if let Some(filter) = &self.clash_filter {
+            // The grid is only hashed again if the receptor moved
+            if !rec_extents.is_empty() {
+                filter.update_grid(clash_grid, receptor_coordinates, &self.receptor.heavy_atoms);
+            }
+            if filter.is_clashing(
+                clash_grid,
+                receptor_coordinates,
+                ligand_coordinates,
+                &self.ligand.heavy_atoms,
+            ) {
+                return REJECTED_POSE_SCORE;
+            }
+        }
+
+        // Ligand atoms around the cells of the receptor, hashed again if its atoms were moved by
+        // the normal modes
+        let cell_list = cell_list.as_mut().expect("SIPPER cell list not built");
+        if !rec_extents.is_empty() {
+            cell_list.update(receptor_coordinates);
+        }
+        cell_list.search(ligand_coordinates);
+
+        // Every residue pair in contact counts once, however many of their atoms are
+        let mut contacts = self.contacts.borrow_mut();
+        contacts.clear();
+        for (i, ra) in receptor_coordinates.iter().enumerate() {
+            let (neighbors, distances) = cell_list.distances(i, ra);
+            for (&j, &distance2) in neighbors.iter().zip(distances.iter()) {
+                if in_interface(distance2) {
+                    interface_receptor[i] = 1;
+                    interface_ligand[j] = 1;
+                    contacts.push((self.receptor.atom_residues[i], self.ligand.atom_residues[j]));
+                }
+            }
+        }
+        contacts.sort_unstable();
+        contacts.dedup();
+        let energy: f64 = contacts
+            .iter()
+            .map(|&(receptor_residue, ligand_residue)| {
+                self.pair_energy(receptor_residue, ligand_residue)
+            })
+            .sum();
+
+        self.biased_score(
+            -energy,
+            receptor_coordinates,
+            ligand_coordinates,
+            interface_receptor,
+            interface_ligand,
+        )
+    }
+
+    fn energy_with_interface(
+        &self,
+        translation: &[f64],
+        rotation: &Quaternion,
+        rec_nmodes: &[f64],
+        lig_nmodes: &[f64],
+    ) -> (f64, Option<Interface>) {
+        let energy = self.energy(translation, rotation, rec_nmodes, lig_nmodes);
+        // The evaluation leaves the interface of the pose in the workspace
+        let workspace = self.workspace.borrow();
+        let interface = Interface {
+            receptor: interface_residues(
+                &workspace.interface_receptor,
+                &self.receptor.atom_residues,
+                &self.receptor.residues,
+            ),
+            ligand: interface_residues(
+                &workspace.interface_ligand,
+                &self.ligand.atom_residues,
+                &self.ligand.residues,
+            ),
+        };
+        (energy, Some(interface))
+    }
+
+    fn restraints_satisfaction(
+        &self,
+        translation: &[f64],
+        rotation: &Quaternion,
+        rec_nmodes: &[f64],
+        lig_nmodes: &[f64],
+    ) -> Option<f64> {
+        if self.receptor.active_restraints.is_empty() && self.ligand.active_restraints.is_empty() {
+            return None;
+        }
+        // The evaluation leaves the interface of the pose in the workspace
+        self.energy(translation, rotation, rec_nmodes, lig_nmodes);
+        let workspace = self.workspace.borrow();
+        restraints_satisfaction(
+            &workspace.interface_receptor,
+            &self.receptor.active_restraints,
+            &workspace.interface_ligand,
+            &self.ligand.active_restraints,
+        )
+    }
+
+    fn residue_energies(
+        &self,
+        translation: &[f64],
+        rotation: &Quaternion,
+        rec_nmodes: &[f64],
+        lig_nmodes: &[f64],
+    ) -> Option<ResidueEnergies> {
+        let (receptor_coordinates, ligand_coordinates) = pose_coordinates(
+            &self.receptor.coordinates,
+            &self.receptor.nmodes,
+            anm_extents(self.use_anm, self.receptor.num_anm, rec_nmodes),
+            &self.ligand.coordinates,
+            &self.ligand.nmodes,
+            anm_extents(self.use_anm, self.ligand.num_anm, lig_nmodes),
+            translation,
+            rotation,
+        );
+        let mut contacts: Vec<(usize, usize)> = Vec::new();
+        for (i, ra) in receptor_coordinates.iter().enumerate() {
+            for (j, la) in ligand_coordinates.iter().enumerate() {
+                let distance2 = (ra[0] - la[0]) * (ra[0] - la[0])
+                    + (ra[1] - la[1]) * (ra[1] - la[1])
+                    + (ra[2] - la[2]) * (ra[2] - la[2]);
+                if in_interface(distance2) {
+                    contacts.push((self.receptor.atom_residues[i], self.ligand.atom_residues[j]));
+                }
+            }
+        }
+        contacts.sort_unstable();
+        contacts.dedup();
+        let mut energies =
+            ResidueEnergies::new(&self.receptor.residues, &self.ligand.residues, 0.0);
+        for (receptor_residue, ligand_residue) in contacts {
+            energies.add(
+                receptor_residue,
+                ligand_residue,
+                -self.pair_energy(receptor_residue, ligand_residue),
+            );
+        }
+        Some(energies)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::structure::parse_structure;
+
+    #[test]
+    fn test_sipper() {
+        let potential = SIPPERPotential::parse(
+            "\
+# Test potential
+types GLY ALA
+-1.0 -2.0
+-2.0 -4.0
+",
+        )
+        .unwrap();
+        assert!(potential.supports_residue("ALA"));
+        assert_eq!(potential.energy(0, 1), -2.0);
+        let error = SIPPERPotential::parse("types GLY ALA\n1.0 2.0\n").unwrap_err();
+        assert!(error.to_string().contains("expected 2 rows of 2 energies"));
+
+        let receptor = parse_structure(
+            "\
+ATOM      1  CA  GLY A   1       0.000   0.000   0.000  1.00  0.00           C\n\
+ATOM      2  HA2 GLY A   1       0.000   0.000   1.000  1.00  0.00           H\n\
+ATOM      3  CA  ALA A   2      10.000   0.000   0.000  1.00  0.00           C\n",
+            1,
+        )
+        .unwrap()
+        .unwrap();
+        let ligand = parse_structure(
+            "\
+ATOM      1  N   ALA B   1       0.000   0.000   3.000  1.00  0.00           N\n\
+ATOM      2  CA  ALA B   1       0.000   0.000   3.500  1.00  0.00           C\n\
+ATOM      3  CB  ALA B   1      10.000   0.000   3.000  1.00  0.00           C\n",
+            1,
+        )
+        .unwrap()
+        .unwrap();
+        let no_weights: HashMap<String, f64> = HashMap::new();
+        let receptor = SIPPERDockingModel::new(
+            &receptor,
+            &[],
+            &[],
+            &no_weights,
+            &no_weights,
+            &Nmodes::default(),
+            0,
+            &potential,
+            ResiduePolicy::Strict,
+        );
+        let ligand = SIPPERDockingModel::new(
+            &ligand,
+            &[String::from("B.ALA.1")],
+            &[],
+            &no_weights,
+            &HashMap::from([(String::from("B.ALA.1"), 0.5)]),
+            &vec![0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 5.0].into(),
+            1,
+            &potential,
+            ResiduePolicy::Strict,
+        );
+        // Hydrogens are left out
+        assert_eq!(receptor.coordinates.len(), 2);
+        assert_eq!(receptor.residue_types, vec![0, 1]);
+        assert_eq!(ligand.weights, vec![0.5]);
+
+        let scoring = SIPPER::from_models(
+            potential,
+            receptor,
+            ligand,
+            true,
+            None,
+            RestraintsMetric::default(),
+        );
+        let rotation = Quaternion::default();
+        // Both receptor residues touch the ligand one, the GLY one with two atoms counting once
+        let energy = scoring.energy(&[0.0, 0.0, 0.0], &rotation, &[], &[0.0]);
+        assert_eq!(energy, (1.0 + 2.0) * 2.0);
+        assert_eq!(
+            scoring.restraints_satisfaction(&[0.0, 0.0, 0.0], &rotation, &[], &[0.0]),
+            Some(1.0)
+        );
+        // CB moved by the normal mode away from the ALA of the receptor
+        let energy = scoring.energy(&[0.0, 0.0, 0.0], &rotation, &[], &[1.0]);
+        assert_eq!(energy, 1.0 * 2.0);
+        let energies = scoring
+            .residue_energies(&[0.0, 0.0, 0.0], &rotation, &[], &[1.0])
+            .unwrap();
+        assert_eq!(energies.total(), 1.0);
+    }
+}
@@ -0,0 +1,310 @@
+use super::qt::Quaternion;
+use std::f64::consts::PI;
+
+// Desolvation energy of a pose from atomic solvation parameters, the term of pyDock and of the
+// optimal docking area (ODA) analysis: the solvent accessible surface of every heavy atom buried
+// by the other partner times the solvation parameter of its class (Eisenberg and McLachlan,
+// 1986). The surface of an atom is sampled by SURFACE_POINTS points on its sphere enlarged by the
+// probe radius (Shrake and Rupley). The points exposed in the unbound partner are found once, so
+// a pose only checks them against the atoms of the other partner close enough to cover them and
+// the energy is the change of solvation on binding. Normal modes move the atoms but not their
+// exposed points, the unbound surface is the one of the starting structure.
+
+// Points per atom, at most the 128 bits of a mask
+pub const SURFACE_POINTS: usize = 96;
+pub const PROBE_RADIUS: f64 = 1.4;
+
+lazy_static! {
+    // Unit vectors evenly spread on the sphere along a golden spiral
+    static ref SPHERE_POINTS: Vec<[f64; 3]> = {
+        let increment = PI * (3.0 - 5.0_f64.sqrt());
+        (0..SURFACE_POINTS)
+            .map(|k| {
+                let z = 1.0 - (2.0 * k as f64 + 1.0) / SURFACE_POINTS as f64;
+                let r = (1.0 - z * z).sqrt();
+                let phi = k as f64 * increment;
+                [r * phi.cos(), r * phi.sin(), z]
+            })
+            .collect()
+    };
+}
+
+// Solvation classes of the heavy atoms of Eisenberg and McLachlan
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SolvationClass {
+    Carbon,
+    // Uncharged nitrogen and oxygen
+    Polar,
+    // Carboxylate and phosphate oxygens
+    Anionic,
+    // Lysine and arginine side chain nitrogens
+    Cationic,
+    Sulfur,
+    Other,
+}
+
+impl SolvationClass {
+    // Class of an atom from its residue and atom names, None for hydrogens and membrane beads
+    pub fn of(residue_name: &str, atom_name: &str) -> Option<SolvationClass> {
+        if residue_name == "MMB" {
+            return None;
+        }
+        let element = atom_name
+            .trim()
+            .chars()
+            .find(|c| !c.is_ascii_digit())
+            .unwrap_or('H');
+        let class = match (residue_name, atom_name, element) {
+            (_, _, 'H') => return None,
+            ("ASP", "OD1" | "OD2", _) | ("GLU", "OE1" | "OE2", _) | (_, "OXT", _) => {
+                SolvationClass::Anionic
+            }
+            (_, "O1P" | "O2P" | "OP1" | "OP2", _) => SolvationClass::Anionic,
+            ("LYS", "NZ", _) | ("ARG", "NE" | "NH1" | "NH2", _) => SolvationClass::Cationic,
+            (_, _, 'C') => SolvationClass::Carbon,
+            (_, _, 'N' | 'O') => SolvationClass::Polar,
+            (_, _, 'S') => SolvationClass::Sulfur,
+            _ => SolvationClass::Other,
+        };
+        Some(class)
+    }
+
+    // Atomic solvation parameter in kcal/mol/A^2
+    pub fn parameter(&self) -> f64 {
+        match self {
+            SolvationClass::Carbon => 0.016,
+            SolvationClass::Polar => -0.006,
+            SolvationClass::Anionic => -0.024,
+            SolvationClass::Cationic => -0.050,
+            SolvationClass::Sulfur => 0.021,
+            SolvationClass::Other => 0.0,
+        }
+    }
+}
+
+// Radius of an atom in Angstroms by its element, the first letter of its name
+pub fn solvation_radius(atom_name: &str) -> f64 {
+    match atom_name.trim().chars().find(|c| !c.is_ascii_digit()) {
+        Some('C') => 1.9,
+        Some('N') => 1.7,
+        Some('O') => 1.4,
+        _ => 1.8,
+    }
+}
+
+// Heavy atoms of a partner with their surface points exposed in the unbound structure
+#[derive(Debug, Clone, Default)]
+pub struct SolvationModel {
+    // Index of the atom in the scoring model
+    pub atoms: Vec<usize>,
+    // Radius of the atom plus the probe
+    pub radii: Vec<f64>,
+    // Solvation parameter times the weight of the atom and the area of one of its points
+    pub parameters: Vec<f64>,
+    // Exposed points, a bit per point of SPHERE_POINTS
+    pub exposed: Vec<u128>,
+}
+
+impl SolvationModel {
+    // Atoms are given by their index in the coordinates, radius, class and scoring weight
+    pub fn new(
+        coordinates: &[[f64; 3]],
+        atoms: &[(usize, f64, SolvationClass, f64)],
+    ) -> SolvationModel {
+        let mut model = SolvationModel::default();
+        for &(atom, radius, class, weight) in atoms.iter() {
+            let radius = radius + PROBE_RADIUS;
+            let point_area = 4.0 * PI * radius * radius / SURFACE_POINTS as f64;
+            model.atoms.push(atom);
+            model.radii.push(radius);
+            model
+                .parameters
+                .push(class.parameter() * weight * point_area);
+        }
+        for i in 0..model.atoms.len() {
+            let neighbors: Vec<usize> = (0..model.atoms.len())
+                .filter(|&j| {
+                    j != i
+                        && distance2(&coordinates[model.atoms[i]], &coordinates[model.atoms[j]])
+                            < (model.radii[i] + model.radii[j]).powi(2)
+                })
+                .collect();
+            let exposed = covered_points(
+                &coordinates[model.atoms[i]],
+                model.radii[i],
+                &SPHERE_POINTS,
+                u128::MAX >> (128 - SURFACE_POINTS),
+                neighbors
+                    .iter()
+                    .map(|&j| (&coordinates[model.atoms[j]], model.radii[j])),
+            );
+            model
+                .exposed
+                .push(!exposed & (u128::MAX >> (128 - SURFACE_POINTS)));
+        }
+        model
+    }
+
+    // Solvation energy of the surface of the atoms buried by the given atoms of the other
+    // partner, the points of the atoms rotated as given
+    fn buried_energy(
+        &self,
+        coordinates: &[[f64; 3]],
+        points: &[[f64; 3]],
+        other: &SolvationModel,
+        other_coordinates: &[[f64; 3]],
+    ) -> f64 {
+        let mut energy = 0.0;
+        for i in 0..self.atoms.len() {
+            if self.parameters[i] == 0.0 || self.exposed[i] == 0 {
+                continue;
+            }
+            let center = &coordinates[self.atoms[i]];
+            let covering = (0..other.atoms.len())
+                .map(|j| (&other_coordinates[other.atoms[j]], other.radii[j]))
+                .filter(|(other_center, other_radius)| {
+                    distance2(center, other_center) < (self.radii[i] + other_radius).powi(2)
+                });
+            let buried = covered_points(center, self.radii[i], points, self.exposed[i], covering);
+            energy -= self.parameters[i] * buried.count_ones() as f64;
+        }
+        energy
+    }
+}
+
+// Desolvation of the receptor and ligand surfaces buried in a pose
+#[derive(Debug, Clone, Default)]
+pub struct Desolvation {
+    pub receptor: SolvationModel,
+    pub ligand: SolvationModel,
+}
+
+impl Desolvation {
+    pub fn new(receptor: SolvationModel, ligand: SolvationModel) -> Desolvation {
+        Desolvation { receptor, ligand }
+    }
+
+    // Energy of the pose, negative if it buries more hydrophobic than polar surface. The
+    // exposed points of the ligand turn with it.
+    pub fn energy(
+        &self,
+        receptor_coordinates: &[[f64; 3]],
+        ligand_coordinates: &[[f64; 3]],
+        rotation: &Quaternion,
+    ) -> f64 {
+        let ligand_points: Vec<[f64; 3]> = SPHERE_POINTS
+            .iter()
+            .map(|point| rotation.rotate_point(point))
+            .collect();
+        self.receptor.buried_energy(
+            receptor_coordinates,
+            &SPHERE_POINTS,
+            &self.ligand,
+            ligand_coordinates,
+        ) + self.ligand.buried_energy(
+            ligand_coordinates,
+            &ligand_points,
+            &self.receptor,
+            receptor_coordinates,
+        )
+    }
+}
+
+fn distance2(a: &[f64; 3], b: &[f64; 3]) -> f64 {
+    (a[0] - b[0]) * (a[0] - b[0]) + (a[1] - b[1]) * (a[1] - b[1]) + (a[2] - b[2]) * (a[2] - b[2])
+}
+
+// Points of the mask on the sphere of the given center and radius inside any of the spheres
+fn covered_points<'a>(
+    center: &[f64; 3],
+    radius: f64,
+    points: &[[f64; 3]],
+    mask: u128,
+    spheres: impl Iterator<Item = (&'a [f64; 3], f64)>,
+) -> u128 {
+    let mut covered: u128 = 0;
+    for (other_center, other_radius) in spheres {
+        let mut remaining = mask & !covered;
+        while remaining != 0 {
+            let k = remaining.trailing_zeros() as usize;
+            remaining &= remaining - 1;
+            let point = [
+                center[0] + radius * points[k][0],
+                center[1] + radius * points[k][1],
+                center[2] + radius * points[k][2],
+            ];
+            if distance2(&point, other_center) < other_radius * other_radius {
+                covered |= 1 << k;
+            }
+        }
+        if covered == mask {
+            break;
+        }
+    }
+    covered
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_desolvation() {
+        assert_eq!(
+            SolvationClass::of("ASP", "OD1"),
+            Some(SolvationClass::Anionic)
+        );
+        assert_eq!(
+            SolvationClass::of("ASP", "CG"),
+            Some(SolvationClass::Carbon)
+        );
+        assert_eq!(
+            SolvationClass::of("LYS", "NZ"),
+            Some(SolvationClass::Cationic)
+        );
+        assert_eq!(SolvationClass::of("LYS", "1HZ"), None);
+        assert_eq!(
+            SolvationClass::of("MET", "SD"),
+            Some(SolvationClass::Sulfur)
+        );
+
+        // A lone atom is fully exposed, half of the surface of two overlapping atoms is not
+        let coordinates = vec![[0.0, 0.0, 0.0], [0.0, 0.0, 10.0]];
+        let lone = SolvationModel::new(&coordinates, &[(0, 1.9, SolvationClass::Carbon, 1.0)]);
+        assert_eq!(lone.exposed[0].count_ones() as usize, SURFACE_POINTS);
+        let coordinates = vec![[0.0, 0.0, 0.0], [0.0, 0.0, 0.1]];
+        let pair = SolvationModel::new(
+            &coordinates,
+            &[
+                (0, 1.9, SolvationClass::Carbon, 1.0),
+                (1, 1.9, SolvationClass::Carbon, 1.0),
+            ],
+        );
+        let exposed = pair.exposed[0].count_ones() as usize;
+        assert!(exposed > SURFACE_POINTS / 2 - 5 && exposed < SURFACE_POINTS / 2 + 5);
+
+        // Burying carbons is favorable, burying charged atoms is not
+        let receptor =
+            SolvationModel::new(&[[0.0, 0.0, 0.0]], &[(0, 1.9, SolvationClass::Carbon, 1.0)]);
+        let carbon =
+            SolvationModel::new(&[[0.0, 0.0, 0.0]], &[(0, 1.9, SolvationClass::Carbon, 1.0)]);
+        let cation = SolvationModel::new(
+            &[[0.0, 0.0, 0.0]],
+            &[(0, 1.7, SolvationClass::Cationic, 1.0)],
+        );
+        let rotation = Quaternion::default();
+        let far = Desolvation::new(receptor.clone(), carbon.clone());
+        assert_eq!(
+            far.energy(&[[0.0, 0.0, 0.0]], &[[0.0, 0.0, 20.0]], &rotation),
+            0.0
+        );
+        let contact = far.energy(&[[0.0, 0.0, 0.0]], &[[0.0, 0.0, 4.0]], &rotation);
+        assert!(contact < 0.0);
+        let charged = Desolvation::new(receptor, cation);
+        assert!(charged.energy(&[[0.0, 0.0, 0.0]], &[[0.0, 0.0, 4.0]], &rotation) > contact);
+        // The same for any rotation of the ligand atom on itself
+        let turned = Quaternion::from_rotation_vector(&[0.3, -1.2, 0.7]);
+        let turned_contact = far.energy(&[[0.0, 0.0, 0.0]], &[[0.0, 0.0, 4.0]], &turned);
+        assert!((turned_contact - contact).abs() < 0.1 * contact.abs());
+    }
+}
@@ -0,0 +1,701 @@
+use pdbtbx::{Atom, Chain, Conformer, Model, Residue, StrictnessLevel, PDB};
+use std::collections::HashMap;
+use std::error::Error;
+use std::fs;
+use std::io::{self, Write};
+use std::thread;
+
+// Reader of the receptor and ligand structures for the docking models. Only the ATOM, HETATM,
+// TER and MODEL records are read, the rest of the file (headers, remarks, ANISOU, CONECT...) is
+// skipped. Chains are parsed and then assembled in parallel, giving the same chains, residues and
+// atoms as pdbtbx would, which is still used for multi-model and non-PDB files.
+
+struct AtomRecord<'a> {
+    hetero: bool,
+    serial_number: usize,
+    name: &'a str,
+    alt_loc: Option<&'a str>,
+    residue_name: &'a str,
+    chain_id: &'a str,
+    residue_serial_number: isize,
+    insertion_code: Option<&'a str>,
+    x: f64,
+    y: f64,
+    z: f64,
+    occupancy: f64,
+    b_factor: f64,
+    element: &'a str,
+}
+
+// Fixed column field of a line, None if the line is too short
+fn field(line: &str, start: usize, end: usize) -> Option<&str> {
+    line.get(start..end.min(line.len()))
+        .filter(|_| line.len() > start)
+        .map(|s| s.trim())
+}
+
+fn optional_char(line: &str, position: usize) -> Option<&str> {
+    line.get(position..position + 1).filter(|c| *c != " ")
+}
+
+fn parse_atom(line_number: usize, line: &str) -> Result<AtomRecord<'_>, String> {
+    let number = |start: usize, end: usize, name: &str| -> Result<f64, String> {
+        match line.get(start..end).map(|s| s.trim().parse::<f64>()) {
+            Some(Ok(value)) => Ok(value),
+            _ => Err(format!("line {}: wrong {} field", line_number, name)),
+        }
+    };
+    let serial_number = match field(line, 6, 11).map(|s| s.parse::<usize>()) {
+        Some(Ok(serial_number)) => serial_number,
+        _ => return Err(format!("line {}: wrong atom serial number", line_number)),
+    };
+    let residue_serial_number = match field(line, 22, 26).map(|s| s.parse::<isize>()) {
+        Some(Ok(serial_number)) => serial_number,
+        _ => return Err(format!("line {}: wrong residue serial number", line_number)),
+    };
+    Ok(AtomRecord {
+        hetero: line.starts_with("HETATM"),
+        serial_number,
+        name: field(line, 12, 16).unwrap_or(""),
+        alt_loc: optional_char(line, 16),
+        residue_name: field(line, 17, 20).unwrap_or(""),
+        chain_id: line.get(21..22).unwrap_or(" "),
+        residue_serial_number,
+        insertion_code: optional_char(line, 26),
+        x: number(30, 38, "x coordinate")?,
+        y: number(38, 46, "y coordinate")?,
+        z: number(46, 54, "z coordinate")?,
+        occupancy: number(54, 60, "occupancy").unwrap_or(1.0),
+        b_factor: number(60, 66, "B-factor").unwrap_or(0.0),
+        element: field(line, 76, 78).unwrap_or(""),
+    })
+}
+
+// Runs the jobs on up to num_threads threads, keeping their order
+fn parallel_map<T: Sync, R: Send>(
+    jobs: &[T],
+    num_threads: usize,
+    f: impl Fn(&T) -> R + Sync,
+) -> Vec<R> {
+    if jobs.is_empty() {
+        return Vec::new();
+    }
+    let chunk_size = jobs.len().div_ceil(num_threads.max(1));
+    thread::scope(|scope| {
+        let handles: Vec<_> = jobs
+            .chunks(chunk_size)
+            .map(|chunk| scope.spawn(|| chunk.iter().map(&f).collect::<Vec<R>>()))
+            .collect();
+        handles
+            .into_iter()
+            .flat_map(|handle| handle.join().unwrap())
+            .collect()
+    })
+}
+
+fn build_chain(
+    chain_id: &str,
+    atoms: &[AtomRecord],
+    residue_serial_numbers: &[isize],
+) -> Result<Chain, String> {
+    let mut residues: Vec<Residue> = Vec::new();
+    let mut index: HashMap<(isize, Option<&str>), usize> = HashMap::new();
+    for (record, &residue_serial_number) in atoms.iter().zip(residue_serial_numbers) {
+        let atom = Atom::new(
+            record.hetero,
+            record.serial_number,
+            record.name,
+            record.x,
+            record.y,
+            record.z,
+            record.occupancy,
+            record.b_factor,
+            record.element,
+            0,
+        )
+        .ok_or(format!(
+            "invalid atom {} {}",
+            record.serial_number, record.name
+        ))?;
+        let conformer_id = (record.residue_name, record.alt_loc);
+        let key = (residue_serial_number, record.insertion_code);
+        match index.get(&key) {
+            Some(&i) => residues[i].add_atom(atom, conformer_id),
+            None => {
+                let conformer = Conformer::new(record.residue_name, record.alt_loc, Some(atom))
+                    .ok_or(format!("invalid residue name {}", record.residue_name))?;
+                let residue = Residue::new(
+                    residue_serial_number,
+                    record.insertion_code,
+                    Some(conformer),
+                )
+                .ok_or(format!("invalid residue {}", residue_serial_number))?;
+                index.insert(key, residues.len());
+                residues.push(residue);
+            }
+        }
+    }
+    Chain::from_iter(chain_id, residues.into_iter()).ok_or(format!("invalid chain id {}", chain_id))
+}
+
+// Parses the coordinate records of a single model PDB file, None if it contains several models
+pub fn parse_structure(contents: &str, num_threads: usize) -> Result<Option<PDB>, String> {
+    // Consecutive atom lines of the same chain, split at TER records
+    let mut segments: Vec<Vec<(usize, &str)>> = Vec::new();
+    let mut model_number = 0;
+    for (i, line) in contents.lines().enumerate() {
+        if line.starts_with("ATOM  ") || line.starts_with("HETATM") {
+            let chain = line.get(21..22);
+            let same_chain = segments
+                .last()
+                .and_then(|segment| segment.last())
+                .map(|(_, last)| last.get(21..22) == chain)
+                .unwrap_or(false);
+            if !same_chain {
+                segments.push(Vec::new());
+            }
+            segments.last_mut().unwrap().push((i + 1, line));
+        } else if line.starts_with("TER") {
+            // Blank chain ids take the next letter after a TER record
+            segments.push(Vec::new());
+        } else if line.starts_with("MODEL") {
+            if model_number > 0 || segments.iter().any(|segment| !segment.is_empty()) {
+                return Ok(None);
+            }
+            model_number = match field(line, 10, 14).map(|s| s.parse::<usize>()) {
+                Some(Ok(number)) => number,
+                _ => 1,
+            };
+        }
+    }
+
+    let parsed: Vec<Result<Vec<AtomRecord>, String>> =
+        parallel_map(&segments, num_threads, |segment| {
+            segment
+                .iter()
+                .map(|&(line_number, line)| parse_atom(line_number, line))
+                .collect()
+        });
+
+    // Chain ids, in order of appearance, and wrapped around serial numbers are sequential
+    let mut chain_iter = ('A'..='Z').cycle();
+    let mut blank_chain_id = chain_iter.next().unwrap().to_string();
+    let mut chain_index: HashMap<String, usize> = HashMap::new();
+    let mut chains: Vec<(String, Vec<AtomRecord>, Vec<isize>)> = Vec::new();
+    let mut last_atom_serial_number = 0;
+    let mut atom_serial_addition = 0;
+    let mut last_residue_serial_number = 0;
+    let mut residue_serial_addition = 0;
+    for (i, records) in parsed.into_iter().enumerate() {
+        let records = records?;
+        if segments[i].is_empty() {
+            blank_chain_id = chain_iter.next().unwrap().to_string();
+        }
+        for mut record in records {
+            if record.serial_number == 0 && last_atom_serial_number == 99_999 {
+                atom_serial_addition += 100_000;
+            }
+            if record.residue_serial_number == 0 && last_residue_serial_number == 9999 {
+                residue_serial_addition += 10000;
+            }
+            last_atom_serial_number = record.serial_number;
+            last_residue_serial_number = record.residue_serial_number;
+            record.serial_number += atom_serial_addition;
+
+            let chain_id = if record.chain_id.trim().is_empty() {
+                blank_chain_id.clone()
+            } else {
+                record.chain_id.to_string()
+            };
+            let index = *chain_index.entry(chain_id.clone()).or_insert_with(|| {
+                chains.push((chain_id, Vec::new(), Vec::new()));
+                chains.len() - 1
+            });
+            chains[index]
+                .2
+                .push(record.residue_serial_number + residue_serial_addition);
+            chains[index].1.push(record);
+        }
+    }
+
+    let chains: Vec<Result<Chain, String>> = parallel_map(
+        &chains,
+        num_threads,
+        |(chain_id, atoms, residue_serial_numbers)| {
+            build_chain(chain_id, atoms, residue_serial_numbers)
+        },
+    );
+    let chains: Vec<Chain> = chains.into_iter().collect::<Result<_, _>>()?;
+
+    let mut structure = PDB::new();
+    if !chains.is_empty() {
+        structure.add_model(Model::from_iter(model_number, chains.into_iter()));
+    }
+    Ok(Some(structure))
+}
+
+pub fn read_structure(path: &str) -> Result<PDB, Box<dyn Error>> {
+    if path.to_lowercase().ends_with(".pdb") {
+        let contents = fs::read_to_string(path)?;
+        let num_threads = thread::available_parallelism()
+            .map(|n| n.get())
+            .unwrap_or(1);
+        if let Some(structure) = parse_structure(&contents, num_threads)
+            .map_err(|e| format!("Error parsing {}: {}", path, e))?
+        {
+            return Ok(structure);
+        }
+    }
+    match pdbtbx::open(path, StrictnessLevel::Medium) {
+        Ok((structure, _errors)) => Ok(structure),
+        Err(errors) => Err(format!("Error parsing {}: {:?}", path, errors).into()),
+    }
+}
+
+// Whether an atom is a hydrogen by its element, so mercury (HG) or helium (HE) atoms are not taken
+// for hydrogens. With a blank element column pdbtbx guesses the element from the atom name, the
+// HG of a serine becoming mercury, so an element equal to the atom name is only trusted for ions,
+// residues named as their atom, and otherwise the name tells the hydrogens.
+pub fn is_hydrogen(residue: &Residue, atom: &Atom) -> bool {
+    let name = atom.name().trim();
+    match atom.element() {
+        Some(element)
+            if !element.symbol().eq_ignore_ascii_case(name) || residue.name() == Some(name) =>
+        {
+            element.symbol() == "H"
+        }
+        _ => name.trim_start_matches(char::is_numeric).starts_with('H'),
+    }
+}
+
+// Atom name in columns 13-16: names of one letter elements start at column 14 unless they
+// already take the four columns, as in the wwPDB format
+fn atom_name(atom: &Atom) -> String {
+    let name = atom.name();
+    let one_letter = atom
+        .element()
+        .map_or(!name.starts_with(|c: char| c.is_ascii_digit()), |element| {
+            element.symbol().len() == 1
+        });
+    if name.len() < 4 && one_letter {
+        format!(" {:<3}", name)
+    } else {
+        format!("{:<4}", name)
+    }
+}
+
+// ATOM/HETATM records of a chain, followed by a TER record if asked. Atoms keep their serial
+// numbers unless a counter of the last serial number written is given, and coordinates are taken
+// from the given ones from the index on.
+fn write_chain<W: Write>(
+    output: &mut W,
+    chain: &Chain,
+    chain_id: &str,
+    coordinates: Option<&[[f64; 3]]>,
+    index: &mut usize,
+    mut serial_counter: Option<&mut usize>,
+    ter: bool,
+) -> io::Result<()> {
+    let mut serial_number = 0;
+    let mut last_residue: Option<&Residue> = None;
+    for residue in chain.residues() {
+        for conformer in residue.conformers() {
+            for atom in conformer.atoms() {
+                let (x, y, z) = match coordinates {
+                    Some(coordinates) => (
+                        coordinates[*index][0],
+                        coordinates[*index][1],
+                        coordinates[*index][2],
+                    ),
+                    None => atom.pos(),
+                };
+                *index += 1;
+                serial_number = match serial_counter.as_deref_mut() {
+                    Some(counter) => {
+                        *counter += 1;
+                        *counter
+                    }
+                    None => atom.serial_number(),
+                };
+                writeln!(
+                    output,
+                    "{:<6}{:>5} {}{:1}{:>3} {:1}{:>4}{:1}   {:>8.3}{:>8.3}{:>8.3}{:>6.2}{:>6.2}          {:>2}{:<2}",
+                    if atom.hetero() { "HETATM" } else { "ATOM" },
+                    serial_number % 100000,
+                    atom_name(atom),
+                    conformer.alternative_location().unwrap_or(""),
+                    conformer.name(),
+                    chain_id,
+                    residue.serial_number(),
+                    residue.insertion_code().unwrap_or(""),
+                    x,
+                    y,
+                    z,
+                    atom.occupancy(),
+                    atom.b_factor(),
+                    atom.element()
+                        .map_or(String::new(), |element| element.symbol().to_uppercase()),
+                    atom.pdb_charge(),
+                )?;
+            }
+        }
+        last_residue = Some(residue);
+    }
+    if let (Some(residue), true) = (last_residue, ter) {
+        let serial_number = match serial_counter {
+            Some(counter) => {
+                *counter += 1;
+                *counter
+            }
+            None => serial_number + 1,
+        };
+        writeln!(
+            output,
+            "TER   {:>5}      {:>3} {:1}{:>4}{}",
+            serial_number % 100000,
+            residue.name().unwrap_or(""),
+            chain_id,
+            residue.serial_number(),
+            residue.insertion_code().unwrap_or(""),
+        )?;
+    }
+    Ok(())
+}
+
+fn check_coordinates(structure: &PDB, coordinates: Option<&[[f64; 3]]>) -> io::Result<()> {
+    match coordinates {
+        Some(coordinates) if coordinates.len() != structure.atom_count() => {
+            Err(io::Error::other(format!(
+                "{} coordinates given for a structure of {} atoms",
+                coordinates.len(),
+                structure.atom_count()
+            )))
+        }
+        _ => Ok(()),
+    }
+}
+
+// Writes the first model of the structure as ATOM/HETATM records with a TER record closing every
+// chain. Coordinates, if given, replace the ones of the structure and follow the order of
+// pose::atom_coordinates, so a transformed docking pose can be written over its structure.
+pub fn write_pdb<W: Write>(
+    output: &mut W,
+    structure: &PDB,
+    coordinates: Option<&[[f64; 3]]>,
+) -> io::Result<()> {
+    check_coordinates(structure, coordinates)?;
+    let mut index = 0;
+    for chain in structure.chains() {
+        write_chain(
+            output,
+            chain,
+            chain.id(),
+            coordinates,
+            &mut index,
+            None,
+            true,
+        )?;
+    }
+    writeln!(output, "END")?;
+    output.flush()
+}
+
+// Metadata of a predicted complex written in its REMARK block
+#[derive(Debug, Clone, Copy)]
+pub struct ComplexRemarks {
+    pub swarm: usize,
+    pub glowworm: usize,
+    pub scoring: f64,
+}
+
+const CHAIN_IDS: &str = "ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789";
+
+// Chain IDs of the ligand in the complex: chains clashing with a receptor chain (or with a
+// previous ligand chain) take the first free ID, or keep theirs if all of them are taken
+pub fn ligand_chain_ids(receptor: &PDB, ligand: &PDB) -> Vec<String> {
+    let mut taken: Vec<String> = receptor
+        .chains()
+        .map(|chain| chain.id().to_string())
+        .collect();
+    let mut chain_ids: Vec<String> = Vec::new();
+    for chain in ligand.chains() {
+        let mut chain_id = chain.id().to_string();
+        if taken.contains(&chain_id) {
+            if let Some(free) = CHAIN_IDS
+                .chars()
+                .map(|c| c.to_string())
+                .find(|id| !taken.contains(id))
+            {
+                chain_id = free;
+            }
+        }
+        taken.push(chain_id.clone());
+        chain_ids.push(chain_id);
+    }
+    chain_ids
+}
+
+// Writes the receptor and ligand at the given coordinates as a single complex: atoms are numbered
+// from 1, ligand chains clashing with the receptor ones are renamed and a REMARK block records the
+// pose and the renamed chains. TER records, which take a serial number, are optional.
+pub fn write_complex<W: Write>(
+    output: &mut W,
+    receptor: &PDB,
+    receptor_coordinates: &[[f64; 3]],
+    ligand: &PDB,
+    ligand_coordinates: &[[f64; 3]],
+    remarks: &ComplexRemarks,
+    ter: bool,
+) -> io::Result<()> {
+    check_coordinates(receptor, Some(receptor_coordinates))?;
+    check_coordinates(ligand, Some(ligand_coordinates))?;
+    let chain_ids = ligand_chain_ids(receptor, ligand);
+
+    writeln!(output, "REMARK   1 LIGHTDOCK PREDICTED COMPLEX")?;
+    writeln!(output, "REMARK   1 SWARM {}", remarks.swarm)?;
+    writeln!(output, "REMARK   1 GLOWWORM {}", remarks.glowworm)?;
+    writeln!(output, "REMARK   1 SCORING {:.8}", remarks.scoring)?;
+    for (chain, chain_id) in ligand.chains().zip(chain_ids.iter()) {
+        if chain.id() != chain_id {
+            writeln!(
+                output,
+                "REMARK   1 LIGAND CHAIN {} RENAMED TO {}",
+                chain.id(),
+                chain_id
+            )?;
+        }
+    }
+
+    let mut serial_number = 0;
+    let mut index = 0;
+    for chain in receptor.chains() {
+        write_chain(
+            output,
+            chain,
+            chain.id(),
+            Some(receptor_coordinates),
+            &mut index,
+            Some(&mut serial_number),
+            ter,
+        )?;
+    }
+    index = 0;
+    for (chain, chain_id) in ligand.chains().zip(chain_ids.iter()) {
+        write_chain(
+            output,
+            chain,
+            chain_id,
+            Some(ligand_coordinates),
+            &mut index,
+            Some(&mut serial_number),
+            ter,
+        )?;
+    }
+    writeln!(output, "END")?;
+    output.flush()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pose;
+    use std::env;
+
+    #[test]
+    fn test_read_structure() {
+        let cargo_path = match env::var("CARGO_MANIFEST_DIR") {
+            Ok(val) => val,
+            Err(_) => String::from("."),
+        };
+        for filename in ["1azp/1azp_receptor.pdb", "2oob/2oob_ligand.pdb"] {
+            let path: String = format!("{}/tests/{}", cargo_path, filename);
+            let (expected, _errors) = pdbtbx::open(&path, StrictnessLevel::Medium).unwrap();
+            let contents = fs::read_to_string(&path).unwrap();
+            let structure = parse_structure(&contents, 3).unwrap().unwrap();
+
+            assert_eq!(structure.chain_count(), expected.chain_count());
+            assert_eq!(structure.residue_count(), expected.residue_count());
+            for (chain, expected_chain) in structure.chains().zip(expected.chains()) {
+                assert_eq!(chain.id(), expected_chain.id());
+                for (residue, expected_residue) in chain.residues().zip(expected_chain.residues()) {
+                    assert_eq!(residue.id(), expected_residue.id());
+                    assert_eq!(residue.name(), expected_residue.name());
+                    for (atom, expected_atom) in residue.atoms().zip(expected_residue.atoms()) {
+                        assert_eq!(atom.name(), expected_atom.name());
+                        assert_eq!(atom.pos(), expected_atom.pos());
+                        assert_eq!(atom.element(), expected_atom.element());
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_parse_structure() {
+        let contents = "\
+REMARK   Blank chain ids\n\
+ATOM      1  N   ALA     1       0.000   1.000   2.000  1.00  0.00           N\n\
+ATOM      2  CA  ALA     1       1.000   1.000   2.000  1.00  0.00           C\n\
+TER\n\
+ATOM      3  CA  GLY     1       2.000   1.000   2.000  1.00  0.00           C\n\
+ATOM      4  CA AGLY B   2       3.000   1.000   2.000  0.50  0.00           C\n\
+ATOM      5  CA BGLY B   2       3.100   1.000   2.000  0.50  0.00           C\n\
+CONECT    1    2\n\
+END\n";
+        let structure = parse_structure(contents, 2).unwrap().unwrap();
+        let ids: Vec<&str> = structure.chains().map(|chain| chain.id()).collect();
+        assert_eq!(ids, vec!["A", "B"]);
+        assert_eq!(structure.atom_count(), 5);
+        let chain_b = structure.chains().nth(1).unwrap();
+        assert_eq!(chain_b.residue_count(), 2);
+        assert_eq!(chain_b.residues().nth(1).unwrap().conformer_count(), 2);
+
+        assert!(parse_structure("MODEL 1\nENDMDL\nMODEL 2\nENDMDL\n", 1)
+            .unwrap()
+            .is_none());
+        let error = parse_structure(
+            "ATOM      1  N   ALA A   1       0.000   x       2.000\n",
+            1,
+        );
+        assert_eq!(error.unwrap_err(), "line 1: wrong y coordinate field");
+    }
+
+    #[test]
+    fn test_is_hydrogen() {
+        let contents = "\
+ATOM      1  OG  SER A   1       0.000   1.000   2.000  1.00  0.00           O\n\
+ATOM      2  HG  SER A   1       1.000   1.000   2.000  1.00  0.00           H\n\
+ATOM      3  HG  SER A   2       2.000   1.000   2.000  1.00  0.00\n\
+ATOM      4 1HB  SER A   2       3.000   1.000   2.000  1.00  0.00\n\
+HETATM    5 HG    HG A   3       4.000   1.000   2.000  1.00  0.00          HG\n\
+HETATM    6 HG1  MBR A   4       5.000   1.000   2.000  1.00  0.00          HG\n\
+END\n";
+        let structure = parse_structure(contents, 1).unwrap().unwrap();
+        let hydrogens: Vec<bool> = structure
+            .residues()
+            .flat_map(|residue| residue.atoms().map(move |atom| is_hydrogen(residue, atom)))
+            .collect();
+        assert_eq!(hydrogens, vec![false, true, true, true, false, false]);
+    }
+
+    #[test]
+    fn test_write_pdb() {
+        let cargo_path = match env::var("CARGO_MANIFEST_DIR") {
+            Ok(val) => val,
+            Err(_) => String::from("."),
+        };
+        let path: String = format!("{}/tests/2oob/2oob_ligand.pdb", cargo_path);
+        let structure = read_structure(&path).unwrap();
+        let mut output: Vec<u8> = Vec::new();
+        write_pdb(&mut output, &structure, None).unwrap();
+        let written = String::from_utf8(output).unwrap();
+
+        // Same ATOM records as the original file
+        let contents = fs::read_to_string(&path).unwrap();
+        let expected: Vec<&str> = contents
+            .lines()
+            .filter(|line| line.starts_with("ATOM"))
+            .collect();
+        let atoms: Vec<&str> = written
+            .lines()
+            .filter(|line| line.starts_with("ATOM"))
+            .collect();
+        assert_eq!(atoms, expected);
+        assert!(written.ends_with("TER     977      ARG B  72\nEND\n"));
+
+        // Transformed coordinates, alternative locations and hetero atoms
+        let contents = "\
+ATOM      1  N   ALA A   1       0.000   1.000   2.000  1.00  0.00           N\n\
+ATOM      2  CA AALA A   1       1.000   1.000   2.000  0.50  0.00           C\n\
+ATOM      3  CA BALA A   1       1.100   1.000   2.000  0.50  0.00           C\n\
+HETATM    4 FE   HEM A   2       5.000   5.000   5.000  1.00 10.00          FE\n";
+        let structure = parse_structure(contents, 1).unwrap().unwrap();
+        let coordinates = vec![[-1.0, 0.0, 0.0]; 4];
+        let mut output: Vec<u8> = Vec::new();
+        write_pdb(&mut output, &structure, Some(&coordinates)).unwrap();
+        let written = String::from_utf8(output).unwrap();
+        let lines: Vec<&str> = written.lines().collect();
+        assert_eq!(
+            lines[2],
+            "ATOM      3  CA BALA A   1      -1.000   0.000   0.000  0.50  0.00           C  "
+        );
+        assert_eq!(
+            lines[3],
+            "HETATM    4 FE   HEM A   2      -1.000   0.000   0.000  1.00 10.00          FE  "
+        );
+        assert_eq!(lines[4], "TER       5      HEM A   2");
+        let reread = parse_structure(&written, 1).unwrap().unwrap();
+        assert_eq!(reread.atom_count(), 4);
+        assert!(write_pdb(&mut Vec::new(), &structure, Some(&coordinates[1..])).is_err());
+    }
+
+    #[test]
+    fn test_write_complex() {
+        let receptor = parse_structure(
+            "\
+ATOM     10  N   ALA A   1       0.000   1.000   2.000  1.00  0.00           N\n\
+ATOM     11  CA  ALA A   1       1.000   1.000   2.000  1.00  0.00           C\n\
+ATOM     20  CA  GLY B   1       2.000   1.000   2.000  1.00  0.00           C\n",
+            1,
+        )
+        .unwrap()
+        .unwrap();
+        let ligand = parse_structure(
+            "\
+ATOM      1  CA  LYS A   5       0.000   0.000   9.000  1.00  0.00           C\n\
+ATOM      1  CA  ASP D   8       0.000   0.000   9.000  1.00  0.00           C\n",
+            1,
+        )
+        .unwrap()
+        .unwrap();
+        assert_eq!(ligand_chain_ids(&receptor, &ligand), vec!["C", "D"]);
+
+        let remarks = ComplexRemarks {
+            swarm: 2,
+            glowworm: 7,
+            scoring: 12.5,
+        };
+        let ligand_coordinates = vec![[1.0, 2.0, 3.0], [4.0, 5.0, 6.0]];
+        let mut output: Vec<u8> = Vec::new();
+        write_complex(
+            &mut output,
+            &receptor,
+            &pose::atom_coordinates(&receptor),
+            &ligand,
+            &ligand_coordinates,
+            &remarks,
+            true,
+        )
+        .unwrap();
+        let written = String::from_utf8(output).unwrap();
+        let lines: Vec<&str> = written.lines().collect();
+        assert_eq!(lines[3], "REMARK   1 SCORING 12.50000000");
+        assert_eq!(lines[4], "REMARK   1 LIGAND CHAIN A RENAMED TO C");
+        assert_eq!(&lines[5][..27], "ATOM      1  N   ALA A   1 ");
+        assert_eq!(lines[7], "TER       3      ALA A   1");
+        assert_eq!(
+            lines[10],
+            "ATOM      6  CA  LYS C   5       1.000   2.000   3.000  1.00  0.00           C  "
+        );
+        assert_eq!(lines.len(), 15);
+
+        // Without TER records the complex is numbered continuously
+        let mut output: Vec<u8> = Vec::new();
+        write_complex(
+            &mut output,
+            &receptor,
+            &pose::atom_coordinates(&receptor),
+            &ligand,
+            &ligand_coordinates,
+            &remarks,
+            false,
+        )
+        .unwrap();
+        let written = String::from_utf8(output).unwrap();
+        let complex = parse_structure(&written, 1).unwrap().unwrap();
+        let ids: Vec<&str> = complex.chains().map(|chain| chain.id()).collect();
+        assert_eq!(ids, vec!["A", "B", "C", "D"]);
+        let serial_numbers: Vec<usize> = complex.atoms().map(|atom| atom.serial_number()).collect();
+        assert_eq!(serial_numbers, vec![1, 2, 3, 4, 5]);
+    }
+}
@@ -1,13 +1,95 @@
+use super::constants::{
+    DEFAULT_INITIAL_LUCIFERIN, DEFAULT_INITIAL_VISION_RANGE, GSO_LEGACY_OUTPUT_COLUMNS,
+    GSO_OUTPUT_COLUMNS, GSO_OUTPUT_VERSION,
+};
 use super::glowworm::distance;
 use super::glowworm::Glowworm;
+use super::minimization::LBFGS;
+use super::moves::{GlowwormPose, Interpolation, MoveOperator};
+use super::output::{gso_output_path, write_file, GSOEntry, RotationOutput};
 use super::qt::Quaternion;
-use super::scoring::Score;
+use super::scoring::{z_scores, Score};
+use super::ternary::LigandPose;
+use log::warn;
 use rand::Rng;
-use std::fs::File;
 use std::io::{Error, Write};
 
+// Random noise of the movement phase, a temperature decreasing by a constant factor every step
+#[derive(Debug, Clone, Copy)]
+pub struct NoiseSchedule {
+    pub temperature: f64,
+    pub decay: f64,
+}
+
+impl NoiseSchedule {
+    pub fn new(temperature: f64, decay: f64) -> Self {
+        NoiseSchedule { temperature, decay }
+    }
+
+    pub fn level(&self, step: u32) -> f64 {
+        self.temperature * self.decay.powi(step.saturating_sub(1) as i32)
+    }
+}
+
+// Glowworms re-seeded at random poses inside the starting sphere of the swarm when the diversity
+// falls below the threshold before the given step
+#[derive(Debug, Clone, Copy)]
+pub struct DiversityInjection {
+    pub threshold: f64,
+    pub min_steps: u32,
+    pub fraction: f64,
+    pub center: [f64; 3],
+    pub radius: f64,
+}
+
+impl DiversityInjection {
+    pub fn new(
+        threshold: f64,
+        min_steps: u32,
+        fraction: f64,
+        center: [f64; 3],
+        radius: f64,
+    ) -> Self {
+        DiversityInjection {
+            threshold,
+            min_steps,
+            fraction,
+            center,
+            radius,
+        }
+    }
+}
+
+// Seed of the random number generator of a swarm, mixing the setup seed and the swarm ID with the
+// SplitMix64 finalizer so swarms of the same setup do not share their random sequences
+pub fn swarm_seed(seed: u64, swarm_id: u64) -> u64 {
+    let mut z = seed.wrapping_add(swarm_id.wrapping_add(1).wrapping_mul(0x9e37_79b9_7f4a_7c15));
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^ (z >> 31)
+}
+
 pub struct Swarm<'a> {
     pub glowworms: Vec<Glowworm<'a>>,
+    // Movements towards the selected neighbor per luciferin update
+    pub movement_steps: u32,
+    // Order of the ring when docking symmetric assemblies, poses are kept symmetric
+    pub symmetry: Option<usize>,
+    // Version, metadata and stagnation column written in the output, which keeps the legacy
+    // header and columns otherwise
+    pub versioned_output: bool,
+    // Orientation columns written after the last column of the output
+    pub rotation_output: Option<RotationOutput>,
+    // Seed of the random number generator, written in the output header
+    pub seed: Option<u64>,
+    // Rule moving the glowworms towards their selected neighbors
+    pub move_operator: Box<dyn MoveOperator>,
+    // Luciferin and vision range the glowworms start with, also given to the re-seeded ones
+    pub initial_luciferin: f64,
+    pub initial_vision_range: f64,
+    // Poses before the current movement phase, the buffers are reused every step so large
+    // swarms do not allocate a copy of every pose and normal modes vector per step
+    previous: Vec<GlowwormPose>,
 }
 
 impl<'a> Default for Swarm<'a> {
@@ -20,6 +102,15 @@ impl<'a> Swarm<'a> {
     pub fn new() -> Self {
         Swarm {
             glowworms: Vec::new(),
+            movement_steps: 1,
+            symmetry: None,
+            versioned_output: false,
+            rotation_output: None,
+            seed: None,
+            move_operator: Box::new(Interpolation),
+            initial_luciferin: DEFAULT_INITIAL_LUCIFERIN,
+            initial_vision_range: DEFAULT_INITIAL_VISION_RANGE,
+            previous: Vec::new(),
         }
     }
 
@@ -46,11 +137,18 @@ impl<'a> Swarm<'a> {
             // ANM for ligand
             let mut lig_nmodes: Vec<f64> = Vec::new();
             if use_anm && lig_num_anm > 0 {
-                for j in 7 + rec_num_anm..positions[i].len() {
+                for j in 7 + rec_num_anm..7 + rec_num_anm + lig_num_anm {
                     lig_nmodes.push(positions[i][j]);
                 }
             }
-            let glowworm = Glowworm::new(
+            // Pose of the second ligand after the extents when docking two ligands
+            let end = 7 + rec_nmodes.len() + lig_nmodes.len();
+            let second_ligand = if position.len() >= end + 7 {
+                Some(LigandPose::from_slice(&position[end..end + 7]))
+            } else {
+                None
+            };
+            let mut glowworm = Glowworm::new(
                 i as u32,
                 translation,
                 rotation,
@@ -59,33 +157,55 @@ impl<'a> Swarm<'a> {
                 scoring,
                 use_anm,
             );
+            glowworm.second_ligand = second_ligand;
             self.glowworms.push(glowworm);
         }
     }
 
+    // Vision range from the spread of the glowworms instead of the fixed initial values: the mean
+    // distance to the closest glowworm, up to the largest distance between two glowworms
+    pub fn vision_range_from_extent(&mut self) {
+        let num_glowworms = self.glowworms.len();
+        if num_glowworms < 2 {
+            return;
+        }
+        let mut total_closest: f64 = 0.0;
+        let mut max_distance: f64 = 0.0;
+        for (i, g1) in self.glowworms.iter().enumerate() {
+            let mut closest = f64::MAX;
+            for (j, g2) in self.glowworms.iter().enumerate() {
+                if i != j {
+                    let distance = distance(g1, g2);
+                    closest = closest.min(distance);
+                    max_distance = max_distance.max(distance);
+                }
+            }
+            total_closest += closest;
+        }
+        let vision_range = (total_closest / num_glowworms as f64).min(max_distance);
+        self.initial_vision_range = vision_range;
+        for glowworm in self.glowworms.iter_mut() {
+            glowworm.vision_range = vision_range;
+            glowworm.max_vision_range = max_distance;
+        }
+    }
+
     pub fn update_luciferin(&mut self) {
         for glowworm in self.glowworms.iter_mut() {
             glowworm.compute_luciferin();
         }
     }
 
-    pub fn movement_phase(&mut self, rng: &mut rand::prelude::StdRng) {
-        // Save original positions
-        let mut positions: Vec<Vec<f64>> = Vec::new();
-        let mut rotations: Vec<Quaternion> = Vec::new();
-        let mut anm_recs: Vec<Vec<f64>> = Vec::new();
-        let mut anm_ligs: Vec<Vec<f64>> = Vec::new();
-        for glowworm in self.glowworms.iter() {
-            positions.push(glowworm.translation.clone());
-            rotations.push(glowworm.rotation);
-            anm_recs.push(glowworm.rec_nmodes.clone());
-            anm_ligs.push(glowworm.lig_nmodes.clone());
-        }
-
-        // First search for each glowworm's neighbors
-        let mut neighbors: Vec<Vec<u32>> = Vec::new();
+    pub fn movement_phase(&mut self, rng: &mut rand::prelude::StdRng, noise: f64) {
+        self.search_neighbors();
+        self.move_glowworms(rng, noise);
+    }
+
+    // Neighbors of each glowworm, the brighter ones within its vision range
+    pub fn search_neighbors(&mut self) {
         for i in 0..self.glowworms.len() {
-            let mut this_neighbors = Vec::new();
+            let mut this_neighbors = std::mem::take(&mut self.glowworms[i].neighbors);
+            this_neighbors.clear();
             let g1 = &self.glowworms[i];
             for j in 0..self.glowworms.len() {
                 if i != j {
@@ -98,41 +218,234 @@ impl<'a> Swarm<'a> {
                     }
                 }
             }
-            neighbors.push(this_neighbors);
+            self.glowworms[i].neighbors = this_neighbors;
         }
+    }
 
-        // Second compute probability moving towards the neighbor
-        let mut luciferins = Vec::new();
-        for glowworm in self.glowworms.iter_mut() {
-            luciferins.push(glowworm.luciferin);
+    // Moves each glowworm towards a neighbor chosen by its probabilities
+    pub fn move_glowworms(&mut self, rng: &mut rand::prelude::StdRng, noise: f64) {
+        // Save original positions
+        self.previous
+            .resize_with(self.glowworms.len(), GlowwormPose::default);
+        for (previous, glowworm) in self.previous.iter_mut().zip(self.glowworms.iter()) {
+            previous.translation.clone_from(&glowworm.translation);
+            previous.rotation = glowworm.rotation;
+            previous.rec_nmodes.clone_from(&glowworm.rec_nmodes);
+            previous.lig_nmodes.clone_from(&glowworm.lig_nmodes);
+            previous.second_ligand.clone_from(&glowworm.second_ligand);
         }
-        for i in 0..self.glowworms.len() {
-            let glowworm = &mut self.glowworms[i];
-            glowworm.neighbors = neighbors[i].clone();
+
+        // Probability of moving towards each neighbor
+        let luciferins: Vec<f64> = self.glowworms.iter().map(|g| g.luciferin).collect();
+        for glowworm in self.glowworms.iter_mut() {
             glowworm.compute_probability_moving_toward_neighbor(&luciferins);
+            // Early steps may follow a less luminous neighbor
+            if noise > 0.0 {
+                glowworm.perturb_probabilities(noise, rng);
+            }
         }
 
         // Finally move to the selected position
+        let movement_steps = self.movement_steps;
         for i in 0..self.glowworms.len() {
             let glowworm = &mut self.glowworms[i];
             let neighbor_id = glowworm.select_random_neighbor(rng.gen::<f64>());
-            let position = &positions[neighbor_id as usize];
-            let rotation = &rotations[neighbor_id as usize];
-            let anm_rec = &anm_recs[neighbor_id as usize];
-            let anm_lig = &anm_ligs[neighbor_id as usize];
-            glowworm.move_towards(neighbor_id, position, rotation, anm_rec, anm_lig);
+            let neighbor = &self.previous[neighbor_id as usize];
+            self.move_operator
+                .move_towards(glowworm, neighbor_id, neighbor);
+            // Further sub-steps towards the same pose, without overshooting it
+            for _ in 1..movement_steps {
+                let remaining = (0..3)
+                    .map(|k| (neighbor.translation[k] - glowworm.translation[k]).powi(2))
+                    .sum::<f64>()
+                    .sqrt();
+                if !glowworm.moved || remaining <= glowworm.translation_step {
+                    break;
+                }
+                self.move_operator
+                    .move_towards(glowworm, neighbor_id, neighbor);
+            }
+            if noise > 0.0 && glowworm.moved {
+                glowworm.perturb_movement(noise, rng);
+            }
+            if let (Some(order), true) = (self.symmetry, glowworm.moved) {
+                glowworm.make_symmetric(order);
+            }
+            // A corrupted pose would spread to the glowworms following this one
+            let previous = &self.previous[i];
+            if glowworm.restore_corrupted(
+                &previous.translation,
+                &previous.rotation,
+                &previous.rec_nmodes,
+                &previous.lig_nmodes,
+                &previous.second_ligand,
+            ) {
+                warn!(
+                    "Corrupted pose of glowworm {} after moving, restored from its previous pose",
+                    glowworm.id
+                );
+            }
             glowworm.update_vision_range();
         }
     }
 
-    pub fn save(&mut self, step: u32, output_directory: &str) -> Result<(), Error> {
-        let path = format!("{}/gso_{:?}.out", output_directory, step);
-        let mut output = File::create(path)?;
-        writeln!(
-            output,
-            "#Coordinates  RecID  LigID  Luciferin  Neighbor's number  Vision Range  Scoring"
-        )?;
-        for glowworm in self.glowworms.iter() {
+    // Mean distance between the translations of all pairs of glowworms
+    pub fn diversity(&self) -> f64 {
+        let mut total_distance: f64 = 0.0;
+        let mut num_pairs: usize = 0;
+        for (i, g1) in self.glowworms.iter().enumerate() {
+            for g2 in self.glowworms[i + 1..].iter() {
+                total_distance += distance(g1, g2);
+                num_pairs += 1;
+            }
+        }
+        if num_pairs == 0 {
+            return 0.0;
+        }
+        total_distance / num_pairs as f64
+    }
+
+    // Moves the given fraction of glowworms, the ones with the lowest luciferin, to random poses
+    pub fn reseed(
+        &mut self,
+        injection: &DiversityInjection,
+        rng: &mut rand::prelude::StdRng,
+    ) -> usize {
+        let num_glowworms = ((injection.fraction * self.glowworms.len() as f64).round() as usize)
+            .min(self.glowworms.len());
+        let mut indexes: Vec<usize> = (0..self.glowworms.len()).collect();
+        indexes.sort_by(|&a, &b| {
+            self.glowworms[a]
+                .luciferin
+                .total_cmp(&self.glowworms[b].luciferin)
+        });
+        for &i in indexes[..num_glowworms].iter() {
+            // Uniform inside the sphere
+            let point = loop {
+                let point: Vec<f64> = (0..3).map(|_| rng.gen_range(-1.0, 1.0)).collect();
+                if point.iter().map(|x| x * x).sum::<f64>() <= 1.0 {
+                    break point;
+                }
+            };
+            let translation: Vec<f64> = (0..3)
+                .map(|k| injection.center[k] + injection.radius * point[k])
+                .collect();
+            self.glowworms[i].reset(
+                translation,
+                Quaternion::random(rng),
+                self.initial_luciferin,
+                self.initial_vision_range,
+            );
+        }
+        num_glowworms
+    }
+
+    // Local refinement of the poses of the glowworms with the best scores, returning the index of
+    // every refined glowworm and its score before refinement
+    pub fn refine(&mut self, top: usize, minimizer: &LBFGS) -> Vec<(usize, f64)> {
+        let mut indexes: Vec<usize> = (0..self.glowworms.len()).collect();
+        indexes.sort_by(|&a, &b| {
+            self.glowworms[b]
+                .scoring
+                .total_cmp(&self.glowworms[a].scoring)
+        });
+        indexes.truncate(top);
+        let mut refined: Vec<(usize, f64)> = Vec::new();
+        for i in indexes {
+            let glowworm = &mut self.glowworms[i];
+            let pose = minimizer.refine(
+                glowworm.scoring_function.as_ref(),
+                &glowworm.translation,
+                &glowworm.rotation,
+                &glowworm.rec_nmodes,
+                &glowworm.lig_nmodes,
+            );
+            refined.push((i, glowworm.scoring));
+            glowworm.translation = pose.translation;
+            glowworm.rotation = pose.rotation;
+            glowworm.scoring = pose.scoring;
+            // The refined pose is moved to the closest ring, so it is scored again
+            if let Some(order) = self.symmetry {
+                glowworm.make_symmetric(order);
+                glowworm.scoring = glowworm.scoring_function.energy(
+                    &glowworm.translation,
+                    &glowworm.rotation,
+                    &glowworm.rec_nmodes,
+                    &glowworm.lig_nmodes,
+                );
+            }
+        }
+        refined
+    }
+
+    pub fn save(&mut self, step: u32, output_directory: &str, compress: bool) -> Result<(), Error> {
+        let mut contents: Vec<u8> = Vec::new();
+        self.write_output(step, &mut contents)?;
+        write_file(
+            &gso_output_path(output_directory, step, compress),
+            &contents,
+            compress,
+        )
+    }
+
+    // Current poses of the glowworms, as they are read from the GSO output
+    pub fn entries(&self) -> Vec<GSOEntry> {
+        let scores: Vec<f64> = self.glowworms.iter().map(|g| g.scoring).collect();
+        let z_scores = z_scores(&scores);
+        self.glowworms
+            .iter()
+            .zip(z_scores)
+            .map(|(glowworm, z_score)| {
+                let mut position = glowworm.translation.clone();
+                position.extend([
+                    glowworm.rotation.w,
+                    glowworm.rotation.x,
+                    glowworm.rotation.y,
+                    glowworm.rotation.z,
+                ]);
+                if glowworm.use_anm {
+                    position.extend(&glowworm.rec_nmodes);
+                    position.extend(&glowworm.lig_nmodes);
+                }
+                if let Some(second_ligand) = &glowworm.second_ligand {
+                    position.extend(second_ligand.to_vec());
+                }
+                GSOEntry {
+                    position,
+                    rec_id: glowworm.rec_id,
+                    lig_id: glowworm.lig_id,
+                    luciferin: glowworm.luciferin,
+                    num_neighbors: glowworm.neighbors.len(),
+                    vision_range: glowworm.vision_range,
+                    scoring: glowworm.scoring,
+                    z_score: Some(z_score),
+                    stagnation: Some(glowworm.stagnation),
+                }
+            })
+            .collect()
+    }
+
+    pub fn write_output<W: Write>(&self, step: u32, output: &mut W) -> Result<(), Error> {
+        let columns = if self.versioned_output {
+            writeln!(output, "#Version: {}", GSO_OUTPUT_VERSION)?;
+            writeln!(output, "#Step: {}", step)?;
+            writeln!(output, "#Glowworms: {}", self.glowworms.len())?;
+            if let Some(seed) = self.seed {
+                writeln!(output, "#Seed: {}", seed)?;
+            }
+            GSO_OUTPUT_COLUMNS
+        } else {
+            GSO_LEGACY_OUTPUT_COLUMNS
+        };
+        match self.rotation_output {
+            Some(rotation_output) => {
+                writeln!(output, "#{}  {}", columns, rotation_output.columns())?
+            }
+            None => writeln!(output, "#{}", columns)?,
+        }
+        let scores: Vec<f64> = self.glowworms.iter().map(|g| g.scoring).collect();
+        let z_scores = z_scores(&scores);
+        for (glowworm, z_score) in self.glowworms.iter().zip(z_scores.iter()) {
             write!(
                 output,
                 "({:.7}, {:.7}, {:.7}, {:.7}, {:.7}, {:.7}, {:.7}",
@@ -154,15 +467,426 @@ impl<'a> Swarm<'a> {
                     write!(output, ", {:.7}", glowworm.lig_nmodes[i])?;
                 }
             }
-            writeln!(
+            if let Some(second_ligand) = &glowworm.second_ligand {
+                for value in second_ligand.to_vec() {
+                    write!(output, ", {:.7}", value)?;
+                }
+            }
+            write!(
                 output,
-                ")    0    0   {:.8}  {:?} {:.3} {:.8}",
+                ")    {}    {}   {:.8}  {:?} {:.3} {:.8} {:.5}",
+                glowworm.rec_id,
+                glowworm.lig_id,
                 glowworm.luciferin,
                 glowworm.neighbors.len(),
                 glowworm.vision_range,
-                glowworm.scoring
+                glowworm.scoring,
+                z_score
             )?;
+            if self.versioned_output {
+                write!(output, " {}", glowworm.stagnation)?;
+            }
+            if let Some(rotation_output) = self.rotation_output {
+                rotation_output.write(&glowworm.rotation, output)?;
+            }
+            writeln!(output)?;
         }
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::output::parse_gso_output;
+    use rand::SeedableRng;
+
+    struct Height;
+
+    impl Score for Height {
+        fn energy(&self, translation: &[f64], _: &Quaternion, _: &[f64], _: &[f64]) -> f64 {
+            translation[2]
+        }
+    }
+
+    // Best poses 5 Angstroms along the z axis
+    struct Well;
+
+    impl Score for Well {
+        fn energy(&self, translation: &[f64], _: &Quaternion, _: &[f64], _: &[f64]) -> f64 {
+            -(translation[0] * translation[0]
+                + translation[1] * translation[1]
+                + (translation[2] - 5.0) * (translation[2] - 5.0))
+        }
+    }
+
+    #[test]
+    fn test_refine_symmetric() {
+        let scoring: Box<dyn Score> = Box::new(Well);
+        let mut swarm = Swarm::new();
+        swarm.symmetry = Some(3);
+        let rotation = Quaternion::from_rotation_vector(&[0.0, 0.0, 2.0]);
+        let positions = vec![vec![
+            1.0, 0.0, 3.0, rotation.w, rotation.x, rotation.y, rotation.z,
+        ]];
+        swarm.add_glowworms(&positions, &scoring, false, 0, 0);
+        swarm.update_luciferin();
+        let minimizer = LBFGS::new(5, 50, 1e-4, 1.0);
+        let refined = swarm.refine(1, &minimizer);
+        assert_eq!(refined.len(), 1);
+
+        // The ring has no translation along its z axis, far from the refined pose
+        let mut output: Vec<u8> = Vec::new();
+        swarm.write_output(1, &mut output).unwrap();
+        let output = parse_gso_output(&String::from_utf8(output).unwrap()).unwrap();
+        let entry = &output.entries[0];
+        let position = &entry.position;
+        assert!(position[2].abs() < 1e-6);
+        let rotation = Quaternion::new(position[3], position[4], position[5], position[6]);
+        let energy = Well.energy(&position[..3], &rotation, &[], &[]);
+        assert!((entry.scoring - energy).abs() < 1e-5);
+    }
+
+    #[test]
+    fn test_move_towards_same_extents() {
+        let scoring: Box<dyn Score> = Box::new(Height);
+        let mut swarm = Swarm::new();
+        let positions = vec![
+            vec![0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0],
+            vec![0.0, 0.0, 3.0, 1.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0],
+        ];
+        swarm.add_glowworms(&positions, &scoring, true, 2, 2);
+
+        // Both glowworms still at the starting zero extents
+        let other = &swarm.glowworms[1];
+        let (position, rotation) = (other.translation.clone(), other.rotation);
+        let (rec_nmodes, lig_nmodes) = (other.rec_nmodes.clone(), other.lig_nmodes.clone());
+        let glowworm = &mut swarm.glowworms[0];
+        glowworm.move_towards(1, &position, &rotation, &rec_nmodes, &lig_nmodes);
+        assert_eq!(glowworm.rec_nmodes, vec![0.0, 0.0]);
+        assert_eq!(glowworm.lig_nmodes, vec![0.0, 0.0]);
+        assert!(glowworm.translation[2] > 0.0);
+    }
+
+    #[test]
+    fn test_diversity() {
+        let scoring: Box<dyn Score> = Box::new(Height);
+        let mut swarm = Swarm::new();
+        let positions = vec![
+            vec![0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0],
+            vec![0.0, 0.0, 3.0, 1.0, 0.0, 0.0, 0.0],
+            vec![0.0, 4.0, 0.0, 1.0, 0.0, 0.0, 0.0],
+        ];
+        swarm.add_glowworms(&positions, &scoring, false, 0, 0);
+        assert_eq!(swarm.diversity(), 4.0);
+        swarm.vision_range_from_extent();
+        swarm.initial_luciferin = 2.0;
+        swarm.update_luciferin();
+
+        // Only the glowworm with the lowest luciferin is moved
+        let injection = DiversityInjection::new(5.0, 10, 0.4, [10.0, 10.0, 10.0], 1.0);
+        let mut rng: rand::prelude::StdRng = SeedableRng::seed_from_u64(0);
+        assert_eq!(swarm.reseed(&injection, &mut rng), 1);
+        let moved = &swarm.glowworms[0];
+        assert!(moved.moved);
+        assert_eq!(moved.luciferin, 2.0);
+        assert!((moved.vision_range - 10.0 / 3.0).abs() < 1e-12);
+        let distance: f64 = moved
+            .translation
+            .iter()
+            .map(|x| (x - 10.0) * (x - 10.0))
+            .sum::<f64>()
+            .sqrt();
+        assert!(distance <= 1.0);
+        assert_eq!(swarm.glowworms[1].translation, vec![0.0, 0.0, 3.0]);
+    }
+
+    #[test]
+    fn test_stagnation() {
+        let scoring: Box<dyn Score> = Box::new(Height);
+        let mut swarm = Swarm::new();
+        let positions = vec![
+            vec![0.0, 0.0, 20.0, 1.0, 0.0, 0.0, 0.0],
+            vec![0.0, 0.0, 1.0, 1.0, 0.0, 0.0, 0.0],
+        ];
+        swarm.add_glowworms(&positions, &scoring, false, 0, 0);
+        // The luciferin of the first glowworm grows towards its scoring, the one of the second
+        // glowworm decays
+        for _ in 0..3 {
+            swarm.update_luciferin();
+        }
+        assert_eq!(swarm.glowworms[0].stagnation, 0);
+        assert_eq!(swarm.glowworms[1].stagnation, 2);
+
+        swarm.glowworms[1].moved = true;
+        swarm.update_luciferin();
+        assert_eq!(swarm.glowworms[1].stagnation, 0);
+
+        // Only written in the versioned output
+        let mut output: Vec<u8> = Vec::new();
+        swarm.write_output(4, &mut output).unwrap();
+        let output = parse_gso_output(&String::from_utf8(output).unwrap()).unwrap();
+        assert_eq!(output.entries[0].stagnation, None);
+        swarm.versioned_output = true;
+        let mut output: Vec<u8> = Vec::new();
+        swarm.write_output(4, &mut output).unwrap();
+        let output = parse_gso_output(&String::from_utf8(output).unwrap()).unwrap();
+        assert_eq!(output.version, GSO_OUTPUT_VERSION);
+        assert_eq!(output.entries[0].stagnation, Some(0));
+        assert_eq!(output.entries[1].stagnation, Some(0));
+    }
+
+    #[test]
+    fn test_legacy_output() {
+        let scoring: Box<dyn Score> = Box::new(Height);
+        let mut swarm = Swarm::new();
+        swarm.seed = Some(324_324);
+        let positions = vec![
+            vec![0.0, 0.0, 20.0, 1.0, 0.0, 0.0, 0.0],
+            vec![0.0, 0.0, 1.0, 1.0, 0.0, 0.0, 0.0],
+        ];
+        swarm.add_glowworms(&positions, &scoring, false, 0, 0);
+        swarm.update_luciferin();
+
+        // The column header only, as written before the format was versioned
+        let mut output: Vec<u8> = Vec::new();
+        swarm.write_output(10, &mut output).unwrap();
+        let output = String::from_utf8(output).unwrap();
+        let lines: Vec<&str> = output.lines().collect();
+        assert_eq!(lines.len(), 3);
+        assert_eq!(lines[0], format!("#{}", GSO_LEGACY_OUTPUT_COLUMNS));
+        let num_columns = lines[0].split("  ").count();
+        assert_eq!(num_columns, 8);
+        for line in &lines[1..] {
+            let end = line.find(')').unwrap();
+            assert_eq!(1 + line[end + 1..].split_whitespace().count(), num_columns);
+        }
+
+        let output = parse_gso_output(&output).unwrap();
+        assert_eq!(output.version, 1);
+        assert!(output.metadata.is_empty());
+        assert_eq!(output.entries.len(), 2);
+        assert_eq!(output.entries[0].scoring, 20.0);
+        assert!(output.entries[0].z_score.is_some());
+        assert_eq!(output.entries[0].stagnation, None);
+    }
+
+    #[test]
+    fn test_vision_range_from_extent() {
+        let scoring: Box<dyn Score> = Box::new(Height);
+        let mut swarm = Swarm::new();
+        let positions = vec![
+            vec![0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0],
+            vec![0.0, 0.0, 3.0, 1.0, 0.0, 0.0, 0.0],
+            vec![0.0, 4.0, 0.0, 1.0, 0.0, 0.0, 0.0],
+        ];
+        swarm.add_glowworms(&positions, &scoring, false, 0, 0);
+        swarm.vision_range_from_extent();
+        // Closest glowworms at 3, 3 and 4 Angstroms
+        assert!((swarm.glowworms[0].vision_range - 10.0 / 3.0).abs() < 1e-12);
+        assert_eq!(swarm.glowworms[2].max_vision_range, 5.0);
+    }
+
+    #[test]
+    fn test_softmax_probabilities() {
+        let scoring: Box<dyn Score> = Box::new(Height);
+        let mut swarm = Swarm::new();
+        let positions = vec![vec![0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0]; 3];
+        swarm.add_glowworms(&positions, &scoring, false, 0, 0);
+        let glowworm = &mut swarm.glowworms[0];
+        glowworm.neighbors = vec![1, 2];
+        let luciferins = [5.0, 6.0, 5.0 + 1.0 + 2.0_f64.ln()];
+        glowworm.compute_probability_moving_toward_neighbor(&luciferins);
+        assert!((glowworm.probabilities[0] - 1.0 / (2.0 + 2.0_f64.ln())).abs() < 1e-12);
+
+        // Twice as likely to follow a neighbor with ln(2) more luciferin
+        glowworm.softmax_temperature = Some(1.0);
+        glowworm.compute_probability_moving_toward_neighbor(&luciferins);
+        assert!((glowworm.probabilities[0] - 1.0 / 3.0).abs() < 1e-12);
+        assert!((glowworm.probabilities[1] - 2.0 / 3.0).abs() < 1e-12);
+    }
+
+    #[test]
+    fn test_movement_steps() {
+        let scoring: Box<dyn Score> = Box::new(Height);
+        let positions = vec![
+            vec![0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0],
+            vec![0.0, 0.0, 2.6, 1.0, 0.0, 0.0, 0.0],
+        ];
+        let mut rng: rand::prelude::StdRng = SeedableRng::seed_from_u64(0);
+        for (movement_steps, expected) in [(1, 0.5), (4, 2.0), (10, 2.5)] {
+            let mut swarm = Swarm::new();
+            swarm.add_glowworms(&positions, &scoring, false, 0, 0);
+            swarm.movement_steps = movement_steps;
+            swarm.glowworms[0].vision_range = 3.0;
+            swarm.update_luciferin();
+            swarm.movement_phase(&mut rng, 0.0);
+            // Sub-steps of 0.5 Angstroms, stopping before overshooting the neighbor
+            assert!((swarm.glowworms[0].translation[2] - expected).abs() < 1e-12);
+            assert_eq!(swarm.glowworms[1].translation[2], 2.6);
+        }
+    }
+
+    #[test]
+    fn test_move_operator() {
+        // Rotation-only rule, the glowworms keep their translations
+        struct RotationOnly;
+        impl MoveOperator for RotationOnly {
+            fn move_towards(
+                &self,
+                glowworm: &mut Glowworm,
+                neighbor_id: u32,
+                neighbor: &GlowwormPose,
+            ) {
+                glowworm.moved = glowworm.id != neighbor_id;
+                glowworm.rotation = neighbor.rotation;
+            }
+        }
+        let scoring: Box<dyn Score> = Box::new(Height);
+        let positions = vec![
+            vec![0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0],
+            vec![0.0, 0.0, 2.6, 0.0, 0.0, 0.0, 1.0],
+        ];
+        let mut rng: rand::prelude::StdRng = SeedableRng::seed_from_u64(0);
+        let mut swarm = Swarm::new();
+        swarm.add_glowworms(&positions, &scoring, false, 0, 0);
+        swarm.move_operator = Box::new(RotationOnly);
+        swarm.glowworms[0].vision_range = 3.0;
+        swarm.update_luciferin();
+        swarm.movement_phase(&mut rng, 0.0);
+        assert!(swarm.glowworms[0].moved);
+        assert_eq!(swarm.glowworms[0].translation[2], 0.0);
+        assert_eq!(
+            swarm.glowworms[0].rotation,
+            Quaternion::new(0.0, 0.0, 0.0, 1.0)
+        );
+    }
+
+    #[test]
+    fn test_corrupted_pose() {
+        let scoring: Box<dyn Score> = Box::new(Height);
+        // Same translation, the zero distance leads to a NaN translation step
+        let positions = vec![
+            vec![1.0, 2.0, 3.0, 1.0, 0.0, 0.0, 0.0],
+            vec![1.0, 2.0, 3.0, 0.0, 1.0, 0.0, 0.0],
+        ];
+        let mut rng: rand::prelude::StdRng = SeedableRng::seed_from_u64(0);
+        let mut swarm = Swarm::new();
+        swarm.add_glowworms(&positions, &scoring, false, 0, 0);
+        swarm.update_luciferin();
+        swarm.glowworms[1].luciferin += 1.0;
+        swarm.movement_phase(&mut rng, 0.0);
+        let glowworm = &swarm.glowworms[0];
+        assert!(glowworm.moved);
+        assert_eq!(glowworm.translation, vec![1.0, 2.0, 3.0]);
+        // The rotation still moves towards the neighbor
+        assert!(glowworm.rotation.x > 0.0);
+    }
+
+    #[test]
+    fn test_translation_step() {
+        let scoring: Box<dyn Score> = Box::new(Height);
+        let positions = vec![
+            vec![0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 0.0, 0.0],
+            vec![0.0, 0.0, 2.6, 1.0, 0.0, 0.0, 0.0, 0.0, 0.0],
+        ];
+        let mut rng: rand::prelude::StdRng = SeedableRng::seed_from_u64(0);
+        let mut swarm = Swarm::new();
+        swarm.add_glowworms(&positions, &scoring, true, 0, 2);
+        swarm.glowworms[0].translation_step = 0.25;
+        swarm.glowworms[0].vision_range = 3.0;
+        swarm.update_luciferin();
+        swarm.movement_phase(&mut rng, 0.0);
+        assert!((swarm.glowworms[0].translation[2] - 0.25).abs() < 1e-12);
+        // Equal extents stay as they are
+        assert_eq!(swarm.glowworms[0].lig_nmodes, vec![0.0, 0.0]);
+    }
+
+    #[test]
+    fn test_second_ligand() {
+        struct Heights;
+        impl Score for Heights {
+            fn energy(&self, translation: &[f64], _: &Quaternion, _: &[f64], _: &[f64]) -> f64 {
+                translation[2]
+            }
+            fn second_ligand_energy(
+                &self,
+                translation: &[f64],
+                _: &Quaternion,
+                _: &[f64],
+                _: &[f64],
+                second: &LigandPose,
+            ) -> Option<f64> {
+                Some(translation[2] + second.translation[2])
+            }
+        }
+        let scoring: Box<dyn Score> = Box::new(Heights);
+        let positions = vec![
+            vec![
+                0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0,
+            ],
+            vec![
+                0.0, 0.0, 1.0, 1.0, 0.0, 0.0, 0.0, 0.0, 0.0, 2.0, 1.0, 0.0, 0.0, 0.0,
+            ],
+        ];
+        let mut rng: rand::prelude::StdRng = SeedableRng::seed_from_u64(0);
+        let mut swarm = Swarm::new();
+        swarm.add_glowworms(&positions, &scoring, false, 0, 0);
+        swarm.glowworms[0].vision_range = 3.0;
+        swarm.update_luciferin();
+        assert_eq!(swarm.glowworms[1].scoring, 3.0);
+        swarm.movement_phase(&mut rng, 0.0);
+        // Both ligands move towards the ones of the brighter glowworm
+        assert_eq!(swarm.glowworms[0].translation[2], 0.5);
+        let second = swarm.glowworms[0].second_ligand.as_ref().unwrap();
+        assert_eq!(second.translation[2], 0.5);
+
+        let mut output: Vec<u8> = Vec::new();
+        swarm.write_output(1, &mut output).unwrap();
+        let output = String::from_utf8(output).unwrap();
+        let line = output.lines().nth(1).unwrap();
+        assert!(line.starts_with("(0.0000000, 0.0000000, 0.5000000, 1.0000000, 0.0000000, 0.0000000, 0.0000000, 0.0000000, 0.0000000, 0.5000000,"));
+
+        // Orientations also as rotation matrices, read back as the same poses
+        swarm.rotation_output = Some(RotationOutput::Matrix);
+        let mut output: Vec<u8> = Vec::new();
+        swarm.write_output(1, &mut output).unwrap();
+        let output = String::from_utf8(output).unwrap();
+        assert!(output
+            .lines()
+            .next()
+            .unwrap()
+            .ends_with("Z-score  Rotation matrix"));
+        let line = output.lines().nth(1).unwrap();
+        assert!(line.ends_with(" 1.0000000 0.0000000 0.0000000 0.0000000 1.0000000 0.0000000 0.0000000 0.0000000 1.0000000"));
+        let entries = parse_gso_output(&output).unwrap().entries;
+        assert_eq!(entries[0].position[2], 0.5);
+        assert!(entries[0].z_score.is_some());
+        assert_eq!(entries[0].stagnation, None);
+    }
+
+    #[test]
+    fn test_noise_schedule() {
+        let schedule = NoiseSchedule::new(2.0, 0.5);
+        assert_eq!(schedule.level(1), 2.0);
+        assert_eq!(schedule.level(3), 0.5);
+        assert_eq!(NoiseSchedule::new(2.0, 1.0).level(100), 2.0);
+    }
+
+    #[test]
+    fn test_swarm_seed() {
+        // Reproducible, different for every swarm and setup seed
+        assert_eq!(swarm_seed(324_324, 3), swarm_seed(324_324, 3));
+        assert_ne!(swarm_seed(324_324, 0), swarm_seed(324_324, 1));
+        assert_ne!(swarm_seed(324_324, 0), swarm_seed(324_325, 0));
+        assert_ne!(swarm_seed(0, 0), 0);
+
+        let mut swarm = Swarm::new();
+        swarm.versioned_output = true;
+        swarm.seed = Some(swarm_seed(324_324, 3));
+        let mut output: Vec<u8> = Vec::new();
+        swarm.write_output(1, &mut output).unwrap();
+        let output = parse_gso_output(&String::from_utf8(output).unwrap()).unwrap();
+        assert_eq!(output.metadata["Seed"], swarm_seed(324_324, 3).to_string());
+    }
+}
@@ -0,0 +1,239 @@
+use super::constants::REJECTED_POSE_SCORE;
+use super::decomposition::ResidueEnergies;
+use super::qt::Quaternion;
+use super::scoring::{Interface, Score};
+use super::ternary::LigandPose;
+use std::f64::consts::PI;
+
+// Docking of homo-oligomeric rings with Cn symmetry. Receptor and ligand are the same subunit and
+// the pose of the ligand is the transformation from one subunit to the next one in the ring. Only
+// rotations of 2 pi / n around an axis, combined with translations perpendicular to it, close the
+// ring after n subunits, so poses are projected onto those before scoring. The score is the one of
+// a subunit against all its symmetry mates.
+
+// Closest pose generating a ring of the given order: same rotation axis with an angle of
+// 2 pi / order and no translation along the axis
+pub fn cn_pose(translation: &[f64], rotation: &Quaternion, order: usize) -> (Vec<f64>, Quaternion) {
+    let mut rotation = *rotation;
+    rotation.normalize();
+    if rotation.w < 0.0 {
+        rotation = -rotation;
+    }
+    let sine = (rotation.x * rotation.x + rotation.y * rotation.y + rotation.z * rotation.z).sqrt();
+    let axis = if sine > 1e-9 {
+        [rotation.x / sine, rotation.y / sine, rotation.z / sine]
+    } else {
+        // No rotation, any axis perpendicular to the translation
+        let candidate = if translation[0].abs() < translation[1].abs().max(translation[2].abs()) {
+            [1.0, 0.0, 0.0]
+        } else {
+            [0.0, 0.0, 1.0]
+        };
+        let along: f64 = (0..3).map(|k| candidate[k] * translation[k]).sum();
+        let norm2: f64 = translation.iter().map(|t| t * t).sum();
+        let mut axis = candidate;
+        if norm2 > 0.0 {
+            for k in 0..3 {
+                axis[k] -= along / norm2 * translation[k];
+            }
+        }
+        let norm = axis.iter().map(|a| a * a).sum::<f64>().sqrt();
+        [axis[0] / norm, axis[1] / norm, axis[2] / norm]
+    };
+    let half_angle = PI / order as f64;
+    let projected = Quaternion::new(
+        half_angle.cos(),
+        half_angle.sin() * axis[0],
+        half_angle.sin() * axis[1],
+        half_angle.sin() * axis[2],
+    );
+    let along: f64 = (0..3).map(|k| axis[k] * translation[k]).sum();
+    let translation: Vec<f64> = (0..3).map(|k| translation[k] - along * axis[k]).collect();
+    (translation, projected)
+}
+
+// Poses of the symmetry mates 1 to order - 1 with respect to the subunit, the successive powers of
+// the pose
+pub fn mate_poses(
+    translation: &[f64],
+    rotation: &Quaternion,
+    order: usize,
+) -> Vec<(Vec<f64>, Quaternion)> {
+    let mut poses: Vec<(Vec<f64>, Quaternion)> = Vec::new();
+    let mut current = (translation.to_vec(), *rotation);
+    for _ in 1..order {
+        poses.push(current.clone());
+        let rotated = rotation.rotate_point(&[current.0[0], current.0[1], current.0[2]]);
+        current = (
+            (0..3).map(|k| rotated[k] + translation[k]).collect(),
+            *rotation * current.1,
+        );
+    }
+    poses
+}
+
+pub struct Symmetric {
+    pub scoring: Box<dyn Score>,
+    pub order: usize,
+}
+
+impl Symmetric {
+    pub fn new(scoring: Box<dyn Score>, order: usize) -> Box<dyn Score> {
+        Box::new(Symmetric { scoring, order })
+    }
+}
+
+impl Score for Symmetric {
+    fn energy(
+        &self,
+        translation: &[f64],
+        rotation: &Quaternion,
+        rec_nmodes: &[f64],
+        lig_nmodes: &[f64],
+    ) -> f64 {
+        let (translation, rotation) = cn_pose(translation, rotation, self.order);
+        let mut total = 0.0;
+        for (mate_translation, mate_rotation) in mate_poses(&translation, &rotation, self.order) {
+            let score =
+                self.scoring
+                    .energy(&mate_translation, &mate_rotation, rec_nmodes, lig_nmodes);
+            if score == REJECTED_POSE_SCORE {
+                return REJECTED_POSE_SCORE;
+            }
+            total += score;
+        }
+        total
+    }
+
+    // Contacts with the closest mate only, the one of the pose
+    fn residue_energies(
+        &self,
+        translation: &[f64],
+        rotation: &Quaternion,
+        rec_nmodes: &[f64],
+        lig_nmodes: &[f64],
+    ) -> Option<ResidueEnergies> {
+        let (translation, rotation) = cn_pose(translation, rotation, self.order);
+        self.scoring
+            .residue_energies(&translation, &rotation, rec_nmodes, lig_nmodes)
+    }
+
+    // Interface with the closest mate only, like the residue energies
+    fn energy_with_interface(
+        &self,
+        translation: &[f64],
+        rotation: &Quaternion,
+        rec_nmodes: &[f64],
+        lig_nmodes: &[f64],
+    ) -> (f64, Option<Interface>) {
+        let (translation, rotation) = cn_pose(translation, rotation, self.order);
+        let mut total = 0.0;
+        let mut closest: Option<Interface> = None;
+        for (i, (mate_translation, mate_rotation)) in
+            mate_poses(&translation, &rotation, self.order)
+                .iter()
+                .enumerate()
+        {
+            let score = if i == 0 {
+                let (score, interface) = self.scoring.energy_with_interface(
+                    mate_translation,
+                    mate_rotation,
+                    rec_nmodes,
+                    lig_nmodes,
+                );
+                closest = interface;
+                score
+            } else {
+                self.scoring
+                    .energy(mate_translation, mate_rotation, rec_nmodes, lig_nmodes)
+            };
+            if score == REJECTED_POSE_SCORE {
+                return (REJECTED_POSE_SCORE, None);
+            }
+            total += score;
+        }
+        (total, closest)
+    }
+
+    // Restraints with the closest mate only, like the residue energies
+    fn restraints_satisfaction(
+        &self,
+        translation: &[f64],
+        rotation: &Quaternion,
+        rec_nmodes: &[f64],
+        lig_nmodes: &[f64],
+    ) -> Option<f64> {
+        let (translation, rotation) = cn_pose(translation, rotation, self.order);
+        self.scoring
+            .restraints_satisfaction(&translation, &rotation, rec_nmodes, lig_nmodes)
+    }
+
+    // The whole ring, plus the terms of the second ligand with the closest mate only
+    fn second_ligand_energy(
+        &self,
+        translation: &[f64],
+        rotation: &Quaternion,
+        rec_nmodes: &[f64],
+        lig_nmodes: &[f64],
+        second: &LigandPose,
+    ) -> Option<f64> {
+        let ring = self.energy(translation, rotation, rec_nmodes, lig_nmodes);
+        let (translation, rotation) = cn_pose(translation, rotation, self.order);
+        let with_second = self.scoring.second_ligand_energy(
+            &translation,
+            &rotation,
+            rec_nmodes,
+            lig_nmodes,
+            second,
+        )?;
+        if ring == REJECTED_POSE_SCORE || with_second == REJECTED_POSE_SCORE {
+            return Some(REJECTED_POSE_SCORE);
+        }
+        let closest = self
+            .scoring
+            .energy(&translation, &rotation, rec_nmodes, lig_nmodes);
+        Some(ring + with_second - closest)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Score of a pose given by the distance between the centers of the subunits
+    struct Distance;
+
+    impl Score for Distance {
+        fn energy(&self, translation: &[f64], _: &Quaternion, _: &[f64], _: &[f64]) -> f64 {
+            -translation.iter().map(|t| t * t).sum::<f64>().sqrt()
+        }
+    }
+
+    #[test]
+    fn test_cn_pose() {
+        // Rotation of 100 degrees around z projected onto a C4 ring
+        let angle = 100.0_f64.to_radians();
+        let rotation = Quaternion::new((angle / 2.0).cos(), 0.0, 0.0, (angle / 2.0).sin());
+        let (translation, projected) = cn_pose(&[2.0, 0.0, 3.0], &rotation, 4);
+        assert_eq!(translation, vec![2.0, 0.0, 0.0]);
+        let expected = Quaternion::new((PI / 4.0).cos(), 0.0, 0.0, (PI / 4.0).sin());
+        assert!(projected == expected);
+
+        // The ring closes after 4 subunits
+        let mates = mate_poses(&translation, &projected, 5);
+        let (closing_translation, closing_rotation) = &mates[3];
+        assert!(closing_translation.iter().all(|t| t.abs() < 1e-9));
+        assert!((closing_rotation.w.abs() - 1.0).abs() < 1e-9);
+
+        // Mates at distances 2, 2 * sqrt(2) and 2
+        let scoring = Symmetric::new(Box::new(Distance), 4);
+        let energy = scoring.energy(&[2.0, 0.0, 3.0], &rotation, &[], &[]);
+        assert!((energy + 4.0 + 8.0_f64.sqrt()).abs() < 1e-9);
+
+        // Without rotation, an axis perpendicular to the translation
+        let (translation, projected) = cn_pose(&[0.0, 0.0, 5.0], &Quaternion::default(), 2);
+        assert_eq!(translation, vec![0.0, 0.0, 5.0]);
+        assert!(projected.w.abs() < 1e-12);
+        assert!(projected.z.abs() < 1e-12);
+    }
+}
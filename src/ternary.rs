@@ -0,0 +1,257 @@
+use super::constants::REJECTED_POSE_SCORE;
+use super::qt::Quaternion;
+use super::scoring::{Interface, Score};
+use rand::Rng;
+
+// Docking of two ligands against one receptor, to model ternary complexes. Every glowworm carries
+// a pose for each ligand and the score is the sum of the three pairwise interactions: receptor
+// and first ligand, receptor and second ligand, and both ligands. The interaction between the
+// ligands is scored with the first ligand as the receptor of the pair, at the pose of the second
+// ligand relative to the first one. Both ligands are rigid.
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct LigandPose {
+    pub translation: Vec<f64>,
+    pub rotation: Quaternion,
+}
+
+impl LigandPose {
+    pub fn new(translation: Vec<f64>, rotation: Quaternion) -> Self {
+        LigandPose {
+            translation,
+            rotation,
+        }
+    }
+
+    // Pose parameters as written in the GSO output, translation and quaternion
+    pub fn to_vec(&self) -> Vec<f64> {
+        vec![
+            self.translation[0],
+            self.translation[1],
+            self.translation[2],
+            self.rotation.w,
+            self.rotation.x,
+            self.rotation.y,
+            self.rotation.z,
+        ]
+    }
+
+    pub fn from_slice(values: &[f64]) -> Self {
+        LigandPose::new(
+            values[0..3].to_vec(),
+            Quaternion::new(values[3], values[4], values[5], values[6]),
+        )
+    }
+}
+
+// Random starting pose of the second ligand at the given distance of the first one
+pub fn place_second_ligand(
+    translation: &[f64],
+    distance: f64,
+    rng: &mut rand::prelude::StdRng,
+) -> LigandPose {
+    // Uniform direction from a point inside the unit sphere
+    let direction = loop {
+        let point: Vec<f64> = (0..3).map(|_| rng.gen_range(-1.0, 1.0)).collect();
+        let norm = point.iter().map(|x| x * x).sum::<f64>().sqrt();
+        if norm > 1e-6 && norm <= 1.0 {
+            break point.iter().map(|x| x / norm).collect::<Vec<f64>>();
+        }
+    };
+    LigandPose::new(
+        (0..3)
+            .map(|k| translation[k] + distance * direction[k])
+            .collect(),
+        Quaternion::random(rng),
+    )
+}
+
+// Pose of the second ligand in the frame of the first one, where the first ligand is at the
+// origin without rotation
+pub fn relative_pose(
+    first_translation: &[f64],
+    first_rotation: &Quaternion,
+    second: &LigandPose,
+) -> LigandPose {
+    let inverse = first_rotation.inverse();
+    let offset = [
+        second.translation[0] - first_translation[0],
+        second.translation[1] - first_translation[1],
+        second.translation[2] - first_translation[2],
+    ];
+    let translation = inverse.rotate_point(&offset);
+    LigandPose::new(translation.to_vec(), inverse * second.rotation)
+}
+
+pub struct Ternary {
+    // Receptor and first ligand, receptor and second ligand, first and second ligands
+    pub first: Box<dyn Score>,
+    pub second: Box<dyn Score>,
+    pub ligands: Box<dyn Score>,
+}
+
+impl Ternary {
+    pub fn new(
+        first: Box<dyn Score>,
+        second: Box<dyn Score>,
+        ligands: Box<dyn Score>,
+    ) -> Box<dyn Score> {
+        Box::new(Ternary {
+            first,
+            second,
+            ligands,
+        })
+    }
+}
+
+impl Score for Ternary {
+    // Without a second ligand pose, only the first ligand is scored
+    fn energy(
+        &self,
+        translation: &[f64],
+        rotation: &Quaternion,
+        rec_nmodes: &[f64],
+        lig_nmodes: &[f64],
+    ) -> f64 {
+        self.first
+            .energy(translation, rotation, rec_nmodes, lig_nmodes)
+    }
+
+    // Without a second ligand pose, only the first ligand is scored
+    fn energy_with_interface(
+        &self,
+        translation: &[f64],
+        rotation: &Quaternion,
+        rec_nmodes: &[f64],
+        lig_nmodes: &[f64],
+    ) -> (f64, Option<Interface>) {
+        self.first
+            .energy_with_interface(translation, rotation, rec_nmodes, lig_nmodes)
+    }
+
+    // Restraints only apply to the receptor and the first ligand
+    fn restraints_satisfaction(
+        &self,
+        translation: &[f64],
+        rotation: &Quaternion,
+        rec_nmodes: &[f64],
+        lig_nmodes: &[f64],
+    ) -> Option<f64> {
+        self.first
+            .restraints_satisfaction(translation, rotation, rec_nmodes, lig_nmodes)
+    }
+
+    fn second_ligand_energy(
+        &self,
+        translation: &[f64],
+        rotation: &Quaternion,
+        rec_nmodes: &[f64],
+        lig_nmodes: &[f64],
+        second: &LigandPose,
+    ) -> Option<f64> {
+        let first = self
+            .first
+            .energy(translation, rotation, rec_nmodes, lig_nmodes);
+        if first == REJECTED_POSE_SCORE {
+            return Some(REJECTED_POSE_SCORE);
+        }
+        let receptor_second = self
+            .second
+            .energy(&second.translation, &second.rotation, &[], &[]);
+        if receptor_second == REJECTED_POSE_SCORE {
+            return Some(REJECTED_POSE_SCORE);
+        }
+        let relative = relative_pose(translation, rotation, second);
+        let ligands = self
+            .ligands
+            .energy(&relative.translation, &relative.rotation, &[], &[]);
+        if ligands == REJECTED_POSE_SCORE {
+            return Some(REJECTED_POSE_SCORE);
+        }
+        Some(first + receptor_second + ligands)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scoring::{Calibrated, Calibration, Memoized};
+
+    // Score of a pose given by the distance of a point of the ligand to a point of the receptor
+    struct Contact {
+        receptor_point: [f64; 3],
+        ligand_point: [f64; 3],
+    }
+
+    impl Score for Contact {
+        fn energy(&self, translation: &[f64], rotation: &Quaternion, _: &[f64], _: &[f64]) -> f64 {
+            let point = rotation.rotate_point(&self.ligand_point);
+            -(0..3)
+                .map(|k| (point[k] + translation[k] - self.receptor_point[k]).powi(2))
+                .sum::<f64>()
+        }
+    }
+
+    #[test]
+    fn test_ternary_energy() {
+        let rotation = Quaternion::new(0.0, 0.0, 0.0, 1.0);
+        let second = LigandPose::new(vec![3.0, 4.0, 5.0], rotation);
+        assert_eq!(LigandPose::from_slice(&second.to_vec()), second);
+
+        // Second ligand at (2, 4, 5) with respect to the first one, rotated by pi around z
+        let relative = relative_pose(&[1.0, 0.0, 0.0], &rotation, &second);
+        for (value, expected) in relative.translation.iter().zip([-2.0, -4.0, 5.0]) {
+            assert!((value - expected).abs() < 1e-12);
+        }
+        assert!((relative.rotation.w.abs() - 1.0).abs() < 1e-12);
+
+        let scoring = Ternary::new(
+            Box::new(Contact {
+                receptor_point: [1.0, 0.0, 0.0],
+                ligand_point: [0.0; 3],
+            }),
+            Box::new(Contact {
+                receptor_point: [3.0, 4.0, 6.0],
+                ligand_point: [0.0; 3],
+            }),
+            // Second ligand touching the point (-2, -4, 5) of the first one
+            Box::new(Contact {
+                receptor_point: [-2.0, -4.0, 5.0],
+                ligand_point: [0.0; 3],
+            }),
+        );
+        assert_eq!(scoring.energy(&[1.0, 0.0, 0.0], &rotation, &[], &[]), 0.0);
+        let energy = scoring
+            .second_ligand_energy(&[1.0, 0.0, 0.0], &rotation, &[], &[], &second)
+            .unwrap();
+        assert!((energy + 1.0).abs() < 1e-12);
+
+        // Forwarded by the wrappers, not supported by the scoring of a pair of partners
+        let calibration = Calibration {
+            scale: 2.0,
+            offset: 1.0,
+        };
+        let scoring = Memoized::new(Calibrated::new(scoring, calibration), 10, 0.1, 0.001);
+        let energy = scoring
+            .second_ligand_energy(&[1.0, 0.0, 0.0], &rotation, &[], &[], &second)
+            .unwrap();
+        assert!((energy + 1.0).abs() < 1e-12);
+        let pair = Contact {
+            receptor_point: [1.0, 0.0, 0.0],
+            ligand_point: [0.0; 3],
+        };
+        assert_eq!(
+            pair.second_ligand_energy(&[1.0, 0.0, 0.0], &rotation, &[], &[], &second),
+            None
+        );
+
+        use rand::SeedableRng;
+        let mut rng: rand::prelude::StdRng = SeedableRng::seed_from_u64(0);
+        let placed = place_second_ligand(&[1.0, 2.0, 3.0], 10.0, &mut rng);
+        let distance = (0..3)
+            .map(|k| (placed.translation[k] - [1.0, 2.0, 3.0][k]).powi(2))
+            .sum::<f64>()
+            .sqrt();
+        assert!((distance - 10.0).abs() < 1e-9);
+    }
+}
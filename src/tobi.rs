@@ -0,0 +1,742 @@
+use super::constants::REJECTED_POSE_SCORE;
+use super::data::find_data_file;
+use super::decomposition::{anm_extents, pose_coordinates, push_residue, ResidueEnergies};
+use super::policy::{PolicyReport, ResidueAction, ResiduePolicy};
+use super::pose::apply_nmodes;
+use super::profile::{add_pose_time, pose_timer};
+use super::qt::Quaternion;
+use super::registry::ScoringInput;
+use super::scoring::{
+    hotspot_bias, in_interface, interface_residues, restraints_satisfaction, CellList, ClashFilter,
+    Interface, RestraintsMetric, Score, Workspace,
+};
+use super::structure::is_hydrogen;
+use log::info;
+use pdbtbx::PDB;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::error::Error;
+use std::fs;
+
+// TOBI coarse-grained docking potential. Every residue is reduced to its backbone N and O and the
+// centroid of its side chain heavy atoms (the CA for glycine), and the energy of a pair of beads
+// is the one of their types in the distance bin of their distance. With about three beads per
+// residue instead of eight heavy atoms, poses of very large complexes are scored much faster than
+// with DFIRE. The potential is not distributed with lightdock-rust and energies have not been
+// checked against the TOBI of the Python LightDock yet, so there is no golden energy in the unit
+// tests nor in selftest. It is read from the data file tobi.dat (see data::find_data_file), a
+// text file of lightdock-rust, not the format of the Python data, with the bead types, the upper
+// distance limits of the bins in Angstroms and a square matrix of energies per bin, a row per
+// type in the order of the types:
+//
+//   types CYS MET PHE ILE LEU VAL TRP TYR ALA GLY THR SER ASN GLN ASP GLU HIS ARG LYS PRO N O
+//   bins 4.0 6.0
+//   -1.46 -0.63 ...
+//
+// Side chains are typed by their residue name and the backbone beads as N and O, residues whose
+// name is not a type are handled by the residue policy. The score of a pose is the opposite of
+// its TOBI energy, biased by the restraints and hot-spots.
+
+pub const TOBI_DATA_FILE: &str = "tobi.dat";
+
+// Atoms of the backbone not in the side chain centroid, N and O are beads of their own
+const BACKBONE: [&str; 5] = ["N", "CA", "C", "O", "OXT"];
+
+#[derive(Debug, Clone, Default)]
+pub struct TOBIPotential {
+    pub types: HashMap<String, usize>,
+    // Squared upper distance limit of every bin
+    pub bins: Vec<f64>,
+    // (bin, type, type) -> 1d
+    pub energies: Vec<f64>,
+}
+
+impl TOBIPotential {
+    pub fn parse(contents: &str) -> Result<TOBIPotential, Box<dyn Error>> {
+        let mut potential = TOBIPotential::default();
+        let mut rows: Vec<Vec<f64>> = Vec::new();
+        for (index, line) in contents.lines().enumerate() {
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            if fields.is_empty() || fields[0].starts_with('#') {
+                continue;
+            }
+            match fields[0] {
+                "types" => {
+                    for (i, name) in fields[1..].iter().enumerate() {
+                        potential.types.insert(name.to_string(), i);
+                    }
+                }
+                "bins" => {
+                    let mut previous = 0.0;
+                    for value in fields[1..].iter() {
+                        let limit = match value.parse::<f64>() {
+                            Ok(limit) if limit > previous => limit,
+                            _ => {
+                                return Err(format!(
+                                    "wrong distance bin {} in line {}, limits must increase",
+                                    value,
+                                    index + 1
+                                )
+                                .into())
+                            }
+                        };
+                        potential.bins.push(limit * limit);
+                        previous = limit;
+                    }
+                }
+                _ => {
+                    let row: Result<Vec<f64>, _> =
+                        fields.iter().map(|v| v.parse::<f64>()).collect();
+                    match row {
+                        Ok(row) => rows.push(row),
+                        Err(e) => {
+                            return Err(format!("wrong energy in line {}: {}", index + 1, e).into())
+                        }
+                    }
+                }
+            }
+        }
+        let num_types = potential.types.len();
+        if num_types == 0 || potential.bins.is_empty() {
+            return Err("types and distance bins not found".into());
+        }
+        if rows.len() != num_types * potential.bins.len()
+            || rows.iter().any(|row| row.len() != num_types)
+        {
+            return Err(format!(
+                "expected {} rows of {} energies, one matrix per distance bin",
+                num_types * potential.bins.len(),
+                num_types
+            )
+            .into());
+        }
+        potential.energies = rows.concat();
+        Ok(potential)
+    }
+
+    pub fn load() -> Result<TOBIPotential, Box<dyn Error>> {
+        let path = find_data_file(TOBI_DATA_FILE)?;
+        let potential = TOBIPotential::parse(&fs::read_to_string(&path)?)
+            .map_err(|e| format!("{}: {}", path.display(), e))?;
+        info!(
+            "TOBI potential of {} bead types and {} distance bins",
+            potential.types.len(),
+            potential.bins.len()
+        );
+        Ok(potential)
+    }
+
+    pub fn bead_type(&self, name: &str) -> Option<usize> {
+        self.types.get(name).copied()
+    }
+
+    pub fn supports_residue(&self, residue_name: &str) -> bool {
+        residue_name != "N" && residue_name != "O" && self.types.contains_key(residue_name)
+    }
+
+    // Upper distance limit of the last bin, pairs farther apart have no energy
+    pub fn cutoff(&self) -> f64 {
+        self.bins[self.bins.len() - 1].sqrt()
+    }
+
+    // Distance bin of a pair of beads from their squared distance, None beyond the last one
+    #[inline]
+    pub fn distance_bin(&self, distance2: f64) -> Option<usize> {
+        self.bins.iter().position(|&limit| distance2 <= limit)
+    }
+
+    #[inline]
+    pub fn energy(&self, first: usize, second: usize, bin: usize) -> f64 {
+        let num_types = self.types.len();
+        self.energies[(bin * num_types + first) * num_types + second]
+    }
+}
+
+pub struct TOBIDockingModel {
+    // Type of every bead
+    pub atoms: Vec<usize>,
+    pub coordinates: Vec<[f64; 3]>,
+    // Every bead is a heavy atom for the clash filter
+    pub heavy_atoms: Vec<usize>,
+    pub active_restraints: HashMap<String, Vec<usize>>,
+    pub passive_restraints: HashMap<String, Vec<usize>>,
+    pub hotspots: HashMap<String, Vec<usize>>,
+    pub hotspot_weights: HashMap<String, f64>,
+    // Scoring weight of every bead, the one of its residue or 1.0
+    pub weights: Vec<f64>,
+    pub num_anm: usize,
+    // Normal modes of the beads, the mean of the ones of their atoms
+    pub nmodes: Vec<f64>,
+    // Residue IDs and the residue of every bead, see decomposition::push_residue
+    pub residues: Vec<String>,
+    pub atom_residues: Vec<usize>,
+}
+
+impl<'a> TOBIDockingModel {
+    pub fn new(
+        structure: &'a PDB,
+        active_restraints: &'a [String],
+        passive_restraints: &'a [String],
+        hotspots: &'a HashMap<String, f64>,
+        weights: &'a HashMap<String, f64>,
+        nmodes: &[f64],
+        num_anm: usize,
+        potential: &TOBIPotential,
+        policy: ResiduePolicy,
+    ) -> TOBIDockingModel {
+        let mut model = TOBIDockingModel {
+            atoms: Vec::new(),
+            coordinates: Vec::new(),
+            heavy_atoms: Vec::new(),
+            active_restraints: HashMap::new(),
+            passive_restraints: HashMap::new(),
+            hotspots: HashMap::new(),
+            hotspot_weights: hotspots.clone(),
+            weights: Vec::new(),
+            nmodes: Vec::new(),
+            residues: Vec::new(),
+            atom_residues: Vec::new(),
+            num_anm,
+        };
+
+        // Indexes in the full structure of the atoms of every bead
+        let mut members: Vec<Vec<usize>> = Vec::new();
+        let mut structure_index: usize = 0;
+        let mut report = PolicyReport::default();
+        for chain in structure.chains() {
+            for residue in chain.residues() {
+                let res_name = match residue.name() {
+                    Some(name) => name,
+                    None => panic!("PDB Parsing Error: Residue name error"),
+                };
+                let mut res_id = format!("{}.{}.{}", chain.id(), res_name, residue.serial_number());
+                if let Some(c) = residue.insertion_code() {
+                    res_id.push_str(c);
+                }
+                let action = policy.action(
+                    "TOBI",
+                    &res_id,
+                    res_name,
+                    potential.supports_residue(res_name),
+                    |name| potential.supports_residue(name),
+                    &mut report,
+                );
+                if action == ResidueAction::Drop {
+                    structure_index += residue.atom_count();
+                    continue;
+                }
+
+                // Beads of the residue: backbone N and O and the side chain centroid
+                let mut beads: Vec<(usize, Vec<usize>)> = Vec::new();
+                let mut side_chain: Vec<usize> = Vec::new();
+                let mut alpha_carbon: Option<usize> = None;
+                let mut scored_residue = res_name;
+                for atom in residue.atoms() {
+                    structure_index += 1;
+                    let (scored_name, atom_name) = action.names(res_name, atom.name().trim());
+                    scored_residue = scored_name;
+                    if is_hydrogen(residue, atom) {
+                        continue;
+                    }
+                    match atom_name {
+                        "N" | "O" => {
+                            if let Some(bead_type) = potential.bead_type(atom_name) {
+                                beads.push((bead_type, vec![structure_index - 1]));
+                            }
+                        }
+                        "CA" => alpha_carbon = Some(structure_index - 1),
+                        name if BACKBONE.contains(&name) => {}
+                        _ => side_chain.push(structure_index - 1),
+                    }
+                }
+                if side_chain.is_empty() {
+                    side_chain.extend(alpha_carbon);
+                }
+                if let Some(bead_type) = potential.bead_type(scored_residue) {
+                    if !side_chain.is_empty() {
+                        beads.push((bead_type, side_chain));
+                    }
+                }
+
+                let atoms: Vec<&pdbtbx::Atom> = residue.atoms().collect();
+                let first_index = structure_index - atoms.len();
+                for (bead_type, bead_members) in beads {
+                    let bead_index = model.atoms.len();
+                    if active_restraints.contains(&res_id) {
+                        model
+                            .active_restraints
+                            .entry(res_id.to_string())
+                            .or_default()
+                            .push(bead_index);
+                    }
+                    if passive_restraints.contains(&res_id) {
+                        model
+                            .passive_restraints
+                            .entry(res_id.to_string())
+                            .or_default()
+                            .push(bead_index);
+                    }
+                    if hotspots.contains_key(&res_id) {
+                        model
+                            .hotspots
+                            .entry(res_id.to_string())
+                            .or_default()
+                            .push(bead_index);
+                    }
+                    let mut center = [0.0; 3];
+                    for &member in bead_members.iter() {
+                        let atom = atoms[member - first_index];
+                        center[0] += atom.x();
+                        center[1] += atom.y();
+                        center[2] += atom.z();
+                    }
+                    for value in center.iter_mut() {
+                        *value /= bead_members.len() as f64;
+                    }
+
+                    model.heavy_atoms.push(bead_index);
+                    model.atoms.push(bead_type);
+                    model.coordinates.push(center);
+                    push_residue(&mut model.residues, &mut model.atom_residues, &res_id);
+                    model
+                        .weights
+                        .push(weights.get(&res_id).copied().unwrap_or(1.0));
+                    members.push(bead_members);
+                }
+            }
+        }
+        report.log("TOBI");
+
+        // Normal modes are stored as (num_anm, num_atoms, 3), a bead moves as the mean of its
+        // atoms
+        if num_anm > 0 && !nmodes.is_empty() {
+            model.nmodes = vec![0.0; num_anm * members.len() * 3];
+            for i_nm in 0..num_anm {
+                for (bead, bead_members) in members.iter().enumerate() {
+                    for &member in bead_members.iter() {
+                        for k in 0..3 {
+                            model.nmodes[(i_nm * members.len() + bead) * 3 + k] += nmodes
+                                [(i_nm * structure_index + member) * 3 + k]
+                                / bead_members.len() as f64;
+                        }
+                    }
+                }
+            }
+        }
+        model
+    }
+}
+
+pub struct TOBI {
+    pub potential: TOBIPotential,
+    pub receptor: TOBIDockingModel,
+    pub ligand: TOBIDockingModel,
+    pub use_anm: bool,
+    pub clash_filter: Option<ClashFilter>,
+    pub restraints_metric: RestraintsMetric,
+    pub workspace: RefCell<Workspace>,
+}
+
+impl<'a> TOBI {
+    pub fn new(input: ScoringInput) -> Result<Box<dyn Score + 'a>, Box<dyn Error>> {
+        let ScoringInput {
+            receptor,
+            rec_active_restraints,
+            rec_passive_restraints,
+            rec_hotspots,
+            rec_weights,
+            rec_nmodes,
+            rec_num_anm,
+            ligand,
+            lig_active_restraints,
+            lig_passive_restraints,
+            lig_hotspots,
+            lig_weights,
+            lig_nmodes,
+            lig_num_anm,
+            use_anm,
+            clash_filter,
+            restraints_metric,
+            policy,
+        } = input;
+        let potential =
+            TOBIPotential::load().map_err(|e| format!("Unable to load TOBI potential: {}", e))?;
+        let receptor = TOBIDockingModel::new(
+            &receptor,
+            &rec_active_restraints,
+            &rec_passive_restraints,
+            &rec_hotspots,
+            &rec_weights,
+            &rec_nmodes,
+            rec_num_anm,
+            &potential,
+            policy,
+        );
+        let ligand = TOBIDockingModel::new(
+            &ligand,
+            &lig_active_restraints,
+            &lig_passive_restraints,
+            &lig_hotspots,
+            &lig_weights,
+            &lig_nmodes,
+            lig_num_anm,
+            &potential,
+            policy,
+        );
+        info!(
+            "TOBI beads: {} receptor, {} ligand",
+            receptor.coordinates.len(),
+            ligand.coordinates.len()
+        );
+        Ok(Box::new(TOBI::from_models(
+            potential,
+            receptor,
+            ligand,
+            use_anm,
+            clash_filter,
+            restraints_metric,
+        )))
+    }
+
+    pub fn from_models(
+        potential: TOBIPotential,
+        receptor: TOBIDockingModel,
+        ligand: TOBIDockingModel,
+        use_anm: bool,
+        clash_filter: Option<ClashFilter>,
+        restraints_metric: RestraintsMetric,
+    ) -> TOBI {
+        let mut workspace = Workspace::new(&receptor.coordinates, &ligand.coordinates);
+        workspace.cell_list = Some(CellList::new(&receptor.coordinates, potential.cutoff()));
+        if let Some(filter) = &clash_filter {
+            workspace.clash_grid = filter.grid(&receptor.coordinates, &receptor.heavy_atoms);
+        }
+        TOBI {
+            potential,
+            receptor,
+            ligand,
+            use_anm,
+            clash_filter,
+            restraints_metric,
+            workspace: RefCell::new(workspace),
+        }
+    }
+
+    // Score of the pose biased by its satisfied restraints and hot-spots
+    fn biased_score(
+        &self,
+        score: f64,
+        receptor_coordinates: &[[f64; 3]],
+        ligand_coordinates: &[[f64; 3]],
+        interface_receptor: &[usize],
+        interface_ligand: &[usize],
+    ) -> f64 {
+        let perc_receptor_restraints: f64 = self.restraints_metric.satisfaction(
+            interface_receptor,
+            &self.receptor.active_restraints,
+            receptor_coordinates,
+            ligand_coordinates,
+        );
+        let perc_ligand_restraints: f64 = self.restraints_metric.satisfaction(
+            interface_ligand,
+            &self.ligand.active_restraints,
+            ligand_coordinates,
+            receptor_coordinates,
+        );
+        let receptor_hotspots: f64 = hotspot_bias(
+            interface_receptor,
+            &self.receptor.hotspots,
+            &self.receptor.hotspot_weights,
+        );
+        let ligand_hotspots: f64 = hotspot_bias(
+            interface_ligand,
+            &self.ligand.hotspots,
+            &self.ligand.hotspot_weights,
+        );
+        score
+            + perc_receptor_restraints * score
+            + perc_ligand_restraints * score
+            + receptor_hotspots * score
+            + ligand_hotspots * score
+    }
+}
+
+impl Score for TOBI {
+    fn energy(
+        &self,
+        translation: &[f64],
+        rotation: &Quaternion,
+        rec_nmodes: &[f64],
+        lig_nmodes: &[f64],
+    ) -> f64 {
+        let rec_extents = anm_extents(self.use_anm, self.receptor.num_anm, rec_nmodes);
+        let lig_extents = anm_extents(self.use_anm, self.ligand.num_anm, lig_nmodes);
+        // Start from the original coordinates in the preallocated buffers
+        let mut workspace = self.workspace.borrow_mut();
+        workspace.reset(
+            &self.receptor.coordinates,
+            &self.ligand.coordinates,
+            !rec_extents.is_empty(),
+        );
+        let Workspace {
+            receptor_coordinates,
+            ligand_coordinates,
+            interface_receptor,
+            interface_ligand,
+            cell_list,
+            clash_grid,
+        } = &mut *workspace;
+
+        let timer = pose_timer();
+        for coordinate in ligand_coordinates.iter_mut() {
+            let rotated_coordinate = rotation.rotate_point(coordinate);
+            for k in 0..3 {
+                coordinate[k] = rotated_coordinate[k] + translation[k];
+            }
+        }
+        apply_nmodes(ligand_coordinates, &self.ligand.nmodes, lig_extents);
+        apply_nmodes(receptor_coordinates, &self.receptor.nmodes, rec_extents);
+        add_pose_time(timer);
+
+        // Discard hopeless poses before the full evaluation
+        if let Some(filter) = &self.clash_filter {
+            // The grid is only hashed again if the receptor moved
+            if !rec_extents.is_empty() {
+                filter.update_grid(clash_grid, receptor_coordinates, &self.receptor.heavy_atoms);
+            }
+            if filter.is_clashing(
+                clash_grid,
+                receptor_coordinates,
+                ligand_coordinates,
+                &self.ligand.heavy_atoms,
+            ) {
+                return REJECTED_POSE_SCORE;
+            }
+        }
+
+        // Ligand beads around the cells of the receptor, hashed again if its beads were moved by
+        // the normal modes
+        let cell_list = cell_list.as_mut().expect("TOBI cell list not built");
+        if !rec_extents.is_empty() {
+            cell_list.update(receptor_coordinates);
+        }
+        cell_list.search(ligand_coordinates);
+
+        let mut energy = 0.0;
+        for (i, ra) in receptor_coordinates.iter().enumerate() {
+            let beada = self.receptor.atoms[i];
+            let weighta = self.receptor.weights[i];
+            let (neighbors, distances) = cell_list.distances(i, ra);
+            for (&j, &distance2) in neighbors.iter().zip(distances.iter()) {
+                if let Some(bin) = self.potential.distance_bin(distance2) {
+                    energy += self.potential.energy(beada, self.ligand.atoms[j], bin)
+                        * weighta
+                        * self.ligand.weights[j];
+                }
+                if in_interface(distance2) {
+                    interface_receptor[i] = 1;
+                    interface_ligand[j] = 1;
+                }
+            }
+        }
+
+        self.biased_score(
+            -energy,
+            receptor_coordinates,
+            ligand_coordinates,
+            interface_receptor,
+            interface_ligand,
+        )
+    }
+
+    fn energy_with_interface(
+        &self,
+        translation: &[f64],
+        rotation: &Quaternion,
+        rec_nmodes: &[f64],
+        lig_nmodes: &[f64],
+    ) -> (f64, Option<Interface>) {
+        let energy = self.energy(translation, rotation, rec_nmodes, lig_nmodes);
+        // The evaluation leaves the interface of the pose in the workspace
+        let workspace = self.workspace.borrow();
+        let interface = Interface {
+            receptor: interface_residues(
+                &workspace.interface_receptor,
+                &self.receptor.atom_residues,
+                &self.receptor.residues,
+            ),
+            ligand: interface_residues(
+                &workspace.interface_ligand,
+                &self.ligand.atom_residues,
+                &self.ligand.residues,
+            ),
+        };
+        (energy, Some(interface))
+    }
+
+    fn restraints_satisfaction(
+        &self,
+        translation: &[f64],
+        rotation: &Quaternion,
+        rec_nmodes: &[f64],
+        lig_nmodes: &[f64],
+    ) -> Option<f64> {
+        if self.receptor.active_restraints.is_empty() && self.ligand.active_restraints.is_empty() {
+            return None;
+        }
+        // The evaluation leaves the interface of the pose in the workspace
+        self.energy(translation, rotation, rec_nmodes, lig_nmodes);
+        let workspace = self.workspace.borrow();
+        restraints_satisfaction(
+            &workspace.interface_receptor,
+            &self.receptor.active_restraints,
+            &workspace.interface_ligand,
+            &self.ligand.active_restraints,
+        )
+    }
+
+    fn residue_energies(
+        &self,
+        translation: &[f64],
+        rotation: &Quaternion,
+        rec_nmodes: &[f64],
+        lig_nmodes: &[f64],
+    ) -> Option<ResidueEnergies> {
+        let (receptor_coordinates, ligand_coordinates) = pose_coordinates(
+            &self.receptor.coordinates,
+            &self.receptor.nmodes,
+            anm_extents(self.use_anm, self.receptor.num_anm, rec_nmodes),
+            &self.ligand.coordinates,
+            &self.ligand.nmodes,
+            anm_extents(self.use_anm, self.ligand.num_anm, lig_nmodes),
+            translation,
+            rotation,
+        );
+        let mut energies =
+            ResidueEnergies::new(&self.receptor.residues, &self.ligand.residues, 0.0);
+        for (i, ra) in receptor_coordinates.iter().enumerate() {
+            for (j, la) in ligand_coordinates.iter().enumerate() {
+                let distance2 = (ra[0] - la[0]) * (ra[0] - la[0])
+                    + (ra[1] - la[1]) * (ra[1] - la[1])
+                    + (ra[2] - la[2]) * (ra[2] - la[2]);
+                if let Some(bin) = self.potential.distance_bin(distance2) {
+                    let energy =
+                        self.potential
+                            .energy(self.receptor.atoms[i], self.ligand.atoms[j], bin);
+                    energies.add(
+                        self.receptor.atom_residues[i],
+                        self.ligand.atom_residues[j],
+                        -energy * self.receptor.weights[i] * self.ligand.weights[j],
+                    );
+                }
+            }
+        }
+        Some(energies)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::structure::parse_structure;
+
+    #[test]
+    fn test_tobi() {
+        let potential = TOBIPotential::parse(
+            "\
+# Test potential
+types GLY ALA N O
+bins 4.0 6.0
+-1.0 -2.0 -3.0 -4.0
+-2.0 -5.0 -6.0 -7.0
+-3.0 -6.0 -8.0 -9.0
+-4.0 -7.0 -9.0 -10.0
+-0.1 -0.2 -0.3 -0.4
+-0.2 -0.5 -0.6 -0.7
+-0.3 -0.6 -0.8 -0.9
+-0.4 -0.7 -0.9 -1.0
+",
+        )
+        .unwrap();
+        assert_eq!(potential.cutoff(), 6.0);
+        assert!(potential.supports_residue("ALA"));
+        assert!(!potential.supports_residue("N"));
+        assert_eq!(potential.distance_bin(16.0), Some(0));
+        assert_eq!(potential.distance_bin(20.0), Some(1));
+        assert_eq!(potential.distance_bin(36.1), None);
+        assert_eq!(potential.energy(1, 2, 1), -0.6);
+        let error = TOBIPotential::parse("types GLY\nbins 4.0\n1.0 2.0\n").unwrap_err();
+        assert!(error.to_string().contains("expected 1 rows of 1 energies"));
+
+        // Glycine is its CA and ALA its CB, hydrogens and the rest of the backbone are left out
+        let receptor = parse_structure(
+            "\
+ATOM      1  CA  GLY A   1       0.000   0.000   0.000  1.00  0.00           C\n\
+ATOM      2  HA2 GLY A   1       0.000   0.000  -1.000  1.00  0.00           H\n",
+            1,
+        )
+        .unwrap()
+        .unwrap();
+        let ligand = parse_structure(
+            "\
+ATOM      1  N   ALA B   1       0.000   0.000   3.000  1.00  0.00           N\n\
+ATOM      2  CA  ALA B   1       0.000   0.000  10.000  1.00  0.00           C\n\
+ATOM      3  CB  ALA B   1       0.000   0.000   5.000  1.00  0.00           C\n",
+            1,
+        )
+        .unwrap()
+        .unwrap();
+        let no_weights: HashMap<String, f64> = HashMap::new();
+        let receptor = TOBIDockingModel::new(
+            &receptor,
+            &[],
+            &[],
+            &no_weights,
+            &no_weights,
+            &[],
+            0,
+            &potential,
+            ResiduePolicy::Strict,
+        );
+        let ligand = TOBIDockingModel::new(
+            &ligand,
+            &[String::from("B.ALA.1")],
+            &[],
+            &no_weights,
+            &no_weights,
+            &[0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 1.0],
+            1,
+            &potential,
+            ResiduePolicy::Strict,
+        );
+        assert_eq!(receptor.coordinates, vec![[0.0, 0.0, 0.0]]);
+        assert_eq!(ligand.coordinates, vec![[0.0, 0.0, 3.0], [0.0, 0.0, 5.0]]);
+        assert_eq!(ligand.atoms, vec![2, 1]);
+        // The normal modes of the beads are the ones of their atoms
+        assert_eq!(ligand.nmodes, vec![0.0, 0.0, 0.0, 0.0, 0.0, 1.0]);
+
+        let scoring = TOBI::from_models(
+            potential,
+            receptor,
+            ligand,
+            true,
+            None,
+            RestraintsMetric::default(),
+        );
+        let rotation = Quaternion::default();
+        // N in the first bin and at the interface, the ALA side chain in the second
+        let energy = scoring.energy(&[0.0, 0.0, 0.0], &rotation, &[], &[0.0]);
+        assert_eq!(energy, (3.0 + 0.2) * 2.0);
+        assert_eq!(
+            scoring.restraints_satisfaction(&[0.0, 0.0, 0.0], &rotation, &[], &[0.0]),
+            Some(1.0)
+        );
+        // The side chain moved by the normal mode beyond the last bin
+        let energy = scoring.energy(&[0.0, 0.0, 0.0], &rotation, &[], &[1.5]);
+        assert_eq!(energy, 3.0 * 2.0);
+        let energies = scoring
+            .residue_energies(&[0.0, 0.0, 0.0], &rotation, &[], &[1.5])
+            .unwrap();
+        assert_eq!(energies.total(), 3.0);
+    }
+}
@@ -0,0 +1,169 @@
+use super::dfire::{atom_type, bin_range, distance_bin, DFIRE_ATOM_TYPES, DFIRE_BINS};
+use pdbtbx::PDB;
+use std::io::{Error, Write};
+
+// Derives a DFIRE-like distance dependent potential from a set of complexes. Pairs of atoms of
+// different chains are counted by atom types and distance bin, and the observed counts are
+// compared to the DFIRE reference state, an ideal gas of points in finite spheres:
+//   u(i, j, r) = -ln(N(i, j, r) / ((r / rcut)^alpha * (dr / drcut) * N(i, j, rcut)))
+// with rcut the last distance bin. The potential uses the DFIRE atom types and is written in the
+// format of the DCparams data file, so it can replace the built-in one.
+
+// Exponent of the DFIRE reference state
+pub const DFIRE_ALPHA: f64 = 1.61;
+
+// Value of the bins without observations, as in the DCparams file
+pub const UNOBSERVED_ENERGY: f64 = 10.0;
+
+#[derive(Debug)]
+pub struct PairCounts {
+    pub counts: Vec<f64>,
+    pub num_structures: usize,
+    pub num_pairs: usize,
+}
+
+impl Default for PairCounts {
+    fn default() -> PairCounts {
+        PairCounts {
+            counts: vec![0.0; DFIRE_ATOM_TYPES * DFIRE_ATOM_TYPES * DFIRE_BINS],
+            num_structures: 0,
+            num_pairs: 0,
+        }
+    }
+}
+
+impl PairCounts {
+    fn index(atoma: usize, atomb: usize, bin: usize) -> usize {
+        atoma * DFIRE_ATOM_TYPES * DFIRE_BINS + atomb * DFIRE_BINS + bin
+    }
+
+    // Counts the pairs of typed atoms of different chains, returning the number of pairs
+    pub fn add_structure(&mut self, structure: &PDB) -> usize {
+        let mut atoms: Vec<(usize, usize, [f64; 3])> = Vec::new();
+        for (i_chain, chain) in structure.chains().enumerate() {
+            for residue in chain.residues() {
+                let res_name = residue.name().unwrap_or("");
+                for atom in residue.atoms() {
+                    if let Some(atom_type) = atom_type(res_name, atom.name()) {
+                        atoms.push((i_chain, atom_type, [atom.x(), atom.y(), atom.z()]));
+                    }
+                }
+            }
+        }
+        let mut num_pairs = 0;
+        for (i, (chain_a, atoma, a)) in atoms.iter().enumerate() {
+            for (chain_b, atomb, b) in atoms[i + 1..].iter() {
+                if chain_a == chain_b {
+                    continue;
+                }
+                let distance =
+                    ((a[0] - b[0]).powi(2) + (a[1] - b[1]).powi(2) + (a[2] - b[2]).powi(2)).sqrt();
+                if let Some(bin) = distance_bin(distance) {
+                    self.counts[PairCounts::index(*atoma, *atomb, bin)] += 1.0;
+                    self.counts[PairCounts::index(*atomb, *atoma, bin)] += 1.0;
+                    num_pairs += 1;
+                }
+            }
+        }
+        self.num_structures += 1;
+        self.num_pairs += num_pairs;
+        num_pairs
+    }
+
+    // Potential in the DCparams order. Pairs of atom types never found in the reference bin get
+    // zero everywhere, as there is no information about them.
+    pub fn potential(&self) -> Vec<f64> {
+        let reference = DFIRE_BINS - 1;
+        let (reference_lower, reference_upper) = bin_range(reference);
+        let reference_center = (reference_lower + reference_upper) / 2.0;
+        let reference_width = reference_upper - reference_lower;
+        let expected_ratios: Vec<f64> = (0..DFIRE_BINS)
+            .map(|bin| {
+                let (lower, upper) = bin_range(bin);
+                ((lower + upper) / 2.0 / reference_center).powf(DFIRE_ALPHA)
+                    * ((upper - lower) / reference_width)
+            })
+            .collect();
+
+        let mut potential = vec![0.0; self.counts.len()];
+        for atoma in 0..DFIRE_ATOM_TYPES {
+            for atomb in 0..DFIRE_ATOM_TYPES {
+                let reference_count = self.counts[PairCounts::index(atoma, atomb, reference)];
+                if reference_count == 0.0 {
+                    continue;
+                }
+                for (bin, ratio) in expected_ratios.iter().enumerate() {
+                    let index = PairCounts::index(atoma, atomb, bin);
+                    let observed = self.counts[index];
+                    potential[index] = if observed > 0.0 {
+                        (ratio * reference_count / observed).ln()
+                    } else {
+                        UNOBSERVED_ENERGY
+                    };
+                }
+            }
+        }
+        potential
+    }
+}
+
+// One value per line, as read by the DFIRE scoring function
+pub fn write_potential<W: Write>(output: &mut W, potential: &[f64]) -> Result<(), Error> {
+    for value in potential.iter() {
+        writeln!(output, "{:.9}", value)?;
+    }
+    output.flush()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::structure::parse_structure;
+
+    #[test]
+    fn test_train_potential() {
+        assert_eq!(distance_bin(1.2), Some(0));
+        assert_eq!(distance_bin(2.2), Some(1));
+        assert_eq!(distance_bin(14.5), Some(19));
+        assert_eq!(distance_bin(15.0), None);
+        assert_eq!(bin_range(0), (0.0, 2.0));
+        assert_eq!(bin_range(19), (14.0, 15.0));
+
+        // Same chain pairs are not counted
+        let contents = "\
+ATOM      1  CA  ALA A   1       0.000   0.000   0.000  1.00  0.00           C\n\
+ATOM      2  CA  ALA A   2       3.800   0.000   0.000  1.00  0.00           C\n\
+ATOM      3  CA  GLY B   1       0.000   4.200   0.000  1.00  0.00           C\n\
+ATOM      4  CA  GLY B   2      14.500   0.000   0.000  1.00  0.00           C\n\
+ATOM      5  H   GLY B   2      14.500   1.000   0.000  1.00  0.00           H\n";
+        let structure = parse_structure(contents, 1).unwrap().unwrap();
+        let mut counts = PairCounts::default();
+        assert_eq!(counts.add_structure(&structure), 4);
+        let ala_ca = atom_type("ALA", "CA").unwrap();
+        let gly_ca = atom_type("GLY", "CA").unwrap();
+        let close = distance_bin(4.2).unwrap();
+        assert_eq!(counts.counts[PairCounts::index(ala_ca, gly_ca, close)], 1.0);
+        assert_eq!(counts.counts[PairCounts::index(gly_ca, ala_ca, 19)], 1.0);
+
+        let potential = counts.potential();
+        assert_eq!(potential[PairCounts::index(ala_ca, gly_ca, 19)], 0.0);
+        assert_eq!(
+            potential[PairCounts::index(ala_ca, gly_ca, 0)],
+            UNOBSERVED_ENERGY
+        );
+        // Enriched at short distance with respect to the reference state
+        assert!(potential[PairCounts::index(ala_ca, gly_ca, close)] < 0.0);
+        assert_eq!(
+            potential[PairCounts::index(ala_ca, gly_ca, close)],
+            potential[PairCounts::index(gly_ca, ala_ca, close)]
+        );
+        assert_eq!(potential[PairCounts::index(ala_ca, ala_ca, close)], 0.0);
+
+        let mut output: Vec<u8> = Vec::new();
+        write_potential(&mut output, &[UNOBSERVED_ENERGY, -0.5]).unwrap();
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "10.000000000\n-0.500000000\n"
+        );
+    }
+}
@@ -0,0 +1,182 @@
+use super::output::TrajectoryWriter;
+use super::scoring::z_scores;
+use super::swarm::Swarm;
+use parquet::data_type::{DoubleType, Int32Type};
+use parquet::file::properties::WriterProperties;
+use parquet::file::writer::SerializedFileWriter;
+use parquet::schema::parser::parse_message_type;
+use std::error::Error;
+use std::fs::File;
+use std::sync::Arc;
+
+// All the glowworm states of a swarm are appended to a single Parquet file, one row group per
+// saved step and one row per glowworm. Normal modes are flattened into rec_nm_<i> and
+// lig_nm_<i> columns so the file can be loaded directly as a pandas or Arrow table.
+
+enum Column {
+    Int(Vec<i32>),
+    Double(Vec<f64>),
+}
+
+pub struct ParquetTrajectory {
+    writer: Option<SerializedFileWriter<File>>,
+    rec_num_anm: usize,
+    lig_num_anm: usize,
+}
+
+impl ParquetTrajectory {
+    pub fn new(path: &str, rec_num_anm: usize, lig_num_anm: usize) -> Result<Self, Box<dyn Error>> {
+        let mut fields: Vec<String> = vec![
+            String::from("REQUIRED INT32 step"),
+            String::from("REQUIRED INT32 id"),
+            String::from("REQUIRED INT32 rec_id"),
+            String::from("REQUIRED INT32 lig_id"),
+        ];
+        for name in ["x", "y", "z", "qw", "qx", "qy", "qz"] {
+            fields.push(format!("REQUIRED DOUBLE {}", name));
+        }
+        for i in 0..rec_num_anm {
+            fields.push(format!("REQUIRED DOUBLE rec_nm_{}", i));
+        }
+        for i in 0..lig_num_anm {
+            fields.push(format!("REQUIRED DOUBLE lig_nm_{}", i));
+        }
+        fields.push(String::from("REQUIRED DOUBLE luciferin"));
+        fields.push(String::from("REQUIRED INT32 neighbors"));
+        fields.push(String::from("REQUIRED DOUBLE vision_range"));
+        fields.push(String::from("REQUIRED DOUBLE scoring"));
+        fields.push(String::from("REQUIRED DOUBLE z_score"));
+        let message = format!("message glowworm {{ {}; }}", fields.join("; "));
+
+        let schema = Arc::new(parse_message_type(&message)?);
+        let properties = Arc::new(WriterProperties::builder().build());
+        let writer = SerializedFileWriter::new(File::create(path)?, schema, properties)?;
+        Ok(ParquetTrajectory {
+            writer: Some(writer),
+            rec_num_anm,
+            lig_num_anm,
+        })
+    }
+
+    fn columns(&self, step: u32, swarm: &Swarm) -> Vec<Column> {
+        let glowworms = &swarm.glowworms;
+        let doubles = |f: &dyn Fn(usize) -> f64| -> Column {
+            Column::Double((0..glowworms.len()).map(f).collect())
+        };
+        let mut columns: Vec<Column> = vec![
+            Column::Int(vec![step as i32; glowworms.len()]),
+            Column::Int(glowworms.iter().map(|g| g.id as i32).collect()),
+            Column::Int(glowworms.iter().map(|g| g.rec_id as i32).collect()),
+            Column::Int(glowworms.iter().map(|g| g.lig_id as i32).collect()),
+        ];
+        for i in 0..3 {
+            columns.push(doubles(&|j| glowworms[j].translation[i]));
+        }
+        columns.push(doubles(&|j| glowworms[j].rotation.w));
+        columns.push(doubles(&|j| glowworms[j].rotation.x));
+        columns.push(doubles(&|j| glowworms[j].rotation.y));
+        columns.push(doubles(&|j| glowworms[j].rotation.z));
+        for i in 0..self.rec_num_anm {
+            columns.push(doubles(&|j| {
+                glowworms[j].rec_nmodes.get(i).copied().unwrap_or(0.0)
+            }));
+        }
+        for i in 0..self.lig_num_anm {
+            columns.push(doubles(&|j| {
+                glowworms[j].lig_nmodes.get(i).copied().unwrap_or(0.0)
+            }));
+        }
+        columns.push(doubles(&|j| glowworms[j].luciferin));
+        columns.push(Column::Int(
+            glowworms.iter().map(|g| g.neighbors.len() as i32).collect(),
+        ));
+        columns.push(doubles(&|j| glowworms[j].vision_range));
+        let scores: Vec<f64> = glowworms.iter().map(|g| g.scoring).collect();
+        let z_scores = z_scores(&scores);
+        columns.push(Column::Double(scores));
+        columns.push(Column::Double(z_scores));
+        columns
+    }
+}
+
+impl TrajectoryWriter for ParquetTrajectory {
+    fn append(&mut self, step: u32, swarm: &Swarm) -> Result<(), Box<dyn Error>> {
+        let columns = self.columns(step, swarm);
+        let writer = self
+            .writer
+            .as_mut()
+            .ok_or("Parquet trajectory already closed")?;
+        let mut row_group = writer.next_row_group()?;
+        let mut columns = columns.iter();
+        while let Some(mut column_writer) = row_group.next_column()? {
+            match columns.next() {
+                Some(Column::Int(values)) => {
+                    column_writer
+                        .typed::<Int32Type>()
+                        .write_batch(values, None, None)?;
+                }
+                Some(Column::Double(values)) => {
+                    column_writer
+                        .typed::<DoubleType>()
+                        .write_batch(values, None, None)?;
+                }
+                None => return Err("Parquet trajectory schema mismatch".into()),
+            }
+            column_writer.close()?;
+        }
+        row_group.close()?;
+        Ok(())
+    }
+
+    fn close(&mut self) -> Result<(), Box<dyn Error>> {
+        if let Some(writer) = self.writer.take() {
+            writer.close()?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::qt::Quaternion;
+    use crate::scoring::Score;
+    use parquet::file::reader::{FileReader, SerializedFileReader};
+
+    struct Height;
+
+    impl Score for Height {
+        fn energy(&self, translation: &[f64], _: &Quaternion, _: &[f64], _: &[f64]) -> f64 {
+            translation[2]
+        }
+    }
+
+    #[test]
+    fn test_parquet_trajectory() {
+        let scoring: Box<dyn Score> = Box::new(Height);
+        let mut swarm = Swarm::new();
+        let positions = vec![
+            vec![0.0, 0.0, 1.0, 1.0, 0.0, 0.0, 0.0, 0.5],
+            vec![0.0, 0.0, 3.0, 1.0, 0.0, 0.0, 0.0, -0.5],
+        ];
+        swarm.add_glowworms(&positions, &scoring, true, 1, 0);
+        swarm.update_luciferin();
+
+        let path = std::env::temp_dir().join("lightdock_test_trajectory.parquet");
+        let path = path.to_str().unwrap().to_string();
+        let mut trajectory = ParquetTrajectory::new(&path, 1, 0).unwrap();
+        trajectory.append(1, &swarm).unwrap();
+        trajectory.append(10, &swarm).unwrap();
+        trajectory.close().unwrap();
+
+        let reader = SerializedFileReader::new(File::open(&path).unwrap()).unwrap();
+        let metadata = reader.metadata();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(metadata.num_row_groups(), 2);
+        assert_eq!(metadata.file_metadata().num_rows(), 4);
+        let schema = metadata.file_metadata().schema_descr();
+        assert_eq!(schema.num_columns(), 17);
+        assert_eq!(schema.column(11).name(), "rec_nm_0");
+        assert_eq!(schema.column(15).name(), "scoring");
+    }
+}